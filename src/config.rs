@@ -1,28 +1,52 @@
 //! Manages the config files (default location ~/.config/up/).
 
+use crate::cmd;
+use crate::exec::UpDuct;
 use crate::opts::start_time::StartTime;
 use crate::opts::GitOptions;
+use crate::opts::ListOptions;
+use crate::opts::OnConflict;
 use crate::opts::Opts;
+use crate::opts::PlanOptions;
 use crate::opts::RunOptions;
 use crate::opts::SubCommand;
+use crate::opts::SummaryFormat;
+use crate::tasks::budget::RunBudget;
 use crate::tasks::git;
+use crate::tasks::include::IncludeConfig;
+use crate::tasks::overlap::OnTaskOverlap;
+use crate::tasks::plugin::PluginConfig;
+use crate::tasks::resources::Resource;
+use crate::tasks::task::TaskDefaults;
+use crate::tasks::task::DEFAULT_SLOW_WARN_AFTER;
+use crate::utils::backup::Backups;
+use crate::utils::duration::HumanDuration;
 use crate::utils::files;
+use crate::utils::host;
+use crate::utils::mac;
+use crate::utils::sleep::PreventSleep;
+use crate::utils::yaml;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use color_eyre::eyre::bail;
 use color_eyre::eyre::ensure;
+use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::io;
 use tracing::debug;
 use tracing::info;
 use tracing::trace;
+use tracing::warn;
 
 /// Internal state used by subcommands.
 #[derive(Default, Debug)]
+#[allow(clippy::struct_excessive_bools)] // These mirror independent CLI flags.
 pub struct UpConfig {
     /// Path to the up config file.
     pub up_yaml_path: Option<Utf8PathBuf>,
@@ -32,16 +56,42 @@ pub struct UpConfig {
     pub bootstrap: bool,
     /// Whether we should keep going if a task fails in bootstrap mode.
     pub keep_going: bool,
-    /// The list of tasks to execute.
+    /// Skip the bootstrap tasks that passed in the last run, if it was interrupted.
+    pub resume: bool,
+    /// The list of tasks to execute (as glob patterns).
     pub tasks: Option<Vec<String>>,
+    /// Execute tasks with any of these tags.
+    pub tags: Option<Vec<String>>,
     /// The list of tasks to not execute.
     pub exclude_tasks: Option<Vec<String>>,
+    /// Only run this task and the tasks it requires.
+    pub until: Option<String>,
+    /// Don't run the `until` task itself, only the tasks it requires.
+    pub only_deps: bool,
     /// Whether task stdout/stderr should inherit from up's stdout/stderr.
     pub console: Option<bool>,
+    /// Whether to show the full-screen task dashboard.
+    pub tui: bool,
+    /// How much detail to show in the end-of-run report.
+    pub summary: SummaryFormat,
+    /// Whether up's stderr output is coloured, tasks are told to match.
+    pub color: bool,
     /// Temporary directory to use for up command execution.
     pub temp_dir: Utf8PathBuf,
+    /// Directory for caches that up can recreate if they're deleted.
+    pub cache_dir: Utf8PathBuf,
+    /// Directory to create per-run task temporary directories in.
+    pub run_temp_dir: Utf8PathBuf,
+    /// Where to back up files that tasks overwrite.
+    pub backups: Backups,
     /// Time we started this command execution.
     pub start_time: StartTime,
+    /// Don't use the network, skipping tasks that need it.
+    pub offline: bool,
+    /// How long the run may take before no more tasks are started (`--max-duration`).
+    pub budget: Option<RunBudget>,
+    /// The entry of the `hosts` section of `up.yaml` for this machine, if there is one.
+    pub host: Option<SelectedHost>,
 }
 
 // TODO(gib): Provide a way for users to easily validate their yaml files.
@@ -51,31 +101,182 @@ pub struct UpConfig {
 #[serde(deny_unknown_fields)]
 pub struct ConfigYaml {
     /// Path to tasks directory (relative to `up.yaml`). Default is ./tasks.
-    tasks_path: Option<String>,
-    /// Environment variables to pass to scripts.
+    pub tasks_path: Option<String>,
+    /// Extensions of the task files in subdirectories of the tasks directory, defaults to
+    /// `[yaml, yml]` (all files directly in the tasks directory are tasks). Add e.g. `sh` to make
+    /// executable scripts in subdirectories tasks too.
+    pub task_extensions: Option<Vec<String>>,
+    /// Dotenv-format file(s) to load env vars from before resolving `env`, e.g.
+    /// `~/.config/up/env`. Later files override earlier ones, and env vars set in them override
+    /// those in `env`. Files that don't exist are skipped.
+    pub env_file: Option<EnvFiles>,
+    /// Environment variables to pass to scripts. Values (and strings in task data) can include the
+    /// output of a command with `$(cmd <program> <args>...)`, e.g. `$(cmd brew --prefix)`, which
+    /// is run (without a shell) once per run.
     pub env: Option<HashMap<String, String>>,
     /// Environment variables to inherit from running env, doesn't error if not
     /// defined.
     pub inherit_env: Option<Vec<String>>,
-    /// List of tasks to run in order in bootstrap mode.
+    /// Directories to set as the `PATH` for tasks, e.g. `["~/bin", "/opt/homebrew/bin",
+    /// "$PATH"]`, so tasks behave the same however up is launched. `~` and env vars are expanded,
+    /// with `$PATH` being the `PATH` set by `env`/`inherit_env` or up's own `PATH`.
+    pub path: Option<Vec<String>>,
+    /// List of tasks to run in order in bootstrap mode. Deprecated: set `bootstrap: true` in the
+    /// tasks instead, and use `requires` to order them.
     pub bootstrap_tasks: Option<Vec<String>>,
+    /// List of tasks to never run, in addition to any passed with `--exclude-tasks`.
+    pub exclude_tasks: Option<Vec<String>>,
+    /// Print a hint at the end of `up run` if a newer version of up has been released. The latest
+    /// version is checked at most once a day.
+    pub update_check: Option<bool>,
+    /// Maximum number of tasks using each resource (`network`, `cpu`, or `disk`) to run at once,
+    /// e.g. `{network: 4, cpu: 8}`. Tasks declare the resources they use in their `resources`
+    /// field. Resources without a limit are unlimited.
+    pub max_parallel: Option<HashMap<Resource, usize>>,
+    /// Task library plugins to fetch from git, used with `run_lib: plugin:<name>`.
+    pub plugins: Option<HashMap<String, PluginConfig>>,
+    /// Warn about tasks (and git repo updates) that take longer than this, e.g. `5m`. Tasks can
+    /// override it with their own `slow_warn_after`. Defaults to `60s`.
+    pub slow_warn_after: Option<HumanDuration>,
+    /// Log a heartbeat (how long the task has been running, and its last line of output) for
+    /// tasks that haven't written any output for this long, e.g. `10m`, so unattended runs don't
+    /// look hung. Defaults to `5m`, `0s` turns heartbeats off.
+    pub heartbeat_after: Option<HumanDuration>,
+    /// Minimum version of up this config needs, e.g. `"0.15"`. Running an older up errors and
+    /// suggests updating with `up self`.
+    pub min_version: Option<String>,
+    /// Default `on_conflict` for link tasks that don't set their own (`backup`, `skip`,
+    /// `overwrite`, or `fail`).
+    pub link_on_conflict: Option<OnConflict>,
+    /// What to do when a link task would create links inside the repo of a git task (so the
+    /// tasks would fight over the same files): `warn` (the default) or `fail` the run.
+    pub on_task_overlap: Option<OnTaskOverlap>,
+    /// When to stop the machine sleeping while tasks run: `always` (the default), `on-ac` (not
+    /// when running on battery), or `never`.
+    pub prevent_sleep: Option<PreventSleep>,
+    /// Config fragments to include tasks from, each an HTTPS `url` with an optional `sha256` hash
+    /// of its contents. Local tasks replace included tasks with the same name.
+    pub include: Option<Vec<IncludeConfig>>,
+    /// Defaults for tasks (including included ones) that don't set these fields themselves:
+    /// `constraints`, `requires`, `requires_commands`, `on_missing_commands`, `auto_run`, `tags`,
+    /// and `resources`.
+    pub defaults: Option<TaskDefaults>,
+    /// Config for specific machines, keyed by hostname (with or without its domain) or serial
+    /// number. The entry matching this machine (or the one passed with `--host`) is applied at
+    /// startup.
+    pub hosts: Option<HashMap<String, HostConfig>>,
+}
+
+/// Config for one machine in the `hosts` section of `up.yaml`.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    /// Env vars to pass to tasks on this machine, overriding those in `env`.
+    pub env: Option<HashMap<String, String>>,
+    /// Only run the tasks matching these patterns on this machine, unless `--tasks` is passed.
+    pub tasks: Option<Vec<String>>,
+    /// Tasks to never run on this machine, in addition to `exclude_tasks`.
+    pub exclude_tasks: Option<Vec<String>>,
+    /// Hardware UUID to use for `current_host` defaults (the `ByHost` plist file names), e.g. to
+    /// keep using the preferences of the machine this one was migrated from.
+    pub hardware_uuid: Option<String>,
+}
+
+/// The `hosts` entry used for this machine.
+#[derive(Debug, Clone)]
+pub struct SelectedHost {
+    /// Key of the entry in `hosts`.
+    pub name: String,
+    /// How the entry was chosen, e.g. `hostname` or `--host`.
+    pub matched_by: String,
+    /// The entry's config.
+    pub config: HostConfig,
+}
+
+impl SelectedHost {
+    /// Whether the host's `exclude_tasks` contains the task `name`.
+    #[must_use]
+    pub fn excludes(&self, name: &str) -> bool {
+        self.config
+            .exclude_tasks
+            .iter()
+            .flatten()
+            .any(|t| t == name)
+    }
+}
+
+impl fmt::Display for SelectedHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{name} (matched by {matched_by})",
+            name = self.name,
+            matched_by = self.matched_by
+        )
+    }
+}
+
+/// Just the `min_version` of an `up.yaml`, parsed first so it's checked even if the config uses
+/// fields this version of up doesn't know about.
+#[derive(Default, Debug, Deserialize)]
+struct MinVersionYaml {
+    /// See [`ConfigYaml::min_version`].
+    min_version: Option<String>,
+}
+
+/// One or more env file paths, so `env_file` can be a single path or a list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EnvFiles {
+    /// A single env file.
+    One(String),
+    /// Multiple env files, later files override earlier ones.
+    Many(Vec<String>),
+}
+
+impl EnvFiles {
+    /// The env file paths, in order.
+    pub fn paths(&self) -> &[String] {
+        match self {
+            Self::One(path) => std::slice::from_ref(path),
+            Self::Many(paths) => paths,
+        }
+    }
 }
 
 impl UpConfig {
     /// Build the `UpConfig` struct by parsing the config yaml files.
     pub fn from(opts: Opts) -> Result<Self> {
         let mut config_yaml = ConfigYaml::default();
+        let backups = Backups::new(&opts)?;
+        let cache_dir = opts.cache_dir()?;
+        let run_temp_dir = opts.run_temp_dir()?;
+        let color = opts.color.enabled(&io::stderr());
 
         let run_options = match opts.cmd {
-            Some(SubCommand::Run(task_opts) | SubCommand::List(task_opts)) => task_opts,
+            Some(
+                SubCommand::Run(task_opts)
+                | SubCommand::List(ListOptions {
+                    run_options: task_opts,
+                    ..
+                })
+                | SubCommand::Plan(PlanOptions {
+                    run_options: task_opts,
+                    ..
+                }),
+            ) => task_opts,
             _ => RunOptions::default(),
         };
 
+        let fallback_url = run_options.fallback_url.filter(|fallback_url| {
+            let use_fallback = !(run_options.no_fallback || opts.offline);
+            if !use_fallback {
+                debug!("Not using fallback URL {fallback_url} as --no-fallback or --offline set.");
+            }
+            use_fallback
+        });
         let mut config_path_explicitly_specified = true;
-        let up_yaml_path = match (
-            Self::get_up_yaml_path(&opts.config),
-            run_options.fallback_url,
-        ) {
+        let up_yaml_path = match (Self::get_up_yaml_path(&opts.config), fallback_url) {
             // File exists, use file.
             (Ok(up_yaml_path), _) if up_yaml_path.exists() => up_yaml_path,
             (result, Some(fallback_url)) => {
@@ -101,7 +302,12 @@ impl UpConfig {
                 if config_str.is_empty() {
                     debug!("Yaml file was empty, using default config.");
                 } else {
-                    config_yaml = serde_yaml::from_str::<ConfigYaml>(&config_str)?;
+                    let min_version_yaml =
+                        serde_yaml::from_str::<MinVersionYaml>(&config_str).unwrap_or_default();
+                    if let Some(min_version) = &min_version_yaml.min_version {
+                        check_min_version(min_version, env!("CARGO_PKG_VERSION"))?;
+                    }
+                    config_yaml = yaml::from_str::<ConfigYaml>(&config_str)?;
                 };
                 debug!("Config_yaml: {config_yaml:?}");
             }
@@ -112,19 +318,64 @@ impl UpConfig {
             None
         };
 
+        let host = match &config_yaml.hosts {
+            Some(hosts) => select_host(hosts, opts.host.as_deref(), host::identifiers)?,
+            None if opts.host.is_some() => bail!("--host was passed, but up.yaml has no hosts."),
+            None => None,
+        };
+        if let Some(host) = &host {
+            info!("Using config for host {host}.");
+            if let Some(host_env) = &host.config.env {
+                config_yaml
+                    .env
+                    .get_or_insert_with(HashMap::new)
+                    .extend(host_env.clone());
+            }
+            if let Some(hardware_uuid) = &host.config.hardware_uuid {
+                mac::set_hardware_uuid_override(hardware_uuid.clone());
+            }
+        }
+
         let bootstrap = run_options.bootstrap;
         let keep_going = run_options.keep_going;
+        let exclude_tasks = match (run_options.exclude_tasks, &config_yaml.exclude_tasks) {
+            (None, None) => None,
+            (cli_excludes, yaml_excludes) => Some(
+                cli_excludes
+                    .into_iter()
+                    .flatten()
+                    .chain(yaml_excludes.iter().flatten().cloned())
+                    .collect(),
+            ),
+        };
 
         Ok(Self {
             up_yaml_path,
             config_yaml,
             bootstrap,
             keep_going,
+            resume: run_options.resume,
             temp_dir: opts.temp_dir.as_ref().to_owned(),
-            tasks: run_options.tasks,
-            exclude_tasks: run_options.exclude_tasks,
+            cache_dir,
+            run_temp_dir,
+            backups,
+            tasks: run_options
+                .tasks
+                .or_else(|| host.as_ref().and_then(|host| host.config.tasks.clone())),
+            tags: run_options.tags,
+            exclude_tasks,
+            until: run_options.until,
+            only_deps: run_options.only_deps,
             start_time: opts.start_time,
             console: run_options.console,
+            tui: run_options.tui,
+            summary: run_options.summary,
+            color,
+            offline: opts.offline,
+            budget: run_options
+                .max_duration
+                .map(|max_duration| RunBudget::new(max_duration, run_options.hard)),
+            host,
         })
     }
 
@@ -136,6 +387,8 @@ impl UpConfig {
     /// 3. `$XDG_CONFIG_HOME/up/up.yaml`
     /// 4. `~/.config/up/yaml`
     ///
+    /// `~` is the `--home` directory if passed, in which case `$XDG_CONFIG_HOME` is ignored.
+    ///
     /// The function will return an error if the file is explicitly specified
     /// via `$UP_CONFIG` or --config flags, or if the user doesn't have a home
     /// directory set.
@@ -160,10 +413,7 @@ impl UpConfig {
 
             trace!("Checking default config paths.");
 
-            let home_dir = files::home_dir()?;
-
-            config_path = env::var("XDG_CONFIG_HOME")
-                .map_or_else(|_e| home_dir.join(".config"), Utf8PathBuf::from);
+            config_path = files::config_dir()?;
 
             config_path.push("up");
 
@@ -180,6 +430,53 @@ impl UpConfig {
     }
 }
 
+/**
+The entry of `hosts` for this machine: the `requested` one (from `--host`) if set, otherwise the
+first of the machine's `identifiers` (see [`host::identifiers`]) that's a key in `hosts`.
+*/
+fn select_host(
+    hosts: &HashMap<String, HostConfig>,
+    requested: Option<&str>,
+    identifiers: impl FnOnce() -> Vec<(&'static str, String)>,
+) -> Result<Option<SelectedHost>> {
+    if let Some(requested) = requested {
+        let Some(config) = hosts.get(requested) else {
+            let mut names: Vec<&String> = hosts.keys().collect();
+            names.sort_unstable();
+            bail!("--host '{requested}' isn't in the hosts of up.yaml, expected one of {names:?}.");
+        };
+        return Ok(Some(SelectedHost {
+            name: requested.to_owned(),
+            matched_by: "--host".to_owned(),
+            config: config.clone(),
+        }));
+    }
+    let identifiers = identifiers();
+    let selected = identifiers.iter().find_map(|(matched_by, identifier)| {
+        hosts.get(identifier).map(|config| SelectedHost {
+            name: identifier.clone(),
+            matched_by: (*matched_by).to_owned(),
+            config: config.clone(),
+        })
+    });
+    if selected.is_none() {
+        debug!("No hosts entry in up.yaml for this machine ({identifiers:?}).");
+    }
+    Ok(selected)
+}
+
+/// Error if the `current` up version is older than the config's `min_version`.
+fn check_min_version(min_version: &str, current: &str) -> Result<()> {
+    let required = semver::Comparator::parse(&format!(">={min_version}"))
+        .wrap_err_with(|| format!("Invalid up.yaml min_version '{min_version}'"))?;
+    ensure!(
+        required.matches(&semver::Version::parse(current)?),
+        "This config requires up version {min_version} or later, but this is up {current}.\n  \
+         Update up with `up self`.",
+    );
+    Ok(())
+}
+
 // TODO(gib): add tests.
 /**
 If the fallback repo path was provided, clone or update that path into a
@@ -187,6 +484,10 @@ temporary directory, and then return the path to the `up.yaml` file within
 that directory by joining `<fallback_url>/<fallback_path>`.
 
 If the `fallback_url` is of the form org/repo , then assume it is a github.com repository.
+
+If the `fallback_url` is a tarball (e.g. a `codeload.github.com` or `.tar.gz` URL), download and
+extract it instead. If updating a github.com repo with git fails (e.g. on a minimal machine
+without working git or ssh), fall back to downloading the repo tarball.
 */
 fn get_fallback_config_path(
     temp_dir: &Utf8Path,
@@ -196,20 +497,36 @@ fn get_fallback_config_path(
     if !fallback_url.contains("://") {
         fallback_url = format!("https://github.com/{fallback_url}");
     }
-    let fallback_repo_path = temp_dir.join("up-rs/fallback_repo");
-    files::create_dir_all(&fallback_repo_path)?;
 
-    let fallback_config_path = fallback_repo_path.join(fallback_path);
-    git::update::update(
-        &GitOptions {
-            git_url: fallback_url,
-            git_path: fallback_repo_path,
-            remote: git::DEFAULT_REMOTE_NAME.to_owned(),
-            ..GitOptions::default()
+    let fallback_repo_path = if is_tarball_url(&fallback_url) {
+        download_fallback_tarball(temp_dir, &fallback_url)?
+    } else {
+        let fallback_repo_path = temp_dir.join("up-rs/fallback_repo");
+        files::create_dir_all(&fallback_repo_path)?;
+        let git_result = git::update::update(
+            &GitOptions {
+                git_url: Some(fallback_url.clone()),
+                git_path: Some(fallback_repo_path.clone()),
+                remote: git::DEFAULT_REMOTE_NAME.to_owned(),
+                ..GitOptions::default()
+            }
+            .into(),
+            DEFAULT_SLOW_WARN_AFTER,
+        );
+        match (git_result, github_tarball_url(&fallback_url)) {
+            (Ok(_), _) => fallback_repo_path,
+            (Err(e), Some(tarball_url)) => {
+                warn!(
+                    "Failed to update fallback repo with git, downloading {tarball_url} \
+                     instead.\n  Error: {e:?}"
+                );
+                download_fallback_tarball(temp_dir, &tarball_url)?
+            }
+            (Err(e), None) => return Err(e),
         }
-        .into(),
-    )?;
+    };
 
+    let fallback_config_path = fallback_repo_path.join(fallback_path);
     ensure!(
         fallback_config_path.exists(),
         "Fallback config path doesn't exist.\n  config_path: {fallback_config_path}",
@@ -217,12 +534,74 @@ fn get_fallback_config_path(
     Ok(fallback_config_path)
 }
 
+/// Whether the fallback URL points to a repo tarball rather than a git repo.
+fn is_tarball_url(url: &str) -> bool {
+    url.starts_with("https://codeload.github.com/")
+        || url.ends_with(".tar.gz")
+        || url.ends_with(".tgz")
+}
+
+/// The codeload tarball URL for the default branch of a github.com repo URL, if it is one.
+fn github_tarball_url(url: &str) -> Option<String> {
+    let repo = url
+        .strip_prefix("https://github.com/")?
+        .trim_end_matches('/')
+        .trim_end_matches(".git");
+    let (org, name) = repo.split_once('/')?;
+    if org.is_empty() || name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some(format!(
+        "https://codeload.github.com/{org}/{name}/tar.gz/HEAD"
+    ))
+}
+
+/**
+Download the repo tarball at `url` over HTTPS and extract it into the fallback tarball dir,
+returning that dir.
+
+The top-level directory of the tarball (e.g. `dot-main/`) is stripped, so the layout matches a
+clone of the repo. Only needs `tar`, not git or ssh.
+*/
+fn download_fallback_tarball(temp_dir: &Utf8Path, url: &str) -> Result<Utf8PathBuf> {
+    let tarball_path = temp_dir.join("up-rs/fallback_repo.tar.gz");
+    let extract_path = temp_dir.join("up-rs/fallback_tarball");
+    files::create_dir_all(temp_dir.join("up-rs"))?;
+
+    info!("Downloading fallback repo tarball from {url}");
+    let mut response = reqwest::blocking::get(url)?.error_for_status()?;
+    let mut tarball = fs::File::create(&tarball_path)?;
+    io::copy(&mut response, &mut tarball)?;
+
+    // Remove files from previous downloads so they don't linger.
+    if extract_path.exists() {
+        fs::remove_dir_all(&extract_path)?;
+    }
+    files::create_dir_all(&extract_path)?;
+    cmd!(
+        "tar",
+        "-xzf",
+        &tarball_path,
+        "--strip-components=1",
+        "-C",
+        &extract_path
+    )
+    .run_with_inherit()?;
+    Ok(extract_path)
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
+    use super::check_min_version;
+    use super::github_tarball_url;
+    use super::is_tarball_url;
+    use super::select_host;
+    use super::HostConfig;
     use super::UpConfig;
     use color_eyre::Result;
     use serial_test::serial;
+    use std::collections::HashMap;
     use std::env;
     use testutils::ensure_eq;
 
@@ -296,4 +675,77 @@ mod tests {
 
         Ok(())
     }
+
+    /// Fallback URLs that should be downloaded as tarballs rather than cloned with git.
+    #[test]
+    fn test_fallback_tarball_urls() -> Result<()> {
+        ensure_eq!(
+            Some("https://codeload.github.com/gibfahn/dot/tar.gz/HEAD".to_owned()),
+            github_tarball_url("https://github.com/gibfahn/dot")
+        );
+        ensure_eq!(
+            Some("https://codeload.github.com/gibfahn/dot/tar.gz/HEAD".to_owned()),
+            github_tarball_url("https://github.com/gibfahn/dot.git/")
+        );
+        ensure_eq!(None, github_tarball_url("https://gitlab.com/gibfahn/dot"));
+        ensure_eq!(None, github_tarball_url("https://github.com/gibfahn"));
+
+        ensure_eq!(
+            true,
+            is_tarball_url("https://codeload.github.com/gibfahn/dot/tar.gz/HEAD")
+        );
+        ensure_eq!(true, is_tarball_url("https://example.com/dot.tgz"));
+        ensure_eq!(false, is_tarball_url("https://github.com/gibfahn/dot"));
+        Ok(())
+    }
+
+    /// Configs can require a minimum up version.
+    #[test]
+    fn test_check_min_version() -> Result<()> {
+        check_min_version("0.15", "0.15.0")?;
+        check_min_version("0.15", "0.16.2")?;
+        check_min_version("0.15.1", "1.0.0")?;
+        ensure_eq!(true, check_min_version("0.15", "0.14.9").is_err());
+        ensure_eq!(true, check_min_version("0.15.3", "0.15.2").is_err());
+        ensure_eq!(true, check_min_version("not-a-version", "0.15.0").is_err());
+        Ok(())
+    }
+
+    /// The `hosts` entry is picked by `--host`, or else the first identifier that matches.
+    #[test]
+    fn test_select_host() -> Result<()> {
+        let hosts = HashMap::from([
+            ("work-laptop".to_owned(), HostConfig::default()),
+            ("C02XL0GHJGH5".to_owned(), HostConfig::default()),
+        ]);
+        let identifiers = || {
+            vec![
+                ("hostname", "work-laptop.corp.example.com".to_owned()),
+                ("short hostname", "work-laptop".to_owned()),
+                ("serial number", "C02XL0GHJGH5".to_owned()),
+            ]
+        };
+
+        let host = select_host(&hosts, None, identifiers)?;
+        ensure_eq!(
+            Some("work-laptop (matched by short hostname)".to_owned()),
+            host.map(|host| host.to_string())
+        );
+
+        let host = select_host(&hosts, Some("C02XL0GHJGH5"), identifiers)?;
+        ensure_eq!(
+            Some("C02XL0GHJGH5 (matched by --host)".to_owned()),
+            host.map(|host| host.to_string())
+        );
+
+        ensure_eq!(
+            true,
+            select_host(&hosts, Some("home"), identifiers).is_err()
+        );
+        ensure_eq!(
+            true,
+            select_host(&hosts, None, || vec![("hostname", "home".to_owned())])?.is_none()
+        );
+        Ok(())
+    }
 }
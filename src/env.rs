@@ -14,14 +14,55 @@ These env vars are automatically resolved, and will override the same env var se
 The `UP_HARDWARE_UUID` maps to the UUID of the currently executing macOS device. This is particularly useful for setting per-host defaults.
 On non-macOS platforms this resolves to the empty string.
 
+### Colour env vars
+
+Tasks are told whether to colour their output to match up's stderr (see `up --color`). If up's
+output is coloured `CLICOLOR=1`, `CLICOLOR_FORCE=1`, and `FORCE_COLOR=1` are set, otherwise
+`NO_COLOR=1`, `CLICOLOR=0`, and `FORCE_COLOR=0` are set. Unlike the other built-in env vars, values
+you set in `up.yaml` or an env file take precedence.
+
+## PATH
+
+If the `path` field of `up.yaml` is set, the task `PATH` is built from its entries, so tasks find
+the same commands whether up is run from a login shell, launchd, or over SSH:
+
+```yaml
+path: ["~/bin", "/opt/homebrew/bin", "$PATH"]
+```
+
+Entries have `~` and env vars expanded, `$PATH` expands to the `PATH` set by the other env config
+(or up's own `PATH` if that doesn't set one). Empty and duplicate entries are dropped.
+
+## Env Files
+
+Files listed in the `env_file` field of `up.yaml` are loaded (in order, later files overriding
+earlier ones) before the `env` field is resolved, so `env` values can refer to them. Values set in
+an env file override the same env var in the `env` field, so machine-local secrets and overrides
+can be kept out of your committed config. Env files that don't exist are skipped.
+
+Env files use the dotenv format:
+
+```sh
+# Comment
+KEY=value
+export OTHER_KEY="double quoted, with \"escapes\"\n"
+SINGLE_QUOTED='taken literally'
+```
+
 */
 use self::EnvError as E;
+use crate::opts::NO_COLOR;
 use crate::utils::files;
+use crate::utils::interpolate::interpolate;
+use crate::utils::interpolate::InterpolateError;
+use camino::Utf8PathBuf;
 use color_eyre::eyre::bail;
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Result;
 use displaydoc::Display;
 use std::collections::HashMap;
+use std::fs;
+use std::io;
 use thiserror::Error;
 use tracing::debug;
 use tracing::trace;
@@ -30,12 +71,20 @@ use tracing::trace;
 /// the defaults `run_lib` or subcommand.
 pub const UP_HARDWARE_UUID: &str = "UP_HARDWARE_UUID";
 
+/// Environment variable set to the directory containing `up.yaml`. Relative paths in task data
+/// are resolved against it.
+pub const UP_CONFIG_DIR: &str = "UP_CONFIG_DIR";
+
+/// Environment variables that up sets for tasks itself, so they don't need to be in the config.
+pub const BUILTIN_ENV_VARS: &[&str] = &[UP_HARDWARE_UUID, UP_CONFIG_DIR];
+
 // TODO(gib): add tests for cyclical config values etc.
 /// Build a set of environment variables from the up config settings and the current command's
 /// environment..
 #[allow(clippy::implicit_hasher)]
 pub fn get_env(
     inherit_env: Option<&Vec<String>>,
+    env_files: &[String],
     input_env: Option<&HashMap<String, String>>,
 ) -> Result<HashMap<String, String>> {
     let mut env: HashMap<String, String> = HashMap::new();
@@ -47,40 +96,59 @@ pub fn get_env(
         }
     }
 
+    let file_env = read_env_files(env_files)?;
+    env.extend(file_env.clone());
+
     add_builtin_env_vars(&mut env)?;
 
-    let mut unresolved_env = Vec::new();
+    // Config env vars that refer to other config env vars, they're expanded once those are.
+    let mut unresolved_env: Vec<String> = Vec::new();
+    let home_dir = files::home_dir()?;
 
     if let Some(config_env) = input_env {
         trace!("Provided env: {config_env:#?}");
         let mut calculated_env = HashMap::new();
-        let home_dir = files::home_dir()?;
         for (key, val) in config_env {
-            calculated_env.insert(
-                key.clone(),
-                shellexpand::full_with_context(
-                    val,
-                    || Some(&home_dir),
-                    |k| {
-                        env.get(k).map_or_else(
-                            || {
-                                if config_env.contains_key(k) {
-                                    unresolved_env.push(key.clone());
-                                    Ok(None)
-                                } else {
-                                    Err(eyre!("Value {k} not found in inherited_env or env vars."))
-                                }
-                            },
-                            |val| Ok(Some(val)),
-                        )
-                    },
-                )
-                .map_err(|e| E::EnvLookup {
-                    var: e.var_name,
-                    source: e.cause,
-                })?
-                .into_owned(),
-            );
+            if file_env.contains_key(key) {
+                debug!("Not setting env var {key} from env as it is set in an env file.");
+                continue;
+            }
+            // Run commands before expanding env vars, so only those written in the config run.
+            let val = interpolate(val, &env).map_err(|e| E::Interpolate {
+                var: key.clone(),
+                source: e,
+            })?;
+            let mut waiting = false;
+            let expanded = shellexpand::full_with_context(
+                &val,
+                || Some(&home_dir),
+                |k| {
+                    env.get(k).map_or_else(
+                        || {
+                            if config_env.contains_key(k) {
+                                waiting = true;
+                                Ok(None)
+                            } else {
+                                Err(eyre!("Value {k} not found in inherited_env or env vars."))
+                            }
+                        },
+                        |val| Ok(Some(val)),
+                    )
+                },
+            )
+            .map_err(|e| E::EnvLookup {
+                var: e.var_name,
+                source: e.cause,
+            })?
+            .into_owned();
+            // Keep the unexpanded value, so it's only expanded once (and escaped `$`s from command
+            // output stay escaped) when the env vars it refers to are resolved.
+            if waiting {
+                unresolved_env.push(key.clone());
+                calculated_env.insert(key.clone(), val);
+            } else {
+                calculated_env.insert(key.clone(), expanded);
+            }
         }
         for (k, v) in calculated_env.drain() {
             env.insert(k, v);
@@ -92,50 +160,228 @@ pub fn get_env(
     while !unresolved_env.is_empty() {
         trace!("Env so far: {env:#?}");
         trace!("Still unresolved env: {unresolved_env:#?}");
-        let mut resolved_indices = Vec::new();
-        for (index, key) in unresolved_env.iter().enumerate() {
+        let mut still_unresolved = Vec::new();
+        for key in &unresolved_env {
             let val = env.get(key).ok_or_else(|| eyre!("How did we get here?"))?;
-            let resolved_val = shellexpand::env_with_context(val, |k| {
-                if unresolved_env.iter().any(|s| s == k) {
-                    Ok(None)
-                } else if let Some(v) = env.get(k) {
-                    resolved_indices.push(index);
-                    Ok(Some(v))
-                } else {
-                    Err(eyre!("Shouldn't be possible to hit this."))
-                }
-            })
+            let mut waiting = false;
+            let resolved_val = shellexpand::full_with_context(
+                val,
+                || Some(&home_dir),
+                |k| {
+                    if unresolved_env.iter().any(|s| s == k) {
+                        waiting = true;
+                        Ok(None)
+                    } else if let Some(v) = env.get(k) {
+                        Ok(Some(v))
+                    } else {
+                        Err(eyre!("Shouldn't be possible to hit this."))
+                    }
+                },
+            )
             .map_err(|e| E::EnvLookup {
                 var: e.var_name,
                 source: e.cause,
             })?
             .into_owned();
-            let val_ref = env
-                .get_mut(key)
-                .ok_or_else(|| eyre!("How did we get here?"))?;
-            *val_ref = resolved_val;
+            if waiting {
+                still_unresolved.push(key.clone());
+            } else {
+                env.insert(key.clone(), resolved_val);
+            }
         }
-        trace!("resolved indices: {resolved_indices:?}");
-        if resolved_indices.is_empty() {
+        if still_unresolved.len() == unresolved_env.len() {
             bail!("Errors resolving env, do you have cycles? Unresolved env: {unresolved_env:#?}",);
         }
-        unresolved_env = unresolved_env
-            .into_iter()
-            .enumerate()
-            .filter_map(|(index, value)| {
-                if resolved_indices.contains(&index) {
-                    None
-                } else {
-                    Some(value)
-                }
-            })
-            .collect();
+        unresolved_env = still_unresolved;
     }
 
     debug!("Expanded config env: {env:#?}");
     Ok(env)
 }
 
+/// Read the env vars set in the dotenv-format `env_files`, later files overriding earlier ones.
+/// Files that don't exist are skipped.
+pub(crate) fn read_env_files(env_files: &[String]) -> Result<HashMap<String, String>> {
+    let mut env = HashMap::new();
+    if env_files.is_empty() {
+        return Ok(env);
+    }
+    let home_dir = files::home_dir()?;
+    for env_file in env_files {
+        let path = Utf8PathBuf::from(
+            shellexpand::full_with_context_no_errors(
+                env_file,
+                || Some(&home_dir),
+                |_| None::<&str>,
+            )
+            .into_owned(),
+        );
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("Skipping env file {path} as it doesn't exist.");
+                continue;
+            }
+            Err(e) => return Err(E::EnvFileRead { path, source: e }.into()),
+        };
+        let vars = parse_env_file(&contents).map_err(|line| E::EnvFileParse {
+            path: path.clone(),
+            line,
+        })?;
+        trace!("Env vars set in {path}: {:?}", vars.keys());
+        env.extend(vars);
+    }
+    Ok(env)
+}
+
+/// Parse the contents of a dotenv-format file, returning the line number (starting from 1) of the
+/// first invalid line on failure.
+fn parse_env_file(contents: &str) -> Result<HashMap<String, String>, usize> {
+    let mut env = HashMap::new();
+    let mut lines = contents.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line.split_once('=').ok_or(index + 1)?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(index + 1);
+        }
+        let value = value.trim_start();
+
+        let value = if let Some(quote @ ('"' | '\'')) = value.chars().next() {
+            // Quoted values can span multiple lines.
+            let mut raw = value[1..].to_owned();
+            let end = loop {
+                if let Some(end) = closing_quote(&raw, quote) {
+                    break end;
+                }
+                let (_, next_line) = lines.next().ok_or(index + 1)?;
+                raw.push('\n');
+                raw.push_str(next_line);
+            };
+            raw.truncate(end);
+            if quote == '"' {
+                unescape(&raw)
+            } else {
+                raw
+            }
+        } else {
+            // Unquoted values end at an inline comment.
+            value
+                .split(" #")
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .to_owned()
+        };
+        env.insert(key.to_owned(), value);
+    }
+    Ok(env)
+}
+
+/// Index of the unescaped `quote` character that ends a quoted value, if any.
+fn closing_quote(value: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            c if c == quote => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Expand the backslash escapes allowed in double-quoted values.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Tell tasks whether to colour their output, without overriding values the user set.
+#[allow(clippy::implicit_hasher)]
+pub fn add_color_env_vars(env: &mut HashMap<String, String>, color: bool) {
+    let vars: &[(&str, &str)] = if color {
+        &[
+            ("CLICOLOR", "1"),
+            ("CLICOLOR_FORCE", "1"),
+            ("FORCE_COLOR", "1"),
+        ]
+    } else {
+        &[(NO_COLOR, "1"), ("CLICOLOR", "0"), ("FORCE_COLOR", "0")]
+    };
+    for (key, value) in vars {
+        env.entry((*key).to_owned())
+            .or_insert_with(|| (*value).to_owned());
+    }
+}
+
+/// Set `PATH` in `env` to the resolved entries of the `path` config field.
+#[allow(clippy::implicit_hasher)]
+pub fn add_path_env_var(env: &mut HashMap<String, String>, path: &[String]) -> Result<()> {
+    let home_dir = files::home_dir()?;
+    let resolved = resolve_path(path, env, home_dir.as_str(), |k| std::env::var(k).ok())?;
+    debug!("Setting task PATH to {resolved}");
+    env.insert("PATH".to_owned(), resolved);
+    Ok(())
+}
+
+/// Expand and join the `path` entries, looking up env vars in `env` and then with `fallback`.
+fn resolve_path(
+    path: &[String],
+    env: &HashMap<String, String>,
+    home_dir: &str,
+    fallback: impl Fn(&str) -> Option<String>,
+) -> Result<String, E> {
+    let mut entries: Vec<String> = Vec::new();
+    for entry in path {
+        let expanded = shellexpand::full_with_context(
+            entry,
+            || Some(home_dir),
+            |k| {
+                env.get(k)
+                    .cloned()
+                    .or_else(|| fallback(k))
+                    .map(Some)
+                    .ok_or_else(|| eyre!("Value {k} not found in env or up's env vars."))
+            },
+        )
+        .map_err(|e| E::EnvLookup {
+            var: e.var_name,
+            source: e.cause,
+        })?;
+        for dir in expanded.split(':') {
+            if !dir.is_empty() && !entries.iter().any(|existing| existing == dir) {
+                entries.push(dir.to_owned());
+            }
+        }
+    }
+    Ok(entries.join(":"))
+}
+
 /// Add environment variables that up generates automatically to the resolved environment.
 fn add_builtin_env_vars(env: &mut HashMap<String, String>) -> Result<()> {
     env.insert(
@@ -159,4 +405,154 @@ pub enum EnvError {
         /// Source error.
         source: color_eyre::eyre::Error,
     },
+    /// Failed to interpolate command output into env var `{var}`.
+    Interpolate {
+        /// Env var being set.
+        var: String,
+        /// Source error.
+        source: InterpolateError,
+    },
+    /// Failed to read env file `{path}`.
+    EnvFileRead {
+        /// Env file path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: io::Error,
+    },
+    /// Invalid line in env file `{path}` at line {line}, expected `KEY=value`.
+    EnvFileParse {
+        /// Env file path.
+        path: Utf8PathBuf,
+        /// Line number of the invalid line.
+        line: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::add_color_env_vars;
+    use super::get_env;
+    use super::parse_env_file;
+    use super::resolve_path;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::collections::HashMap;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_add_color_env_vars() -> Result<()> {
+        let mut env = HashMap::from([("FORCE_COLOR".to_owned(), "3".to_owned())]);
+        add_color_env_vars(&mut env, true);
+        ensure_eq!(
+            HashMap::from([
+                ("CLICOLOR".to_owned(), "1".to_owned()),
+                ("CLICOLOR_FORCE".to_owned(), "1".to_owned()),
+                ("FORCE_COLOR".to_owned(), "3".to_owned()),
+            ]),
+            env
+        );
+
+        let mut env = HashMap::new();
+        add_color_env_vars(&mut env, false);
+        ensure_eq!(Some("1"), env.get("NO_COLOR").map(String::as_str));
+        ensure_eq!(Some("0"), env.get("CLICOLOR").map(String::as_str));
+        ensure_eq!(None, env.get("CLICOLOR_FORCE"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_env_interpolate() -> Result<()> {
+        let config_env = HashMap::from([
+            ("prefix".to_owned(), "$(cmd echo /opt/brew)".to_owned()),
+            ("bin".to_owned(), "${prefix}/bin".to_owned()),
+            ("dollar".to_owned(), "$(cmd echo '$bin')".to_owned()),
+            // Expanded once the config env var it refers to is, still leaving the output as is.
+            (
+                "dollar_prefix".to_owned(),
+                "$(cmd echo '$bin') ${prefix}".to_owned(),
+            ),
+            // Commands in the values of env vars aren't run.
+            ("expanded".to_owned(), "${COMMAND}".to_owned()),
+        ]);
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let env_file = temp_dir.join("env");
+        std::fs::write(&env_file, "COMMAND='$(cmd false)'\n")?;
+        let env = get_env(None, &[env_file.to_string()], Some(&config_env))?;
+        ensure_eq!(Some("/opt/brew"), env.get("prefix").map(String::as_str));
+        ensure_eq!(Some("/opt/brew/bin"), env.get("bin").map(String::as_str));
+        ensure_eq!(Some("$bin"), env.get("dollar").map(String::as_str));
+        ensure_eq!(
+            Some("$bin /opt/brew"),
+            env.get("dollar_prefix").map(String::as_str)
+        );
+        ensure_eq!(
+            Some("$(cmd false)"),
+            env.get("expanded").map(String::as_str)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_path() -> Result<()> {
+        let path: Vec<String> = ["~/bin", "/opt/homebrew/bin", "$PATH", "$EXTRA_DIR", "~/bin"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+        let env = HashMap::from([("EXTRA_DIR".to_owned(), "/extra".to_owned())]);
+        let fallback = |k: &str| (k == "PATH").then(|| "/usr/bin::/opt/homebrew/bin".to_owned());
+        ensure_eq!(
+            "/home/me/bin:/opt/homebrew/bin:/usr/bin:/extra",
+            resolve_path(&path, &env, "/home/me", fallback)?
+        );
+
+        // The env's PATH is used in preference to up's own.
+        let env = HashMap::from([
+            ("EXTRA_DIR".to_owned(), "/extra".to_owned()),
+            ("PATH".to_owned(), "/env/bin".to_owned()),
+        ]);
+        ensure_eq!(
+            "/home/me/bin:/opt/homebrew/bin:/env/bin:/extra",
+            resolve_path(&path, &env, "/home/me", fallback)?
+        );
+
+        ensure!(resolve_path(&path, &HashMap::new(), "/home/me", |_| None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_env_file() -> Result<()> {
+        let contents = r#"
+# A comment.
+PLAIN=value
+export EXPORTED=exported
+SPACES = spaced out  # inline comment
+EMPTY=
+DOUBLE="double \"quoted\"\nvalue # not a comment"
+SINGLE='single $NOT_EXPANDED \n'
+MULTI="first
+second"
+"#;
+        let expected: HashMap<String, String> = [
+            ("PLAIN", "value"),
+            ("EXPORTED", "exported"),
+            ("SPACES", "spaced out"),
+            ("EMPTY", ""),
+            ("DOUBLE", "double \"quoted\"\nvalue # not a comment"),
+            ("SINGLE", "single $NOT_EXPANDED \\n"),
+            ("MULTI", "first\nsecond"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+        ensure_eq!(Ok(expected), parse_env_file(contents));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_env_file_errors() -> Result<()> {
+        ensure_eq!(Err(2), parse_env_file("A=1\nnot a var\n"));
+        ensure_eq!(Err(2), parse_env_file("A=1\nB C=2\n"));
+        ensure_eq!(Err(1), parse_env_file("A=\"unterminated\n"));
+        Ok(())
+    }
 }
@@ -21,6 +21,20 @@ pub enum UpError {
         /// Source error.
         source: io::Error,
     },
+    /**
+    Failed to back up file.
+
+    From: `{path}`
+    To: `{backup_path}`
+    */
+    BackupError {
+        /// Path we tried to back up.
+        path: Utf8PathBuf,
+        /// Path we tried to back it up to.
+        backup_path: Utf8PathBuf,
+        /// Source error.
+        source: io::Error,
+    },
     /// Couldn't calculate the current user's home directory.
     NoHomeDir,
 }
@@ -5,12 +5,14 @@ use crate::tasks::TasksAction;
 use crate::tasks::TasksDir;
 use color_eyre::eyre::Result;
 
+pub(crate) mod brew;
 pub mod git;
 
 /// Comment to add to top of files generated by this program.
-const GENERATED_PRELUDE_COMMENT: &str = "# This file was auto-generated by up-rs.\n";
+pub(crate) const GENERATED_PRELUDE_COMMENT: &str = "# This file was auto-generated by up-rs.\n";
 
 /// Run `up generate` subcommand.
 pub fn run(config: &config::UpConfig) -> Result<()> {
-    tasks::run(config, TasksDir::GenerateTasks, TasksAction::Run)
+    tasks::run(config, TasksDir::GenerateTasks, TasksAction::Run)?;
+    Ok(())
 }
@@ -0,0 +1,180 @@
+/*!
+Generate a `brew` task from what Homebrew has installed (`up generate brew-dump`), like
+`brew bundle dump` but writing up's `brew` run library config.
+
+Formulae are the ones installed on request that nothing else depends on (`brew leaves`), so
+dependencies aren't listed. Mac App Store apps are only included if `mas` is installed. Each list
+is sorted alphabetically, so regenerating the file only changes it when what's installed changes.
+*/
+use self::GenerateBrewError as E;
+use super::GENERATED_PRELUDE_COMMENT;
+use crate::opts::GenerateBrewConfig;
+use crate::tasks::brew;
+use crate::tasks::brew::BrewConfig;
+use crate::tasks::brew::BrewError;
+use crate::tasks::brew::MasApp;
+use crate::tasks::task::Task;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskConfig;
+use crate::tasks::task::TaskStatus;
+use crate::utils::files;
+use crate::utils::files::AtomicWriteOptions;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use std::fs;
+use std::io::ErrorKind;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+
+/// Run `up generate brew-dump`, writing the installed packages to the task file.
+pub(crate) fn run(opts: &GenerateBrewConfig) -> Result<TaskStatus> {
+    let path = &opts.path;
+    let mas = match brew::list("mas", &["list"]) {
+        Ok(output) => brew::parse_mas_list(&output),
+        Err(BrewError::List { source, .. }) if source.kind() == ErrorKind::NotFound => {
+            debug!("Not dumping Mac App Store apps as mas isn't installed.");
+            Vec::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let brew_config = dump_config(
+        brew::list_taps()?,
+        brew::list("brew", &["leaves", "--installed-on-request"])?,
+        brew::list_casks()?,
+        mas,
+        &opts.exclude,
+    );
+
+    let existing_contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut task_config = if existing_contents.is_empty() {
+        TaskConfig {
+            run_lib: Some("brew".to_owned()),
+            ..TaskConfig::default()
+        }
+    } else {
+        Task::parse_config(path, &existing_contents)?
+    };
+    task_config.data = Some(serde_yaml::to_value(brew_config)?);
+    let mut serialized_task = GENERATED_PRELUDE_COMMENT.to_owned();
+    serialized_task.push_str(&serde_yaml::to_string(&task_config)?);
+
+    if serialized_task == existing_contents {
+        info!("Skipped writing '{path}' as the installed Homebrew packages are unchanged.");
+        return Ok(TaskStatus::Skipped);
+    }
+    if opts.check {
+        return Err(E::WouldChange { path: path.clone() }.into());
+    }
+    files::atomic_write(
+        path,
+        serialized_task,
+        AtomicWriteOptions {
+            fsync: true,
+            ..AtomicWriteOptions::default()
+        },
+    )?;
+    info!("Installed Homebrew packages written to '{path}'.");
+    Ok(TaskStatus::Passed(TaskChanges::default()))
+}
+
+/// The config to install the `taps`, `formulae`, `casks`, and `mas` apps, without any in `exclude`
+/// and sorted.
+fn dump_config(
+    taps: Vec<String>,
+    formulae: Vec<String>,
+    casks: Vec<String>,
+    mas: Vec<MasApp>,
+    exclude: &[String],
+) -> BrewConfig {
+    let excluded = |name: &str| {
+        let short_name = name.rsplit('/').next().unwrap_or(name);
+        exclude
+            .iter()
+            .any(|excluded| excluded == name || excluded == short_name)
+    };
+    let sorted = |names: Vec<String>| {
+        let mut names: Vec<String> = names.into_iter().filter(|name| !excluded(name)).collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    };
+    let mut mas: Vec<MasApp> = mas
+        .into_iter()
+        .filter(|app| !excluded(&app.name) && !excluded(&app.id.to_string()))
+        .collect();
+    mas.sort_unstable_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+    mas.dedup();
+    BrewConfig {
+        taps: sorted(taps),
+        formulae: sorted(formulae),
+        casks: sorted(casks),
+        mas,
+    }
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum GenerateBrewError {
+    /// Generated brew task `{path}` is out of date, run `up generate brew-dump` to update it.
+    WouldChange {
+        /// Task file path.
+        path: Utf8PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dump_config;
+    use crate::tasks::brew::MasApp;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_dump_config() -> Result<()> {
+        let to_strings = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect::<Vec<_>>();
+        let config = dump_config(
+            to_strings(&["homebrew/cask-fonts", "gibfahn/tap"]),
+            to_strings(&["ripgrep", "git", "gibfahn/tap/up", "node"]),
+            to_strings(&["zoom", "firefox"]),
+            vec![
+                MasApp {
+                    name: "Xcode".to_owned(),
+                    id: 497_799_835,
+                },
+                MasApp {
+                    name: "Amphetamine".to_owned(),
+                    id: 937_984_704,
+                },
+                MasApp {
+                    name: "Keynote".to_owned(),
+                    id: 409_183_694,
+                },
+            ],
+            &to_strings(&["node", "up", "zoom", "409183694"]),
+        );
+        ensure_eq!(
+            "taps:
+- gibfahn/tap
+- homebrew/cask-fonts
+formulae:
+- git
+- ripgrep
+casks:
+- firefox
+mas:
+- name: Amphetamine
+  id: 937984704
+- name: Xcode
+  id: 497799835
+",
+            serde_yaml::to_string(&config)?
+        );
+        Ok(())
+    }
+}
@@ -2,13 +2,19 @@
 use self::GenerateGitError as E;
 use super::GENERATED_PRELUDE_COMMENT;
 use crate::opts::GenerateGitConfig;
+use crate::tasks::git::fetch::GitRetry;
 use crate::tasks::git::GitConfig;
 use crate::tasks::git::GitRemote;
+use crate::tasks::git::IGNORE_MARKER_FILE;
+use crate::tasks::resolve_config_path;
 use crate::tasks::task::Task;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskConfig;
 use crate::tasks::task::TaskStatus;
 use crate::tasks::ResolveEnv;
 use crate::tasks::TaskError;
 use crate::utils::files;
+use crate::utils::files::AtomicWriteOptions;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use color_eyre::eyre::Context;
@@ -17,7 +23,10 @@ use displaydoc::Display;
 use git2::Repository;
 use rayon::iter::Either;
 use rayon::prelude::*;
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::ErrorKind;
+use std::iter;
 use thiserror::Error;
 use tracing::debug;
 use tracing::error;
@@ -40,7 +49,7 @@ pub fn run(configs: &[GenerateGitConfig]) -> Result<TaskStatus> {
         if statuses.iter().all(|s| matches!(s, TaskStatus::Skipped)) {
             Ok(TaskStatus::Skipped)
         } else {
-            Ok(TaskStatus::Passed)
+            Ok(TaskStatus::Passed(TaskChanges::default()))
         }
     } else {
         for error in &errors {
@@ -51,47 +60,202 @@ pub fn run(configs: &[GenerateGitConfig]) -> Result<TaskStatus> {
     }
 }
 
+/**
+Marker comment for the managed section of a generated git task file.
+
+If a line in the existing task file starts with this marker (it should be placed inside the `data:`
+list), everything above it is kept as-is when regenerating, and only the entries below it are
+rewritten. Repos listed above the marker are left out of the generated entries.
+
+```yaml
+run_lib: git
+data:
+- path: ~/work/manually_added_repo
+  remotes: [{name: origin, fetch_url: https://example.com/repo.git}]
+# up-rs managed section, entries below this line are regenerated.
+- path: ~/code/generated_repo
+  ...
+```
+*/
+pub(crate) const MANAGED_SECTION_MARKER: &str = "# up-rs managed section";
+
 /// Run a single git config generation.
 pub fn run_single(generate_git_config: &GenerateGitConfig) -> Result<TaskStatus> {
     let _span =
         tracing::info_span!("generate_git", repo = &generate_git_config.path.as_str()).entered();
     debug!("Generating git config");
-    let mut git_task = Task::from(&generate_git_config.path)?;
-    debug!("Existing git config: {git_task:?}");
-    let name = git_task.name.as_str();
-    let mut git_configs = Vec::new();
+    let path = &generate_git_config.path;
+    let routes = generate_git_config
+        .route
+        .iter()
+        .map(|route| {
+            let pattern = glob::Pattern::new(&route.pattern).map_err(|e| E::InvalidRoute {
+                pattern: route.pattern.clone(),
+                source: e,
+            })?;
+            let route_path = path
+                .parent()
+                .map_or_else(|| route.path.clone(), |dir| dir.join(&route.path));
+            Ok((pattern, route_path))
+        })
+        .collect::<Result<Vec<_>, E>>()?;
+
+    // The generated configs for each task file, including files with no repos so they're updated.
+    let mut task_files: BTreeMap<&Utf8Path, Vec<GitConfig>> = iter::once(path.as_path())
+        .chain(routes.iter().map(|(_, route_path)| route_path.as_path()))
+        .map(|task_path| (task_path, Vec::new()))
+        .collect();
     let home_dir = files::home_dir()?;
-    for path in find_repos(
+    for repo_path in find_repos(
         &generate_git_config.search_paths,
         generate_git_config.excludes.as_ref(),
+        &generate_git_config.skip_paths,
     )? {
-        git_configs.push(parse_git_config(
-            &path,
+        let git_config = parse_git_config(
+            &repo_path,
             generate_git_config.prune,
             &generate_git_config.remote_order,
             &home_dir,
-        )?);
+        )?;
+        let task_path = routes
+            .iter()
+            .find(|(pattern, _)| pattern.matches(repo_path.as_str()))
+            .map_or(path.as_path(), |(_, route_path)| route_path.as_path());
+        trace!("Adding {repo_path} to '{task_path}'.");
+        task_files.entry(task_path).or_default().push(git_config);
     }
 
-    git_configs.sort_unstable_by(|c1, c2| c1.path.cmp(&c2.path));
+    let mut status = TaskStatus::Skipped;
+    for (task_path, git_configs) in task_files {
+        if let TaskStatus::Passed(_) =
+            write_task_file(task_path, git_configs, generate_git_config.check)?
+        {
+            status = TaskStatus::Passed(TaskChanges::default());
+        }
+    }
+    Ok(status)
+}
 
-    git_task.config.data = Some(serde_yaml::to_value(git_configs)?);
+/**
+Write the `git_configs` to the git task file at `path`, creating it if it doesn't exist, and
+keeping its manual section (if any).
 
-    debug!("New git config: {git_task:?}");
-    let mut serialized_task = GENERATED_PRELUDE_COMMENT.to_owned();
-    serialized_task.push_str(&serde_yaml::to_string(&git_task.config)?);
+With `check`, errors instead of writing if the file would change.
+*/
+fn write_task_file(
+    path: &Utf8Path,
+    mut git_configs: Vec<GitConfig>,
+    check: bool,
+) -> Result<TaskStatus> {
+    let existing_contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let manual_section = manual_section(&existing_contents);
+    let manual_paths: Vec<Utf8PathBuf> = match manual_section {
+        Some(manual_section) => {
+            let manual_task: TaskConfig =
+                serde_yaml::from_str(manual_section).map_err(|e| E::InvalidManualSection {
+                    path: path.to_owned(),
+                    source: e,
+                })?;
+            manual_task
+                .data
+                .map(serde_yaml::from_value::<Vec<GitConfig>>)
+                .transpose()
+                .map_err(|e| E::InvalidManualSection {
+                    path: path.to_owned(),
+                    source: e,
+                })?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|config| config.path)
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    let git_task_config = if existing_contents.is_empty() {
+        TaskConfig {
+            run_lib: Some("git".to_owned()),
+            ..TaskConfig::default()
+        }
+    } else {
+        Task::parse_config(path, &existing_contents)?
+    };
+    let mut git_task = Task::from_config(path, git_task_config)?;
+    debug!("Existing git config: {git_task:?}");
+    let name = git_task.name.as_str();
+
+    git_configs.retain(|git_config| {
+        let manual = manual_paths.contains(&git_config.path);
+        if manual {
+            debug!(
+                "Skipping {repo_path} as it is in the manual section.",
+                repo_path = git_config.path
+            );
+        }
+        !manual
+    });
+    git_configs.sort_unstable_by(|c1, c2| c1.path.cmp(&c2.path));
+
+    let serialized_task = if let Some(manual_section) = manual_section {
+        let mut serialized_task = manual_section.to_owned();
+        serialized_task.push_str(MANAGED_SECTION_MARKER);
+        serialized_task.push_str(", entries below this line are regenerated.\n");
+        if !git_configs.is_empty() {
+            serialized_task.push_str(&serde_yaml::to_string(&git_configs)?);
+        }
+        // Make sure the marker was somewhere that gives us a valid task file.
+        serde_yaml::from_str::<TaskConfig>(&serialized_task).map_err(|e| {
+            E::InvalidManualSection {
+                path: path.to_owned(),
+                source: e,
+            }
+        })?;
+        serialized_task
+    } else {
+        git_task.config.data = Some(serde_yaml::to_value(git_configs)?);
+        debug!("New git config: {git_task:?}");
+        let mut serialized_task = GENERATED_PRELUDE_COMMENT.to_owned();
+        serialized_task.push_str(&serde_yaml::to_string(&git_task.config)?);
+        serialized_task
+    };
     trace!("New yaml file: <<<{serialized_task}>>>");
-    if serialized_task == fs::read_to_string(&generate_git_config.path)? {
+    if serialized_task == existing_contents {
         info!("Skipped task '{name}' as git repo layout unchanged.",);
         return Ok(TaskStatus::Skipped);
     }
 
-    fs::write(&generate_git_config.path, serialized_task)?;
-    info!(
-        "Git repo layout generated for task '{name}' and written to '{path}'",
-        path = generate_git_config.path
-    );
-    Ok(TaskStatus::Passed)
+    if check {
+        return Err(E::WouldChange {
+            path: path.to_owned(),
+        }
+        .into());
+    }
+
+    files::atomic_write(
+        path,
+        serialized_task,
+        AtomicWriteOptions {
+            fsync: true,
+            ..AtomicWriteOptions::default()
+        },
+    )?;
+    info!("Git repo layout generated for task '{name}' and written to '{path}'");
+    Ok(TaskStatus::Passed(TaskChanges::default()))
+}
+
+/// The part of a task file above the [`MANAGED_SECTION_MARKER`] line, if it has one.
+fn manual_section(contents: &str) -> Option<&str> {
+    let mut offset = 0;
+    for line in contents.split_inclusive('\n') {
+        if line.starts_with(MANAGED_SECTION_MARKER) {
+            return Some(&contents[..offset]);
+        }
+        offset += line.len();
+    }
+    None
 }
 
 impl ResolveEnv for Vec<GenerateGitConfig> {
@@ -100,14 +264,20 @@ impl ResolveEnv for Vec<GenerateGitConfig> {
         F: Fn(&str) -> Result<String, TaskError>,
     {
         for config in self.iter_mut() {
-            config.path = Utf8PathBuf::from(env_fn(config.path.as_str())?);
+            config.path = resolve_config_path(config.path.as_str(), &env_fn)?;
 
             let mut new_search_paths = Vec::new();
             for search_path in &config.search_paths {
-                new_search_paths.push(Utf8PathBuf::from(env_fn(search_path.as_str())?));
+                new_search_paths.push(resolve_config_path(search_path.as_str(), &env_fn)?);
             }
             config.search_paths = new_search_paths;
 
+            let mut new_skip_paths = Vec::new();
+            for skip_path in &config.skip_paths {
+                new_skip_paths.push(resolve_config_path(skip_path.as_str(), &env_fn)?);
+            }
+            config.skip_paths = new_skip_paths;
+
             if let Some(excludes) = config.excludes.as_ref() {
                 let mut new_excludes = Vec::new();
                 for exclude in excludes {
@@ -115,15 +285,22 @@ impl ResolveEnv for Vec<GenerateGitConfig> {
                 }
                 config.excludes = Some(new_excludes);
             }
+
+            for route in &mut config.route {
+                route.pattern = env_fn(&route.pattern)?;
+                route.path = Utf8PathBuf::from(env_fn(route.path.as_str())?);
+            }
         }
         Ok(())
     }
 }
 
-/// Find repositories in a set of search paths.
+/// Find repositories in a set of search paths, skipping `skip_paths` and directories containing a
+/// [`IGNORE_MARKER_FILE`].
 fn find_repos(
     search_paths: &[Utf8PathBuf],
     excludes: Option<&Vec<String>>,
+    skip_paths: &[Utf8PathBuf],
 ) -> Result<Vec<Utf8PathBuf>> {
     let mut repo_paths = Vec::new();
     for path in search_paths {
@@ -149,6 +326,15 @@ fn find_repos(
                 }
             }
 
+            if entry.file_type().is_dir()
+                && (skip_paths.iter().any(|p| entry.path() == p)
+                    || entry.path().join(IGNORE_MARKER_FILE).exists())
+            {
+                debug!("Skipping '{path}'", path = entry.path().display());
+                it.skip_current_dir();
+                continue;
+            }
+
             // Add anything that has a .git dir inside it.
             if entry.file_type().is_dir() && entry.path().join(".git").is_dir() {
                 // Found matching entry, add it.
@@ -207,6 +393,11 @@ fn parse_git_config(
         branch: None,
         remotes,
         prune,
+        prune_dry_run: false,
+        trash_pruned: false,
+        clean_ignored: false,
+        retry: GitRetry::default(),
+        on_detached_head: None,
     };
     trace!("Parsed GitConfig: {config:?}");
     Ok(config)
@@ -224,4 +415,67 @@ pub enum GenerateGitError {
     },
     /// Unexpected None in option.
     UnexpectedNone,
+    /// Invalid yaml above the managed section marker in `{path}`, it should be inside `data:`.
+    InvalidManualSection {
+        /// Task file path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_yaml::Error,
+    },
+    /// Invalid `route` pattern `{pattern}`.
+    InvalidRoute {
+        /// Glob pattern.
+        pattern: String,
+        /// Source error.
+        source: glob::PatternError,
+    },
+    /// Generated git task `{path}` is out of date, run `up generate` to update it.
+    WouldChange {
+        /// Task file path.
+        path: Utf8PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_single;
+    use crate::opts::GenerateGitConfig;
+    use crate::opts::GenerateGitRoute;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use git2::Repository;
+    use std::fs;
+
+    #[test]
+    fn test_run_single_routes() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let scan_dir = temp_dir.join("scan");
+        for repo in ["personal/dotfiles", "work/api", "work/web"] {
+            Repository::init(scan_dir.join(repo))?;
+        }
+        let tasks_dir = temp_dir.join("tasks");
+        fs::create_dir_all(&tasks_dir)?;
+        fs::write(tasks_dir.join("git.yaml"), "run_lib: git\n")?;
+
+        run_single(&GenerateGitConfig {
+            path: tasks_dir.join("git.yaml"),
+            search_paths: vec![scan_dir.clone()],
+            excludes: None,
+            skip_paths: Vec::new(),
+            prune: false,
+            remote_order: Vec::new(),
+            check: false,
+            route: vec![GenerateGitRoute {
+                pattern: format!("{scan_dir}/work/**"),
+                path: "git-work.yaml".into(),
+            }],
+        })?;
+
+        let personal = fs::read_to_string(tasks_dir.join("git.yaml"))?;
+        let work = fs::read_to_string(tasks_dir.join("git-work.yaml"))?;
+        ensure!(personal.contains("personal/dotfiles") && !personal.contains("work/"));
+        ensure!(work.contains("work/api") && work.contains("work/web"));
+        ensure!(!work.contains("personal/") && work.contains("run_lib: git"));
+        Ok(())
+    }
 }
@@ -58,13 +58,18 @@
 use crate::config::UpConfig;
 use crate::opts::Opts;
 use crate::opts::SubCommand;
+use color_eyre::eyre::bail;
 use color_eyre::eyre::Result;
 use opts::DefaultsSubcommand;
 use opts::GenerateLib;
+use opts::TaskSubcommand;
+use opts::UpdateSelfSubcommand;
 use tasks::defaults;
 use tasks::TasksAction;
 use tasks::TasksDir;
+use tracing::info;
 use tracing::trace;
+use utils::backup::Backups;
 
 mod config;
 pub mod env;
@@ -90,32 +95,74 @@ pub const UP_BUNDLE_ID: &str = "co.fahn.up";
 ///
 /// [Opts]: crate::opts::Opts
 pub fn run(opts: Opts) -> Result<()> {
+    if opts.offline && matches!(opts.cmd, Some(SubCommand::Git(_) | SubCommand::Self_(_))) {
+        bail!("`up git` and `up self` need the network, so can't be run with --offline.");
+    }
+    let backups = Backups::new(&opts)?;
+    defaults::managed::set_state_dir(&opts.state_dir()?);
     match opts.cmd {
         Some(SubCommand::Link(link_options)) => {
-            tasks::link::run(link_options, &opts.temp_dir)?;
+            tasks::link::run_from_options(link_options, backups.run_dir())?;
+            backups.prune_or_warn();
         }
         Some(SubCommand::Git(git_options)) => {
-            tasks::git::update::update(&git_options.into())?;
+            if let Some(from_file) = &git_options.from_file {
+                tasks::git::run_from_file(from_file)?;
+            } else {
+                tasks::git::run_from_options(git_options)?;
+            }
         }
         Some(SubCommand::Defaults(defaults_options)) => match defaults_options.subcommand {
             DefaultsSubcommand::Read(defaults_read_opts) => {
                 defaults::read(defaults_options.current_host, defaults_read_opts)?;
             }
+            DefaultsSubcommand::Domains => defaults::domains(defaults_options.current_host)?,
             DefaultsSubcommand::Write(defaults_write_opts) => {
                 defaults::write(
                     defaults_options.current_host,
                     defaults_write_opts,
-                    &opts.temp_dir,
+                    backups.run_dir(),
+                )?;
+                backups.prune_or_warn();
+            }
+            DefaultsSubcommand::Apply(defaults_apply_opts) => {
+                defaults::apply(
+                    defaults_options.current_host,
+                    defaults_apply_opts,
+                    backups.run_dir(),
                 )?;
+                backups.prune_or_warn();
+            }
+            DefaultsSubcommand::Unmanage(defaults_unmanage_opts) => {
+                defaults::unmanage(
+                    defaults_options.current_host,
+                    defaults_unmanage_opts,
+                    backups.run_dir(),
+                )?;
+                backups.prune_or_warn();
+            }
+            DefaultsSubcommand::Merge(defaults_merge_opts) => {
+                defaults::merge(
+                    defaults_options.current_host,
+                    defaults_merge_opts,
+                    backups.run_dir(),
+                )?;
+                backups.prune_or_warn();
+            }
+        },
+        Some(SubCommand::Self_(cmd_opts)) => match cmd_opts.subcommand {
+            Some(UpdateSelfSubcommand::Check) => tasks::update_self::check()?,
+            None => {
+                tasks::update_self::run(&cmd_opts)?;
             }
         },
-        Some(SubCommand::Self_(cmd_opts)) => {
-            tasks::update_self::run(&cmd_opts)?;
-        }
         Some(SubCommand::Generate(ref cmd_opts)) => match cmd_opts.lib {
             Some(GenerateLib::Git(ref git_opts)) => {
                 generate::git::run_single(git_opts)?;
             }
+            Some(GenerateLib::BrewDump(ref brew_opts)) => {
+                generate::brew::run(brew_opts)?;
+            }
             Some(GenerateLib::Defaults(ref defaults_opts)) => {
                 trace!("Options: {defaults_opts:?}");
                 // TODO(gib): implement defaults generation.
@@ -126,23 +173,102 @@ pub fn run(opts: Opts) -> Result<()> {
                 generate::run(&config)?;
             }
         },
-        Some(SubCommand::Completions(ref cmd_opts)) => {
-            tasks::completions::run(cmd_opts);
+        Some(SubCommand::Import(ref cmd_opts)) => {
+            let cmd_opts = cmd_opts.clone();
+            let tasks_dir = match &cmd_opts.tasks_dir {
+                Some(tasks_dir) => tasks_dir.clone(),
+                None => tasks::import::default_tasks_dir(&UpConfig::from(opts)?)?,
+            };
+            tasks::import::run(&cmd_opts, &tasks_dir)?;
+        }
+        Some(SubCommand::Task(ref cmd_opts)) => match cmd_opts.subcommand {
+            TaskSubcommand::New(ref new_opts) => {
+                let new_opts = new_opts.clone();
+                let tasks_dir = match &new_opts.tasks_dir {
+                    Some(tasks_dir) => tasks_dir.clone(),
+                    None => tasks::import::default_tasks_dir(&UpConfig::from(opts)?)?,
+                };
+                tasks::scaffold::run(&new_opts, &tasks_dir)?;
+            }
+            TaskSubcommand::Which(ref name_opts) => {
+                let task = name_opts.task.clone();
+                tasks::locate::which(&UpConfig::from(opts)?, &task)?;
+            }
+            TaskSubcommand::Cat(ref name_opts) => {
+                let task = name_opts.task.clone();
+                tasks::locate::cat(&UpConfig::from(opts)?, &task)?;
+            }
+            TaskSubcommand::Edit(ref name_opts) => {
+                let task = name_opts.task.clone();
+                tasks::locate::edit(&UpConfig::from(opts)?, &task)?;
+            }
+        },
+        Some(SubCommand::Completions(ref cmd_opts)) => match cmd_opts.values {
+            Some(values) => tasks::completions::print_values(opts, values)?,
+            None => tasks::completions::run(cmd_opts)?,
+        },
+        Some(SubCommand::Man(ref cmd_opts)) => {
+            tasks::man::run(cmd_opts)?;
         }
         Some(SubCommand::Schema(ref cmd_opts)) => {
             tasks::schema::run(cmd_opts)?;
         }
-        Some(SubCommand::List(ref _cmd_opts)) => {
+        Some(SubCommand::Clean(ref cmd_opts)) => {
+            tasks::clean::run(&opts, &backups, cmd_opts)?;
+        }
+        Some(SubCommand::PromptHook(ref cmd_opts)) => {
+            tasks::prompt_hook::run(&opts, cmd_opts)?;
+        }
+        Some(SubCommand::Watch(ref cmd_opts)) => {
+            let cmd_opts = cmd_opts.clone();
             let config = UpConfig::from(opts)?;
-            tasks::run(&config, TasksDir::Tasks, TasksAction::List)?;
+            tasks::watch::run(&cmd_opts, config)?;
         }
-        Some(SubCommand::Run(ref _cmd_opts)) => {
+        Some(SubCommand::List(ref cmd_opts)) => {
+            let json = cmd_opts.json;
             let config = UpConfig::from(opts)?;
-            tasks::run(&config, TasksDir::Tasks, TasksAction::Run)?;
+            tasks::run(&config, TasksDir::Tasks, TasksAction::List { json })?;
+        }
+        Some(SubCommand::Lint) => {
+            let config = UpConfig::from(opts)?;
+            tasks::lint::run(&config)?;
+        }
+        Some(SubCommand::Explain(ref cmd_opts)) => {
+            let task = cmd_opts.task.clone();
+            let config = UpConfig::from(opts)?;
+            tasks::explain::run(&config, &task)?;
+        }
+        Some(SubCommand::Env(ref cmd_opts)) => {
+            let (format, show_secrets) = (cmd_opts.format, cmd_opts.show_secrets);
+            let config = UpConfig::from(opts)?;
+            tasks::print_env::run(&config, format, show_secrets)?;
+        }
+        Some(SubCommand::Plan(ref cmd_opts)) => {
+            let format = cmd_opts.output;
+            let config = UpConfig::from(opts)?;
+            tasks::run(&config, TasksDir::Tasks, TasksAction::Plan(format))?;
+        }
+        Some(SubCommand::Run(ref cmd_opts)) => {
+            let generate_first = cmd_opts.generate_first;
+            let sandbox = cmd_opts.sandbox.clone();
+            let config = UpConfig::from(opts)?;
+            if let Some(sandbox) = sandbox {
+                return tasks::sandbox::run(&config, &sandbox);
+            }
+            if generate_first {
+                let generate_summary =
+                    tasks::run(&config, TasksDir::GenerateTasks, TasksAction::Run)?;
+                let run_summary = tasks::run(&config, TasksDir::Tasks, TasksAction::Run)?;
+                info!("Generate tasks: {generate_summary}. Tasks: {run_summary}.");
+            } else {
+                tasks::run(&config, TasksDir::Tasks, TasksAction::Run)?;
+            }
+            tasks::update_self::passive_check(&config);
         }
         None => {
             let config = UpConfig::from(opts)?;
             tasks::run(&config, TasksDir::Tasks, TasksAction::Run)?;
+            tasks::update_self::passive_check(&config);
         }
     }
     Ok(())
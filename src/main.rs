@@ -15,6 +15,8 @@
 
 use camino::Utf8PathBuf;
 use chrono::SecondsFormat;
+use color_eyre::config::PanicHook;
+use color_eyre::config::Theme;
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
@@ -22,7 +24,12 @@ use color_eyre::Section;
 use color_eyre::SectionExt;
 use indicatif::ProgressState;
 use indicatif::ProgressStyle;
+use std::backtrace::Backtrace;
 use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::panic;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
@@ -34,16 +41,23 @@ use tracing::Level;
 use tracing_error::ErrorLayer;
 use tracing_indicatif::filter::hide_indicatif_span_fields;
 use tracing_indicatif::filter::IndicatifFilter;
+use tracing_indicatif::writer::IndicatifWriter;
+use tracing_indicatif::writer::Stderr;
 use tracing_indicatif::IndicatifLayer;
 use tracing_subscriber::filter::EnvFilter;
 use tracing_subscriber::fmt::format::DefaultFields;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::util::SubscriberInitExt;
 use up_rs::log;
 use up_rs::opts::Opts;
+use up_rs::tasks::tui::StderrWriter;
+use up_rs::utils::chrome_trace::chrome_trace_layer;
+use up_rs::utils::chrome_trace::ChromeTraceWriter;
 use up_rs::utils::errors::log_error;
 use up_rs::utils::files;
+use up_rs::utils::log::TaskLogLevelFilter;
 
 /// Env vars to avoid printing when we log the current environment.
 const IGNORED_ENV_VARS: [&str; 1] = [
@@ -59,26 +73,52 @@ fn main() -> Result<()> {
 
     let mut opts = up_rs::opts::parse();
 
-    color_eyre::config::HookBuilder::new()
+    let mut hook_builder = color_eyre::config::HookBuilder::new();
+    if !opts.color.enabled(&io::stderr()) {
+        hook_builder = hook_builder.theme(Theme::new());
+    }
+    let (panic_hook, eyre_hook) = hook_builder
         // Avoids printing these lines when up fails:
         // ```
         // Backtrace omitted. Run with RUST_BACKTRACE=1 environment variable to display it.
         // Run with RUST_BACKTRACE=full to include source snippets.
         // ```
         .display_env_section(false)
-        .install()?;
+        .into_hooks();
+    eyre_hook.install()?;
+
+    // Set before logging is set up, as the default log directory is under the home directory.
+    if let Some(home) = &opts.home {
+        let home = home
+            .canonicalize_utf8()
+            .wrap_err_with(|| format!("Failed to find the --home directory '{home}'."))?;
+        files::set_home_dir_override(home);
+    }
 
-    let log_path = match set_up_logging(&opts) {
-        Ok((log_path, level_filter)) => {
+    // `up prompt-hook` runs on every shell prompt, so shouldn't leave a log file each time.
+    let logging = if opts.writes_log_file() {
+        set_up_logging(&opts).map(Some)
+    } else {
+        Ok(None)
+    };
+    // Kept until the end of `main()`, as the trace file is written when it's dropped.
+    let (log_path, _trace_writer) = match logging {
+        Ok(None) => {
+            install_panic_hook(panic_hook, None);
+            (None, None)
+        }
+        Ok(Some((log_path, level_filter, panic_output, trace_writer))) => {
+            install_panic_hook(panic_hook, Some(panic_output));
             // If we set a log filter, save that filter back to the log option.
             // This allows us to run `up -l up=trace`, and get back a `trace` variable we can use
             // to check log levels later in the application.
             opts.log = level_filter.to_string();
-            Some(log_path)
+            (Some(log_path), trace_writer)
         }
         Err(e) => {
+            install_panic_hook(panic_hook, None);
             eprintln!(" WARN Failed to set up logging.{err}", err = log_error(&e));
-            None
+            (None, None)
         }
     };
 
@@ -113,8 +153,17 @@ fn main() -> Result<()> {
 }
 
 /// Set up logging to stderr and to a temp file path.
-/// Returns the log level filter chosen by the user if available, and the path to the log file.
-fn set_up_logging(opts: &Opts) -> Result<(Utf8PathBuf, LevelFilter)> {
+/// Returns the log level filter chosen by the user if available, the path to the log file, and
+/// where to report panics.
+fn set_up_logging(
+    opts: &Opts,
+) -> Result<(
+    Utf8PathBuf,
+    LevelFilter,
+    PanicOutput,
+    Option<ChromeTraceWriter>,
+)> {
+    let color = opts.color.enabled(&io::stderr());
     // Mostly copied from <https://github.com/emersonford/tracing-indicatif/blob/main/examples/build_console.rs>
     let indicatif_layer = IndicatifLayer::new()
         .with_progress_style(
@@ -132,9 +181,12 @@ fn set_up_logging(opts: &Opts) -> Result<(Utf8PathBuf, LevelFilter)> {
             )
             .with_key(
                 "color_start",
-                |state: &ProgressState, writer: &mut dyn std::fmt::Write| {
+                move |state: &ProgressState, writer: &mut dyn std::fmt::Write| {
                     let elapsed = state.elapsed();
 
+                    if !color {
+                        return;
+                    }
                     if elapsed > Duration::from_secs(60) {
                         // Red
                         let _ = write!(writer, "\x1b[{}m", 1 + 30);
@@ -146,8 +198,8 @@ fn set_up_logging(opts: &Opts) -> Result<(Utf8PathBuf, LevelFilter)> {
             )
             .with_key(
                 "color_end",
-                |state: &ProgressState, writer: &mut dyn std::fmt::Write| {
-                    if state.elapsed() > Duration::from_secs(10) {
+                move |state: &ProgressState, writer: &mut dyn std::fmt::Write| {
+                    if color && state.elapsed() > Duration::from_secs(10) {
                         let _ = write!(writer, "\x1b[0m");
                     }
                 },
@@ -164,14 +216,24 @@ fn set_up_logging(opts: &Opts) -> Result<(Utf8PathBuf, LevelFilter)> {
             )?),
         );
 
+    // The task dashboard replaces the indicatif progress bars, and hides stderr logs while shown.
+    // With `--quiet` there are no progress bars.
+    let show_progress = !opts.tui() && !opts.quiet;
+    let indicatif_writer = show_progress.then(|| indicatif_layer.get_stderr_writer());
+    let stderr_writer = indicatif_writer.as_ref().map_or_else(
+        || BoxMakeWriter::new(StderrWriter),
+        |writer| BoxMakeWriter::new(writer.clone()),
+    );
+
     let stderr_log = tracing_subscriber::fmt::layer()
         .compact()
         .with_target(false)
         .without_time()
-        .with_writer(indicatif_layer.get_stderr_writer());
+        .with_ansi(color)
+        .with_writer(stderr_writer);
 
     // Logs go to e.g. ~/Library/Logs/co.fahn.up/up_2024-04-26T11_22_24.834348Z.log
-    let log_path = files::log_dir()?.join(format!(
+    let log_path = opts.log_dir()?.join(format!(
         "up_{}.log",
         opts.start_time
             .to_rfc3339_opts(SecondsFormat::AutoSi, true)
@@ -179,33 +241,40 @@ fn set_up_logging(opts: &Opts) -> Result<(Utf8PathBuf, LevelFilter)> {
             .replace(':', "_")
     ));
 
-    let log_file = files::create(&log_path, None).wrap_err("Failed to create log file.")?;
+    let log_file = Arc::new(files::create(&log_path, None).wrap_err("Failed to create log file.")?);
 
+    let log_directives = opts.log_filter();
     let stderr_envfilter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
-        .parse_lossy(&opts.log);
+        .parse_lossy(&log_directives);
     let log_filter = stderr_envfilter
         .max_level_hint()
-        .ok_or_else(|| eyre!("Failed to work out the max level hint for {}", &opts.log))?;
+        .ok_or_else(|| eyre!("Failed to work out the max level hint for {log_directives}"))?;
 
     let file_envfilter = EnvFilter::builder()
         .with_default_directive(LevelFilter::TRACE.into())
         .parse_lossy("up=trace");
 
     let file_log = tracing_subscriber::fmt::layer()
-        .with_writer(Arc::new(log_file))
+        .with_writer(Arc::clone(&log_file))
         .with_target(true)
         .with_file(true)
         .with_line_number(true)
         .pretty()
         .with_ansi(false);
 
+    let (trace_layer, trace_writer) = opts.trace_file.as_deref().map(chrome_trace_layer).unzip();
+    let trace_envfilter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::TRACE.into())
+        .parse_lossy("up=trace");
+
     // Always log to stderr, also log to a file if we can successfully set that up.
     tracing_subscriber::registry()
+        .with(trace_layer.with_filter(trace_envfilter))
         .with(file_log.with_filter(file_envfilter))
-        .with(stderr_log.with_filter(stderr_envfilter))
+        .with(stderr_log.with_filter(TaskLogLevelFilter::new(stderr_envfilter)))
         // Filter out anything with the tracing field `indicatif.pb_hide`.
-        .with(indicatif_layer.with_filter(IndicatifFilter::new(true)))
+        .with(show_progress.then(|| indicatif_layer.with_filter(IndicatifFilter::new(true))))
         // Adds a color_eyre spantrace layer. This isn't used unless we start adding `#[instrument]`
         // to functions.
         .with(ErrorLayer::default())
@@ -213,5 +282,91 @@ fn set_up_logging(opts: &Opts) -> Result<(Utf8PathBuf, LevelFilter)> {
 
     debug!("Writing trace logs to {log_path:?}");
 
-    Ok((log_path, log_filter))
+    let panic_output = PanicOutput {
+        log_path: log_path.clone(),
+        log_file,
+        indicatif_writer,
+    };
+    Ok((log_path, log_filter, panic_output, trace_writer))
+}
+
+/// Where to report panics, once logging is set up.
+struct PanicOutput {
+    /// Path of the log file.
+    log_path: Utf8PathBuf,
+    /// The log file.
+    log_file: Arc<File>,
+    /// Writes to stderr while hiding the progress bars, unless the task dashboard is used.
+    indicatif_writer: Option<IndicatifWriter<Stderr>>,
+}
+
+/// Install a panic hook that prints the `color_eyre` panic report. Once logging is set up, the
+/// panic is also written to the log file (which is synced to disk), the report is printed with the
+/// progress bars hidden, and the log file path is printed. This means panics (e.g. in a task
+/// library) don't lose the end of the trace log or get mixed up with half-drawn progress bars.
+fn install_panic_hook(panic_hook: PanicHook, output: Option<PanicOutput>) {
+    panic::set_hook(Box::new(move |panic_info| {
+        let report = panic_hook.panic_report(panic_info).to_string();
+        let Some(output) = &output else {
+            eprintln!("{report}");
+            return;
+        };
+
+        // Errors are ignored, as there's nothing more we can do while panicking.
+        let mut log_file: &File = &output.log_file;
+        _ = writeln!(
+            log_file,
+            "up panicked: {panic_info}\nBacktrace:\n{backtrace}",
+            backtrace = Backtrace::force_capture()
+        );
+        _ = log_file.sync_all();
+
+        let message = format!(
+            "{report}\nLog file:\n   {log_path}\n",
+            log_path = output.log_path
+        );
+        match &output.indicatif_writer {
+            Some(writer) => _ = writer.clone().write_all(message.as_bytes()),
+            None => eprint!("{message}"),
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::install_panic_hook;
+    use super::PanicOutput;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::fs;
+    use std::panic;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_panic_hook_writes_log_file() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let log_path = temp_dir.join("up.log");
+        let (panic_hook, _) = color_eyre::config::HookBuilder::new().into_hooks();
+        install_panic_hook(
+            panic_hook,
+            Some(PanicOutput {
+                log_path: log_path.clone(),
+                log_file: Arc::new(fs::File::create(&log_path)?),
+                indicatif_writer: None,
+            }),
+        );
+
+        let result = panic::catch_unwind(|| panic!("task library bug"));
+        // Restore the default hook so other panics aren't written to our log file.
+        drop(panic::take_hook());
+
+        ensure!(result.is_err());
+        let log = fs::read_to_string(&log_path)?;
+        ensure!(
+            log.starts_with("up panicked: ") && log.contains("task library bug"),
+            "Panic missing from log file:\n{log}"
+        );
+        ensure!(log.contains("Backtrace:"), "Backtrace missing from:\n{log}");
+        Ok(())
+    }
 }
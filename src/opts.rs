@@ -1,19 +1,34 @@
 //! CLI options passed to `up` commands.
-mod paths;
+pub(crate) mod paths;
 pub(crate) mod start_time;
 
 use crate::opts::paths::TempDir;
 use crate::opts::start_time::StartTime;
+use crate::tasks::git::fetch::GitRetry;
+use crate::tasks::git::OnDetachedHead;
+use crate::utils::duration::HumanDuration;
+use crate::utils::files;
+use crate::utils::log::SUMMARY_TARGET;
+use camino::Utf8Path;
 use camino::Utf8PathBuf;
+use clap::ArgAction;
 use clap::Parser;
 use clap::ValueEnum;
 use clap::ValueHint;
 use clap_complete::Shell;
+use color_eyre::Result;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use tracing::warn;
 
 /// The default fallback path inside a fallback repo to look for the up.yaml file in.
 pub(crate) const FALLBACK_CONFIG_PATH: &str = "dotfiles/.config/up/up.yaml";
+/// The default number of runs' backups to keep.
+pub(crate) const DEFAULT_KEEP_BACKUPS: usize = 20;
 /// URL to use to find the latest version of up.
 pub(crate) const LATEST_RELEASE_URL: &str =
     "https://api.github.com/repos/gibfahn/up-rs/releases/latest";
@@ -53,7 +68,8 @@ There are also a number of libraries built into up, that can be accessed directl
 up task configs, e.g. `up link` to link dotfiles.
 
 For debugging, run with `RUST_LIB_BACKTRACE=1` to show error/panic traces.
-Logs from the latest run are available at $TMPDIR/up-rs/logs/up-rs_latest.log by default.
+Logs from each run are written to `--log-dir`, `~/Library/Logs/co.fahn.up` on macOS and
+`$XDG_STATE_HOME/up/logs` elsewhere by default.
 */
 #[derive(Debug, Parser)]
 #[clap(version)]
@@ -69,12 +85,85 @@ pub struct Opts {
     )]
     pub log: String,
 
+    /// Only show warnings, errors, and the final run summary (overrides `--log`).
+    #[clap(long, short = 'q', conflicts_with = "verbose")]
+    pub quiet: bool,
+
     /**
-    Temporary directory to use for logs, fifos, and other intermediate artifacts.
+    Show more logs (overrides `--log`): `-v` for debug logs, `-vv` for trace logs, and `-vvv` for
+    trace logs from the libraries up uses as well.
+    */
+    #[clap(long, short = 'v', action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /**
+    Temporary directory to use for fifos, downloads, and other intermediate artifacts that don't
+    need to outlive the run.
     */
     #[clap(long, env = "UP_TEMP_DIR", default_value_t, value_hint = ValueHint::DirPath, alias = "up-dir")]
     pub temp_dir: TempDir,
 
+    /**
+    Directory for caches that up can recreate if they're deleted (parsed tasks, included task
+    files, plugin clones, and the update check).
+
+    Defaults to `$XDG_CACHE_HOME/up` (`~/.cache/up` if unset).
+    */
+    #[clap(long, env = "UP_CACHE_DIR", value_hint = ValueHint::DirPath)]
+    pub cache_dir: Option<Utf8PathBuf>,
+
+    /**
+    Directory for state that should survive OS temp dir cleanup (backups and previous runs).
+
+    Defaults to `$XDG_STATE_HOME/up` (`~/.local/state/up` if unset).
+    */
+    #[clap(long, env = "UP_STATE_DIR", value_hint = ValueHint::DirPath)]
+    pub state_dir: Option<Utf8PathBuf>,
+
+    /**
+    Directory to write each run's log file to.
+
+    Defaults to `~/Library/Logs/co.fahn.up` on macOS, and the `logs` subdirectory of the
+    `--state-dir` elsewhere.
+    */
+    #[clap(long, env = "UP_LOG_DIR", value_hint = ValueHint::DirPath)]
+    pub log_dir: Option<Utf8PathBuf>,
+
+    /**
+    Directory to create each run's task temporary directories in (task scripts and their
+    output), which `up explain` reads afterwards.
+
+    Pass `ram` to use a RAM-backed tmpfs (`/dev/shm` on Linux) so heavy tasks don't churn your
+    disk. On other platforms, point this at a RAM disk you've mounted.
+
+    Defaults to the `--state-dir`.
+    */
+    #[clap(long, env = "UP_RUN_TEMP_DIR", value_hint = ValueHint::DirPath)]
+    pub run_temp_dir: Option<Utf8PathBuf>,
+
+    /**
+    Directory to back up files into before up overwrites them (e.g. in `up link` and `up
+    defaults`). Each run's backups are kept in a timestamped subdirectory.
+
+    Defaults to the `backup` subdirectory of the `--state-dir`.
+    */
+    #[clap(long, env = "UP_BACKUP_DIR", value_hint = ValueHint::DirPath)]
+    pub backup_dir: Option<Utf8PathBuf>,
+
+    /// Number of runs' backups to keep, older backups are removed after each run and by `up
+    /// clean`.
+    #[clap(long, env = "UP_KEEP_BACKUPS", default_value_t = DEFAULT_KEEP_BACKUPS)]
+    pub keep_backups: usize,
+
+    /**
+    Write a Chrome trace (JSON) of the run's spans (tasks, commands, git updates) to this file,
+    to see where a run spends its time.
+
+    Open it in <https://ui.perfetto.dev>, `chrome://tracing`, or <https://www.speedscope.app>.
+    */
+    #[clap(long, env = "UP_TRACE_FILE", value_hint = ValueHint::FilePath)]
+    pub trace_file: Option<Utf8PathBuf>,
+
     /// Set the file logging level explicitly (options: Off, Error, Warn, Info,
     /// Debug, Trace).
     #[clap(long, default_value = "trace", env = "FILE_RUST_LOG")]
@@ -84,6 +173,33 @@ pub struct Opts {
     #[clap(long, default_value = "auto", ignore_case = true, value_enum)]
     pub color: Color,
 
+    /**
+    Don't use the network, e.g. on a plane. Tasks that need it (e.g. `brew`, `git`,
+    `software_update`, and `vscode` tasks, and tasks using the `network` resource) are skipped and
+    reported as `offline`, as is the update check.
+    Included configs and plugins are used from the cache, and `--fallback-url` is ignored.
+    */
+    #[clap(long, env = "UP_OFFLINE")]
+    pub offline: bool,
+
+    /**
+    Use this entry of the `hosts` section of up.yaml, rather than the one matching this machine's
+    hostname or serial number.
+    */
+    #[clap(long, env = "UP_HOST")]
+    pub host: Option<String>,
+
+    /**
+    Use this directory as the home directory rather than `$HOME`, e.g. to set up another user's
+    home directory or a mounted disk image. It's used to expand `~` (in env vars, task data, and
+    paths like the default link `--to` and defaults plist files), and to find the up.yaml and the
+    default cache, state, and log directories, ignoring the `$XDG_*` env vars.
+
+    Commands run by tasks still get the real `$HOME`.
+    */
+    #[clap(long, env = "UP_HOME", value_hint = ValueHint::DirPath)]
+    pub home: Option<Utf8PathBuf>,
+
     /// Path to the up.yaml file for up.
     #[clap(long, short = 'c', default_value = "$XDG_CONFIG_HOME/up/up.yaml", value_hint = ValueHint::FilePath)]
     pub(crate) config: String,
@@ -101,10 +217,80 @@ pub struct Opts {
     pub(crate) cmd: Option<SubCommand>,
 }
 
+impl Opts {
+    /// The stderr log filter directives, from `--quiet` or `--verbose` if set, else `--log`.
+    #[must_use]
+    pub fn log_filter(&self) -> String {
+        match self.verbose {
+            0 if self.quiet => format!("warn,{SUMMARY_TARGET}=info"),
+            0 => self.log.clone(),
+            1 => "up=debug,up_rs=debug".to_owned(),
+            2 => "up=trace,up_rs=trace".to_owned(),
+            _ => "trace".to_owned(),
+        }
+    }
+
+    /// Whether the task dashboard was requested (`up run --tui`).
+    #[must_use]
+    pub fn tui(&self) -> bool {
+        matches!(&self.cmd, Some(SubCommand::Run(run_options)) if run_options.tui)
+    }
+
+    /// Directory for up's caches, resolving `--cache-dir`.
+    pub fn cache_dir(&self) -> Result<Utf8PathBuf> {
+        self.cache_dir.clone().map_or_else(files::cache_dir, Ok)
+    }
+
+    /// Directory for up's state, resolving `--state-dir`.
+    pub fn state_dir(&self) -> Result<Utf8PathBuf> {
+        self.state_dir.clone().map_or_else(files::state_dir, Ok)
+    }
+
+    /// Whether to write a log file, which `up prompt-hook` and `up completions` don't as the
+    /// shell runs them on every prompt or completion.
+    #[must_use]
+    pub fn writes_log_file(&self) -> bool {
+        !matches!(
+            self.cmd,
+            Some(SubCommand::PromptHook(_) | SubCommand::Completions(_))
+        )
+    }
+
+    /// Directory to write log files to, resolving `--log-dir`.
+    pub fn log_dir(&self) -> Result<Utf8PathBuf> {
+        self.log_dir.clone().map_or_else(files::log_dir, Ok)
+    }
+
+    /// Directory to create per-run task temporary directories in, resolving `--run-temp-dir`.
+    pub fn run_temp_dir(&self) -> Result<Utf8PathBuf> {
+        match &self.run_temp_dir {
+            Some(dir) if dir == RAM_RUN_TEMP_DIR => {
+                if let Some(dir) = ram_temp_dir() {
+                    return Ok(dir);
+                }
+                let state_dir = self.state_dir()?;
+                warn!("No RAM-backed temp dir found on this platform, using {state_dir} instead.");
+                Ok(state_dir)
+            }
+            Some(dir) => Ok(dir.clone()),
+            None => self.state_dir(),
+        }
+    }
+}
+
+/// Value of `--run-temp-dir` that selects a RAM-backed temp dir.
+const RAM_RUN_TEMP_DIR: &str = "ram";
+
+/// A RAM-backed (tmpfs) directory for temporary files, if this platform has a standard one.
+fn ram_temp_dir() -> Option<Utf8PathBuf> {
+    let shm_dir = Utf8Path::new("/dev/shm");
+    (cfg!(target_os = "linux") && shm_dir.is_dir()).then(|| shm_dir.join("up-rs"))
+}
+
 /// Settings for colouring output.
 #[derive(Debug, ValueEnum, Clone)]
 pub enum Color {
-    /// Auto: Colour on if stderr isatty, else off.
+    /// Auto: Colour on if the output stream is a terminal and `NO_COLOR` isn't set, else off.
     Auto,
     /// Always: Always enable colours.
     Always,
@@ -112,6 +298,28 @@ pub enum Color {
     Never,
 }
 
+impl Color {
+    /**
+    Whether to colour output written to `stream`.
+
+    Each stream should be checked separately, so e.g. piping stdout to `jq` doesn't stop stderr
+    logs being coloured (and JSON written to a pipe is never coloured).
+    */
+    pub fn enabled(&self, stream: &impl IsTerminal) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => {
+                // <https://no-color.org>: a non-empty `NO_COLOR` disables colour.
+                env::var_os(NO_COLOR).is_none_or(|value| value.is_empty()) && stream.is_terminal()
+            }
+        }
+    }
+}
+
+/// Env var that disables colour output if set to a non-empty value.
+pub(crate) const NO_COLOR: &str = "NO_COLOR";
+
 /// Optional subcommand (e.g. the "link" in "up link").
 #[derive(Debug, Parser)]
 pub(crate) enum SubCommand {
@@ -126,27 +334,95 @@ pub(crate) enum SubCommand {
     Defaults(DefaultsOptions),
     /// Generate up config from current system state.
     Generate(GenerateOptions),
+    /**
+    Convert the config of another dotfile manager into up task files.
+
+    Supports GNU stow directories, chezmoi source directories, and Homebrew Brewfiles.
+    Task files are written to the tasks directory next to your up.yaml (or `--tasks-dir`).
+    */
+    Import(ImportOptions),
+    /// Create and manage task files.
+    Task(TaskOptions),
     /// Update the up CLI itself.
     Self_(UpdateSelfOptions),
     /// Generate shell completions to stdout.
     Completions(CompletionsOptions),
+    /// Generate man pages for up and its subcommands.
+    Man(ManOptions),
     /// List available tasks.
-    List(RunOptions),
+    List(ListOptions),
+    /**
+    Check the up config and tasks for problems, e.g. tasks that don't do anything or env vars
+    that aren't set.
+
+    Prints each problem with its location and a suggested fix, and fails if any are found.
+    */
+    Lint,
+    /// Print the tasks that `up run` would run, in order, without running them.
+    Plan(PlanOptions),
+    /**
+    Print everything up knows about a task: its source file, dependencies, the commands it would
+    run (with env vars resolved), and how its last run went.
+    */
+    Explain(ExplainOptions),
+    /**
+    Print the env vars tasks would be run with, after resolving `env`, `env_file`, and
+    `inherit_env` from up.yaml.
+
+    Values of env vars whose names look like secrets (e.g. containing `TOKEN` or `PASSWORD`) are
+    masked unless you pass `--show-secrets`.
+    */
+    Env(EnvOptions),
     /// Write the up yaml schema.
     Schema(SchemaOptions),
+    /// Remove old backups and run directories, keeping the most recent `--keep-backups` of each,
+    /// and the cache of parsed tasks.
+    Clean(CleanOptions),
+    /**
+    Print a short status for shell prompts, e.g. `up: 2 tasks failing, last run 3d ago`.
+
+    Only reads the run history, so it's fast enough to run on every prompt. Prints nothing if no
+    task is failing and up ran recently.
+
+    EXAMPLES:
+
+    ❯ PS1='$(up prompt-hook)'"$PS1"
+    */
+    PromptHook(PromptHookOptions),
+    /**
+    Watch up.yaml and the tasks directory, re-running tasks when their files change.
+
+    Changing up.yaml re-runs all the tasks, and changing a task file re-runs that task. With
+    `--dotfiles`, link tasks are also re-run when files in their `from_dir` change. Tasks left out
+    by the host's `tasks` filter in up.yaml aren't re-run. Useful while iterating on defaults or
+    link tasks.
+    */
+    Watch(WatchOptions),
 }
 
 /// CLI options passed to `up run`.
 #[derive(Debug, Parser, Default)]
+#[allow(clippy::struct_excessive_bools)] // These are independent CLI flags.
 pub(crate) struct RunOptions {
-    /// Run the bootstrap list of tasks in series first, then run the rest in
-    /// parallel. Designed for first-time setup.
+    /// Run the `bootstrap_tasks` list (deprecated) in series first, then run the rest in
+    /// parallel. Designed for first-time setup. Tasks with `bootstrap: true` run before the
+    /// others whether or not this is passed.
     #[clap(short, long)]
     pub(crate) bootstrap: bool,
     /// Keep going even if a bootstrap task fails.
     #[clap(short, long)]
     pub(crate) keep_going: bool,
-    /// Fallback git repo URL to download to get the config.
+    /**
+    Resume the last run if it was interrupted (or a bootstrap task failed), skipping the bootstrap
+    tasks that already passed in it.
+
+    Tasks with a `run_if_cmd` are still run, so the command can check whether they need running
+    again.
+    */
+    #[clap(long)]
+    pub(crate) resume: bool,
+    /// Fallback git repo URL to download to get the config. Can also be a repo tarball URL
+    /// (e.g. from codeload.github.com), which is downloaded over HTTPS without needing git.
     #[clap(short = 'f', long, value_hint = ValueHint::Url)]
     pub(crate) fallback_url: Option<String>,
     /// Fallback path inside the git repo to get the config.
@@ -159,37 +435,242 @@ pub(crate) struct RunOptions {
         value_hint = ValueHint::FilePath
     )]
     pub(crate) fallback_path: Utf8PathBuf,
+    /// Don't fetch the config from the `--fallback-url` if it isn't found, e.g. to override a
+    /// `--fallback-url` set in a shell alias.
+    #[clap(long)]
+    pub(crate) no_fallback: bool,
     /**
     Optionally pass one or more tasks to run. The default is to run all
     tasks. This option can be provided multiple times, or use a comma-separated list of values.
+    Task names can be glob patterns (quote them so your shell doesn't expand them).
 
     EXAMPLES:
 
     ❯ up run --tasks=rust,apt --tasks=otherslowtask
+
+    ❯ up run --tasks='brew*,git-*'
     */
-    #[clap(short = 't', long, value_delimiter = ',')]
+    #[clap(short = 't', long, value_name = "TASK", value_delimiter = ',')]
     pub(crate) tasks: Option<Vec<String>>,
 
+    /**
+    Optionally pass one or more tags, to run the tasks that have any of those tags (set in the
+    `tags` field of the task config). If `--tasks` is also passed, tasks that match either
+    are run. This option can be provided multiple times, or use a comma-separated list of values.
+
+    EXAMPLES:
+
+    ❯ up run --tags=work,gui
+    */
+    #[clap(long, value_name = "TAG", value_delimiter = ',')]
+    pub(crate) tags: Option<Vec<String>>,
+
     /**
     Tasks stdout/stderr inherit from up's stdout/stderr.
 
     By default this is true if only one task is executed, and false otherwise.
     Piping multiple commands to the stdout/stderr of the process will cause task output to be interleaved, which is very confusing when many tasks are run.
     */
-    #[clap(long)]
+    #[clap(long, conflicts_with = "tui")]
     pub(crate) console: Option<bool>,
 
+    /**
+    Show a full-screen dashboard of the running tasks, with each task's status, elapsed time, and
+    latest output line.
+
+    Use the arrow keys to select a task, and enter to show more of its output. Logs are only
+    written to the log file while the dashboard is shown.
+    */
+    #[clap(long)]
+    pub(crate) tui: bool,
+    /// Run the generate tasks (as `up generate` does) first, then run the regenerated tasks.
+    #[clap(long)]
+    pub(crate) generate_first: bool,
+
+    /**
+    Run up inside a throwaway container rather than on this machine, to safely test changes to
+    your tasks. Pass `docker` to use the `ubuntu:latest` image, or `docker:<image>` to choose the
+    image.
+
+    The directory containing your up.yaml is mounted read-only at the same path, and a Linux up
+    binary for the image's architecture (this one if it's a static Linux build, otherwise the
+    latest release) is mounted as `up`. The other arguments are passed through to `up` in the
+    container, except for the ones taking paths on this machine (like `--state-dir` and `--home`).
+
+    EXAMPLES:
+
+    ❯ up run --sandbox docker --tasks=mynewtask
+
+    ❯ up run --sandbox docker:debian:bookworm
+    */
+    #[clap(long, value_name = "docker[:IMAGE]")]
+    pub(crate) sandbox: Option<Sandbox>,
+
     /**
     Optionally pass one or more tasks to exclude. The default is to exclude no
     tasks. Excluded tasks are not run even if specified in `--tasks` (excluding takes
-    priority). This option can be provided multiple times. Tasks specified do not have to exist.
+    priority). This option can be provided multiple times, or use a comma-separated list of
+    values. Tasks listed in the `exclude_tasks` field of the up.yaml are also excluded.
+    Tasks specified must exist.
 
     EXAMPLES:
 
-    ❯ up run --exclude-tasks=brew,slowtask --exclude-tasks=otherslowtask
+    ❯ up run --exclude-tasks=brew,slowtask -x otherslowtask
     */
-    #[clap(long, value_delimiter = ',')]
+    #[clap(short = 'x', long, value_name = "TASK", value_delimiter = ',')]
     pub(crate) exclude_tasks: Option<Vec<String>>,
+
+    /**
+    Only run the named task and the tasks it requires (directly or indirectly, via the `requires`
+    field of the task config). Tasks are run after the tasks they require.
+
+    EXAMPLES:
+
+    ❯ up run --until=mynewtask
+    */
+    #[clap(long, value_name = "TASK")]
+    pub(crate) until: Option<String>,
+
+    /**
+    Run only the tasks that the `--until` task requires, not the task itself.
+
+    Useful when iterating on a new task whose prerequisites are slow.
+    */
+    #[clap(long, requires = "until")]
+    pub(crate) only_deps: bool,
+
+    /**
+    Stop starting new tasks once the run has taken this long, e.g. `30m` or `1h30m`. Tasks that
+    are already running are left to finish, and the tasks that didn't get to start are reported
+    as `not run`. A warning is logged when 80% of the budget has been used.
+
+    EXAMPLES:
+
+    ❯ up run --max-duration=30m
+    */
+    #[clap(long, value_name = "DURATION")]
+    pub(crate) max_duration: Option<HumanDuration>,
+
+    /// Kill the commands of tasks that are still running once the `--max-duration` is exceeded,
+    /// rather than letting them finish.
+    #[clap(long, requires = "max_duration")]
+    pub(crate) hard: bool,
+
+    /**
+    How much detail to show in the end-of-run report of which tasks passed, failed, and were
+    skipped, grouped by each task's first tag.
+
+    `full` lists the task names, `compact` only the counts for each tag, and `quiet` only shows the
+    overall counts.
+    */
+    #[clap(long, value_enum, default_value_t)]
+    pub(crate) summary: SummaryFormat,
+}
+
+/// CLI options passed to `up list`.
+#[derive(Debug, Parser)]
+pub(crate) struct ListOptions {
+    /// Print the tasks as JSON, with the status, start time, and duration of each task's last run
+    /// on this machine.
+    #[clap(long)]
+    pub(crate) json: bool,
+    /// Options that select which tasks are listed, as for `up run`.
+    #[clap(flatten)]
+    pub(crate) run_options: RunOptions,
+}
+
+/// CLI options passed to `up plan`.
+#[derive(Debug, Parser)]
+pub(crate) struct PlanOptions {
+    /// Format to print the plan in.
+    #[clap(short, long, value_enum, default_value_t)]
+    pub(crate) output: PlanFormat,
+    /// Options that select which tasks would be run, as for `up run`.
+    #[clap(flatten)]
+    pub(crate) run_options: RunOptions,
+}
+
+/// Where to run `up run --sandbox`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Sandbox {
+    /// A docker container using this image.
+    Docker {
+        /// Image to run.
+        image: String,
+    },
+}
+
+/// Image used by `--sandbox docker` if none is given.
+const DEFAULT_SANDBOX_IMAGE: &str = "ubuntu:latest";
+
+impl FromStr for Sandbox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "docker" => Ok(Self::Docker {
+                image: DEFAULT_SANDBOX_IMAGE.to_owned(),
+            }),
+            Some(("docker", image)) if !image.is_empty() => Ok(Self::Docker {
+                image: image.to_owned(),
+            }),
+            _ => Err(format!(
+                "unsupported sandbox '{s}', expected `docker` or `docker:<image>`"
+            )),
+        }
+    }
+}
+
+/// CLI options passed to `up explain`.
+#[derive(Debug, Parser)]
+pub(crate) struct ExplainOptions {
+    /// Name of the task to explain.
+    pub(crate) task: String,
+}
+
+/// CLI options passed to `up env`.
+#[derive(Debug, Parser)]
+pub(crate) struct EnvOptions {
+    /// Format to print the env vars in.
+    #[clap(long, value_enum, default_value_t)]
+    pub(crate) format: EnvFormat,
+    /// Print the values of env vars that look like secrets rather than masking them.
+    #[clap(long)]
+    pub(crate) show_secrets: bool,
+}
+
+/// Output formats for `up env`.
+#[derive(Debug, ValueEnum, Clone, Copy, Default)]
+pub enum EnvFormat {
+    /// `KEY=value` lines.
+    #[default]
+    Text,
+    /// Shell `export KEY='value'` lines, which can be sourced.
+    Export,
+    /// A JSON object, for use by other tools.
+    Json,
+}
+
+/// How much detail to show in the `up run` end-of-run report.
+#[derive(Debug, ValueEnum, Clone, Copy, Default)]
+pub enum SummaryFormat {
+    /// List the tasks for each tag.
+    #[default]
+    Full,
+    /// Only show how many tasks passed, failed, and were skipped for each tag.
+    Compact,
+    /// Only show the overall counts.
+    Quiet,
+}
+
+/// Output formats for `up plan`.
+#[derive(Debug, ValueEnum, Clone, Copy, Default)]
+pub enum PlanFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// JSON, for use by other tools.
+    Json,
 }
 
 /// CLI options passed to `up link`.
@@ -201,17 +682,86 @@ pub(crate) struct LinkOptions {
     /// Path to link them to.
     #[clap(short = 't', long = "to", default_value = "~", value_hint = ValueHint::DirPath)]
     pub(crate) to_dir: String,
+    /// Create relative links (e.g. `../code/dotfiles/.bashrc`) rather than absolute ones, so
+    /// links still work if the directories are mounted or restored at a different path.
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) relative: bool,
+    /// What to do if a file, directory, or different link already exists where a link would be
+    /// created. Defaults to `link_on_conflict` in `up.yaml` for link tasks.
+    #[clap(long, value_enum, default_value_t)]
+    #[serde(default, skip_serializing_if = "OnConflict::is_backup")]
+    pub(crate) on_conflict: OnConflict,
+    /// Shell commands to run when links are created or changed, keyed by the path (relative to
+    /// `from_dir`) of a file or of a directory containing files, e.g.
+    /// `{.config/karabiner: karabiner_cli --reload}`. Each command runs at most once, after
+    /// linking. Only settable in link tasks.
+    #[clap(skip)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) on_change: BTreeMap<String, String>,
+}
+
+/// What the link task does when something already exists where a link would be created.
+#[derive(Debug, ValueEnum, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Move it into the backup directory for the run, then create the link.
+    #[default]
+    Backup,
+    /// Leave it alone and don't create the link.
+    Skip,
+    /// Delete it, then create the link.
+    Overwrite,
+    /// Fail the task without creating any links.
+    Fail,
+}
+
+impl OnConflict {
+    /// Whether this is the default, [`Self::Backup`].
+    #[allow(clippy::trivially_copy_pass_by_ref)] // Signature required by serde.
+    fn is_backup(&self) -> bool {
+        *self == Self::Backup
+    }
 }
 
 /// CLI options passed to `up git`.
 #[derive(Debug, Default, Parser)]
+#[allow(clippy::struct_excessive_bools)] // These are independent CLI flags.
 pub struct GitOptions {
     /// URL of git repo to download.
-    #[clap(long, value_hint = ValueHint::Url)]
-    pub git_url: String,
+    #[clap(long, value_hint = ValueHint::Url, required_unless_present = "from_file")]
+    pub git_url: Option<String>,
     /// Path to download git repo to.
-    #[clap(long, value_hint = ValueHint::DirPath)]
-    pub git_path: Utf8PathBuf,
+    #[clap(long, value_hint = ValueHint::DirPath, required_unless_present = "from_file")]
+    pub git_path: Option<Utf8PathBuf>,
+    /**
+    Clone or update every repo in a YAML or JSON file instead, in parallel. The file is a list of
+    entries in the same format as the `data` of a `run_lib: git` task, env vars in it are
+    expanded from the current environment.
+
+    EXAMPLES:
+
+    ❯ up git --from-file ~/repos.yaml
+    */
+    #[clap(
+        long,
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = [
+            "git_url",
+            "git_path",
+            "branch",
+            "prune",
+            "prune_dry_run",
+            "trash_pruned",
+            "clean_ignored",
+            "auth_retries",
+            "network_retries",
+            "retry_backoff",
+            "max_retry_backoff",
+            "on_detached_head",
+        ]
+    )]
+    pub from_file: Option<Utf8PathBuf>,
     /// Remote to set/update.
     #[clap(long, default_value = crate::tasks::git::DEFAULT_REMOTE_NAME)]
     pub remote: String,
@@ -224,6 +774,24 @@ pub struct GitOptions {
     /// been deleted.
     #[clap(long)]
     pub prune: bool,
+    /// Log the branches that `--prune` would delete, without deleting them.
+    #[clap(long, conflicts_with = "trash_pruned")]
+    pub prune_dry_run: bool,
+    /// Move pruned branches to `refs/up/trash/<date>/<branch>` instead of deleting them. Trashed
+    /// branches are deleted after 30 days, restore one with
+    /// `git branch <branch> refs/up/trash/<date>/<branch>`.
+    #[clap(long, requires = "prune")]
+    pub trash_pruned: bool,
+    /// Remove files ignored by git (e.g. build artifacts) after updating, like `git clean -dfX`.
+    #[clap(long)]
+    pub clean_ignored: bool,
+    /// How to retry fetching when authentication or the network fails.
+    #[clap(flatten)]
+    pub retry: GitRetry,
+    /// What to do if the repo's HEAD is detached (not on a branch): warn and skip updating it,
+    /// or check out the `--branch` (or default branch) if the repo is clean.
+    #[clap(long, value_enum)]
+    pub on_detached_head: Option<OnDetachedHead>,
 }
 
 /// Options passed to `up generate`.
@@ -244,29 +812,225 @@ pub(crate) struct SchemaOptions {
 /// CLI options passed to `up self`.
 #[derive(Debug, Parser, Serialize, Deserialize)]
 pub(crate) struct UpdateSelfOptions {
-    /// URL to download update from.
+    /**
+    URL to download update from.
+
+    For the default URL, the binary for the current architecture (e.g. `up-darwin-arm64`) is
+    downloaded if one was released, falling back to the universal binary.
+
+    This can be any HTTPS mirror of the release binary. Only the default URL checks the GitHub
+    releases API for a newer version first, for other URLs the binary is downloaded and its
+    `--version` output is compared with the current version instead.
+    */
     #[clap(long, default_value = SELF_UPDATE_URL, value_hint = ValueHint::Url)]
     pub(crate) url: String,
+    /**
+    Path to a local up binary to update from, instead of downloading one.
+
+    The binary is only installed if its `--version` output is newer than the current version.
+    */
+    #[clap(long, value_hint = ValueHint::FilePath, conflicts_with = "url")]
+    pub(crate) from_file: Option<Utf8PathBuf>,
     /// Set to update self even if it seems to be a development install.
     /// Assumes a dev install when the realpath of the current binary is in a
     /// subdirectory of the cargo root path that the binary was originally built in.
     #[clap(long)]
     pub(crate) always_update: bool,
+    /// Action to take instead of updating.
+    #[clap(subcommand)]
+    #[serde(skip)]
+    pub(crate) subcommand: Option<UpdateSelfSubcommand>,
+}
+
+/// Subcommands supported by `up self`.
+#[derive(Debug, Parser)]
+pub(crate) enum UpdateSelfSubcommand {
+    /**
+    Check whether a newer version of up has been released, without updating.
+
+    Exits with an error if an update is available.
+    */
+    Check,
 }
 
 /// CLI options passed to `up completions`.
 #[derive(Debug, Parser)]
 pub(crate) struct CompletionsOptions {
     /// Shell for which to generate completions.
-    #[clap(value_enum)]
-    pub(crate) shell: Shell,
+    #[clap(value_enum, required_unless_present = "values")]
+    pub(crate) shell: Option<Shell>,
+    /// Print the possible values of an argument, one per line, rather than a completion script.
+    /// The bash, zsh, and fish scripts run this to complete task names, tags, and defaults
+    /// domains.
+    #[clap(long, value_enum, conflicts_with = "shell")]
+    pub(crate) values: Option<CompletionValues>,
+}
+
+/// Arguments whose possible values `up completions --values` prints.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CompletionValues {
+    /// Names of the tasks in the tasks directory.
+    Tasks,
+    /// Tags used by the tasks in the tasks directory.
+    Tags,
+    /// Defaults domains that have preferences for the current user.
+    Domains,
+}
+
+/// CLI options passed to `up clean`.
+#[derive(Debug, Parser)]
+pub(crate) struct CleanOptions {
+    /// Print what would be removed without removing anything.
+    #[clap(long)]
+    pub(crate) dry_run: bool,
+}
+
+/// CLI options passed to `up prompt-hook`.
+#[derive(Debug, Parser)]
+pub(crate) struct PromptHookOptions {
+    /// Also show the status if up hasn't run for longer than this, even if no task is failing.
+    #[clap(long, value_name = "DURATION", default_value = "7d")]
+    pub(crate) stale_after: HumanDuration,
+}
+
+/// CLI options passed to `up watch`.
+#[derive(Debug, Clone, Parser)]
+pub(crate) struct WatchOptions {
+    /// Also re-run link tasks when files in their `from_dir` change.
+    #[clap(long)]
+    pub(crate) dotfiles: bool,
+    /// Wait until files have stopped changing for this long before re-running tasks, so saving
+    /// several files only re-runs them once.
+    #[clap(long, value_name = "DURATION", default_value = "500ms")]
+    pub(crate) debounce: HumanDuration,
+    /// Exit after re-running tasks once, rather than watching until interrupted.
+    #[clap(long)]
+    pub(crate) once: bool,
+}
+
+/// CLI options passed to `up import`.
+#[derive(Debug, Clone, Parser)]
+pub(crate) struct ImportOptions {
+    /// Dotfile manager to import from.
+    #[clap(long, value_enum)]
+    pub(crate) from: ImportSource,
+    /// Stow directory, chezmoi source directory, or Brewfile to import.
+    #[clap(value_hint = ValueHint::AnyPath)]
+    pub(crate) path: Utf8PathBuf,
+    /**
+    Directory that files should be linked into (stow's target directory, or chezmoi's destination
+    directory).
+    */
+    #[clap(long, default_value = "~", value_hint = ValueHint::DirPath)]
+    pub(crate) to: String,
+    /// Directory to write the task files to. Defaults to the tasks directory next to your up.yaml.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub(crate) tasks_dir: Option<Utf8PathBuf>,
+    /// Overwrite task files that already exist.
+    #[clap(long)]
+    pub(crate) force: bool,
+}
+
+/// Dotfile managers that `up import` can convert from.
+#[derive(Debug, ValueEnum, Clone, Copy)]
+pub(crate) enum ImportSource {
+    /// A GNU stow directory, each package becomes a `link` task.
+    Stow,
+    /// A chezmoi source directory, which becomes a task that links each managed file.
+    Chezmoi,
+    /// A Homebrew Bundle Brewfile, which becomes a `brew` library task that installs each package.
+    HomebrewBundle,
+}
+
+/// CLI options passed to `up task`.
+#[derive(Debug, Parser)]
+pub(crate) struct TaskOptions {
+    /// Task action to take.
+    #[clap(subcommand)]
+    pub(crate) subcommand: TaskSubcommand,
+}
+
+/// Subcommands supported by `up task`.
+#[derive(Debug, Parser)]
+pub(crate) enum TaskSubcommand {
+    /**
+    Create a new task file from a template, and open it in `$VISUAL` or `$EDITOR`.
+
+    Pass `--lib` to use a run library (with example `data` for that library), or `--cmd` to
+    run a command.
+    */
+    New(TaskNewOptions),
+    /// Print the path to a task's file.
+    Which(TaskNameOptions),
+    /// Print a task's file.
+    Cat(TaskNameOptions),
+    /// Open a task's file in `$VISUAL` or `$EDITOR`.
+    Edit(TaskNameOptions),
+}
+
+/// CLI options passed to `up task` subcommands that act on an existing task.
+#[derive(Debug, Parser)]
+pub(crate) struct TaskNameOptions {
+    /// Name of the task, e.g. `work/vpn`. A name without a `/` also matches a task with that name
+    /// in a subdirectory, if there's only one.
+    pub(crate) task: String,
+}
+
+/// CLI options passed to `up task new`.
+#[derive(Debug, Clone, Parser)]
+pub(crate) struct TaskNewOptions {
+    /// Name of the task, e.g. `brew-extras`. Use a `/` to create it in a subdirectory, e.g.
+    /// `work/vpn` is written to `tasks/work/vpn.yaml`.
+    pub(crate) name: String,
+    /// Run library to use, e.g. `link` or `plugin:<name>`.
+    #[clap(long, required_unless_present = "cmd")]
+    pub(crate) lib: Option<String>,
+    /// Run a command (`run_cmd`) rather than a run library.
+    #[clap(long, conflicts_with = "lib")]
+    pub(crate) cmd: bool,
+    /// Description of what the task does.
+    #[clap(long)]
+    pub(crate) description: Option<String>,
+    /// Directory to write the task file to. Defaults to the tasks directory next to your up.yaml.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub(crate) tasks_dir: Option<Utf8PathBuf>,
+    /// Overwrite the task file if it already exists.
+    #[clap(long)]
+    pub(crate) force: bool,
+    /// Don't open the new task file in `$VISUAL` or `$EDITOR`.
+    #[clap(long)]
+    pub(crate) no_edit: bool,
+}
+
+/// CLI options passed to `up man`.
+#[derive(Debug, Parser)]
+pub(crate) struct ManOptions {
+    /**
+    Directory to write the man pages to, one file per command and subcommand (e.g. `up.1`,
+    `up-run.1`).
+
+    If neither this nor `--install` is set, the man page for the top-level `up` command is
+    written to stdout.
+    */
+    #[clap(long, value_hint = ValueHint::DirPath, conflicts_with = "install")]
+    pub(crate) out_dir: Option<Utf8PathBuf>,
+    /**
+    Install the man pages into the user's manpath.
+
+    Man pages are written to `$XDG_DATA_HOME/man/man1`, or `~/.local/share/man/man1` if
+    `$XDG_DATA_HOME` is unset.
+    */
+    #[clap(long)]
+    pub(crate) install: bool,
 }
 
 impl Default for UpdateSelfOptions {
     fn default() -> Self {
         Self {
             url: SELF_UPDATE_URL.to_owned(),
+            from_file: None,
             always_update: false,
+            subcommand: None,
         }
     }
 }
@@ -278,12 +1042,16 @@ pub(crate) enum GenerateLib {
     Git(GenerateGitConfig),
     /// Generate macOS defaults commands (not yet implemented).
     Defaults(GenerateDefaultsConfig),
+    /// Generate a `brew` task from the installed Homebrew taps, formulae, casks, and Mac App Store
+    /// apps, like `brew bundle dump`.
+    BrewDump(GenerateBrewConfig),
 }
 
 /// Options
 #[derive(Debug, Parser, Serialize, Deserialize)]
 pub struct GenerateGitConfig {
-    /// Path to yaml file to update.
+    /// Path to yaml file to update. If the file has a `# up-rs managed section` comment line
+    /// inside its `data:` list, entries above that line are kept as they are.
     #[clap(long, value_hint = ValueHint::FilePath)]
     pub(crate) path: Utf8PathBuf,
     /// Paths to search within.
@@ -293,6 +1061,11 @@ pub struct GenerateGitConfig {
     /// a tmp dir.
     #[clap(long)]
     pub(crate) excludes: Option<Vec<String>>,
+    /// Skip these paths and everything inside them. Repos can also be skipped by adding a
+    /// `.up-ignore` file to them.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) skip_paths: Vec<Utf8PathBuf>,
     /// Prune all repos for branches that have already been merged and deleted
     /// upstream.
     #[clap(long)]
@@ -300,6 +1073,43 @@ pub struct GenerateGitConfig {
     /// Order to save remotes, other remotes will be included after those listed here.
     #[clap(long)]
     pub(crate) remote_order: Vec<String>,
+    /// Don't write the task file, error if it would change (e.g. for CI).
+    #[clap(long)]
+    #[serde(default)]
+    pub(crate) check: bool,
+    /// Write repos matching these rules to other task files instead of `path`, e.g.
+    /// `[{match: "~/code/work/**", path: git-work.yaml}]`. The first matching rule wins, and
+    /// relative paths are relative to the directory containing `path`.
+    #[clap(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) route: Vec<GenerateGitRoute>,
+}
+
+/// A rule to write some of the repos found by `up generate git` to their own task file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerateGitRoute {
+    /// Glob matching repo paths, e.g. `~/code/work/**`.
+    #[serde(rename = "match")]
+    pub(crate) pattern: String,
+    /// Git task file to write the matching repos to, created if it doesn't exist.
+    pub(crate) path: Utf8PathBuf,
+}
+
+/// Options passed to `up generate brew-dump`.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+pub struct GenerateBrewConfig {
+    /// Path to the yaml file to write. Fields other than `data` are kept if it already exists.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub(crate) path: Utf8PathBuf,
+    /// Leave out these taps, formulae, casks, or Mac App Store apps (by name or ID).
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) exclude: Vec<String>,
+    /// Don't write the task file, error if it would change (e.g. for CI).
+    #[clap(long)]
+    #[serde(default)]
+    pub(crate) check: bool,
 }
 
 /// Options passed to `up generate defaults`.
@@ -324,13 +1134,55 @@ pub struct DefaultsOptions {
 /// Subcommands supported by `up defaults`.
 #[derive(Debug, Parser, Serialize, Deserialize)]
 pub enum DefaultsSubcommand {
-    /// Read a defaults option and print it to the stdout as yaml.
+    /// Read a defaults option and print it to stdout (as yaml by default).
     Read(DefaultsReadOptions),
     /**
+    List the defaults domains that have preferences for the current user, one per line.
+
+    Includes sandboxed apps' domains from `~/Library/Containers`.
+    */
+    Domains,
+    /**
     Write a yaml-encoded value to a defaults plist file.
     A domain, key, and value must be provided (you can optionally use `-g` to specify the global domain).
     */
     Write(DefaultsWriteOptions),
+    /**
+    Apply a yaml file of defaults, in the same format as the `data` of a defaults task.
+
+    Values are merged and backed up in the same way as in a defaults task, so this is useful for
+    sharing one-off settings files, or for testing config before adding it as a task.
+    */
+    Apply(DefaultsApplyOptions),
+    /**
+    Merge a yaml file of keys and values into a defaults domain or plist file, and print the merged
+    plist (as XML).
+
+    Values are merged as for `up defaults write` (so `...` in arrays and dictionaries stands for
+    the existing items), but nothing is changed unless you pass `--in-place`, so this is useful for
+    editing plist files by hand.
+
+    EXAMPLES:
+
+    ❯ up defaults merge com.apple.dock dock.yaml
+
+    ❯ up defaults merge ./app.plist patch.yaml --in-place
+    */
+    Merge(DefaultsMergeOptions),
+    /**
+    Stop managing defaults up has written: restore each key's value from before up first wrote it
+    (deleting keys that didn't exist), and forget the key.
+
+    up records the keys it writes (in `defaults.yaml` in the `--state-dir`), so after removing a
+    setting from your config you can run this to revert it.
+
+    EXAMPLES:
+
+    ❯ up defaults unmanage com.apple.dock autohide
+
+    ❯ up defaults unmanage com.apple.finder
+    */
+    Unmanage(DefaultsUnmanageOptions),
 }
 
 /// CLI options passed to `up defaults read`.
@@ -345,6 +1197,34 @@ pub struct DefaultsReadOptions {
     pub(crate) domain: Option<String>,
     /// Defaults key to print.
     pub(crate) key: Option<String>,
+    /**
+    Format to print the value in.
+
+    Binary data can't be represented in yaml, json, or defaults format, so it is printed as a
+    hex-encoded string instead.
+    */
+    #[clap(long, value_enum, default_value_t)]
+    #[serde(default)]
+    pub(crate) format: DefaultsReadFormat,
+    /// Only print the keys of the domain (or of the key's value if a key is passed), one per line.
+    #[clap(long, conflicts_with = "format")]
+    #[serde(default)]
+    pub(crate) keys: bool,
+}
+
+/// Output formats for `up defaults read`.
+#[derive(Debug, ValueEnum, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DefaultsReadFormat {
+    /// YAML, the same format `up defaults write` and the defaults task take.
+    #[default]
+    Yaml,
+    /// JSON, for use by other tools.
+    Json,
+    /// XML plist.
+    PlistXml,
+    /// The format the macOS `defaults read` command prints.
+    Defaults,
 }
 
 /// CLI options passed to `up defaults write`.
@@ -353,10 +1233,15 @@ pub struct DefaultsWriteOptions {
     /// Read from the global domain. If you set this, do not also pass a domain argument.
     #[clap(short = 'g', long = "globalDomain")]
     pub(crate) global_domain: bool,
-    /// Defaults domain to write to.
-    pub(crate) domain: String,
+    /**
+    Defaults domain to write to. Use `-` to read a plist from stdin and write the updated plist
+    to stdout.
+    */
+    #[clap(required_unless_present = "from_file")]
+    pub(crate) domain: Option<String>,
     /// Defaults key to write to.
-    pub(crate) key: String,
+    #[clap(required_unless_present = "from_file")]
+    pub(crate) key: Option<String>,
     /**
     Value to write (as a yaml string).
 
@@ -366,6 +1251,64 @@ pub struct DefaultsWriteOptions {
     So if the array contained `["a", "foo", "b", "bar", "c"]`, and you write `["foo", "...", "bar", "baz"]`, you would end up with `["foo", "a", "b", "bar", "c", "baz"]`
 
     Similarly if the dict contained `{"a": 1, "foo": 2, "b": 3, "bar": 4, "c": 5}`, and you write `{"foo": 6 "...":"...", "bar": 7, "baz": 8}`, you would end up with `{"a": 1, "foo": 6, "b": 3, "bar": 4, "c": 5, "baz": 8}`
+
+    For arrays of dictionaries, use `{"...": {"identity_key": "<key>"}}` as the array value to match existing dictionaries by the value of `<key>`, updating them in place rather than adding a duplicate entry.
+    So if the array contained `[{"replace": "omw", "with": "On my way"}]`, and you write `[{"...": {"identity_key": "replace"}}, {"replace": "omw", "with": "On my way!"}]`, you would end up with `[{"replace": "omw", "with": "On my way!"}]`
     */
     pub(crate) value: Option<String>,
+    /**
+    Yaml file mapping keys to the values to write to them, to write several keys to the domain at
+    once. Use `-` to read the yaml from stdin. Values are merged as described for `value`, and the
+    plist file is only backed up and written once.
+
+    EXAMPLES:
+
+    ❯ up defaults write -g --from-file global.yaml
+
+    ❯ echo 'autohide: true' | up defaults write com.apple.dock --from-file -
+    */
+    #[clap(long, value_hint = ValueHint::FilePath, conflicts_with_all = ["key", "value"])]
+    pub(crate) from_file: Option<Utf8PathBuf>,
+}
+
+/// CLI options passed to `up defaults merge`.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+pub struct DefaultsMergeOptions {
+    /**
+    Defaults domain (e.g. `com.apple.dock` or `NSGlobalDomain`) or plist file path (containing a
+    `/`, e.g. `./app.plist`) to merge into. Use `-` to read the plist from stdin.
+    */
+    pub(crate) target: String,
+    /// Yaml file mapping keys to the values to merge into them. Use `-` to read the yaml from
+    /// stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub(crate) patch: Utf8PathBuf,
+    /// Write the merged plist back to the domain or file (backing it up first) instead of printing
+    /// it.
+    #[clap(long)]
+    #[serde(default)]
+    pub(crate) in_place: bool,
+}
+
+/// CLI options passed to `up defaults unmanage`.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+pub struct DefaultsUnmanageOptions {
+    /**
+    Defaults domain (e.g. `com.apple.dock` or `NSGlobalDomain`) or plist file path to unmanage
+    keys in.
+    */
+    pub(crate) domain: String,
+    /// Key to unmanage. Defaults to all the keys up has written in the domain.
+    pub(crate) key: Option<String>,
+}
+
+/// CLI options passed to `up defaults apply`.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+pub struct DefaultsApplyOptions {
+    /**
+    Yaml file of defaults to apply, a mapping of domains to the keys and values to set in them.
+    `~` and env vars in the domains are expanded.
+    */
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub(crate) path: Utf8PathBuf,
 }
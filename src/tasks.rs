@@ -1,16 +1,40 @@
 //! Logic for dealing with tasks executed by up.
+use self::budget::BudgetWatcher;
+use self::budget::RunBudget;
+use self::cache::TaskCache;
+use self::checkpoint::BootstrapCheckpoint;
+use self::heartbeat::Heartbeat;
+use self::heartbeat::DEFAULT_HEARTBEAT_AFTER;
+use self::plugin::Plugins;
+use self::report::Outcome;
+use self::report::ReportEntry;
+use self::resources::Resource;
+use self::resources::ResourceLimiter;
 use self::task::CommandType;
 use self::task::Task;
+use self::task::TaskRunRecord;
+use self::task::TASK_OUTPUT_FILE;
 use self::TaskError as E;
 use crate::config;
+use crate::env::add_color_env_vars;
+use crate::env::add_path_env_var;
 use crate::env::get_env;
+use crate::env::UP_CONFIG_DIR;
+use crate::opts::PlanFormat;
+use crate::tasks::task::TaskChanges;
 use crate::tasks::task::TaskStatus;
+use crate::utils::backup;
+use crate::utils::duration::HumanDuration;
 use crate::utils::files;
+use crate::utils::interpolate::interpolate;
+use crate::utils::interpolate::InterpolateError;
+use crate::utils::log::TaskLogLevelGuard;
+use crate::utils::log::SUMMARY_TARGET;
+use crate::utils::sleep;
 use crate::utils::user::current_user_is_root;
 use crate::utils::user::get_and_keep_sudo;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
-use chrono::SecondsFormat;
 use color_eyre::eyre::bail;
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Result;
@@ -18,27 +42,70 @@ use displaydoc::Display;
 use indicatif::ProgressState;
 use indicatif::ProgressStyle;
 use itertools::Itertools;
-use rayon::prelude::*;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
 use std::io;
+use std::io::IsTerminal;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
 use std::time::Duration;
 use std::time::Instant;
 use thiserror::Error;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
+use tracing::level_filters::LevelFilter;
 use tracing::trace;
 use tracing::warn;
 use tracing_indicatif::span_ext::IndicatifSpanExt;
+use walkdir::WalkDir;
 
+pub mod brew;
+pub mod budget;
+mod cache;
+mod checkpoint;
+pub(crate) mod clean;
+pub mod colima;
 pub mod completions;
+pub mod config_file;
+pub mod default_apps;
 pub mod defaults;
+mod deps;
+pub mod developer_tools;
+pub(crate) mod explain;
+pub mod finder;
 pub mod git;
+mod heartbeat;
+mod history;
+pub(crate) mod import;
+pub mod include;
+pub mod keygen;
 pub mod link;
+pub(crate) mod lint;
+mod list;
+pub(crate) mod locate;
+pub(crate) mod man;
+pub mod overlap;
+mod plan;
+pub mod plugin;
+pub(crate) mod print_env;
+pub(crate) mod prompt_hook;
+mod report;
+pub mod resources;
+pub(crate) mod sandbox;
+pub(crate) mod scaffold;
 pub(crate) mod schema;
+pub mod software_update;
 pub mod task;
+pub mod tui;
 pub mod update_self;
+pub mod vscode;
+pub(crate) mod watch;
 
 /// Trait that tasks implement to specify how to replace environment variables in their
 /// configuration.
@@ -56,13 +123,25 @@ pub trait ResolveEnv {
     }
 }
 
+/// Extensions of task files in subdirectories of the tasks directory, unless `task_extensions` is
+/// set in the up.yaml.
+const DEFAULT_TASK_EXTENSIONS: [&str; 2] = ["yaml", "yml"];
+
+/// Subdirectory of the up temp dir containing a timestamped directory for each `up run`.
+pub(crate) const RUNS_DIR: &str = "runs";
+
 /// What to do with the tasks.
 #[derive(Debug, Clone, Copy)]
 pub enum TasksAction {
     /// Run tasks.
     Run,
-    /// Just list the matching tasks.
-    List,
+    /// Just list the matching tasks, as JSON (with their last runs) if `json` is set.
+    List {
+        /// Print the tasks as JSON.
+        json: bool,
+    },
+    /// Print the tasks that would be run, and in what order, without running them.
+    Plan(PlanFormat),
 }
 
 /// Directory in which to find the tasks.
@@ -84,47 +163,113 @@ impl TasksDir {
     }
 }
 
-/// Run a set of tasks specified in a subdir of the directory containing the up
-/// config.
+/// Counts of how the tasks in a run finished.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    /// Tasks that passed.
+    pub passed: usize,
+    /// Tasks that failed.
+    pub failed: usize,
+    /// Tasks that were skipped.
+    pub skipped: usize,
+    /// Tasks that failed, but have `allow_failure` set.
+    pub warned: usize,
+    /// Tasks that weren't run as they need the network and up is running with `--offline`.
+    pub offline: usize,
+    /// Tasks that weren't run as the `--max-duration` was exceeded before they started.
+    pub not_run: usize,
+    /// Tasks that didn't finish.
+    pub incomplete: usize,
+    /// Tasks that took longer than their `slow_warn_after`, and how long they took, slowest first.
+    pub slow: Vec<(String, Duration)>,
+    /// Changes applied by the tasks that passed.
+    pub changes: TaskChanges,
+}
+
+impl RunSummary {
+    /// The number of tasks that were started, so not those that were offline or not run.
+    #[must_use]
+    pub const fn ran(&self) -> usize {
+        self.passed + self.failed + self.skipped + self.warned + self.incomplete
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            passed,
+            failed,
+            skipped,
+            warned,
+            offline,
+            not_run,
+            incomplete,
+            slow,
+            changes,
+        } = self;
+        let ran = self.ran();
+        write!(
+            f,
+            "ran {ran} tasks, {passed} passed, {failed} failed, {skipped} skipped"
+        )?;
+        for (count, status) in [
+            (warned, "warned"),
+            (incomplete, "incomplete"),
+            (offline, "offline"),
+            (not_run, "not run"),
+        ] {
+            if *count > 0 {
+                write!(f, ", {count} {status}")?;
+            }
+        }
+        if !slow.is_empty() {
+            write!(f, ", {slow} slow", slow = slow.len())?;
+        }
+        if !changes.is_empty() {
+            write!(f, ", changes applied: {changes}")?;
+        }
+        Ok(())
+    }
+}
+
+/**
+Run a set of tasks specified in a subdir of the directory containing the up config.
+
+The bootstrap, `--tasks`, `--tags`, and `--until` filters only apply to the main tasks directory,
+so `up run --generate-first` runs all the generate tasks.
+
+Returns a summary of the tasks run (empty unless `tasks_action` is [`TasksAction::Run`]).
+*/
 pub fn run(
     config: &config::UpConfig,
     tasks_dirname: TasksDir,
     tasks_action: TasksAction,
-) -> Result<()> {
-    // TODO(gib): Handle missing dir & move into config.
-    let mut tasks_dir = config
-        .up_yaml_path
-        .as_ref()
-        .ok_or(E::UnexpectedNone)?
-        .clone();
-    tasks_dir.pop();
-    tasks_dir.push(tasks_dirname.to_dir_name());
-
-    let env = get_env(
-        config.config_yaml.inherit_env.as_ref(),
-        config.config_yaml.env.as_ref(),
-    )?;
+) -> Result<RunSummary> {
+    let tasks_dir = tasks_dir(config, tasks_dirname)?;
+    let env = config_env(config)?;
 
-    // If in macOS, don't let the display sleep until the command exits.
-    #[cfg(target_os = "macos")]
-    {
-        use crate::cmd;
-        _ = cmd!("caffeinate", "-ds", "-w", &std::process::id().to_string()).start()?;
-    }
+    // Don't let the machine sleep until the tasks have finished running.
+    let _sleep_inhibitor = matches!(tasks_action, TasksAction::Run)
+        .then(|| sleep::prevent_sleep(config.config_yaml.prevent_sleep.unwrap_or_default()));
 
     // TODO(gib): Handle and filter by constraints.
 
-    let bootstrap_tasks = match (config.bootstrap, &config.config_yaml.bootstrap_tasks) {
-        (false, _) => Ok(Vec::new()),
-        (true, None) => Err(eyre!(
-            "Bootstrap flag set but no bootstrap_tasks specified in config."
-        )),
-        (true, Some(b_tasks)) => Ok(b_tasks.clone()),
-    }?;
+    let filters_apply = matches!(tasks_dirname, TasksDir::Tasks);
+
+    let filter_patterns = config
+        .tasks
+        .as_deref()
+        .filter(|_| filters_apply)
+        .map(task_patterns)
+        .transpose()?;
+    debug!("Filter tasks patterns: {filter_patterns:?}");
 
-    let filter_tasks_set: Option<HashSet<String>> =
-        config.tasks.clone().map(|v| v.into_iter().collect());
-    debug!("Filter tasks set: {filter_tasks_set:?}");
+    let filter_tags: Option<HashSet<&str>> = config
+        .tags
+        .as_ref()
+        .filter(|_| filters_apply)
+        .map(|tags| tags.iter().map(String::as_str).collect());
+    debug!("Filter tags set: {filter_tags:?}");
 
     let excluded_tasks: HashSet<String> = config
         .exclude_tasks
@@ -132,38 +277,155 @@ pub fn run(
         .map_or_else(HashSet::new, |v| v.into_iter().collect());
     debug!("Excluded tasks set: {excluded_tasks:?}");
 
-    let mut tasks: HashMap<String, task::Task> = HashMap::new();
-    for entry in tasks_dir.read_dir().map_err(|e| E::ReadDir {
-        path: tasks_dir.clone(),
-        source: e,
-    })? {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            continue;
+    let mut tasks = load_tasks(
+        &tasks_dir,
+        &config.cache_dir,
+        config.config_yaml.task_extensions.as_deref(),
+    )?;
+    if let (TasksDir::Tasks, Some(includes)) = (tasks_dirname, &config.config_yaml.include) {
+        include::add_included_tasks(includes, &config.cache_dir, config.offline, &mut tasks)?;
+    }
+    apply_config_defaults(&config.config_yaml, &mut tasks);
+    // Tasks with `bootstrap: true` are required by all the other tasks, so they run first.
+    let graph_bootstrap_tasks = if filters_apply {
+        deps::add_bootstrap_requires(&mut tasks)?
+    } else {
+        Vec::new()
+    };
+    let mut bootstrap_tasks = match (
+        config.bootstrap && filters_apply,
+        &config.config_yaml.bootstrap_tasks,
+    ) {
+        (true, None) if graph_bootstrap_tasks.is_empty() => bail!(
+            "Bootstrap flag set but no tasks have `bootstrap: true` (and no bootstrap_tasks \
+             specified in config)."
+        ),
+        (false, _) | (true, None) => Vec::new(),
+        (true, Some(b_tasks)) => {
+            warn_bootstrap_tasks_conflicts(b_tasks, &graph_bootstrap_tasks);
+            b_tasks.clone()
         }
-        let path = Utf8PathBuf::try_from(entry.path())?;
-        // If file is a broken symlink.
-        if !path.exists() && path.symlink_metadata().is_ok() {
-            files::remove_broken_symlink(&path)?;
-            continue;
+    };
+
+    set_link_on_conflict(config, &mut tasks)?;
+
+    // Exclusions are for the main tasks, so generation tasks needn't match them.
+    let mut unknown_excluded_tasks: Vec<String> = excluded_tasks
+        .iter()
+        .filter(|name| !tasks.contains_key(*name))
+        .cloned()
+        .collect();
+    if matches!(tasks_dirname, TasksDir::Tasks) && !unknown_excluded_tasks.is_empty() {
+        unknown_excluded_tasks.sort();
+        return Err(E::UnknownExcludedTasks {
+            names: unknown_excluded_tasks,
+            tasks_dir,
         }
-        let task = task::Task::from(&path)?;
-        let name = &task.name;
+        .into());
+    }
 
-        if excluded_tasks.contains(name) {
-            debug!(
-                "Not running task '{name}' as it is in the excluded tasks set {excluded_tasks:?}"
-            );
-            continue;
+    // Tasks that were filtered out, and the reason they were.
+    let mut excluded: Vec<(Task, String)> = Vec::new();
+
+    if let Some(until) = config.until.as_deref().filter(|_| filters_apply) {
+        let mut selected_tasks = deps::transitive_requires(&tasks, until)?;
+        if config.only_deps {
+            debug!("Only running the tasks that '{until}' requires: {selected_tasks:?}");
+        } else {
+            selected_tasks.insert(until.to_owned());
+            debug!("Running '{until}' and the tasks it requires: {selected_tasks:?}");
         }
+        exclude_tasks(&mut tasks, &mut excluded, |task| {
+            (!selected_tasks.contains(&task.name)).then(|| {
+                if task.name == until {
+                    "it is the --until task and --only-deps was passed".to_owned()
+                } else {
+                    format!("it is not required by the --until task '{until}'")
+                }
+            })
+        });
+    }
 
-        if let Some(filter) = filter_tasks_set.as_ref() {
-            if !filter.contains(name) {
-                debug!("Not running task '{name}' as not in tasks filter {filter:?}",);
-                continue;
-            }
+    exclude_tasks(&mut tasks, &mut excluded, |task| {
+        if excluded_tasks.contains(&task.name) {
+            return Some(format!(
+                "it is in the excluded tasks set {excluded_tasks:?}"
+            ));
         }
-        tasks.insert(name.clone(), task);
+        if let Some(host) = config
+            .host
+            .as_ref()
+            .filter(|host| host.excludes(&task.name))
+        {
+            return Some(format!(
+                "it is in the exclude_tasks of host '{name}'",
+                name = host.name
+            ));
+        }
+        if filter_patterns.is_none() && filter_tags.is_none() {
+            return None;
+        }
+        let matches_pattern = filter_patterns
+            .iter()
+            .flatten()
+            .any(|pattern| pattern.matches(&task.name));
+        let matches_tag = filter_tags.as_ref().is_some_and(|filter_tags| {
+            task.config
+                .tags
+                .iter()
+                .flatten()
+                .any(|tag| filter_tags.contains(tag.as_str()))
+        });
+        (!matches_pattern && !matches_tag).then(|| {
+            format!(
+                "it matches neither the tasks filter {:?} nor the tags filter {:?}",
+                config.tasks.iter().flatten().collect::<Vec<_>>(),
+                config.tags.iter().flatten().collect::<Vec<_>>(),
+            )
+        })
+    });
+
+    // Tasks with `auto_run: false` are only run if they're needed by another task that runs, or
+    // asked for by name. All the tasks left after `--until` are needed by the `--until` task.
+    if config.until.is_none() || !filters_apply {
+        let named: HashSet<&str> = config
+            .tasks
+            .iter()
+            .flatten()
+            .filter(|_| filters_apply)
+            .map(String::as_str)
+            .collect();
+        let mut required = HashSet::new();
+        for task in tasks.values().filter(|task| {
+            task.config.auto_run.unwrap_or(true) || named.contains(task.name.as_str())
+        }) {
+            required.extend(deps::transitive_requires(&tasks, &task.name)?);
+        }
+        exclude_tasks(&mut tasks, &mut excluded, |task| {
+            (!task.config.auto_run.unwrap_or(true)
+                && !named.contains(task.name.as_str())
+                && !required.contains(&task.name)
+                && !bootstrap_tasks.contains(&task.name))
+            .then(|| "its auto_run field is false and no task that runs requires it".to_owned())
+        });
+    }
+
+    let runs_dir = config.run_temp_dir.join(RUNS_DIR);
+    // Bootstrap tasks that aren't run as they passed in the interrupted run being resumed.
+    let resumed = if config.resume && filters_apply {
+        resume_bootstrap(&runs_dir, &mut tasks, &mut excluded)?
+    } else {
+        Vec::new()
+    };
+    bootstrap_tasks.retain(|name| !resumed.contains(name));
+
+    // Found after filtering, so excluding one of the tasks resolves an overlap.
+    let overlaps = match tasks_action {
+        TasksAction::Run | TasksAction::Plan(_) => overlap::find(&tasks, &env),
+        TasksAction::List { .. } => Vec::new(),
+    };
+    if matches!(tasks_action, TasksAction::Run) {
+        overlap::check(&overlaps, config.config_yaml.on_task_overlap)?;
     }
 
     if matches!(tasks_action, TasksAction::Run)
@@ -176,66 +438,395 @@ pub fn run(
     debug!("Task count: {:?}", tasks.len());
     trace!("Task list: {tasks:#?}");
 
-    let console = config
-        .console
-        .unwrap_or_else(|| bootstrap_tasks.len() + tasks.len() == 1);
+    // Task output would mess up the dashboard, so it always goes to files with --tui.
+    let console = !config.tui
+        && config
+            .console
+            .unwrap_or_else(|| bootstrap_tasks.len() + tasks.len() == 1);
     trace!("Setting console option to: {console}");
 
+    let mut summary = RunSummary::default();
     match tasks_action {
-        TasksAction::List => println!("{}", tasks.keys().join("\n")),
+        TasksAction::List { json: false } => println!("{}", tasks.keys().join("\n")),
+        TasksAction::List { json: true } => list::print_json(config, &tasks)?,
+        TasksAction::Plan(format) => {
+            plan::print(config, bootstrap_tasks, tasks, excluded, &overlaps, format)?;
+        }
         TasksAction::Run => {
-            let run_tempdir = config.temp_dir.join(format!(
-                "runs/{start_time}",
-                start_time = config
-                    .start_time
-                    .to_rfc3339_opts(SecondsFormat::AutoSi, true)
-                    // : is not an allowed filename character in Finder.
-                    .replace(':', "_")
-            ));
+            let run_tempdir = runs_dir.join(backup::timestamp_dir_name(&config.start_time));
+            let checkpoint = BootstrapCheckpoint::new(
+                bootstrap_tasks.iter().chain(&graph_bootstrap_tasks),
+                &tasks,
+                resumed,
+            );
 
-            run_tasks(
+            summary = run_tasks(
                 bootstrap_tasks,
                 tasks,
                 &env,
                 &run_tempdir,
-                config.keep_going,
+                config,
                 console,
+                checkpoint,
             )?;
+            config.backups.prune_or_warn();
+        }
+    }
+    Ok(summary)
+}
+
+/// Set the `on_conflict` of link tasks that don't set their own to `link_on_conflict` in up.yaml.
+fn set_link_on_conflict(
+    config: &config::UpConfig,
+    tasks: &mut HashMap<String, Task>,
+) -> Result<()> {
+    let Some(on_conflict) = config.config_yaml.link_on_conflict else {
+        return Ok(());
+    };
+    let on_conflict = serde_yaml::to_value(on_conflict)?;
+    for task in tasks.values_mut() {
+        if task.config.run_lib.as_deref() != Some("link") {
+            continue;
+        }
+        if let Some(serde_yaml::Value::Mapping(data)) = &mut task.config.data {
+            data.entry("on_conflict".into())
+                .or_insert_with(|| on_conflict.clone());
         }
     }
     Ok(())
 }
 
+/// Path to the `tasks_dirname` directory next to the up config.
+pub(crate) fn tasks_dir(config: &config::UpConfig, tasks_dirname: TasksDir) -> Result<Utf8PathBuf> {
+    // TODO(gib): Handle missing dir & move into config.
+    let mut tasks_dir = config
+        .up_yaml_path
+        .as_ref()
+        .ok_or(E::UnexpectedNone)?
+        .clone();
+    tasks_dir.pop();
+    match (tasks_dirname, &config.config_yaml.tasks_path) {
+        (TasksDir::Tasks, Some(tasks_path)) => tasks_dir.push(tasks_path),
+        _ => tasks_dir.push(tasks_dirname.to_dir_name()),
+    }
+    Ok(tasks_dir)
+}
+
+/// Env vars to pass to tasks, built from the `env`, `env_file`, `inherit_env`, and `path` config
+/// fields.
+pub(crate) fn config_env(config: &config::UpConfig) -> Result<HashMap<String, String>> {
+    let mut env = get_env(
+        config.config_yaml.inherit_env.as_ref(),
+        config
+            .config_yaml
+            .env_file
+            .as_ref()
+            .map(config::EnvFiles::paths)
+            .unwrap_or_default(),
+        config.config_yaml.env.as_ref(),
+    )?;
+    if let Some(path) = &config.config_yaml.path {
+        add_path_env_var(&mut env, path)?;
+    }
+    add_color_env_vars(&mut env, config.color);
+    if let Some(up_yaml_path) = &config.up_yaml_path {
+        let up_yaml_path = up_yaml_path.canonicalize_utf8()?;
+        if let Some(config_dir) = up_yaml_path.parent() {
+            env.insert(UP_CONFIG_DIR.to_owned(), config_dir.to_string());
+        }
+    }
+    Ok(env)
+}
+
+/**
+Expand env vars and `~` in a `path` from task data with `env_fn`, and resolve it against the
+directory containing `up.yaml` ([`UP_CONFIG_DIR`]) if it's relative.
+
+Without an `up.yaml` (e.g. for `up link`), relative paths are left relative to the current
+directory.
+*/
+pub(crate) fn resolve_config_path<F>(path: &str, env_fn: &F) -> Result<Utf8PathBuf, E>
+where
+    F: Fn(&str) -> Result<String, E>,
+{
+    let path = Utf8PathBuf::from(env_fn(path)?);
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    Ok(match env_fn(&format!("${{{UP_CONFIG_DIR}}}")) {
+        Ok(config_dir) => Utf8PathBuf::from(config_dir).join(path),
+        Err(_) => path,
+    })
+}
+
+/// Apply the `defaults` and `slow_warn_after` from `up.yaml` to the `tasks` that don't set their
+/// own.
+pub(crate) fn apply_config_defaults(
+    config_yaml: &config::ConfigYaml,
+    tasks: &mut HashMap<String, Task>,
+) {
+    for task in tasks.values_mut() {
+        if let Some(defaults) = &config_yaml.defaults {
+            let had_requires = task.config.requires.is_some();
+            task.config.apply_defaults(defaults);
+            // A task can't require itself, e.g. the task the default `requires` points to.
+            if let (false, Some(requires)) = (had_requires, &mut task.config.requires) {
+                requires.retain(|name| *name != task.name);
+            }
+        }
+        if let Some(slow_warn_after) = &config_yaml.slow_warn_after {
+            task.config
+                .slow_warn_after
+                .get_or_insert_with(|| slow_warn_after.clone());
+        }
+    }
+}
+
+/// Load all the tasks in `tasks_dir` (including its subdirectories), keyed by name. Broken
+/// symlinks are removed.
+pub(crate) fn load_tasks(
+    tasks_dir: &Utf8Path,
+    cache_dir: &Utf8Path,
+    task_extensions: Option<&[String]>,
+) -> Result<HashMap<String, Task>> {
+    let mut cache = TaskCache::load(cache_dir);
+    let mut tasks: HashMap<String, task::Task> = HashMap::new();
+    for path in task_file_paths(tasks_dir, task_extensions)? {
+        // If file is a broken symlink.
+        if !path.exists() && path.symlink_metadata().is_ok() {
+            files::remove_broken_symlink(&path)?;
+            continue;
+        }
+        if path.is_dir() {
+            debug!("Skipping symlink to directory {path}.");
+            continue;
+        }
+        let mut task = cache.task(&path)?;
+        task.name = namespaced_name(tasks_dir, &path, &task.name);
+        tasks.insert(task.name.clone(), task);
+    }
+    cache.save(tasks_dir);
+    Ok(tasks)
+}
+
+/// Paths of the task files (and symlinks) in `tasks_dir`, sorted.
+///
+/// All files directly in `tasks_dir` are tasks. In subdirectories only files with the
+/// `task_extensions` (by default `.yaml` and `.yml`) are, so tasks can keep their helper scripts
+/// next to them.
+pub(crate) fn task_file_paths(
+    tasks_dir: &Utf8Path,
+    task_extensions: Option<&[String]>,
+) -> Result<Vec<Utf8PathBuf>> {
+    let is_task_extension = |extension: &str| match task_extensions {
+        Some(task_extensions) => task_extensions.iter().any(|e| e == extension),
+        None => DEFAULT_TASK_EXTENSIONS.contains(&extension),
+    };
+    let mut paths = Vec::new();
+    for entry in WalkDir::new(tasks_dir).min_depth(1).sort_by_file_name() {
+        let entry = entry.map_err(|e| E::ReadDir {
+            path: tasks_dir.to_owned(),
+            source: e.into(),
+        })?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let path = Utf8PathBuf::try_from(entry.into_path())?;
+        if path.parent() != Some(tasks_dir) && !path.extension().is_some_and(is_task_extension) {
+            trace!("Skipping file without a task extension in task subdirectory: {path}");
+            continue;
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Prefix the task `name` with the subdirectory of `tasks_dir` that its file at `path` is in, so
+/// `tasks/work/vpn.yaml` is called `work/vpn`.
+pub(crate) fn namespaced_name(tasks_dir: &Utf8Path, path: &Utf8Path, name: &str) -> String {
+    match path
+        .strip_prefix(tasks_dir)
+        .ok()
+        .and_then(Utf8Path::parent)
+        .filter(|namespace| !namespace.as_str().is_empty())
+    {
+        Some(namespace) => format!("{namespace}/{name}"),
+        None => name.to_owned(),
+    }
+}
+
+/// Parse the `--tasks` filter glob `patterns`.
+fn task_patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>, E> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern).map_err(|e| E::InvalidTaskPattern {
+                pattern: pattern.clone(),
+                source: e,
+            })
+        })
+        .collect()
+}
+
+/// Remove the tasks for which `exclude_reason` returns a reason from `tasks`, and add them to
+/// `excluded`.
+fn exclude_tasks(
+    tasks: &mut HashMap<String, Task>,
+    excluded: &mut Vec<(Task, String)>,
+    exclude_reason: impl Fn(&Task) -> Option<String>,
+) {
+    let to_exclude: Vec<(String, String)> = tasks
+        .values()
+        .filter_map(|task| exclude_reason(task).map(|reason| (task.name.clone(), reason)))
+        .collect();
+    for (name, reason) in to_exclude {
+        debug!("Not running task '{name}' as {reason}");
+        if let Some(task) = tasks.remove(&name) {
+            excluded.push((task, reason));
+        }
+    }
+}
+
+/**
+Exclude the bootstrap tasks that passed in the last run if it was interrupted, returning their names.
+
+Tasks with a `run_if_cmd` aren't excluded, so the command can check whether they need running again.
+*/
+fn resume_bootstrap(
+    runs_dir: &Utf8Path,
+    tasks: &mut HashMap<String, Task>,
+    excluded: &mut Vec<(Task, String)>,
+) -> Result<Vec<String>> {
+    let Some((resumed_run_dir, checkpoint)) =
+        BootstrapCheckpoint::latest(runs_dir)?.filter(|(_, checkpoint)| !checkpoint.is_complete())
+    else {
+        info!("No interrupted bootstrap run to resume, running all the tasks.");
+        return Ok(Vec::new());
+    };
+    let resumed: Vec<String> = checkpoint
+        .passed
+        .into_iter()
+        .filter(|name| {
+            tasks
+                .get(name)
+                .is_some_and(|task| task.config.run_if_cmd.is_none())
+        })
+        .collect();
+    info!(
+        "Resuming the interrupted run {resumed_run_dir}, skipping bootstrap tasks that passed: \
+         {resumed:?}"
+    );
+    exclude_tasks(tasks, excluded, |task| {
+        resumed
+            .contains(&task.name)
+            .then(|| format!("it passed in the interrupted run {resumed_run_dir}"))
+    });
+    Ok(resumed)
+}
+
+/// Warn that `bootstrap_tasks` is deprecated, and about tasks where it disagrees with the tasks
+/// that have `bootstrap: true` (`graph_bootstrap_tasks`).
+fn warn_bootstrap_tasks_conflicts(bootstrap_tasks: &[String], graph_bootstrap_tasks: &[String]) {
+    warn!(
+        "The `bootstrap_tasks` config field is deprecated, set `bootstrap: true` in those tasks \
+         instead (and use `requires` to order them)."
+    );
+    if graph_bootstrap_tasks.is_empty() {
+        return;
+    }
+    let only_listed: Vec<&String> = bootstrap_tasks
+        .iter()
+        .filter(|name| !graph_bootstrap_tasks.contains(name))
+        .collect();
+    if !only_listed.is_empty() {
+        warn!(
+            "Tasks {only_listed:?} are in `bootstrap_tasks` but don't have `bootstrap: true`, so \
+             they run before the bootstrap tasks {graph_bootstrap_tasks:?} that they would \
+             otherwise require."
+        );
+    }
+    let only_marked: Vec<&String> = graph_bootstrap_tasks
+        .iter()
+        .filter(|name| !bootstrap_tasks.contains(name))
+        .collect();
+    if !only_marked.is_empty() {
+        warn!(
+            "Tasks {only_marked:?} have `bootstrap: true` but aren't in `bootstrap_tasks`, so \
+             they run after the `bootstrap_tasks` {bootstrap_tasks:?}."
+        );
+    }
+}
+
 /// Runs a set of tasks.
 fn run_tasks(
     bootstrap_tasks: Vec<String>,
     mut tasks: HashMap<String, task::Task>,
     env: &HashMap<String, String>,
     temp_dir: &Utf8Path,
-    keep_going: bool,
+    config: &config::UpConfig,
     console: bool,
-) -> Result<()> {
+    mut checkpoint: Option<BootstrapCheckpoint>,
+) -> Result<RunSummary> {
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.write(temp_dir)?;
+    }
     let mut completed_tasks = Vec::new();
+    let resource_limiter =
+        ResourceLimiter::new(config.config_yaml.max_parallel.clone().unwrap_or_default());
+    let plugins = Plugins::new(config);
+    let _budget_watcher = BudgetWatcher::start(config.budget.as_ref());
+    let tasks_count = tasks.len() + bootstrap_tasks.len();
 
-    // Has to be top-level so span continues for whole run.
-    let _header_span;
-    if !console {
-        _header_span = set_up_header(tasks.len() + bootstrap_tasks.len())?;
-    }
+    let has_interactive_tasks = warn_interactive_tasks(&tasks, config.tui);
+
+    let dashboard = if config.tui && !has_interactive_tasks {
+        let other_task_names = tasks
+            .keys()
+            .filter(|name| !bootstrap_tasks.contains(name))
+            .cloned()
+            .sorted();
+        Some(tui::Dashboard::start(
+            bootstrap_tasks.iter().cloned().chain(other_task_names),
+        )?)
+    } else {
+        None
+    };
+
+    // Has to be top-level so span continues for whole run (except while interactive tasks run).
+    let mut header_span = if !console && dashboard.is_none() {
+        Some(set_up_header(tasks_count)?)
+    } else {
+        None
+    };
 
     if !bootstrap_tasks.is_empty() {
         for task_name in bootstrap_tasks {
             let task_tempdir = create_task_tempdir(temp_dir, &task_name)?;
+            let task = tasks
+                .remove(&task_name)
+                .ok_or_else(|| eyre!("Task '{task_name}' was missing."))?;
 
-            let task = run_task(
-                tasks
-                    .remove(&task_name)
-                    .ok_or_else(|| eyre!("Task '{task_name}' was missing."))?,
-                env,
-                &task_tempdir,
-                console,
-            );
-            if !keep_going {
+            let interactive = task.config.interactive;
+            let run = || {
+                run_task(
+                    task,
+                    env,
+                    &task_tempdir,
+                    config,
+                    console || interactive,
+                    &plugins,
+                    dashboard.as_ref(),
+                )
+            };
+            let task = if interactive {
+                run_interactive(&mut header_span, tasks_count, run)?
+            } else {
+                run()
+            };
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.record(&task, temp_dir)?;
+            }
+            if !config.keep_going {
                 if let TaskStatus::Failed(e) = task.status {
                     bail!(e);
                 }
@@ -244,57 +835,314 @@ fn run_tasks(
         }
     }
 
-    completed_tasks.extend(
-        tasks
-            .into_par_iter()
-            .filter(|(_, task)| task.config.auto_run.unwrap_or(true))
-            .map(|(_, task)| {
-                let task_name = task.name.as_str();
-                let _span = if console {
-                    tracing::info_span!("task", task = task_name, indicatif.pb_hide = true)
-                        .entered()
-                } else {
-                    tracing::info_span!("task", task = task_name).entered()
+    // Names of tasks that failed, tasks that require them are not run.
+    let failed_task_names: HashSet<String> = completed_tasks
+        .iter()
+        .filter(|t| matches!(t.status, TaskStatus::Failed(_)))
+        .map(|t| t.name.clone())
+        .collect();
+
+    let failed_task_names = Mutex::new(failed_task_names);
+    let start_task = |mut task: Task, console: bool| {
+        let task_name = task.name.as_str();
+        let _span = if console {
+            tracing::info_span!("task", task = task_name, indicatif.pb_hide = true).entered()
+        } else {
+            tracing::info_span!("task", task = task_name).entered()
+        };
+        // Once the budget is used up tasks are reported as not run, whether or not the tasks
+        // they require passed.
+        let budget_exceeded = config.budget.as_ref().is_some_and(RunBudget::exceeded);
+        let failed_task_names = failed_task_names
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(required) = task
+            .config
+            .requires
+            .iter()
+            .flatten()
+            .filter(|_| !budget_exceeded)
+            .find(|r| failed_task_names.contains(*r))
+        {
+            task.status = task.failed_status(E::RequiredTaskFailed {
+                name: task.name.clone(),
+                required: required.clone(),
+            });
+            if let Some(dashboard) = &dashboard {
+                dashboard.task_finished(&task);
+            }
+            return Ok(task);
+        }
+        let task_tempdir = create_task_tempdir(temp_dir, task_name)?;
+        Ok(run_task(
+            task,
+            env,
+            &task_tempdir,
+            config,
+            console,
+            &plugins,
+            dashboard.as_ref(),
+        ))
+    };
+    run_scheduled(
+        tasks,
+        &resource_limiter,
+        |task| run_interactive(&mut header_span, tasks_count, || start_task(task, true))?,
+        |task| start_task(task, console),
+        |task| {
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.record(&task, temp_dir)?;
+            }
+            if matches!(task.status, TaskStatus::Failed(_)) {
+                failed_task_names
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(task.name.clone());
+            }
+            completed_tasks.push(task);
+            Ok(())
+        },
+    )?;
+    if let Some(dashboard) = dashboard {
+        dashboard.finish()?;
+    }
+    summarise_run(completed_tasks, config)
+}
+
+/**
+Run `tasks` in parallel with `run_task`, starting each as soon as the tasks it requires have
+finished and the resources and mutex it needs are free. `on_finished` is called with each task
+once it has finished.
+
+Interactive tasks need the terminal, so once one is ready no more tasks are started until the
+running ones finish, and then it's run on its own with `run_interactive_task`.
+
+Tasks are started on this thread rather than inside the rayon pool, so tasks waiting for resources
+don't block pool threads that running tasks (e.g. the `git` and `defaults` libraries) need for
+their own parallel work.
+*/
+fn run_scheduled(
+    mut tasks: HashMap<String, Task>,
+    resource_limiter: &ResourceLimiter,
+    mut run_interactive_task: impl FnMut(Task) -> Result<Task>,
+    run_task: impl Fn(Task) -> Result<Task> + Sync,
+    mut on_finished: impl FnMut(Task) -> Result<()>,
+) -> Result<()> {
+    let mut waiting_for = deps::requirements(&tasks)?;
+    // Tasks that become ready together are started in name order, so runs are repeatable.
+    let mut ready: Vec<Task> = Vec::new();
+    let (sender, receiver) = mpsc::channel();
+    let mut running = 0_usize;
+
+    rayon::in_place_scope(|scope| loop {
+        let newly_ready: Vec<String> = waiting_for
+            .iter()
+            .filter(|(_, requires)| requires.is_empty())
+            .map(|(name, _)| name.clone())
+            .sorted()
+            .collect();
+        for name in newly_ready {
+            waiting_for.remove(&name);
+            ready.extend(tasks.remove(&name));
+        }
+
+        if let Some(index) = ready.iter().position(|task| task.config.interactive) {
+            if running == 0 {
+                let task = run_interactive_task(ready.remove(index))?;
+                finish_scheduled_task(task, &mut waiting_for, &mut on_finished)?;
+                continue;
+            }
+        } else {
+            loop {
+                let needs: Vec<(&[Resource], Option<&str>)> = ready
+                    .iter()
+                    .map(|task| {
+                        (
+                            task.config.resources.as_deref().unwrap_or_default(),
+                            task.config.mutex.as_deref(),
+                        )
+                    })
+                    .collect();
+                let Some((index, guard)) = resource_limiter.try_acquire_any(&needs) else {
+                    break;
                 };
-                let task_tempdir = create_task_tempdir(temp_dir, task_name)?;
-                Ok(run_task(task, env, &task_tempdir, console))
-            })
-            .collect::<Result<Vec<Task>>>()?,
-    );
-    let completed_tasks_len = completed_tasks.len();
+                let task = ready.remove(index);
+                let (run_task, sender) = (&run_task, sender.clone());
+                running += 1;
+                scope.spawn(move |_| {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| run_task(task)))
+                        .unwrap_or_else(|_| Err(eyre!("Task panicked while running.")));
+                    drop(guard);
+                    // The receiver only goes away if scheduling failed, so there's nothing to
+                    // report to.
+                    _ = sender.send(result);
+                });
+            }
+        }
+
+        if running == 0 {
+            // Nothing is running to free resources or finish requirements, so we're done.
+            return Ok(());
+        }
+        let task = receiver.recv().map_err(|_| E::UnexpectedNone)??;
+        running -= 1;
+        finish_scheduled_task(task, &mut waiting_for, &mut on_finished)?;
+    })
+}
 
+/// Mark `task` as finished, so the tasks in `waiting_for` that require it can start.
+fn finish_scheduled_task(
+    task: Task,
+    waiting_for: &mut HashMap<String, Vec<String>>,
+    on_finished: &mut impl FnMut(Task) -> Result<()>,
+) -> Result<()> {
+    for requires in waiting_for.values_mut() {
+        requires.retain(|name| *name != task.name);
+    }
+    on_finished(task)
+}
+
+/// Log the results of the completed tasks, returning an error if any failed.
+fn summarise_run(completed_tasks: Vec<Task>, config: &config::UpConfig) -> Result<RunSummary> {
     let mut tasks_passed = Vec::new();
     let mut tasks_skipped = Vec::new();
     let mut tasks_failed = Vec::new();
+    let mut tasks_warned = Vec::new();
+    let mut tasks_offline = Vec::new();
+    let mut tasks_not_run = Vec::new();
     let mut tasks_incomplete = Vec::new();
 
+    let mut slow: Vec<(String, Duration)> = completed_tasks
+        .iter()
+        .filter_map(|task| {
+            task.run_time
+                .filter(|run_time| *run_time > task.slow_warn_after())
+                .map(|run_time| (task.name.clone(), run_time))
+        })
+        .collect();
+    slow.sort_by_key(|(_, run_time)| Reverse(*run_time));
+
+    let mut changes = TaskChanges::default();
     for task in completed_tasks {
         match task.status {
             TaskStatus::Failed(_) => {
                 tasks_failed.push(task);
             }
-            TaskStatus::Passed => tasks_passed.push(task),
+            TaskStatus::Passed(ref task_changes) => {
+                changes += task_changes.clone();
+                tasks_passed.push(task);
+            }
             TaskStatus::Skipped => tasks_skipped.push(task),
+            TaskStatus::Warned(_) => tasks_warned.push(task),
+            TaskStatus::Offline => tasks_offline.push(task),
+            TaskStatus::NotRun => tasks_not_run.push(task),
             TaskStatus::Incomplete => tasks_incomplete.push(task),
         }
     }
 
+    let summary = RunSummary {
+        passed: tasks_passed.len(),
+        failed: tasks_failed.len(),
+        skipped: tasks_skipped.len(),
+        warned: tasks_warned.len(),
+        offline: tasks_offline.len(),
+        not_run: tasks_not_run.len(),
+        incomplete: tasks_incomplete.len(),
+        slow,
+        changes,
+    };
     info!(
-        "Ran {completed_tasks_len} tasks, {} passed, {} failed, {} skipped",
-        tasks_passed.len(),
-        tasks_failed.len(),
-        tasks_skipped.len()
+        target: SUMMARY_TARGET,
+        "Ran {} tasks, {} passed, {} failed, {} skipped{}",
+        summary.ran(),
+        summary.passed,
+        summary.failed,
+        summary.skipped,
+        [
+            (summary.warned, "warned"),
+            (summary.incomplete, "incomplete"),
+            (summary.offline, "offline"),
+            (summary.not_run, "not run"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, status)| format!(", {count} {status}"))
+        .join("")
     );
-    if !tasks_passed.is_empty() {
+    let report_entries: Vec<ReportEntry> = tasks_passed
+        .iter()
+        .map(|t| ReportEntry::new(t, Outcome::Passed))
+        .chain(
+            tasks_skipped
+                .iter()
+                .map(|t| ReportEntry::new(t, Outcome::Skipped)),
+        )
+        .chain(
+            tasks_offline
+                .iter()
+                .map(|t| ReportEntry::new(t, Outcome::Offline)),
+        )
+        .chain(
+            tasks_not_run
+                .iter()
+                .map(|t| ReportEntry::new(t, Outcome::NotRun)),
+        )
+        .chain(
+            tasks_warned
+                .iter()
+                .map(|t| ReportEntry::new(t, Outcome::Warned)),
+        )
+        .chain(
+            tasks_failed
+                .iter()
+                .map(|t| ReportEntry::new(t, Outcome::Failed)),
+        )
+        .collect();
+    if let Some(report) = report::format_report(&report_entries, config.summary) {
+        info!("{report}");
+    }
+    if !summary.changes.is_empty() {
         info!(
-            "Tasks passed: {:?}",
-            tasks_passed.iter().map(|t| &t.name).collect::<Vec<_>>()
+            target: SUMMARY_TARGET,
+            "Changes applied: {changes}",
+            changes = summary.changes
         );
     }
-    if !tasks_skipped.is_empty() {
+    if !summary.changes.branches_pruned.is_empty() {
         info!(
-            "Tasks skipped: {:?}",
-            tasks_skipped.iter().map(|t| &t.name).collect::<Vec<_>>()
+            target: SUMMARY_TARGET,
+            "Pruned branches:\n  {}",
+            summary.changes.branches_pruned.join("\n  ")
+        );
+    }
+    if !summary.changes.branches_to_prune.is_empty() {
+        info!(
+            target: SUMMARY_TARGET,
+            "Branches that would be pruned:\n  {}",
+            summary.changes.branches_to_prune.join("\n  ")
+        );
+    }
+    if !tasks_warned.is_empty() {
+        warn!(
+            "Tasks failed but have allow_failure set: {}",
+            tasks_warned.iter().map(|t| &t.name).join(", ")
+        );
+    }
+    if !tasks_not_run.is_empty() {
+        warn!(
+            "Tasks not run as the --max-duration was exceeded: {}",
+            tasks_not_run.iter().map(|t| &t.name).sorted().join(", ")
+        );
+    }
+    if !summary.slow.is_empty() {
+        warn!(
+            "Slow tasks: {}",
+            summary
+                .slow
+                .iter()
+                .map(|(name, run_time)| format!("{name} ({run_time:.1?})"))
+                .join(", ")
         );
     }
 
@@ -315,7 +1163,53 @@ fn run_tasks(
         tasks_failed_iter.fold(Err(err), color_eyre::Help::error)?;
     }
 
-    Ok(())
+    Ok(summary)
+}
+
+/**
+Warn about any interactive tasks being run without a terminal for input, or with the dashboard
+(`tui`), which isn't shown when they're run. Returns whether there are any interactive tasks.
+*/
+fn warn_interactive_tasks(tasks: &HashMap<String, Task>, tui: bool) -> bool {
+    let interactive_tasks = tasks
+        .values()
+        .filter(|task| task.config.interactive)
+        .map(|task| &task.name)
+        .sorted()
+        .join(", ");
+    if interactive_tasks.is_empty() {
+        return false;
+    }
+    if !io::stdin().is_terminal() {
+        warn!(
+            "Running interactive tasks ({interactive_tasks}) but stdin isn't a terminal, so they \
+             may hang or fail waiting for input."
+        );
+    }
+    if tui {
+        warn!(
+            "Not showing the task dashboard, as interactive tasks ({interactive_tasks}) need the \
+             terminal."
+        );
+    }
+    true
+}
+
+/**
+Run an interactive task with `run`, hiding the header progress bar (if shown) while it runs, so it
+isn't redrawn over the task's prompts.
+*/
+fn run_interactive<T>(
+    header_span: &mut Option<tracing::Span>,
+    tasks_count: usize,
+    run: impl FnOnce() -> T,
+) -> Result<T> {
+    let header_shown = header_span.take().is_some();
+    let result = run();
+    if header_shown {
+        *header_span = Some(set_up_header(tasks_count)?);
+    }
+    Ok(result)
 }
 
 /// Runs a specific task.
@@ -323,33 +1217,92 @@ fn run_task(
     mut task: Task,
     env: &HashMap<String, String>,
     task_tempdir: &Utf8Path,
+    config: &config::UpConfig,
     console: bool,
+    plugins: &Plugins,
+    dashboard: Option<&tui::Dashboard>,
 ) -> Task {
-    let env_fn = &|s: &str| {
-        let home_dir = files::home_dir().map_err(|e| E::EyreError { source: e })?;
-        let out = shellexpand::full_with_context(
-            s,
-            || Some(home_dir),
-            |k| env.get(k).ok_or_else(|| eyre!("Value not found")).map(Some),
-        )
-        .map(std::borrow::Cow::into_owned)
-        .map_err(|e| E::ResolveEnv {
-            var: e.var_name,
-            source: e.cause,
-        })?;
-
-        Ok(out)
+    if let Some(dashboard) = dashboard {
+        dashboard.task_started(&task.name, task_tempdir);
+    }
+    let env_fn = &|s: &str| resolve_env_value(s, env);
+    // Interactive tasks are probably waiting for input, so don't log over their prompts.
+    let heartbeat_after = if task.config.interactive {
+        Duration::ZERO
+    } else {
+        config
+            .config_yaml
+            .heartbeat_after
+            .as_ref()
+            .map_or(DEFAULT_HEARTBEAT_AFTER, HumanDuration::duration)
     };
+    let heartbeat = Heartbeat::start(
+        &task.name,
+        (!console).then(|| task_tempdir.join(TASK_OUTPUT_FILE)),
+        heartbeat_after,
+    );
 
     let now = Instant::now();
-    task.run(env_fn, env, task_tempdir, console);
+    if config.budget.as_ref().is_some_and(RunBudget::exceeded) {
+        info!("Not running task as the run's --max-duration was exceeded.");
+        task.status = TaskStatus::NotRun;
+    } else if config.offline && task.needs_network() {
+        info!("Not running task as it needs the network and up is running offline.");
+        task.status = TaskStatus::Offline;
+    } else {
+        let _log_level = TaskLogLevelGuard::set(task.config.log_level.map(LevelFilter::from));
+        task.kill_at = config.budget.as_ref().and_then(RunBudget::kill_at);
+        task.run(
+            env_fn,
+            env,
+            task_tempdir,
+            config.backups.run_dir(),
+            console,
+            plugins,
+        );
+    }
     let elapsed_time = now.elapsed();
-    if elapsed_time > Duration::from_secs(60) {
+    drop(heartbeat);
+    task.run_time = Some(elapsed_time);
+    if elapsed_time > task.slow_warn_after() {
         warn!("Task took {elapsed_time:?}");
     }
+    if let Err(e) = TaskRunRecord::new(&task.status, elapsed_time).write(task_tempdir) {
+        warn!("Failed to save task status: {e:?}");
+    }
+    if let Some(dashboard) = dashboard {
+        dashboard.task_finished(&task);
+    }
     task
 }
 
+/**
+Run the `$(cmd ...)` commands in a task config value, then expand `~` and env vars (from `env`).
+
+Commands are only taken from the config text itself, so an env var whose value contains
+`$(cmd ...)` doesn't run it.
+*/
+pub(crate) fn resolve_env_value(s: &str, env: &HashMap<String, String>) -> Result<String, E> {
+    let interpolated = interpolate(s, env).map_err(|e| E::Interpolate { source: e })?;
+    resolve_env_vars(&interpolated, env)
+}
+
+/// Expand `~` and env vars (from `env`) in a task config value without running any `$(cmd ...)`
+/// commands in it, for showing config without changing anything.
+pub(crate) fn resolve_env_vars(s: &str, env: &HashMap<String, String>) -> Result<String, E> {
+    let home_dir = files::home_dir().map_err(|e| E::EyreError { source: e })?;
+    shellexpand::full_with_context(
+        s,
+        || Some(home_dir),
+        |k| env.get(k).ok_or_else(|| eyre!("Value not found")).map(Some),
+    )
+    .map(std::borrow::Cow::into_owned)
+    .map_err(|e| E::ResolveEnv {
+        var: e.var_name,
+        source: e.cause,
+    })
+}
+
 /// Create a subdir of the current temporary directory for the task.
 fn create_task_tempdir(temp_dir: &Utf8Path, task_name: &str) -> Result<Utf8PathBuf> {
     let task_tempdir = temp_dir.join(task_name);
@@ -418,6 +1371,50 @@ pub enum TaskError {
         /// Source error.
         source: color_eyre::eyre::Error,
     },
+    /// Task `{name}` was not run as the task it requires, `{required}`, failed.
+    RequiredTaskFailed {
+        /// The task name.
+        name: String,
+        /// The required task that failed.
+        required: String,
+    },
+    /// Excluded tasks {names:?} were not found in the tasks directory `{tasks_dir}`.
+    UnknownExcludedTasks {
+        /// The excluded task names that weren't found.
+        names: Vec<String>,
+        /// The tasks directory.
+        tasks_dir: Utf8PathBuf,
+    },
+    /// Invalid glob pattern `{pattern}` passed to `--tasks`.
+    InvalidTaskPattern {
+        /// The invalid pattern.
+        pattern: String,
+        /// Source error.
+        source: glob::PatternError,
+    },
+    /// Failed to write the run script to `{path}`.
+    WriteScript {
+        /// The path we failed to write to.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: io::Error,
+    },
+    /// Task `{name}` requires commands that weren't found in the PATH: {commands}.
+    MissingCommands {
+        /// The task name.
+        name: String,
+        /// The missing commands.
+        commands: String,
+    },
+    /// Task `{name}` has an invalid `redact` pattern `{pattern}`.
+    InvalidRedact {
+        /// The task name.
+        name: String,
+        /// The pattern.
+        pattern: String,
+        /// Source error.
+        source: regex::Error,
+    },
     /// Command was empty.
     EmptyCmd,
     /// Task `{name}` had no run command.
@@ -479,6 +1476,26 @@ pub enum TaskError {
         /// Source error.
         source: serde_yaml::Error,
     },
+    /// Task script `{path}` isn't executable, make it executable with `chmod +x {path}`.
+    ScriptNotExecutable {
+        /// Path to the script.
+        path: Utf8PathBuf,
+    },
+    /// Invalid `# up:` front-matter in task script `{path}`: {source}
+    InvalidFrontMatter {
+        /// Path to the script.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_yaml::Error,
+    },
+    /**
+    Task script `{path}` sets `run_cmd`, `run_script`, or `run_lib` in its front-matter, but
+      script tasks run the script itself.
+    */
+    ScriptRunField {
+        /// Path to the script.
+        path: Utf8PathBuf,
+    },
     /// Unable to calculate the current user's home directory.
     MissingHomeDir,
     /// Env lookup error, please define `{var}` in your up.yaml
@@ -488,6 +1505,11 @@ pub enum TaskError {
         /// Source error.
         source: color_eyre::eyre::Error,
     },
+    /// Failed to interpolate command output into task data.
+    Interpolate {
+        /// Source error.
+        source: InterpolateError,
+    },
     /// Task {task} must have data.
     TaskDataRequired {
         /// Task name.
@@ -504,3 +1526,197 @@ pub enum TaskError {
         source: color_eyre::Report,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_config_path;
+    use super::resolve_env_value;
+    use super::resolve_env_vars;
+    use super::run_scheduled;
+    use super::ResolveEnv;
+    use super::RunSummary;
+    use crate::env::UP_CONFIG_DIR;
+    use crate::opts::GenerateGitConfig;
+    use crate::opts::LinkOptions;
+    use crate::tasks::defaults::DefaultsConfig;
+    use crate::tasks::git::GitConfig;
+    use crate::tasks::resources::ResourceLimiter;
+    use crate::tasks::task::Task;
+    use crate::tasks::task::TaskChanges;
+    use crate::tasks::task::TaskConfig;
+    use crate::tasks::task::TaskStatus;
+    use camino::Utf8PathBuf;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+    use testutils::ensure_eq;
+
+    /// Resolve env vars in task data, with `up.yaml` in `/config`.
+    fn resolve<T: ResolveEnv + for<'de> serde::Deserialize<'de>>(yaml: &str) -> Result<T> {
+        let env = HashMap::from([
+            (UP_CONFIG_DIR.to_owned(), "/config".to_owned()),
+            ("dir".to_owned(), "dotfiles".to_owned()),
+        ]);
+        let mut data: T = serde_yaml::from_str(yaml)?;
+        data.resolve_env(|s| resolve_env_value(s, &env))?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_run_summary_display() -> Result<()> {
+        let summary = RunSummary {
+            passed: 3,
+            failed: 1,
+            skipped: 2,
+            warned: 1,
+            offline: 4,
+            not_run: 5,
+            incomplete: 1,
+            slow: vec![("slow".to_owned(), Duration::from_mins(2))],
+            changes: TaskChanges {
+                files_linked: 2,
+                ..TaskChanges::default()
+            },
+        };
+        // Offline and not run tasks weren't started, so aren't counted as ran.
+        ensure_eq!(8, summary.ran());
+        ensure_eq!(
+            "ran 8 tasks, 3 passed, 1 failed, 2 skipped, 1 warned, 1 incomplete, 4 offline, 5 not \
+             run, 1 slow, changes applied: 2 files linked",
+            summary.to_string()
+        );
+        ensure_eq!(
+            "ran 0 tasks, 0 passed, 0 failed, 0 skipped",
+            RunSummary::default().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_env_value() -> Result<()> {
+        let env = HashMap::from([
+            ("PATH".to_owned(), std::env::var("PATH")?),
+            ("command".to_owned(), "$(cmd false)".to_owned()),
+        ]);
+        ensure_eq!(
+            "$command/bin",
+            resolve_env_value("$(cmd echo '$command')/bin", &env)?
+        );
+        // Commands in the values of env vars aren't run.
+        ensure_eq!("$(cmd false)", resolve_env_value("${command}", &env)?);
+        ensure_eq!("$(cmd false)", resolve_env_vars("$(cmd false)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_config_path() -> Result<()> {
+        let link: LinkOptions = resolve("{from_dir: $dir, to_dir: /home/me}")?;
+        ensure_eq!("/config/dotfiles", link.from_dir);
+        ensure_eq!("/home/me", link.to_dir);
+
+        let git: Vec<GitConfig> =
+            resolve("[{path: ../code/up, remotes: [{name: origin, fetch_url: up.git}]}]")?;
+        ensure_eq!(
+            Some(&Utf8PathBuf::from("/config/../code/up")),
+            git.first().map(|git| &git.path)
+        );
+
+        let defaults: DefaultsConfig =
+            resolve("{./prefs/test.plist: {key: 1}, com.apple.dock: {autohide: true}}")?;
+        let defaults = serde_yaml::to_string(&defaults)?;
+        ensure_eq!(
+            true,
+            defaults.contains("/config/./prefs/test.plist:")
+                && defaults.contains("com.apple.dock:")
+        );
+
+        let generate: Vec<GenerateGitConfig> = resolve(
+            "[{path: tasks/git.yaml, search_paths: [code, /src], remote_order: [], prune: false}]",
+        )?;
+        let generate = generate
+            .first()
+            .ok_or_else(|| eyre!("Expected a generate config."))?;
+        ensure_eq!(Utf8PathBuf::from("/config/tasks/git.yaml"), generate.path);
+        ensure_eq!(
+            vec![Utf8PathBuf::from("/config/code"), Utf8PathBuf::from("/src")],
+            generate.search_paths
+        );
+
+        // Without an up.yaml, relative paths are left as they are.
+        ensure_eq!(
+            Utf8PathBuf::from("dotfiles"),
+            resolve_config_path("dotfiles", &|s| resolve_env_value(s, &HashMap::new()))?
+        );
+        Ok(())
+    }
+
+    /// Tasks start as soon as the tasks they require finish, without waiting for unrelated tasks.
+    #[test]
+    fn test_run_scheduled() -> Result<()> {
+        let task = |name: &str, requires: &[&str]| {
+            (
+                name.to_owned(),
+                Task {
+                    name: name.to_owned(),
+                    path: Utf8PathBuf::from(format!("/config/tasks/{name}.yaml")),
+                    config: TaskConfig {
+                        requires: Some(requires.iter().map(|&r| r.to_owned()).collect()),
+                        ..TaskConfig::default()
+                    },
+                    start_time: Instant::now(),
+                    status: TaskStatus::Incomplete,
+                    run_time: None,
+                    kill_at: None,
+                },
+            )
+        };
+        let tasks = HashMap::from([
+            task("first", &[]),
+            task("second", &["first"]),
+            task("slow", &[]),
+        ]);
+
+        // `slow` only passes if `second` finishes while it's still running.
+        let second_done = AtomicBool::new(false);
+        let mut finished = Vec::new();
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()?
+            .install(|| {
+                run_scheduled(
+                    tasks,
+                    &ResourceLimiter::default(),
+                    |_| Err(eyre!("No tasks are interactive.")),
+                    |mut task| {
+                        task.status = TaskStatus::Skipped;
+                        if task.name == "second" {
+                            second_done.store(true, Ordering::SeqCst);
+                        } else if task.name == "slow" {
+                            let deadline = Instant::now() + Duration::from_secs(10);
+                            while !second_done.load(Ordering::SeqCst) {
+                                if Instant::now() > deadline {
+                                    return Err(eyre!("Second task didn't run alongside slow."));
+                                }
+                                thread::sleep(Duration::from_millis(10));
+                            }
+                        }
+                        Ok(task)
+                    },
+                    |task| {
+                        finished.push(task.name);
+                        Ok(())
+                    },
+                )
+            })?;
+        let position = |name: &str| finished.iter().position(|n| n == name);
+        ensure_eq!(3, finished.len());
+        ensure_eq!(true, position("first") < position("second"));
+        ensure_eq!(true, position("second") < position("slow"));
+        Ok(())
+    }
+}
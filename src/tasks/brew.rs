@@ -0,0 +1,279 @@
+/*!
+The `brew` library task, to make sure Homebrew taps, formulae, casks, and Mac App Store apps are
+installed.
+
+```yaml
+run_lib: brew
+data:
+  taps:
+    - homebrew/cask-fonts
+  formulae:
+    - git
+    - ripgrep
+  casks:
+    - firefox
+  mas:
+    - name: Xcode
+      id: 497799835
+```
+
+Only what isn't already installed is installed (Mac App Store apps with [mas]), nothing is upgraded
+or removed. The task is skipped if everything is already installed.
+
+`up generate brew-dump` writes a task like this from what's currently installed.
+
+[mas]: https://github.com/mas-cli/mas
+*/
+use self::BrewError as E;
+use crate::exec::cmd;
+use crate::exec::cmd_log;
+use crate::exec::UpDuct;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TaskError;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashSet;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::Level;
+
+/// Configuration for a `brew` run library task.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrewConfig {
+    /// Taps to add, e.g. `homebrew/cask-fonts`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub taps: Vec<String>,
+    /// Formulae to install, e.g. `git` or `user/tap/formula`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub formulae: Vec<String>,
+    /// Casks to install, e.g. `firefox`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub casks: Vec<String>,
+    /// Mac App Store apps to install.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mas: Vec<MasApp>,
+}
+
+/// A Mac App Store app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MasApp {
+    /// App name, only used for logging.
+    pub name: String,
+    /// App Store ID, e.g. `497799835` for Xcode.
+    pub id: u64,
+}
+
+impl ResolveEnv for BrewConfig {
+    fn resolve_env<F>(&mut self, env_fn: F) -> Result<(), TaskError>
+    where
+        F: Fn(&str) -> Result<String, TaskError>,
+    {
+        for name in self
+            .taps
+            .iter_mut()
+            .chain(&mut self.formulae)
+            .chain(&mut self.casks)
+        {
+            *name = env_fn(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a `brew` run library task.
+pub(crate) fn run(config: &BrewConfig) -> Result<TaskStatus> {
+    let mut did_work = false;
+    if !config.taps.is_empty() {
+        // `brew tap` only takes one tap at a time.
+        for tap in missing(&config.taps, &list_taps()?) {
+            did_work |= install("brew", &["tap"], &[tap])?;
+        }
+    }
+    if !config.formulae.is_empty() {
+        let missing = missing(
+            &config.formulae,
+            &list("brew", &["list", "--formula", "-1"])?,
+        );
+        did_work |= install("brew", &["install"], &missing)?;
+    }
+    if !config.casks.is_empty() {
+        let missing = missing(&config.casks, &list_casks()?);
+        did_work |= install("brew", &["install", "--cask"], &missing)?;
+    }
+    if !config.mas.is_empty() {
+        let installed: HashSet<u64> = parse_mas_list(&list("mas", &["list"])?)
+            .into_iter()
+            .map(|app| app.id)
+            .collect();
+        let missing: Vec<String> = config
+            .mas
+            .iter()
+            .filter(|app| !installed.contains(&app.id))
+            .inspect(|app| debug!("Mac App Store app '{name}' is missing.", name = app.name))
+            .map(|app| app.id.to_string())
+            .collect();
+        did_work |= install("mas", &["install"], &missing)?;
+    }
+
+    if did_work {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    } else {
+        Ok(TaskStatus::Skipped)
+    }
+}
+
+/// The installed taps.
+pub(crate) fn list_taps() -> Result<Vec<String>, E> {
+    list("brew", &["tap"])
+}
+
+/// The installed casks.
+pub(crate) fn list_casks() -> Result<Vec<String>, E> {
+    list("brew", &["list", "--cask", "-1"])
+}
+
+/// The non-empty lines of the output of `program args`.
+pub(crate) fn list(program: &str, args: &[&str]) -> Result<Vec<String>, E> {
+    let output = cmd_log(Level::DEBUG, program, args)
+        .read()
+        .map_err(|e| E::List {
+            command: format!("{program} {}", args.join(" ")),
+            source: e,
+        })?;
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Parse the output of `mas list`, e.g. `497799835  Xcode  (15.0)`.
+pub(crate) fn parse_mas_list(output: &[String]) -> Vec<MasApp> {
+    output
+        .iter()
+        .filter_map(|line| {
+            let (id, rest) = line.trim().split_once(char::is_whitespace)?;
+            let name = rest
+                .rsplit_once(" (")
+                .map_or(rest, |(name, _version)| name)
+                .trim();
+            Some(MasApp {
+                name: name.to_owned(),
+                id: id.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// The `wanted` names that aren't `installed`. Formulae and casks from taps are listed by their
+/// short name, so `user/tap/name` is installed if `name` is.
+fn missing(wanted: &[String], installed: &[String]) -> Vec<String> {
+    let installed: HashSet<&str> = installed.iter().map(String::as_str).collect();
+    wanted
+        .iter()
+        .filter(|name| {
+            let short_name = name.rsplit('/').next().unwrap_or(name);
+            !installed.contains(name.as_str()) && !installed.contains(short_name)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Run `program args names`, returning whether there were any `names` to install.
+fn install(program: &str, args: &[&str], names: &[String]) -> Result<bool, E> {
+    if names.is_empty() {
+        return Ok(false);
+    }
+    let command = format!("{program} {}", args.join(" "));
+    info!("Running `{command}` for: {names}", names = names.join(", "));
+    cmd(
+        program,
+        args.iter().copied().chain(names.iter().map(String::as_str)),
+    )
+    .run_with(Expression::stdout_to_stderr)
+    .map_err(|e| E::Install {
+        command,
+        names: names.join(", "),
+        source: e,
+    })?;
+    Ok(true)
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum BrewError {
+    /// Failed to run `{command}`, is it installed?
+    List {
+        /// The command.
+        command: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// `{command}` failed for {names}.
+    Install {
+        /// The command.
+        command: String,
+        /// What was being installed.
+        names: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::missing;
+    use super::parse_mas_list;
+    use super::MasApp;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_missing() -> Result<()> {
+        let installed = vec!["git".to_owned(), "swiftformat".to_owned()];
+        ensure_eq!(
+            vec!["ripgrep".to_owned()],
+            missing(
+                &[
+                    "git".to_owned(),
+                    "ripgrep".to_owned(),
+                    "nicklockwood/formulae/swiftformat".to_owned()
+                ],
+                &installed
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mas_list() -> Result<()> {
+        let output = vec![
+            "497799835  Xcode                (15.0)".to_owned(),
+            "1295203466 Microsoft Remote Desktop (10.9.4)".to_owned(),
+            "not an app".to_owned(),
+        ];
+        ensure_eq!(
+            vec![
+                MasApp {
+                    name: "Xcode".to_owned(),
+                    id: 497_799_835
+                },
+                MasApp {
+                    name: "Microsoft Remote Desktop".to_owned(),
+                    id: 1_295_203_466
+                },
+            ],
+            parse_mas_list(&output)
+        );
+        Ok(())
+    }
+}
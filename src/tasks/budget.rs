@@ -0,0 +1,132 @@
+/*!
+The run-duration budget set by `up run --max-duration`.
+
+Once the budget is used up no more tasks are started: running tasks are left to finish (or with
+`--hard` their commands are killed), and the tasks that didn't get to start are reported as
+`not run`. A warning is logged when 80% of the budget has been used, so a run that's going to
+overrun is noticed before tasks start being dropped.
+
+```text
+WARN Used 80% of the 30m --max-duration, 6m left.
+WARN Exceeded the 30m --max-duration, not starting any more tasks.
+```
+*/
+use crate::tasks::heartbeat::format_elapsed;
+use crate::utils::duration::HumanDuration;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Instant;
+use tracing::warn;
+
+/// Percentage of the budget after which we warn that the run is likely to overrun.
+const WARN_PERCENT: u32 = 80;
+
+/// How long a run may take, from `--max-duration`.
+#[derive(Debug, Clone)]
+pub struct RunBudget {
+    /// How long the run may take.
+    pub max_duration: HumanDuration,
+    /// When the budget runs out.
+    pub deadline: Instant,
+    /// Kill the commands of running tasks at the deadline, rather than letting them finish.
+    pub hard: bool,
+}
+
+impl RunBudget {
+    /// A budget of `max_duration` starting now.
+    #[must_use]
+    pub fn new(max_duration: HumanDuration, hard: bool) -> Self {
+        Self {
+            deadline: Instant::now() + max_duration.duration(),
+            max_duration,
+            hard,
+        }
+    }
+
+    /// Whether the budget has been used up, so no more tasks should be started.
+    #[must_use]
+    pub fn exceeded(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// When running task commands should be killed, if `--hard` was passed.
+    #[must_use]
+    pub fn kill_at(&self) -> Option<Instant> {
+        self.hard.then_some(self.deadline)
+    }
+}
+
+/// Warns as the run budget is used up, on a separate thread until this is dropped.
+#[derive(Debug)]
+pub(super) struct BudgetWatcher {
+    /// Dropping this tells the watcher thread to stop.
+    stop: Option<mpsc::Sender<()>>,
+    /// The watcher thread.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BudgetWatcher {
+    /**
+    Start warning when [`WARN_PERCENT`] of the `budget` has been used, and again when it runs out.
+
+    Warnings whose time has already passed aren't logged, so watching again later in the run
+    (e.g. for the main tasks after `--generate-first`) doesn't repeat them.
+    */
+    pub(super) fn start(budget: Option<&RunBudget>) -> Self {
+        let Some(budget) = budget else {
+            return Self {
+                stop: None,
+                thread: None,
+            };
+        };
+        let max_duration = budget.max_duration.clone();
+        let deadline = budget.deadline;
+        let hard = budget.hard;
+        let warn_at = deadline
+            .checked_sub(max_duration.duration() * (100 - WARN_PERCENT) / 100)
+            .unwrap_or(deadline);
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let now = Instant::now();
+            if now < warn_at {
+                // Returns on a message or disconnect, i.e. when the run finishes.
+                let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(warn_at - now) else {
+                    return;
+                };
+                let left = format_elapsed(deadline.saturating_duration_since(Instant::now()));
+                warn!("Used {WARN_PERCENT}% of the {max_duration} --max-duration, {left} left.");
+            }
+            let now = Instant::now();
+            if now < deadline {
+                let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(deadline - now) else {
+                    return;
+                };
+                if hard {
+                    warn!(
+                        "Exceeded the {max_duration} --max-duration, not starting any more tasks \
+                         and killing running task commands."
+                    );
+                } else {
+                    warn!(
+                        "Exceeded the {max_duration} --max-duration, not starting any more tasks."
+                    );
+                }
+            }
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for BudgetWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
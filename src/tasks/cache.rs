@@ -0,0 +1,308 @@
+//! Cache parsed task config files, so unchanged tasks aren't re-parsed on every invocation.
+use crate::tasks::task::Task;
+use crate::tasks::task::TaskConfig;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use ring::digest;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::process;
+use std::time::SystemTime;
+use tracing::debug;
+use tracing::trace;
+
+/// Path (relative to the up cache dir, see `--cache-dir`) of the task cache file.
+const TASK_CACHE_FILE: &str = "tasks.bin";
+
+/// Version of up that wrote the cache, the cache is ignored if it was written by a different
+/// version (as the task config format may have changed).
+const CACHE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Contents of the task cache file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    /// Version of up that wrote the cache.
+    version: String,
+    /// Parsed task configs by task file path.
+    tasks: HashMap<Utf8PathBuf, CachedTask>,
+}
+
+/// A parsed task config, and what the file looked like when it was parsed.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedTask {
+    /// Modification time of the task file.
+    modified: Option<SystemTime>,
+    /// Size of the task file in bytes.
+    size: u64,
+    /// Permission mode of the task file, as script tasks have to be executable.
+    mode: u32,
+    /// SHA-256 hash of the task file contents.
+    hash: String,
+    /// Parsed task config.
+    config: TaskConfig,
+}
+
+/// Parsed task configs from previous runs, keyed by task file path.
+#[derive(Debug)]
+pub(super) struct TaskCache {
+    /// Path of the cache file.
+    path: Utf8PathBuf,
+    /// Cache contents.
+    file: CacheFile,
+    /// Task files loaded with this cache.
+    seen: HashSet<Utf8PathBuf>,
+    /// Whether the cache needs to be written back to disk.
+    changed: bool,
+}
+
+impl TaskCache {
+    /// Load the task cache from the up `cache_dir`. If it is missing or can't be read, we start
+    /// with an empty cache.
+    pub(super) fn load(cache_dir: &Utf8Path) -> Self {
+        let path = cache_dir.join(TASK_CACHE_FILE);
+        let file = match fs::read(&path).map(|bytes| rmp_serde::from_slice::<CacheFile>(&bytes)) {
+            Ok(Ok(file)) if file.version == CACHE_VERSION => file,
+            Ok(Ok(file)) => {
+                debug!(
+                    "Ignoring task cache {path} written by up version {version}.",
+                    version = file.version
+                );
+                CacheFile::default()
+            }
+            Ok(Err(e)) => {
+                debug!("Ignoring invalid task cache {path}: {e}");
+                CacheFile::default()
+            }
+            Err(e) => {
+                trace!("No task cache found at {path}: {e}");
+                CacheFile::default()
+            }
+        };
+        Self {
+            path,
+            file,
+            seen: HashSet::new(),
+            changed: false,
+        }
+    }
+
+    /**
+    Load the task at `path`, using the cached config if the file hasn't changed since it was
+    parsed.
+
+    The file is only read and hashed if its modification time or size changed, so unchanged tasks
+    cost a `stat` per run. The cached config is never used if the file's permissions changed, as
+    that decides whether a script task is valid.
+    */
+    pub(super) fn task(&mut self, path: &Utf8Path) -> Result<Task> {
+        let metadata = fs::metadata(path).ok();
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+        let size = metadata.as_ref().map_or(0, fs::Metadata::len);
+        let mode = metadata.as_ref().map_or(0, |m| m.permissions().mode());
+        self.seen.insert(path.to_owned());
+
+        if let Some(cached) = self.file.tasks.get(path).filter(|cached| {
+            modified.is_some()
+                && cached.modified == modified
+                && cached.size == size
+                && cached.mode == mode
+        }) {
+            trace!("Using cached config for unmodified task file {path}");
+            return Task::from_config(path, cached.config.clone());
+        }
+
+        let contents = Task::read_file(path)?;
+        let hash = hex::encode(digest::digest(&digest::SHA256, contents.as_bytes()));
+        if let Some(cached) = self
+            .file
+            .tasks
+            .get_mut(path)
+            .filter(|cached| cached.hash == hash && cached.mode == mode)
+        {
+            trace!("Using cached config for task file {path}, whose contents didn't change");
+            cached.modified = modified;
+            cached.size = size;
+            self.changed = true;
+            return Task::from_config(path, cached.config.clone());
+        }
+
+        let config = Task::parse_config(path, &contents)?;
+        // YAML tags (e.g. `!!binary`) aren't preserved by the cache format.
+        if config.data.as_ref().is_some_and(has_tags) {
+            trace!("Not caching task file {path} as its data contains YAML tags.");
+            self.changed |= self.file.tasks.remove(path).is_some();
+        } else {
+            self.file.tasks.insert(
+                path.to_owned(),
+                CachedTask {
+                    modified,
+                    size,
+                    mode,
+                    hash,
+                    config: config.clone(),
+                },
+            );
+            self.changed = true;
+        }
+        Task::from_config(path, config)
+    }
+
+    /// Drop cached tasks in `tasks_dir` that weren't loaded (as they've been deleted), and write
+    /// the cache back to disk if it changed. Failing to write the cache isn't an error, as it only
+    /// makes the next run slower.
+    pub(super) fn save(mut self, tasks_dir: &Utf8Path) {
+        let before = self.file.tasks.len();
+        self.file
+            .tasks
+            .retain(|path, _| !path.starts_with(tasks_dir) || self.seen.contains(path));
+        if !self.changed && self.file.tasks.len() == before {
+            return;
+        }
+        CACHE_VERSION.clone_into(&mut self.file.version);
+        if let Err(e) = self.write() {
+            debug!("Failed to write task cache {path}: {e}", path = self.path);
+        }
+    }
+
+    /// Write the cache file, via a temporary file so concurrent runs don't see a partial write.
+    fn write(&self) -> Result<()> {
+        let bytes = rmp_serde::to_vec_named(&self.file)?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = self
+            .path
+            .with_extension(format!("bin.{pid}", pid = process::id()));
+        fs::write(&temp_path, bytes)?;
+        fs::rename(&temp_path, &self.path)?;
+        trace!("Wrote task cache {path}", path = self.path);
+        Ok(())
+    }
+}
+
+/**
+Remove the task cache from the up `cache_dir` (unless `dry_run`), returning its path if there was
+one.
+*/
+pub(super) fn remove(cache_dir: &Utf8Path, dry_run: bool) -> Result<Option<Utf8PathBuf>> {
+    let path = cache_dir.join(TASK_CACHE_FILE);
+    if dry_run {
+        return Ok(path.exists().then_some(path));
+    }
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(Some(path)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether a YAML value uses tags (e.g. `!custom`), which can't be cached.
+fn has_tags(value: &serde_yaml::Value) -> bool {
+    match value {
+        serde_yaml::Value::Tagged(_) => true,
+        serde_yaml::Value::Sequence(seq) => seq.iter().any(has_tags),
+        serde_yaml::Value::Mapping(map) => map.iter().any(|(k, v)| has_tags(k) || has_tags(v)),
+        serde_yaml::Value::Null
+        | serde_yaml::Value::Bool(_)
+        | serde_yaml::Value::Number(_)
+        | serde_yaml::Value::String(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::remove;
+    use super::TaskCache;
+    use super::TASK_CACHE_FILE;
+    use color_eyre::eyre::ensure;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+    use std::time::SystemTime;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_task_cache() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let tasks_dir = temp_dir.join("tasks");
+        fs::create_dir_all(&tasks_dir)?;
+        let task_path = tasks_dir.join("hello.yaml");
+        fs::write(&task_path, "run_cmd: [echo, hello]\n")?;
+
+        let mut cache = TaskCache::load(&temp_dir);
+        let task = cache.task(&task_path)?;
+        ensure_eq!(
+            Some(vec!["echo".to_owned(), "hello".to_owned()]),
+            task.config.run_cmd
+        );
+        cache.save(&tasks_dir);
+
+        // While the file is unchanged, the cached config is used rather than re-parsing it.
+        let mut cache = TaskCache::load(&temp_dir);
+        ensure_eq!(1, cache.file.tasks.len());
+        cache
+            .file
+            .tasks
+            .get_mut(&task_path)
+            .ok_or_else(|| eyre!("Task {task_path} wasn't cached."))?
+            .config
+            .description = Some("from cache".to_owned());
+        let task = cache.task(&task_path)?;
+        ensure_eq!(Some("from cache".to_owned()), task.config.description);
+
+        // Touching the file without changing its contents still uses the cached config.
+        fs::File::options()
+            .write(true)
+            .open(&task_path)?
+            .set_modified(SystemTime::now() + Duration::from_secs(90))?;
+        let task = cache.task(&task_path)?;
+        ensure_eq!(Some("from cache".to_owned()), task.config.description);
+
+        // Changing the file invalidates the cache entry.
+        fs::write(&task_path, "run_cmd: [echo, goodbye]\n")?;
+        let task = cache.task(&task_path)?;
+        ensure_eq!(
+            Some(vec!["echo".to_owned(), "goodbye".to_owned()]),
+            task.config.run_cmd
+        );
+        ensure_eq!(None, task.config.description);
+
+        // Making a script task non-executable doesn't change its modification time or size, but
+        // the cached config isn't used.
+        let script_path = tasks_dir.join("script");
+        fs::write(&script_path, "#!/bin/sh\necho hello\n")?;
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))?;
+        cache.task(&script_path)?;
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o644))?;
+        ensure!(cache.task(&script_path).is_err());
+        fs::remove_file(&script_path)?;
+        cache.save(&tasks_dir);
+
+        // Deleted tasks are dropped from the cache.
+        fs::remove_file(&task_path)?;
+        let cache = TaskCache::load(&temp_dir);
+        cache.save(&tasks_dir);
+        ensure_eq!(0, TaskCache::load(&temp_dir).file.tasks.len());
+
+        // `up clean` removes the cache.
+        ensure_eq!(
+            Some(temp_dir.join(TASK_CACHE_FILE)),
+            remove(&temp_dir, true)?
+        );
+        ensure_eq!(
+            Some(temp_dir.join(TASK_CACHE_FILE)),
+            remove(&temp_dir, false)?
+        );
+        ensure_eq!(None, remove(&temp_dir, false)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+/*!
+Bootstrap progress saved in each run directory, so `up run --resume` can skip the bootstrap tasks
+that already passed in an interrupted run.
+*/
+use crate::tasks::history::RunHistory;
+use crate::tasks::task::Task;
+use crate::tasks::task::TaskStatus;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use itertools::Itertools;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+
+/// File in the run directory that the [`BootstrapCheckpoint`] is written to.
+pub(super) const CHECKPOINT_FILE: &str = "bootstrap_checkpoint.json";
+
+/// Which bootstrap tasks have passed so far in a run.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct BootstrapCheckpoint {
+    /// The bootstrap tasks in the run.
+    pub(super) tasks: Vec<String>,
+    /// The bootstrap tasks that passed (or were skipped as there was nothing to do).
+    pub(super) passed: Vec<String>,
+}
+
+impl BootstrapCheckpoint {
+    /**
+    Checkpoint for a run of the `bootstrap_tasks` that are in `tasks`, after the `resumed` tasks
+    passed in an earlier run. `None` if there are no bootstrap tasks.
+    */
+    pub(super) fn new<'a>(
+        bootstrap_tasks: impl Iterator<Item = &'a String>,
+        tasks: &HashMap<String, Task>,
+        resumed: Vec<String>,
+    ) -> Option<Self> {
+        let checkpoint_tasks: Vec<String> = bootstrap_tasks
+            .filter(|name| tasks.contains_key(*name))
+            .unique()
+            .cloned()
+            .chain(resumed.iter().cloned())
+            .collect();
+        (!checkpoint_tasks.is_empty()).then_some(Self {
+            tasks: checkpoint_tasks,
+            passed: resumed,
+        })
+    }
+
+    /// Whether all the bootstrap tasks passed.
+    pub(super) fn is_complete(&self) -> bool {
+        self.tasks.iter().all(|name| self.passed.contains(name))
+    }
+
+    /// The checkpoint of the most recent run in `runs_dir` that had bootstrap tasks, and its run
+    /// directory.
+    pub(super) fn latest(runs_dir: &Utf8Path) -> Result<Option<(Utf8PathBuf, Self)>> {
+        for run_dir in RunHistory::read(runs_dir)?.run_dirs() {
+            match fs::read_to_string(run_dir.join(CHECKPOINT_FILE)) {
+                Ok(contents) => {
+                    return Ok(Some((run_dir.clone(), serde_json::from_str(&contents)?)));
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Save the checkpoint to the [`CHECKPOINT_FILE`] in `run_dir`.
+    pub(super) fn write(&self, run_dir: &Utf8Path) -> Result<()> {
+        fs::create_dir_all(run_dir)?;
+        fs::write(run_dir.join(CHECKPOINT_FILE), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Record that the `task` finished, saving the checkpoint to `run_dir` if it was a bootstrap
+    /// task that passed.
+    pub(super) fn record(&mut self, task: &Task, run_dir: &Utf8Path) -> Result<()> {
+        if !self.tasks.contains(&task.name)
+            || self.passed.contains(&task.name)
+            || !matches!(task.status, TaskStatus::Passed(_) | TaskStatus::Skipped)
+        {
+            return Ok(());
+        }
+        self.passed.push(task.name.clone());
+        self.write(run_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BootstrapCheckpoint;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_latest() -> Result<()> {
+        let runs_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        ensure!(BootstrapCheckpoint::latest(&runs_dir)?.is_none());
+
+        let checkpoint = BootstrapCheckpoint {
+            tasks: vec!["xcode".to_owned(), "brew".to_owned()],
+            passed: vec!["xcode".to_owned()],
+        };
+        checkpoint.write(&runs_dir.join("2024-01-01T00_00_00Z"))?;
+        // Runs without bootstrap tasks don't have a checkpoint.
+        std::fs::create_dir_all(runs_dir.join("2024-01-02T00_00_00Z"))?;
+
+        let latest = BootstrapCheckpoint::latest(&runs_dir)?;
+        ensure_eq!(
+            Some((runs_dir.join("2024-01-01T00_00_00Z"), checkpoint)),
+            latest
+        );
+        ensure!(latest
+            .as_ref()
+            .is_some_and(|(_, checkpoint)| !checkpoint.is_complete()));
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+//! Removes old up backups and run directories, and the task cache.
+use crate::opts::CleanOptions;
+use crate::opts::Opts;
+use crate::tasks::cache;
+use crate::tasks::RUNS_DIR;
+use crate::utils::backup;
+use crate::utils::backup::Backups;
+use color_eyre::Result;
+use tracing::info;
+
+/// Run the `up clean` command.
+pub(crate) fn run(opts: &Opts, backups: &Backups, cmd_opts: &CleanOptions) -> Result<()> {
+    let CleanOptions { dry_run } = *cmd_opts;
+
+    let mut removed = backups.prune(dry_run)?;
+    removed.extend(backup::prune_timestamped_dirs(
+        &opts.run_temp_dir()?.join(RUNS_DIR),
+        opts.keep_backups,
+        dry_run,
+    )?);
+
+    if dry_run {
+        info!("Would remove {} old directories.", removed.len());
+    } else {
+        info!("Removed {} old directories.", removed.len());
+    }
+
+    if let Some(task_cache) = cache::remove(&opts.cache_dir()?, dry_run)? {
+        if dry_run {
+            info!("Would remove the task cache {task_cache}.");
+        } else {
+            info!("Removed the task cache {task_cache}.");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,395 @@
+/*!
+The `colima` library task, to make sure a [Colima] (Lima) VM for running containers exists, is
+running, and has the resources you want.
+
+```yaml
+run_lib: colima
+data:
+  profile: default
+  cpus: 4
+  memory: 8
+  disk: 100
+  runtime: docker
+  mounts:
+    - ~/code:w
+  docker_context: true
+```
+
+The VM is created if it doesn't exist, and started if it's stopped. If it's running with a
+different number of `cpus`, `memory`, `disk` size, or `runtime`, it's restarted with the new
+settings (Colima can grow a VM's disk, but not shrink it). Fields that aren't set use Colima's
+defaults when creating the VM, and aren't checked afterwards. `colima list` doesn't show mounts, so
+`mounts` are only applied when the VM is created or restarted.
+
+With `docker_context: true` the VM's Docker context is also made the current one.
+
+The task is skipped if the VM is already running with the right settings.
+
+[Colima]: https://github.com/abiosoft/colima
+*/
+use self::ColimaError as E;
+use crate::cmd;
+use crate::cmd_debug;
+use crate::exec::UpDuct;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TaskError;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fmt;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+
+/// Bytes in a GiB, `colima list` shows memory and disk sizes in bytes.
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Configuration for a `colima` run library task.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColimaConfig {
+    /// Colima profile (VM) name, defaults to `default`.
+    pub profile: Option<String>,
+    /// Number of CPUs to give the VM.
+    pub cpus: Option<u64>,
+    /// Memory to give the VM, in GiB.
+    pub memory: Option<u64>,
+    /// Disk size of the VM, in GiB.
+    pub disk: Option<u64>,
+    /// Container runtime, e.g. `docker` or `containerd`.
+    pub runtime: Option<String>,
+    /// Directories to mount in the VM, e.g. `~/code:w` for a writable mount.
+    #[serde(default)]
+    pub mounts: Vec<String>,
+    /// Make the VM's Docker context the current one.
+    #[serde(default)]
+    pub docker_context: bool,
+}
+
+impl ResolveEnv for ColimaConfig {
+    fn resolve_env<F>(&mut self, env_fn: F) -> Result<(), TaskError>
+    where
+        F: Fn(&str) -> Result<String, TaskError>,
+    {
+        for mount in &mut self.mounts {
+            *mount = env_fn(mount)?;
+        }
+        Ok(())
+    }
+}
+
+impl ColimaConfig {
+    /// The profile name.
+    fn profile(&self) -> &str {
+        self.profile.as_deref().unwrap_or("default")
+    }
+
+    /// Name of the profile's Docker context.
+    fn docker_context_name(&self) -> String {
+        match self.profile() {
+            "default" => "colima".to_owned(),
+            profile => format!("colima-{profile}"),
+        }
+    }
+
+    /// Arguments to `colima start` to create or restart the VM with this config.
+    fn start_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "start".to_owned(),
+            "--profile".to_owned(),
+            self.profile().to_owned(),
+        ];
+        for (flag, value) in [
+            ("--cpu", self.cpus),
+            ("--memory", self.memory),
+            ("--disk", self.disk),
+        ] {
+            if let Some(value) = value {
+                args.extend([flag.to_owned(), value.to_string()]);
+            }
+        }
+        if let Some(runtime) = &self.runtime {
+            args.extend(["--runtime".to_owned(), runtime.clone()]);
+        }
+        for mount in &self.mounts {
+            args.extend(["--mount".to_owned(), mount.clone()]);
+        }
+        args
+    }
+}
+
+/// A VM listed by `colima list --json`.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct Instance {
+    /// Profile name.
+    name: String,
+    /// `Running` or `Stopped`.
+    status: String,
+    /// Number of CPUs.
+    #[serde(default)]
+    cpus: u64,
+    /// Memory in bytes.
+    #[serde(default)]
+    memory: u64,
+    /// Disk size in bytes.
+    #[serde(default)]
+    disk: u64,
+    /// Container runtime.
+    #[serde(default)]
+    runtime: String,
+}
+
+/// What needs doing to make the VM match the config.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    /// Nothing, the VM is running with the right settings.
+    None,
+    /// Create the VM, as it doesn't exist.
+    Create,
+    /// Start the stopped VM.
+    Start,
+    /// Restart the running VM, as these settings differ.
+    Restart(Vec<String>),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "nothing to do"),
+            Self::Create => write!(f, "creating it"),
+            Self::Start => write!(f, "starting it"),
+            Self::Restart(diffs) => write!(f, "restarting it to change {}", diffs.join(", ")),
+        }
+    }
+}
+
+/// Run a `colima` run library task.
+pub(crate) fn run(config: &ColimaConfig) -> Result<TaskStatus> {
+    let profile = config.profile();
+    let list_output = cmd_debug!("colima", "list", "--json")
+        .read()
+        .map_err(|e| E::List { source: e })?;
+    let instance = parse_list(&list_output)?
+        .into_iter()
+        .find(|instance| instance.name == profile);
+    let action = action(config, instance.as_ref());
+    debug!("Colima profile '{profile}': {action}.");
+
+    let mut changed = action != Action::None;
+    if changed {
+        info!("Colima profile '{profile}': {action}.");
+    }
+    if let Action::Restart(_) = action {
+        cmd!("colima", "stop", "--profile", profile)
+            .run_with(Expression::stdout_to_stderr)
+            .map_err(|e| E::Stop {
+                profile: profile.to_owned(),
+                source: e,
+            })?;
+    }
+    if changed {
+        crate::exec::cmd("colima", config.start_args())
+            .run_with(Expression::stdout_to_stderr)
+            .map_err(|e| E::Start {
+                profile: profile.to_owned(),
+                source: e,
+            })?;
+    }
+
+    if config.docker_context {
+        changed |= use_docker_context(&config.docker_context_name())?;
+    }
+
+    if changed {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    } else {
+        Ok(TaskStatus::Skipped)
+    }
+}
+
+/// Parse the output of `colima list --json`, which has a JSON object per line.
+fn parse_list(output: &str) -> Result<Vec<Instance>, E> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| E::ParseList { source: e }))
+        .collect()
+}
+
+/// Work out what needs doing to make the `instance` (if it exists) match the `config`.
+fn action(config: &ColimaConfig, instance: Option<&Instance>) -> Action {
+    let Some(instance) = instance else {
+        return Action::Create;
+    };
+    if instance.status != "Running" {
+        return Action::Start;
+    }
+    let mut diffs = Vec::new();
+    for (name, wanted, actual) in [
+        ("cpus", config.cpus, instance.cpus),
+        ("memory", config.memory, instance.memory / GIB),
+        ("disk", config.disk, instance.disk / GIB),
+    ] {
+        if let Some(wanted) = wanted.filter(|wanted| *wanted != actual) {
+            diffs.push(format!("{name} from {actual} to {wanted}"));
+        }
+    }
+    if let Some(runtime) = config.runtime.as_ref().filter(|r| **r != instance.runtime) {
+        diffs.push(format!(
+            "runtime from {actual} to {runtime}",
+            actual = instance.runtime
+        ));
+    }
+    if diffs.is_empty() {
+        Action::None
+    } else {
+        Action::Restart(diffs)
+    }
+}
+
+/// Make `context` the current Docker context, returning whether it wasn't already.
+fn use_docker_context(context: &str) -> Result<bool, E> {
+    let current = cmd_debug!("docker", "context", "show")
+        .read()
+        .map_err(|e| E::DockerContext {
+            context: context.to_owned(),
+            source: e,
+        })?;
+    if current.trim() == context {
+        debug!("Docker context is already {context}.");
+        return Ok(false);
+    }
+    cmd!("docker", "context", "use", context)
+        .run_with(Expression::stdout_to_stderr)
+        .map_err(|e| E::DockerContext {
+            context: context.to_owned(),
+            source: e,
+        })?;
+    Ok(true)
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum ColimaError {
+    /// Failed to list Colima VMs, is Colima installed?
+    List {
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to parse the output of `colima list --json`.
+    ParseList {
+        /// Source error.
+        source: serde_json::Error,
+    },
+    /// Failed to stop Colima profile '{profile}'.
+    Stop {
+        /// Colima profile.
+        profile: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to start Colima profile '{profile}'.
+    Start {
+        /// Colima profile.
+        profile: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to switch to Docker context '{context}'.
+    DockerContext {
+        /// Docker context.
+        context: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::action;
+    use super::parse_list;
+    use super::Action;
+    use super::ColimaConfig;
+    use super::Instance;
+    use super::GIB;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    /// A running VM with 2 CPUs, 4 GiB of memory, and a 60 GiB disk.
+    fn running() -> Instance {
+        Instance {
+            name: "default".to_owned(),
+            status: "Running".to_owned(),
+            cpus: 2,
+            memory: 4 * GIB,
+            disk: 60 * GIB,
+            runtime: "docker".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_parse_list() -> Result<()> {
+        let output = r#"{"name":"default","status":"Running","arch":"aarch64","cpus":2,"memory":4294967296,"disk":64424509440,"runtime":"docker","address":""}
+{"name":"work","status":"Stopped","arch":"aarch64","cpus":4,"memory":8589934592,"disk":107374182400}
+"#;
+        let instances = parse_list(output)?;
+        ensure_eq!(Some(&running()), instances.first());
+        let work = instances
+            .get(1)
+            .ok_or_else(|| eyre!("Expected two instances."))?;
+        ensure_eq!("Stopped", work.status);
+        ensure_eq!("", work.runtime);
+        ensure_eq!(0, parse_list("")?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_action() -> Result<()> {
+        let mut config = ColimaConfig {
+            cpus: Some(2),
+            memory: Some(4),
+            ..ColimaConfig::default()
+        };
+        ensure_eq!(Action::Create, action(&config, None));
+        ensure_eq!(Action::None, action(&config, Some(&running())));
+        let stopped = Instance {
+            status: "Stopped".to_owned(),
+            ..running()
+        };
+        ensure_eq!(Action::Start, action(&config, Some(&stopped)));
+
+        config.memory = Some(8);
+        config.runtime = Some("containerd".to_owned());
+        ensure_eq!(
+            Action::Restart(vec![
+                "memory from 4 to 8".to_owned(),
+                "runtime from docker to containerd".to_owned()
+            ]),
+            action(&config, Some(&running()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_args() -> Result<()> {
+        let config = ColimaConfig {
+            profile: Some("work".to_owned()),
+            cpus: Some(4),
+            disk: Some(100),
+            mounts: vec!["/Users/me/code:w".to_owned()],
+            ..ColimaConfig::default()
+        };
+        ensure_eq!(
+            "start --profile work --cpu 4 --disk 100 --mount /Users/me/code:w",
+            config.start_args().join(" ")
+        );
+        ensure_eq!("colima-work", config.docker_context_name());
+        ensure_eq!("colima", ColimaConfig::default().docker_context_name());
+        Ok(())
+    }
+}
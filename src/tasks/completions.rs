@@ -1,14 +1,399 @@
-//! Generates up CLI completions.
+/*!
+Generates up CLI completions.
+
+The scripts clap generates can only complete fixed values, so for bash, zsh, and fish the script is
+edited to complete the arguments that take task names, tags, or defaults domains (found by their
+value names) with the output of `up completions --values <kind>`, which is run each time the
+argument is completed.
+*/
+use crate::config::UpConfig;
+use crate::opts::CompletionValues;
 use crate::opts::CompletionsOptions;
 use crate::opts::Opts;
+use crate::tasks;
+use crate::tasks::defaults;
+use crate::tasks::TasksDir;
+use clap::Command;
 use clap::CommandFactory;
+use clap::ValueEnum;
+use clap_complete::Shell;
+use color_eyre::eyre::bail;
+use color_eyre::eyre::Result;
+use itertools::Itertools;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Value names of the arguments that are completed with `up completions --values`.
+const DYNAMIC_VALUE_NAMES: [(&str, CompletionValues); 3] = [
+    ("TASK", CompletionValues::Tasks),
+    ("TAG", CompletionValues::Tags),
+    ("DOMAIN", CompletionValues::Domains),
+];
 
 /// Run the `up completions` command.
-pub(crate) fn run(cmd_opts: &CompletionsOptions) {
-    clap_complete::generate(
-        cmd_opts.shell,
-        &mut Opts::command(),
-        "up",
-        &mut std::io::stdout(),
-    );
+pub(crate) fn run(cmd_opts: &CompletionsOptions) -> Result<()> {
+    let Some(shell) = cmd_opts.shell else {
+        bail!("Pass the shell to generate completions for.");
+    };
+    let mut command = Opts::command();
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut command, "up", &mut script);
+    let script = String::from_utf8(script)?;
+    let dynamic_args = dynamic_args(&command, &[]);
+    let script = match shell {
+        Shell::Bash => bash_script(&script, &dynamic_args),
+        Shell::Zsh => zsh_script(&script, &dynamic_args),
+        Shell::Fish => fish_script(&script, &dynamic_args),
+        _ => script,
+    };
+    print!("{script}");
+    Ok(())
+}
+
+/// Run `up completions --values`, printing the possible `values` one per line.
+pub(crate) fn print_values(opts: Opts, values: CompletionValues) -> Result<()> {
+    if values == CompletionValues::Domains {
+        defaults::domains(false)?;
+        return Ok(());
+    }
+    let config = UpConfig::from(opts)?;
+    let tasks = tasks::load_tasks(
+        &tasks::tasks_dir(&config, TasksDir::Tasks)?,
+        &config.cache_dir,
+        config.config_yaml.task_extensions.as_deref(),
+    )?;
+    let values: BTreeSet<&str> = if values == CompletionValues::Tasks {
+        tasks.keys().map(String::as_str).collect()
+    } else {
+        tasks
+            .values()
+            .flat_map(|task| task.config.tags.iter().flatten())
+            .map(String::as_str)
+            .collect()
+    };
+    for value in values {
+        println!("{value}");
+    }
+    Ok(())
+}
+
+/// An argument whose values are completed with `up completions --values`.
+#[derive(Debug)]
+struct DynamicArg {
+    /// Names of the subcommands the argument belongs to, e.g. `["defaults", "read"]`.
+    path: Vec<String>,
+    /// Long flag, e.g. `exclude-tasks`.
+    long: Option<String>,
+    /// Short flag, e.g. `x`.
+    short: Option<char>,
+    /// What to complete the argument with.
+    values: CompletionValues,
+}
+
+impl DynamicArg {
+    /// The command that prints the argument's possible values.
+    fn values_cmd(&self) -> String {
+        let kind = self
+            .values
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default();
+        format!("up completions --values {kind} 2>/dev/null")
+    }
+
+    /// Whether the argument is positional.
+    fn is_positional(&self) -> bool {
+        self.long.is_none() && self.short.is_none()
+    }
+}
+
+/// The arguments of `command` and its subcommands (under the subcommand `path`) that are completed
+/// with `up completions --values`.
+fn dynamic_args(command: &Command, path: &[String]) -> Vec<DynamicArg> {
+    let mut args: Vec<DynamicArg> = command
+        .get_arguments()
+        .filter_map(|arg| {
+            let value_name = arg.get_value_names()?.first()?.as_str();
+            let (_, values) = DYNAMIC_VALUE_NAMES
+                .iter()
+                .find(|(name, _)| *name == value_name)?;
+            Some(DynamicArg {
+                path: path.to_vec(),
+                long: arg.get_long().map(ToOwned::to_owned),
+                short: arg.get_short(),
+                values: *values,
+            })
+        })
+        .collect();
+    for subcommand in command.get_subcommands() {
+        let mut subcommand_path = path.to_vec();
+        subcommand_path.push(subcommand.get_name().to_owned());
+        args.extend(dynamic_args(subcommand, &subcommand_path));
+    }
+    args
+}
+
+/**
+Complete the `dynamic_args` in a bash `script`.
+
+Each subcommand has a `case` branch named after its path, e.g. `up__subcmd__defaults__subcmd__read)`,
+which completes flag values in a `case "${prev}"` statement, and otherwise the subcommand's flags.
+Positional values are added to those flags.
+*/
+fn bash_script(script: &str, dynamic_args: &[DynamicArg]) -> String {
+    let block_name = |path: &[String]| {
+        path.iter().fold("up".to_owned(), |mut name, subcommand| {
+            write!(name, "__subcmd__{}", subcommand.replace('-', "__subcmd__"))
+                .expect("Writing to a String can't fail.");
+            name
+        })
+    };
+    let mut out = String::new();
+    let mut block_args: Vec<&DynamicArg> = Vec::new();
+    let mut flag_arg: Option<&DynamicArg> = None;
+    for line in script.lines() {
+        let trimmed = line.trim();
+        let mut line = line.to_owned();
+        if line.starts_with("        up") && !line.starts_with("         ") {
+            let name = trimmed.trim_end_matches(')');
+            block_args = dynamic_args
+                .iter()
+                .filter(|arg| block_name(&arg.path) == name)
+                .collect();
+        } else if let Some(arg) = flag_arg.take() {
+            if trimmed == r#"COMPREPLY=($(compgen -f "${cur}"))"# {
+                line = format!(
+                    r#"                    COMPREPLY=($(compgen -W "$({})" -- "${{cur}}"))"#,
+                    arg.values_cmd()
+                );
+            }
+        } else if let Some(arg) = block_args.iter().find(|arg| {
+            arg.long
+                .as_ref()
+                .is_some_and(|long| trimmed == format!("--{long})"))
+                || arg
+                    .short
+                    .is_some_and(|short| trimmed == format!("-{short})"))
+        }) {
+            flag_arg = Some(arg);
+        } else if line == r#"            COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )"# {
+            if let Some(arg) = block_args.iter().find(|arg| arg.is_positional()) {
+                line = format!(
+                    r#"            COMPREPLY=( $(compgen -W "${{opts}} $({})" -- "${{cur}}") )"#,
+                    arg.values_cmd()
+                );
+            }
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/**
+Complete the `dynamic_args` in a zsh `script`.
+
+Flags are completed with `_default` after their value name (`:TASK:_default'`), and positional
+arguments with `_default` after their name (`':task -- <help>:_default'`), so those are replaced
+with a function that completes the output of `up completions --values`.
+*/
+fn zsh_script(script: &str, dynamic_args: &[DynamicArg]) -> String {
+    let mut script = script.to_owned();
+    for (value_name, values) in DYNAMIC_VALUE_NAMES {
+        let Some(arg) = dynamic_args.iter().find(|arg| arg.values == values) else {
+            continue;
+        };
+        let kind = arg.values_cmd();
+        let kind = kind
+            .split_whitespace()
+            .nth(3)
+            .expect("values_cmd has the kind as its fourth word.");
+        script = script.replace(
+            &format!(":{value_name}:_default'"),
+            &format!(":{value_name}:_up_values {kind}'"),
+        );
+        script = script
+            .lines()
+            .map(|line| {
+                let name = value_name.to_lowercase();
+                let is_positional = [format!("':{name} -- "), format!("'::{name} -- ")]
+                    .iter()
+                    .any(|prefix| line.starts_with(prefix.as_str()));
+                if is_positional && line.ends_with(":_default' \\") {
+                    line.replace(":_default' \\", &format!(":_up_values {kind}' \\"))
+                } else {
+                    line.to_owned()
+                }
+            })
+            .join("\n")
+            + "\n";
+    }
+    let values_fn = "\
+# Complete the values printed by `up completions --values <kind>`.
+_up_values() {
+    local -a values
+    values=(${(f)\"$(up completions --values $1 2>/dev/null)\"})
+    compadd -a values
+}
+
+";
+    // The function has to be defined before the script calls `_up`.
+    match script.find("if [ \"$funcstack[1]\" = \"_up\" ]; then") {
+        Some(index) => script.insert_str(index, values_fn),
+        None => script.push_str(values_fn),
+    }
+    script
+}
+
+/**
+Complete the `dynamic_args` in a fish `script`.
+
+Flags are given the output of `up completions --values` as their values, and positional arguments
+get a new line completing it for their subcommand.
+*/
+fn fish_script(script: &str, dynamic_args: &[DynamicArg]) -> String {
+    let condition = |path: &[String]| match path {
+        [] => "__fish_up_needs_command".to_owned(),
+        [subcommand] => format!("__fish_up_using_subcommand {subcommand}"),
+        [subcommand, rest @ ..] => format!(
+            "__fish_up_using_subcommand {subcommand}; and __fish_seen_subcommand_from {}",
+            rest.join(" ")
+        ),
+    };
+    let mut out = String::new();
+    for line in script.lines() {
+        out.push_str(line);
+        let arg = dynamic_args.iter().find(|arg| {
+            line.starts_with(&format!("complete -c up -n \"{}\" ", condition(&arg.path)))
+                && arg.long.as_ref().is_some_and(|long| {
+                    line.contains(&format!(" -l {long} ")) || line.ends_with(&format!(" -l {long}"))
+                })
+        });
+        if let Some(arg) = arg {
+            write!(out, " -f -a \"({})\"", arg.values_cmd())
+                .expect("Writing to a String can't fail.");
+        }
+        out.push('\n');
+    }
+    for arg in dynamic_args.iter().filter(|arg| arg.is_positional()) {
+        writeln!(
+            out,
+            "complete -c up -n \"{}\" -f -a \"({})\"",
+            condition(&arg.path),
+            arg.values_cmd()
+        )
+        .expect("Writing to a String can't fail.");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bash_script;
+    use super::dynamic_args;
+    use super::fish_script;
+    use super::zsh_script;
+    use crate::opts::Opts;
+    use clap::CommandFactory;
+    use clap_complete::Shell;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    /// The generated script for `shell`, with dynamic values added by `edit`.
+    fn script(shell: Shell, edit: fn(&str, &[super::DynamicArg]) -> String) -> Result<String> {
+        let mut command = Opts::command();
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut command, "up", &mut script);
+        Ok(edit(
+            &String::from_utf8(script)?,
+            &dynamic_args(&command, &[]),
+        ))
+    }
+
+    #[test]
+    fn test_dynamic_args() -> Result<()> {
+        let args = dynamic_args(&Opts::command(), &[]);
+        let describe = |arg: &super::DynamicArg| {
+            format!(
+                "{} {}",
+                arg.path.join(" "),
+                arg.long.as_deref().unwrap_or("<positional>")
+            )
+        };
+        for expected in [
+            "run exclude-tasks",
+            "run tags",
+            "plan until",
+            "explain <positional>",
+            "defaults read <positional>",
+        ] {
+            ensure!(
+                args.iter().any(|arg| describe(arg) == expected),
+                "Expected a dynamic arg '{expected}' in {args:?}."
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bash_script() -> Result<()> {
+        let script = script(Shell::Bash, bash_script)?;
+        ensure_eq!(
+            true,
+            script.contains(
+                "                --exclude-tasks)
+                    COMPREPLY=($(compgen -W \"$(up completions --values tasks 2>/dev/null)\" -- \
+                 \"${cur}\"))"
+            )
+        );
+        ensure_eq!(
+            true,
+            script.contains(
+                "                --tags)
+                    COMPREPLY=($(compgen -W \"$(up completions --values tags 2>/dev/null)\" -- \
+                 \"${cur}\"))"
+            )
+        );
+        ensure_eq!(
+            true,
+            script.contains(
+                "            COMPREPLY=( $(compgen -W \"${opts} $(up completions --values domains \
+                 2>/dev/null)\" -- \"${cur}\") )"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_zsh_script() -> Result<()> {
+        let script = script(Shell::Zsh, zsh_script)?;
+        ensure_eq!(true, script.contains(":TASK:_up_values tasks' \\"));
+        ensure_eq!(true, script.contains(":TAG:_up_values tags' \\"));
+        ensure_eq!(true, script.contains(":_up_values domains' \\"));
+        ensure_eq!(
+            true,
+            script.find("_up_values() {") < script.find("if [ \"$funcstack[1]\" = \"_up\" ]; then")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fish_script() -> Result<()> {
+        let script = script(Shell::Fish, fish_script)?;
+        ensure_eq!(
+            true,
+            script.lines().any(|line| line.contains("-l exclude-tasks")
+                && line.ends_with("-f -a \"(up completions --values tasks 2>/dev/null)\""))
+        );
+        ensure_eq!(
+            true,
+            script.contains(
+                "complete -c up -n \"__fish_up_using_subcommand defaults; and \
+                 __fish_seen_subcommand_from read\" -f -a \"(up completions --values domains \
+                 2>/dev/null)\""
+            )
+        );
+        Ok(())
+    }
 }
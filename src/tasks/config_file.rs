@@ -0,0 +1,546 @@
+/*!
+The `json` and `toml` library tasks, which merge values into arbitrary JSON or TOML config files.
+
+The declared `values` are deep-merged into the file at `path`: objects (tables) are merged key by
+key, so keys you don't declare are left alone, and other values replace the existing ones. As with
+the defaults library, arrays containing `...` have the `...` replaced by the existing values.
+
+```yaml
+run_lib: toml
+data:
+  path: ~/.config/starship.toml
+  values:
+    add_newline: false
+    character:
+      success_symbol: "[➜](bold green)"
+```
+
+TOML files keep their comments and formatting, JSON files keep their key order and indentation
+(but JSON files with comments or trailing commas can't be updated). Files are backed up to the
+backup directory before they're changed.
+*/
+use self::ConfigFileError as E;
+use crate::tasks::resolve_config_path;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::vscode::resolve_json_strings;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TaskError;
+use crate::utils::ellipsis::replace_ellipsis_array;
+use crate::utils::ellipsis::replace_ellipsis_dict;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use serde::Serialize as _;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fs;
+use std::io::ErrorKind;
+use thiserror::Error;
+use toml_edit::DocumentMut;
+use toml_edit::Item;
+use toml_edit::TableLike;
+use tracing::debug;
+use tracing::info;
+
+/// Indentation used for new JSON files, or existing ones that aren't indented.
+const DEFAULT_JSON_INDENT: &str = "  ";
+
+/// The format of the config file, which is the `run_lib` the task used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConfigFormat {
+    /// A JSON file (`run_lib: json`).
+    Json,
+    /// A TOML file (`run_lib: toml`).
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Name of the run library, also used as the backup subdirectory.
+    const fn lib_name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+        }
+    }
+}
+
+/// Configuration for a `json` or `toml` run library task.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFileConfig {
+    /// Path of the config file to update, created if it doesn't exist.
+    pub path: String,
+    /// Values to merge into the file.
+    pub values: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ResolveEnv for ConfigFileConfig {
+    fn resolve_env<F>(&mut self, env_fn: F) -> Result<(), TaskError>
+    where
+        F: Fn(&str) -> Result<String, TaskError>,
+    {
+        self.path = resolve_config_path(&self.path, &env_fn)?.into_string();
+        for value in self.values.values_mut() {
+            resolve_json_strings(value, &env_fn)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a `json` or `toml` run library task.
+pub(crate) fn run(
+    format: ConfigFormat,
+    config: ConfigFileConfig,
+    backup_dir: &Utf8Path,
+) -> Result<TaskStatus> {
+    let path = Utf8PathBuf::from(config.path);
+    let backup_dir = backup_dir.join(format.lib_name());
+    if merge_file(format, &path, config.values, &backup_dir)? {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    } else {
+        Ok(TaskStatus::Skipped)
+    }
+}
+
+/// Merge `values` into the config file at `path`, returning whether it changed.
+/// Existing files are backed up to `backup_dir` before they're changed.
+pub(crate) fn merge_file(
+    format: ConfigFormat,
+    path: &Utf8Path,
+    values: serde_json::Map<String, serde_json::Value>,
+    backup_dir: &Utf8Path,
+) -> Result<bool, E> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(E::Read {
+                path: path.to_owned(),
+                source: e,
+            })
+        }
+    };
+    let existing = contents.as_deref().unwrap_or_default();
+
+    let Some(new_contents) = (match format {
+        ConfigFormat::Json => merge_json_file(path, existing, values)?,
+        ConfigFormat::Toml => merge_toml_file(path, existing, values)?,
+    }) else {
+        debug!("Values in {path} already up to date.");
+        return Ok(false);
+    };
+
+    if contents.is_some() {
+        let backup_path = backup_dir.join(path.file_name().unwrap_or(format.lib_name()));
+        fs::create_dir_all(backup_dir)
+            .and_then(|()| fs::copy(path, &backup_path))
+            .map_err(|e| E::Backup {
+                path: backup_path.clone(),
+                source: e,
+            })?;
+    } else if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| E::Write {
+            path: path.to_owned(),
+            source: e,
+        })?;
+    }
+
+    fs::write(path, new_contents).map_err(|e| E::Write {
+        path: path.to_owned(),
+        source: e,
+    })?;
+    Ok(true)
+}
+
+/// Merge `values` into the JSON file `contents`, returning the new contents if they changed.
+fn merge_json_file(
+    path: &Utf8Path,
+    contents: &str,
+    values: serde_json::Map<String, serde_json::Value>,
+) -> Result<Option<String>, E> {
+    let mut current: serde_json::Map<String, serde_json::Value> = if contents.trim().is_empty() {
+        serde_json::Map::new()
+    } else {
+        serde_json::from_str(contents).map_err(|e| E::ParseJson {
+            path: path.to_owned(),
+            source: e,
+        })?
+    };
+    if merge_json(&mut current, values, "") == 0 {
+        return Ok(None);
+    }
+
+    let indent = json_indent(contents);
+    let mut bytes = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    current
+        .serialize(&mut serde_json::Serializer::with_formatter(
+            &mut bytes, formatter,
+        ))
+        .map_err(|e| E::SerializeJson {
+            path: path.to_owned(),
+            source: e,
+        })?;
+    bytes.push(b'\n');
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Deep-merge `values` into `current`, returning the number of values changed.
+/// `key_path` is the dotted path of `current`, used for logging.
+fn merge_json(
+    current: &mut serde_json::Map<String, serde_json::Value>,
+    values: serde_json::Map<String, serde_json::Value>,
+    key_path: &str,
+) -> usize {
+    let mut changed = 0;
+    for (key, mut new_value) in values {
+        let full_key = join_key(key_path, &key);
+        if let (Some(serde_json::Value::Object(existing)), serde_json::Value::Object(new_object)) =
+            (current.get_mut(&key), &mut new_value)
+        {
+            new_object.shift_remove("...");
+            changed += merge_json(existing, std::mem::take(new_object), &full_key);
+            continue;
+        }
+        let old_value = current.get(&key);
+        replace_ellipsis_array(&mut new_value, old_value);
+        replace_ellipsis_dict(&mut new_value, old_value);
+        if old_value == Some(&new_value) {
+            continue;
+        }
+        info!("Changing {full_key}: {old_value:?} -> {new_value:?}");
+        current.insert(key, new_value);
+        changed += 1;
+    }
+    changed
+}
+
+/// The indentation of the first indented line in `contents`.
+fn json_indent(contents: &str) -> String {
+    contents
+        .lines()
+        .find_map(|line| {
+            let indent = line.strip_suffix(line.trim_start())?;
+            (!indent.is_empty() && !line.trim().is_empty()).then_some(indent)
+        })
+        .unwrap_or(DEFAULT_JSON_INDENT)
+        .to_owned()
+}
+
+/// Merge `values` into the TOML file `contents`, returning the new contents if they changed.
+fn merge_toml_file(
+    path: &Utf8Path,
+    contents: &str,
+    values: serde_json::Map<String, serde_json::Value>,
+) -> Result<Option<String>, E> {
+    let mut document: DocumentMut = contents.parse().map_err(|e| E::ParseToml {
+        path: path.to_owned(),
+        source: e,
+    })?;
+    let changed =
+        merge_toml(document.as_table_mut(), false, values, "").map_err(|key| E::TomlNull {
+            path: path.to_owned(),
+            key,
+        })?;
+    Ok((changed != 0).then(|| document.to_string()))
+}
+
+/**
+Deep-merge `values` into the TOML `table`, returning the number of values changed.
+`inline` is whether `table` is an inline table, and `key_path` its dotted path.
+
+Errors with the key of any null value, as TOML has no null.
+*/
+fn merge_toml(
+    table: &mut dyn TableLike,
+    inline: bool,
+    values: serde_json::Map<String, serde_json::Value>,
+    key_path: &str,
+) -> Result<usize, String> {
+    let mut changed = 0;
+    for (key, mut new_value) in values {
+        let full_key = join_key(key_path, &key);
+        if let serde_json::Value::Object(new_table) = &mut new_value {
+            if let Some(existing) = table.get_mut(&key) {
+                let existing_inline = existing.is_inline_table();
+                if let Some(existing) = existing.as_table_like_mut() {
+                    new_table.shift_remove("...");
+                    changed += merge_toml(
+                        existing,
+                        existing_inline,
+                        std::mem::take(new_table),
+                        &full_key,
+                    )?;
+                    continue;
+                }
+            }
+        }
+
+        let old_value = table.get(&key).map(toml_item_to_json);
+        replace_ellipsis_array(&mut new_value, old_value.as_ref());
+        replace_ellipsis_dict(&mut new_value, old_value.as_ref());
+        if old_value.as_ref() == Some(&new_value) {
+            continue;
+        }
+        info!("Changing {full_key}: {old_value:?} -> {new_value:?}");
+        let mut item = json_to_toml_item(new_value, &full_key)?;
+        if inline {
+            item = Item::Value(item.into_value().map_err(|_| full_key.clone())?);
+        }
+        // Replace existing values in place to keep any comments and whitespace around them.
+        if let Some(old_item) = table.get_mut(&key) {
+            if let (Some(old_value), Some(new_value)) = (old_item.as_value(), item.as_value_mut()) {
+                *new_value.decor_mut() = old_value.decor().clone();
+            }
+            *old_item = item;
+        } else {
+            table.insert(&key, item);
+        }
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+/// Convert a JSON value to a TOML item, objects become (non-inline) tables.
+fn json_to_toml_item(value: serde_json::Value, key: &str) -> Result<Item, String> {
+    let serde_json::Value::Object(object) = value else {
+        return Ok(Item::Value(json_to_toml_value(value, key)?));
+    };
+    let mut table = toml_edit::Table::new();
+    table.set_implicit(true);
+    for (k, v) in object {
+        let full_key = join_key(key, &k);
+        table.insert(&k, json_to_toml_item(v, &full_key)?);
+    }
+    Ok(Item::Table(table))
+}
+
+/// Convert a JSON value to a TOML value, objects become inline tables.
+fn json_to_toml_value(value: serde_json::Value, key: &str) -> Result<toml_edit::Value, String> {
+    Ok(match value {
+        serde_json::Value::Null => return Err(key.to_owned()),
+        serde_json::Value::Bool(b) => b.into(),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64().unwrap_or_default().into(),
+        },
+        serde_json::Value::String(s) => s.into(),
+        serde_json::Value::Array(array) => array
+            .into_iter()
+            .map(|v| json_to_toml_value(v, key))
+            .collect::<Result<toml_edit::Array, _>>()?
+            .into(),
+        serde_json::Value::Object(object) => object
+            .into_iter()
+            .map(|(k, v)| {
+                let full_key = join_key(key, &k);
+                Ok((k, json_to_toml_value(v, &full_key)?))
+            })
+            .collect::<Result<toml_edit::InlineTable, String>>()?
+            .into(),
+    })
+}
+
+/// Convert a TOML item to JSON, so it can be compared with the new value.
+fn toml_item_to_json(item: &Item) -> serde_json::Value {
+    match item {
+        Item::None => serde_json::Value::Null,
+        Item::Value(value) => toml_value_to_json(value),
+        Item::Table(table) => toml_table_to_json(table),
+        Item::ArrayOfTables(array) => array.iter().map(toml_table_to_json).collect(),
+    }
+}
+
+/// Convert a TOML table to a JSON object.
+fn toml_table_to_json(table: &toml_edit::Table) -> serde_json::Value {
+    table
+        .iter()
+        .map(|(k, v)| (k.to_owned(), toml_item_to_json(v)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Convert a TOML value to JSON, datetimes become strings.
+fn toml_value_to_json(value: &toml_edit::Value) -> serde_json::Value {
+    match value {
+        toml_edit::Value::String(s) => s.value().clone().into(),
+        toml_edit::Value::Integer(i) => (*i.value()).into(),
+        toml_edit::Value::Float(f) => (*f.value()).into(),
+        toml_edit::Value::Boolean(b) => (*b.value()).into(),
+        toml_edit::Value::Datetime(d) => d.value().to_string().into(),
+        toml_edit::Value::Array(array) => array.iter().map(toml_value_to_json).collect(),
+        toml_edit::Value::InlineTable(table) => table
+            .iter()
+            .map(|(k, v)| (k.to_owned(), toml_value_to_json(v)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+    }
+}
+
+/// Join a dotted key path and a key, for logging.
+fn join_key(key_path: &str, key: &str) -> String {
+    if key_path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{key_path}.{key}")
+    }
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum ConfigFileError {
+    /// Failed to read config file `{path}`.
+    Read {
+        /// Config file path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /**
+    Failed to parse JSON file `{path}`.
+    up can only update files that are valid JSON objects, so remove any comments or trailing
+    commas.
+    */
+    ParseJson {
+        /// Config file path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_json::Error,
+    },
+    /// Failed to parse TOML file `{path}`.
+    ParseToml {
+        /// Config file path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: toml_edit::TomlError,
+    },
+    /// Can't set `{key}` in TOML file `{path}` to null, as TOML has no null value.
+    TomlNull {
+        /// Config file path.
+        path: Utf8PathBuf,
+        /// Dotted key of the null value.
+        key: String,
+    },
+    /// Failed to serialize JSON for `{path}`.
+    SerializeJson {
+        /// Config file path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_json::Error,
+    },
+    /// Failed to back up config file to `{path}`.
+    Backup {
+        /// Backup path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to write config file `{path}`.
+    Write {
+        /// Config file path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConfigFormat;
+    use color_eyre::Result;
+    use std::fs;
+    use testutils::ensure_eq;
+
+    /// TOML values are deep-merged, keeping comments, formatting, and undeclared keys.
+    #[test]
+    fn test_merge_toml() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let path = temp_dir.join("starship.toml");
+        let backup_dir = temp_dir.join("backup");
+        fs::write(
+            &path,
+            "# Prompt config.\nadd_newline = true # Blank line between prompts.\nformat = \
+             \"$all\"\n\n[character]\nerror_symbol = \"[x](red)\"\nsuccess_symbol = \
+             \"[>](green)\"\n\n[git_branch]\nstyle = { fg = \"purple\", bold = true }\n",
+        )?;
+
+        let values: serde_json::Map<_, _> = serde_yaml::from_str(
+            "{add_newline: false, character: {success_symbol: '[➜](bold green)'}, git_branch: \
+             {style: {bold: false}}, directory: {truncation_length: 3, substitutions: {Code: C}}}",
+        )?;
+        ensure_eq!(
+            true,
+            super::merge_file(ConfigFormat::Toml, &path, values.clone(), &backup_dir)?
+        );
+        ensure_eq!(
+            "# Prompt config.\nadd_newline = false # Blank line between prompts.\nformat = \
+             \"$all\"\n\n[character]\nerror_symbol = \"[x](red)\"\nsuccess_symbol = \"[➜](bold \
+             green)\"\n\n[git_branch]\nstyle = { fg = \"purple\", bold = false \
+             }\n\n[directory]\ntruncation_length = 3\n\n[directory.substitutions]\nCode = \"C\"\n",
+            fs::read_to_string(&path)?
+        );
+        ensure_eq!(true, backup_dir.join("starship.toml").exists());
+
+        // Running again changes nothing.
+        ensure_eq!(
+            false,
+            super::merge_file(ConfigFormat::Toml, &path, values, &backup_dir)?
+        );
+
+        let values: serde_json::Map<_, _> = serde_yaml::from_str("{character: {bad: null}}")?;
+        ensure_eq!(
+            true,
+            super::merge_file(ConfigFormat::Toml, &path, values, &backup_dir).is_err()
+        );
+        Ok(())
+    }
+
+    /// JSON values are deep-merged, keeping key order and indentation.
+    #[test]
+    fn test_merge_json() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let path = temp_dir.join("config.json");
+        let backup_dir = temp_dir.join("backup");
+
+        // Missing files are created.
+        let values: serde_json::Map<_, _> = serde_yaml::from_str("{b: 1, nested: {list: [x, y]}}")?;
+        ensure_eq!(
+            true,
+            super::merge_file(ConfigFormat::Json, &path, values, &backup_dir)?
+        );
+        ensure_eq!(
+            "{\n  \"b\": 1,\n  \"nested\": {\n    \"list\": [\n      \"x\",\n      \"y\"\n    \
+             ]\n  }\n}\n",
+            fs::read_to_string(&path)?
+        );
+        ensure_eq!(false, backup_dir.exists());
+
+        fs::write(
+            &path,
+            "{\n    \"z\": true,\n    \"nested\": {\"keep\": 1, \"list\": [\"x\"]}\n}\n",
+        )?;
+        let values: serde_json::Map<_, _> =
+            serde_yaml::from_str("{nested: {list: [w, ...]}, a: text}")?;
+        ensure_eq!(
+            true,
+            super::merge_file(ConfigFormat::Json, &path, values.clone(), &backup_dir)?
+        );
+        ensure_eq!(
+            serde_json::json!({"z": true, "nested": {"keep": 1, "list": ["w", "x"]}, "a": "text"}),
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&path)?)?
+        );
+        ensure_eq!(
+            true,
+            fs::read_to_string(&path)?.starts_with("{\n    \"z\": true,\n    \"nested\": {\n")
+        );
+        ensure_eq!(true, backup_dir.join("config.json").exists());
+
+        ensure_eq!(
+            false,
+            super::merge_file(ConfigFormat::Json, &path, values, &backup_dir)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+/*!
+The `default_apps` library task, to set the apps macOS opens files and URLs with (like `duti`).
+
+Handlers are app bundle IDs, set for file `extensions`, `url_schemes`, or `content_types` (Uniform
+Type Identifiers), and applied with the `LaunchServices` APIs for all roles (viewer, editor, and
+shell):
+
+```yaml
+run_lib: default_apps
+data:
+  extensions:
+    md: com.microsoft.VSCode
+    json: com.microsoft.VSCode
+  url_schemes:
+    mailto: com.fastmail.mac.Fastmail
+  content_types:
+    public.plain-text: com.microsoft.VSCode
+```
+
+Only handlers that differ from the current ones are set, so the task is skipped if they all
+already match (or if not running on macOS). macOS may ask you to confirm changes to the `http`
+and `https` handlers.
+*/
+use self::DefaultAppsError as E;
+use crate::cmd_debug;
+use crate::exec::UpDuct;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TaskError;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+
+/// Configuration for a `default_apps` run library task.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultAppsConfig {
+    /// Bundle ID of the app to open files with each extension, e.g. `{md: com.microsoft.VSCode}`.
+    #[serde(default)]
+    pub extensions: BTreeMap<String, String>,
+    /// Bundle ID of the app to open URLs with each scheme, e.g. `{mailto: com.apple.mail}`.
+    #[serde(default)]
+    pub url_schemes: BTreeMap<String, String>,
+    /// Bundle ID of the app to open each content type (UTI) with, e.g.
+    /// `{public.plain-text: com.microsoft.VSCode}`.
+    #[serde(default)]
+    pub content_types: BTreeMap<String, String>,
+}
+
+impl ResolveEnv for DefaultAppsConfig {
+    fn resolve_env<F>(&mut self, env_fn: F) -> Result<(), TaskError>
+    where
+        F: Fn(&str) -> Result<String, TaskError>,
+    {
+        for handler in self
+            .extensions
+            .values_mut()
+            .chain(self.url_schemes.values_mut())
+            .chain(self.content_types.values_mut())
+        {
+            *handler = env_fn(handler)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a handler is set for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum HandlerKind {
+    /// A file extension, e.g. `md`.
+    Extension,
+    /// A URL scheme, e.g. `mailto`.
+    UrlScheme,
+    /// A content type (Uniform Type Identifier), e.g. `public.plain-text`.
+    ContentType,
+}
+
+impl fmt::Display for HandlerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Extension => write!(f, "extension"),
+            Self::UrlScheme => write!(f, "URL scheme"),
+            Self::ContentType => write!(f, "content type"),
+        }
+    }
+}
+
+/// A handler to set, passed to [`SET_HANDLERS_SCRIPT`].
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Handler<'a> {
+    /// What the handler is set for.
+    kind: HandlerKind,
+    /// The extension, URL scheme, or content type.
+    name: &'a str,
+    /// Bundle ID of the app to open it with.
+    bundle_id: &'a str,
+}
+
+/// A handler that [`SET_HANDLERS_SCRIPT`] changed.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct ChangedHandler {
+    /// What the handler was set for.
+    kind: HandlerKind,
+    /// The extension, URL scheme, or content type.
+    name: String,
+    /// Bundle ID of the previous handler, if there was one.
+    previous: Option<String>,
+    /// Bundle ID of the new handler.
+    bundle_id: String,
+}
+
+/**
+JavaScript for Automation script that sets the handlers in its (JSON) argument, skipping those
+that are already set, and prints the ones it changed as JSON.
+
+Bundle IDs are compared case-insensitively, as `LaunchServices` returns them lowercased.
+*/
+const SET_HANDLERS_SCRIPT: &str = r"
+ObjC.import('AppKit');
+ObjC.import('CoreServices');
+ObjC.import('UniformTypeIdentifiers');
+
+function unwrapRef(ref) {
+  return ref ? ObjC.castRefToObject(ref).js : null;
+}
+
+function run(argv) {
+  const changed = [];
+  for (const handler of JSON.parse(argv[0])) {
+    const description = `${handler.kind.replace('_', ' ')} '${handler.name}'`;
+    if ($.NSWorkspace.sharedWorkspace.URLForApplicationWithBundleIdentifier(handler.bundle_id).isNil()) {
+      throw new Error(`Can't set the handler for ${description}, app ${handler.bundle_id} isn't installed.`);
+    }
+    let uti = handler.name;
+    if (handler.kind === 'extension') {
+      const type = $.UTType.typeWithFilenameExtension(handler.name);
+      if (type.isNil()) {
+        throw new Error(`No content type found for ${description}.`);
+      }
+      uti = type.identifier.js;
+    }
+    const previous = handler.kind === 'url_scheme'
+      ? unwrapRef($.LSCopyDefaultHandlerForURLScheme($(handler.name)))
+      : unwrapRef($.LSCopyDefaultRoleHandlerForContentType($(uti), $.kLSRolesAll));
+    if (previous && previous.toLowerCase() === handler.bundle_id.toLowerCase()) {
+      continue;
+    }
+    const status = handler.kind === 'url_scheme'
+      ? $.LSSetDefaultHandlerForURLScheme($(handler.name), $(handler.bundle_id))
+      : $.LSSetDefaultRoleHandlerForContentType($(uti), $.kLSRolesAll, $(handler.bundle_id));
+    if (status !== 0) {
+      throw new Error(`Setting the handler for ${description} to ${handler.bundle_id} failed with OSStatus ${status}.`);
+    }
+    changed.push({ kind: handler.kind, name: handler.name, previous, bundle_id: handler.bundle_id });
+  }
+  return JSON.stringify(changed);
+}
+";
+
+/// Run a `default_apps` run library task.
+pub(crate) fn run(config: &DefaultAppsConfig) -> Result<TaskStatus> {
+    if !cfg!(target_os = "macos") {
+        debug!("Default apps: skipping as not on macOS.");
+        return Ok(TaskStatus::Skipped);
+    }
+    let handlers = handlers(config);
+    if handlers.is_empty() {
+        debug!("Default apps: no handlers to set.");
+        return Ok(TaskStatus::Skipped);
+    }
+
+    let output = cmd_debug!(
+        "osascript",
+        "-l",
+        "JavaScript",
+        "-e",
+        SET_HANDLERS_SCRIPT,
+        serde_json::to_string(&handlers)?,
+    )
+    .stderr_capture()
+    .unchecked()
+    .run_with(Expression::stdout_capture)
+    .map_err(|e| E::Osascript { source: e })?;
+    if !output.status.success() {
+        return Err(E::SetHandlers {
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        }
+        .into());
+    }
+    let changed: Vec<ChangedHandler> =
+        serde_json::from_slice(&output.stdout).map_err(|e| E::ParseOutput { source: e })?;
+
+    for ChangedHandler {
+        kind,
+        name,
+        previous,
+        bundle_id,
+    } in &changed
+    {
+        let previous = previous.as_deref().unwrap_or("nothing");
+        info!("Set the default app for {kind} '{name}' to {bundle_id} (was {previous}).");
+    }
+    if changed.is_empty() {
+        debug!(
+            "All {count} default apps already set.",
+            count = handlers.len()
+        );
+        Ok(TaskStatus::Skipped)
+    } else {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    }
+}
+
+/// The handlers to set from the config, with any leading `.` removed from extensions.
+fn handlers(config: &DefaultAppsConfig) -> Vec<Handler<'_>> {
+    kind_handlers(HandlerKind::Extension, &config.extensions)
+        .chain(kind_handlers(HandlerKind::UrlScheme, &config.url_schemes))
+        .chain(kind_handlers(
+            HandlerKind::ContentType,
+            &config.content_types,
+        ))
+        .collect()
+}
+
+/// The handlers of one `kind` from the config.
+fn kind_handlers(
+    kind: HandlerKind,
+    map: &BTreeMap<String, String>,
+) -> impl Iterator<Item = Handler<'_>> {
+    map.iter().map(move |(name, bundle_id)| Handler {
+        kind,
+        name: if kind == HandlerKind::Extension {
+            name.trim_start_matches('.')
+        } else {
+            name
+        },
+        bundle_id,
+    })
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum DefaultAppsError {
+    /// Failed to run `osascript` to set the default apps.
+    Osascript {
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to set the default apps: {stderr}
+    SetHandlers {
+        /// Stderr of the script, which includes the error it threw.
+        stderr: String,
+    },
+    /// Failed to parse the default apps that were changed.
+    ParseOutput {
+        /// Source error.
+        source: serde_json::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::handlers;
+    use super::ChangedHandler;
+    use super::DefaultAppsConfig;
+    use super::Handler;
+    use super::HandlerKind;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_handlers() -> Result<()> {
+        let config: DefaultAppsConfig = serde_yaml::from_str(
+            "
+extensions:
+  .md: com.microsoft.VSCode
+  json: com.microsoft.VSCode
+url_schemes:
+  mailto: com.apple.mail
+",
+        )?;
+        ensure_eq!(
+            vec![
+                Handler {
+                    kind: HandlerKind::Extension,
+                    name: "md",
+                    bundle_id: "com.microsoft.VSCode",
+                },
+                Handler {
+                    kind: HandlerKind::Extension,
+                    name: "json",
+                    bundle_id: "com.microsoft.VSCode",
+                },
+                Handler {
+                    kind: HandlerKind::UrlScheme,
+                    name: "mailto",
+                    bundle_id: "com.apple.mail",
+                },
+            ],
+            handlers(&config)
+        );
+        ensure_eq!(
+            r#"[{"kind":"url_scheme","name":"mailto","bundle_id":"com.apple.mail"}]"#,
+            serde_json::to_string(&handlers(&config).get(2..))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_changed_handlers() -> Result<()> {
+        let changed: Vec<ChangedHandler> = serde_json::from_str(
+            r#"[{"kind":"content_type","name":"public.plain-text","previous":null,"bundle_id":"com.microsoft.VSCode"}]"#,
+        )?;
+        ensure_eq!(
+            vec![ChangedHandler {
+                kind: HandlerKind::ContentType,
+                name: "public.plain-text".to_owned(),
+                previous: None,
+                bundle_id: "com.microsoft.VSCode".to_owned(),
+            }],
+            changed
+        );
+        Ok(())
+    }
+}
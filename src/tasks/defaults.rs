@@ -22,6 +22,24 @@ data:
 ```
 
 You can also use a full path to a plist file (the `.plist` file extension is optional, as with the `defaults` command).
+Paths starting with `./` or `../` are relative to the directory containing `up.yaml`.
+
+## Arrays of dictionaries
+
+To add to an existing array, use `...` to represent the existing items. For arrays of dictionaries
+you can instead provide an `identity_key`, and existing dictionaries with the same value for that
+key are updated in place rather than duplicated:
+
+```yaml
+run_lib: defaults
+data:
+  NSGlobalDomain:
+    NSUserDictionaryReplacementItems:
+      - ...:
+          identity_key: replace
+      - replace: omw
+        with: On my way!
+```
 
 ## Current Host modifications
 
@@ -56,19 +74,41 @@ data:
 
 */
 
+mod diff;
+mod hints;
+pub(crate) mod managed;
 mod plist_utils;
 mod ser;
 
+use crate::errors::UpError;
+use crate::opts::DefaultsApplyOptions;
+use crate::opts::DefaultsMergeOptions;
+use crate::opts::DefaultsReadFormat;
 use crate::opts::DefaultsReadOptions;
+use crate::opts::DefaultsUnmanageOptions;
 use crate::opts::DefaultsWriteOptions;
+use crate::tasks;
 use crate::tasks::defaults::plist_utils::get_plist_value_type;
+use crate::tasks::defaults::plist_utils::merged_plist;
 use crate::tasks::defaults::plist_utils::plist_path;
+use crate::tasks::defaults::plist_utils::read_stdin_plist;
 use crate::tasks::defaults::plist_utils::write_defaults_values;
+use crate::tasks::defaults::plist_utils::write_plist_file_values;
+use crate::tasks::defaults::plist_utils::ChangedDefault;
+use crate::tasks::defaults::plist_utils::DomainPrefs;
+use crate::tasks::defaults::plist_utils::STDIN_DOMAIN;
+use crate::tasks::defaults::ser::from_yaml;
+use crate::tasks::defaults::ser::prefs_from_yaml;
 use crate::tasks::defaults::ser::replace_data_in_plist;
+use crate::tasks::defaults::ser::to_defaults_string;
+use crate::tasks::defaults::ser::to_yaml;
 use crate::tasks::defaults::DefaultsError as E;
+use crate::tasks::resolve_config_path;
+use crate::tasks::task::TaskChanges;
 use crate::tasks::task::TaskStatus;
 use crate::tasks::ResolveEnv;
 use crate::tasks::TaskError;
+use crate::utils::files;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use color_eyre::eyre::eyre;
@@ -76,14 +116,19 @@ use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use displaydoc::Display;
 use itertools::Itertools;
+use rayon::prelude::*;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::io::Read;
+use std::env;
+use std::fs;
+use std::io;
 use std::process::ExitStatus;
 use thiserror::Error;
 use tracing::debug;
 use tracing::error;
+use tracing::info;
 use tracing::trace;
 use tracing::warn;
 
@@ -94,7 +139,12 @@ impl ResolveEnv for DefaultsConfig {
     {
         let keys = self.0.keys().cloned().collect_vec();
         for domain in keys {
-            let replaced_domain = env_fn(&domain)?;
+            // Domains starting with `./` or `../` are plist files relative to the config dir.
+            let replaced_domain = if domain.starts_with("./") || domain.starts_with("../") {
+                resolve_config_path(&domain, &env_fn)?.into_string()
+            } else {
+                env_fn(&domain)?
+            };
             if replaced_domain == domain {
                 continue;
             }
@@ -112,47 +162,127 @@ impl ResolveEnv for DefaultsConfig {
     }
 }
 
+/// Number of domains in a task above which plist files are written in parallel.
+const PARALLEL_DOMAINS_THRESHOLD: usize = 50;
+
 /// Configuration for a defaults run library command.
 #[derive(Debug, Default, Serialize, Deserialize)]
-pub struct DefaultsConfig(HashMap<String, HashMap<String, plist::Value>>);
+pub struct DefaultsConfig(
+    #[serde(
+        deserialize_with = "ser::deserialize_domains",
+        serialize_with = "ser::serialize_domains"
+    )]
+    HashMap<String, HashMap<String, plist::Value>>,
+);
 
 /// Run a defaults run library command.
-pub(crate) fn run(config: DefaultsConfig, up_dir: &Utf8Path) -> Result<TaskStatus> {
+pub(crate) fn run(config: DefaultsConfig, backup_dir: &Utf8Path) -> Result<TaskStatus> {
     if !(cfg!(target_os = "macos") || cfg!(target_os = "ios")) {
         debug!("Defaults: skipping setting defaults as not on a Darwin platform.");
         return Ok(TaskStatus::Skipped);
     }
+    write_config(config, false, backup_dir)
+}
 
+/// Write all the values in a defaults config, backing up any files that are changed. Returns the
+/// defaults that changed, and the errors writing the others.
+fn write_values(
+    config: DefaultsConfig,
+    current_host: bool,
+    backup_dir: &Utf8Path,
+) -> (Vec<ChangedDefault>, Vec<E>) {
     debug!("Setting defaults");
-    let (passed, errors): (Vec<_>, Vec<_>) = config
-        .0
-        .into_iter()
-        .map(|(domain, prefs)| write_defaults_values(&domain, prefs, false, up_dir))
-        .partition(Result::is_ok);
+    let domain_count = config.0.len();
+    let mut results = Vec::new();
+    // Several domains can map to the same file (e.g. `NSGlobalDomain` and the path to
+    // `.GlobalPreferences.plist`), so group them to read and write each file once.
+    let mut plist_files: BTreeMap<Utf8PathBuf, Vec<DomainPrefs>> = BTreeMap::new();
+    for (domain, prefs) in config.0.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+        if domain == STDIN_DOMAIN {
+            results.push(write_defaults_values(
+                &domain,
+                prefs,
+                current_host,
+                backup_dir,
+            ));
+            continue;
+        }
+        match plist_path(&domain, current_host) {
+            Ok(path) => plist_files.entry(path).or_default().push((domain, prefs)),
+            Err(e) => results.push(Err(e)),
+        }
+    }
+    let write_file =
+        |(path, domains): (Utf8PathBuf, _)| write_plist_file_values(&path, domains, backup_dir);
+    if domain_count >= PARALLEL_DOMAINS_THRESHOLD {
+        debug!(
+            "Writing {file_count} plist files in parallel.",
+            file_count = plist_files.len()
+        );
+        results.par_extend(plist_files.into_par_iter().map(write_file));
+    } else {
+        results.extend(plist_files.into_iter().map(write_file));
+    }
+
+    let (passed, errors): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
     let errors: Vec<_> = errors.into_iter().map(Result::unwrap_err).collect();
     let passed: Vec<_> = passed.into_iter().map(Result::unwrap).collect();
+    (passed.into_iter().flatten().collect(), errors)
+}
 
-    if passed.iter().all(|r| !r) && errors.is_empty() {
+/// Write all the values in a defaults config, backing up any files that are changed.
+fn write_config(
+    config: DefaultsConfig,
+    current_host: bool,
+    backup_dir: &Utf8Path,
+) -> Result<TaskStatus> {
+    let (changed, errors) = write_values(config, current_host, backup_dir);
+    let defaults_changed = changed.len();
+    if defaults_changed == 0 && errors.is_empty() {
         return Ok(TaskStatus::Skipped);
     }
 
-    if passed.into_iter().any(|r| r) {
+    if defaults_changed > 0 {
         warn!(
             "Defaults values have been changed, these may not take effect until you restart the \
              system or run `sudo killall cfprefsd`"
         );
+        for hint in hints::hints(&changed) {
+            warn!("{hint}");
+        }
     }
 
+    combine_errors(errors)?;
+    Ok(TaskStatus::Passed(TaskChanges {
+        defaults_changed,
+        ..TaskChanges::default()
+    }))
+}
+
+/**
+Write defaults values for another run library (e.g. `finder`), which takes care of restarting
+the apps that read them, so no hints are shown. Returns the number of values changed.
+*/
+pub(crate) fn write_lib_values(
+    domains: HashMap<String, HashMap<String, plist::Value>>,
+    backup_dir: &Utf8Path,
+) -> Result<usize> {
+    let (changed, errors) = write_values(DefaultsConfig(domains), false, backup_dir);
+    combine_errors(errors)?;
+    Ok(changed.len())
+}
+
+/// Log all the `errors`, and return the first one (with the rest as context) if there are any.
+fn combine_errors(errors: Vec<E>) -> Result<()> {
     if errors.is_empty() {
-        Ok(TaskStatus::Passed)
-    } else {
-        for error in &errors {
-            error!("{error:?}");
-        }
-        let mut errors_iter = errors.into_iter();
-        Err(errors_iter.next().ok_or(E::UnexpectedNone)?)
-            .wrap_err_with(|| eyre!("{:?}", errors_iter.collect::<Vec<_>>()))
+        return Ok(());
+    }
+    for error in &errors {
+        error!("{error:?}");
     }
+    let mut errors_iter = errors.into_iter();
+    Err(errors_iter.next().ok_or(E::UnexpectedNone)?)
+        .wrap_err_with(|| eyre!("{:?}", errors_iter.collect::<Vec<_>>()))
 }
 
 #[allow(clippy::doc_markdown)]
@@ -199,21 +329,6 @@ pub enum DefaultsError {
         source: std::io::Error,
     },
 
-    /**
-    Unable to copy file.
-
-    From: `{from_path}`
-    To: `{to_path}`
-    */
-    FileCopy {
-        /// Path we tried to copy from.
-        from_path: Utf8PathBuf,
-        /// Path we tried to copy to.
-        to_path: Utf8PathBuf,
-        /// Source error.
-        source: std::io::Error,
-    },
-
     /// Failed to read bytes from path `{path}`.
     FileRead {
         /// File we tried to read.
@@ -222,6 +337,22 @@ pub enum DefaultsError {
         source: std::io::Error,
     },
 
+    /// Failed to parse defaults file `{path}`, it should map domains to keys and values to set.
+    ApplyFileParse {
+        /// File we tried to parse.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_yaml::Error,
+    },
+
+    /// Failed to parse defaults file `{path}`, it should map keys to the values to write.
+    WriteFileParse {
+        /// File we tried to parse.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_yaml::Error,
+    },
+
     /// Unable to find user's home directory.
     MissingHomeDir {
         /// Source error.
@@ -254,6 +385,28 @@ pub enum DefaultsError {
         plist_type: &'static str,
     },
 
+    /// Failed to read directory {path}.
+    DirRead {
+        /// Directory we tried to read.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+
+    /**
+    Can only list the keys of a plist dictionary, but found a {plist_type} instead.
+    Domain: {domain:?}
+    Key: {key:?}
+    */
+    KeysOfNonDictionary {
+        /// Plist domain.
+        domain: String,
+        /// Plist key.
+        key: Option<String>,
+        /// Type found instead of a dictionary.
+        plist_type: &'static str,
+    },
+
     /// Failed to read Plist file {path}.
     PlistRead {
         /// Path to plist file we failed to read.
@@ -270,6 +423,14 @@ pub enum DefaultsError {
         source: plist::Error,
     },
 
+    /// Failed to write plist file {path}
+    PlistFileWrite {
+        /// Path to plist file we failed to write.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: UpError,
+    },
+
     /// Failed to write a value to plist file {path} as sudo.
     PlistSudoWrite {
         /// Path to plist file we failed to write.
@@ -292,6 +453,34 @@ pub enum DefaultsError {
         source: serde_yaml::Error,
     },
 
+    /**
+    Failed to serialize plist to json.
+    Domain: {domain:?}
+    Key: {key:?}
+    */
+    JsonSerializationFailed {
+        /// Plist domain we failed to serialize.
+        domain: String,
+        /// Plist key we failed to serialize.
+        key: Option<String>,
+        /// Source error.
+        source: serde_json::Error,
+    },
+
+    /**
+    Failed to serialize plist to xml.
+    Domain: {domain:?}
+    Key: {key:?}
+    */
+    PlistXmlSerializationFailed {
+        /// Plist domain we failed to serialize.
+        domain: String,
+        /// Plist key we failed to serialize.
+        key: Option<String>,
+        /// Source error.
+        source: plist::Error,
+    },
+
     /**
     Expected 3 arguments, domain, key, value. Only found two (the global_domain flag was not set):
     Domain: {domain}
@@ -321,6 +510,21 @@ pub enum DefaultsError {
     */
     MissingDomain {},
 
+    /**
+    The global_domain flag was set, so not expecting a domain to be passed with `--from-file`.
+    Domain: {domain}
+    */
+    TooManyArgumentsWriteFile {
+        /// Plist domain found.
+        domain: String,
+    },
+
+    /// Can't read both the plist and the values to write from stdin.
+    BothFromStdin {},
+
+    /// Can't merge in place into a plist read from stdin, drop `--in-place` to print the result.
+    InPlaceStdin {},
+
     /**
     The global_domain flag was set, so not expecting a domain, a key, and a value to be passed.
     Domain: {domain}
@@ -342,18 +546,40 @@ pub enum DefaultsError {
         value: String,
     },
 
-    /// Unable to get plist filename. Path: {path}.
-    UnexpectedPlistPath {
-        /// Path to plist file.
-        path: Utf8PathBuf,
-    },
-
     /// Yaml value claimed to be a string but failed to convert to one: '{value:?}'.
     UnexpectedString {
         /// Value and conversion error.
         value: Result<String, serde_yaml::Error>,
     },
 
+    /// Failed to parse the managed defaults manifest `{path}`.
+    ManifestParse {
+        /// Manifest we tried to parse.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_yaml::Error,
+    },
+
+    /// Failed to write the managed defaults manifest `{path}`.
+    ManifestWrite {
+        /// Manifest we tried to write.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: color_eyre::Report,
+    },
+
+    /**
+    up hasn't written this default, so there's nothing to unmanage.
+    Domain: {domain:?}
+    Key: {key:?}
+    */
+    NotManaged {
+        /// Plist domain.
+        domain: String,
+        /// Plist key, unset for the whole domain.
+        key: Option<String>,
+    },
+
     /// Unexpectedly empty option found.
     UnexpectedNone,
 
@@ -384,23 +610,15 @@ pub(crate) fn read(current_host: bool, defaults_opts: DefaultsReadOptions) -> Re
     let plist_path = plist_path(&domain, current_host)?;
     debug!("Plist path: {plist_path}");
 
-    let plist: plist::Value = if &plist_path == "-" {
+    let plist: plist::Value = if plist_path == STDIN_DOMAIN {
         // Read from stdin directly if specified.
-        let mut bytes = Vec::new();
-        _ = std::io::stdin()
-            .read_to_end(&mut bytes)
-            .map_err(|e| E::FileRead {
-                path: plist_path.clone(),
-                source: e,
-            })?;
-        plist::from_bytes(&bytes)
+        read_stdin_plist()?.0
     } else {
-        plist::from_file(&plist_path)
-    }
-    .map_err(|e| E::PlistRead {
-        path: plist_path,
-        source: e,
-    })?;
+        plist::from_file(&plist_path).map_err(|e| E::PlistRead {
+            path: plist_path,
+            source: e,
+        })?
+    };
     trace!("Plist: {plist:?}");
 
     let value = match key.as_ref() {
@@ -419,68 +637,260 @@ pub(crate) fn read(current_host: bool, defaults_opts: DefaultsReadOptions) -> Re
         None => &plist,
     };
 
-    let serialization_result = serde_yaml::to_string(value);
-    let serialized_string = if let Ok(s) = serialization_result {
-        s
-    } else {
-        warn!(
-            "Serializing plist value to YAML failed, assuming this is because it contained binary \
-             data and replacing that with hex-encoded binary data. This is incorrect, but allows \
-             the output to be printed."
-        );
-        let mut value = value.clone();
-        replace_data_in_plist(&mut value).map_err(|e| E::EyreError { source: e })?;
-        serde_yaml::to_string(&value).map_err(|e| E::SerializationFailed {
-            domain,
-            key,
-            source: e,
-        })?
+    if defaults_opts.keys {
+        let dict = value
+            .as_dictionary()
+            .ok_or_else(|| E::KeysOfNonDictionary {
+                domain,
+                key,
+                plist_type: get_plist_value_type(value),
+            })?;
+        for key in dict.keys() {
+            println!("{key}");
+        }
+        return Ok(());
+    }
+
+    let serialized_string = match defaults_opts.format {
+        // Dates and binary data are written with `!date` and `!data` tags, which can be written
+        // back with `up defaults write`.
+        DefaultsReadFormat::Yaml => {
+            serde_yaml::to_string(&to_yaml(value)).map_err(|e| E::SerializationFailed {
+                domain,
+                key,
+                source: e,
+            })?
+        }
+        // JSON has no binary type (serde_json would print an array of numbers), so always use the
+        // hex-encoded fallback.
+        DefaultsReadFormat::Json => {
+            let mut s = serde_json::to_string_pretty(&with_data_as_hex(value)?).map_err(|e| {
+                E::JsonSerializationFailed {
+                    domain,
+                    key,
+                    source: e,
+                }
+            })?;
+            s.push('\n');
+            s
+        }
+        DefaultsReadFormat::PlistXml => {
+            let mut bytes = Vec::new();
+            value
+                .to_writer_xml(&mut bytes)
+                .map_err(|e| E::PlistXmlSerializationFailed {
+                    domain,
+                    key,
+                    source: e,
+                })?;
+            let mut s = String::from_utf8_lossy(&bytes).into_owned();
+            if !s.ends_with('\n') {
+                s.push('\n');
+            }
+            s
+        }
+        DefaultsReadFormat::Defaults => to_defaults_string(&with_data_as_hex(value)?),
     };
     print!("{serialized_string}");
     Ok(())
 }
 
+/// `up defaults domains` command.
+pub(crate) fn domains(current_host: bool) -> Result<(), E> {
+    let home_dir = files::home_dir().map_err(|e| E::MissingHomeDir { source: e })?;
+    for domain in plist_utils::domains(&home_dir, current_host)? {
+        println!("{domain}");
+    }
+    Ok(())
+}
+
+/// A copy of `value` with any binary data replaced by hex-encoded strings.
+fn with_data_as_hex(value: &plist::Value) -> Result<plist::Value, E> {
+    let mut value = value.clone();
+    replace_data_in_plist(&mut value).map_err(|e| E::EyreError { source: e })?;
+    Ok(value)
+}
+
 /// `up defaults write` command.
 pub(crate) fn write(
     current_host: bool,
     defaults_opts: DefaultsWriteOptions,
-    up_dir: &Utf8Path,
+    backup_dir: &Utf8Path,
 ) -> Result<(), E> {
+    if let Some(from_file) = defaults_opts.from_file {
+        let domain = match (defaults_opts.global_domain, defaults_opts.domain) {
+            (true, None) => "NSGlobalDomain".to_owned(),
+            (true, Some(domain)) => return Err(E::TooManyArgumentsWriteFile { domain }),
+            (false, Some(domain)) => domain,
+            (false, None) => return Err(E::MissingDomain {}),
+        };
+        return write_from_file(&domain, &from_file, current_host, backup_dir);
+    }
+    let domain = defaults_opts.domain.ok_or(E::MissingDomain {})?;
+    let key = defaults_opts.key.unwrap_or_default();
     let (domain, key, value) = if defaults_opts.global_domain {
         if defaults_opts.value.is_some() {
             return Err(E::TooManyArgumentsWrite {
-                domain: defaults_opts.domain,
-                key: defaults_opts.key,
+                domain,
+                key,
                 value: defaults_opts.value,
             });
         }
-        (
-            "NSGlobalDomain".to_owned(),
-            defaults_opts.domain,
-            defaults_opts.key,
-        )
+        ("NSGlobalDomain".to_owned(), domain, key)
     } else if let Some(value) = defaults_opts.value {
-        (defaults_opts.domain, defaults_opts.key, value)
+        (domain, key, value)
     } else {
-        return Err(E::TooFewArgumentsWrite {
-            domain: defaults_opts.domain,
-            key: defaults_opts.key,
-        });
+        return Err(E::TooFewArgumentsWrite { domain, key });
     };
     debug!("Domain: {domain:?}, Key: {key:?}, Value: {value:?}");
     let mut prefs = HashMap::new();
 
     let new_value: plist::Value =
-        serde_yaml::from_str(&value).map_err(|e| E::DeSerializationFailed {
-            domain: domain.clone(),
-            key: key.clone(),
-            value: value.clone(),
-            source: e,
-        })?;
+        serde_yaml::from_str(&value)
+            .and_then(from_yaml)
+            .map_err(|e| E::DeSerializationFailed {
+                domain: domain.clone(),
+                key: key.clone(),
+                value: value.clone(),
+                source: e,
+            })?;
     trace!("Serialized Plist value: {new_value:?}");
 
     prefs.insert(key, new_value);
 
-    write_defaults_values(&domain, prefs, current_host, up_dir)?;
+    write_defaults_values(&domain, prefs, current_host, backup_dir)?;
+    Ok(())
+}
+
+/// Write the keys and values in the yaml file at `path` (or stdin if `path` is `-`) to `domain`.
+fn write_from_file(
+    domain: &str,
+    path: &Utf8Path,
+    current_host: bool,
+    backup_dir: &Utf8Path,
+) -> Result<(), E> {
+    if domain == STDIN_DOMAIN && path == STDIN_DOMAIN {
+        return Err(E::BothFromStdin {});
+    }
+    let prefs = read_prefs_file(path)?;
+    trace!("Values to write: {prefs:?}");
+
+    let values_changed = write_defaults_values(domain, prefs, current_host, backup_dir)?.len();
+    if domain != STDIN_DOMAIN {
+        info!("Wrote {path} to {domain}, {values_changed} defaults changed.");
+    }
+    Ok(())
+}
+
+/// Read the yaml file at `path` (or stdin if `path` is `-`) mapping keys to values to write.
+fn read_prefs_file(path: &Utf8Path) -> Result<HashMap<String, plist::Value>, E> {
+    let contents = if path == STDIN_DOMAIN {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|e| E::FileRead {
+        path: path.to_owned(),
+        source: e,
+    })?;
+    serde_yaml::from_str(&contents)
+        .and_then(prefs_from_yaml)
+        .map_err(|e| E::WriteFileParse {
+            path: path.to_owned(),
+            source: e,
+        })
+}
+
+/**
+`up defaults merge` command: merge the values in the patch file into the target domain or plist
+file, printing the merged plist, or writing it back if `--in-place` was passed.
+*/
+pub(crate) fn merge(
+    current_host: bool,
+    merge_opts: DefaultsMergeOptions,
+    backup_dir: &Utf8Path,
+) -> Result<(), E> {
+    let DefaultsMergeOptions {
+        target,
+        patch,
+        in_place,
+    } = merge_opts;
+    if target == STDIN_DOMAIN && patch == STDIN_DOMAIN {
+        return Err(E::BothFromStdin {});
+    }
+    if target == STDIN_DOMAIN && in_place {
+        return Err(E::InPlaceStdin {});
+    }
+    let plist_path = if target == STDIN_DOMAIN || target.contains('/') {
+        Utf8PathBuf::from(&target)
+    } else {
+        plist_path(&target, current_host)?
+    };
+    let prefs = read_prefs_file(&patch)?;
+    trace!("Values to merge: {prefs:?}");
+
+    if in_place {
+        let values_changed =
+            write_plist_file_values(&plist_path, vec![(target, prefs)], backup_dir)?.len();
+        info!("Merged {patch} into {plist_path}, {values_changed} defaults changed.");
+        return Ok(());
+    }
+    let (merged, values_changed) = merged_plist(&target, &plist_path, prefs)?;
+    debug!(
+        "Merging {patch} into {plist_path} would change {count} defaults.",
+        count = values_changed.len()
+    );
+    plist::to_writer_xml(io::stdout().lock(), &merged).map_err(|e| E::PlistWrite {
+        path: Utf8PathBuf::from("/dev/stdout"),
+        source: e,
+    })?;
+    println!();
+    Ok(())
+}
+
+/// `up defaults apply` command.
+pub(crate) fn apply(
+    current_host: bool,
+    apply_opts: DefaultsApplyOptions,
+    backup_dir: &Utf8Path,
+) -> Result<()> {
+    let path = apply_opts.path;
+    let contents = fs::read_to_string(&path).map_err(|e| E::FileRead {
+        path: path.clone(),
+        source: e,
+    })?;
+    let mut config: DefaultsConfig =
+        serde_yaml::from_str(&contents).map_err(|e| E::ApplyFileParse {
+            path: path.clone(),
+            source: e,
+        })?;
+    let env: HashMap<String, String> = env::vars().collect();
+    config.resolve_env(|s| tasks::resolve_env_value(s, &env))?;
+
+    if let TaskStatus::Passed(changes) = write_config(config, current_host, backup_dir)? {
+        info!("Applied {path}, changes: {changes}");
+    } else {
+        info!("Defaults in {path} already up to date.");
+    }
+    Ok(())
+}
+
+/// `up defaults unmanage` command.
+pub(crate) fn unmanage(
+    current_host: bool,
+    unmanage_opts: DefaultsUnmanageOptions,
+    backup_dir: &Utf8Path,
+) -> Result<(), E> {
+    let DefaultsUnmanageOptions { domain, key } = unmanage_opts;
+    let manifest_path = managed::manifest_path().ok_or(E::UnexpectedNone)?;
+    let plist_path = plist_path(&domain, current_host)?;
+    let count = managed::unmanage(
+        manifest_path,
+        &domain,
+        &plist_path,
+        key.as_deref(),
+        &backup_dir.join("defaults"),
+    )?;
+    info!("Unmanaged {count} defaults in {domain}.");
     Ok(())
 }
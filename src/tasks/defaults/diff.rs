@@ -0,0 +1,227 @@
+//! Key-path level diffs of plist values, so changes to big nested defaults (like the Dock's
+//! `persistent-apps`) can be logged readably.
+use plist::Value;
+use std::fmt;
+use std::fmt::Write as _;
+
+/// Most leaf changes to list when logging a diff, the rest are summarised as a count.
+const MAX_LOGGED_CHANGES: usize = 20;
+
+/// A change to a leaf (or a whole added or removed subtree) of a plist value.
+#[derive(Debug, PartialEq)]
+pub(super) enum LeafChange<'a> {
+    /// The path didn't exist before.
+    Added {
+        /// Key path, e.g. `persistent-apps[3].tile-data.file-label`.
+        path: String,
+        /// New value.
+        new: &'a Value,
+    },
+    /// The path no longer exists.
+    Removed {
+        /// Key path.
+        path: String,
+        /// Old value.
+        old: &'a Value,
+    },
+    /// The value at the path changed.
+    Changed {
+        /// Key path.
+        path: String,
+        /// Old value.
+        old: &'a Value,
+        /// New value.
+        new: &'a Value,
+    },
+}
+
+impl fmt::Display for LeafChange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { path, new } => write!(f, "+ {path}: {}", Summary(new)),
+            Self::Removed { path, old } => write!(f, "- {path}: {}", Summary(old)),
+            Self::Changed { path, old, new } => {
+                write!(f, "~ {path}: {} -> {}", Summary(old), Summary(new))
+            }
+        }
+    }
+}
+
+/// Short display of a plist value, containers and data are shown as their size.
+struct Summary<'a>(&'a Value);
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Value::Array(array) => write!(f, "[{} items]", array.len()),
+            Value::Dictionary(dict) => write!(f, "{{{} keys}}", dict.len()),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::Data(data) => write!(f, "<{} bytes>", data.len()),
+            Value::Date(date) => write!(f, "{}", date.to_xml_format()),
+            Value::Real(real) => write!(f, "{real}"),
+            Value::Integer(integer) => write!(f, "{integer}"),
+            Value::String(string) => write!(f, "{string:?}"),
+            Value::Uid(uid) => write!(f, "uid {}", uid.get()),
+            value => write!(f, "{value:?}"),
+        }
+    }
+}
+
+/// Whether the value contains other values.
+pub(super) fn is_nested(value: &Value) -> bool {
+    matches!(value, Value::Array(_) | Value::Dictionary(_))
+}
+
+/**
+The leaf changes from `old` to `new`, with paths relative to `path`.
+
+Dictionaries are compared key by key, and arrays index by index (so inserting an item shows up as
+every later item changing).
+*/
+pub(super) fn diff<'a>(path: &str, old: &'a Value, new: &'a Value) -> Vec<LeafChange<'a>> {
+    let mut changes = Vec::new();
+    diff_into(path, old, new, &mut changes);
+    changes
+}
+
+/// Add the changes from `old` to `new` under `path` to `changes`.
+fn diff_into<'a>(path: &str, old: &'a Value, new: &'a Value, changes: &mut Vec<LeafChange<'a>>) {
+    match (old, new) {
+        (Value::Dictionary(old_dict), Value::Dictionary(new_dict)) => {
+            for (key, old_value) in old_dict {
+                let key_path = format!("{path}.{key}");
+                match new_dict.get(key) {
+                    Some(new_value) => diff_into(&key_path, old_value, new_value, changes),
+                    None => changes.push(LeafChange::Removed {
+                        path: key_path,
+                        old: old_value,
+                    }),
+                }
+            }
+            for (key, new_value) in new_dict {
+                if !old_dict.contains_key(key) {
+                    changes.push(LeafChange::Added {
+                        path: format!("{path}.{key}"),
+                        new: new_value,
+                    });
+                }
+            }
+        }
+        (Value::Array(old_array), Value::Array(new_array)) => {
+            for (index, old_value) in old_array.iter().enumerate() {
+                let index_path = format!("{path}[{index}]");
+                match new_array.get(index) {
+                    Some(new_value) => diff_into(&index_path, old_value, new_value, changes),
+                    None => changes.push(LeafChange::Removed {
+                        path: index_path,
+                        old: old_value,
+                    }),
+                }
+            }
+            for (index, new_value) in new_array.iter().enumerate().skip(old_array.len()) {
+                changes.push(LeafChange::Added {
+                    path: format!("{path}[{index}]"),
+                    new: new_value,
+                });
+            }
+        }
+        _ if old == new => {}
+        _ => changes.push(LeafChange::Changed {
+            path: path.to_owned(),
+            old,
+            new,
+        }),
+    }
+}
+
+/// The `changes`, one per indented line, with any beyond [`MAX_LOGGED_CHANGES`] counted.
+pub(super) fn format_changes(changes: &[LeafChange<'_>]) -> String {
+    let mut output = String::new();
+    for change in changes.iter().take(MAX_LOGGED_CHANGES) {
+        _ = write!(output, "\n  {change}");
+    }
+    if let Some(more) = changes.len().checked_sub(MAX_LOGGED_CHANGES) {
+        if more > 0 {
+            _ = write!(output, "\n  ... and {more} more changes");
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use super::format_changes;
+    use super::LeafChange;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use plist::Value;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let old: Value = plist::from_bytes(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+  <key>apps</key>
+  <array>
+    <dict><key>label</key><string>Safari</string></dict>
+    <dict><key>label</key><string>Mail</string></dict>
+  </array>
+  <key>autohide</key><false/>
+  <key>size</key><integer>48</integer>
+</dict>
+</plist>"#,
+        )?;
+        let new: Value = plist::from_bytes(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+  <key>apps</key>
+  <array>
+    <dict><key>label</key><string>Safari</string></dict>
+    <dict><key>label</key><string>Notes</string></dict>
+    <dict><key>label</key><string>Mail</string></dict>
+  </array>
+  <key>autohide</key><true/>
+  <key>tilesize</key><integer>36</integer>
+</dict>
+</plist>"#,
+        )?;
+
+        let changes = diff("com.apple.dock", &old, &new);
+        ensure_eq!(
+            "
+  ~ com.apple.dock.apps[1].label: \"Mail\" -> \"Notes\"
+  + com.apple.dock.apps[2]: {1 keys}
+  ~ com.apple.dock.autohide: false -> true
+  - com.apple.dock.size: 48
+  + com.apple.dock.tilesize: 36",
+            format_changes(&changes)
+        );
+        ensure_eq!(0, diff("key", &old, &old).len());
+        ensure_eq!(
+            vec![LeafChange::Changed {
+                path: "key".to_owned(),
+                old: &Value::from(1),
+                new: &Value::from("1"),
+            }],
+            diff("key", &Value::from(1), &Value::from("1"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_changes_truncates() -> Result<()> {
+        let old = Value::Array(vec![Value::from(0); 25]);
+        let new = Value::Array(vec![Value::from(1); 25]);
+        let formatted = format_changes(&diff("key", &old, &new));
+        ensure_eq!(21, formatted.lines().count() - 1);
+        ensure!(
+            formatted.ends_with("\n  ... and 5 more changes"),
+            "{formatted}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+//! Hints for what to do after changing well-known defaults: which app to restart (or whether to
+//! log out) for the change to take effect, and which System Settings pane shows the setting.
+use crate::tasks::defaults::plist_utils::ChangedDefault;
+use camino::Utf8Path;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// What's needed for a changed default to take effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Restart {
+    /// Restart the app (with `killall`).
+    App(&'static str),
+    /// Log out and back in.
+    Logout,
+}
+
+impl fmt::Display for Restart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::App(app) => write!(f, "run `killall {app}` for them to take effect"),
+            Self::Logout => write!(f, "log out and back in for them to take effect"),
+        }
+    }
+}
+
+/// A known default (or every default in a domain if `key` is `None`).
+#[derive(Debug)]
+struct Hint {
+    /// Domain, as the plist file name without the `.plist` extension.
+    domain: &'static str,
+    /// Key in the domain, `None` to match all keys.
+    key: Option<&'static str>,
+    /// What's needed for a change to take effect.
+    restart: Option<Restart>,
+    /// System Settings pane that shows the setting.
+    pane: Option<&'static str>,
+}
+
+/// Shorthand to build the [`HINTS`] table.
+const fn hint(
+    domain: &'static str,
+    key: Option<&'static str>,
+    restart: Option<Restart>,
+    pane: Option<&'static str>,
+) -> Hint {
+    Hint {
+        domain,
+        key,
+        restart,
+        pane,
+    }
+}
+
+/// System Settings pane link.
+const APPEARANCE: &str = "x-apple.systempreferences:com.apple.Appearance-Settings.extension";
+/// System Settings pane link.
+const ACCESSIBILITY: &str = "x-apple.systempreferences:com.apple.Accessibility-Settings.extension";
+/// System Settings pane link.
+const CONTROL_CENTER: &str = "x-apple.systempreferences:com.apple.ControlCenter-Settings.extension";
+/// System Settings pane link.
+const DESKTOP_DOCK: &str = "x-apple.systempreferences:com.apple.Desktop-Settings.extension";
+/// System Settings pane link.
+const KEYBOARD: &str = "x-apple.systempreferences:com.apple.Keyboard-Settings.extension";
+/// System Settings pane link.
+const LANGUAGE: &str = "x-apple.systempreferences:com.apple.Localization-Settings.extension";
+/// System Settings pane link.
+const MOUSE: &str = "x-apple.systempreferences:com.apple.Mouse-Settings.extension";
+/// System Settings pane link.
+const SOFTWARE_UPDATE: &str =
+    "x-apple.systempreferences:com.apple.Software-Update-Settings.extension";
+/// System Settings pane link.
+const TRACKPAD: &str = "x-apple.systempreferences:com.apple.Trackpad-Settings.extension";
+/// System Settings pane link.
+const USERS: &str = "x-apple.systempreferences:com.apple.Users-Groups-Settings.extension";
+
+/// Global domain, as normalized by [`normalize_domain`].
+const GLOBAL_DOMAIN: &str = "NSGlobalDomain";
+
+/// Known defaults. Hints for a specific key take precedence over hints for the whole domain.
+const HINTS: &[Hint] = &[
+    hint(
+        "com.apple.dock",
+        None,
+        Some(Restart::App("Dock")),
+        Some(DESKTOP_DOCK),
+    ),
+    hint("com.apple.WindowManager", None, None, Some(DESKTOP_DOCK)),
+    hint("com.apple.finder", None, Some(Restart::App("Finder")), None),
+    hint(
+        "com.apple.screencapture",
+        None,
+        Some(Restart::App("SystemUIServer")),
+        None,
+    ),
+    hint(
+        "com.apple.menuextra.clock",
+        None,
+        Some(Restart::App("ControlCenter")),
+        Some(CONTROL_CENTER),
+    ),
+    hint(
+        "com.apple.controlcenter",
+        None,
+        Some(Restart::App("ControlCenter")),
+        Some(CONTROL_CENTER),
+    ),
+    hint(
+        "com.apple.AppleMultitouchTrackpad",
+        None,
+        Some(Restart::Logout),
+        Some(TRACKPAD),
+    ),
+    hint(
+        "com.apple.driver.AppleBluetoothMultitouch.trackpad",
+        None,
+        Some(Restart::Logout),
+        Some(TRACKPAD),
+    ),
+    hint(
+        "com.apple.universalaccess",
+        None,
+        Some(Restart::Logout),
+        Some(ACCESSIBILITY),
+    ),
+    hint(
+        "com.apple.loginwindow",
+        None,
+        Some(Restart::Logout),
+        Some(USERS),
+    ),
+    hint(
+        "com.apple.SoftwareUpdate",
+        None,
+        None,
+        Some(SOFTWARE_UPDATE),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("KeyRepeat"),
+        Some(Restart::Logout),
+        Some(KEYBOARD),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("InitialKeyRepeat"),
+        Some(Restart::Logout),
+        Some(KEYBOARD),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("ApplePressAndHoldEnabled"),
+        Some(Restart::Logout),
+        Some(KEYBOARD),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("com.apple.swipescrolldirection"),
+        Some(Restart::Logout),
+        Some(TRACKPAD),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("com.apple.mouse.tapBehavior"),
+        Some(Restart::Logout),
+        Some(TRACKPAD),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("com.apple.trackpad.scaling"),
+        Some(Restart::Logout),
+        Some(TRACKPAD),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("com.apple.mouse.scaling"),
+        Some(Restart::Logout),
+        Some(MOUSE),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("AppleInterfaceStyle"),
+        Some(Restart::Logout),
+        Some(APPEARANCE),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("AppleShowScrollBars"),
+        None,
+        Some(APPEARANCE),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("AppleLanguages"),
+        Some(Restart::Logout),
+        Some(LANGUAGE),
+    ),
+    hint(
+        GLOBAL_DOMAIN,
+        Some("AppleLocale"),
+        Some(Restart::Logout),
+        Some(LANGUAGE),
+    ),
+];
+
+/// Messages telling the user what to do for the `changed` defaults to take effect, one per
+/// distinct hint.
+pub(super) fn hints(changed: &[ChangedDefault]) -> Vec<String> {
+    let mut grouped: BTreeMap<(Option<Restart>, Option<&str>), BTreeSet<String>> = BTreeMap::new();
+    for (domain, key) in changed {
+        let domain = normalize_domain(domain);
+        let Some(hint) = HINTS
+            .iter()
+            .find(|h| h.domain == domain && h.key == Some(key.as_str()))
+            .or_else(|| HINTS.iter().find(|h| h.domain == domain && h.key.is_none()))
+        else {
+            continue;
+        };
+        grouped
+            .entry((hint.restart, hint.pane))
+            .or_default()
+            .insert(format!("{domain} {key}"));
+    }
+
+    grouped
+        .into_iter()
+        .map(|((restart, pane), defaults)| {
+            let defaults = defaults.into_iter().collect::<Vec<_>>().join(", ");
+            match (restart, pane) {
+                (Some(restart), Some(pane)) => {
+                    format!("Changed {defaults}: {restart}, see {pane}")
+                }
+                (Some(restart), None) => format!("Changed {defaults}: {restart}"),
+                (None, Some(pane)) => format!("Changed {defaults}: see {pane}"),
+                (None, None) => format!("Changed {defaults}"),
+            }
+        })
+        .collect()
+}
+
+/// The domain name for a domain or plist path, e.g. `~/Library/Preferences/com.apple.dock.plist`
+/// is `com.apple.dock`, and `.GlobalPreferences.plist` is `NSGlobalDomain`.
+fn normalize_domain(domain: &str) -> &str {
+    let name = Utf8Path::new(domain).file_name().unwrap_or(domain);
+    let name = name.strip_suffix(".plist").unwrap_or(name);
+    if name.starts_with(".GlobalPreferences") {
+        GLOBAL_DOMAIN
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hints;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_hints() -> color_eyre::Result<()> {
+        let changed = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(domain, key)| ((*domain).to_owned(), (*key).to_owned()))
+                .collect::<Vec<_>>()
+        };
+
+        ensure_eq!(
+            vec![
+                "Changed com.apple.dock autohide, com.apple.dock tilesize: run `killall Dock` for \
+                 them to take effect, see \
+                 x-apple.systempreferences:com.apple.Desktop-Settings.extension"
+                    .to_owned(),
+                "Changed NSGlobalDomain KeyRepeat: log out and back in for them to take effect, \
+                 see x-apple.systempreferences:com.apple.Keyboard-Settings.extension"
+                    .to_owned(),
+            ],
+            hints(&changed(&[
+                ("com.apple.dock", "tilesize"),
+                ("~/Library/Preferences/com.apple.dock.plist", "autohide"),
+                (
+                    "~/Library/Preferences/.GlobalPreferences.plist",
+                    "KeyRepeat"
+                ),
+                ("NSGlobalDomain", "SomethingUnknown"),
+                ("com.example.unknown", "key"),
+            ]))
+        );
+        ensure_eq!(Vec::<String>::new(), hints(&[]));
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+/*!
+The manifest of defaults keys up has written, and `up defaults unmanage` to revert them.
+
+Each time up changes a key in a plist file it records the key's value from before up first wrote
+it (or that it didn't exist) in `defaults.yaml` in the state directory, keyed by plist file path:
+
+```yaml
+/Users/me/Library/Preferences/com.apple.dock.plist:
+  autohide:
+    original: false
+  tilesize: {}
+```
+
+Here `tilesize` didn't exist, so unmanaging it deletes it rather than restoring a value.
+*/
+use crate::tasks::defaults::plist_utils::get_plist_value_type;
+use crate::tasks::defaults::plist_utils::read_plist_or_empty;
+use crate::tasks::defaults::plist_utils::write_plist;
+use crate::tasks::defaults::ser::from_yaml;
+use crate::tasks::defaults::ser::to_yaml;
+use crate::tasks::defaults::DefaultsError as E;
+use crate::utils::files;
+use crate::utils::files::AtomicWriteOptions;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use tracing::debug;
+use tracing::info;
+
+/// Name of the manifest file in the state directory.
+const MANIFEST_FILE: &str = "defaults.yaml";
+
+/// Path to the manifest, set from the state directory at startup.
+static MANIFEST_PATH: OnceLock<Utf8PathBuf> = OnceLock::new();
+
+/// Held while reading and writing the manifest, as plist files are written in parallel.
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+/**
+Record the defaults keys up writes in `state_dir` for the rest of the run.
+
+Until this is called (e.g. when up is used as a library) written keys aren't recorded.
+*/
+pub(crate) fn set_state_dir(state_dir: &Utf8Path) {
+    let path = state_dir.join(MANIFEST_FILE);
+    if let Err(path) = MANIFEST_PATH.set(path) {
+        debug!("Defaults manifest path already set, ignoring {path}.");
+    }
+}
+
+/// Path to the manifest, if [`set_state_dir`] has been called.
+pub(super) fn manifest_path() -> Option<&'static Utf8Path> {
+    MANIFEST_PATH.get().map(Utf8PathBuf::as_path)
+}
+
+/// The keys up has written, keyed by plist file path then key.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Manifest(BTreeMap<Utf8PathBuf, BTreeMap<String, ManagedKey>>);
+
+/// A key up has written.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct ManagedKey {
+    /// The key's value before up first wrote it (in yaml), unset if the key didn't exist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original: Option<serde_yaml::Value>,
+}
+
+impl Manifest {
+    /// Read the manifest at `path`, or an empty manifest if it doesn't exist.
+    fn read(path: &Utf8Path) -> Result<Self, E> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(E::FileRead {
+                    path: path.to_owned(),
+                    source: e,
+                })
+            }
+        };
+        serde_yaml::from_str(&contents).map_err(|e| E::ManifestParse {
+            path: path.to_owned(),
+            source: e,
+        })
+    }
+
+    /// Write the manifest to `path`, creating its directory if needed.
+    fn write(&self, path: &Utf8Path) -> Result<(), E> {
+        let write = || -> color_eyre::Result<()> {
+            files::create_dir_all(files::parent(path)?)?;
+            files::atomic_write(
+                path,
+                serde_yaml::to_string(self)?,
+                AtomicWriteOptions {
+                    fsync: true,
+                    ..AtomicWriteOptions::default()
+                },
+            )?;
+            Ok(())
+        };
+        write().map_err(|e| E::ManifestWrite {
+            path: path.to_owned(),
+            source: e,
+        })
+    }
+}
+
+/**
+Record in the manifest at `manifest_path` that up wrote the `keys` in `plist_path`, each with its
+value before the write (`None` if it didn't exist).
+
+Keys that were already recorded keep the value from before up first wrote them.
+*/
+pub(super) fn record(
+    manifest_path: &Utf8Path,
+    plist_path: &Utf8Path,
+    keys: Vec<(String, Option<&plist::Value>)>,
+) -> Result<(), E> {
+    let _lock = MANIFEST_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let mut manifest = Manifest::read(manifest_path)?;
+    let managed_keys = manifest.0.entry(plist_path.to_owned()).or_default();
+    let mut changed = false;
+    for (key, original) in keys {
+        managed_keys.entry(key).or_insert_with(|| {
+            changed = true;
+            ManagedKey {
+                original: original.map(to_yaml),
+            }
+        });
+    }
+    if changed {
+        manifest.write(manifest_path)?;
+    }
+    Ok(())
+}
+
+/**
+Stop managing `key` (or all the keys recorded for the file if `None`) of `domain`, whose plist file
+is at `plist_path`: restore each key's original value (or delete it if it didn't exist), and
+remove it from the manifest at `manifest_path`. The plist file is backed up into `backup_dir`.
+
+Returns the number of keys unmanaged.
+*/
+pub(super) fn unmanage(
+    manifest_path: &Utf8Path,
+    domain: &str,
+    plist_path: &Utf8Path,
+    key: Option<&str>,
+    backup_dir: &Utf8Path,
+) -> Result<usize, E> {
+    let _lock = MANIFEST_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let mut manifest = Manifest::read(manifest_path)?;
+    let not_managed = || E::NotManaged {
+        domain: domain.to_owned(),
+        key: key.map(ToOwned::to_owned),
+    };
+    let managed_keys = manifest.0.get_mut(plist_path).ok_or_else(not_managed)?;
+    let unmanaged: BTreeMap<String, ManagedKey> = match key {
+        Some(key) => {
+            let managed_key = managed_keys.remove(key).ok_or_else(not_managed)?;
+            BTreeMap::from([(key.to_owned(), managed_key)])
+        }
+        None => std::mem::take(managed_keys),
+    };
+    if managed_keys.is_empty() {
+        manifest.0.remove(plist_path);
+    }
+
+    let plist_path_exists = plist_path.exists();
+    let mut plist_value = read_plist_or_empty(plist_path)?;
+    let plist_type = get_plist_value_type(&plist_value);
+    let dict = plist_value
+        .as_dictionary_mut()
+        .ok_or_else(|| E::NotADictionary {
+            domain: domain.to_owned(),
+            key: key.unwrap_or_default().to_owned(),
+            plist_type,
+        })?;
+    let mut restored = false;
+    for (key, managed_key) in &unmanaged {
+        if let Some(original) = &managed_key.original {
+            let value = from_yaml(original.clone()).map_err(|e| E::DeSerializationFailed {
+                domain: domain.to_owned(),
+                key: key.clone(),
+                value: format!("{original:?}"),
+                source: e,
+            })?;
+            info!("Restoring {domain} {key} to its value before up wrote it.");
+            dict.insert(key.clone(), value);
+            restored = true;
+        } else {
+            info!("Deleting {domain} {key}, as it didn't exist before up wrote it.");
+            dict.remove(key);
+        }
+    }
+    // If the file has been deleted there's nothing to delete keys from, but restored keys still
+    // need writing.
+    if plist_path_exists || restored {
+        write_plist(plist_path_exists, plist_path, &plist_value, backup_dir)?;
+    }
+    manifest.write(manifest_path)?;
+    Ok(unmanaged.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::record;
+    use super::unmanage;
+    use super::ManagedKey;
+    use super::Manifest;
+    use crate::tasks::defaults::plist_utils::write_plist_file_values;
+    use crate::tasks::defaults::DefaultsError as E;
+    use color_eyre::Result;
+    use plist::Dictionary;
+    use plist::Value;
+    use std::collections::BTreeMap;
+    use std::collections::HashMap;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_record_and_unmanage() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let manifest_path = temp_dir.join("state/defaults.yaml");
+        let backup_dir = temp_dir.join("backup");
+        let plist_path = temp_dir.join("test.plist");
+        let mut dict = Dictionary::new();
+        dict.insert("kept".to_owned(), Value::from(1));
+        dict.insert("changed".to_owned(), Value::from("old"));
+        plist::to_file_xml(&plist_path, &Value::Dictionary(dict))?;
+
+        // Write the keys as a defaults task would, recording them in the manifest.
+        let old_value = Value::from("old");
+        write_plist_file_values(
+            &plist_path,
+            vec![(
+                "test".to_owned(),
+                HashMap::from([
+                    ("changed".to_owned(), Value::from("new")),
+                    ("added".to_owned(), Value::from(true)),
+                ]),
+            )],
+            &backup_dir,
+        )?;
+        record(
+            &manifest_path,
+            &plist_path,
+            vec![
+                ("changed".to_owned(), Some(&old_value)),
+                ("added".to_owned(), None),
+            ],
+        )?;
+        // Writing a key again doesn't replace its original value.
+        let new_value = Value::from("new");
+        record(
+            &manifest_path,
+            &plist_path,
+            vec![("changed".to_owned(), Some(&new_value))],
+        )?;
+        ensure_eq!(
+            Manifest(BTreeMap::from([(
+                plist_path.clone(),
+                BTreeMap::from([
+                    ("added".to_owned(), ManagedKey { original: None }),
+                    (
+                        "changed".to_owned(),
+                        ManagedKey {
+                            original: Some(serde_yaml::Value::from("old"))
+                        }
+                    ),
+                ])
+            )])),
+            Manifest::read(&manifest_path)?
+        );
+
+        // Unmanaging a key restores its original value.
+        ensure_eq!(
+            1,
+            unmanage(
+                &manifest_path,
+                "test",
+                &plist_path,
+                Some("changed"),
+                &backup_dir
+            )?
+        );
+        let plist_dict = |plist_path| -> Result<Dictionary> {
+            Ok(Value::from_file(plist_path)?
+                .into_dictionary()
+                .unwrap_or_default())
+        };
+        let dict = plist_dict(&plist_path)?;
+        ensure_eq!(Some(&Value::from("old")), dict.get("changed"));
+        ensure_eq!(Some(&Value::from(true)), dict.get("added"));
+
+        // Unmanaging the rest deletes keys that didn't exist, and leaves unmanaged keys alone.
+        ensure_eq!(
+            1,
+            unmanage(&manifest_path, "test", &plist_path, None, &backup_dir)?
+        );
+        let dict = plist_dict(&plist_path)?;
+        ensure_eq!(None, dict.get("added"));
+        ensure_eq!(Some(&Value::from(1)), dict.get("kept"));
+        ensure_eq!(Manifest::default(), Manifest::read(&manifest_path)?);
+
+        // Nothing is left to unmanage.
+        let result = unmanage(&manifest_path, "test", &plist_path, None, &backup_dir);
+        ensure_eq!(true, matches!(result, Err(E::NotManaged { .. })));
+
+        // Original values are restored even if the plist file has since been deleted.
+        record(
+            &manifest_path,
+            &plist_path,
+            vec![
+                ("changed".to_owned(), Some(&old_value)),
+                ("added".to_owned(), None),
+            ],
+        )?;
+        std::fs::remove_file(&plist_path)?;
+        ensure_eq!(
+            2,
+            unmanage(&manifest_path, "test", &plist_path, None, &backup_dir)?
+        );
+        let dict = plist_dict(&plist_path)?;
+        ensure_eq!(Some(&Value::from("old")), dict.get("changed"));
+        ensure_eq!(None, dict.get("added"));
+        ensure_eq!(Manifest::default(), Manifest::read(&manifest_path)?);
+        Ok(())
+    }
+}
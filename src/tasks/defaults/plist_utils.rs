@@ -1,25 +1,42 @@
 //! Utility functions for updating plist files.
 use crate::cmd;
+use crate::errors::UpError;
 use crate::exec::UpDuct;
+use crate::tasks::defaults::diff;
+use crate::tasks::defaults::managed;
 use crate::tasks::defaults::DefaultsError as E;
+use crate::utils::ellipsis::replace_ellipsis_array;
+use crate::utils::ellipsis::replace_ellipsis_dict;
 use crate::utils::files;
+use crate::utils::files::AtomicWriteOptions;
 use crate::utils::mac;
+use camino::Utf8DirEntry;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use duct::Expression;
-use itertools::Itertools;
 use plist::Dictionary;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
+use std::io;
+use std::io::ErrorKind;
 use std::io::Read;
 use tracing::debug;
 use tracing::info;
 use tracing::trace;
 use tracing::warn;
 
-/// A value or key-value pair that means "insert existing values here" for arrays and dictionaries.
-const ELLIPSIS: &str = "...";
+/// A preference domain and the prefs to set in it.
+pub(super) type DomainPrefs = (String, HashMap<String, plist::Value>);
+
+/// A default that was changed, as its domain and key.
+pub(super) type ChangedDefault = (String, String);
+
+/// Domain that means the plist should be read from stdin (and, when writing, written to stdout).
+pub(super) const STDIN_DOMAIN: &str = "-";
+/// The first bytes of a binary plist file.
+const BINARY_PLIST_MAGIC: &[u8; 8] = b"bplist00";
 
 /**
 Get the path to the plist file given a domain.
@@ -60,7 +77,7 @@ pub(super) fn plist_path(domain: &str, current_host: bool) -> Result<Utf8PathBuf
     }
 
     // User wants to read from stdin, use that directly.
-    if domain == "-" {
+    if domain == STDIN_DOMAIN {
         return Ok(Utf8PathBuf::from(domain));
     }
 
@@ -95,6 +112,68 @@ pub(super) fn plist_path(domain: &str, current_host: bool) -> Result<Utf8PathBuf
     Ok(plist_path)
 }
 
+/**
+List the preference domains with a plist file in `home_dir`, using the same rules as
+[`plist_path`] (so only sandboxed container plists whose name matches their container are
+included).
+
+Domains are sorted, and `.GlobalPreferences` is listed as `NSGlobalDomain`.
+*/
+pub(super) fn domains(home_dir: &Utf8Path, current_host: bool) -> Result<Vec<String>, E> {
+    // Plist filenames are `{domain}{suffix}`.
+    let suffix = plist_filename("", current_host)?;
+    let mut domains = BTreeSet::new();
+
+    let mut prefs_dir = home_dir.to_owned();
+    extend_with_prefs_folders(current_host, &mut prefs_dir, "");
+    for entry in read_dir_if_exists(&prefs_dir)? {
+        let entry = entry.map_err(|e| E::DirRead {
+            path: prefs_dir.clone(),
+            source: e,
+        })?;
+        match entry.file_name().strip_suffix(&suffix) {
+            Some(".GlobalPreferences") => domains.insert("NSGlobalDomain".to_owned()),
+            Some(domain) if !domain.is_empty() => domains.insert(domain.to_owned()),
+            _ => false,
+        };
+    }
+
+    let containers_dir = home_dir.join("Library/Containers");
+    for entry in read_dir_if_exists(&containers_dir)? {
+        let entry = entry.map_err(|e| E::DirRead {
+            path: containers_dir.clone(),
+            source: e,
+        })?;
+        let domain = entry.file_name();
+        let mut sandboxed_plist_path = entry.path().join("Data");
+        extend_with_prefs_folders(
+            current_host,
+            &mut sandboxed_plist_path,
+            &format!("{domain}{suffix}"),
+        );
+        if sandboxed_plist_path.exists() {
+            domains.insert(domain.to_owned());
+        }
+    }
+
+    Ok(domains.into_iter().collect())
+}
+
+/// Read the entries of a directory, returning no entries if it doesn't exist.
+fn read_dir_if_exists(dir: &Utf8Path) -> Result<Vec<std::io::Result<Utf8DirEntry>>, E> {
+    match dir.read_dir_utf8() {
+        Ok(entries) => Ok(entries.collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            debug!("Directory {dir} doesn't exist, so contains no domains.");
+            Ok(Vec::new())
+        }
+        Err(e) => Err(E::DirRead {
+            path: dir.to_owned(),
+            source: e,
+        }),
+    }
+}
+
 /// Take a directory path, and add on the directories and files containing the application's
 /// preferences. Normally this is `./Library/Preferences/{domain}.plist`, but if `current_host` is
 /// `true`, then we need to look in the `ByHost` subfolder.
@@ -150,43 +229,169 @@ pub(super) fn is_binary(file: &Utf8Path) -> Result<bool, E> {
         source: e,
     })?;
 
-    Ok(&magic == b"bplist00")
+    Ok(&magic == BINARY_PLIST_MAGIC)
+}
+
+/**
+Read a plist (binary or XML) from stdin, returning it and whether it was binary.
+
+Empty input is treated as an empty (XML) plist dictionary.
+*/
+pub(super) fn read_stdin_plist() -> Result<(plist::Value, bool), E> {
+    let mut bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut bytes)
+        .map_err(|e| E::FileRead {
+            path: Utf8PathBuf::from(STDIN_DOMAIN),
+            source: e,
+        })?;
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        debug!("Stdin was empty, using an empty plist.");
+        return Ok((plist::Value::Dictionary(Dictionary::new()), false));
+    }
+    let value = plist::from_bytes(&bytes).map_err(|e| E::PlistRead {
+        path: Utf8PathBuf::from(STDIN_DOMAIN),
+        source: e,
+    })?;
+    Ok((value, bytes.starts_with(BINARY_PLIST_MAGIC)))
 }
 
-/// Write a `HashMap` of key-value pairs to a plist file.
+/**
+Write a `HashMap` of key-value pairs to a plist file, returning the keys changed.
+
+If the domain is `-`, the plist is read from stdin and the updated plist is written to stdout (in
+the same format, binary or XML), even if nothing changed.
+*/
 pub(super) fn write_defaults_values(
     domain: &str,
     prefs: HashMap<String, plist::Value>,
     current_host: bool,
-    up_dir: &Utf8Path,
-) -> Result<bool, E> {
-    let backup_dir = up_dir.join("backup/defaults");
+    backup_dir: &Utf8Path,
+) -> Result<Vec<ChangedDefault>, E> {
+    if domain == STDIN_DOMAIN {
+        let (mut plist_value, binary) = read_stdin_plist()?;
+        let values_changed = update_plist_values(domain, &mut plist_value, prefs)?;
+        let stdout = io::stdout().lock();
+        if binary {
+            plist::to_writer_binary(stdout, &plist_value)
+        } else {
+            plist::to_writer_xml(stdout, &plist_value)
+        }
+        .map_err(|e| E::PlistWrite {
+            path: Utf8PathBuf::from("/dev/stdout"),
+            source: e,
+        })?;
+        if !binary {
+            println!();
+        }
+        return Ok(values_changed);
+    }
 
     let plist_path = plist_path(domain, current_host)?;
+    write_plist_file_values(&plist_path, vec![(domain.to_owned(), prefs)], backup_dir)
+}
+
+/**
+Write the prefs for each of `domains` (which must all resolve to `plist_path`) to the plist file,
+reading and writing the file only once. Returns the keys changed.
+
+Domains are applied in order, so if two domains set the same key the last one wins.
+*/
+pub(super) fn write_plist_file_values(
+    plist_path: &Utf8Path,
+    domains: Vec<DomainPrefs>,
+    backup_dir: &Utf8Path,
+) -> Result<Vec<ChangedDefault>, E> {
+    let backup_dir = backup_dir.join("defaults");
     debug!("Plist path: {plist_path}");
 
     let plist_path_exists = plist_path.exists();
+    let mut plist_value = read_plist_or_empty(plist_path)?;
+
+    // Values before this write, to record in the managed keys manifest.
+    let original_value = managed::manifest_path().map(|_| plist_value.clone());
+    let mut values_changed = Vec::new();
+    for (domain, prefs) in domains {
+        values_changed.extend(update_plist_values(&domain, &mut plist_value, prefs)?);
+    }
+    if values_changed.is_empty() {
+        return Ok(values_changed);
+    }
 
-    let mut plist_value: plist::Value = if plist_path_exists {
-        plist::from_file(&plist_path).map_err(|e| E::PlistRead {
-            path: plist_path.clone(),
+    if !plist_path_exists {
+        warn!("Defaults plist doesn't exist, creating it: {plist_path}");
+        let plist_dirpath = plist_path.parent().ok_or(E::UnexpectedNone)?;
+        fs::create_dir_all(plist_dirpath).map_err(|e| E::DirCreation {
+            path: plist_dirpath.to_owned(),
             source: e,
-        })?
+        })?;
+    }
+
+    write_plist(plist_path_exists, plist_path, &plist_value, &backup_dir)?;
+    trace!("Plist updated at {plist_path}");
+
+    if let (Some(manifest_path), Some(original_value)) = (managed::manifest_path(), original_value)
+    {
+        let original_dict = original_value.as_dictionary();
+        let keys = values_changed
+            .iter()
+            .map(|(_, key)| (key.clone(), original_dict.and_then(|dict| dict.get(key))))
+            .collect();
+        // The plist has already been written, so don't fail the write if recording fails.
+        if let Err(e) = managed::record(manifest_path, plist_path, keys) {
+            warn!("Failed to record the defaults written to {plist_path}: {e}");
+        }
+    }
+
+    Ok(values_changed)
+}
+
+/**
+The plist at `plist_path` (or stdin if the path is `-`) with the `prefs` for `domain` merged in,
+without writing anything. Returns the merged plist and the keys changed.
+*/
+pub(super) fn merged_plist(
+    domain: &str,
+    plist_path: &Utf8Path,
+    prefs: HashMap<String, plist::Value>,
+) -> Result<(plist::Value, Vec<ChangedDefault>), E> {
+    let mut plist_value = if plist_path == STDIN_DOMAIN {
+        read_stdin_plist()?.0
     } else {
-        plist::Value::Dictionary(Dictionary::new())
+        read_plist_or_empty(plist_path)?
     };
+    let values_changed = update_plist_values(domain, &mut plist_value, prefs)?;
+    Ok((plist_value, values_changed))
+}
+
+/// Read the plist file at `plist_path`, or an empty plist dictionary if it doesn't exist.
+pub(super) fn read_plist_or_empty(plist_path: &Utf8Path) -> Result<plist::Value, E> {
+    if !plist_path.exists() {
+        return Ok(plist::Value::Dictionary(Dictionary::new()));
+    }
+    plist::from_file(plist_path).map_err(|e| E::PlistRead {
+        path: plist_path.to_owned(),
+        source: e,
+    })
+}
 
+/// Update the `prefs` in `plist_value` (a plist from `domain`), returning the keys changed.
+fn update_plist_values(
+    domain: &str,
+    plist_value: &mut plist::Value,
+    prefs: HashMap<String, plist::Value>,
+) -> Result<Vec<ChangedDefault>, E> {
     trace!("Plist: {plist_value:?}");
 
-    // Whether we changed anything.
-    let mut values_changed = false;
+    // Keys we changed.
+    let mut values_changed = Vec::new();
     for (key, mut new_value) in prefs {
         let old_value = plist_value
             .as_dictionary()
             .ok_or_else(|| E::NotADictionary {
                 domain: domain.to_owned(),
                 key: key.clone(),
-                plist_type: get_plist_value_type(&plist_value),
+                plist_type: get_plist_value_type(plist_value),
             })?
             .get(&key);
         debug!(
@@ -205,11 +410,10 @@ pub(super) fn write_defaults_values(
             }
         }
 
-        values_changed = true;
-
-        info!("Changing default {domain} {key}: {old_value:?} -> {new_value:?}",);
+        log_change(domain, &key, old_value, &new_value);
+        values_changed.push((domain.to_owned(), key.clone()));
 
-        let plist_type = get_plist_value_type(&plist_value);
+        let plist_type = get_plist_value_type(plist_value);
         trace!("Plist type: {plist_type:?}");
 
         plist_value
@@ -222,85 +426,73 @@ pub(super) fn write_defaults_values(
             .insert(key, new_value);
     }
 
-    if !values_changed {
-        return Ok(values_changed);
-    }
+    Ok(values_changed)
+}
 
-    if plist_path_exists {
-        let backup_plist_path =
-            backup_dir.join(
-                plist_path
-                    .file_name()
-                    .ok_or_else(|| E::UnexpectedPlistPath {
-                        path: plist_path.clone(),
-                    })?,
+/**
+Log a default being changed. Nested values are logged as the leaf values that changed, with the
+full values only logged at trace level.
+*/
+fn log_change(domain: &str, key: &str, old_value: Option<&plist::Value>, new_value: &plist::Value) {
+    match old_value {
+        Some(old_value) if diff::is_nested(old_value) || diff::is_nested(new_value) => {
+            let changes = diff::diff(key, old_value, new_value);
+            info!(
+                "Changing default {domain} {key}, {count} changes:{changes}",
+                count = changes.len(),
+                changes = diff::format_changes(&changes),
             );
-
-        trace!("Backing up plist file {plist_path} -> {backup_plist_path}",);
-        fs::create_dir_all(&backup_dir).map_err(|e| E::DirCreation {
-            path: backup_dir.clone(),
-            source: e,
-        })?;
-        fs::copy(&plist_path, &backup_plist_path).map_err(|e| E::FileCopy {
-            from_path: plist_path.clone(),
-            to_path: backup_plist_path.clone(),
-            source: e,
-        })?;
-    } else {
-        warn!("Defaults plist doesn't exist, creating it: {plist_path}");
-        let plist_dirpath = plist_path.parent().ok_or(E::UnexpectedNone)?;
-        fs::create_dir_all(plist_dirpath).map_err(|e| E::DirCreation {
-            path: plist_dirpath.to_owned(),
-            source: e,
-        })?;
+            trace!("Full values for {domain} {key}: {old_value:?} -> {new_value:?}");
+        }
+        _ if diff::is_nested(new_value) => {
+            info!("Setting default {domain} {key} to a new nested value.");
+            trace!("Full value for {domain} {key}: {new_value:?}");
+        }
+        _ => info!("Changing default {domain} {key}: {old_value:?} -> {new_value:?}"),
     }
-
-    write_plist(plist_path_exists, &plist_path, plist_value)?;
-    trace!("Plist updated at {plist_path}");
-
-    Ok(values_changed)
 }
 
-/// Write a plist file to a path. Will fall back to trying to use sudo if a normal write fails.
-fn write_plist(
+/**
+Write a plist file to a path atomically, backing up any existing file to `backup_dir` first. Will
+fall back to trying to use sudo if a normal write fails.
+*/
+pub(super) fn write_plist(
     plist_path_exists: bool,
     plist_path: &Utf8Path,
-    plist_value: plist::Value,
+    plist_value: &plist::Value,
+    backup_dir: &Utf8Path,
 ) -> Result<(), E> {
     let should_write_binary = !plist_path_exists || is_binary(plist_path)?;
-    let write_result = if should_write_binary {
+    let mut plist_bytes = Vec::new();
+    if should_write_binary {
         trace!("Writing binary plist");
-        plist::to_file_binary(plist_path, &plist_value)
+        plist::to_writer_binary(&mut plist_bytes, plist_value)
     } else {
         trace!("Writing xml plist");
-        plist::to_file_xml(plist_path, &plist_value)
-    };
-    let Err(plist_error) = write_result else {
-        return Ok(());
-    };
+        plist::to_writer_xml(&mut plist_bytes, plist_value)
+    }
+    .map_err(|e| E::PlistWrite {
+        path: plist_path.to_owned(),
+        source: e,
+    })?;
 
-    let io_error = match plist_error.into_io() {
-        Ok(io_error) => io_error,
-        Err(plist_error) => {
-            return Err(E::PlistWrite {
-                path: plist_path.to_path_buf(),
-                source: plist_error,
+    let opts = AtomicWriteOptions {
+        fsync: true,
+        backup_dir: Some(backup_dir),
+        mode: None,
+    };
+    let io_error = match files::atomic_write(plist_path, &plist_bytes, opts) {
+        Ok(_) => return Ok(()),
+        Err(UpError::IoError { source, .. }) => source,
+        Err(e) => {
+            return Err(E::PlistFileWrite {
+                path: plist_path.to_owned(),
+                source: e,
             })
         }
     };
     trace!("Tried to write plist file, got IO error {io_error:?}, trying again with sudo");
 
-    let mut plist_bytes = Vec::new();
-    if should_write_binary {
-        plist::to_writer_binary(&mut plist_bytes, &plist_value)
-    } else {
-        plist::to_writer_xml(&mut plist_bytes, &plist_value)
-    }
-    .map_err(|e| E::PlistWrite {
-        path: Utf8Path::new("/dev/stdout").to_path_buf(),
-        source: e,
-    })?;
-
     cmd!("sudo", "tee", plist_path)
         .stdin_bytes(plist_bytes)
         .run_with(Expression::stdout_null)
@@ -312,95 +504,60 @@ fn write_plist(
     Ok(())
 }
 
-/**
-Replace `...` values in an input array.
-Does nothing if not an array.
-You end up with: [`<new values before ...>`, `<old values>`, `<new values after ...>`]
-But any duplicates between old and new values are removed, with the first value taking
-precedence.
-*/
-fn replace_ellipsis_array(new_value: &mut plist::Value, old_value: Option<&plist::Value>) {
-    let Some(array) = new_value.as_array_mut() else {
-        trace!("Value isn't an array, skipping ellipsis replacement...");
-        return;
-    };
-    let ellipsis = plist::Value::String("...".to_owned());
-    let Some(position) = array.iter().position(|x| x == &ellipsis) else {
-        trace!("New value doesn't contain ellipsis, skipping ellipsis replacement...");
-        return;
-    };
-
-    let Some(old_array) = old_value.and_then(plist::Value::as_array) else {
-        trace!("Old value wasn't an array, skipping ellipsis replacement...");
-        array.remove(position);
-        return;
-    };
-
-    let array_copy: Vec<_> = std::mem::take(array);
-
-    trace!("Performing array ellipsis replacement...");
-    for element in array_copy {
-        if element == ellipsis {
-            for old_element in old_array {
-                if array.contains(old_element) {
-                    continue;
-                }
-                array.push(old_element.clone());
-            }
-        } else if !array.contains(&element) {
-            array.push(element);
-        }
-    }
-}
-
-/// Replace `...` keys in an input dict.
-/// Does nothing if not a dictionary.
-/// You end up with: [`<new contents before ...>`, `<old contents>`, `<new contents after ...>`]
-/// But any duplicates between old and new values are removed, with the first value taking
-/// precedence.
-fn replace_ellipsis_dict(new_value: &mut plist::Value, old_value: Option<&plist::Value>) {
-    let Some(dict) = new_value.as_dictionary_mut() else {
-        trace!("Value isn't a dict, skipping ellipsis replacement...");
-        return;
-    };
-
-    if !dict.contains_key(ELLIPSIS) {
-        trace!("New value doesn't contain ellipsis, skipping ellipsis replacement...");
-        return;
-    }
-
-    let before = dict
-        .keys()
-        .take_while(|x| x != &ELLIPSIS)
-        .cloned()
-        .collect_vec();
-    dict.remove(ELLIPSIS);
-
-    let Some(old_dict) = old_value.and_then(plist::Value::as_dictionary) else {
-        trace!("Old value wasn't a dict, skipping ellipsis replacement...");
-        return;
-    };
-
-    trace!("Performing dict ellipsis replacement...");
-    for (key, value) in old_dict {
-        if !before.contains(key) {
-            dict.insert(key.clone(), value.clone());
-        }
-    }
-}
-
-#[cfg(target_os = "macos")]
 #[cfg(test)]
 mod tests {
-    use crate::utils::mac;
-    use camino::Utf8PathBuf;
     use color_eyre::Result;
-    use serial_test::serial;
+    use std::collections::HashMap;
+    use std::fs;
     use testutils::ensure_eq;
 
     #[test]
-    #[serial(home_dir)] // Test relies on or changes the $HOME env var.
+    fn test_domains() -> Result<()> {
+        let home_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let prefs_dir = home_dir.join("Library/Preferences");
+        fs::create_dir_all(prefs_dir.join("ByHost"))?;
+        for file in [
+            ".GlobalPreferences.plist",
+            "com.apple.dock.plist",
+            "notes.txt",
+        ] {
+            fs::write(prefs_dir.join(file), "")?;
+        }
+        let container_prefs_dir = |domain| {
+            home_dir.join(format!(
+                "Library/Containers/{domain}/Data/Library/Preferences"
+            ))
+        };
+        fs::create_dir_all(container_prefs_dir("com.apple.Safari"))?;
+        fs::write(
+            container_prefs_dir("com.apple.Safari").join("com.apple.Safari.plist"),
+            "",
+        )?;
+        // Not read by `defaults read`, as the plist name doesn't match the container.
+        fs::create_dir_all(container_prefs_dir("com.apple.Notes"))?;
+        fs::write(
+            container_prefs_dir("com.apple.Notes").join("com.apple.other.plist"),
+            "",
+        )?;
+
+        ensure_eq!(
+            vec![
+                "NSGlobalDomain".to_owned(),
+                "com.apple.Safari".to_owned(),
+                "com.apple.dock".to_owned(),
+            ],
+            super::domains(&home_dir, false)?
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    #[serial_test::serial(home_dir)] // Test relies on or changes the $HOME env var.
     fn test_plist_path() -> Result<()> {
+        use crate::utils::mac;
+        use camino::Utf8PathBuf;
+
         let home_dir = Utf8PathBuf::try_from(dirs::home_dir().unwrap()).unwrap();
 
         {
@@ -464,4 +621,58 @@ mod tests {
 
         Ok(())
     }
+
+    /// Domains that map to the same plist file are merged into a single write.
+    #[test]
+    fn test_write_plist_file_values_batched() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let plist_path = temp_dir.join(".GlobalPreferences.plist");
+        let backup_dir = temp_dir.join("backup");
+        let prefs = |yaml| serde_yaml::from_str::<HashMap<String, plist::Value>>(yaml);
+
+        let changed = super::write_plist_file_values(
+            &plist_path,
+            vec![
+                ("NSGlobalDomain".to_owned(), prefs("{a: 1, shared: first}")?),
+                (plist_path.to_string(), prefs("{b: true, shared: second}")?),
+            ],
+            &backup_dir,
+        )?;
+        // `shared` is counted twice, as both domains change it.
+        ensure_eq!(4, changed.len());
+        let expected: plist::Value = serde_yaml::from_str("{a: 1, b: true, shared: second}")?;
+        ensure_eq!(expected, plist::from_file::<_, plist::Value>(&plist_path)?);
+
+        // Writing the same values again changes nothing.
+        let changed = super::write_plist_file_values(
+            &plist_path,
+            vec![("NSGlobalDomain".to_owned(), prefs("{a: 1, b: true}")?)],
+            &backup_dir,
+        )?;
+        ensure_eq!(0, changed.len());
+        Ok(())
+    }
+
+    /// Merging leaves the plist file unchanged, and `...` keeps the existing array items.
+    #[test]
+    fn test_merged_plist() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let plist_path = temp_dir.join("app.plist");
+        let original: plist::Value = serde_yaml::from_str("{list: [b, c], kept: true}")?;
+        plist::to_file_xml(&plist_path, &original)?;
+
+        let prefs = serde_yaml::from_str("{list: [a, '...', d], added: 1}")?;
+        let (merged, changed) = super::merged_plist("app", &plist_path, prefs)?;
+        ensure_eq!(2, changed.len());
+        let expected: plist::Value =
+            serde_yaml::from_str("{list: [a, b, c, d], kept: true, added: 1}")?;
+        ensure_eq!(expected, merged);
+        ensure_eq!(original, plist::from_file::<_, plist::Value>(&plist_path)?);
+
+        // A missing plist file is treated as empty.
+        let prefs = serde_yaml::from_str("{added: 1}")?;
+        let (merged, _) = super::merged_plist("app", &temp_dir.join("missing.plist"), prefs)?;
+        ensure_eq!(serde_yaml::from_str::<plist::Value>("{added: 1}")?, merged);
+        Ok(())
+    }
 }
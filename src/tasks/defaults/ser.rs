@@ -1,8 +1,185 @@
-//! Helper to allow serializing plists containing binary data to yaml.
+/*!
+Helpers for serializing plists to the formats `up defaults read` supports, and for converting
+between plist and YAML values.
 
+YAML has no date or binary data types, so these are written with tags:
+
+```yaml
+LastRun: !date 2024-01-01T00:00:00Z
+Salt: !data base64:ERERESIiIiI=
+```
+*/
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
 use color_eyre::Result;
+use plist::Dictionary;
 use plist::Value;
+use serde::Deserialize as _;
+use serde::Deserializer;
+use serde::Serialize as _;
+use serde::Serializer;
+use serde_yaml::value::Tag;
+use serde_yaml::value::TaggedValue;
+use std::collections::HashMap;
+use std::fmt::Write;
 use std::mem;
+use std::time::SystemTime;
+
+/// Indentation used for each level of nesting in the `defaults read` format.
+const DEFAULTS_INDENT: &str = "    ";
+
+/// YAML tag for plist dates.
+const DATE_TAG: &str = "date";
+
+/// YAML tag for plist binary data.
+const DATA_TAG: &str = "data";
+
+/// Prefix of the value of a `!data` tag.
+const BASE64_PREFIX: &str = "base64:";
+
+/// Domains mapped to the keys and values to set in them.
+type DomainsPrefs = HashMap<String, HashMap<String, Value>>;
+
+/// Convert a plist value to YAML, using tags for dates and binary data.
+pub(super) fn to_yaml(value: &Value) -> serde_yaml::Value {
+    match value {
+        Value::Array(arr) => serde_yaml::Value::Sequence(arr.iter().map(to_yaml).collect()),
+        Value::Dictionary(dict) => serde_yaml::Value::Mapping(
+            dict.iter()
+                .map(|(key, value)| (serde_yaml::Value::from(key.as_str()), to_yaml(value)))
+                .collect(),
+        ),
+        Value::Boolean(b) => serde_yaml::Value::Bool(*b),
+        Value::Integer(i) => match (i.as_signed(), i.as_unsigned()) {
+            (Some(i), _) => serde_yaml::Value::from(i),
+            (None, Some(u)) => serde_yaml::Value::from(u),
+            (None, None) => serde_yaml::Value::from(i.to_string()),
+        },
+        Value::Real(r) => serde_yaml::Value::from(*r),
+        Value::String(s) => serde_yaml::Value::from(s.as_str()),
+        Value::Date(date) => {
+            let date: DateTime<Utc> = SystemTime::from(*date).into();
+            tagged(DATE_TAG, date.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        }
+        Value::Data(bytes) => tagged(DATA_TAG, format!("{BASE64_PREFIX}{}", BASE64.encode(bytes))),
+        Value::Uid(uid) => serde_yaml::Value::from(uid.get()),
+        // Value is non-exhaustive, fall back to the debug output for new types.
+        other => serde_yaml::Value::from(format!("{other:?}")),
+    }
+}
+
+/// A YAML string value with a tag.
+fn tagged(tag: &str, value: String) -> serde_yaml::Value {
+    serde_yaml::Value::Tagged(Box::new(TaggedValue {
+        tag: Tag::new(tag),
+        value: serde_yaml::Value::String(value),
+    }))
+}
+
+/// Convert a YAML value to a plist value, parsing `!date` and `!data` tags.
+pub(super) fn from_yaml<E: serde::de::Error>(value: serde_yaml::Value) -> Result<Value, E> {
+    Ok(match value {
+        serde_yaml::Value::Null => return Err(E::custom("null can't be stored in a plist")),
+        serde_yaml::Value::Bool(b) => Value::Boolean(b),
+        serde_yaml::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::Integer(i.into())
+            } else if let Some(u) = n.as_u64() {
+                Value::Integer(u.into())
+            } else {
+                Value::Real(n.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(seq) => {
+            Value::Array(seq.into_iter().map(from_yaml).collect::<Result<_, E>>()?)
+        }
+        serde_yaml::Value::Mapping(map) => {
+            let mut dict = Dictionary::new();
+            for (key, value) in map {
+                let serde_yaml::Value::String(key) = key else {
+                    return Err(E::custom(format!(
+                        "plist dictionary keys must be strings, found {key:?}"
+                    )));
+                };
+                dict.insert(key, from_yaml(value)?);
+            }
+            Value::Dictionary(dict)
+        }
+        serde_yaml::Value::Tagged(tagged) => {
+            let TaggedValue { tag, value } = *tagged;
+            let serde_yaml::Value::String(s) = value else {
+                return Err(E::custom(format!("expected a string after the {tag} tag")));
+            };
+            if tag == DATE_TAG {
+                let date = DateTime::parse_from_rfc3339(&s).map_err(|e| {
+                    E::custom(format!(
+                        "invalid {tag} '{s}', expected e.g. '2024-01-01T00:00:00Z': {e}"
+                    ))
+                })?;
+                Value::Date(SystemTime::from(date).into())
+            } else if tag == DATA_TAG {
+                let bytes = s
+                    .strip_prefix(BASE64_PREFIX)
+                    .ok_or_else(|| {
+                        E::custom(format!("expected {tag} to start with '{BASE64_PREFIX}'"))
+                    })
+                    .and_then(|encoded| {
+                        BASE64
+                            .decode(encoded.trim())
+                            .map_err(|e| E::custom(format!("invalid base64 in {tag}: {e}")))
+                    })?;
+                Value::Data(bytes)
+            } else {
+                return Err(E::custom(format!(
+                    "unknown tag {tag}, expected !{DATE_TAG} or !{DATA_TAG}"
+                )));
+            }
+        }
+    })
+}
+
+/// Deserialize domains of plist values, parsing `!date` and `!data` tags.
+pub(super) fn deserialize_domains<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DomainsPrefs, D::Error> {
+    HashMap::<String, HashMap<String, serde_yaml::Value>>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(domain, prefs)| Ok((domain, prefs_from_yaml(prefs)?)))
+        .collect()
+}
+
+/// Serialize domains of plist values, using tags for dates and binary data.
+pub(super) fn serialize_domains<S: Serializer>(
+    domains: &DomainsPrefs,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    domains
+        .iter()
+        .map(|(domain, prefs)| {
+            let prefs: HashMap<&String, serde_yaml::Value> = prefs
+                .iter()
+                .map(|(key, value)| (key, to_yaml(value)))
+                .collect();
+            (domain, prefs)
+        })
+        .collect::<HashMap<_, _>>()
+        .serialize(serializer)
+}
+
+/// Convert keys and YAML values to keys and plist values.
+pub(super) fn prefs_from_yaml<E: serde::de::Error>(
+    prefs: HashMap<String, serde_yaml::Value>,
+) -> Result<HashMap<String, Value>, E> {
+    prefs
+        .into_iter()
+        .map(|(key, value)| Ok((key, from_yaml(value)?)))
+        .collect()
+}
 
 /// Replace binary data attributes to work around <https://github.com/dtolnay/serde-yaml/issues/91>.
 pub(super) fn replace_data_in_plist(value: &mut Value) -> Result<()> {
@@ -29,9 +206,105 @@ pub(super) fn replace_data_in_plist(value: &mut Value) -> Result<()> {
     Ok(())
 }
 
+/**
+Serialize a plist value in the (old-style `NeXTSTEP`) format that the macOS `defaults read` command
+prints, e.g.
+
+```text
+{
+    "com.apple.swipescrolldirection" = 0;
+    AppleLanguages =     (
+        "en-GB"
+    );
+}
+```
+
+Binary data is printed as a hex-encoded string, as in the other formats.
+*/
+pub(super) fn to_defaults_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_defaults_value(&mut out, value, 0);
+    out.push('\n');
+    out
+}
+
+/// Write `value` to `out`, with nested lines indented by `depth + 1` levels.
+fn write_defaults_value(out: &mut String, value: &Value, depth: usize) {
+    let indent = DEFAULTS_INDENT.repeat(depth);
+    let inner_indent = DEFAULTS_INDENT.repeat(depth + 1);
+    match value {
+        Value::Dictionary(dict) => {
+            out.push_str("{\n");
+            for (key, value) in dict {
+                out.push_str(&inner_indent);
+                out.push_str(&defaults_quote(key));
+                out.push_str(" = ");
+                if matches!(value, Value::Dictionary(_) | Value::Array(_)) {
+                    // `defaults read` pads nested containers like this.
+                    out.push_str(DEFAULTS_INDENT);
+                }
+                write_defaults_value(out, value, depth + 1);
+                out.push_str(";\n");
+            }
+            out.push_str(&indent);
+            out.push('}');
+        }
+        Value::Array(arr) => {
+            out.push_str("(\n");
+            for (i, value) in arr.iter().enumerate() {
+                out.push_str(&inner_indent);
+                write_defaults_value(out, value, depth + 1);
+                if i + 1 < arr.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            out.push_str(&indent);
+            out.push(')');
+        }
+        Value::Boolean(b) => out.push_str(if *b { "1" } else { "0" }),
+        Value::Integer(i) => _ = write!(out, "{i}"),
+        Value::Real(r) => _ = write!(out, "{r}"),
+        Value::Date(date) => {
+            let date: DateTime<Utc> = SystemTime::from(*date).into();
+            out.push_str(&defaults_quote(
+                &date.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+            ));
+        }
+        Value::Data(bytes) => out.push_str(&hex::encode(bytes)),
+        Value::String(s) => out.push_str(&defaults_quote(s)),
+        Value::Uid(uid) => _ = write!(out, "{}", uid.get()),
+        // Value is non-exhaustive, fall back to the debug output for new types.
+        other => _ = write!(out, "{other:?}"),
+    }
+}
+
+/// Quote a string the way `defaults read` does, leaving it unquoted if it's a simple identifier.
+fn defaults_quote(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return s.to_owned();
+    }
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::tasks::defaults::ser::from_yaml;
     use crate::tasks::defaults::ser::replace_data_in_plist;
+    use crate::tasks::defaults::ser::to_defaults_string;
+    use crate::tasks::defaults::ser::to_yaml;
+    use color_eyre::eyre::ensure;
     use color_eyre::Result;
     use test_log::test;
     use testutils::ensure_eq;
@@ -55,4 +328,65 @@ mod tests {
         ensure_eq!(expected_yaml, yaml_string);
         Ok(())
     }
+
+    #[test]
+    fn test_yaml_tags() -> Result<()> {
+        let yaml = "date: !date 2024-01-01T00:00:00Z\ndata: !data base64:ERERESIi\nlist:\n- 1\n- \
+                    !date 2024-06-30T12:34:56.500Z\n";
+        let value = from_yaml::<serde_yaml::Error>(serde_yaml::from_str(yaml)?)?;
+        let dict = value
+            .as_dictionary()
+            .ok_or_else(|| color_eyre::eyre::eyre!("Expected a dictionary."))?;
+        ensure_eq!(
+            Some(&plist::Value::Data(vec![
+                0x11, 0x11, 0x11, 0x11, 0x22, 0x22
+            ])),
+            dict.get("data")
+        );
+        ensure_eq!(
+            Some(&plist::Value::Date(plist::Date::from_xml_format(
+                "2024-01-01T00:00:00Z"
+            )?)),
+            dict.get("date")
+        );
+        // Converting back to YAML gives the same tagged values.
+        ensure_eq!(yaml, serde_yaml::to_string(&to_yaml(&value))?);
+
+        ensure!(from_yaml::<serde_yaml::Error>(serde_yaml::from_str("!data ERERESIi")?).is_err());
+        ensure!(from_yaml::<serde_yaml::Error>(serde_yaml::from_str("!date yesterday")?).is_err());
+        ensure!(from_yaml::<serde_yaml::Error>(serde_yaml::from_str("!other x")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_defaults_string() -> Result<()> {
+        let value: plist::Value = serde_yaml::from_str(
+            r#"
+            com.apple.swipescrolldirection: false
+            AppleLanguages: [en-GB, en]
+            NSUserDictionaryReplacementItems:
+              - replace: omw
+                with: "On my \"way\"!"
+            Count: 3
+            "#,
+        )?;
+        let expected = r#"{
+    "com.apple.swipescrolldirection" = 0;
+    AppleLanguages =     (
+        "en-GB",
+        en
+    );
+    NSUserDictionaryReplacementItems =     (
+        {
+            replace = omw;
+            with = "On my \"way\"!";
+        }
+    );
+    Count = 3;
+}
+"#;
+        ensure_eq!(expected, to_defaults_string(&value));
+        ensure_eq!("\"\"\n", to_defaults_string(&plist::Value::from("")));
+        Ok(())
+    }
 }
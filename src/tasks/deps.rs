@@ -0,0 +1,159 @@
+//! Resolve the dependencies between tasks (set with the `requires` task config field).
+use crate::tasks::task::Task;
+use color_eyre::eyre::bail;
+use color_eyre::eyre::Result;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use tracing::debug;
+use tracing::trace;
+
+/// The tasks that a task requires to have been run beforehand, ignoring any that aren't in
+/// `tasks` (e.g. because they were filtered out).
+fn requires<'a>(task: &'a Task, tasks: &'a HashMap<String, Task>) -> impl Iterator<Item = &'a str> {
+    task.config
+        .requires
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .filter(move |required| {
+            let found = tasks.contains_key(*required);
+            if !found {
+                debug!(
+                    "Ignoring requirement '{required}' of task '{name}' as it isn't in the set of \
+                     tasks to run.",
+                    name = task.name
+                );
+            }
+            found
+        })
+}
+
+/**
+Make every task that isn't a bootstrap task (`bootstrap: true`) require all the bootstrap tasks,
+so the bootstrap tasks are the roots of the dependency graph.
+
+Returns the names of the bootstrap tasks. Errors if a bootstrap task requires a task that isn't a
+bootstrap task, as that would be a cycle.
+*/
+pub(super) fn add_bootstrap_requires(tasks: &mut HashMap<String, Task>) -> Result<Vec<String>> {
+    let bootstrap_tasks: BTreeSet<String> = tasks
+        .values()
+        .filter(|task| task.config.bootstrap.unwrap_or(false))
+        .map(|task| task.name.clone())
+        .collect();
+    for name in &bootstrap_tasks {
+        if let Some(required) = tasks[name]
+            .config
+            .requires
+            .iter()
+            .flatten()
+            .find(|r| tasks.contains_key(*r) && !bootstrap_tasks.contains(*r))
+        {
+            bail!(
+                "Bootstrap task '{name}' requires task '{required}', which isn't a bootstrap \
+                 task. Set `bootstrap: true` in '{required}' too, or remove it from `requires`."
+            );
+        }
+    }
+    if bootstrap_tasks.is_empty() {
+        return Ok(Vec::new());
+    }
+    debug!("Bootstrap tasks: {bootstrap_tasks:?}");
+    for task in tasks.values_mut() {
+        if !bootstrap_tasks.contains(&task.name) {
+            let requires = task.config.requires.get_or_insert_with(Vec::new);
+            for name in &bootstrap_tasks {
+                if !requires.contains(name) {
+                    requires.push(name.clone());
+                }
+            }
+        }
+    }
+    Ok(bootstrap_tasks.into_iter().collect())
+}
+
+/**
+Work out all the tasks that `name` requires, directly or indirectly.
+
+The returned set does not include `name` itself.
+*/
+pub(super) fn transitive_requires(
+    tasks: &HashMap<String, Task>,
+    name: &str,
+) -> Result<HashSet<String>> {
+    let Some(task) = tasks.get(name) else {
+        bail!("Task '{name}' not found in the tasks directory.");
+    };
+    let mut required = HashSet::new();
+    let mut to_visit: Vec<&str> = requires(task, tasks).collect();
+    while let Some(next) = to_visit.pop() {
+        if next == name || !required.insert(next.to_owned()) {
+            continue;
+        }
+        if let Some(next_task) = tasks.get(next) {
+            to_visit.extend(requires(next_task, tasks));
+        }
+    }
+    trace!("Task '{name}' requires: {required:?}");
+    Ok(required)
+}
+
+/**
+Group tasks into layers that can be run one after another.
+
+Each task is in a later layer than all the tasks it requires, so the tasks within a layer can be
+run in parallel. Errors if the requirements contain a cycle.
+
+These are the steps shown by `up plan`, when running, each task starts as soon as the tasks it
+requires have finished (see [`requirements`]) rather than waiting for the whole previous layer.
+*/
+pub(super) fn execution_layers(tasks: &HashMap<String, Task>) -> Result<Vec<Vec<String>>> {
+    // Sorted so that the layers are deterministic.
+    let mut remaining: BTreeSet<&str> = tasks.keys().map(String::as_str).collect();
+    let mut layers = Vec::new();
+    let mut done: HashSet<&str> = HashSet::new();
+
+    while !remaining.is_empty() {
+        let layer: Vec<&str> = remaining
+            .iter()
+            .copied()
+            .filter(|name| {
+                tasks
+                    .get(*name)
+                    .is_none_or(|task| requires(task, tasks).all(|r| done.contains(r)))
+            })
+            .collect();
+        if layer.is_empty() {
+            bail!(
+                "Task requirements contain a cycle, check the `requires` field of these tasks: \
+                 {remaining:?}"
+            );
+        }
+        for name in &layer {
+            remaining.remove(name);
+            done.insert(name);
+        }
+        layers.push(layer.into_iter().map(ToOwned::to_owned).collect());
+    }
+    debug!("Task execution layers: {layers:?}");
+    Ok(layers)
+}
+
+/**
+The names of the tasks in `tasks` that each task requires to have finished before it starts,
+ignoring any that aren't in `tasks`. Errors if the requirements contain a cycle.
+*/
+pub(super) fn requirements(tasks: &HashMap<String, Task>) -> Result<HashMap<String, Vec<String>>> {
+    // Check for cycles up front, so we don't run some of the tasks and then get stuck.
+    execution_layers(tasks)?;
+    Ok(tasks
+        .values()
+        .map(|task| {
+            (
+                task.name.clone(),
+                requires(task, tasks).map(ToOwned::to_owned).collect(),
+            )
+        })
+        .collect())
+}
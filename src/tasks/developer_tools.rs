@@ -0,0 +1,274 @@
+/*!
+The `developer_tools` library task, which installs the Xcode Command Line Tools on macOS.
+
+Most other tasks need the Command Line Tools (for `git`, `make`, compilers etc.), so this is
+normally a bootstrap task (with `bootstrap: true`) that the other bootstrap tasks require. It:
+
+1. checks whether the Command Line Tools (or Xcode) are installed with `xcode-select -p`.
+2. if not, installs them with `softwareupdate` (which needs `needs_sudo: true` in the task), or
+   falls back to the `xcode-select --install` dialog if that isn't possible.
+3. waits until the install has finished.
+4. accepts the Xcode license if Xcode is installed and the license hasn't been accepted.
+
+```yaml
+run_lib: developer_tools
+needs_sudo: true
+```
+*/
+use self::DeveloperToolsError as E;
+use crate::cmd_debug;
+use crate::exec::cmd;
+use crate::exec::UpDuct;
+use crate::tasks::software_update::parse_updates;
+use crate::tasks::software_update::softwareupdate;
+use crate::tasks::software_update::AvailableUpdate;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::utils::duration::HumanDuration;
+use crate::utils::user::current_user_is_root;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// While this file exists, `softwareupdate --list` includes the Command Line Tools.
+const INSTALL_ON_DEMAND_FILE: &str =
+    "/tmp/.com.apple.dt.CommandLineTools.installondemand.in-progress";
+
+/// Prefix of the `softwareupdate` labels for the Command Line Tools.
+const CLT_LABEL_PREFIX: &str = "Command Line Tools";
+
+/// How long to wait for the install to finish if `timeout` isn't set.
+const DEFAULT_TIMEOUT: Duration = Duration::from_hours(1);
+
+/// How often to check whether the install has finished.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Configuration for a `developer_tools` run library task.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeveloperToolsConfig {
+    /// How long to wait for the install to finish before failing, defaults to 1h.
+    pub timeout: Option<HumanDuration>,
+    /// Don't accept the Xcode license (you'll have to accept it yourself before using Xcode).
+    #[serde(default)]
+    pub skip_license: bool,
+}
+
+impl ResolveEnv for DeveloperToolsConfig {}
+
+/// Run a `developer_tools` run library task. `needs_sudo` is the task's `needs_sudo` field.
+pub(crate) fn run(config: &DeveloperToolsConfig, needs_sudo: bool) -> Result<TaskStatus> {
+    if !cfg!(target_os = "macos") {
+        debug!("Developer tools: skipping as not on macOS.");
+        return Ok(TaskStatus::Skipped);
+    }
+    let is_root = current_user_is_root();
+    let can_sudo = needs_sudo || is_root;
+    let mut did_work = false;
+
+    if let Some(path) = developer_dir() {
+        debug!("Developer tools already installed at {path}");
+    } else {
+        install(config, can_sudo, is_root)?;
+        did_work = true;
+    }
+
+    if !config.skip_license && developer_dir().is_some_and(|path| path.as_str().contains(".app/")) {
+        did_work |= accept_license(can_sudo, is_root)?;
+    }
+
+    if did_work {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    } else {
+        Ok(TaskStatus::Skipped)
+    }
+}
+
+/// Path of the active developer directory, if the developer tools are installed.
+fn developer_dir() -> Option<Utf8PathBuf> {
+    let path = Utf8PathBuf::from(cmd_debug!("xcode-select", "-p").stderr_null().read().ok()?);
+    path.is_dir().then_some(path)
+}
+
+/// Install the Command Line Tools, and wait until they're installed.
+fn install(config: &DeveloperToolsConfig, can_sudo: bool, is_root: bool) -> Result<()> {
+    let label = if can_sudo {
+        clt_label()?
+    } else {
+        warn!("Can't install the Command Line Tools without prompting, as needs_sudo isn't set.");
+        None
+    };
+
+    if let Some(label) = label {
+        info!("Installing {label} with softwareupdate.");
+        let update = AvailableUpdate {
+            label,
+            recommended: true,
+            restart: false,
+        };
+        let result = softwareupdate(&["--install"], &[update], is_root);
+        remove_install_on_demand_file();
+        result?;
+    } else {
+        info!("Opening the Command Line Tools installer, follow the prompts to install them.");
+        cmd("xcode-select", ["--install"])
+            .run_with(Expression::stdout_to_stderr)
+            .map_err(|e| E::Install { source: e })?;
+    }
+
+    let timeout = config
+        .timeout
+        .as_ref()
+        .map_or(DEFAULT_TIMEOUT, HumanDuration::duration);
+    let start = Instant::now();
+    while developer_dir().is_none() {
+        if start.elapsed() > timeout {
+            return Err(E::Timeout {
+                timeout: format!("{timeout:?}"),
+            }
+            .into());
+        }
+        debug!("Waiting for the Command Line Tools install to finish.");
+        thread::sleep(POLL_INTERVAL);
+    }
+    info!("Command Line Tools installed.");
+    Ok(())
+}
+
+/// Find the `softwareupdate` label of the latest Command Line Tools, if `softwareupdate` offers
+/// them.
+fn clt_label() -> Result<Option<String>> {
+    fs::write(INSTALL_ON_DEMAND_FILE, "").map_err(|e| E::InstallOnDemand { source: e })?;
+    let output = cmd("softwareupdate", ["--list"]).stderr_to_stdout().read();
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            remove_install_on_demand_file();
+            return Err(E::List { source: e }.into());
+        }
+    };
+    let label = latest_clt_label(&output);
+    if label.is_none() {
+        remove_install_on_demand_file();
+    }
+    Ok(label)
+}
+
+/// Remove the [`INSTALL_ON_DEMAND_FILE`].
+fn remove_install_on_demand_file() {
+    if let Err(e) = fs::remove_file(INSTALL_ON_DEMAND_FILE) {
+        debug!("Failed to remove {INSTALL_ON_DEMAND_FILE}: {e}");
+    }
+}
+
+/// The label of the newest Command Line Tools in `softwareupdate --list` output.
+fn latest_clt_label(output: &str) -> Option<String> {
+    parse_updates(output)
+        .into_iter()
+        .map(|update| update.label)
+        .filter(|label| label.starts_with(CLT_LABEL_PREFIX))
+        .max_by_key(|label| {
+            // Labels look like `Command Line Tools for Xcode-15.3`.
+            label
+                .rsplit('-')
+                .next()
+                .unwrap_or_default()
+                .split('.')
+                .map(|part| part.parse::<u32>().unwrap_or_default())
+                .collect::<Vec<_>>()
+        })
+}
+
+/// Accept the Xcode license if it hasn't been accepted, returning whether we accepted it.
+fn accept_license(can_sudo: bool, is_root: bool) -> Result<bool> {
+    if cmd_debug!("xcodebuild", "-license", "check")
+        .stderr_null()
+        .run_with(Expression::stdout_null)
+        .is_ok()
+    {
+        debug!("Xcode license already accepted.");
+        return Ok(false);
+    }
+    if !can_sudo {
+        warn!(
+            "Not accepting the Xcode license as needs_sudo isn't set, run `sudo xcodebuild \
+             -license accept` to accept it."
+        );
+        return Ok(false);
+    }
+    info!("Accepting the Xcode license.");
+    let command = if is_root {
+        cmd("xcodebuild", ["-license", "accept"])
+    } else {
+        cmd("sudo", ["-n", "xcodebuild", "-license", "accept"])
+    };
+    command
+        .run_with(Expression::stdout_to_stderr)
+        .map_err(|e| E::License { source: e })?;
+    Ok(true)
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum DeveloperToolsError {
+    /// Failed to create `/tmp/.com.apple.dt.CommandLineTools.installondemand.in-progress`.
+    InstallOnDemand {
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to list software updates.
+    List {
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to start the Command Line Tools installer.
+    Install {
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Command Line Tools still not installed after {timeout}.
+    Timeout {
+        /// How long we waited.
+        timeout: String,
+    },
+    /// Failed to accept the Xcode license.
+    License {
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_latest_clt_label() -> color_eyre::Result<()> {
+        let output = "Software Update found the following new or updated software:
+* Label: Command Line Tools for Xcode-9.4
+\tTitle: Command Line Tools for Xcode, Version: 9.4, Size: 100KiB, Recommended: YES,
+* Label: Command Line Tools for Xcode-15.3
+\tTitle: Command Line Tools for Xcode, Version: 15.3, Size: 751226KiB, Recommended: YES,
+* Label: macOS Sonoma 14.5-23F79
+\tTitle: macOS Sonoma 14.5, Version: 14.5, Size: 1234567KiB, Recommended: YES, Action: restart,
+";
+        ensure_eq!(
+            Some("Command Line Tools for Xcode-15.3".to_owned()),
+            super::latest_clt_label(output)
+        );
+        ensure_eq!(None, super::latest_clt_label("No new software available."));
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+//! Show everything up knows about a task (`up explain`).
+use self::ExplainError as E;
+use crate::config::SelectedHost;
+use crate::config::UpConfig;
+use crate::tasks;
+use crate::tasks::deps;
+use crate::tasks::history::LastRun;
+use crate::tasks::history::RunHistory;
+use crate::tasks::include;
+use crate::tasks::lint::yaml_strings;
+use crate::tasks::plugin::PLUGIN_PREFIX;
+use crate::tasks::task::OnMissingCommands;
+use crate::tasks::task::Task;
+use crate::tasks::task::TaskRunRecord;
+use crate::tasks::task::TASK_OUTPUT_FILE;
+use crate::tasks::TasksDir;
+use crate::tasks::RUNS_DIR;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use itertools::Itertools;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fmt::Write;
+use thiserror::Error;
+
+/// Print the details of the task called `name`.
+pub(crate) fn run(config: &UpConfig, name: &str) -> Result<()> {
+    let tasks_dir = tasks::tasks_dir(config, TasksDir::Tasks)?;
+    let mut tasks = tasks::load_tasks(
+        &tasks_dir,
+        &config.cache_dir,
+        config.config_yaml.task_extensions.as_deref(),
+    )?;
+    if let Some(includes) = &config.config_yaml.include {
+        include::add_included_tasks(includes, &config.cache_dir, config.offline, &mut tasks)?;
+    }
+    tasks::apply_config_defaults(&config.config_yaml, &mut tasks);
+    let task = tasks.get(name).ok_or_else(|| E::TaskNotFound {
+        name: name.to_owned(),
+        tasks_dir: tasks_dir.clone(),
+        names: tasks.keys().sorted().join(", "),
+    })?;
+    let env = tasks::config_env(config)?;
+    let runs_dir = config.run_temp_dir.join(RUNS_DIR);
+
+    print!(
+        "{}",
+        explain(task, &tasks, config.host.as_ref(), &env, &runs_dir)?
+    );
+    Ok(())
+}
+
+/// Build the explanation for `task`.
+fn explain(
+    task: &Task,
+    tasks: &HashMap<String, Task>,
+    host: Option<&SelectedHost>,
+    env: &HashMap<String, String>,
+    runs_dir: &Utf8Path,
+) -> Result<String> {
+    let config = &task.config;
+    let mut out = String::new();
+
+    writeln!(out, "Task: {name}", name = task.name)?;
+    writeln!(out, "Source file: {path}", path = task.path)?;
+    if let Some(description) = &config.description {
+        writeln!(out, "Description: {description}")?;
+    }
+    writeln!(
+        out,
+        "Runs by default: {}",
+        if config.auto_run.unwrap_or(true) {
+            "yes"
+        } else {
+            "no (auto_run is false), only when required by another task or named in --tasks"
+        }
+    )?;
+    writeln!(out, "Needs sudo: {}", yes_no(config.needs_sudo))?;
+    if let Some(tags) = &config.tags {
+        writeln!(out, "Tags: {}", tags.join(", "))?;
+    }
+    if let Some(resources) = &config.resources {
+        writeln!(out, "Resources: {resources:?}")?;
+    }
+    if let Some(mutex) = &config.mutex {
+        writeln!(
+            out,
+            "Mutex: {mutex} (never runs at the same time as other tasks using it)"
+        )?;
+    }
+    if let Some(slow_warn_after) = &config.slow_warn_after {
+        writeln!(out, "Warns if slower than: {slow_warn_after}")?;
+    }
+    match &config.constraints {
+        Some(constraints) if !constraints.is_empty() => writeln!(
+            out,
+            "Constraints: {} (not yet checked by up, the task runs regardless)",
+            constraints
+                .iter()
+                .sorted()
+                .map(|(k, v)| format!("{k}={v}"))
+                .join(", ")
+        )?,
+        _ => writeln!(out, "Constraints: none")?,
+    }
+    if let Some(host) = host {
+        if host.excludes(&task.name) {
+            writeln!(out, "Host: {host}, which excludes this task")?;
+        } else if let Some(host_tasks) = &host.config.tasks {
+            writeln!(
+                out,
+                "Host: {host}, which only runs tasks matching {} (unless --tasks is passed)",
+                host_tasks.join(", ")
+            )?;
+        } else {
+            writeln!(out, "Host: {host}")?;
+        }
+    }
+
+    writeln!(out, "\nDependencies:")?;
+    writeln!(
+        out,
+        "  Requires: {}",
+        list_or_none(config.requires.iter().flatten().cloned())
+    )?;
+    let all_required = deps::transitive_requires(tasks, &task.name)?;
+    writeln!(
+        out,
+        "  Requires (including indirectly): {}",
+        list_or_none(all_required)
+    )?;
+    let required_by = tasks
+        .values()
+        .filter(|t| t.config.requires.iter().flatten().any(|r| *r == task.name))
+        .map(|t| t.name.clone());
+    writeln!(out, "  Required by: {}", list_or_none(required_by))?;
+    let missing = config
+        .requires
+        .iter()
+        .flatten()
+        .filter(|r| !tasks.contains_key(*r));
+    for required in missing {
+        writeln!(
+            out,
+            "  Warning: required task '{required}' doesn't exist, so is ignored."
+        )?;
+    }
+
+    if let Some(requires_commands) = &config.requires_commands {
+        let missing = task.missing_commands(env);
+        let action = match config.on_missing_commands.unwrap_or_default() {
+            OnMissingCommands::Fail => "fail",
+            OnMissingCommands::Skip => "be skipped",
+        };
+        writeln!(
+            out,
+            "  Requires commands: {}",
+            list_or_none(requires_commands.iter().cloned())
+        )?;
+        if !missing.is_empty() {
+            writeln!(
+                out,
+                "  Warning: commands {} are missing, so the task will {action}.",
+                missing.join(", ")
+            )?;
+        }
+    }
+
+    writeln!(out, "\nCommands:")?;
+    if let Some(run_if_cmd) = &config.run_if_cmd {
+        writeln!(
+            out,
+            "  run_if_cmd (task is skipped if this fails): {}",
+            resolve_cmd(run_if_cmd, env)
+        )?;
+    }
+    let mut used = false;
+    if let Some(run_lib) = &config.run_lib {
+        used = true;
+        match run_lib.strip_prefix(PLUGIN_PREFIX) {
+            Some(plugin) => writeln!(
+                out,
+                "  run_lib: up runs the '{plugin}' plugin, passing it the data as JSON"
+            )?,
+            None => writeln!(out, "  run_lib: up runs its built-in '{run_lib}' library")?,
+        }
+        if let Some(data) = &config.data {
+            let mut data = data.clone();
+            resolve_yaml_strings(&mut data, env);
+            writeln!(out, "    with data:")?;
+            for line in serde_yaml::to_string(&data)?.lines() {
+                writeln!(out, "      {line}")?;
+            }
+        }
+    }
+    if let Some(run_script) = &config.run_script {
+        let ignored = if used {
+            " (ignored as run_lib is set)"
+        } else {
+            ""
+        };
+        used = true;
+        writeln!(
+            out,
+            "  run_script{ignored}: written to <run temp dir>/{name}/run_script and executed:",
+            name = task.name
+        )?;
+        for line in run_script.lines() {
+            writeln!(out, "      {line}")?;
+        }
+    }
+    if let Some(run_cmd) = &config.run_cmd {
+        let ignored = if used {
+            " (ignored as run_lib or run_script is set)"
+        } else {
+            ""
+        };
+        used = true;
+        writeln!(out, "  run_cmd{ignored}: {}", resolve_cmd(run_cmd, env))?;
+    }
+    if !used {
+        writeln!(
+            out,
+            "  none (no run_lib, run_script, or run_cmd), so the task will fail"
+        )?;
+    }
+
+    let mut strings: Vec<&str> = config
+        .run_if_cmd
+        .iter()
+        .chain(config.run_cmd.iter())
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if let Some(data) = &config.data {
+        yaml_strings(data, &mut strings);
+    }
+    let mut vars = BTreeSet::new();
+    for s in strings {
+        _ = shellexpand::env_with_context_no_errors(s, |var| {
+            vars.insert(var.to_owned());
+            None::<String>
+        });
+    }
+    if !vars.is_empty() {
+        writeln!(out, "\nEnv vars used:")?;
+        for var in vars {
+            match env.get(&var) {
+                Some(value) => writeln!(out, "  ${var} = {value}")?,
+                None => writeln!(out, "  ${var} is not set, so the task will fail")?,
+            }
+        }
+    }
+
+    writeln!(out, "\nLast run: {}", last_run(runs_dir, &task.name)?)?;
+    Ok(out)
+}
+
+/// Describe the most recent run of the task called `name`.
+fn last_run(runs_dir: &Utf8Path, name: &str) -> Result<String> {
+    let Some(LastRun {
+        started,
+        task_tempdir,
+        record,
+    }) = RunHistory::read(runs_dir)?.last_run(name)
+    else {
+        return Ok("never".to_owned());
+    };
+
+    let mut description = match record {
+        Some(TaskRunRecord {
+            status,
+            error,
+            duration,
+        }) => {
+            let mut description = format!("{status} after {duration:.1?} (run started {started})");
+            if let Some(error) = error {
+                write!(description, "\n  Error: {error}")?;
+            }
+            description
+        }
+        None => format!("started {started}, status not recorded"),
+    };
+    let output_file = task_tempdir.join(TASK_OUTPUT_FILE);
+    if output_file.exists() {
+        write!(description, "\n  Output: {output_file}")?;
+    }
+    Ok(description)
+}
+
+/// Shell-escaped command with env vars resolved (or an explanation of why they couldn't be).
+fn resolve_cmd(cmd: &[String], env: &HashMap<String, String>) -> String {
+    cmd.iter()
+        .map(|arg| match tasks::resolve_env_vars(arg, env) {
+            Ok(resolved) => shell_escape::escape(resolved.into()).into_owned(),
+            Err(_) => format!("{arg}<unresolved>"),
+        })
+        .join(" ")
+}
+
+/// Resolve env vars in all the strings in `value`, leaving those that can't be resolved as-is.
+fn resolve_yaml_strings(value: &mut serde_yaml::Value, env: &HashMap<String, String>) {
+    match value {
+        serde_yaml::Value::String(s) => {
+            if let Ok(resolved) = tasks::resolve_env_vars(s, env) {
+                *s = resolved;
+            }
+        }
+        serde_yaml::Value::Sequence(seq) => {
+            for v in seq {
+                resolve_yaml_strings(v, env);
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for (_, v) in map.iter_mut() {
+                resolve_yaml_strings(v, env);
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => resolve_yaml_strings(&mut tagged.value, env),
+        serde_yaml::Value::Null | serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_) => {}
+    }
+}
+
+/// Comma-separated sorted list, or `none`.
+fn list_or_none(items: impl IntoIterator<Item = String>) -> String {
+    let list = items.into_iter().sorted().join(", ");
+    if list.is_empty() {
+        "none".to_owned()
+    } else {
+        list
+    }
+}
+
+/// `yes` or `no`.
+const fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum ExplainError {
+    /// Task '{name}' not found in `{tasks_dir}`, tasks are: {names}.
+    TaskNotFound {
+        /// Task name.
+        name: String,
+        /// Tasks directory searched.
+        tasks_dir: Utf8PathBuf,
+        /// Names of the tasks that do exist.
+        names: String,
+    },
+}
@@ -0,0 +1,381 @@
+/*!
+The `finder` library task, for the Finder sidebar, the desktop, and where screenshots go.
+
+These settings are spread over several defaults domains and the sidebar's shared file list, so
+this library sets them from structured yaml, and restarts Finder (or `SystemUIServer` for the
+screenshot settings) if anything changed:
+
+```yaml
+run_lib: finder
+data:
+  sidebar_favorites:
+    - ~/code
+    - ~/Downloads
+  new_window_folder: ~/code
+  desktop:
+    show_icons: true
+    show_hard_drives: false
+    show_external_drives: true
+  screenshot_folder: ~/Pictures/Screenshots
+  screenshot_format: png
+```
+
+Sidebar favorites that are missing are added (with `sfltool`) at the end of the list, existing
+favorites are left alone. The screenshot folder is created if it doesn't exist.
+
+The task is skipped if nothing needed changing (or if not running on macOS).
+*/
+use self::FinderError as E;
+use crate::cmd_debug;
+use crate::exec::UpDuct;
+use crate::tasks::defaults;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TaskError;
+use crate::utils::files;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use url::Url;
+
+/// Defaults domain for Finder settings.
+const FINDER_DOMAIN: &str = "com.apple.finder";
+/// Defaults domain for screenshot settings.
+const SCREENCAPTURE_DOMAIN: &str = "com.apple.screencapture";
+/// Shared file list containing the sidebar favorites.
+const FAVORITES_LIST: &str = "com.apple.LSSharedFileList.FavoriteItems";
+/// Directory (relative to the home directory) containing the shared file list storage files.
+const SHARED_FILE_LIST_DIR: &str = "Library/Application Support/com.apple.sharedfilelist";
+/// Storage file extensions for the shared file lists, newest format first.
+const SHARED_FILE_LIST_EXTENSIONS: &[&str] = &["sfl3", "sfl2"];
+
+/// Configuration for a `finder` run library task.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FinderConfig {
+    /// Folders that should be in the sidebar's Favorites, e.g. `[~/code]`.
+    #[serde(default)]
+    pub sidebar_favorites: Vec<String>,
+    /// Folder that new Finder windows open in, e.g. `~/code`.
+    pub new_window_folder: Option<String>,
+    /// What to show on the desktop.
+    #[serde(default)]
+    pub desktop: DesktopConfig,
+    /// Folder to save screenshots in, e.g. `~/Pictures/Screenshots`.
+    pub screenshot_folder: Option<String>,
+    /// File format for screenshots, e.g. `png`, `jpg`, or `pdf`.
+    pub screenshot_format: Option<String>,
+}
+
+/// What Finder shows on the desktop, unset fields are left as they are.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DesktopConfig {
+    /// Show files and folders on the desktop at all.
+    pub show_icons: Option<bool>,
+    /// Show internal hard drives.
+    pub show_hard_drives: Option<bool>,
+    /// Show external drives.
+    pub show_external_drives: Option<bool>,
+    /// Show CDs, DVDs, and iPods.
+    pub show_removable_media: Option<bool>,
+    /// Show connected servers.
+    pub show_servers: Option<bool>,
+}
+
+impl ResolveEnv for FinderConfig {
+    fn resolve_env<F>(&mut self, env_fn: F) -> Result<(), TaskError>
+    where
+        F: Fn(&str) -> Result<String, TaskError>,
+    {
+        for folder in self
+            .sidebar_favorites
+            .iter_mut()
+            .chain(&mut self.new_window_folder)
+            .chain(&mut self.screenshot_folder)
+        {
+            *folder = env_fn(folder)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a `finder` run library task.
+pub(crate) fn run(config: &FinderConfig, backup_dir: &Utf8Path) -> Result<TaskStatus> {
+    if !cfg!(target_os = "macos") {
+        debug!("Finder: skipping as not on macOS.");
+        return Ok(TaskStatus::Skipped);
+    }
+
+    let mut restart_finder = add_sidebar_favorites(&config.sidebar_favorites)?;
+
+    if let Some(folder) = &config.screenshot_folder {
+        files::create_dir_all(folder)?;
+    }
+    let mut domains = finder_defaults(config)?;
+    let screencapture = domains.remove(SCREENCAPTURE_DOMAIN);
+    restart_finder |= defaults::write_lib_values(domains, backup_dir)? > 0;
+    let restart_screencapture = match screencapture {
+        Some(prefs) => {
+            defaults::write_lib_values(
+                HashMap::from([(SCREENCAPTURE_DOMAIN.to_owned(), prefs)]),
+                backup_dir,
+            )? > 0
+        }
+        None => false,
+    };
+
+    if restart_finder {
+        restart("Finder");
+    }
+    if restart_screencapture {
+        restart("SystemUIServer");
+    }
+    if restart_finder || restart_screencapture {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    } else {
+        Ok(TaskStatus::Skipped)
+    }
+}
+
+/// The defaults values to write for the config, keyed by domain.
+fn finder_defaults(
+    config: &FinderConfig,
+) -> Result<HashMap<String, HashMap<String, plist::Value>>, E> {
+    let mut finder = HashMap::new();
+    if let Some(folder) = &config.new_window_folder {
+        // `PfLo` means "Other…", i.e. the folder in `NewWindowTargetPath`.
+        finder.insert("NewWindowTarget".to_owned(), "PfLo".into());
+        finder.insert(
+            "NewWindowTargetPath".to_owned(),
+            folder_url(folder)?.to_string().into(),
+        );
+    }
+    let DesktopConfig {
+        show_icons,
+        show_hard_drives,
+        show_external_drives,
+        show_removable_media,
+        show_servers,
+    } = config.desktop;
+    for (key, value) in [
+        ("CreateDesktop", show_icons),
+        ("ShowHardDrivesOnDesktop", show_hard_drives),
+        ("ShowExternalHardDrivesOnDesktop", show_external_drives),
+        ("ShowRemovableMediaOnDesktop", show_removable_media),
+        ("ShowMountedServersOnDesktop", show_servers),
+    ] {
+        if let Some(value) = value {
+            finder.insert(key.to_owned(), value.into());
+        }
+    }
+
+    let mut screencapture = HashMap::new();
+    if let Some(folder) = &config.screenshot_folder {
+        screencapture.insert("location".to_owned(), folder.clone().into());
+    }
+    if let Some(format) = &config.screenshot_format {
+        screencapture.insert("type".to_owned(), format.clone().into());
+    }
+
+    Ok([
+        (FINDER_DOMAIN, finder),
+        (SCREENCAPTURE_DOMAIN, screencapture),
+    ]
+    .into_iter()
+    .filter(|(_, prefs)| !prefs.is_empty())
+    .map(|(domain, prefs)| (domain.to_owned(), prefs))
+    .collect())
+}
+
+/// Add the `favorites` that aren't already in the Finder sidebar, returning whether any were
+/// added.
+fn add_sidebar_favorites(favorites: &[String]) -> Result<bool> {
+    if favorites.is_empty() {
+        return Ok(false);
+    }
+    let existing = existing_favorites()?;
+    let mut added = false;
+    for favorite in favorites {
+        let url = folder_url(favorite)?;
+        if existing.contains(&url) {
+            debug!("Finder sidebar already contains {favorite}.");
+            continue;
+        }
+        cmd_debug!("sfltool", "add-item", FAVORITES_LIST, url.as_str())
+            .run_with(Expression::stdout_null)
+            .map_err(|e| E::AddFavorite {
+                folder: favorite.clone(),
+                source: e,
+            })?;
+        info!("Added {favorite} to the Finder sidebar.");
+        added = true;
+    }
+    Ok(added)
+}
+
+/// The URLs of the folders in the Finder sidebar's Favorites.
+fn existing_favorites() -> Result<Vec<Url>> {
+    let home_dir = files::home_dir()?;
+    let Some(storage) = SHARED_FILE_LIST_EXTENSIONS
+        .iter()
+        .map(|ext| home_dir.join(format!("{SHARED_FILE_LIST_DIR}/{FAVORITES_LIST}.{ext}")))
+        .find(|path| path.exists())
+    else {
+        debug!("No Finder sidebar favorites storage found.");
+        return Ok(Vec::new());
+    };
+    let output = cmd_debug!("sfltool", "dump-storage", &storage)
+        .read()
+        .map_err(|e| E::ListFavorites {
+            path: storage.clone(),
+            source: e,
+        })?;
+    Ok(parse_favorites(&output))
+}
+
+/// Find the `file://` URLs in the output of `sfltool dump-storage`.
+fn parse_favorites(output: &str) -> Vec<Url> {
+    output
+        .split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ',' | '<' | '>'))
+        .filter(|word| word.starts_with("file://"))
+        .filter_map(|word| Url::parse(word).ok())
+        .map(|url| with_trailing_slash(&url))
+        .collect()
+}
+
+/// The `file://` URL of a folder, as the sidebar stores it (with a trailing slash).
+fn folder_url(folder: &str) -> Result<Url, E> {
+    Url::from_directory_path(folder).map_err(|()| E::RelativeFolder {
+        folder: folder.to_owned(),
+    })
+}
+
+/// The URL with a trailing slash, so folder URLs can be compared.
+fn with_trailing_slash(url: &Url) -> Url {
+    let mut url = url.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    url
+}
+
+/// Restart an app so it picks up changed settings, it's fine if it isn't running.
+fn restart(app: &str) {
+    match cmd_debug!("killall", app)
+        .unchecked()
+        .stderr_null()
+        .run_with(Expression::stdout_null)
+    {
+        Ok(output) if output.status.success() => info!("Restarted {app}."),
+        result => debug!("Didn't restart {app}: {result:?}"),
+    }
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum FinderError {
+    /// Folder '{folder}' should be an absolute path (or start with `~`).
+    RelativeFolder {
+        /// The folder from the config.
+        folder: String,
+    },
+    /// Failed to list the Finder sidebar favorites in `{path}`.
+    ListFavorites {
+        /// The favorites storage file.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to add {folder} to the Finder sidebar.
+    AddFavorite {
+        /// The folder from the config.
+        folder: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::finder_defaults;
+    use super::folder_url;
+    use super::parse_favorites;
+    use super::FinderConfig;
+    use super::FINDER_DOMAIN;
+    use super::SCREENCAPTURE_DOMAIN;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::collections::HashMap;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_finder_defaults() -> Result<()> {
+        let config: FinderConfig = serde_yaml::from_str(
+            "
+new_window_folder: /Users/me/My Code
+desktop:
+  show_icons: false
+  show_servers: true
+screenshot_folder: /Users/me/Pictures/Screenshots
+",
+        )?;
+        let expected = HashMap::from([
+            (
+                FINDER_DOMAIN.to_owned(),
+                HashMap::from([
+                    ("NewWindowTarget".to_owned(), "PfLo".into()),
+                    (
+                        "NewWindowTargetPath".to_owned(),
+                        "file:///Users/me/My%20Code/".into(),
+                    ),
+                    ("CreateDesktop".to_owned(), false.into()),
+                    ("ShowMountedServersOnDesktop".to_owned(), true.into()),
+                ]),
+            ),
+            (
+                SCREENCAPTURE_DOMAIN.to_owned(),
+                HashMap::from([(
+                    "location".to_owned(),
+                    "/Users/me/Pictures/Screenshots".into(),
+                )]),
+            ),
+        ]);
+        ensure_eq!(expected, finder_defaults(&config)?);
+
+        ensure_eq!(HashMap::new(), finder_defaults(&FinderConfig::default())?);
+        let config = FinderConfig {
+            new_window_folder: Some("code".to_owned()),
+            ..FinderConfig::default()
+        };
+        ensure!(finder_defaults(&config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_favorites() -> Result<()> {
+        let output = r#"
+Contents of /Users/me/Library/Application Support/com.apple.sharedfilelist/com.apple.LSSharedFileList.FavoriteItems.sfl3:
+    [0] "Applications" URL: file:///Applications/, properties: {}
+    [1] "My Code" URL: "file:///Users/me/My%20Code", properties: {}
+    [2] "AirDrop" URL: nwnode://domain-AirDrop, properties: {}
+"#;
+        ensure_eq!(
+            vec![
+                folder_url("/Applications")?,
+                folder_url("/Users/me/My Code")?
+            ],
+            parse_favorites(output)
+        );
+        Ok(())
+    }
+}
@@ -1,11 +1,18 @@
 //! The git library task.
 use self::GitTaskError as E;
 use crate::opts::GitOptions;
+use crate::tasks;
+use crate::tasks::git::fetch::GitRetry;
+use crate::tasks::resolve_config_path;
 use crate::tasks::task::TaskStatus;
+use crate::tasks::task::DEFAULT_SLOW_WARN_AFTER;
 use crate::tasks::ResolveEnv;
 use crate::tasks::TaskError;
+use crate::utils::progress::TaskProgress;
+use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use clap::Parser;
+use clap::ValueEnum;
 use color_eyre::eyre::Result;
 use displaydoc::Display;
 use git2::Remote;
@@ -13,25 +20,40 @@ use rayon::iter::Either;
 use rayon::prelude::*;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use std::collections::HashMap;
 use std::convert::From;
+use std::env;
+use std::fs;
+use std::io;
+use std::time::Duration;
 use thiserror::Error;
 use tracing::error;
+use tracing::info;
 
 pub mod branch;
 pub mod checkout;
 pub mod cherry;
+pub mod clean;
+pub mod default_branch;
 pub mod errors;
 pub mod fetch;
+pub mod lfs;
 pub mod merge;
+pub(crate) mod preflight;
 pub mod prune;
 pub mod status;
 pub mod update;
+pub mod url_rewrite;
 
 /// Default git remote name.
 pub const DEFAULT_REMOTE_NAME: &str = "origin";
 
+/// Repos containing a file with this name aren't updated by git tasks or added by `generate git`.
+pub(crate) const IGNORE_MARKER_FILE: &str = ".up-ignore";
+
 /// `up git` configuration options.
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)] // These are independent per-repo options.
 pub struct GitConfig {
     /// Path to download git repo to.
     pub path: Utf8PathBuf,
@@ -45,6 +67,35 @@ pub struct GitConfig {
     /// Prune local branches whose changes have already been merged upstream.
     #[serde(default = "prune_default")]
     pub prune: bool,
+    /// Report the branches that would be pruned in the run summary, without deleting them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prune_dry_run: bool,
+    /// Move pruned branches to `refs/up/trash/<date>/<branch>` rather than deleting them outright.
+    /// Trashed branches are deleted once they are older than 30 days.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trash_pruned: bool,
+    /// Remove files ignored by git (e.g. build artifacts) after updating, like `git clean -dfX`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub clean_ignored: bool,
+    /// How to retry fetching when authentication or the network fails.
+    #[serde(default, skip_serializing_if = "GitRetry::is_unset")]
+    pub retry: GitRetry,
+    /// What to do if the repo has a detached HEAD (isn't on a branch), `skip` (the default) or
+    /// `checkout`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_detached_head: Option<OnDetachedHead>,
+}
+
+/// What to do when updating a repo whose HEAD is detached (not on a branch).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OnDetachedHead {
+    /// Warn and leave the HEAD where it is, only fetching the remotes.
+    #[default]
+    Skip,
+    /// Check out the `branch` (or the default branch of the first remote) if the repo is clean,
+    /// otherwise warn and skip.
+    Checkout,
 }
 
 /// Serde needs a function to set a default, so this sets a default of false.
@@ -53,10 +104,32 @@ const fn prune_default() -> bool {
 }
 
 /// Run the `up git` task.
-pub(crate) fn run(configs: &[GitConfig]) -> Result<TaskStatus> {
+pub(crate) fn run(configs: &[GitConfig], slow_warn_after: Duration) -> Result<TaskStatus> {
+    let configs: Vec<&GitConfig> = configs
+        .iter()
+        .filter(|config| {
+            let ignored = config.path.join(IGNORE_MARKER_FILE).exists();
+            if ignored {
+                info!(
+                    "Skipping {path} as it contains a {IGNORE_MARKER_FILE} file.",
+                    path = config.path
+                );
+            }
+            !ignored
+        })
+        .collect();
+    // Fail once up front rather than for every repo after retrying authentication.
+    if configs.len() > 1 {
+        preflight::check_auth(configs.iter().copied())?;
+    }
+    let progress = TaskProgress::new(configs.len());
     let (statuses, errors): (Vec<_>, Vec<_>) = configs
-        .par_iter()
-        .map(update::update)
+        .into_par_iter()
+        .map(|config| {
+            let status = update::update(config, slow_warn_after);
+            progress.step(config.path.as_str());
+            status
+        })
         .partition_map(|x| match x {
             Ok(status) => Either::Left(status),
             Err(e) => Either::Right(e),
@@ -66,7 +139,15 @@ pub(crate) fn run(configs: &[GitConfig]) -> Result<TaskStatus> {
         if statuses.iter().all(|s| matches!(s, TaskStatus::Skipped)) {
             Ok(TaskStatus::Skipped)
         } else {
-            Ok(TaskStatus::Passed)
+            Ok(TaskStatus::Passed(
+                statuses
+                    .into_iter()
+                    .filter_map(|s| match s {
+                        TaskStatus::Passed(changes) => Some(changes),
+                        _ => None,
+                    })
+                    .sum(),
+            ))
         }
     } else {
         for error in &errors {
@@ -77,17 +158,64 @@ pub(crate) fn run(configs: &[GitConfig]) -> Result<TaskStatus> {
     }
 }
 
+/// Clone or update the repos listed in the YAML or JSON file at `path` (`up git --from-file`).
+pub(crate) fn run_from_file(path: &Utf8Path) -> Result<()> {
+    let contents = fs::read_to_string(path).map_err(|e| E::ReadFile {
+        path: path.to_owned(),
+        source: e,
+    })?;
+    let mut configs: Vec<GitConfig> =
+        serde_yaml::from_str(&contents).map_err(|e| E::ParseFile {
+            path: path.to_owned(),
+            source: e,
+        })?;
+    resolve_current_env(&mut configs)?;
+
+    let count = configs.len();
+    info!("Updating {count} repos from {path}.");
+    if let TaskStatus::Passed(changes) = run(&configs, DEFAULT_SLOW_WARN_AFTER)? {
+        info!("Updated {count} repos from {path}: {changes}.");
+    } else {
+        info!("All {count} repos from {path} were already up to date.");
+    }
+    Ok(())
+}
+
+/**
+Clone or update the repo passed on the command line (`up git --git-url <url> --git-path <path>`).
+
+`~` and env vars in the options are expanded as in a task's config, so they work even if the shell
+didn't expand them (e.g. `--git-path=~/code/foo`).
+*/
+pub(crate) fn run_from_options(opts: GitOptions) -> Result<TaskStatus> {
+    let mut configs = vec![GitConfig::from(opts)];
+    resolve_current_env(&mut configs)?;
+    let config = configs.first().ok_or(E::UnexpectedNone)?;
+    update::update(config, DEFAULT_SLOW_WARN_AFTER)
+}
+
+/// Expand `~` and env vars (from the current environment) in the `configs`.
+fn resolve_current_env(configs: &mut Vec<GitConfig>) -> Result<(), TaskError> {
+    let env: HashMap<String, String> = env::vars().collect();
+    configs.resolve_env(|s| tasks::resolve_env_value(s, &env))
+}
+
 impl From<GitOptions> for GitConfig {
     fn from(item: GitOptions) -> Self {
         Self {
-            path: item.git_path,
+            path: item.git_path.unwrap_or_default(),
             remotes: vec![GitRemote {
                 name: item.remote,
                 push_url: None,
-                fetch_url: item.git_url,
+                fetch_url: item.git_url.unwrap_or_default(),
             }],
             branch: item.branch,
             prune: item.prune,
+            prune_dry_run: item.prune_dry_run,
+            trash_pruned: item.trash_pruned,
+            clean_ignored: item.clean_ignored,
+            retry: item.retry,
+            on_detached_head: item.on_detached_head,
         }
     }
 }
@@ -101,7 +229,7 @@ impl ResolveEnv for Vec<GitConfig> {
             if let Some(branch) = config.branch.as_ref() {
                 config.branch = Some(env_fn(branch)?);
             }
-            config.path = Utf8PathBuf::from(env_fn(config.path.as_str())?);
+            config.path = resolve_config_path(config.path.as_str(), &env_fn)?;
             for remote in &mut config.remotes {
                 remote.name = env_fn(&remote.name)?;
                 remote.push_url = if let Some(push_url) = &remote.push_url {
@@ -153,4 +281,18 @@ pub enum GitTaskError {
     InvalidRemote,
     /// Unexpected None in option.
     UnexpectedNone,
+    /// Failed to read repos file `{path}`.
+    ReadFile {
+        /// Path to the file.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: io::Error,
+    },
+    /// Failed to parse repos file `{path}` as a list of git configs.
+    ParseFile {
+        /// Path to the file.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: serde_yaml::Error,
+    },
 }
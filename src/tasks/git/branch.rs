@@ -1,6 +1,7 @@
 //! Git branch shortcuts.
 use crate::tasks::git::errors::GitError as E;
 use crate::tasks::git::fetch::remote_callbacks;
+use crate::tasks::git::fetch::GitRetry;
 use crate::tasks::git::update::get_config_value;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
@@ -96,7 +97,11 @@ fn get_push_remote(branch: &str, config: &git2::Config) -> Result<Option<String>
 }
 
 /// Return the HEAD branch of the specified remote in the repository.
-pub(super) fn calculate_head(repo: &Repository, remote: &mut Remote) -> Result<String> {
+pub(super) fn calculate_head(
+    repo: &Repository,
+    remote: &mut Remote,
+    retry: &GitRetry,
+) -> Result<String> {
     let head_if_set = repo.head();
     Ok(match head_if_set {
         Ok(head) => head
@@ -106,8 +111,16 @@ pub(super) fn calculate_head(repo: &Repository, remote: &mut Remote) -> Result<S
         Err(head_err) if head_err.code() == ErrorCode::UnbornBranch => {
             // TODO(gib): avoid fetching again here.
             {
-                let mut count = 0;
-                remote.connect_auth(Direction::Fetch, Some(remote_callbacks(&mut count)), None)?;
+                retry.retry_network(|| {
+                    let mut count = 0;
+                    remote
+                        .connect_auth(
+                            Direction::Fetch,
+                            Some(remote_callbacks(&mut count, retry)),
+                            None,
+                        )
+                        .map(drop)
+                })?;
             }
             let default_branch = remote
                 .default_branch()?
@@ -121,6 +134,17 @@ pub(super) fn calculate_head(repo: &Repository, remote: &mut Remote) -> Result<S
     })
 }
 
+/// The short hash of the commit HEAD points to.
+pub(super) fn short_head_commit(repo: &Repository) -> Result<String> {
+    let head_commit = repo.head()?.peel_to_commit()?;
+    Ok(head_commit
+        .as_object()
+        .short_id()?
+        .as_str()
+        .ok_or(E::InvalidBranchError)?
+        .to_owned())
+}
+
 /// Convert a git branch to a String name.
 pub(super) fn get_branch_name(branch: &Branch) -> Result<String> {
     Ok(branch.name()?.ok_or(E::InvalidBranchError)?.to_owned())
@@ -1,5 +1,6 @@
 //! Checkout a git branch or ref.
 use crate::tasks::git::fetch::remote_callbacks;
+use crate::tasks::git::fetch::GitRetry;
 use crate::tasks::git::status::ensure_repo_clean;
 use color_eyre::eyre::bail;
 use color_eyre::eyre::eyre;
@@ -122,9 +123,11 @@ fn force_checkout_head(repo: &Repository) -> Result<()> {
             .conflict_style_merge(true);
 
         // Update the submodule's head.
+        // Submodules use the default retry options, as they're updated in many code paths.
         let mut count = 0;
+        let retry = GitRetry::default();
         let mut fetch_options = FetchOptions::new();
-        fetch_options.remote_callbacks(remote_callbacks(&mut count));
+        fetch_options.remote_callbacks(remote_callbacks(&mut count, &retry));
 
         submodule.update(
             false,
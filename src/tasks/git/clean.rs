@@ -0,0 +1,212 @@
+//! Remove ignored files (build artifacts) from a repo, like `git clean -dfX`.
+use crate::tasks::git::errors::GitError as E;
+use crate::utils::files;
+use camino::Utf8Path;
+use color_eyre::eyre::Result;
+use git2::Repository;
+use git2::StatusOptions;
+use std::fmt::Write;
+use std::fs;
+use tracing::debug;
+use tracing::info;
+use tracing::trace;
+use walkdir::WalkDir;
+
+/// Remove files and directories ignored by git, equivalent to `git clean -dfX`.
+///
+/// Ignore rules come from `.gitignore` files, `.git/info/exclude`, and the
+/// user's `core.excludesFile`. Git repos nested anywhere inside an ignored
+/// directory are left alone (as `git clean` does without `-ff`).
+///
+/// Returns whether we did any work (`false` means there was nothing to clean).
+pub(super) fn clean_ignored_files(repo: &Repository) -> Result<bool> {
+    let workdir = files::to_utf8_path(repo.workdir().ok_or(E::NoGitDirFound)?)?;
+
+    let mut status_options = StatusOptions::new();
+    // Ignored directories are returned as a single entry, so we remove them in one go.
+    status_options
+        .include_ignored(true)
+        .recurse_ignored_dirs(false)
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .exclude_submodules(true);
+    let statuses = repo.statuses(Some(&mut status_options))?;
+
+    let mut removed_count = 0;
+    let mut removed_bytes = 0;
+    let mut report = String::new();
+    for entry in statuses.iter().filter(|e| e.status().is_ignored()) {
+        let Some(relative_path) = entry.path() else {
+            trace!("Not cleaning non-UTF-8 path {:?}", entry.path_bytes());
+            continue;
+        };
+        let path = workdir.join(relative_path.trim_end_matches('/'));
+        let Ok(metadata) = path.symlink_metadata() else {
+            trace!("Not cleaning {path} as it no longer exists.");
+            continue;
+        };
+        let mut note = "";
+        if metadata.is_dir() {
+            let (size, removed) = remove_dir_skipping_repos(&path)?;
+            if size == 0 && !removed {
+                continue;
+            }
+            if !removed {
+                note = " (kept nested git repos)";
+            }
+            removed_bytes += size;
+        } else {
+            fs::remove_file(&path).map_err(|e| E::DeleteError {
+                path: path.clone(),
+                source: e,
+            })?;
+            removed_bytes += metadata.len();
+        }
+        removed_count += 1;
+        write!(report, "\n  - {relative_path}{note}")?;
+    }
+
+    if removed_count == 0 {
+        debug!("No ignored files to clean.");
+        return Ok(false);
+    }
+    info!(
+        "Cleaned {removed_count} ignored path(s) in '{workdir}', freeing {size}:{report}",
+        size = human_size(removed_bytes),
+    );
+    Ok(true)
+}
+
+/// Remove the ignored directory `dir`, leaving any git repos nested inside it (at any depth) and
+/// the directories leading to them in place.
+///
+/// Returns the number of bytes freed, and whether `dir` itself was removed.
+fn remove_dir_skipping_repos(dir: &Utf8Path) -> Result<(u64, bool)> {
+    if dir.join(".git").exists() {
+        debug!("Not cleaning {dir} as it contains a git repo.");
+        return Ok((0, false));
+    }
+    let contains_repo = WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| entry.file_name() == ".git");
+    if !contains_repo {
+        let size = dir_size(dir);
+        fs::remove_dir_all(dir).map_err(|e| E::DeleteError {
+            path: dir.to_owned(),
+            source: e,
+        })?;
+        return Ok((size, true));
+    }
+
+    let mut freed_bytes = 0;
+    let mut removed_all = true;
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = path.symlink_metadata()?;
+        if metadata.is_dir() {
+            let (size, removed) = remove_dir_skipping_repos(path)?;
+            freed_bytes += size;
+            removed_all &= removed;
+        } else {
+            fs::remove_file(path).map_err(|e| E::DeleteError {
+                path: path.to_owned(),
+                source: e,
+            })?;
+            freed_bytes += metadata.len();
+        }
+    }
+    if removed_all {
+        fs::remove_dir(dir).map_err(|e| E::DeleteError {
+            path: dir.to_owned(),
+            source: e,
+        })?;
+    }
+    Ok((freed_bytes, removed_all))
+}
+
+/// Total size in bytes of the files in `dir` (not following symlinks).
+fn dir_size(dir: &Utf8Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| !metadata.is_dir())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Format a number of bytes for humans, e.g. `1.5 MiB`.
+#[allow(clippy::cast_precision_loss)] // Only used for display.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{size:.1} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clean_ignored_files;
+    use super::human_size;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use git2::Repository;
+    use std::fs;
+    use testutils::ensure_eq;
+    use testutils::ensure_utils;
+
+    #[test]
+    fn test_human_size() -> Result<()> {
+        ensure_eq!("0 B", human_size(0));
+        ensure_eq!("1023 B", human_size(1023));
+        ensure_eq!("1.0 KiB", human_size(1024));
+        ensure_eq!("1.5 MiB", human_size(1536 * 1024));
+        ensure_eq!("5.0 GiB", human_size(5 * 1024 * 1024 * 1024));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_ignored_files() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let repo = Repository::init(&temp_dir)?;
+        fs::write(temp_dir.join(".gitignore"), "*.log\nbuild/\nvendor/\n")?;
+        fs::write(temp_dir.join("debug.log"), "ignored file")?;
+        fs::create_dir_all(temp_dir.join("build/out"))?;
+        fs::write(temp_dir.join("build/out/artifact"), "ignored dir")?;
+        fs::create_dir_all(temp_dir.join("vendor/x/y"))?;
+        Repository::init(temp_dir.join("vendor/x/y"))?;
+        fs::write(temp_dir.join("vendor/x/y/lib.rs"), "nested repo")?;
+        fs::write(
+            temp_dir.join("vendor/x/generated"),
+            "ignored next to a nested repo",
+        )?;
+        fs::write(temp_dir.join("untracked.txt"), "untracked file")?;
+
+        ensure!(clean_ignored_files(&repo)?);
+
+        ensure_utils::nothing_at(&temp_dir.join("debug.log"))?;
+        ensure_utils::nothing_at(&temp_dir.join("build"))?;
+        ensure_utils::nothing_at(&temp_dir.join("vendor/x/generated"))?;
+        ensure_utils::file(&temp_dir.join("vendor/x/y/lib.rs"), "nested repo")?;
+        ensure_utils::dir(&temp_dir.join("vendor/x/y/.git"))?;
+        ensure_utils::file(&temp_dir.join("untracked.txt"), "untracked file")?;
+        ensure_utils::file(&temp_dir.join(".gitignore"), "*.log\nbuild/\nvendor/\n")?;
+
+        // Only the nested repo is left, so there's nothing more to do.
+        ensure!(!clean_ignored_files(&repo)?);
+        Ok(())
+    }
+}
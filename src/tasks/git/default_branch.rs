@@ -0,0 +1,95 @@
+//! Handle a remote renaming its default branch (e.g. `master` -> `main`).
+use crate::tasks::git::branch::get_branch_name;
+use crate::tasks::git::branch::shorten_branch_ref;
+use crate::tasks::git::errors::GitError as E;
+use crate::tasks::git::update::get_config_value;
+use color_eyre::eyre::Result;
+use git2::BranchType;
+use git2::ErrorCode;
+use git2::Remote;
+use git2::Repository;
+use tracing::debug;
+use tracing::warn;
+
+/**
+If the remote's default branch was renamed since we last fetched, retarget local branches that
+tracked the old default branch to track the new one, rename the local old default branch to the
+new name (if there isn't already a local branch with that name), and delete the stale
+remote-tracking branch.
+
+The remote must be connected (i.e. just fetched), and `default_branch` is its current default
+branch. Returns whether anything was changed.
+*/
+pub(super) fn handle_renamed_default_branch(
+    repo: &Repository,
+    remote: &Remote,
+    default_branch: &str,
+) -> Result<bool> {
+    let remote_name = remote.name().ok_or(E::RemoteNameMissing)?;
+    let new_branch = shorten_branch_ref(default_branch);
+    let remote_prefix = format!("refs/remotes/{remote_name}/");
+
+    let old_head_target = match repo.find_reference(&format!("{remote_prefix}HEAD")) {
+        Ok(reference) => reference.symbolic_target().map(ToOwned::to_owned),
+        Err(e) if e.code() == ErrorCode::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let Some(old_branch) = old_head_target
+        .as_deref()
+        .and_then(|target| target.strip_prefix(&remote_prefix))
+    else {
+        return Ok(false);
+    };
+    if old_branch == new_branch {
+        return Ok(false);
+    }
+    let old_branch_ref = format!("refs/heads/{old_branch}");
+    if remote
+        .list()?
+        .iter()
+        .any(|head| head.name() == old_branch_ref)
+    {
+        debug!(
+            "Remote {remote_name} default branch changed to {new_branch}, but {old_branch} still \
+             exists."
+        );
+        return Ok(false);
+    }
+
+    warn!(
+        "Remote {remote_name} default branch was renamed from {old_branch} to {new_branch}, \
+         updating local branches."
+    );
+    let new_upstream = format!("{remote_name}/{new_branch}");
+    let config = repo.config()?;
+    let new_branch_exists = repo.find_branch(new_branch, BranchType::Local).is_ok();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (mut branch, _) = branch?;
+        let name = get_branch_name(&branch)?;
+        let tracks_old_branch = get_config_value(&config, &format!("branch.{name}.remote"))?
+            .is_some_and(|remote| remote == remote_name)
+            && get_config_value(&config, &format!("branch.{name}.merge"))?
+                .is_some_and(|merge| merge == old_branch_ref);
+        if !tracks_old_branch {
+            continue;
+        }
+        warn!(
+            "Changing upstream of branch {name} from {remote_name}/{old_branch} to {new_upstream}."
+        );
+        branch.set_upstream(Some(&new_upstream))?;
+        if name == old_branch && !new_branch_exists {
+            warn!("Renaming local branch {old_branch} to {new_branch}.");
+            branch.rename(new_branch, false)?;
+        }
+    }
+
+    match repo.find_reference(&format!("{remote_prefix}{old_branch}")) {
+        Ok(mut reference) => {
+            debug!("Deleting stale remote-tracking branch {remote_name}/{old_branch}.");
+            reference.delete()?;
+        }
+        Err(e) if e.code() == ErrorCode::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(true)
+}
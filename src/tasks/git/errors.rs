@@ -10,6 +10,26 @@ use thiserror::Error;
 #[derive(Error, Debug, Display)]
 /// Errors thrown by the Git task.
 pub enum GitError {
+    /**
+    Can't fetch `{url}` (and maybe other repos) over SSH, as no ssh-agent is running.
+      Start one with `eval "$(ssh-agent)"`, then add your keys. {hint}
+    */
+    NoSshAgent {
+        /// The first SSH URL that would be fetched.
+        url: String,
+        /// How to add keys to the agent.
+        hint: String,
+    },
+    /**
+    Can't fetch `{url}` (and maybe other repos) over SSH, as the ssh-agent has no keys.
+      {hint}
+    */
+    NoSshKeys {
+        /// The first SSH URL that would be fetched.
+        url: String,
+        /// How to add keys to the agent.
+        hint: String,
+    },
     /// Failed to update git repo at `{path}`.
     GitUpdate {
         /// The path we failed to update.
@@ -22,6 +42,28 @@ pub enum GitError {
         /// Source error.
         source: io::Error,
     },
+    /// Failed to delete ignored path `{path}`.
+    DeleteError {
+        /// The path we failed to delete.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: io::Error,
+    },
+    /**
+    Repo at `{path}` uses Git LFS, but `git lfs` isn't installed, so LFS files are pointer files.
+      Install git-lfs to fix.
+    */
+    LfsNotInstalled {
+        /// The repo path.
+        path: Utf8PathBuf,
+    },
+    /// Failed to run `git lfs pull` in `{path}`, LFS files may be pointer files.
+    LfsPull {
+        /// The repo path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: io::Error,
+    },
     /// Must specify at least one remote.
     NoRemotes,
     /// Current branch is not valid UTF-8
@@ -1,6 +1,8 @@
 //! Fetch updates to a branch.
 use crate::tasks::git::branch::shorten_branch_ref;
 use crate::tasks::git::errors::GitError as E;
+use crate::utils::duration::HumanDuration;
+use clap::Args;
 use color_eyre::eyre::Result;
 use git2::Cred;
 use git2::CredentialType;
@@ -9,35 +11,126 @@ use git2::ErrorCode;
 use git2::Remote;
 use git2::RemoteCallbacks;
 use git2::Repository;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
 use std::thread;
 use std::time::Duration;
 use tracing::debug;
 use tracing::warn;
 
-/// Number of times to try authenticating when fetching.
+/// Default number of times to try authenticating when fetching.
 const AUTH_RETRY_COUNT: usize = 10;
-/// Length of time to sleep after multiple fetch failures.
-const RETRY_SLEEP_INTERVAL_S: u64 = 2;
+/// Default number of times to retry fetching after a network error.
+const NETWORK_RETRY_COUNT: usize = 3;
+/// Default length of time to sleep before the first retry.
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+/// Default longest time to sleep between retries.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How to retry fetching when authentication or the network fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Args)]
+#[serde(deny_unknown_fields)]
+pub struct GitRetry {
+    /// Number of times to try authenticating when fetching (default 10).
+    #[clap(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_retries: Option<usize>,
+    /// Number of times to retry fetching after a network error, e.g. a connection reset or DNS
+    /// failure (default 3).
+    #[clap(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_retries: Option<usize>,
+    /// How long to wait before the first retry, e.g. `500ms`. The wait doubles after each retry
+    /// (default 2s).
+    #[clap(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_backoff: Option<HumanDuration>,
+    /// Longest time to wait between retries (default 30s).
+    #[clap(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retry_backoff: Option<HumanDuration>,
+}
+
+impl GitRetry {
+    /// Whether no retry options are set, so the defaults are used.
+    pub(crate) fn is_unset(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// How long to wait before retry number `retry` (starting from 1).
+    fn backoff(&self, retry: usize) -> Duration {
+        let initial = self
+            .retry_backoff
+            .as_ref()
+            .map_or(RETRY_BACKOFF, HumanDuration::duration);
+        let max = self
+            .max_retry_backoff
+            .as_ref()
+            .map_or(MAX_RETRY_BACKOFF, HumanDuration::duration);
+        let exponent = u32::try_from(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+        initial
+            .saturating_mul(2_u32.saturating_pow(exponent))
+            .min(max)
+    }
+
+    /// Run `f`, retrying with backoff if it fails with a transient network error.
+    pub(super) fn retry_network<T>(
+        &self,
+        mut f: impl FnMut() -> Result<T, git2::Error>,
+    ) -> Result<T, git2::Error> {
+        let max_retries = self.network_retries.unwrap_or(NETWORK_RETRY_COUNT);
+        let mut retry = 0;
+        loop {
+            match f() {
+                Err(e) if retry < max_retries && is_transient_network_error(&e) => {
+                    retry += 1;
+                    let backoff = self.backoff(retry);
+                    warn!("Fetch failed, retrying in {backoff:?} ({retry}/{max_retries}): {e}");
+                    thread::sleep(backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether `e` is a network error that might go away if we try again.
+fn is_transient_network_error(e: &git2::Error) -> bool {
+    if e.code() == ErrorCode::Auth {
+        return false;
+    }
+    if e.class() == ErrorClass::Net {
+        return true;
+    }
+    let message = e.message().to_lowercase();
+    [
+        "connection reset",
+        "connection refused",
+        "timed out",
+        "failed to resolve",
+        "could not resolve",
+        "temporary failure in name resolution",
+    ]
+    .iter()
+    .any(|transient| message.contains(transient))
+}
 
 /// Prepare the remote authentication callbacks for fetching.
 ///
 /// Refs: <https://github.com/rust-lang/cargo/blob/2f115a76e5a1e5eb11cd29e95f972ed107267847/src/cargo/sources/git/utils.rs#L588>
-pub(super) fn remote_callbacks(count: &mut usize) -> RemoteCallbacks {
+pub(super) fn remote_callbacks<'a>(
+    count: &'a mut usize,
+    retry: &'a GitRetry,
+) -> RemoteCallbacks<'a> {
     let mut remote_callbacks = RemoteCallbacks::new();
     remote_callbacks.credentials(move |url, username_from_url, allowed_types| {
         *count += 1;
-        if *count > 2 {
-            thread::sleep(Duration::from_secs(RETRY_SLEEP_INTERVAL_S));
-        }
-        if *count > AUTH_RETRY_COUNT {
+        if *count > retry.auth_retries.unwrap_or(AUTH_RETRY_COUNT) {
             let extra = if allowed_types.contains(CredentialType::SSH_KEY) {
-                // On macOS ssh-add takes a -K argument to automatically add the ssh key's password
-                // to the system keychain. This argument isn't present on other platforms.
-                let ssh_add_keychain = if cfg!(target_os = "macos") { "-K " } else { "" };
                 format!(
                     "\nIf 'git clone {url}' works, you probably need to add your ssh keys to the \
-                     ssh-agent. Try running 'ssh-add {ssh_add_keychain}-A' or 'ssh-add \
-                     {ssh_add_keychain}~/.ssh/*id_{{rsa,ed25519}}'."
+                     ssh-agent. {hint}",
+                    hint = ssh_add_hint()
                 )
             } else {
                 String::new()
@@ -48,6 +141,10 @@ pub(super) fn remote_callbacks(count: &mut usize) -> RemoteCallbacks {
             );
             return Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Ssh, message));
         }
+        // The first two attempts are usually a username then a key, so only back off after that.
+        if *count > 2 {
+            thread::sleep(retry.backoff(*count - 2));
+        }
         debug!("SSH_AUTH_SOCK: {:?}", std::env::var("SSH_AUTH_SOCK"));
         debug!(
             "Fetching credentials, url: {url}, username_from_url: {username_from_url:?}, count: \
@@ -68,6 +165,17 @@ pub(super) fn remote_callbacks(count: &mut usize) -> RemoteCallbacks {
     remote_callbacks
 }
 
+/// Suggestion for how to add ssh keys to the ssh-agent.
+pub(super) fn ssh_add_hint() -> String {
+    // On macOS ssh-add takes a -K argument to automatically add the ssh key's password to the
+    // system keychain. This argument isn't present on other platforms.
+    let ssh_add_keychain = if cfg!(target_os = "macos") { "-K " } else { "" };
+    format!(
+        "Try running 'ssh-add {ssh_add_keychain}-A' or 'ssh-add \
+         {ssh_add_keychain}~/.ssh/*id_{{rsa,ed25519}}'."
+    )
+}
+
 /// Equivalent of: `git remote set-head --auto <remote>`
 /// Find remote HEAD, then set the symbolic-ref `refs/remotes/<remote>/HEAD` to
 /// `refs/remotes/<remote>/<branch>`
@@ -109,3 +217,31 @@ pub(super) fn set_remote_head(
     }
     Ok(did_work)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::GitRetry;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::time::Duration;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_backoff() -> Result<()> {
+        let retry = GitRetry::default();
+        ensure_eq!(Duration::from_secs(2), retry.backoff(1));
+        ensure_eq!(Duration::from_secs(4), retry.backoff(2));
+        ensure_eq!(Duration::from_secs(16), retry.backoff(4));
+        ensure_eq!(Duration::from_secs(30), retry.backoff(5));
+        ensure_eq!(Duration::from_secs(30), retry.backoff(100));
+
+        let retry = GitRetry {
+            retry_backoff: Some("500ms".parse().map_err(|e: String| eyre!(e))?),
+            max_retry_backoff: Some("1s".parse().map_err(|e: String| eyre!(e))?),
+            ..GitRetry::default()
+        };
+        ensure_eq!(Duration::from_millis(500), retry.backoff(1));
+        ensure_eq!(Duration::from_secs(1), retry.backoff(3));
+        Ok(())
+    }
+}
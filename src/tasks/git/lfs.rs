@@ -0,0 +1,86 @@
+//! Fetch Git LFS objects, which libgit2 doesn't download on checkout.
+use crate::cmd;
+use crate::cmd_debug;
+use crate::exec::UpDuct;
+use crate::tasks::git::errors::GitError as E;
+use crate::utils::files;
+use camino::Utf8Path;
+use color_eyre::eyre::Result;
+use duct::Expression;
+use git2::Repository;
+use std::fs;
+use tracing::debug;
+
+/// Whether the repo uses Git LFS (has an LFS filter in its top-level `.gitattributes`).
+pub(super) fn uses_lfs(repo: &Repository) -> Result<bool> {
+    let workdir = files::to_utf8_path(repo.workdir().ok_or(E::NoGitDirFound)?)?;
+    let Ok(attributes) = fs::read_to_string(workdir.join(".gitattributes")) else {
+        return Ok(false);
+    };
+    Ok(has_lfs_filter(&attributes))
+}
+
+/// Whether the contents of a `.gitattributes` file set the LFS filter for any paths.
+fn has_lfs_filter(attributes: &str) -> bool {
+    attributes
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .any(|line| {
+            line.split_whitespace()
+                .skip(1)
+                .any(|attr| attr == "filter=lfs")
+        })
+}
+
+/// Replace LFS pointer files left by libgit2 checkouts with their real contents, by running
+/// `git lfs pull` in the repo.
+pub(super) fn pull_lfs_objects(repo: &Repository) -> Result<()> {
+    let path = files::to_utf8_path(repo.workdir().ok_or(E::NoGitDirFound)?)?;
+    ensure_lfs_installed(path)?;
+    cmd!("git", "-C", path, "lfs", "pull")
+        .run_with(Expression::stdout_to_stderr)
+        .map_err(|e| E::LfsPull {
+            path: path.to_owned(),
+            source: e,
+        })?;
+    Ok(())
+}
+
+/// Error if the `git lfs` command isn't available.
+fn ensure_lfs_installed(path: &Utf8Path) -> Result<()> {
+    if let Err(e) = cmd_debug!("git", "lfs", "version")
+        .stderr_null()
+        .run_with(Expression::stdout_null)
+    {
+        debug!("Running git lfs version failed: {e}");
+        return Err(E::LfsNotInstalled {
+            path: path.to_owned(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::has_lfs_filter;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+
+    #[test]
+    fn test_has_lfs_filter() -> Result<()> {
+        ensure!(has_lfs_filter(
+            "*.psd filter=lfs diff=lfs merge=lfs -text\n"
+        ));
+        ensure!(has_lfs_filter(
+            "# Binaries\n*.sh text eol=lf\nassets/** filter=lfs -text\n"
+        ));
+        ensure!(!has_lfs_filter("*.sh text eol=lf\n"));
+        ensure!(!has_lfs_filter(
+            "# *.psd filter=lfs diff=lfs merge=lfs -text\n"
+        ));
+        ensure!(!has_lfs_filter(""));
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+//! Check authentication will work before fetching many repos.
+//!
+//! Without this, if the ssh-agent has no keys every repo retries authenticating (sleeping between
+//! attempts) before failing with the same error.
+use crate::cmd_debug;
+use crate::exec::UpDuct;
+use crate::tasks::git::errors::GitError as E;
+use crate::tasks::git::fetch::ssh_add_hint;
+use crate::tasks::git::url_rewrite;
+use crate::tasks::git::GitConfig;
+use duct::Expression;
+use git2::Config;
+use std::env;
+use tracing::debug;
+
+/// Check that the ssh-agent has keys if any of the `configs` fetch over SSH.
+///
+/// HTTPS remotes aren't checked, as public repos don't need credentials.
+pub(crate) fn check_auth<'a>(configs: impl IntoIterator<Item = &'a GitConfig>) -> Result<(), E> {
+    let git_config = Config::open_default().ok();
+    let ssh_url = configs
+        .into_iter()
+        .flat_map(|config| &config.remotes)
+        .map(|remote| match &git_config {
+            Some(git_config) => url_rewrite::fetch_url(git_config, &remote.fetch_url)
+                .unwrap_or_else(|_| remote.fetch_url.clone()),
+            None => remote.fetch_url.clone(),
+        })
+        .find(|url| is_ssh_url(url));
+    let Some(url) = ssh_url else {
+        debug!("No repos fetch over SSH, skipping ssh-agent check.");
+        return Ok(());
+    };
+
+    if env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Err(E::NoSshAgent {
+            url,
+            hint: ssh_add_hint(),
+        });
+    }
+    match cmd_debug!("ssh-add", "-l")
+        .stderr_null()
+        .unchecked()
+        .run_with(Expression::stdout_null)
+    {
+        Ok(output) => match output.status.code() {
+            Some(0) => Ok(()),
+            // No identities in the agent.
+            Some(1) => Err(E::NoSshKeys {
+                url,
+                hint: ssh_add_hint(),
+            }),
+            _ => Err(E::NoSshAgent {
+                url,
+                hint: ssh_add_hint(),
+            }),
+        },
+        Err(e) => {
+            debug!("Failed to run ssh-add, skipping ssh-agent check: {e}");
+            Ok(())
+        }
+    }
+}
+
+/// Whether `url` is fetched over SSH, either `ssh://` or scp-like `user@host:path`.
+fn is_ssh_url(url: &str) -> bool {
+    if let Some((scheme, _)) = url.split_once("://") {
+        return matches!(scheme, "ssh" | "git+ssh" | "ssh+git");
+    }
+    // Git treats `host:path` as scp-like syntax if there's no slash before the colon.
+    url.split_once(':')
+        .is_some_and(|(host, _)| !host.is_empty() && !host.contains('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_ssh_url;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+
+    #[test]
+    fn test_is_ssh_url() -> Result<()> {
+        ensure!(is_ssh_url("git@github.com:gibfahn/up-rs.git"));
+        ensure!(is_ssh_url("ssh://git@github.com/gibfahn/up-rs.git"));
+        ensure!(is_ssh_url("github.com:gibfahn/up-rs"));
+        ensure!(!is_ssh_url("https://github.com/gibfahn/up-rs.git"));
+        ensure!(!is_ssh_url("file:///tmp/repo"));
+        ensure!(!is_ssh_url("/tmp/repo"));
+        ensure!(!is_ssh_url("./some:dir"));
+        Ok(())
+    }
+}
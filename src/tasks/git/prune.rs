@@ -6,38 +6,80 @@ use crate::tasks::git::checkout::checkout_branch;
 use crate::tasks::git::cherry::unmerged_commits;
 use crate::tasks::git::errors::GitError as E;
 use crate::tasks::git::status::ensure_repo_clean;
+use crate::tasks::git::GitConfig;
+use crate::tasks::task::TaskChanges;
 use crate::utils::files;
+use chrono::NaiveDate;
+use chrono::Utc;
 use color_eyre::eyre::Result;
 use git2::Branch;
 use git2::BranchType;
 use git2::Repository;
+use std::fmt::Write;
 use tracing::debug;
 use tracing::trace;
 
+/// Ref namespace that pruned branches are moved to when `trash_pruned` is set.
+const TRASH_REF_PREFIX: &str = "refs/up/trash/";
+
+/// Number of days to keep trashed branches before deleting them.
+const TRASH_DAYS: i64 = 30;
+
 /// Prune merged PR branches. Deletes local branches where the push branch
 /// has been merged into the upstream branch, and the push branch has now
 /// been deleted.
 ///
 /// If the branch to be pruned is the currently checked out branch, switch to the HEAD branch of the
 /// `remote_name` remote.
+///
+/// With `prune_dry_run` set, only report the branches that would be pruned. With `trash_pruned`
+/// set, move pruned branches to `refs/up/trash/<date>/<branch>`, and delete trashed branches older
+/// than [`TRASH_DAYS`].
+/// The branches pruned (or that would be) are added to `changes` for the run summary.
 /// Returns whether we did any work (`false` means we skipped).
-pub(super) fn prune_merged_branches(repo: &Repository, remote_name: &str) -> Result<bool> {
+pub(super) fn prune_merged_branches(
+    repo: &Repository,
+    remote_name: &str,
+    git_config: &GitConfig,
+    changes: &mut TaskChanges,
+) -> Result<bool> {
+    let dry_run = git_config.prune_dry_run;
+    let mut did_work = false;
+    if git_config.trash_pruned && !dry_run {
+        did_work = expire_trashed_branches(repo)?;
+    }
+
     let branches_to_prune = branches_to_prune(repo)?;
     if branches_to_prune.is_empty() {
         debug!("Nothing to prune.");
-        return Ok(false);
+        return Ok(did_work);
     }
+    let repo_path = files::to_utf8_path(repo.workdir().ok_or(E::NoGitDirFound)?)?;
+
+    if dry_run {
+        for branch in &branches_to_prune {
+            let commit = branch.get().peel_to_commit()?;
+            let report = format!(
+                "{repo_path}: {name} (at {short_id})",
+                name = get_branch_name(branch)?,
+                short_id = commit.as_object().short_id()?.as_str().unwrap_or_default(),
+            );
+            debug!("Would prune branch {report}");
+            changes.branches_to_prune.push(report);
+        }
+        return Ok(did_work);
+    }
+
     ensure_repo_clean(repo)?;
-    debug!(
-        "Pruning branches in '{}': {:?}",
-        files::to_utf8_path(repo.workdir().ok_or(E::NoGitDirFound)?)?,
-        &branches_to_prune
-            .iter()
-            .map(get_branch_name)
-            .collect::<Result<Vec<String>>>()?,
-    );
+    let trash_prefix = git_config.trash_pruned.then(|| {
+        format!(
+            "{TRASH_REF_PREFIX}{date}/",
+            date = Utc::now().format("%Y-%m-%d")
+        )
+    });
     for mut branch in branches_to_prune {
-        debug!("Pruning branch: {}", get_branch_name(&branch)?);
+        let branch_name = get_branch_name(&branch)?;
+        debug!("Pruning branch: {branch_name}");
         if branch.is_head() {
             let remote_ref_name = format!("refs/remotes/{remote_name}/HEAD");
             let remote_ref = repo.find_reference(&remote_ref_name)?;
@@ -48,11 +90,55 @@ pub(super) fn prune_merged_branches(repo: &Repository, remote_name: &str) -> Res
             let branch_name = format!("refs/heads/{short_branch}");
             checkout_branch(repo, &branch_name, short_branch, remote_name, false)?;
         }
+        let commit = branch.get().peel_to_commit()?;
+        let mut report = format!(
+            "{repo_path}: {branch_name} (was at {short_id})",
+            short_id = commit.as_object().short_id()?.as_str().unwrap_or_default(),
+        );
+        if let Some(trash_prefix) = &trash_prefix {
+            let trash_ref = format!("{trash_prefix}{branch_name}");
+            repo.reference(
+                &trash_ref,
+                commit.id(),
+                true,
+                &format!("up: trash pruned branch {branch_name}"),
+            )?;
+            write!(report, ", moved to {trash_ref}")?;
+        }
         delete_branch(repo, &mut branch)?;
+        changes.branches_pruned.push(report);
     }
     Ok(true)
 }
 
+/// Delete branches in [`TRASH_REF_PREFIX`] that were trashed more than [`TRASH_DAYS`] ago.
+/// Returns whether any were deleted.
+fn expire_trashed_branches(repo: &Repository) -> Result<bool> {
+    let today = Utc::now().date_naive();
+    let mut did_work = false;
+    for reference in repo.references_glob(&format!("{TRASH_REF_PREFIX}*"))? {
+        let mut reference = reference?;
+        let Some(ref_name) = reference.name().map(ToOwned::to_owned) else {
+            continue;
+        };
+        let date = ref_name
+            .trim_start_matches(TRASH_REF_PREFIX)
+            .split('/')
+            .next()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+        let Some(date) = date else {
+            trace!("Not expiring {ref_name} as it doesn't have a valid date.");
+            continue;
+        };
+        if (today - date).num_days() > TRASH_DAYS {
+            debug!("Deleting trashed branch {ref_name}, trashed on {date}.");
+            reference.delete()?;
+            did_work = true;
+        }
+    }
+    Ok(did_work)
+}
+
 /// Work out branches that we can prune.
 /// These should be PR branches that have already been merged into their
 /// upstream branches.
@@ -1,6 +1,7 @@
 //! Show repo status.
 use crate::tasks::git::branch::get_branch_name;
 use crate::tasks::git::branch::get_push_branch;
+use crate::tasks::git::branch::short_head_commit;
 use crate::tasks::git::cherry::unmerged_commits;
 use crate::tasks::git::errors::GitError as E;
 use crate::utils::files::to_utf8_path;
@@ -58,6 +59,14 @@ pub(super) fn warn_for_unpushed_changes(
         }
     }
 
+    // Warn for a detached HEAD, as commits made on it aren't on any branch.
+    if repo.head_detached()? {
+        warn!(
+            "HEAD is detached at {}, not on any branch.",
+            short_head_commit(repo)?
+        );
+    }
+
     // Warn for any stashed changes
     {
         let mut stash_messages = Vec::new();
@@ -4,17 +4,27 @@
 use crate::tasks::git::branch::calculate_head;
 use crate::tasks::git::branch::get_branch_name;
 use crate::tasks::git::branch::get_push_branch;
+use crate::tasks::git::branch::short_head_commit;
 use crate::tasks::git::branch::shorten_branch_ref;
 use crate::tasks::git::checkout::checkout_branch;
 use crate::tasks::git::checkout::needs_checkout;
+use crate::tasks::git::clean::clean_ignored_files;
+use crate::tasks::git::default_branch::handle_renamed_default_branch;
 use crate::tasks::git::errors::GitError as E;
 use crate::tasks::git::fetch::remote_callbacks;
 use crate::tasks::git::fetch::set_remote_head;
+use crate::tasks::git::fetch::GitRetry;
+use crate::tasks::git::lfs::pull_lfs_objects;
+use crate::tasks::git::lfs::uses_lfs;
 use crate::tasks::git::merge::do_ff_merge;
 use crate::tasks::git::prune::prune_merged_branches;
+use crate::tasks::git::status::ensure_repo_clean;
 use crate::tasks::git::status::warn_for_unpushed_changes;
+use crate::tasks::git::url_rewrite;
 use crate::tasks::git::GitConfig;
 use crate::tasks::git::GitRemote;
+use crate::tasks::git::OnDetachedHead;
+use crate::tasks::task::TaskChanges;
 use crate::tasks::task::TaskStatus;
 use color_eyre::eyre::bail;
 use color_eyre::eyre::Context;
@@ -31,41 +41,42 @@ use std::str;
 use std::time::Duration;
 use std::time::Instant;
 use tracing::debug;
+use tracing::info;
 use tracing::trace;
 use tracing::warn;
 use url::Url;
 
-/// Update a git repo.
-pub(crate) fn update(git_config: &GitConfig) -> Result<TaskStatus> {
+/// Update a git repo, warning if it takes longer than `slow_warn_after`.
+pub(crate) fn update(git_config: &GitConfig, slow_warn_after: Duration) -> Result<TaskStatus> {
     let now = Instant::now();
     let _span = tracing::info_span!("git", repo = &git_config.path.as_str()).entered();
     let result = real_update(git_config)
-        .map(|did_work| {
-            if did_work {
-                TaskStatus::Passed
-            } else {
+        .map(|changes| {
+            if changes.is_empty() && changes.branches_to_prune.is_empty() {
                 TaskStatus::Skipped
+            } else {
+                TaskStatus::Passed(changes)
             }
         })
         .wrap_err_with(|| E::GitUpdate {
             path: git_config.path.clone(),
         });
     let elapsed_time = now.elapsed();
-    // TODO(gib): configurable logging for long actions.
-    if elapsed_time > Duration::from_secs(60) {
+    if elapsed_time > slow_warn_after {
         warn!("Git update took {elapsed_time:?}",);
     }
     result
 }
 
-/// Update a git repo, returns `true` if we did any work (or `false` if we skipped).
+/// Update a git repo, returns the changes made (empty if we skipped).
 // TODO(gib): remove more stuff from this function.
-// TODO(gib): Handle the case where a repo update has changed the default
-// branch, e.g. master -> main, and now there's a branch with an upstream
-// pointing to nothing.
 #[allow(clippy::too_many_lines)]
-pub(crate) fn real_update(git_config: &GitConfig) -> Result<bool> {
+pub(crate) fn real_update(git_config: &GitConfig) -> Result<TaskChanges> {
     let mut did_work = false;
+    // Branches pruned, counted in the changes we return.
+    let mut changes = TaskChanges::default();
+    // Whether we fast-forwarded the branch.
+    let mut fast_forwarded = false;
 
     // Create dir if it doesn't exist.
     let git_path = git_config.path.clone();
@@ -117,7 +128,7 @@ pub(crate) fn real_update(git_config: &GitConfig) -> Result<bool> {
     }
 
     for remote_config in &git_config.remotes {
-        set_up_remote(&repo, remote_config)?;
+        set_up_remote(&repo, remote_config, &git_config.retry)?;
     }
     debug!(
         "Created remotes: {:?}",
@@ -140,16 +151,33 @@ pub(crate) fn real_update(git_config: &GitConfig) -> Result<bool> {
             })?;
 
     if !newly_created_repo
-        && git_config.prune
-        && prune_merged_branches(&repo, &default_remote_name)?
+        && (git_config.prune || git_config.prune_dry_run)
+        && prune_merged_branches(&repo, &default_remote_name, git_config, &mut changes)?
     {
         did_work = true;
     }
 
-    let branch_name: String = if let Some(branch_name) = &git_config.branch {
+    // Merging into a detached HEAD would update no branch, so either check one out or skip.
+    let detached_head_branch = if !newly_created_repo && repo.head_detached()? {
+        let Some(branch) = detached_head_branch(&repo, git_config, &default_remote_name)? else {
+            drop(default_remote);
+            warn_for_unpushed_changes(&mut repo, &user_git_config)?;
+            return Ok(TaskChanges {
+                repos_updated: usize::from(did_work),
+                ..changes
+            });
+        };
+        Some(branch)
+    } else {
+        None
+    };
+
+    let branch_name: String = if let Some(branch_name) = detached_head_branch {
+        branch_name
+    } else if let Some(branch_name) = &git_config.branch {
         branch_name.clone()
     } else {
-        calculate_head(&repo, &mut default_remote)?
+        calculate_head(&repo, &mut default_remote, &git_config.retry)?
     };
     let short_branch = shorten_branch_ref(&branch_name);
     // TODO(gib): Find better way to make branch_name long and short_branch short.
@@ -180,6 +208,7 @@ pub(crate) fn real_update(git_config: &GitConfig) -> Result<bool> {
             merge_ref: push_branch_name,
         })? {
             did_work = true;
+            fast_forwarded = true;
         }
     } else {
         debug!("Branch doesn't have an @{{push}} branch, checking @{{upstream}} instead.");
@@ -199,6 +228,7 @@ pub(crate) fn real_update(git_config: &GitConfig) -> Result<bool> {
                     }
                 })? {
                     did_work = true;
+                    fast_forwarded = true;
                 }
             }
             Err(e) if e.code() == ErrorCode::NotFound => {
@@ -210,14 +240,71 @@ pub(crate) fn real_update(git_config: &GitConfig) -> Result<bool> {
         }
     };
     drop(default_remote); // Can't mutably use repo while this value is around.
+                          // libgit2 doesn't run the LFS filter, so checkouts and merges leave pointer files.
+    if did_work && uses_lfs(&repo)? {
+        pull_lfs_objects(&repo)?;
+    }
+    if git_config.clean_ignored && clean_ignored_files(&repo)? {
+        did_work = true;
+    }
     if !newly_created_repo {
         warn_for_unpushed_changes(&mut repo, &user_git_config)?;
     }
-    Ok(did_work)
+    Ok(TaskChanges {
+        repos_updated: usize::from(did_work),
+        repos_fast_forwarded: usize::from(fast_forwarded),
+        ..changes
+    })
+}
+
+/**
+The branch to check out in a repo whose HEAD is detached, or `None` (after warning) if its update
+should be skipped, as `on_detached_head` is `skip` or the repo has uncommitted changes.
+*/
+fn detached_head_branch(
+    repo: &Repository,
+    git_config: &GitConfig,
+    default_remote_name: &str,
+) -> Result<Option<String>> {
+    let head_commit = short_head_commit(repo)?;
+    if git_config.on_detached_head.unwrap_or_default() == OnDetachedHead::Skip {
+        warn!(
+            "Not updating repo as HEAD is detached at {head_commit}. Check out a branch, or set \
+             `on_detached_head: checkout` to have up check one out."
+        );
+        return Ok(None);
+    }
+    let branch = match &git_config.branch {
+        Some(branch) => branch.clone(),
+        None => remote_default_branch(repo, default_remote_name)?,
+    };
+    if let Err(e) = ensure_repo_clean(repo) {
+        warn!(
+            "Not checking out branch '{branch}' or updating repo as HEAD is detached at \
+             {head_commit} and the repo isn't clean: {e}"
+        );
+        return Ok(None);
+    }
+    info!("Checking out branch '{branch}' as HEAD was detached at {head_commit}.");
+    Ok(Some(branch))
+}
+
+/// The branch `refs/remotes/<remote>/HEAD` points to, e.g. `main`.
+fn remote_default_branch(repo: &Repository, remote_name: &str) -> Result<String> {
+    let remote_head = format!("refs/remotes/{remote_name}/HEAD");
+    let target = repo
+        .find_reference(&remote_head)?
+        .symbolic_target()
+        .map(ToOwned::to_owned)
+        .ok_or(E::NoHeadSet)?;
+    Ok(target
+        .strip_prefix(&format!("refs/remotes/{remote_name}/"))
+        .unwrap_or(&target)
+        .to_owned())
 }
 
 /// Set up the specified remote in a git repo.
-fn set_up_remote(repo: &Repository, remote_config: &GitRemote) -> Result<bool> {
+fn set_up_remote(repo: &Repository, remote_config: &GitRemote, retry: &GitRetry) -> Result<bool> {
     let mut did_work = false;
     let remote_name = &remote_config.name;
 
@@ -227,8 +314,12 @@ fn set_up_remote(repo: &Repository, remote_config: &GitRemote) -> Result<bool> {
         did_work = true;
         repo.remote(remote_name, &remote_config.fetch_url)
     })?;
+    // libgit2 returns the URLs with any `insteadOf` rewrites from the git config applied.
+    let git_config = repo.config()?;
     if let Some(url) = remote.url() {
-        if url != remote_config.fetch_url {
+        if url != remote_config.fetch_url
+            && url != url_rewrite::fetch_url(&git_config, &remote_config.fetch_url)?
+        {
             debug!(
                 "Changing remote {remote_name} fetch URL from {url} to {new_url}",
                 new_url = remote_config.fetch_url
@@ -238,18 +329,27 @@ fn set_up_remote(repo: &Repository, remote_config: &GitRemote) -> Result<bool> {
         }
     }
     if let Some(push_url) = &remote_config.push_url {
-        repo.remote_set_pushurl(remote_name, Some(push_url))?;
-        did_work = true;
+        let current_push_url = remote.pushurl();
+        if current_push_url != Some(push_url)
+            && current_push_url != Some(&url_rewrite::push_url(&git_config, push_url)?)
+        {
+            repo.remote_set_pushurl(remote_name, Some(push_url))?;
+            did_work = true;
+        }
     }
     let fetch_refspecs: [&str; 0] = [];
     {
-        let mut count = 0;
-        remote
-            .fetch(
-                &fetch_refspecs,
-                Some(FetchOptions::new().remote_callbacks(remote_callbacks(&mut count))),
-                Some("up-rs automated fetch"),
-            )
+        retry
+            .retry_network(|| {
+                let mut count = 0;
+                let mut fetch_options = FetchOptions::new();
+                fetch_options.remote_callbacks(remote_callbacks(&mut count, retry));
+                remote.fetch(
+                    &fetch_refspecs,
+                    Some(&mut fetch_options),
+                    Some("up-rs automated fetch"),
+                )
+            })
             .map_err(|e| {
                 let extra_info = if e.to_string()
                     == "failed to acquire username/password from local configuration"
@@ -304,6 +404,9 @@ fn set_up_remote(repo: &Repository, remote_config: &GitRemote) -> Result<bool> {
         remote.name(),
         &default_branch
     );
+    if handle_renamed_default_branch(repo, &remote, &default_branch)? {
+        did_work = true;
+    }
     if set_remote_head(repo, &remote, &default_branch)? {
         did_work = true;
     };
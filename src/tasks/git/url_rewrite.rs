@@ -0,0 +1,96 @@
+//! Apply the `url.<base>.insteadOf` and `url.<base>.pushInsteadOf` rewrites from git config.
+//!
+//! libgit2 applies these rewrites when it loads a remote, so the URL it returns for a remote may
+//! not match the one in the up config even though git would treat them as the same.
+use color_eyre::eyre::Result;
+use git2::Config;
+
+/// A `url.<base>.insteadOf = <prefix>` rule: URLs starting with `prefix` are rewritten to start
+/// with `base` instead.
+#[derive(Debug, PartialEq, Eq)]
+struct UrlRewrite {
+    /// What to replace the prefix with.
+    base: String,
+    /// URL prefix to replace.
+    prefix: String,
+}
+
+/// Rewrite `url` as git would when fetching from it.
+pub(super) fn fetch_url(config: &Config, url: &str) -> Result<String> {
+    Ok(rewrite(url, &rewrites(config, "insteadof")?).unwrap_or_else(|| url.to_owned()))
+}
+
+/// Rewrite `url` as git would when pushing to it (`pushInsteadOf` takes precedence).
+pub(super) fn push_url(config: &Config, url: &str) -> Result<String> {
+    let push_rewrites = rewrites(config, "pushinsteadof")?;
+    let fetch_rewrites = rewrites(config, "insteadof")?;
+    Ok(rewrite(url, &push_rewrites)
+        .or_else(|| rewrite(url, &fetch_rewrites))
+        .unwrap_or_else(|| url.to_owned()))
+}
+
+/// Read all the `url.<base>.<key>` rewrites from `config`.
+fn rewrites(config: &Config, key: &str) -> Result<Vec<UrlRewrite>> {
+    let mut rewrites = Vec::new();
+    let mut entries = config.entries(Some(&format!(r"^url\..*\.{key}$")))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let (Some(name), Some(prefix)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        // Config keys are lowercased, but the base is the case-sensitive middle part.
+        let Some(base) = name
+            .strip_prefix("url.")
+            .and_then(|rest| rest.strip_suffix(&format!(".{key}")))
+        else {
+            continue;
+        };
+        rewrites.push(UrlRewrite {
+            base: base.to_owned(),
+            prefix: prefix.to_owned(),
+        });
+    }
+    Ok(rewrites)
+}
+
+/// Apply the rewrite with the longest matching prefix to `url`, if any match.
+fn rewrite(url: &str, rewrites: &[UrlRewrite]) -> Option<String> {
+    rewrites
+        .iter()
+        .filter(|rewrite| url.starts_with(&rewrite.prefix))
+        .max_by_key(|rewrite| rewrite.prefix.len())
+        .map(|rewrite| format!("{}{}", rewrite.base, &url[rewrite.prefix.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rewrite;
+    use super::UrlRewrite;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_rewrite() -> Result<()> {
+        let rewrites = [
+            UrlRewrite {
+                base: "git@github.com:".to_owned(),
+                prefix: "https://github.com/".to_owned(),
+            },
+            UrlRewrite {
+                base: "git@work.example.com:".to_owned(),
+                prefix: "https://github.com/work/".to_owned(),
+            },
+        ];
+        ensure_eq!(
+            Some("git@github.com:gibfahn/up-rs".to_owned()),
+            rewrite("https://github.com/gibfahn/up-rs", &rewrites)
+        );
+        // The longest matching prefix wins.
+        ensure_eq!(
+            Some("git@work.example.com:repo".to_owned()),
+            rewrite("https://github.com/work/repo", &rewrites)
+        );
+        ensure_eq!(None, rewrite("https://gitlab.com/gibfahn/up-rs", &rewrites));
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+/*!
+Log a heartbeat for tasks that have been quiet for a while, so unattended runs (e.g. bootstrapping
+in CI) don't look hung, and don't get killed by inactivity timeouts.
+
+```text
+INFO Task 'brew' still running, 12m elapsed, last output: ==> Pouring llvm--19.1.7.bottle.tar.gz
+```
+*/
+use crate::tasks::tui;
+use camino::Utf8PathBuf;
+use std::fs;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::info;
+
+/// How long a task can go without writing output before we log a heartbeat, unless
+/// `heartbeat_after` is set in `up.yaml`.
+pub(crate) const DEFAULT_HEARTBEAT_AFTER: Duration = Duration::from_mins(5);
+
+/// Logs heartbeats for a running task on a separate thread until this is dropped.
+#[derive(Debug)]
+pub(super) struct Heartbeat {
+    /// Dropping this tells the heartbeat thread to stop.
+    stop: Option<mpsc::Sender<()>>,
+    /// The heartbeat thread.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    /**
+    Start logging a heartbeat for task `name` whenever its `output_file` hasn't been written to
+    for `after`. A zero `after` disables heartbeats.
+
+    Without an `output_file` (e.g. with `--console`, where output goes to the terminal) we can't
+    tell when the task last wrote output, so we log a heartbeat every `after`.
+    */
+    pub(super) fn start(name: &str, output_file: Option<Utf8PathBuf>, after: Duration) -> Self {
+        if after.is_zero() {
+            return Self {
+                stop: None,
+                thread: None,
+            };
+        }
+        let (stop, stopped) = mpsc::channel();
+        let name = name.to_owned();
+        let thread = thread::spawn(move || {
+            let started = Instant::now();
+            let mut wait = after;
+            // Returns on a message or disconnect, i.e. when the task finishes.
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                let Some(output_file) = &output_file else {
+                    let elapsed = format_elapsed(started.elapsed());
+                    info!("Task '{name}' still running, {elapsed} elapsed.");
+                    continue;
+                };
+                let idle = fs::metadata(output_file)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .map_or_else(|| started.elapsed(), |idle| idle.min(started.elapsed()));
+                if let Some(remaining) = after.checked_sub(idle).filter(|d| !d.is_zero()) {
+                    wait = remaining;
+                    continue;
+                }
+                wait = after;
+                let elapsed = format_elapsed(started.elapsed());
+                if let Some(line) = tui::last_output_line(output_file) {
+                    info!("Task '{name}' still running, {elapsed} elapsed, last output: {line}");
+                } else {
+                    info!("Task '{name}' still running, {elapsed} elapsed, no output yet.");
+                }
+            }
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+/// Format a duration as whole minutes (e.g. `12m`), or seconds if it's less than a minute.
+pub(super) fn format_elapsed(duration: Duration) -> String {
+    match duration.as_secs() {
+        secs @ 0..60 => format!("{secs}s"),
+        secs => format!("{}m", secs / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_elapsed;
+    use super::Heartbeat;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::fs;
+    use std::time::Duration;
+    use std::time::Instant;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_format_elapsed() -> Result<()> {
+        ensure_eq!("0s", format_elapsed(Duration::from_millis(400)));
+        ensure_eq!("59s", format_elapsed(Duration::from_secs(59)));
+        ensure_eq!("1m", format_elapsed(Duration::from_mins(1)));
+        ensure_eq!("12m", format_elapsed(Duration::from_secs(12 * 60 + 59)));
+        Ok(())
+    }
+
+    /// Dropping the heartbeat stops its thread straight away, even with a long interval.
+    #[test]
+    fn test_heartbeat_stops_on_drop() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let output_file = temp_dir.join("output.txt");
+        fs::write(&output_file, "first line\nlast line\n")?;
+
+        let start = Instant::now();
+        drop(Heartbeat::start(
+            "test",
+            Some(output_file.clone()),
+            Duration::from_hours(1),
+        ));
+        drop(Heartbeat::start("test", None, Duration::from_hours(1)));
+        drop(Heartbeat::start("test", Some(output_file), Duration::ZERO));
+        ensure!(start.elapsed() < Duration::from_mins(1));
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! How tasks went in previous runs, read from the run directories that `up run` leaves behind.
+use crate::tasks::task::TaskRunRecord;
+use crate::tasks::task::TASK_STATUS_FILE;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use chrono::DateTime;
+use chrono::FixedOffset;
+use color_eyre::eyre::Result;
+use itertools::Itertools;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+
+/// The run directories of previous runs.
+pub(super) struct RunHistory {
+    /// Run directories, newest first.
+    run_dirs: Vec<Utf8PathBuf>,
+}
+
+/// The most recent run of a task.
+pub(super) struct LastRun {
+    /// When the run the task was part of started (RFC 3339), or the run directory name if it
+    /// isn't a timestamp.
+    pub(super) started: String,
+    /// The task's directory in the run directory.
+    pub(super) task_tempdir: Utf8PathBuf,
+    /// How the task finished, unless it wasn't recorded (e.g. if up was killed).
+    pub(super) record: Option<TaskRunRecord>,
+}
+
+impl RunHistory {
+    /// Read the run directories in `runs_dir`, which needn't exist.
+    pub(super) fn read(runs_dir: &Utf8Path) -> Result<Self> {
+        let mut run_dirs: Vec<Utf8PathBuf> = match runs_dir.read_dir_utf8() {
+            Ok(entries) => entries
+                .map_ok(camino::Utf8DirEntry::into_path)
+                .try_collect()?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        // Run dirs are named with timestamps, so sort newest first.
+        run_dirs.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(Self { run_dirs })
+    }
+
+    /// The run directories, newest first.
+    pub(super) fn run_dirs(&self) -> &[Utf8PathBuf] {
+        &self.run_dirs
+    }
+
+    /// The most recent run of the task called `name`, if it has been run.
+    pub(super) fn last_run(&self, name: &str) -> Option<LastRun> {
+        let (run_dir, task_tempdir) = self
+            .run_dirs
+            .iter()
+            .map(|run_dir| (run_dir, run_dir.join(name)))
+            .find(|(_, task_tempdir)| task_tempdir.is_dir())?;
+        let started = run_started(run_dir).map_or_else(
+            || run_dir.file_name().unwrap_or_default().to_owned(),
+            |time| time.to_rfc3339(),
+        );
+        Some(LastRun {
+            started,
+            record: TaskRunRecord::read(&task_tempdir).ok(),
+            task_tempdir,
+        })
+    }
+
+    /// The most recent record of each task in the newest `max_runs` runs, keyed by task name.
+    pub(super) fn latest_records(&self, max_runs: usize) -> Result<HashMap<String, TaskRunRecord>> {
+        let mut records = HashMap::new();
+        for run_dir in self.run_dirs.iter().take(max_runs) {
+            add_records(run_dir, run_dir, &mut records)?;
+        }
+        Ok(records)
+    }
+}
+
+/// When the run in `run_dir` started, if its name is a timestamp.
+pub(super) fn run_started(run_dir: &Utf8Path) -> Option<DateTime<FixedOffset>> {
+    // Run dir names are timestamps with `:` replaced by `_`.
+    DateTime::parse_from_rfc3339(&run_dir.file_name()?.replace('_', ":")).ok()
+}
+
+/**
+Add the records of the tasks in `dir` (the `run_dir` or a namespace directory in it) to `records`,
+unless a newer run already recorded them.
+*/
+fn add_records(
+    run_dir: &Utf8Path,
+    dir: &Utf8Path,
+    records: &mut HashMap<String, TaskRunRecord>,
+) -> Result<()> {
+    let entries = match dir.read_dir_utf8() {
+        Ok(entries) => entries,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let task_tempdir = entry.into_path();
+        if !task_tempdir.join(TASK_STATUS_FILE).is_file() {
+            add_records(run_dir, &task_tempdir, records)?;
+            continue;
+        }
+        let name = task_tempdir.strip_prefix(run_dir)?.to_string();
+        if let Entry::Vacant(entry) = records.entry(name) {
+            if let Ok(record) = TaskRunRecord::read(&task_tempdir) {
+                entry.insert(record);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunHistory;
+    use crate::tasks::task::TaskRunRecord;
+    use crate::tasks::task::TaskStatus;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use itertools::Itertools;
+    use std::fs;
+    use std::time::Duration;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_last_run() -> Result<()> {
+        let runs_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        ensure!(RunHistory::read(&runs_dir.join("missing"))?
+            .last_run("brew")
+            .is_none());
+
+        for run_dir in ["2024-01-01T00_00_00Z", "2024-01-02T00_00_00Z"] {
+            fs::create_dir_all(runs_dir.join(run_dir).join("brew"))?;
+        }
+        TaskRunRecord::new(&TaskStatus::Skipped, Duration::from_secs(3))
+            .write(&runs_dir.join("2024-01-01T00_00_00Z/brew"))?;
+        fs::create_dir_all(runs_dir.join("2024-01-01T00_00_00Z/git"))?;
+        TaskRunRecord::new(&TaskStatus::Skipped, Duration::from_secs(3))
+            .write(&runs_dir.join("2024-01-01T00_00_00Z/git"))?;
+
+        let history = RunHistory::read(&runs_dir)?;
+        let brew = history.last_run("brew");
+        ensure_eq!(
+            Some("2024-01-02T00:00:00+00:00"),
+            brew.as_ref().map(|run| run.started.as_str())
+        );
+        // The newest run was interrupted, so has no record.
+        ensure!(brew.as_ref().is_some_and(|run| run.record.is_none()));
+        let git = history.last_run("git");
+        ensure_eq!(
+            Some("skipped"),
+            git.as_ref()
+                .and_then(|run| run.record.as_ref())
+                .map(|record| record.status.as_str())
+        );
+        ensure!(history.last_run("missing").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_latest_records() -> Result<()> {
+        let runs_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let write = |run_dir: &str, name: &str, status: &str| -> Result<()> {
+            let task_tempdir = runs_dir.join(run_dir).join(name);
+            fs::create_dir_all(&task_tempdir)?;
+            TaskRunRecord {
+                status: status.to_owned(),
+                error: None,
+                duration: Duration::from_secs(1),
+            }
+            .write(&task_tempdir)?;
+            Ok(())
+        };
+        write("2024-01-01T00_00_00Z", "brew", "failed")?;
+        write("2024-01-01T00_00_00Z", "work/vpn", "skipped")?;
+        write("2024-01-02T00_00_00Z", "brew", "skipped")?;
+        write("2023-12-31T00_00_00Z", "git", "failed")?;
+
+        let history = RunHistory::read(&runs_dir)?;
+        let statuses = |max_runs| -> Result<Vec<(String, String)>> {
+            Ok(history
+                .latest_records(max_runs)?
+                .into_iter()
+                .map(|(name, record)| (name, record.status))
+                .sorted()
+                .collect())
+        };
+        ensure_eq!(
+            vec![
+                ("brew".to_owned(), "skipped".to_owned()),
+                ("work/vpn".to_owned(), "skipped".to_owned()),
+            ],
+            statuses(2)?
+        );
+        ensure_eq!(
+            Some(("git".to_owned(), "failed".to_owned())),
+            statuses(3)?.get(1).cloned()
+        );
+        Ok(())
+    }
+}
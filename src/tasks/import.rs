@@ -0,0 +1,555 @@
+/*!
+Convert the config of other dotfile managers into up tasks (`up import`).
+
+- GNU stow: each package directory becomes a `link` task, linking the package into the target
+  directory.
+- chezmoi: the source directory becomes a single `run_script` task that symlinks each managed file
+  (the `link` library can't rename files, and chezmoi source file names encode attributes like
+  `dot_` and `private_`). Templates, scripts, and encrypted files are skipped with a warning.
+- Homebrew Bundle: the Brewfile becomes a single `brew` library task with its taps, formulae,
+  casks, and Mac App Store apps. Other entry types are skipped with a warning.
+*/
+use self::ImportError as E;
+use crate::config::UpConfig;
+use crate::generate::GENERATED_PRELUDE_COMMENT;
+use crate::opts::ImportOptions;
+use crate::opts::ImportSource;
+use crate::opts::LinkOptions;
+use crate::tasks;
+use crate::tasks::brew::BrewConfig;
+use crate::tasks::brew::MasApp;
+use crate::tasks::task::TaskConfig;
+use crate::tasks::TasksDir;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use std::fmt::Write;
+use std::fs;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+use walkdir::WalkDir;
+
+/// Shell function used by the generated chezmoi task to link files without overwriting existing
+/// ones.
+const CHEZMOI_LINK_FUNCTION: &str = r#"set -euo pipefail
+
+# Link $1 to $2, unless $2 already exists and isn't a symlink.
+link() {
+  if [[ -e $2 && ! -L $2 ]]; then
+    echo "Not linking $2 as it already exists, move it away and re-run to link it." >&2
+    return
+  fi
+  mkdir -p "$(dirname "$2")"
+  ln -sfn "$1" "$2"
+}
+"#;
+
+/// The tasks directory next to the up.yaml (or `tasks_path`), where tasks are imported to by
+/// default.
+pub(crate) fn default_tasks_dir(config: &UpConfig) -> Result<Utf8PathBuf> {
+    if config.up_yaml_path.is_none() {
+        return Err(E::MissingTasksDir.into());
+    }
+    tasks::tasks_dir(config, TasksDir::Tasks)
+}
+
+/// Run the `up import` command, writing the imported tasks to `tasks_dir`.
+pub(crate) fn run(opts: &ImportOptions, tasks_dir: &Utf8Path) -> Result<()> {
+    let path = opts.path.canonicalize_utf8().map_err(|e| E::ReadSource {
+        path: opts.path.clone(),
+        source: e,
+    })?;
+
+    let tasks = match opts.from {
+        ImportSource::Stow => stow_tasks(&path, &opts.to)?,
+        ImportSource::Chezmoi => vec![("chezmoi".to_owned(), chezmoi_task(&path, &opts.to)?)],
+        ImportSource::HomebrewBundle => {
+            let brewfile = fs::read_to_string(&path).map_err(|e| E::ReadSource {
+                path: path.clone(),
+                source: e,
+            })?;
+            vec![("brew".to_owned(), brewfile_task(&brewfile)?)]
+        }
+    };
+
+    // Check everything before writing anything, so we don't leave a partial import behind.
+    let tasks: Vec<(Utf8PathBuf, TaskConfig)> = tasks
+        .into_iter()
+        .map(|(name, task)| (tasks_dir.join(format!("{name}.yaml")), task))
+        .collect();
+    if !opts.force {
+        if let Some((path, _)) = tasks.iter().find(|(path, _)| path.exists()) {
+            return Err(E::TaskExists { path: path.clone() }.into());
+        }
+    }
+
+    fs::create_dir_all(tasks_dir).map_err(|e| E::WriteTask {
+        path: tasks_dir.to_owned(),
+        source: e,
+    })?;
+    for (task_path, task) in &tasks {
+        let mut contents = GENERATED_PRELUDE_COMMENT.to_owned();
+        contents.push_str(&serde_yaml::to_string(task)?);
+        fs::write(task_path, contents).map_err(|e| E::WriteTask {
+            path: task_path.clone(),
+            source: e,
+        })?;
+        info!("Wrote {task_path}");
+    }
+    info!(
+        "Imported {count} task(s) from {path}, check them with `up plan`.",
+        count = tasks.len()
+    );
+    Ok(())
+}
+
+/// One `link` task per package (subdirectory) of the stow directory `stow_dir`.
+fn stow_tasks(stow_dir: &Utf8Path, to: &str) -> Result<Vec<(String, TaskConfig)>> {
+    let mut tasks = Vec::new();
+    for entry in stow_dir.read_dir_utf8().map_err(|e| E::ReadSource {
+        path: stow_dir.to_owned(),
+        source: e,
+    })? {
+        let entry = entry?;
+        let package = entry.file_name();
+        // Stow ignores hidden directories like `.git`.
+        if package.starts_with('.') || !entry.file_type()?.is_dir() {
+            debug!(
+                "Skipping {path} as it isn't a stow package.",
+                path = entry.path()
+            );
+            continue;
+        }
+        let has_dot_prefixed_files = WalkDir::new(entry.path())
+            .into_iter()
+            .filter_map(Result::ok)
+            .any(|e| e.file_name().to_string_lossy().starts_with("dot-"));
+        if has_dot_prefixed_files {
+            warn!(
+                "Stow package '{package}' contains 'dot-' files, which stow --dotfiles renames to \
+                 '.' files. The link task doesn't rename files, so rename them in the package."
+            );
+        }
+
+        let link_options = LinkOptions {
+            from_dir: entry.path().to_string(),
+            to_dir: to.to_owned(),
+            ..LinkOptions::default()
+        };
+        tasks.push((
+            format!("link-{package}"),
+            TaskConfig {
+                run_lib: Some("link".to_owned()),
+                description: Some(format!("Link the {package} stow package into {to}.")),
+                tags: Some(vec!["link".to_owned()]),
+                data: Some(serde_yaml::to_value(link_options)?),
+                ..TaskConfig::default()
+            },
+        ));
+    }
+    tasks.sort_by(|(a, _), (b, _)| a.cmp(b));
+    if tasks.is_empty() {
+        return Err(E::NothingToImport {
+            path: stow_dir.to_owned(),
+        }
+        .into());
+    }
+    Ok(tasks)
+}
+
+/// A `run_script` task that links each file in the chezmoi source directory `source_dir` into `to`.
+fn chezmoi_task(source_dir: &Utf8Path, to: &str) -> Result<TaskConfig> {
+    let mut script = CHEZMOI_LINK_FUNCTION.to_owned();
+    let mut linked_count = 0;
+
+    let mut it = WalkDir::new(source_dir).sort_by_file_name().into_iter();
+    while let Some(entry) = it.next() {
+        let entry = entry?;
+        let path = Utf8Path::from_path(entry.path()).ok_or(E::InvalidUtf8)?;
+        let Ok(relative_path) = path.strip_prefix(source_dir) else {
+            continue;
+        };
+        if relative_path.as_str().is_empty() {
+            continue;
+        }
+        // chezmoi ignores hidden files in the source directory (e.g. `.git`, `.chezmoiignore`).
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            if entry.file_type().is_dir() {
+                it.skip_current_dir();
+            }
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let mut target = Utf8PathBuf::new();
+        let mut components = relative_path.components().peekable();
+        let mut chezmoi_file = None;
+        while let Some(component) = components.next() {
+            if components.peek().is_some() {
+                target.push(chezmoi_dir_name(component.as_str()));
+            } else {
+                chezmoi_file = Some(ChezmoiFile::parse(component.as_str()));
+            }
+        }
+        let Some(file) = chezmoi_file else {
+            continue;
+        };
+        if let Some(reason) = file.unsupported {
+            warn!("Skipping chezmoi file {relative_path} as {reason}.");
+            continue;
+        }
+        target.push(&file.name);
+
+        let target = shell_target_path(to, &target);
+        if file.symlink {
+            let link_target = fs::read_to_string(path).map_err(|e| E::ReadSource {
+                path: path.to_owned(),
+                source: e,
+            })?;
+            _ = writeln!(
+                script,
+                "link {link_target} {target}",
+                link_target = shell_escape::escape(link_target.trim().into()),
+            );
+        } else {
+            if file.executable {
+                _ = writeln!(
+                    script,
+                    "chmod +x {}",
+                    shell_escape::escape(path.as_str().into())
+                );
+            }
+            _ = writeln!(
+                script,
+                "link {source} {target}",
+                source = shell_escape::escape(path.as_str().into()),
+            );
+        }
+        linked_count += 1;
+    }
+
+    if linked_count == 0 {
+        return Err(E::NothingToImport {
+            path: source_dir.to_owned(),
+        }
+        .into());
+    }
+    Ok(TaskConfig {
+        description: Some(format!("Link the files managed by chezmoi into {to}.")),
+        tags: Some(vec!["link".to_owned()]),
+        run_script: Some(script),
+        ..TaskConfig::default()
+    })
+}
+
+/// A file in a chezmoi source directory.
+#[derive(Debug, PartialEq, Eq)]
+struct ChezmoiFile {
+    /// Name of the file in the destination directory.
+    name: String,
+    /// Whether the file should be executable.
+    executable: bool,
+    /// Whether the file contains the target of a symlink, rather than the file contents.
+    symlink: bool,
+    /// Why the file can't be imported, if it can't.
+    unsupported: Option<&'static str>,
+}
+
+impl ChezmoiFile {
+    /// Parse the attributes out of a chezmoi source file name, e.g.
+    /// `private_executable_dot_script.sh`.
+    fn parse(source_name: &str) -> Self {
+        let mut name = source_name;
+        let mut file = Self {
+            name: String::new(),
+            executable: false,
+            symlink: false,
+            unsupported: None,
+        };
+
+        if name.ends_with(".tmpl") {
+            file.unsupported = Some("templates aren't supported");
+        }
+        for (prefix, reason) in [
+            ("run_", "scripts aren't supported"),
+            ("modify_", "modify scripts aren't supported"),
+            ("remove_", "removing files isn't supported"),
+            ("create_", "create-only files aren't supported"),
+        ] {
+            if name.starts_with(prefix) {
+                file.unsupported = Some(reason);
+            }
+        }
+        if let Some(rest) = name.strip_prefix("symlink_") {
+            file.symlink = true;
+            name = rest;
+        }
+        loop {
+            if let Some(rest) = name.strip_prefix("encrypted_") {
+                file.unsupported = Some("encrypted files aren't supported");
+                name = rest;
+            } else if let Some(rest) = name.strip_prefix("executable_") {
+                file.executable = true;
+                name = rest;
+            } else if let Some(rest) = ["private_", "readonly_", "empty_"]
+                .into_iter()
+                .find_map(|prefix| name.strip_prefix(prefix))
+            {
+                name = rest;
+            } else {
+                break;
+            }
+        }
+        file.name = chezmoi_literal_name(name.strip_suffix(".literal").unwrap_or(name));
+        file
+    }
+}
+
+/// Destination name for a chezmoi source directory name, e.g. `private_dot_ssh` -> `.ssh`.
+fn chezmoi_dir_name(source_name: &str) -> String {
+    let mut name = source_name;
+    while let Some(rest) = ["exact_", "private_", "readonly_", "external_", "remove_"]
+        .into_iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+    {
+        name = rest;
+    }
+    chezmoi_literal_name(name)
+}
+
+/// Handle the final `dot_` or `literal_` prefix of a chezmoi source name.
+fn chezmoi_literal_name(name: &str) -> String {
+    if let Some(rest) = name.strip_prefix("literal_") {
+        rest.to_owned()
+    } else if let Some(rest) = name.strip_prefix("dot_") {
+        format!(".{rest}")
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Shell-quoted path to `relative_path` inside `to`, keeping a leading `~` unquoted so the shell
+/// expands it.
+fn shell_target_path(to: &str, relative_path: &Utf8Path) -> String {
+    let (prefix, to) = match to.strip_prefix('~') {
+        Some(rest) => ("\"$HOME\"", rest.trim_start_matches('/')),
+        None => ("", to),
+    };
+    let path = Utf8Path::new(to).join(relative_path);
+    let path = if prefix.is_empty() {
+        path.to_string()
+    } else {
+        format!("/{path}")
+    };
+    format!("{prefix}{}", shell_escape::escape(path.into()))
+}
+
+/// A `brew` library task that installs everything in a Brewfile with the contents `brewfile`.
+fn brewfile_task(brewfile: &str) -> Result<TaskConfig> {
+    let mut brew_config = BrewConfig::default();
+
+    for line in brewfile.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((kind, rest)) = line.split_once(char::is_whitespace) else {
+            warn!("Skipping Brewfile line '{line}' as it couldn't be parsed.");
+            continue;
+        };
+        let Some((name, options)) = brewfile_string(rest) else {
+            warn!("Skipping Brewfile line '{line}' as it couldn't be parsed.");
+            continue;
+        };
+        let options = options.split('#').next().unwrap_or_default().trim();
+        if !options.is_empty() && matches!(kind, "tap" | "brew" | "cask") {
+            warn!("Ignoring options '{options}' for {kind} '{name}' in the Brewfile.");
+        }
+        match kind {
+            "tap" => brew_config.taps.push(name),
+            "brew" => brew_config.formulae.push(name),
+            "cask" => brew_config.casks.push(name),
+            "mas" => {
+                if let Some(id) = options
+                    .split_once("id:")
+                    .and_then(|(_, id)| id.trim().trim_end_matches(',').parse().ok())
+                {
+                    brew_config.mas.push(MasApp { name, id });
+                } else {
+                    warn!("Skipping Brewfile line '{line}' as it has no app id.");
+                }
+            }
+            _ => warn!("Skipping Brewfile line '{line}' as '{kind}' entries aren't supported."),
+        }
+    }
+
+    Ok(TaskConfig {
+        description: Some("Install the packages from the Homebrew Brewfile.".to_owned()),
+        tags: Some(vec!["brew".to_owned()]),
+        run_lib: Some("brew".to_owned()),
+        data: Some(serde_yaml::to_value(brew_config)?),
+        ..TaskConfig::default()
+    })
+}
+
+/// Parse the quoted string at the start of `s`, returning it and the rest of `s` after it.
+fn brewfile_string(s: &str) -> Option<(String, &str)> {
+    let s = s.trim_start();
+    let quote = s.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let (name, rest) = s.get(1..)?.split_once(quote)?;
+    Some((
+        name.to_owned(),
+        rest.trim_start().trim_start_matches(',').trim(),
+    ))
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum ImportError {
+    /// No up.yaml found to put the tasks next to, pass `--tasks-dir` to choose where they go.
+    MissingTasksDir,
+    /// Failed to read {path}.
+    ReadSource {
+        /// Path we failed to read.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Found nothing to import in {path}.
+    NothingToImport {
+        /// Path that was imported.
+        path: Utf8PathBuf,
+    },
+    /// Task file {path} already exists, pass `--force` to overwrite it.
+    TaskExists {
+        /// Path to the existing task file.
+        path: Utf8PathBuf,
+    },
+    /// Failed to write task file {path}.
+    WriteTask {
+        /// Path we failed to write.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Path was not valid UTF-8.
+    InvalidUtf8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::brewfile_task;
+    use super::chezmoi_dir_name;
+    use super::shell_target_path;
+    use super::stow_tasks;
+    use super::ChezmoiFile;
+    use crate::tasks::brew::BrewConfig;
+    use crate::tasks::brew::MasApp;
+    use camino::Utf8Path;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::fs;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_brewfile_task() -> Result<()> {
+        let brewfile = r#"
+            # Taps
+            tap "homebrew/cask-fonts"
+            brew "git"
+            brew 'ripgrep', args: ["with-pcre2"]
+            cask "firefox" # Browser
+            mas "Xcode", id: 497799835
+            vscode "rust-lang.rust-analyzer"
+        "#;
+        let task = brewfile_task(brewfile)?;
+        ensure_eq!(Some("brew"), task.run_lib.as_deref());
+        ensure_eq!(
+            BrewConfig {
+                taps: vec!["homebrew/cask-fonts".to_owned()],
+                formulae: vec!["git".to_owned(), "ripgrep".to_owned()],
+                casks: vec!["firefox".to_owned()],
+                mas: vec![MasApp {
+                    name: "Xcode".to_owned(),
+                    id: 497_799_835,
+                }],
+            },
+            serde_yaml::from_value(task.data.ok_or_else(|| eyre!("Expected brew data."))?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chezmoi_names() -> Result<()> {
+        ensure_eq!(".ssh", chezmoi_dir_name("private_dot_ssh"));
+        ensure_eq!("dot_config", chezmoi_dir_name("literal_dot_config"));
+        ensure_eq!(
+            ChezmoiFile {
+                name: ".script.sh".to_owned(),
+                executable: true,
+                symlink: false,
+                unsupported: None,
+            },
+            ChezmoiFile::parse("private_executable_dot_script.sh")
+        );
+        ensure_eq!(
+            ChezmoiFile {
+                name: ".vimrc".to_owned(),
+                executable: false,
+                symlink: true,
+                unsupported: None,
+            },
+            ChezmoiFile::parse("symlink_dot_vimrc")
+        );
+        ensure_eq!(
+            Some("templates aren't supported"),
+            ChezmoiFile::parse("dot_gitconfig.tmpl").unsupported
+        );
+        ensure_eq!(
+            Some("scripts aren't supported"),
+            ChezmoiFile::parse("run_once_install.sh").unsupported
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_shell_target_path() -> Result<()> {
+        let path = Utf8Path::new(".config/my file");
+        ensure_eq!("\"$HOME\"'/.config/my file'", shell_target_path("~", path));
+        ensure_eq!(
+            "/tmp/home/.config/fish",
+            shell_target_path("/tmp/home", Utf8Path::new(".config/fish"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stow_tasks() -> Result<()> {
+        let stow_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        for dir in ["git", "fish/.config/fish", ".git"] {
+            fs::create_dir_all(stow_dir.join(dir))?;
+        }
+        fs::write(stow_dir.join("README.md"), "")?;
+
+        let tasks = stow_tasks(&stow_dir, "~")?;
+        ensure_eq!(
+            vec!["link-fish", "link-git"],
+            tasks
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+        let (_, fish_task) = tasks
+            .first()
+            .ok_or_else(|| eyre!("Expected a fish task."))?;
+        ensure_eq!(
+            format!("from_dir: {stow_dir}/fish\nto_dir: '~'\n"),
+            serde_yaml::to_string(&fish_task.data)?
+        );
+        Ok(())
+    }
+}
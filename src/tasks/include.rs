@@ -0,0 +1,301 @@
+/*!
+Tasks included from config fragments served over HTTPS (the `include` field of `up.yaml`).
+
+This lets a team share a base set of tasks centrally, while each person layers their own tasks on
+top (a local task with the same name as an included one replaces it).
+
+```yaml
+include:
+  - url: https://example.com/team/up-tasks.yaml
+    sha256: 3a7bd3e2360a3d29eea436fcfb7e44c735d117c42d1c1835420b6b9942dd4f1b
+```
+
+A fragment is a YAML file mapping task names to task configs (the same as the contents of a task
+file):
+
+```yaml
+tasks:
+  rust:
+    run_lib: rustup
+  brew:
+    run_lib: brew
+    data: ...
+```
+
+Fragments are cached in the up cache dir (`--cache-dir`, by default `$XDG_CACHE_HOME/up`). If the
+`sha256` is set, the download must match it, and a matching cached copy is used without downloading
+the fragment again. Otherwise the fragment is downloaded each run, falling back to the cached copy
+if the download fails.
+*/
+use self::IncludeError as E;
+use crate::tasks::task::Task;
+use crate::tasks::task::TaskConfig;
+use crate::utils::files;
+use crate::utils::yaml;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use ring::digest;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// Directory (relative to the up cache dir) that included fragments are cached in.
+const INCLUDES_DIR: &str = "includes";
+
+/// A config fragment to include tasks from, in the `include` field of `up.yaml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeConfig {
+    /// HTTPS URL of the fragment.
+    pub url: String,
+    /// Expected SHA-256 hash (hex-encoded) of the fragment.
+    pub sha256: Option<String>,
+}
+
+/// The contents of an included fragment.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fragment {
+    /// Task configs, keyed by task name.
+    #[serde(default)]
+    tasks: BTreeMap<String, TaskConfig>,
+}
+
+/// Add the tasks from the `includes` to `tasks`, unless a task with the same name is already
+/// there. If `offline`, only cached copies of the includes are used.
+pub(crate) fn add_included_tasks(
+    includes: &[IncludeConfig],
+    cache_dir: &Utf8Path,
+    offline: bool,
+    tasks: &mut HashMap<String, Task>,
+) -> Result<()> {
+    let includes_dir = cache_dir.join(INCLUDES_DIR);
+    for include in includes {
+        let path = fetch(include, &includes_dir, offline)?;
+        let contents = Task::read_file(&path)?;
+        let fragment: Fragment = yaml::from_str(&contents).map_err(|e| E::InvalidYaml {
+            url: include.url.clone(),
+            source: e,
+        })?;
+        for (name, config) in fragment.tasks {
+            if tasks.contains_key(&name) {
+                debug!(
+                    "Not using task '{name}' from {url} as a local task has the same name.",
+                    url = include.url
+                );
+                continue;
+            }
+            let mut task = Task::from_config(&path, config)?;
+            task.name.clone_from(&name);
+            tasks.insert(name, task);
+        }
+    }
+    Ok(())
+}
+
+/// Get the path to an up to date copy of the fragment, downloading it if needed (and not
+/// `offline`).
+fn fetch(include: &IncludeConfig, cache_dir: &Utf8Path, offline: bool) -> Result<Utf8PathBuf> {
+    let url = &include.url;
+    if !url.starts_with("https://") {
+        return Err(E::NotHttps { url: url.clone() }.into());
+    }
+    let cache_path = cache_path(cache_dir, url);
+    let cached = fs::read(&cache_path).ok();
+
+    if let (Some(expected), Some(cached)) = (&include.sha256, &cached) {
+        if sha256(cached).eq_ignore_ascii_case(expected) {
+            debug!("Using cached copy of {url} at {cache_path}");
+            return Ok(cache_path);
+        }
+    }
+
+    if offline {
+        // A pinned hash means the cached copy (if any) didn't match it.
+        if include.sha256.is_none() && cached.is_some() {
+            debug!("Using cached copy of {url} at {cache_path} as running offline.");
+            return Ok(cache_path);
+        }
+        return Err(E::Offline { url: url.clone() }.into());
+    }
+
+    info!("Downloading included config {url}");
+    let contents = match download(url) {
+        Ok(contents) => contents,
+        Err(e) => {
+            // A pinned hash means the cached copy (if any) didn't match it.
+            if include.sha256.is_none() && cached.is_some() {
+                warn!("Failed to download {url}, using cached copy at {cache_path}: {e:?}");
+                return Ok(cache_path);
+            }
+            return Err(E::Download {
+                url: url.clone(),
+                source: e,
+            }
+            .into());
+        }
+    };
+    if let Some(expected) = &include.sha256 {
+        let actual = sha256(&contents);
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(E::HashMismatch {
+                url: url.clone(),
+                expected: expected.clone(),
+                actual,
+            }
+            .into());
+        }
+    }
+    files::create_dir_all(cache_dir)?;
+    fs::write(&cache_path, &contents).map_err(|e| E::WriteCache {
+        path: cache_path.clone(),
+        source: e,
+    })?;
+    Ok(cache_path)
+}
+
+/// Download the contents of `url`.
+fn download(url: &str) -> Result<Vec<u8>> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
+    Ok(response.bytes()?.to_vec())
+}
+
+/// Path the fragment at `url` is cached at.
+fn cache_path(cache_dir: &Utf8Path, url: &str) -> Utf8PathBuf {
+    cache_dir.join(format!("{}.yaml", sha256(url.as_bytes())))
+}
+
+/// Hex-encoded SHA-256 hash of `contents`.
+fn sha256(contents: &[u8]) -> String {
+    hex::encode(digest::digest(&digest::SHA256, contents))
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum IncludeError {
+    /// Included config `{url}` must be an https:// URL.
+    NotHttps {
+        /// The URL.
+        url: String,
+    },
+    /// Can't download included config `{url}` when offline, and there's no usable cached copy.
+    Offline {
+        /// The URL.
+        url: String,
+    },
+    /// Failed to download included config `{url}`.
+    Download {
+        /// The URL.
+        url: String,
+        /// Source error.
+        source: color_eyre::eyre::Error,
+    },
+    /**
+    Included config `{url}` has SHA-256 hash {actual}, but {expected} was expected.
+      If the change is expected, update the `sha256` in your up.yaml.
+    */
+    HashMismatch {
+        /// The URL.
+        url: String,
+        /// The hash set in the up.yaml.
+        expected: String,
+        /// The hash of the downloaded file.
+        actual: String,
+    },
+    /// Failed to cache included config at `{path}`.
+    WriteCache {
+        /// The cache path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Included config `{url}` is invalid.
+    InvalidYaml {
+        /// The URL.
+        url: String,
+        /// Source error.
+        source: serde_yaml::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::add_included_tasks;
+    use super::cache_path;
+    use super::sha256;
+    use super::IncludeConfig;
+    use super::INCLUDES_DIR;
+    use crate::tasks::task::Task;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::collections::HashMap;
+    use std::fs;
+    use testutils::ensure_eq;
+
+    /// A cached fragment matching the pinned hash is used without downloading it.
+    #[test]
+    fn test_include_cached() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let cache_dir = temp_dir.join(INCLUDES_DIR);
+        fs::create_dir_all(&cache_dir)?;
+
+        // Unreachable, so the test fails if it's downloaded.
+        let url = "https://up-rs.invalid/team.yaml";
+        let contents = "tasks:\n  shared:\n    run_cmd: [true]\n  local:\n    run_cmd: [false]\n";
+        fs::write(cache_path(&cache_dir, url), contents)?;
+
+        let local_task_path = temp_dir.join("local.yaml");
+        fs::write(&local_task_path, "run_cmd: [echo]\n")?;
+        let mut tasks = HashMap::from([("local".to_owned(), Task::from(&local_task_path)?)]);
+
+        let includes = [IncludeConfig {
+            url: url.to_owned(),
+            sha256: Some(sha256(contents.as_bytes())),
+        }];
+        add_included_tasks(&includes, &temp_dir, false, &mut tasks)?;
+        ensure_eq!(2, tasks.len());
+        let run_cmd = |name: &str| tasks.get(name).and_then(|t| t.config.run_cmd.clone());
+        ensure_eq!(Some(vec!["true".to_owned()]), run_cmd("shared"));
+        // Local tasks replace included ones.
+        ensure_eq!(Some(vec!["echo".to_owned()]), run_cmd("local"));
+
+        // If the hash doesn't match the fragment is downloaded again, which fails.
+        let includes = [IncludeConfig {
+            url: url.to_owned(),
+            sha256: Some(sha256(b"other contents")),
+        }];
+        ensure!(add_included_tasks(&includes, &temp_dir, false, &mut HashMap::new()).is_err());
+        // Offline, the cached copy can't be used as it doesn't match the hash.
+        let err = add_included_tasks(&includes, &temp_dir, true, &mut HashMap::new())
+            .err()
+            .ok_or_else(|| color_eyre::eyre::eyre!("Expected an error when offline."))?;
+        ensure!(err.to_string().contains("offline"), "{err}");
+        // Without a pinned hash the cached copy is used.
+        let includes = [IncludeConfig {
+            url: url.to_owned(),
+            sha256: None,
+        }];
+        let mut tasks = HashMap::new();
+        add_included_tasks(&includes, &temp_dir, true, &mut tasks)?;
+        ensure_eq!(2, tasks.len());
+
+        // Only HTTPS URLs are allowed.
+        let includes = [IncludeConfig {
+            url: "http://example.com/team.yaml".to_owned(),
+            sha256: None,
+        }];
+        let err = add_included_tasks(&includes, &temp_dir, false, &mut HashMap::new())
+            .err()
+            .ok_or_else(|| color_eyre::eyre::eyre!("Expected an error for a http:// URL."))?;
+        ensure!(err.to_string().contains("https://"), "{err}");
+        Ok(())
+    }
+}
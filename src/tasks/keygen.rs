@@ -0,0 +1,347 @@
+/*!
+The `keygen` library task, for setting up an SSH key on a new machine.
+
+Generates an ed25519 SSH key if there isn't one, makes sure its permissions are ones `ssh` will
+accept, adds it to the ssh agent (and on macOS the keychain), and when the key is new prints (or
+uploads to GitHub with `gh`) the public key.
+
+Make it a bootstrap task so it runs before your git tasks, and they can authenticate:
+
+```yaml
+run_lib: keygen
+bootstrap: true
+data:
+  github_title: "$USER laptop"
+```
+*/
+use self::KeygenError as E;
+use crate::cmd;
+use crate::exec::UpDuct;
+use crate::tasks::resolve_config_path;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TaskError;
+use crate::utils::files;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::env;
+use std::fs;
+use std::fs::DirBuilder;
+use std::fs::Permissions;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::PermissionsExt;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// Default path of the private key.
+const DEFAULT_KEY_PATH: &str = "~/.ssh/id_ed25519";
+
+/// Permissions `ssh` requires for a private key.
+const PRIVATE_KEY_MODE: u32 = 0o600;
+/// Permissions for a public key.
+const PUBLIC_KEY_MODE: u32 = 0o644;
+/// Permissions for the directory containing the key, if we create it.
+const KEY_DIR_MODE: u32 = 0o700;
+
+/// Configuration for a keygen run library task.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeygenConfig {
+    /// Path of the private key (the public key is `<path>.pub`), defaults to `~/.ssh/id_ed25519`.
+    #[serde(default = "default_key_path")]
+    pub path: String,
+    /// Comment for a generated key, defaults to ssh-keygen's `<user>@<hostname>`.
+    pub comment: Option<String>,
+    /// Whether to add the key to the ssh agent (and on macOS the keychain), defaults to true.
+    #[serde(default = "add_to_agent_default")]
+    pub add_to_agent: bool,
+    /// If set, upload a newly generated public key to GitHub with `gh ssh-key add` using this
+    /// title. Otherwise the public key is printed so you can add it yourself.
+    pub github_title: Option<String>,
+}
+
+impl Default for KeygenConfig {
+    fn default() -> Self {
+        Self {
+            path: default_key_path(),
+            comment: None,
+            add_to_agent: add_to_agent_default(),
+            github_title: None,
+        }
+    }
+}
+
+/// Serde needs a function to set a default, so this sets the default key path.
+fn default_key_path() -> String {
+    DEFAULT_KEY_PATH.to_owned()
+}
+
+/// Serde needs a function to set a default, so this sets a default of true.
+const fn add_to_agent_default() -> bool {
+    true
+}
+
+impl ResolveEnv for KeygenConfig {
+    fn resolve_env<F>(&mut self, env_fn: F) -> Result<(), TaskError>
+    where
+        F: Fn(&str) -> Result<String, TaskError>,
+    {
+        self.path = resolve_config_path(&self.path, &env_fn)?.into_string();
+        if let Some(comment) = &self.comment {
+            self.comment = Some(env_fn(comment)?);
+        }
+        if let Some(github_title) = &self.github_title {
+            self.github_title = Some(env_fn(github_title)?);
+        }
+        Ok(())
+    }
+}
+
+/// Run a keygen run library task.
+pub(crate) fn run(config: KeygenConfig) -> Result<TaskStatus> {
+    let key_path = Utf8PathBuf::from(config.path);
+    let public_key_path = Utf8PathBuf::from(format!("{key_path}.pub"));
+    let mut did_work = false;
+
+    let key_dir = files::parent(&key_path)?;
+    if !key_dir.exists() {
+        debug!("Creating key directory {key_dir}");
+        DirBuilder::new()
+            .recursive(true)
+            .mode(KEY_DIR_MODE)
+            .create(key_dir)
+            .map_err(|e| E::CreateDir {
+                path: key_dir.to_owned(),
+                source: e,
+            })?;
+        did_work = true;
+    }
+
+    let generated = !key_path.exists();
+    if generated {
+        info!("Generating SSH key {key_path}");
+        let mut args = vec!["-t", "ed25519", "-q", "-N", "", "-f", key_path.as_str()];
+        if let Some(comment) = &config.comment {
+            args.extend(["-C", comment.as_str()]);
+        }
+        crate::exec::cmd("ssh-keygen", &args)
+            .run_with(Expression::stdout_to_stderr)
+            .map_err(|e| E::Generate {
+                path: key_path.clone(),
+                source: e,
+            })?;
+        did_work = true;
+    } else if !public_key_path.exists() {
+        info!("Recreating missing public key {public_key_path} from {key_path}");
+        let public_key = cmd!("ssh-keygen", "-y", "-f", &key_path)
+            .read()
+            .map_err(|e| E::Generate {
+                path: public_key_path.clone(),
+                source: e,
+            })?;
+        fs::write(&public_key_path, format!("{public_key}\n")).map_err(|e| E::Write {
+            path: public_key_path.clone(),
+            source: e,
+        })?;
+        did_work = true;
+    }
+
+    did_work |= set_mode(&key_path, PRIVATE_KEY_MODE)?;
+    did_work |= set_mode(&public_key_path, PUBLIC_KEY_MODE)?;
+
+    let public_key = fs::read_to_string(&public_key_path).map_err(|e| E::Read {
+        path: public_key_path.clone(),
+        source: e,
+    })?;
+    let public_key = public_key.trim();
+
+    if config.add_to_agent {
+        did_work |= add_to_agent(&key_path, public_key)?;
+    }
+
+    if generated {
+        if let Some(title) = &config.github_title {
+            info!("Uploading public key {public_key_path} to GitHub as '{title}'");
+            cmd!("gh", "ssh-key", "add", &public_key_path, "--title", title)
+                .run_with(Expression::stdout_to_stderr)
+                .map_err(|e| E::Upload {
+                    path: public_key_path.clone(),
+                    source: e,
+                })?;
+        } else {
+            info!(
+                "Generated SSH key, add the public key to the services you use (e.g. \
+                 https://github.com/settings/ssh/new):\n{public_key}"
+            );
+        }
+    }
+
+    if did_work {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    } else {
+        Ok(TaskStatus::Skipped)
+    }
+}
+
+/// Set the permissions of `path` to `mode`, returning whether they changed.
+fn set_mode(path: &Utf8Path, mode: u32) -> Result<bool, E> {
+    let current_mode = path
+        .metadata()
+        .map_err(|e| E::Permissions {
+            path: path.to_owned(),
+            source: e,
+        })?
+        .permissions()
+        .mode()
+        & 0o777;
+    if current_mode == mode {
+        return Ok(false);
+    }
+    info!("Changing permissions of {path} from {current_mode:o} to {mode:o}");
+    fs::set_permissions(path, Permissions::from_mode(mode)).map_err(|e| E::Permissions {
+        path: path.to_owned(),
+        source: e,
+    })?;
+    Ok(true)
+}
+
+/// Add the key at `key_path` to the ssh agent if it isn't already there, returning whether we
+/// added it.
+fn add_to_agent(key_path: &Utf8Path, public_key: &str) -> Result<bool, E> {
+    if env::var_os("SSH_AUTH_SOCK").is_none() {
+        warn!("Not adding {key_path} to the ssh agent as SSH_AUTH_SOCK isn't set.");
+        return Ok(false);
+    }
+    // Public keys are `<type> <base64 key> <comment>`, the agent may have a different comment.
+    let key_data = public_key.split_whitespace().nth(1).unwrap_or(public_key);
+    // `ssh-add -L` fails if the agent has no keys, which just means ours isn't there.
+    let agent_keys = cmd!("ssh-add", "-L")
+        .stderr_null()
+        .unchecked()
+        .read()
+        .unwrap_or_default();
+    if agent_keys
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(key_data))
+    {
+        debug!("Key {key_path} is already in the ssh agent.");
+        return Ok(false);
+    }
+
+    let mut args = Vec::new();
+    if cfg!(target_os = "macos") {
+        args.push("--apple-use-keychain");
+    }
+    args.push(key_path.as_str());
+    crate::exec::cmd("ssh-add", &args)
+        .run_with(Expression::stdout_to_stderr)
+        .map_err(|e| E::AddToAgent {
+            path: key_path.to_owned(),
+            source: e,
+        })?;
+    Ok(true)
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum KeygenError {
+    /// Failed to create key directory `{path}`.
+    CreateDir {
+        /// Directory path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to generate SSH key `{path}`.
+    Generate {
+        /// Key path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to write public key `{path}`.
+    Write {
+        /// Public key path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to read public key `{path}`.
+    Read {
+        /// Public key path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to set the permissions of `{path}`.
+    Permissions {
+        /// Key path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to add `{path}` to the ssh agent.
+    AddToAgent {
+        /// Key path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to upload public key `{path}` to GitHub, is `gh` installed and logged in?
+    Upload {
+        /// Public key path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeygenConfig;
+    use crate::tasks::task::TaskStatus;
+    use color_eyre::Result;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use testutils::ensure_eq;
+
+    /// Generating a key creates it with the right permissions, and running again does nothing.
+    #[test]
+    fn test_keygen() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let key_path = temp_dir.join("ssh/id_ed25519");
+        let config = || KeygenConfig {
+            path: key_path.to_string(),
+            comment: Some("up test key".to_owned()),
+            add_to_agent: false,
+            github_title: None,
+        };
+
+        let status = super::run(config())?;
+        ensure_eq!(true, matches!(status, TaskStatus::Passed(_)));
+        let mode = |path| -> Result<u32> { Ok(fs::metadata(path)?.permissions().mode() & 0o777) };
+        ensure_eq!(0o700, mode(temp_dir.join("ssh"))?);
+        ensure_eq!(0o600, mode(key_path.clone())?);
+        let public_key = fs::read_to_string(format!("{key_path}.pub"))?;
+        ensure_eq!(true, public_key.starts_with("ssh-ed25519 "));
+        ensure_eq!(true, public_key.trim().ends_with(" up test key"));
+
+        let status = super::run(config())?;
+        ensure_eq!(true, matches!(status, TaskStatus::Skipped));
+
+        // Loose permissions are fixed.
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o644))?;
+        let status = super::run(config())?;
+        ensure_eq!(true, matches!(status, TaskStatus::Passed(_)));
+        ensure_eq!(0o600, mode(key_path.clone())?);
+        Ok(())
+    }
+}
@@ -1,9 +1,16 @@
 //! The link library task.
+use crate::cmd;
+use crate::exec::UpDuct;
 use crate::opts::LinkOptions;
+use crate::opts::OnConflict;
+use crate::tasks;
+use crate::tasks::resolve_config_path;
+use crate::tasks::task::TaskChanges;
 use crate::tasks::task::TaskStatus;
 use crate::tasks::ResolveEnv;
 use crate::tasks::TaskError;
 use crate::utils::files;
+use crate::utils::progress::task_progress;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use chrono::DateTime;
@@ -14,6 +21,12 @@ use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use displaydoc::Display;
+use duct::Expression;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::io;
 use std::io::ErrorKind;
@@ -23,7 +36,6 @@ use tracing::debug;
 use tracing::info;
 use tracing::trace;
 use tracing::warn;
-use walkdir::DirEntry;
 use walkdir::WalkDir;
 
 impl ResolveEnv for LinkOptions {
@@ -31,28 +43,48 @@ impl ResolveEnv for LinkOptions {
     where
         F: Fn(&str) -> Result<String, TaskError>,
     {
-        self.from_dir = env_fn(&self.from_dir)?;
-        self.to_dir = env_fn(&self.to_dir)?;
+        self.from_dir = resolve_config_path(&self.from_dir, &env_fn)?.into_string();
+        self.to_dir = resolve_config_path(&self.to_dir, &env_fn)?.into_string();
+        for command in self.on_change.values_mut() {
+            *command = env_fn(command)?;
+        }
         Ok(())
     }
 }
 
+/**
+Run the `up link` command with the `config` passed on the command line.
+
+`~` and env vars in the options are expanded as in a task's config, so the default `--to` of `~` is
+the `--home` directory if one was passed.
+*/
+pub(crate) fn run_from_options(
+    mut config: LinkOptions,
+    backup_dir: &Utf8Path,
+) -> Result<TaskStatus> {
+    let env: HashMap<String, String> = env::vars().collect();
+    config.resolve_env(|s| tasks::resolve_env_value(s, &env))?;
+    run(config, backup_dir)
+}
+
 /// Symlink everything from `to_dir` (default: ~/code/dotfiles/) into `from_dir`
-/// (default: ~). Anything that would be overwritten is copied into `backup_dir`
-/// (default: `up_dir/backup/link/`).
+/// (default: ~). Anything that would be overwritten is moved into the `link`
+/// subdirectory of `backup_dir` (the backup directory for this run).
 ///
 /// Basically you put your dotfiles in ~/code/dotfiles/, in the same structure
 /// they were in relative to ~. Then if you want to edit your .bashrc (for
 /// example) you just edit ~/.bashrc, and as it's a symlink it'll actually edit
 /// ~/code/dotfiles/.bashrc. Then you can add and commit that change in ~/code/
 /// dotfiles.
-pub(crate) fn run(config: LinkOptions, up_dir: &Utf8Path) -> Result<TaskStatus> {
+///
+/// The `on_change` commands for any links that were created or changed are run at the end.
+pub(crate) fn run(config: LinkOptions, backup_dir: &Utf8Path) -> Result<TaskStatus> {
     let now: DateTime<Utc> = Utc::now();
     debug!("UTC time is: {now}");
 
     let from_dir = Utf8PathBuf::from(config.from_dir);
     let to_dir = Utf8PathBuf::from(config.to_dir);
-    let backup_dir = up_dir.join("backup/link");
+    let backup_dir = backup_dir.join("link");
 
     let from_dir = resolve_directory(from_dir, "From")?;
     let to_dir = resolve_directory(to_dir, "To")?;
@@ -77,20 +109,60 @@ pub(crate) fn run(config: LinkOptions, up_dir: &Utf8Path) -> Result<TaskStatus>
             .collect::<Result<Vec<_>>>()
     );
 
-    let mut work_done = false;
-    // For each non-directory file in from_dir.
-    for from_path in WalkDir::new(&from_dir)
+    // For each non-directory file in from_dir, its relative path and what the link should point to.
+    let mut links = Vec::new();
+    for entry in WalkDir::new(&from_dir)
         .min_depth(1)
         .into_iter()
         .filter_map(Result::ok)
         .filter(|f| !f.file_type().is_dir())
     {
-        let rel_path = Utf8Path::from_path(from_path.path())
-            .ok_or_else(|| eyre!("Invalid path {from_path:?}"))?
-            .strip_prefix(&from_dir)?;
-        create_parent_dir(&to_dir, rel_path, &backup_dir)?;
-        if link_path(&from_path, &to_dir, rel_path, &backup_dir)? {
-            work_done = true;
+        let from_path = Utf8Path::from_path(entry.path())
+            .ok_or_else(|| eyre!("Invalid UTF-8 in path {entry:?}"))?;
+        let rel_path = from_path.strip_prefix(&from_dir)?.to_owned();
+        let link_target = if config.relative {
+            relative_path(from_path, get_parent_path(&to_dir.join(&rel_path))?)
+        } else {
+            from_path.to_owned()
+        };
+        links.push((rel_path, link_target));
+    }
+
+    if config.on_conflict == OnConflict::Fail {
+        let conflicts: BTreeSet<Utf8PathBuf> = links
+            .iter()
+            .filter_map(|(rel_path, link_target)| find_conflict(&to_dir, rel_path, link_target))
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(LinkError::Conflicts {
+                paths: conflicts.iter().join("\n  "),
+            }
+            .into());
+        }
+    }
+
+    let mut changed_paths = Vec::new();
+    let total = links.len() as u64;
+    for (index, (rel_path, link_target)) in (1..).zip(&links) {
+        task_progress(index, total, rel_path.as_str());
+        if config.on_conflict == OnConflict::Skip {
+            if let Some(conflict) = find_conflict(&to_dir, rel_path, link_target) {
+                warn!(
+                    "Not linking {to_path} as {conflict} already exists.",
+                    to_path = to_dir.join(rel_path)
+                );
+                continue;
+            }
+        }
+        create_parent_dir(&to_dir, rel_path, &backup_dir, config.on_conflict)?;
+        if link_path(
+            link_target,
+            &to_dir,
+            rel_path,
+            &backup_dir,
+            config.on_conflict,
+        )? {
+            changed_paths.push(rel_path.as_path());
         }
     }
 
@@ -101,7 +173,14 @@ pub(crate) fn run(config: LinkOptions, up_dir: &Utf8Path) -> Result<TaskStatus>
         }
 
         Err(e) => warn!("Backup dir {backup_dir} non-empty, check contents: {e:?}"),
-        Ok(()) => (),
+        Ok(()) => {
+            // Also remove this run's backup dir if nothing else was backed up in it.
+            if let Some(run_backup_dir) = backup_dir.parent() {
+                if let Err(e) = fs::remove_dir(run_backup_dir) {
+                    trace!("Not removing run backup dir {run_backup_dir}: {e}");
+                }
+            }
+        }
     }
 
     debug!(
@@ -120,13 +199,48 @@ pub(crate) fn run(config: LinkOptions, up_dir: &Utf8Path) -> Result<TaskStatus>
         );
     }
 
-    if work_done {
-        Ok(TaskStatus::Passed)
-    } else {
+    for (path, command) in on_change_commands(&config.on_change, &links, &changed_paths) {
+        info!("Running on_change command for {path}: {command}");
+        cmd!("sh", "-c", command)
+            .run_with(Expression::stdout_to_stderr)
+            .map_err(|e| LinkError::OnChange {
+                path: path.to_owned(),
+                source: e,
+            })?;
+    }
+
+    if changed_paths.is_empty() {
         Ok(TaskStatus::Skipped)
+    } else {
+        Ok(TaskStatus::Passed(TaskChanges {
+            files_linked: changed_paths.len(),
+            ..TaskChanges::default()
+        }))
     }
 }
 
+/**
+The `on_change` commands (and the paths they're for) to run for the `changed` links, in the order
+they're configured. Warns about `on_change` paths that don't match any of the `links`.
+*/
+fn on_change_commands<'a>(
+    on_change: &'a BTreeMap<String, String>,
+    links: &[(Utf8PathBuf, Utf8PathBuf)],
+    changed: &[&Utf8Path],
+) -> Vec<(&'a str, &'a str)> {
+    on_change
+        .iter()
+        .filter(|(path, _)| {
+            let path = Utf8Path::new(path);
+            if !links.iter().any(|(rel_path, _)| rel_path.starts_with(path)) {
+                warn!("on_change path {path} doesn't match any files to link.");
+            }
+            changed.iter().any(|rel_path| rel_path.starts_with(path))
+        })
+        .map(|(path, command)| (path.as_str(), command.as_str()))
+        .collect()
+}
+
 /// Ensure dir exists, and resolve symlinks to find it's canonical path.
 fn resolve_directory(dir_path: Utf8PathBuf, name: &str) -> Result<Utf8PathBuf> {
     ensure!(
@@ -146,8 +260,45 @@ fn resolve_directory(dir_path: Utf8PathBuf, name: &str) -> Result<Utf8PathBuf> {
     })
 }
 
+/**
+The first existing path that would be replaced by linking `rel_path` in `to_dir` to
+`link_target`: either something other than a directory where a parent directory of the link is
+needed, or something other than the expected link at the link path.
+*/
+fn find_conflict(
+    to_dir: &Utf8Path,
+    rel_path: &Utf8Path,
+    link_target: &Utf8Path,
+) -> Option<Utf8PathBuf> {
+    let parents: Vec<&Utf8Path> = rel_path
+        .ancestors()
+        .skip(1)
+        .filter(|p| p != &Utf8Path::new(""))
+        .collect();
+    let parent_conflict = parents
+        .into_iter()
+        .rev()
+        .map(|path| to_dir.join(path))
+        .find(|path| path.symlink_metadata().is_ok() && !path.is_dir());
+    if parent_conflict.is_some() {
+        return parent_conflict;
+    }
+
+    let to_path = to_dir.join(rel_path);
+    match to_path.read_link_utf8() {
+        Ok(existing_link) if existing_link == link_target => None,
+        _ if to_path.symlink_metadata().is_ok() => Some(to_path),
+        _ => None,
+    }
+}
+
 /// Create the parent directory to create the symlink in.
-fn create_parent_dir(to_dir: &Utf8Path, rel_path: &Utf8Path, backup_dir: &Utf8Path) -> Result<()> {
+fn create_parent_dir(
+    to_dir: &Utf8Path,
+    rel_path: &Utf8Path,
+    backup_dir: &Utf8Path,
+    on_conflict: OnConflict,
+) -> Result<()> {
     let to_path = to_dir.join(rel_path);
     let to_path_parent = get_parent_path(&to_path)?;
     fs::create_dir_all(to_path_parent).or_else(|_err| {
@@ -175,7 +326,10 @@ fn create_parent_dir(to_dir: &Utf8Path, rel_path: &Utf8Path, backup_dir: &Utf8Pa
                     "File will be overwritten by parent directory of link.\n  File: {abs_path}\n  \
                      Link: {to_path}",
                 );
-                if abs_path.is_file() {
+                if abs_path.is_file() && on_conflict == OnConflict::Overwrite {
+                    info!("Removing file: {abs_path}");
+                    fs::remove_file(abs_path)?;
+                } else if abs_path.is_file() {
                     if let Some(parent_path) = &path.parent() {
                         info!("Path: {path}, parent: {parent_path}");
                         if parent_path != &Utf8Path::new("") {
@@ -200,6 +354,24 @@ fn create_parent_dir(to_dir: &Utf8Path, rel_path: &Utf8Path, backup_dir: &Utf8Pa
     })
 }
 
+/// The path to `path` relative to the directory `base`, e.g. `/a/b/c` relative to `/a/d` is
+/// `../b/c`. Both paths must be absolute.
+fn relative_path(path: &Utf8Path, base: &Utf8Path) -> Utf8PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    while let (Some(p), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if p != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+    base_components
+        .map(|_| Utf8Path::new(".."))
+        .chain(path_components.map(|c| Utf8Path::new(c.as_str())))
+        .collect()
+}
+
 /// Get the parent directory of a path.
 fn get_parent_path(path: &Utf8Path) -> Result<&Utf8Path> {
     Ok(path.parent().ok_or_else(|| LinkError::MissingParentDir {
@@ -209,18 +381,18 @@ fn get_parent_path(path: &Utf8Path) -> Result<&Utf8Path> {
 
 /// Create a symlink from `from_path` -> `to_path`.
 /// `rel_path` is the relative path within `from_dir`.
-/// Moves any existing files that would be overwritten into `backup_dir`.
+/// Moves any existing files that would be overwritten into `backup_dir` (or deletes them if
+/// `on_conflict` is [`OnConflict::Overwrite`]).
 /// Returns a boolean indicating whether any symlinks were created.
 #[allow(clippy::filetype_is_file)]
 fn link_path(
-    from_path_direntry: &DirEntry,
+    from_path: &Utf8Path,
     to_dir: &Utf8Path,
     rel_path: &Utf8Path,
     backup_dir: &Utf8Path,
+    on_conflict: OnConflict,
 ) -> Result<bool> {
     let to_path = to_dir.join(rel_path);
-    let from_path = Utf8Path::from_path(from_path_direntry.path())
-        .ok_or_else(|| eyre!("Invalid UTF-8 in path {from_path_direntry:?}"))?;
     if to_path.exists() {
         let to_path_file_type = to_path.symlink_metadata()?.file_type();
         if to_path_file_type.is_symlink() {
@@ -240,6 +412,12 @@ fn link_path(
                     bail!("read_link returned error {e:?} for {to_path}");
                 }
             }
+        } else if to_path_file_type.is_dir() && on_conflict == OnConflict::Overwrite {
+            warn!("Expected file or link at {to_path}, found directory, deleting it.");
+            fs::remove_dir_all(&to_path).map_err(|e| LinkError::DeleteError {
+                path: to_path.clone(),
+                source: e,
+            })?;
         } else if to_path_file_type.is_dir() {
             warn!("Expected file or link at {to_path}, found directory, moving to {backup_dir}",);
             let backup_path = backup_dir.join(rel_path);
@@ -252,6 +430,12 @@ fn link_path(
                 to_path: backup_path,
                 source: e,
             })?;
+        } else if to_path_file_type.is_file() && on_conflict == OnConflict::Overwrite {
+            warn!("Existing file at {to_path}, deleting it.");
+            fs::remove_file(&to_path).map_err(|e| LinkError::DeleteError {
+                path: to_path.clone(),
+                source: e,
+            })?;
         } else if to_path_file_type.is_file() {
             warn!("Existing file at {to_path}, moving to {backup_dir}");
             let backup_path = backup_dir.join(rel_path);
@@ -343,6 +527,21 @@ pub enum LinkError {
         /// Source error.
         source: io::Error,
     },
+    /**
+    Paths already exist where links would be created (`on_conflict` is `fail`):
+      {paths}
+    */
+    Conflicts {
+        /// The conflicting paths, one per line.
+        paths: String,
+    },
+    /// The `on_change` command for `{path}` failed.
+    OnChange {
+        /// The `on_change` path whose command failed.
+        path: String,
+        /// Source error.
+        source: io::Error,
+    },
     /// Path `{path}` should have a parent directory.
     MissingParentDir {
         /// Path that doesn't have a parent dir.
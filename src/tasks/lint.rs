@@ -0,0 +1,318 @@
+//! Check the up config and tasks for mistakes that schema validation doesn't catch (`up lint`).
+use self::LintError as E;
+use crate::config::EnvFiles;
+use crate::config::UpConfig;
+use crate::env::read_env_files;
+use crate::env::BUILTIN_ENV_VARS;
+use crate::tasks;
+use crate::tasks::plugin::Plugins;
+use crate::tasks::plugin::PLUGIN_PREFIX;
+use crate::tasks::task;
+use crate::tasks::task::Task;
+use crate::tasks::task::TaskConfig;
+use crate::tasks::task::RUN_LIBS;
+use crate::tasks::task::SCRIPT_FRONT_MATTER_PREFIX;
+use crate::tasks::TasksDir;
+use crate::utils::yaml;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use thiserror::Error;
+use tracing::info;
+
+/// A problem found in the config, with a suggested fix.
+#[derive(Debug, PartialEq, Eq)]
+struct Lint {
+    /// File containing the problem.
+    path: Utf8PathBuf,
+    /// Line of the file the problem is on (starting from 1).
+    line: usize,
+    /// What the problem is.
+    message: String,
+    /// How to fix it.
+    fix: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            path,
+            line,
+            message,
+            fix,
+        } = self;
+        write!(f, "{path}:{line}: {message}\n    fix: {fix}")
+    }
+}
+
+/// A task file that was successfully parsed.
+struct TaskFile {
+    /// Path to the task file.
+    path: Utf8PathBuf,
+    /// Raw contents of the file, used to find line numbers.
+    contents: String,
+    /// Parsed task config.
+    config: TaskConfig,
+}
+
+/// Run the `up lint` command, printing any problems found and erroring if there were any.
+pub(crate) fn run(config: &UpConfig) -> Result<()> {
+    let up_yaml_path = config.up_yaml_path.as_ref().ok_or(E::MissingConfig)?;
+    let tasks_dir = tasks::tasks_dir(config, TasksDir::Tasks)?;
+
+    let lints = lint(config, up_yaml_path, &tasks_dir)?;
+    for lint in &lints {
+        println!("{lint}");
+    }
+    if !lints.is_empty() {
+        return Err(E::LintsFound { count: lints.len() }.into());
+    }
+    info!("No problems found in {up_yaml_path} or {tasks_dir}.");
+    Ok(())
+}
+
+/// Check the up.yaml at `up_yaml_path` and the task files in `tasks_dir`.
+fn lint(config: &UpConfig, up_yaml_path: &Utf8Path, tasks_dir: &Utf8Path) -> Result<Vec<Lint>> {
+    let mut lints = Vec::new();
+
+    let mut task_files = Vec::new();
+    let task_paths =
+        tasks::task_file_paths(tasks_dir, config.config_yaml.task_extensions.as_deref())?;
+    for path in task_paths.into_iter().filter(|path| path.is_file()) {
+        let contents = fs::read_to_string(&path).map_err(|e| E::ReadFile {
+            path: path.clone(),
+            source: e,
+        })?;
+        if task::is_script(&contents) {
+            match Task::parse_config(&path, &contents) {
+                Ok(config) => task_files.push(TaskFile {
+                    path,
+                    contents,
+                    config,
+                }),
+                Err(e) => lints.push(Lint {
+                    line: find_line(&contents, SCRIPT_FRONT_MATTER_PREFIX),
+                    path,
+                    message: format!("Task script isn't a valid task: {e}"),
+                    fix: "make the script executable, and fix its `# up:` line to match the task \
+                          schema (see `up schema`)."
+                        .to_owned(),
+                }),
+            }
+            continue;
+        }
+        match yaml::from_str::<TaskConfig>(&contents) {
+            Ok(config) => task_files.push(TaskFile {
+                path,
+                contents,
+                config,
+            }),
+            Err(e) => lints.push(Lint {
+                line: e.location().map_or(1, |l| l.line()),
+                path,
+                message: format!("Task file isn't a valid task: {e}"),
+                fix: "fix the yaml to match the task schema (see `up schema`).".to_owned(),
+            }),
+        }
+    }
+
+    let file_env = read_env_files(
+        config
+            .config_yaml
+            .env_file
+            .as_ref()
+            .map(EnvFiles::paths)
+            .unwrap_or_default(),
+    )?;
+    let defined_env: HashSet<&str> = config
+        .config_yaml
+        .env
+        .iter()
+        .flat_map(|env| env.keys())
+        .chain(file_env.keys())
+        .chain(config.config_yaml.inherit_env.iter().flatten())
+        .map(String::as_str)
+        .chain(BUILTIN_ENV_VARS.iter().copied())
+        .collect();
+
+    let plugins = Plugins::new(config);
+    let mut names: BTreeMap<String, Vec<&TaskFile>> = BTreeMap::new();
+    for task_file in &task_files {
+        lint_task(task_file, &defined_env, &plugins, &mut lints);
+        let name = task_file
+            .config
+            .name
+            .clone()
+            .unwrap_or_else(|| task_file.path.file_stem().unwrap_or_default().to_owned());
+        let name = tasks::namespaced_name(tasks_dir, &task_file.path, &name);
+        names.entry(name).or_default().push(task_file);
+    }
+
+    for (name, files) in &names {
+        if let [first, rest @ ..] = files.as_slice() {
+            for duplicate in rest {
+                lints.push(Lint {
+                    path: duplicate.path.clone(),
+                    line: find_line(&duplicate.contents, "name:"),
+                    message: format!(
+                        "Task name '{name}' is also used by {first}, only one of them will be run.",
+                        first = first.path
+                    ),
+                    fix: "rename one of the tasks (with the `name` field or by renaming the file)."
+                        .to_owned(),
+                });
+            }
+        }
+    }
+
+    let up_yaml = fs::read_to_string(up_yaml_path).unwrap_or_default();
+    for bootstrap_task in config.config_yaml.bootstrap_tasks.iter().flatten() {
+        if !names.contains_key(bootstrap_task) {
+            lints.push(Lint {
+                path: up_yaml_path.to_owned(),
+                line: find_line(&up_yaml, bootstrap_task),
+                message: format!(
+                    "bootstrap_tasks entry '{bootstrap_task}' doesn't match any task in \
+                     {tasks_dir}."
+                ),
+                fix: format!(
+                    "remove it, or fix it to one of: {names}.",
+                    names = names.keys().join(", ")
+                ),
+            });
+        }
+    }
+
+    Ok(lints)
+}
+
+/// Check a single task file.
+fn lint_task(
+    task_file: &TaskFile,
+    defined_env: &HashSet<&str>,
+    plugins: &Plugins,
+    lints: &mut Vec<Lint>,
+) {
+    let TaskFile {
+        path,
+        contents,
+        config,
+    } = task_file;
+
+    match &config.run_lib {
+        Some(run_lib) if run_lib.starts_with(PLUGIN_PREFIX) => {
+            let plugin = run_lib.trim_start_matches(PLUGIN_PREFIX);
+            if !plugins.exists(plugin) {
+                lints.push(Lint {
+                    path: path.clone(),
+                    line: find_line(contents, "run_lib:"),
+                    message: format!("plugin '{plugin}' doesn't exist."),
+                    fix: format!(
+                        "add an executable called '{plugin}' to the libs directory next to \
+                         up.yaml, or add '{plugin}' to the `plugins` section of up.yaml."
+                    ),
+                });
+            }
+        }
+        Some(run_lib) if !RUN_LIBS.contains(&run_lib.as_str()) => lints.push(Lint {
+            path: path.clone(),
+            line: find_line(contents, "run_lib:"),
+            message: format!("run_lib '{run_lib}' doesn't exist."),
+            fix: format!("use one of: {libs}.", libs = RUN_LIBS.join(", ")),
+        }),
+        None if config.run_cmd.is_none() && config.run_script.is_none() => lints.push(Lint {
+            path: path.clone(),
+            line: 1,
+            message: "Task has no run_cmd, run_script, or run_lib, so won't do anything."
+                .to_owned(),
+            fix: "add one of them, or delete the task.".to_owned(),
+        }),
+        _ => {}
+    }
+
+    // These are the fields that have env vars expanded by up (run_script is expanded by the shell).
+    let mut strings: Vec<&str> = config
+        .run_if_cmd
+        .iter()
+        .chain(config.run_cmd.iter())
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if let Some(data) = &config.data {
+        yaml_strings(data, &mut strings);
+    }
+    let mut undefined_env = BTreeSet::new();
+    for s in strings {
+        _ = shellexpand::env_with_context_no_errors(s, |var| {
+            if !defined_env.contains(var) {
+                undefined_env.insert(var.to_owned());
+            }
+            None::<String>
+        });
+    }
+    for var in undefined_env {
+        lints.push(Lint {
+            path: path.clone(),
+            line: find_line(contents, &format!("${{{var}}}"))
+                .max(find_line(contents, &format!("${var}"))),
+            message: format!("Env var '{var}' is used but isn't set in up.yaml or an env file."),
+            fix: format!(
+                "add '{var}' to the `env` section of up.yaml or to an `env_file`, or to \
+                 `inherit_env` to pass it through from the environment up is run in."
+            ),
+        });
+    }
+}
+
+/// Collect all the string values in `value` (not including map keys).
+pub(super) fn yaml_strings<'a>(value: &'a serde_yaml::Value, strings: &mut Vec<&'a str>) {
+    match value {
+        serde_yaml::Value::String(s) => strings.push(s),
+        serde_yaml::Value::Sequence(seq) => {
+            for v in seq {
+                yaml_strings(v, strings);
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for v in map.values() {
+                yaml_strings(v, strings);
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => yaml_strings(&tagged.value, strings),
+        serde_yaml::Value::Null | serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_) => {}
+    }
+}
+
+/// The first line (starting from 1) of `contents` containing `needle`, or 1 if none do.
+fn find_line(contents: &str, needle: &str) -> usize {
+    contents
+        .lines()
+        .position(|line| line.contains(needle))
+        .map_or(1, |index| index + 1)
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum LintError {
+    /// No up.yaml found to lint.
+    MissingConfig,
+    /// Failed to read {path}.
+    ReadFile {
+        /// Path we failed to read.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Found {count} problem(s) in the config.
+    LintsFound {
+        /// Number of problems found.
+        count: usize,
+    },
+}
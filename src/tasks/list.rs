@@ -0,0 +1,47 @@
+//! List the tasks as JSON with how their last run went (`up list --json`).
+use crate::config::UpConfig;
+use crate::tasks::history::RunHistory;
+use crate::tasks::task::Task;
+use crate::tasks::RUNS_DIR;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+
+/// A task in the `up list --json` output.
+#[derive(Debug, Serialize)]
+struct ListedTask {
+    /// Task name.
+    name: String,
+    /// Path to the task config file.
+    path: Utf8PathBuf,
+    /// Status of the last run (`passed`, `skipped`, `failed`, `warned`, `offline`, `not run`,
+    /// or `incomplete`), unset if the task hasn't been run or the status wasn't recorded.
+    last_status: Option<String>,
+    /// When the last run that included the task started (RFC 3339), unset if it hasn't been run.
+    last_run: Option<String>,
+    /// How long the task took in its last run, in seconds.
+    last_duration_secs: Option<f64>,
+}
+
+/// Print the `tasks` as a JSON array sorted by name, with their last runs from the run history.
+pub(super) fn print_json(config: &UpConfig, tasks: &HashMap<String, Task>) -> Result<()> {
+    let history = RunHistory::read(&config.run_temp_dir.join(RUNS_DIR))?;
+    let mut listed_tasks: Vec<ListedTask> = tasks
+        .values()
+        .map(|task| {
+            let last_run = history.last_run(&task.name);
+            let record = last_run.as_ref().and_then(|run| run.record.as_ref());
+            ListedTask {
+                name: task.name.clone(),
+                path: task.path.clone(),
+                last_status: record.map(|record| record.status.clone()),
+                last_duration_secs: record.map(|record| record.duration.as_secs_f64()),
+                last_run: last_run.map(|run| run.started),
+            }
+        })
+        .collect();
+    listed_tasks.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    println!("{}", serde_json::to_string_pretty(&listed_tasks)?);
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//! Find a task's file from its name (`up task which`, `up task cat`, and `up task edit`).
+use self::LocateError as E;
+use crate::config::UpConfig;
+use crate::tasks;
+use crate::tasks::scaffold;
+use crate::tasks::TasksDir;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use thiserror::Error;
+
+/// Print the path to the file of the task called `name`.
+pub(crate) fn which(config: &UpConfig, name: &str) -> Result<()> {
+    println!("{}", task_path(config, name)?);
+    Ok(())
+}
+
+/// Print the file of the task called `name`.
+pub(crate) fn cat(config: &UpConfig, name: &str) -> Result<()> {
+    let path = task_path(config, name)?;
+    let contents = fs::read(&path).map_err(|e| E::ReadTask {
+        path: path.clone(),
+        source: e,
+    })?;
+    io::stdout().write_all(&contents)?;
+    Ok(())
+}
+
+/// Open the file of the task called `name` in the user's editor.
+pub(crate) fn edit(config: &UpConfig, name: &str) -> Result<()> {
+    let path = task_path(config, name)?;
+    let editor = scaffold::editor().ok_or(E::NoEditor { path: path.clone() })?;
+    scaffold::open_in_editor(&editor, &path)?;
+    Ok(())
+}
+
+/// Path to the file of the task called `name`.
+fn task_path(config: &UpConfig, name: &str) -> Result<Utf8PathBuf> {
+    let tasks_dir = tasks::tasks_dir(config, TasksDir::Tasks)?;
+    let paths: HashMap<String, Utf8PathBuf> = tasks::load_tasks(
+        &tasks_dir,
+        &config.cache_dir,
+        config.config_yaml.task_extensions.as_deref(),
+    )?
+    .into_values()
+    .map(|task| (task.name, task.path))
+    .collect();
+    match find(&paths, name) {
+        Ok(path) => Ok(path.clone()),
+        Err(matches) if matches.is_empty() => Err(E::TaskNotFound {
+            name: name.to_owned(),
+            tasks_dir,
+            names: paths.keys().sorted().join(", "),
+        }
+        .into()),
+        Err(matches) => Err(E::Ambiguous {
+            name: name.to_owned(),
+            matches: matches.join(", "),
+        }
+        .into()),
+    }
+}
+
+/**
+Find the task called `name` in `paths` (task names to task file paths).
+
+A name without a `/` also matches a task with that name in a subdirectory (e.g. `vpn` matches
+`work/vpn`) if there's only one. Otherwise returns the names of the tasks that matched.
+*/
+fn find<'a>(
+    paths: &'a HashMap<String, Utf8PathBuf>,
+    name: &str,
+) -> Result<&'a Utf8PathBuf, Vec<&'a str>> {
+    if let Some(path) = paths.get(name) {
+        return Ok(path);
+    }
+    if name.contains('/') {
+        return Err(Vec::new());
+    }
+    let suffix = format!("/{name}");
+    let matches: Vec<&String> = paths
+        .keys()
+        .filter(|task| task.ends_with(&suffix))
+        .sorted()
+        .collect();
+    match matches.as_slice() {
+        [task] => Ok(&paths[*task]),
+        _ => Err(matches.into_iter().map(String::as_str).collect()),
+    }
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum LocateError {
+    /// Task '{name}' not found in `{tasks_dir}`, tasks are: {names}.
+    TaskNotFound {
+        /// Task name.
+        name: String,
+        /// Tasks directory searched.
+        tasks_dir: Utf8PathBuf,
+        /// Names of the tasks that do exist.
+        names: String,
+    },
+    /// Task name '{name}' matches more than one task, pass one of: {matches}.
+    Ambiguous {
+        /// Task name.
+        name: String,
+        /// Names of the matching tasks.
+        matches: String,
+    },
+    /// Failed to read task file `{path}`.
+    ReadTask {
+        /// Path to the task file.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Set $VISUAL or $EDITOR to edit tasks, the task file is `{path}`.
+    NoEditor {
+        /// Path to the task file.
+        path: Utf8PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find;
+    use camino::Utf8PathBuf;
+    use color_eyre::Result;
+    use std::collections::HashMap;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_find() -> Result<()> {
+        let path = |name: &str| Utf8PathBuf::from(format!("/tasks/{name}.yaml"));
+        let paths: HashMap<String, Utf8PathBuf> =
+            ["brew", "work/vpn", "work/brew", "home/brew", "home/backup"]
+                .into_iter()
+                .map(|name| (name.to_owned(), path(name)))
+                .collect();
+
+        ensure_eq!(Ok(&path("brew")), find(&paths, "brew"));
+        ensure_eq!(Ok(&path("work/vpn")), find(&paths, "work/vpn"));
+        ensure_eq!(Ok(&path("work/vpn")), find(&paths, "vpn"));
+        ensure_eq!(Ok(&path("home/backup")), find(&paths, "backup"));
+        ensure_eq!(Err(vec![]), find(&paths, "missing"));
+        ensure_eq!(Err(vec![]), find(&paths, "other/vpn"));
+
+        let paths: HashMap<String, Utf8PathBuf> = paths
+            .into_iter()
+            .filter(|(name, _)| name != "brew")
+            .collect();
+        ensure_eq!(Err(vec!["home/brew", "work/brew"]), find(&paths, "brew"));
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+//! Generates up CLI man pages.
+use crate::opts::ManOptions;
+use crate::opts::Opts;
+use crate::utils::files;
+use camino::Utf8PathBuf;
+use clap::CommandFactory;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use std::env;
+use tracing::info;
+
+/// Run the `up man` command.
+pub(crate) fn run(cmd_opts: &ManOptions) -> Result<()> {
+    let command = Opts::command().name("up");
+
+    let out_dir = if cmd_opts.install {
+        Some(install_dir()?)
+    } else {
+        cmd_opts.out_dir.clone()
+    };
+
+    let Some(out_dir) = out_dir else {
+        clap_mangen::Man::new(command).render(&mut std::io::stdout())?;
+        return Ok(());
+    };
+
+    files::create_dir_all(&out_dir)?;
+    clap_mangen::generate_to(command, &out_dir)
+        .wrap_err_with(|| format!("Failed to write man pages to {out_dir}"))?;
+    info!("Man pages written to {out_dir}");
+    Ok(())
+}
+
+/// The directory in the user's manpath to install man pages to.
+fn install_dir() -> Result<Utf8PathBuf> {
+    let data_dir = match env::var("XDG_DATA_HOME") {
+        Ok(data_dir) if !data_dir.is_empty() => Utf8PathBuf::from(data_dir),
+        _ => files::home_dir()?.join(".local/share"),
+    };
+    Ok(data_dir.join("man/man1"))
+}
@@ -0,0 +1,268 @@
+/*!
+Find `link` tasks that would create links inside (or over) a repo that a `git` task manages.
+
+If both tasks run, the git task sees the links as untracked (or modified) files, and the link task
+backs up files the git task checks out, depending on which finishes first. Overlaps are found
+before any task runs, and are warned about or fail the run depending on `on_task_overlap` in
+`up.yaml`.
+
+```text
+WARN Link task 'dotfiles' would create links in the repo of git task 'repos':
+  ~/code/up-rs: ~/code/up-rs/.envrc
+```
+*/
+use crate::opts::LinkOptions;
+use crate::tasks;
+use crate::tasks::git::GitConfig;
+use crate::tasks::task::Task;
+use crate::tasks::ResolveEnv;
+use camino::Utf8PathBuf;
+use displaydoc::Display;
+use itertools::Itertools;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+use tracing::debug;
+use tracing::warn;
+use walkdir::WalkDir;
+
+/// What to do when a link task would create links in the repo of a git task.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnTaskOverlap {
+    /// Log a warning and run the tasks anyway.
+    #[default]
+    Warn,
+    /// Fail the run before any task is started.
+    Fail,
+}
+
+/// A link task and a git task that both manage the same paths.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(super) struct Overlap {
+    /// Name of the link task.
+    pub(super) link_task: String,
+    /// Name of the git task.
+    pub(super) git_task: String,
+    /// Paths the link task would link, keyed by the path of the git task's repo they overlap.
+    pub(super) paths: BTreeMap<Utf8PathBuf, Vec<Utf8PathBuf>>,
+}
+
+/**
+The link tasks in `tasks` that would create links in (or over) the repos of git tasks in `tasks`,
+sorted by link task then git task.
+
+Task data is resolved with `env` as it would be when running the task. Tasks whose data can't be
+parsed are skipped, as are link tasks whose `from_dir` doesn't exist yet (e.g. as a git task will
+clone it), as what they would link isn't known.
+*/
+pub(super) fn find(tasks: &HashMap<String, Task>, env: &HashMap<String, String>) -> Vec<Overlap> {
+    let links: Vec<(&str, LinkOptions)> = tasks
+        .values()
+        .filter_map(|task| Some((task.name.as_str(), task_data(task, "link", env)?)))
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .collect();
+    let repos: Vec<(&str, Vec<GitConfig>)> = tasks
+        .values()
+        .filter_map(|task| Some((task.name.as_str(), task_data(task, "git", env)?)))
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .collect();
+
+    let mut overlaps = Vec::new();
+    for (link_task, link_options) in &links {
+        let link_paths = link_paths(link_options);
+        for (git_task, git_configs) in &repos {
+            let paths: BTreeMap<Utf8PathBuf, Vec<Utf8PathBuf>> = git_configs
+                .iter()
+                .map(|git_config| {
+                    let overlapping = link_paths
+                        .iter()
+                        .filter(|path| {
+                            path.starts_with(&git_config.path) || git_config.path.starts_with(path)
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (git_config.path.clone(), overlapping)
+                })
+                .filter(|(_, overlapping)| !overlapping.is_empty())
+                .collect();
+            if !paths.is_empty() {
+                overlaps.push(Overlap {
+                    link_task: (*link_task).to_owned(),
+                    git_task: (*git_task).to_owned(),
+                    paths,
+                });
+            }
+        }
+    }
+    overlaps
+}
+
+/**
+Warn about the `overlaps`, or return an error listing them if `on_task_overlap` (which defaults to
+[`OnTaskOverlap::Warn`]) is [`OnTaskOverlap::Fail`].
+*/
+pub(super) fn check(
+    overlaps: &[Overlap],
+    on_task_overlap: Option<OnTaskOverlap>,
+) -> Result<(), OverlapError> {
+    if overlaps.is_empty() {
+        return Ok(());
+    }
+    match on_task_overlap.unwrap_or_default() {
+        OnTaskOverlap::Warn => {
+            for overlap in overlaps {
+                warn!("{overlap}");
+            }
+            Ok(())
+        }
+        OnTaskOverlap::Fail => Err(OverlapError::Overlaps {
+            overlaps: overlaps.iter().join("\n"),
+        }),
+    }
+}
+
+/// The data of `task` if it's a `run_lib` task, resolved with `env`.
+fn task_data<T: ResolveEnv + for<'de> serde::Deserialize<'de>>(
+    task: &Task,
+    run_lib: &str,
+    env: &HashMap<String, String>,
+) -> Option<T> {
+    if task.config.run_lib.as_deref() != Some(run_lib) {
+        return None;
+    }
+    let skip = |e: &dyn fmt::Display| {
+        debug!(
+            "Not checking {run_lib} task '{}' for overlaps: {e}",
+            task.name
+        );
+    };
+    let mut data: T = serde_yaml::from_value(task.config.data.clone()?)
+        .inspect_err(|e| skip(e))
+        .ok()?;
+    data.resolve_env(|s| tasks::resolve_env_vars(s, env))
+        .inspect_err(|e| skip(e))
+        .ok()?;
+    Some(data)
+}
+
+/// The paths the link task with `options` would create links at, the same way it finds them.
+fn link_paths(options: &LinkOptions) -> Vec<Utf8PathBuf> {
+    let from_dir = Utf8PathBuf::from(&options.from_dir);
+    let to_dir = Utf8PathBuf::from(&options.to_dir);
+    WalkDir::new(&from_dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_type().is_dir())
+        .filter_map(|entry| {
+            let rel_path = entry.path().strip_prefix(&from_dir).ok()?;
+            Some(to_dir.join(Utf8PathBuf::from_path_buf(rel_path.to_owned()).ok()?))
+        })
+        .collect()
+}
+
+impl fmt::Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Link task '{}' would create links in the repo of git task '{}':",
+            self.link_task, self.git_task
+        )?;
+        for (repo, paths) in &self.paths {
+            write!(f, "\n  {repo}: {}", paths.iter().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum OverlapError {
+    /**
+    Link and git tasks manage the same paths, change the tasks so they don't overlap or set
+    `on_task_overlap: warn` in up.yaml to run them anyway:
+    {overlaps}
+    */
+    Overlaps {
+        /// The overlapping tasks, one per line.
+        overlaps: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find;
+    use super::Overlap;
+    use crate::tasks::task::Task;
+    use color_eyre::Result;
+    use std::collections::BTreeMap;
+    use std::collections::HashMap;
+    use std::fs;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_find() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let tasks_dir = temp_dir.join("tasks");
+        let dotfiles_dir = temp_dir.join("dotfiles");
+        fs::create_dir_all(&tasks_dir)?;
+        fs::create_dir_all(dotfiles_dir.join("code/repo"))?;
+        fs::create_dir_all(dotfiles_dir.join("code/other"))?;
+        fs::write(dotfiles_dir.join(".bashrc"), "")?;
+        fs::write(dotfiles_dir.join("code/repo/.envrc"), "")?;
+        fs::write(dotfiles_dir.join("code/other/.envrc"), "")?;
+
+        let mut tasks = HashMap::new();
+        for (name, contents) in [
+            (
+                "dotfiles",
+                "run_lib: link\ndata:\n  from_dir: $dotfiles_dir\n  to_dir: $home_dir\n",
+            ),
+            (
+                "repos",
+                "run_lib: git\ndata:\n  - path: $home_dir/code/repo\n    remotes: []\n  - path: \
+                 $home_dir/code/unlinked\n    remotes: []\n",
+            ),
+            (
+                "dotfiles_repo",
+                "run_lib: git\ndata:\n  - path: $dotfiles_dir\n    remotes: []\n",
+            ),
+        ] {
+            let path = tasks_dir.join(format!("{name}.yaml"));
+            fs::write(&path, contents)?;
+            tasks.insert(name.to_owned(), Task::from(&path)?);
+        }
+        let home_dir = temp_dir.join("home");
+        let env = HashMap::from([
+            ("dotfiles_dir".to_owned(), dotfiles_dir.to_string()),
+            ("home_dir".to_owned(), home_dir.to_string()),
+        ]);
+
+        ensure_eq!(
+            vec![Overlap {
+                link_task: "dotfiles".to_owned(),
+                git_task: "repos".to_owned(),
+                paths: BTreeMap::from([(
+                    home_dir.join("code/repo"),
+                    vec![home_dir.join("code/repo/.envrc")]
+                )]),
+            }],
+            find(&tasks, &env)
+        );
+        ensure_eq!(
+            "Link task 'dotfiles' would create links in the repo of git task 'repos':\n  \
+             {home}/code/repo: {home}/code/repo/.envrc"
+                .replace("{home}", home_dir.as_str()),
+            find(&tasks, &env)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! Work out what `up run` would do, without running anything (`up plan`).
+use crate::config::UpConfig;
+use crate::opts::PlanFormat;
+use crate::tasks::deps;
+use crate::tasks::overlap::Overlap;
+use crate::tasks::task::Task;
+use crate::utils::user::current_user_is_root;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The tasks that `up run` would run, and those it wouldn't.
+#[derive(Debug, Serialize)]
+struct Plan<'a> {
+    /// The `hosts` entry of `up.yaml` used for this machine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    /// Whether `up run` would prompt for sudo (a task needs it and up isn't running as root).
+    sudo_prompt: bool,
+    /// Tasks that would be run, in the order they would be run.
+    tasks: Vec<PlannedTask>,
+    /// Tasks that would not be run, sorted by name.
+    excluded_tasks: Vec<PlannedTask>,
+    /// Link tasks that would create links in the repos of git tasks that would run.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    overlaps: &'a [Overlap],
+}
+
+/// What would happen to a single task.
+#[derive(Debug, Serialize)]
+struct PlannedTask {
+    /// Task name.
+    name: String,
+    /// Path to the task config file.
+    path: Utf8PathBuf,
+    /// The step the task would be run in, starting from 1. Tasks in the same step are run in
+    /// parallel. Unset if the task would not be run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    step: Option<usize>,
+    /// Why the task would or wouldn't be run.
+    reason: String,
+    /// The `run_lib` the task uses.
+    run_lib: Option<String>,
+    /// The `run_cmd` the task uses (ignored if `run_lib` or `run_script` is set).
+    run_cmd: Option<Vec<String>>,
+    /// The `run_script` the task uses (ignored if `run_lib` is set).
+    run_script: Option<String>,
+    /// Whether the task needs sudo.
+    needs_sudo: bool,
+    /// Tasks that must be run before this one.
+    requires: Vec<String>,
+    /// The task's tags.
+    tags: Vec<String>,
+}
+
+impl PlannedTask {
+    /// Plan for `task`, which would be run in `step` (or not run if `None`).
+    fn new(task: &Task, step: Option<usize>, reason: String) -> Self {
+        Self {
+            name: task.name.clone(),
+            path: task.path.clone(),
+            step,
+            reason,
+            run_lib: task.config.run_lib.clone(),
+            run_cmd: task.config.run_cmd.clone(),
+            run_script: task.config.run_script.clone(),
+            needs_sudo: task.config.needs_sudo,
+            requires: task.config.requires.clone().unwrap_or_default(),
+            tags: task.config.tags.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/**
+Print the plan for the `tasks` that passed the filters, `excluded` contains the tasks that were
+filtered out and the reason they were, and `overlaps` the link and git tasks that would run that
+manage the same paths.
+
+Tasks are ordered the same way `up run` would order them: bootstrap tasks first, one per step,
+then the remaining tasks grouped into steps by their `requires` fields.
+*/
+pub(super) fn print(
+    config: &UpConfig,
+    bootstrap_tasks: Vec<String>,
+    mut tasks: HashMap<String, Task>,
+    excluded: Vec<(Task, String)>,
+    overlaps: &[Overlap],
+    format: PlanFormat,
+) -> Result<()> {
+    let mut planned_tasks = Vec::new();
+    let mut excluded_tasks: Vec<PlannedTask> = excluded
+        .into_iter()
+        .map(|(task, reason)| PlannedTask::new(&task, None, reason))
+        .collect();
+
+    let mut step = 0;
+    for name in bootstrap_tasks {
+        let task = tasks
+            .remove(&name)
+            .ok_or_else(|| eyre!("Task '{name}' was missing."))?;
+        step += 1;
+        planned_tasks.push(PlannedTask::new(
+            &task,
+            Some(step),
+            "it is a bootstrap task".to_owned(),
+        ));
+    }
+
+    for layer in deps::execution_layers(&tasks)? {
+        let layer_step = step + 1;
+        for task in layer.iter().filter_map(|name| tasks.get(name)) {
+            step = layer_step;
+            planned_tasks.push(PlannedTask::new(
+                task,
+                Some(layer_step),
+                included_reason(config, task),
+            ));
+        }
+    }
+    excluded_tasks.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let plan = Plan {
+        host: config.host.as_ref().map(ToString::to_string),
+        sudo_prompt: planned_tasks.iter().any(|t| t.needs_sudo) && !current_user_is_root(),
+        tasks: planned_tasks,
+        excluded_tasks,
+        overlaps,
+    };
+    match format {
+        PlanFormat::Text => print!("{plan}"),
+        PlanFormat::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+    }
+    Ok(())
+}
+
+/// Why a task that passed the filters would be run.
+fn included_reason(config: &UpConfig, task: &Task) -> String {
+    let name = task.name.as_str();
+    match config.until.as_deref() {
+        Some(until) if until == name => "it is the --until task".to_owned(),
+        Some(until) => format!("it is required by the --until task '{until}'"),
+        None if task.config.bootstrap.unwrap_or(false) => {
+            "it is a bootstrap task, so the other tasks require it".to_owned()
+        }
+        None if !task.config.auto_run.unwrap_or(true) => {
+            if config.tasks.iter().flatten().any(|pattern| pattern == name) {
+                "its auto_run field is false, but it was named in --tasks".to_owned()
+            } else {
+                "its auto_run field is false, but a task that runs requires it".to_owned()
+            }
+        }
+        None if config.tasks.is_some() || config.tags.is_some() => {
+            "it matches the tasks or tags filter".to_owned()
+        }
+        None => "all tasks are run by default".to_owned(),
+    }
+}
+
+impl fmt::Display for Plan<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(host) = &self.host {
+            writeln!(f, "Host: {host}\n")?;
+        }
+        writeln!(f, "Tasks to run (tasks in the same step run in parallel):")?;
+        for task in &self.tasks {
+            let step = task.step.unwrap_or_default();
+            writeln!(f, "  {step}. {task}")?;
+        }
+        if !self.excluded_tasks.is_empty() {
+            writeln!(f, "\nTasks not run:")?;
+            for task in &self.excluded_tasks {
+                writeln!(f, "  - {task}")?;
+            }
+        }
+        if !self.overlaps.is_empty() {
+            writeln!(f, "\nOverlapping tasks:")?;
+            for overlap in self.overlaps {
+                writeln!(f, "  - {}", overlap.to_string().replace('\n', "\n    "))?;
+            }
+        }
+        if self.sudo_prompt {
+            writeln!(f, "\nup run would prompt for sudo.")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlannedTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = &self.name;
+        match (&self.run_lib, &self.run_script, &self.run_cmd) {
+            (Some(run_lib), _, _) => write!(f, "{name} (run_lib: {run_lib}")?,
+            (None, Some(_), _) => write!(f, "{name} (run_script")?,
+            (None, None, Some(_)) => write!(f, "{name} (run_cmd")?,
+            (None, None, None) => write!(f, "{name} (no run_lib, run_script, or run_cmd")?,
+        }
+        if self.needs_sudo {
+            write!(f, ", needs sudo")?;
+        }
+        write!(f, "): {reason}", reason = self.reason)
+    }
+}
@@ -0,0 +1,382 @@
+/*!
+Task libraries provided by external executables (`run_lib: plugin:<name>`).
+
+Plugins let you add task libraries without changing up itself. A task with
+`run_lib: plugin:<name>` runs the plugin executable, which is found at:
+
+1. `libs/<name>` in the up config directory (e.g. `~/.config/up/libs/<name>`), or
+2. the `path` (default `<name>`) in the git repo declared for `<name>` in the `plugins` field of
+   `up.yaml`, which up clones (and updates) before running it:
+
+```yaml
+plugins:
+  brew:
+    git_url: https://github.com/example/up-brew
+    path: bin/up-brew
+```
+
+The plugin is run in the task's temporary directory, with:
+
+- the task's `data:` block (with env vars resolved) as JSON on stdin.
+- the up env vars, plus `UP_TASK_NAME`, `UP_TASK_TEMPDIR`, and `UP_BACKUP_DIR`.
+
+As with `run_cmd`, an exit code of 0 means the task passed, 204 means it was skipped, and
+anything else means it failed. A plugin that exits with 0 or 204 can also report its status by
+printing a JSON object as the last line of stdout, e.g.
+`{"status": "skipped", "message": "Already up to date."}` (`status` is one of `passed`, `skipped`,
+or `failed`), other stdout is ignored. stderr is written to the task's output file.
+*/
+use self::PluginError as E;
+use crate::config::UpConfig;
+use crate::exec::cmd_log;
+use crate::exec::UpDuct;
+use crate::tasks::git::update;
+use crate::tasks::git::GitConfig;
+use crate::tasks::git::GitRemote;
+use crate::tasks::git::DEFAULT_REMOTE_NAME;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::task::DEFAULT_SLOW_WARN_AFTER;
+use crate::tasks::task::TASK_OUTPUT_FILE;
+use crate::tasks::TaskError;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::Level;
+
+/// Prefix of a `run_lib` value that refers to a plugin.
+pub(crate) const PLUGIN_PREFIX: &str = "plugin:";
+
+/// Directory (relative to the up config directory) that local plugin executables are found in.
+const LIBS_DIR: &str = "libs";
+
+/// Directory (relative to the up cache dir) that plugin repos are cloned into.
+const PLUGIN_CLONES_DIR: &str = "plugins";
+
+/// A plugin fetched from a git repo, declared in the `plugins` field of `up.yaml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// URL of the git repo containing the plugin.
+    pub git_url: String,
+    /// Path of the plugin executable inside the repo, defaults to the plugin name.
+    pub path: Option<Utf8PathBuf>,
+}
+
+/// How the plugin reported its status on stdout.
+#[derive(Debug, Deserialize)]
+struct PluginOutput {
+    /// Status of the task.
+    status: PluginStatus,
+    /// Message to log (or error with if the task failed).
+    message: Option<String>,
+}
+
+/// Task status reported by a plugin.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PluginStatus {
+    /// Task passed.
+    Passed,
+    /// Task had nothing to do.
+    Skipped,
+    /// Task failed.
+    Failed,
+}
+
+/// The plugins available to tasks in this run.
+#[derive(Debug, Default)]
+pub struct Plugins {
+    /// Directory containing local plugin executables.
+    libs_dir: Option<Utf8PathBuf>,
+    /// Plugins declared in `up.yaml`.
+    declared: HashMap<String, PluginConfig>,
+    /// Directory to clone declared plugins into.
+    clones_dir: Utf8PathBuf,
+    /// Executables of declared plugins that have already been fetched in this run, so tasks using
+    /// the same plugin don't update its repo at the same time.
+    fetched: Mutex<HashMap<String, Utf8PathBuf>>,
+    /// Use existing clones of declared plugins without updating them.
+    offline: bool,
+}
+
+impl Plugins {
+    /// Plugins available with the up `config`.
+    pub(crate) fn new(config: &UpConfig) -> Self {
+        Self {
+            libs_dir: config
+                .up_yaml_path
+                .as_ref()
+                .and_then(|path| path.parent())
+                .map(|dir| dir.join(LIBS_DIR)),
+            declared: config.config_yaml.plugins.clone().unwrap_or_default(),
+            clones_dir: config.cache_dir.join(PLUGIN_CLONES_DIR),
+            fetched: Mutex::default(),
+            offline: config.offline,
+        }
+    }
+
+    /// Whether plugin `name` is available locally or declared in `up.yaml` (without fetching it).
+    pub(crate) fn exists(&self, name: &str) -> bool {
+        self.local_executable(name).is_some() || self.declared.contains_key(name)
+    }
+
+    /// The plugin's executable in the libs dir, if there is one.
+    fn local_executable(&self, name: &str) -> Option<Utf8PathBuf> {
+        let path = self.libs_dir.as_ref()?.join(name);
+        path.is_file().then_some(path)
+    }
+
+    /// Find the executable for plugin `name`, cloning or updating its repo if it is declared in
+    /// `up.yaml`.
+    fn executable(&self, name: &str) -> Result<Utf8PathBuf> {
+        if let Some(path) = self.local_executable(name) {
+            return Ok(path);
+        }
+        let Some(plugin) = self.declared.get(name) else {
+            return Err(E::NotFound {
+                name: name.to_owned(),
+                libs_dir: self.libs_dir.clone().unwrap_or_default(),
+            }
+            .into());
+        };
+
+        let mut fetched = self.fetched.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(path) = fetched.get(name) {
+            return Ok(path.clone());
+        }
+        let repo_path = self.clones_dir.join(name);
+        let path = repo_path.join(
+            plugin
+                .path
+                .as_deref()
+                .unwrap_or_else(|| Utf8Path::new(name)),
+        );
+        if self.offline {
+            if !path.is_file() {
+                return Err(E::Offline {
+                    name: name.to_owned(),
+                }
+                .into());
+            }
+            debug!("Using existing clone of plugin '{name}' as running offline.");
+            fetched.insert(name.to_owned(), path.clone());
+            return Ok(path);
+        }
+        info!(
+            "Fetching plugin '{name}' from {url} to {repo_path}",
+            url = plugin.git_url
+        );
+        update::update(
+            &GitConfig {
+                path: repo_path.clone(),
+                remotes: vec![GitRemote {
+                    name: DEFAULT_REMOTE_NAME.to_owned(),
+                    push_url: None,
+                    fetch_url: plugin.git_url.clone(),
+                }],
+                ..GitConfig::default()
+            },
+            DEFAULT_SLOW_WARN_AFTER,
+        )
+        .map_err(|e| E::Fetch {
+            name: name.to_owned(),
+            source: e,
+        })?;
+        if !path.is_file() {
+            return Err(E::NotInRepo {
+                name: name.to_owned(),
+                path,
+            }
+            .into());
+        }
+        fetched.insert(name.to_owned(), path.clone());
+        Ok(path)
+    }
+}
+
+/// Run plugin `name` for the task `task_name`, passing it the (env-resolved) `data`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run<F>(
+    plugins: &Plugins,
+    name: &str,
+    data: Option<serde_yaml::Value>,
+    env_fn: F,
+    env: &HashMap<String, String>,
+    task_name: &str,
+    task_tempdir: &Utf8Path,
+    backup_dir: &Utf8Path,
+    console: bool,
+) -> Result<TaskStatus>
+where
+    F: Fn(&str) -> Result<String, TaskError>,
+{
+    let executable = plugins.executable(name)?;
+    if executable
+        .metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o111 == 0)
+    {
+        return Err(E::NotExecutable { path: executable }.into());
+    }
+
+    let mut data = data.unwrap_or_default();
+    resolve_data(&mut data, &env_fn)?;
+    let input = serde_json::to_vec(&data).map_err(|e| E::DataToJson {
+        name: name.to_owned(),
+        source: e,
+    })?;
+
+    let mut plugin_env = env.clone();
+    plugin_env.insert("UP_TASK_NAME".to_owned(), task_name.to_owned());
+    plugin_env.insert("UP_TASK_TEMPDIR".to_owned(), task_tempdir.to_string());
+    plugin_env.insert("UP_BACKUP_DIR".to_owned(), backup_dir.to_string());
+
+    let task_output_file = task_tempdir.join(TASK_OUTPUT_FILE);
+    let mut command = cmd_log(Level::DEBUG, executable.as_str(), Vec::<String>::new())
+        .dir(task_tempdir)
+        .full_env(&plugin_env)
+        .stdin_bytes(input)
+        .stdout_capture()
+        .unchecked();
+    if !console {
+        command = command.stderr_path(&task_output_file);
+    }
+    let output = command.run_with_inherit().map_err(|e| E::Run {
+        path: executable.clone(),
+        source: e,
+    })?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    debug!("Plugin '{name}' stdout: <<<{stdout}>>>");
+    // Plugins may print other output first (e.g. from commands they run), so only a last line
+    // that looks like a JSON object is taken as the reported status.
+    let reported: Option<PluginOutput> =
+        match stdout.lines().map(str::trim).rfind(|l| !l.is_empty()) {
+            Some(line) if line.starts_with('{') => {
+                Some(serde_json::from_str(line).map_err(|e| E::InvalidOutput {
+                    name: name.to_owned(),
+                    source: e,
+                })?)
+            }
+            _ => None,
+        };
+    let message = reported.as_ref().and_then(|r| r.message.clone());
+    if let Some(message) = &message {
+        info!("{message}");
+    }
+
+    // A failing exit code fails the task whatever the plugin reported.
+    match (reported.map(|r| r.status), output.status.code()) {
+        (Some(PluginStatus::Failed), code) | (_, code @ (None | Some(1..=203 | 205..))) => {
+            Err(E::Failed {
+                name: name.to_owned(),
+                code: code.map_or_else(|| "none".to_owned(), |c| c.to_string()),
+                message: message.unwrap_or_else(|| format!("see {task_output_file}")),
+            }
+            .into())
+        }
+        (Some(PluginStatus::Skipped), _) | (None, Some(204)) => Ok(TaskStatus::Skipped),
+        (Some(PluginStatus::Passed), _) | (None, Some(_)) => {
+            Ok(TaskStatus::Passed(TaskChanges::default()))
+        }
+    }
+}
+
+/// Resolve env vars in all the strings in `value`.
+fn resolve_data<F>(value: &mut serde_yaml::Value, env_fn: &F) -> Result<(), TaskError>
+where
+    F: Fn(&str) -> Result<String, TaskError>,
+{
+    match value {
+        serde_yaml::Value::String(s) => *s = env_fn(s)?,
+        serde_yaml::Value::Sequence(seq) => {
+            for v in seq {
+                resolve_data(v, env_fn)?;
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for (_, v) in map.iter_mut() {
+                resolve_data(v, env_fn)?;
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => resolve_data(&mut tagged.value, env_fn)?,
+        serde_yaml::Value::Null | serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum PluginError {
+    /// Plugin '{name}' not found in `{libs_dir}` or the `plugins` field of up.yaml.
+    NotFound {
+        /// Plugin name.
+        name: String,
+        /// Directory searched for local plugins.
+        libs_dir: Utf8PathBuf,
+    },
+    /// Can't fetch plugin '{name}' when running offline, and it hasn't been fetched before.
+    Offline {
+        /// Plugin name.
+        name: String,
+    },
+    /// Failed to fetch plugin '{name}'.
+    Fetch {
+        /// Plugin name.
+        name: String,
+        /// Source error.
+        source: color_eyre::eyre::Error,
+    },
+    /// Plugin '{name}' executable not found at `{path}` in the plugin repo.
+    NotInRepo {
+        /// Plugin name.
+        name: String,
+        /// Expected executable path.
+        path: Utf8PathBuf,
+    },
+    /// Plugin `{path}` isn't executable, try running `chmod +x {path}`.
+    NotExecutable {
+        /// Plugin executable path.
+        path: Utf8PathBuf,
+    },
+    /// Failed to convert the data for plugin '{name}' to JSON.
+    DataToJson {
+        /// Plugin name.
+        name: String,
+        /// Source error.
+        source: serde_json::Error,
+    },
+    /// Failed to run plugin `{path}`.
+    Run {
+        /// Plugin executable path.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Plugin '{name}' printed an invalid JSON status as the last line of stdout.
+    InvalidOutput {
+        /// Plugin name.
+        name: String,
+        /// Source error.
+        source: serde_json::Error,
+    },
+    /// Plugin '{name}' failed (exit code {code}): {message}
+    Failed {
+        /// Plugin name.
+        name: String,
+        /// Exit code of the plugin.
+        code: String,
+        /// Message from the plugin.
+        message: String,
+    },
+}
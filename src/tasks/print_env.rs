@@ -0,0 +1,73 @@
+//! Print the env vars tasks would be run with (`up env`).
+use crate::config::UpConfig;
+use crate::opts::EnvFormat;
+use crate::tasks;
+use color_eyre::eyre::Result;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Printed instead of the values of env vars that look like secrets.
+const MASKED_VALUE: &str = "********";
+
+/// Parts of env var names (upper-cased) that mean the value is probably a secret.
+const SECRET_NAME_PARTS: [&str; 7] = [
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "CREDENTIAL",
+    "API_KEY",
+    "PRIVATE_KEY",
+];
+
+/// Print the resolved task env for the config.
+pub(crate) fn run(config: &UpConfig, format: EnvFormat, show_secrets: bool) -> Result<()> {
+    let env = tasks::config_env(config)?;
+    print!("{}", format_env(&env, format, show_secrets)?);
+    Ok(())
+}
+
+/// Format the `env` sorted by name, masking secret values unless `show_secrets` is set.
+fn format_env(
+    env: &HashMap<String, String>,
+    format: EnvFormat,
+    show_secrets: bool,
+) -> Result<String> {
+    let env: BTreeMap<&str, &str> = env
+        .iter()
+        .map(|(key, value)| {
+            let value = if show_secrets || value.is_empty() || !is_secret(key) {
+                value.as_str()
+            } else {
+                MASKED_VALUE
+            };
+            (key.as_str(), value)
+        })
+        .collect();
+
+    let mut out = String::new();
+    match format {
+        EnvFormat::Text => {
+            for (key, value) in env {
+                writeln!(out, "{key}={value}")?;
+            }
+        }
+        EnvFormat::Export => {
+            for (key, value) in env {
+                writeln!(out, "export {key}={}", shell_escape::escape(value.into()))?;
+            }
+        }
+        EnvFormat::Json => {
+            out = serde_json::to_string_pretty(&env)?;
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Whether the env var called `key` probably contains a secret.
+fn is_secret(key: &str) -> bool {
+    let key = key.to_uppercase();
+    SECRET_NAME_PARTS.iter().any(|part| key.contains(part))
+}
@@ -0,0 +1,140 @@
+/*!
+A short status of up's recent runs for shell prompts (`up prompt-hook`), e.g.
+`up: 2 tasks failing, last run 3d ago`.
+
+Only the run history is read (not the config, the tasks, or the network), so it's quick enough to
+run on every prompt. Nothing is printed if no task is failing and up ran recently, so the prompt
+only changes when the machine may have drifted from its config.
+
+```zsh
+# ~/.zshrc
+setopt PROMPT_SUBST
+RPROMPT='$(up prompt-hook)'
+```
+
+```bash
+# ~/.bashrc
+PS1='$(up prompt-hook)'"$PS1"
+```
+*/
+use crate::opts::Opts;
+use crate::opts::PromptHookOptions;
+use crate::tasks::history;
+use crate::tasks::history::RunHistory;
+use crate::tasks::RUNS_DIR;
+use color_eyre::eyre::Result;
+use std::time::Duration;
+
+/// Number of most recent runs to read task statuses from, so the hook stays fast however long the
+/// run history is.
+const MAX_RUNS: usize = 10;
+
+/// Run the `up prompt-hook` command.
+pub(crate) fn run(opts: &Opts, cmd_opts: &PromptHookOptions) -> Result<()> {
+    let history = RunHistory::read(&opts.run_temp_dir()?.join(RUNS_DIR))?;
+    let failing = history
+        .latest_records(MAX_RUNS)?
+        .values()
+        .filter(|record| record.status == "failed")
+        .count();
+    let since_last_run = match history.run_dirs().first() {
+        None => SinceLastRun::Never,
+        Some(run_dir) => history::run_started(run_dir).map_or(SinceLastRun::Unknown, |started| {
+            SinceLastRun::Ago(
+                (*opts.start_time - started.to_utc())
+                    .to_std()
+                    .unwrap_or_default(),
+            )
+        }),
+    };
+    if let Some(status) = status(failing, since_last_run, cmd_opts.stale_after.duration()) {
+        println!("{status}");
+    }
+    Ok(())
+}
+
+/// How long ago up last ran.
+#[derive(Debug, Clone, Copy)]
+enum SinceLastRun {
+    /// There are no runs in the run history.
+    Never,
+    /// The last run's directory name isn't a timestamp.
+    Unknown,
+    /// The last run started this long ago.
+    Ago(Duration),
+}
+
+/// The prompt status with `failing` tasks, or `None` if nothing needs attention.
+fn status(failing: usize, since_last_run: SinceLastRun, stale_after: Duration) -> Option<String> {
+    let since_last_run = match since_last_run {
+        SinceLastRun::Never => return Some("up: never run".to_owned()),
+        SinceLastRun::Unknown => None,
+        SinceLastRun::Ago(since_last_run) => Some(since_last_run),
+    };
+    let stale = since_last_run.is_some_and(|since_last_run| since_last_run > stale_after);
+    if failing == 0 && !stale {
+        return None;
+    }
+    let mut parts = Vec::new();
+    match failing {
+        0 => {}
+        1 => parts.push("1 task failing".to_owned()),
+        failing => parts.push(format!("{failing} tasks failing")),
+    }
+    if let Some(since_last_run) = since_last_run {
+        parts.push(format!("last run {} ago", format_ago(since_last_run)));
+    }
+    Some(format!("up: {}", parts.join(", ")))
+}
+
+/// Format a duration in its largest whole unit, e.g. `3d`, `5h`, or `12m`.
+fn format_ago(duration: Duration) -> String {
+    match duration.as_secs() {
+        secs @ 0..60 => format!("{secs}s"),
+        secs @ 60..3600 => format!("{}m", secs / 60),
+        secs @ 3600..86400 => format!("{}h", secs / 3600),
+        secs => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::status;
+    use super::SinceLastRun;
+    use color_eyre::Result;
+    use std::time::Duration;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_status() -> Result<()> {
+        let day = Duration::from_hours(24);
+        let week = day * 7;
+        ensure_eq!(
+            Some("up: never run".to_owned()),
+            status(0, SinceLastRun::Never, week)
+        );
+        ensure_eq!(None, status(0, SinceLastRun::Ago(day), week));
+        ensure_eq!(None, status(0, SinceLastRun::Unknown, week));
+        ensure_eq!(
+            Some("up: 2 tasks failing, last run 3d ago".to_owned()),
+            status(2, SinceLastRun::Ago(day * 3), week)
+        );
+        ensure_eq!(
+            Some("up: 1 task failing, last run 5m ago".to_owned()),
+            status(
+                1,
+                SinceLastRun::Ago(Duration::from_mins(5) + Duration::from_secs(3)),
+                week
+            )
+        );
+        ensure_eq!(
+            Some("up: last run 8d ago".to_owned()),
+            status(0, SinceLastRun::Ago(day * 8), week)
+        );
+        ensure_eq!(
+            Some("up: 1 task failing".to_owned()),
+            status(1, SinceLastRun::Unknown, week)
+        );
+        Ok(())
+    }
+}
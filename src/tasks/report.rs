@@ -0,0 +1,161 @@
+//! Format the end-of-run report of how each task finished, grouped by tag.
+use crate::opts::SummaryFormat;
+use crate::tasks::task::Task;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Group shown for tasks that have no tags.
+const UNTAGGED: &str = "untagged";
+
+/// How a task finished, in the order they're shown in the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Outcome {
+    /// The task passed.
+    Passed,
+    /// The task was skipped.
+    Skipped,
+    /// The task wasn't run as it needs the network and up is running offline.
+    Offline,
+    /// The task wasn't run as the run's `--max-duration` was exceeded before it started.
+    NotRun,
+    /// The task failed, but has `allow_failure` set.
+    Warned,
+    /// The task failed.
+    Failed,
+}
+
+impl Outcome {
+    /// Name shown in the report.
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Passed => "passed",
+            Outcome::Skipped => "skipped",
+            Outcome::Offline => "offline",
+            Outcome::NotRun => "not run",
+            Outcome::Warned => "warned",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// A task to include in the report.
+#[derive(Debug)]
+pub(crate) struct ReportEntry<'a> {
+    /// Task name.
+    pub(crate) name: &'a str,
+    /// Group the task is shown in, its first tag.
+    pub(crate) group: &'a str,
+    /// How the task finished.
+    pub(crate) outcome: Outcome,
+}
+
+impl<'a> ReportEntry<'a> {
+    /// Report entry for a finished task.
+    pub(crate) fn new(task: &'a Task, outcome: Outcome) -> Self {
+        Self {
+            name: &task.name,
+            group: task
+                .config
+                .tags
+                .iter()
+                .flatten()
+                .next()
+                .map_or(UNTAGGED, String::as_str),
+            outcome,
+        }
+    }
+}
+
+/**
+Format the report of how tasks finished, grouped by their first tag (untagged tasks last).
+
+`full` lists the task names for each outcome, `compact` only the counts, and `quiet` shows
+nothing (returns `None`).
+*/
+pub(crate) fn format_report(entries: &[ReportEntry], format: SummaryFormat) -> Option<String> {
+    if entries.is_empty() || matches!(format, SummaryFormat::Quiet) {
+        return None;
+    }
+
+    let mut groups: BTreeMap<(bool, &str), BTreeMap<Outcome, Vec<&str>>> = BTreeMap::new();
+    for entry in entries {
+        groups
+            .entry((entry.group == UNTAGGED, entry.group))
+            .or_default()
+            .entry(entry.outcome)
+            .or_default()
+            .push(entry.name);
+    }
+
+    let mut out = String::from("Tasks by tag:");
+    for ((_, group), outcomes) in groups {
+        let outcomes = outcomes
+            .into_iter()
+            .map(|(outcome, mut names)| {
+                let count = names.len();
+                let outcome = outcome.as_str();
+                match format {
+                    SummaryFormat::Full => {
+                        names.sort_unstable();
+                        format!("{count} {outcome} ({})", names.join(", "))
+                    }
+                    SummaryFormat::Compact | SummaryFormat::Quiet => format!("{count} {outcome}"),
+                }
+            })
+            .join(", ");
+        // Writing to a String can't fail.
+        _ = write!(out, "\n  {group}: {outcomes}");
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_report;
+    use super::Outcome;
+    use super::ReportEntry;
+    use crate::opts::SummaryFormat;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    /// Shorthand to create a report entry.
+    fn entry<'a>(name: &'a str, group: &'a str, outcome: Outcome) -> ReportEntry<'a> {
+        ReportEntry {
+            name,
+            group,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_format_report() -> Result<()> {
+        let entries = [
+            entry("rust", "packages", Outcome::Passed),
+            entry("zsh", "untagged", Outcome::Skipped),
+            entry("brew", "packages", Outcome::Failed),
+            entry("apt", "packages", Outcome::Passed),
+            entry("link", "dotfiles", Outcome::Passed),
+        ];
+
+        ensure_eq!(
+            Some(
+                "Tasks by tag:\n  dotfiles: 1 passed (link)\n  packages: 2 passed (apt, rust), 1 \
+                 failed (brew)\n  untagged: 1 skipped (zsh)"
+                    .to_owned()
+            ),
+            format_report(&entries, SummaryFormat::Full)
+        );
+        ensure_eq!(
+            Some(
+                "Tasks by tag:\n  dotfiles: 1 passed\n  packages: 2 passed, 1 failed\n  untagged: \
+                 1 skipped"
+                    .to_owned()
+            ),
+            format_report(&entries, SummaryFormat::Compact)
+        );
+        ensure_eq!(None, format_report(&entries, SummaryFormat::Quiet));
+        ensure_eq!(None, format_report(&[], SummaryFormat::Full));
+        Ok(())
+    }
+}
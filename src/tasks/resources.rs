@@ -0,0 +1,218 @@
+//! Limit how many tasks using the same resource (e.g. the network) run at once, and stop tasks
+//! sharing a `mutex` from running at the same time.
+use schemars::JsonSchema;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use tracing::debug;
+
+/// A resource that a task makes heavy use of, set in the task's `resources` field.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    /// Large downloads or lots of network requests.
+    Network,
+    /// CPU heavy work, e.g. compiling.
+    Cpu,
+    /// Lots of disk reads or writes.
+    Disk,
+}
+
+/**
+Holds back tasks while running tasks are using the maximum number of slots for any of the
+resources they need (from the `max_parallel` field in `up.yaml`), or hold their mutex.
+
+Resources and mutexes are acquired together, so tasks can't deadlock waiting for each other.
+*/
+#[derive(Debug, Default)]
+pub(super) struct ResourceLimiter {
+    /// Maximum number of tasks using each resource that can run at once. Resources not in the map
+    /// are unlimited.
+    limits: HashMap<Resource, usize>,
+    /// What the running tasks are using.
+    in_use: Mutex<InUse>,
+}
+
+/// Resources and mutexes used by running tasks.
+#[derive(Debug, Default)]
+struct InUse {
+    /// Number of running tasks using each resource.
+    resources: HashMap<Resource, usize>,
+    /// Mutexes held by running tasks.
+    mutexes: HashSet<String>,
+}
+
+/// Marks a task's resources (and mutex) as in use, they are freed when this is dropped.
+#[derive(Debug)]
+pub(super) struct ResourceGuard<'a> {
+    /// Limiter the resources were acquired from.
+    limiter: &'a ResourceLimiter,
+    /// Resources to free on drop.
+    resources: Vec<Resource>,
+    /// Mutex to release on drop.
+    mutex: Option<String>,
+}
+
+impl ResourceLimiter {
+    /// Create a limiter with the `limits` from the config. Limits of 0 are treated as 1.
+    pub(super) fn new(limits: HashMap<Resource, usize>) -> Self {
+        Self {
+            limits: limits
+                .into_iter()
+                .map(|(resource, limit)| (resource, limit.max(1)))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /**
+    If all the resources and the mutex of one of the `candidates` are available, mark them as in
+    use until the returned guard is dropped. Returns the index of the candidate that was acquired
+    (the first available one), or `None` if none of them can run yet.
+
+    This doesn't wait for running tasks to free their resources, the caller should try again once
+    a task finishes.
+    */
+    pub(super) fn try_acquire_any(
+        &self,
+        candidates: &[(&[Resource], Option<&str>)],
+    ) -> Option<(usize, ResourceGuard<'_>)> {
+        let mut in_use = self.in_use.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(index) = candidates.iter().position(|(resources, mutex)| {
+            resources.iter().all(|r| {
+                in_use.resources.get(r).copied().unwrap_or_default()
+                    < self.limits.get(r).copied().unwrap_or(usize::MAX)
+            }) && !mutex.is_some_and(|mutex| in_use.mutexes.contains(mutex))
+        }) else {
+            if !candidates.is_empty() {
+                debug!("Waiting for resources or mutexes, in use: {in_use:?}");
+            }
+            return None;
+        };
+        let (resources, mutex) = candidates.get(index)?;
+        let mut resources: Vec<Resource> = resources
+            .iter()
+            .copied()
+            .filter(|r| self.limits.contains_key(r))
+            .collect();
+        resources.sort_unstable();
+        resources.dedup();
+        for resource in &resources {
+            *in_use.resources.entry(*resource).or_default() += 1;
+        }
+        if let Some(mutex) = mutex {
+            in_use.mutexes.insert((*mutex).to_owned());
+        }
+        Some((
+            index,
+            ResourceGuard {
+                limiter: self,
+                resources,
+                mutex: mutex.map(ToOwned::to_owned),
+            },
+        ))
+    }
+}
+
+impl Drop for ResourceGuard<'_> {
+    fn drop(&mut self) {
+        if self.resources.is_empty() && self.mutex.is_none() {
+            return;
+        }
+        let mut in_use = self
+            .limiter
+            .in_use
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for resource in &self.resources {
+            if let Some(count) = in_use.resources.get_mut(resource) {
+                *count = count.saturating_sub(1);
+            }
+        }
+        if let Some(mutex) = &self.mutex {
+            in_use.mutexes.remove(mutex);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resource;
+    use super::ResourceLimiter;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::collections::HashMap;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_resource_limits() -> Result<()> {
+        let limiter = ResourceLimiter::new(HashMap::from([(Resource::Network, 2)]));
+        let network_and_cpu: &[Resource] = &[Resource::Network, Resource::Cpu];
+
+        let guard_1 = limiter.try_acquire_any(&[(network_and_cpu, None)]);
+        let guard_2 = limiter.try_acquire_any(&[(network_and_cpu, None)]);
+        ensure_eq!(true, guard_1.is_some() && guard_2.is_some());
+        // Both network slots are in use.
+        ensure_eq!(
+            true,
+            limiter
+                .try_acquire_any(&[(network_and_cpu, None)])
+                .is_none()
+        );
+        // Finishing a task frees its slot.
+        drop(guard_1);
+        let (index, _guard_3) = limiter
+            .try_acquire_any(&[(network_and_cpu, None)])
+            .ok_or_else(|| eyre!("Expected the freed slot to be acquired."))?;
+        ensure_eq!(0, index);
+
+        // Unlimited resources don't block.
+        let _guard_4 = limiter.try_acquire_any(&[(&[Resource::Cpu], None)]);
+        let _guard_5 = limiter.try_acquire_any(&[(&[Resource::Cpu], None)]);
+
+        // The first candidate whose resources are free is picked.
+        let (index, _guard_6) = limiter
+            .try_acquire_any(&[(&[Resource::Network], None), (&[Resource::Disk], None)])
+            .ok_or_else(|| eyre!("Expected a candidate to be acquired."))?;
+        ensure_eq!(1, index);
+
+        ensure_eq!(true, limiter.try_acquire_any(&[]).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutex() -> Result<()> {
+        // Mutexes apply even without any resource limits.
+        let limiter = ResourceLimiter::default();
+        let guard_1 = limiter.try_acquire_any(&[(&[], Some("homebrew"))]);
+        ensure_eq!(true, guard_1.is_some());
+        ensure_eq!(
+            true,
+            limiter
+                .try_acquire_any(&[(&[], Some("homebrew"))])
+                .is_none()
+        );
+
+        // Different mutexes don't block each other, so a task waiting on a held mutex doesn't hold
+        // back one that can run.
+        let (index, _guard_2) = limiter
+            .try_acquire_any(&[(&[], Some("homebrew")), (&[], Some("apt"))])
+            .ok_or_else(|| eyre!("Expected a candidate to be acquired."))?;
+        ensure_eq!(1, index);
+
+        // Releasing the mutex lets the next task take it.
+        drop(guard_1);
+        ensure_eq!(
+            true,
+            limiter
+                .try_acquire_any(&[(&[], Some("homebrew"))])
+                .is_some()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+//! Run up inside a throwaway container (`up run --sandbox`).
+use self::SandboxError as E;
+use crate::cmd_debug;
+use crate::config::UpConfig;
+use crate::exec::cmd_log;
+use crate::exec::UpDuct;
+use crate::opts::Sandbox;
+use crate::tasks::update_self;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Context;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::fs::Permissions;
+use std::io;
+use std::io::IsTerminal;
+use std::os::unix::fs::PermissionsExt;
+use std::process;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::Level;
+
+/// Where the latest Linux up binary is released, used when this binary can't run in the sandbox.
+/// The release binaries are statically linked with musl, so they run on any Linux image.
+const LINUX_RELEASE_URL: &str =
+    "https://github.com/gibfahn/up-rs/releases/latest/download/up-linux";
+
+/**
+Args taking paths on this machine, which don't exist in the container, so they're dropped from
+the forwarded args and up uses its defaults in the container instead.
+*/
+const HOST_PATH_ARGS: &[&str] = &[
+    "--temp-dir",
+    "--up-dir",
+    "--cache-dir",
+    "--state-dir",
+    "--log-dir",
+    "--run-temp-dir",
+    "--backup-dir",
+    "--trace-file",
+    "--home",
+];
+
+/// How long a downloaded Linux up binary is used for before downloading the latest release again.
+const LINUX_BINARY_MAX_AGE: Duration = Duration::from_hours(24);
+
+/// Path the up binary is mounted at inside the container.
+const SANDBOX_UP_PATH: &str = "/usr/local/bin/up";
+
+/// Run up with the current arguments inside the `sandbox` instead of on this machine.
+pub(crate) fn run(config: &UpConfig, sandbox: &Sandbox) -> Result<()> {
+    let Sandbox::Docker { image } = sandbox;
+    let up_yaml_path = config.up_yaml_path.as_ref().ok_or(E::MissingUpYaml)?;
+    let arch = image_arch(image)?;
+    let up_binary = linux_up_binary(&config.temp_dir, image, &arch)?;
+
+    let mut args = docker_args(up_yaml_path, &up_binary, image, io::stdin().is_terminal())?;
+    args.extend(forwarded_args(env::args().skip(1)));
+
+    info!("Running up in a {image} docker container.");
+    let output = cmd_log(Level::INFO, "docker", &args)
+        .unchecked()
+        .run_with_inherit()
+        .map_err(|e| E::Run { source: e })?;
+    if !output.status.success() {
+        return Err(E::Failed {
+            image: image.clone(),
+            status: output.status,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/**
+The `docker run` args to run up from `up_binary` in the `image` container, with the directory
+containing the up config mounted read-only at the same path.
+
+The config path is made absolute first, as a relative `--config` would mount the wrong
+directory, and wouldn't resolve inside the container.
+*/
+fn docker_args(
+    up_yaml_path: &Utf8Path,
+    up_binary: &Utf8Path,
+    image: &str,
+    tty: bool,
+) -> Result<Vec<String>> {
+    let up_yaml_path = up_yaml_path
+        .canonicalize_utf8()
+        .wrap_err_with(|| E::Canonicalize {
+            path: up_yaml_path.to_owned(),
+        })?;
+    let config_dir = up_yaml_path.parent().ok_or_else(|| E::NoParent {
+        path: up_yaml_path.clone(),
+    })?;
+
+    let mut args = vec!["run".to_owned(), "--rm".to_owned()];
+    if tty {
+        args.push("-it".to_owned());
+    }
+    args.extend([
+        "--volume".to_owned(),
+        format!("{up_binary}:{SANDBOX_UP_PATH}:ro"),
+        "--volume".to_owned(),
+        format!("{config_dir}:{config_dir}:ro"),
+        image.to_owned(),
+        "up".to_owned(),
+        "--config".to_owned(),
+        up_yaml_path.to_string(),
+    ]);
+    Ok(args)
+}
+
+/**
+The args to pass through to `up` in the container.
+
+Drops `--sandbox` (so we don't recurse), `--config` (as the container needs the absolute
+path), and the [`HOST_PATH_ARGS`], along with their values.
+*/
+fn forwarded_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut forwarded = Vec::new();
+    let mut skip_value = false;
+    for arg in args {
+        if skip_value {
+            skip_value = false;
+            continue;
+        }
+        let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+        let dropped = matches!(name, "--sandbox" | "--config") || HOST_PATH_ARGS.contains(&name);
+        if dropped || arg == "-c" {
+            // The value is the next arg unless it was passed as `--arg=value`.
+            skip_value = name == arg;
+            continue;
+        }
+        forwarded.push(arg);
+    }
+    forwarded
+}
+
+/**
+The architecture of the docker `image` (e.g. `amd64` or `arm64`), pulling the image first if it
+isn't available locally.
+*/
+fn image_arch(image: &str) -> Result<String> {
+    let inspect = || {
+        cmd_debug!(
+            "docker",
+            "image",
+            "inspect",
+            "--format",
+            "{{.Architecture}}",
+            image
+        )
+        .stderr_null()
+        .read()
+    };
+    let arch = match inspect() {
+        Ok(arch) => arch,
+        Err(e) => {
+            debug!("Failed to inspect image {image}, pulling it: {e}");
+            cmd_log(Level::INFO, "docker", ["pull", image])
+                .run_with_inherit()
+                .map_err(|e| E::Pull {
+                    image: image.to_owned(),
+                    source: e,
+                })?;
+            inspect().map_err(|e| E::Inspect {
+                image: image.to_owned(),
+                source: e,
+            })?
+        }
+    };
+    Ok(arch.trim().to_owned())
+}
+
+/// The suffix of the Linux up release asset for a docker image architecture, if there is one.
+fn release_arch(image_arch: &str) -> Option<&'static str> {
+    match image_arch {
+        "amd64" => Some("x86_64"),
+        "arm64" => Some("arm64"),
+        _ => None,
+    }
+}
+
+/**
+Path to an up binary that can run in a Linux container with architecture `arch`.
+
+That's the current binary if it's a statically linked (musl) Linux binary for the same
+architecture, so it runs whatever libc the image has. Otherwise the latest Linux release for
+`arch` is downloaded into the up temp dir, and downloaded again once it's older than
+[`LINUX_BINARY_MAX_AGE`].
+*/
+fn linux_up_binary(temp_dir: &Utf8Path, image: &str, arch: &str) -> Result<Utf8PathBuf> {
+    let release_arch = release_arch(arch).ok_or_else(|| E::UnsupportedArch {
+        image: image.to_owned(),
+        arch: arch.to_owned(),
+    })?;
+    let host_arch = match env::consts::ARCH {
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    if cfg!(all(target_os = "linux", target_env = "musl")) && host_arch == release_arch {
+        let current_exe = env::current_exe().map_err(|e| E::CurrentExe { source: e })?;
+        return Ok(Utf8PathBuf::try_from(current_exe)?);
+    }
+
+    let sandbox_dir = temp_dir.join("sandbox");
+    fs::create_dir_all(&sandbox_dir).wrap_err_with(|| E::CreateDir {
+        path: sandbox_dir.clone(),
+    })?;
+    let up_path = sandbox_dir.join(format!("up-linux-{release_arch}"));
+    let up_to_date = fs::metadata(&up_path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| {
+            modified
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed < LINUX_BINARY_MAX_AGE)
+        });
+    if up_to_date {
+        debug!("Using the Linux up binary downloaded to {up_path}.");
+        return Ok(up_path);
+    }
+
+    info!("Downloading the latest {release_arch} Linux up binary to run in the sandbox.");
+    // The x86_64 binary is released without an architecture suffix.
+    let url = match release_arch {
+        "x86_64" => LINUX_RELEASE_URL.to_owned(),
+        release_arch => format!("{LINUX_RELEASE_URL}-{release_arch}"),
+    };
+    // Download next to the binary and rename it into place, so an interrupted download (or a
+    // concurrent run) never leaves a partial binary at `up_path`.
+    let download_path = sandbox_dir.join(format!(
+        "up-linux-{release_arch}.{}.download",
+        process::id()
+    ));
+    let result = download_binary(&url, &download_path).and_then(|()| {
+        fs::rename(&download_path, &up_path).wrap_err_with(|| E::Rename {
+            from: download_path.clone(),
+            to: up_path.clone(),
+        })
+    });
+    if result.is_err() {
+        _ = fs::remove_file(&download_path);
+    }
+    result?;
+    Ok(up_path)
+}
+
+/// Download the Linux up release at `url` to `path`, and make it executable.
+fn download_binary(url: &str, path: &Utf8Path) -> Result<()> {
+    let mut response = update_self::download(url).wrap_err_with(|| E::Download {
+        url: url.to_owned(),
+    })?;
+    let mut dest = File::create(path).wrap_err_with(|| E::CreateFile {
+        path: path.to_owned(),
+    })?;
+    io::copy(&mut response, &mut dest).wrap_err_with(|| E::CreateFile {
+        path: path.to_owned(),
+    })?;
+    fs::set_permissions(path, Permissions::from_mode(0o755)).wrap_err_with(|| {
+        E::SetPermissions {
+            path: path.to_owned(),
+        }
+    })?;
+    Ok(())
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum SandboxError {
+    /// Running in a sandbox requires an up.yaml, pass one with --config.
+    MissingUpYaml,
+    /// Failed to find the absolute path of the up config `{path}`.
+    Canonicalize {
+        /// The up.yaml path.
+        path: Utf8PathBuf,
+    },
+    /// Up config path `{path}` has no parent directory.
+    NoParent {
+        /// The up.yaml path.
+        path: Utf8PathBuf,
+    },
+    /// Failed to pull the docker image `{image}`, is docker installed?
+    Pull {
+        /// Container image.
+        image: String,
+        /// Source error.
+        source: io::Error,
+    },
+    /// Failed to find the architecture of the docker image `{image}`.
+    Inspect {
+        /// Container image.
+        image: String,
+        /// Source error.
+        source: io::Error,
+    },
+    /// Image `{image}` is `{arch}`, but up only has Linux binaries for `amd64` and `arm64`.
+    UnsupportedArch {
+        /// Container image.
+        image: String,
+        /// Image architecture.
+        arch: String,
+    },
+    /// Failed to download the Linux up binary from {url}.
+    Download {
+        /// Release URL.
+        url: String,
+    },
+    /// Failed to find the path to the current up binary.
+    CurrentExe {
+        /// Source error.
+        source: io::Error,
+    },
+    /// Failed to create directory `{path}`.
+    CreateDir {
+        /// Directory path.
+        path: Utf8PathBuf,
+    },
+    /// Failed to create file `{path}`.
+    CreateFile {
+        /// File path.
+        path: Utf8PathBuf,
+    },
+    /// Failed to set permissions for `{path}`.
+    SetPermissions {
+        /// File path.
+        path: Utf8PathBuf,
+    },
+    /// Failed to move `{from}` to `{to}`.
+    Rename {
+        /// Downloaded file.
+        from: Utf8PathBuf,
+        /// Path it was moved to.
+        to: Utf8PathBuf,
+    },
+    /// Failed to run docker, is it installed?
+    Run {
+        /// Source error.
+        source: io::Error,
+    },
+    /// Up failed in the `{image}` container with {status}.
+    Failed {
+        /// Container image.
+        image: String,
+        /// Exit status of docker.
+        status: std::process::ExitStatus,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::docker_args;
+    use super::forwarded_args;
+    use super::release_arch;
+    use camino::Utf8Path;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    /// Sandbox, config, and host path args are dropped, everything else is passed through.
+    #[test]
+    fn test_forwarded_args() -> Result<()> {
+        let args = [
+            "--config",
+            "up.yaml",
+            "run",
+            "--sandbox",
+            "docker:debian",
+            "--tasks=foo",
+            "--sandbox=docker",
+            "-c",
+            "other.yaml",
+            "--config=third.yaml",
+            "--state-dir",
+            "/Users/me/.local/state/up",
+            "--cache-dir=/Users/me/.cache/up",
+            "--home",
+            "/Users/me",
+            "--log-dir=/tmp/logs",
+            "--offline",
+            "--keep-going",
+        ]
+        .map(str::to_owned);
+        ensure_eq!(
+            vec!["run", "--tasks=foo", "--offline", "--keep-going"],
+            forwarded_args(args)
+        );
+        Ok(())
+    }
+
+    /// Images are matched to the up release for their architecture.
+    #[test]
+    fn test_release_arch() -> Result<()> {
+        ensure_eq!(Some("x86_64"), release_arch("amd64"));
+        ensure_eq!(Some("arm64"), release_arch("arm64"));
+        ensure_eq!(None, release_arch("s390x"));
+        Ok(())
+    }
+
+    /// A relative config path is mounted and passed to the container as an absolute path.
+    #[test]
+    fn test_docker_args_relative_config() -> Result<()> {
+        // Unit tests run in the crate root.
+        let config_dir = Utf8Path::new(env!("CARGO_MANIFEST_DIR"))
+            .canonicalize_utf8()?
+            .join("tests/fixtures/config/test_basic_yaml");
+        let args = docker_args(
+            Utf8Path::new("tests/fixtures/config/test_basic_yaml/up.yaml"),
+            Utf8Path::new("/tmp/up-linux-arm64"),
+            "debian",
+            false,
+        )?;
+        ensure_eq!(
+            vec![
+                "run".to_owned(),
+                "--rm".to_owned(),
+                "--volume".to_owned(),
+                "/tmp/up-linux-arm64:/usr/local/bin/up:ro".to_owned(),
+                "--volume".to_owned(),
+                format!("{config_dir}:{config_dir}:ro"),
+                "debian".to_owned(),
+                "up".to_owned(),
+                "--config".to_owned(),
+                config_dir.join("up.yaml").to_string(),
+            ],
+            args
+        );
+        Ok(())
+    }
+}
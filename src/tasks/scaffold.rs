@@ -0,0 +1,305 @@
+/*!
+Create new task files from a template (`up task new`).
+
+```console
+❯ up task new dotfiles --lib link
+❯ up task new work/vpn --cmd --description "Connect to the work VPN."
+```
+
+Tasks using a run library get example `data` for that library, to be edited to match what you
+want the task to do.
+*/
+use self::ScaffoldError as E;
+use crate::cmd;
+use crate::exec::UpDuct;
+use crate::opts::TaskNewOptions;
+use crate::tasks::plugin::PLUGIN_PREFIX;
+use crate::tasks::task::TaskConfig;
+use crate::tasks::task::RUN_LIBS;
+use camino::Utf8Component;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use std::env;
+use std::fs;
+use thiserror::Error;
+use tracing::info;
+
+/// Comment at the top of new task files.
+const NEW_TASK_COMMENT: &str =
+    "# Created by `up task new`, run `up schema` to see all the fields a task can set.\n";
+
+/// Example `data` for each run library that needs it.
+const LIB_DATA: &[(&str, &str)] = &[
+    (
+        "brew",
+        "formulae:\n  - git\n  - ripgrep\ncasks:\n  - firefox\n",
+    ),
+    (
+        "colima",
+        "cpus: 4\nmemory: 8\nmounts:\n  - ~/code:w\ndocker_context: true\n",
+    ),
+    (
+        "default_apps",
+        "extensions:\n  md: com.microsoft.VSCode\nurl_schemes:\n  mailto: com.apple.mail\n",
+    ),
+    ("defaults", "com.apple.dock:\n  autohide: true\n"),
+    (
+        "finder",
+        "sidebar_favorites:\n  - ~/code\nscreenshot_folder: ~/Pictures/Screenshots\n",
+    ),
+    (
+        "generate_git",
+        "- path: ~/.config/up/tasks/git.yaml\n  search_paths: [~/code]\n  prune: true\n  \
+         remote_order: [origin]\n",
+    ),
+    (
+        "git",
+        "- path: ~/code/up-rs\n  remotes:\n    - name: origin\n      fetch_url: \
+         https://github.com/gibfahn/up-rs\n",
+    ),
+    (
+        "json",
+        "path: ~/.config/app/config.json\nvalues:\n  setting: value\n",
+    ),
+    ("keygen", "github_title: $USER laptop\n"),
+    ("link", "from_dir: ~/code/dotfiles\nto_dir: \"~\"\n"),
+    (
+        "toml",
+        "path: ~/.config/app/config.toml\nvalues:\n  setting: value\n",
+    ),
+    ("vscode", "extensions:\n  - rust-lang.rust-analyzer\n"),
+];
+
+/// Run libraries that need root, so the template sets `needs_sudo: true`.
+const SUDO_LIBS: &[&str] = &["developer_tools", "software_update"];
+
+/// Run the `up task new` command, writing the new task file to `tasks_dir`.
+pub(crate) fn run(opts: &TaskNewOptions, tasks_dir: &Utf8Path) -> Result<()> {
+    let path = task_path(tasks_dir, &opts.name)?;
+    if !opts.force && path.exists() {
+        return Err(E::TaskExists { path }.into());
+    }
+
+    let mut contents = NEW_TASK_COMMENT.to_owned();
+    contents.push_str(&serde_yaml::to_string(&task_config(opts)?)?);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| E::WriteTask {
+            path: parent.to_owned(),
+            source: e,
+        })?;
+    }
+    fs::write(&path, contents).map_err(|e| E::WriteTask {
+        path: path.clone(),
+        source: e,
+    })?;
+    info!("Wrote {path}");
+
+    if !opts.no_edit {
+        if let Some(editor) = editor() {
+            open_in_editor(&editor, &path)?;
+        } else {
+            info!("Set $EDITOR to open new tasks automatically, or pass --no-edit.");
+        }
+    }
+    Ok(())
+}
+
+/// Path of the task file for a task called `name` in `tasks_dir`.
+fn task_path(tasks_dir: &Utf8Path, name: &str) -> Result<Utf8PathBuf, E> {
+    let name = name.strip_suffix(".yaml").unwrap_or(name);
+    let relative = Utf8Path::new(name);
+    if name.is_empty()
+        || !relative
+            .components()
+            .all(|c| matches!(c, Utf8Component::Normal(_)))
+    {
+        return Err(E::InvalidName {
+            name: name.to_owned(),
+        });
+    }
+    Ok(tasks_dir.join(format!("{name}.yaml")))
+}
+
+/// The task config for a new task.
+fn task_config(opts: &TaskNewOptions) -> Result<TaskConfig> {
+    let description = opts
+        .description
+        .clone()
+        .unwrap_or_else(|| "TODO: describe what this task does.".to_owned());
+    let Some(lib) = &opts.lib else {
+        return Ok(TaskConfig {
+            description: Some(description),
+            run_cmd: Some(vec![
+                "echo".to_owned(),
+                "TODO: replace with the command to run.".to_owned(),
+            ]),
+            ..TaskConfig::default()
+        });
+    };
+
+    if !RUN_LIBS.contains(&lib.as_str()) && !lib.starts_with(PLUGIN_PREFIX) {
+        return Err(E::UnknownLib {
+            lib: lib.clone(),
+            libs: RUN_LIBS.join(", "),
+        }
+        .into());
+    }
+    let data = LIB_DATA
+        .iter()
+        .find(|(name, _)| name == lib)
+        .map(|(_, data)| serde_yaml::from_str(data))
+        .transpose()?;
+    Ok(TaskConfig {
+        description: Some(description),
+        run_lib: Some(lib.clone()),
+        needs_sudo: SUDO_LIBS.contains(&lib.as_str()),
+        data,
+        ..TaskConfig::default()
+    })
+}
+
+/// The user's `$VISUAL` or `$EDITOR`, if either is set.
+pub(crate) fn editor() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+}
+
+/// Open `path` in `editor`.
+pub(crate) fn open_in_editor(editor: &str, path: &Utf8Path) -> Result<(), E> {
+    // The editor can have arguments, e.g. `code --wait`, so let the shell split it.
+    cmd!("sh", "-c", format!("{editor} \"$1\""), "sh", path)
+        .run_with_inherit()
+        .map_err(|e| E::Editor {
+            editor: editor.to_owned(),
+            path: path.to_owned(),
+            source: e,
+        })?;
+    Ok(())
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum ScaffoldError {
+    /// Task name '{name}' isn't valid, it should be a relative path like `brew` or `work/vpn`.
+    InvalidName {
+        /// The task name.
+        name: String,
+    },
+    /// Unknown run library '{lib}', expected one of: {libs}, or `plugin:<name>`.
+    UnknownLib {
+        /// The run library passed.
+        lib: String,
+        /// The built-in run libraries.
+        libs: String,
+    },
+    /// Task file {path} already exists, pass `--force` to overwrite it.
+    TaskExists {
+        /// Path to the existing task file.
+        path: Utf8PathBuf,
+    },
+    /// Failed to write task file {path}.
+    WriteTask {
+        /// Path we failed to write.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to open {path} with editor `{editor}`.
+    Editor {
+        /// The editor command.
+        editor: String,
+        /// The task file.
+        path: Utf8PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::task_config;
+    use super::task_path;
+    use super::LIB_DATA;
+    use crate::opts::GenerateGitConfig;
+    use crate::opts::LinkOptions;
+    use crate::opts::TaskNewOptions;
+    use crate::tasks::brew::BrewConfig;
+    use crate::tasks::colima::ColimaConfig;
+    use crate::tasks::config_file::ConfigFileConfig;
+    use crate::tasks::default_apps::DefaultAppsConfig;
+    use crate::tasks::defaults::DefaultsConfig;
+    use crate::tasks::finder::FinderConfig;
+    use crate::tasks::git::GitConfig;
+    use crate::tasks::keygen::KeygenConfig;
+    use crate::tasks::task::RUN_LIBS;
+    use crate::tasks::vscode::VsCodeConfig;
+    use camino::Utf8Path;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    /// Options for `up task new <name> --lib <lib>`.
+    fn lib_opts(name: &str, lib: &str) -> TaskNewOptions {
+        TaskNewOptions {
+            name: name.to_owned(),
+            lib: Some(lib.to_owned()),
+            cmd: false,
+            description: None,
+            tasks_dir: None,
+            force: false,
+            no_edit: true,
+        }
+    }
+
+    #[test]
+    fn test_task_path() -> Result<()> {
+        let tasks_dir = Utf8Path::new("/up/tasks");
+        ensure_eq!(
+            Utf8Path::new("/up/tasks/brew.yaml"),
+            task_path(tasks_dir, "brew")?
+        );
+        ensure_eq!(
+            Utf8Path::new("/up/tasks/work/vpn.yaml"),
+            task_path(tasks_dir, "work/vpn.yaml")?
+        );
+        for name in ["", "../brew", "/brew", "work/../brew"] {
+            ensure!(task_path(tasks_dir, name).is_err(), "{name}");
+        }
+        Ok(())
+    }
+
+    /// The example data for each run library is valid config for it.
+    #[test]
+    fn test_lib_data() -> Result<()> {
+        for (lib, _) in LIB_DATA {
+            ensure!(RUN_LIBS.contains(lib), "{lib}");
+            let data = task_config(&lib_opts("test", lib))?.data;
+            let data = data.ok_or_else(|| color_eyre::eyre::eyre!("No data for {lib}"))?;
+            match *lib {
+                "brew" => _ = serde_yaml::from_value::<BrewConfig>(data)?,
+                "colima" => _ = serde_yaml::from_value::<ColimaConfig>(data)?,
+                "default_apps" => _ = serde_yaml::from_value::<DefaultAppsConfig>(data)?,
+                "defaults" => _ = serde_yaml::from_value::<DefaultsConfig>(data)?,
+                "finder" => _ = serde_yaml::from_value::<FinderConfig>(data)?,
+                "generate_git" => _ = serde_yaml::from_value::<Vec<GenerateGitConfig>>(data)?,
+                "git" => _ = serde_yaml::from_value::<Vec<GitConfig>>(data)?,
+                "json" | "toml" => _ = serde_yaml::from_value::<ConfigFileConfig>(data)?,
+                "keygen" => _ = serde_yaml::from_value::<KeygenConfig>(data)?,
+                "link" => _ = serde_yaml::from_value::<LinkOptions>(data)?,
+                "vscode" => _ = serde_yaml::from_value::<VsCodeConfig>(data)?,
+                _ => color_eyre::eyre::bail!("No test for the example data for {lib}."),
+            }
+        }
+
+        let config = task_config(&lib_opts("test", "developer_tools"))?;
+        ensure!(config.needs_sudo);
+        ensure!(config.data.is_none());
+        ensure!(task_config(&lib_opts("test", "plugin:brew")).is_ok());
+        ensure!(task_config(&lib_opts("test", "homebrew")).is_err());
+        Ok(())
+    }
+}
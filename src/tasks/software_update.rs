@@ -0,0 +1,287 @@
+/*!
+The `software_update` library task, a wrapper around the macOS `softwareupdate` tool.
+
+Lists the available macOS updates, and depending on the `action` downloads or installs them.
+Installing updates needs root, so tasks that install must set `needs_sudo: true`. Updates that need
+a restart are only installed if the `reboot` policy allows restarting:
+
+```yaml
+run_lib: software_update
+needs_sudo: true
+data:
+  action: install
+  reboot: prompt
+```
+
+The task is skipped if there are no updates available (or if not running on macOS).
+*/
+use self::SoftwareUpdateError as E;
+use crate::exec::cmd;
+use crate::exec::UpDuct;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::utils::user::current_user_is_root;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::io;
+use std::io::IsTerminal;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// What to do with the available updates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SoftwareUpdateAction {
+    /// Only log the available updates.
+    #[default]
+    List,
+    /// Download the available updates, but don't install them.
+    Download,
+    /// Download and install the available updates.
+    Install,
+}
+
+/// Whether to restart the machine to install updates that need a restart.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RebootPolicy {
+    /// Never restart, updates that need a restart are downloaded but not installed.
+    #[default]
+    Never,
+    /// Ask before restarting (treated as `never` if up isn't running in a terminal).
+    Prompt,
+    /// Restart without asking if an update needs it.
+    Auto,
+}
+
+/// Configuration for a `software_update` run library task.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoftwareUpdateConfig {
+    /// What to do with the available updates, defaults to `list`.
+    #[serde(default)]
+    pub action: SoftwareUpdateAction,
+    /// Whether to restart to install updates that need it, defaults to `never`.
+    #[serde(default)]
+    pub reboot: RebootPolicy,
+    /// Only include updates Apple marks as recommended.
+    #[serde(default)]
+    pub recommended_only: bool,
+}
+
+impl ResolveEnv for SoftwareUpdateConfig {}
+
+/// An update listed by `softwareupdate --list`.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct AvailableUpdate {
+    /// Label used to refer to the update in `softwareupdate` commands.
+    pub(super) label: String,
+    /// Whether Apple recommends the update.
+    pub(super) recommended: bool,
+    /// Whether installing the update needs a restart.
+    pub(super) restart: bool,
+}
+
+/// Run a `software_update` run library task. `needs_sudo` is the task's `needs_sudo` field.
+pub(crate) fn run(config: &SoftwareUpdateConfig, needs_sudo: bool) -> Result<TaskStatus> {
+    if !cfg!(target_os = "macos") {
+        debug!("Software update: skipping as not on macOS.");
+        return Ok(TaskStatus::Skipped);
+    }
+    let is_root = current_user_is_root();
+    if config.action == SoftwareUpdateAction::Install && !needs_sudo && !is_root {
+        return Err(E::NeedsSudo.into());
+    }
+
+    let output = cmd("softwareupdate", ["--list"])
+        .stderr_to_stdout()
+        .read()
+        .map_err(|e| E::List { source: e })?;
+    let updates: Vec<AvailableUpdate> = parse_updates(&output)
+        .into_iter()
+        .filter(|update| update.recommended || !config.recommended_only)
+        .collect();
+    if updates.is_empty() {
+        debug!("No software updates available.");
+        return Ok(TaskStatus::Skipped);
+    }
+    info!(
+        "Software updates available: {}",
+        updates
+            .iter()
+            .map(|update| if update.restart {
+                format!("{} (needs restart)", update.label)
+            } else {
+                update.label.clone()
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    match config.action {
+        SoftwareUpdateAction::List => return Ok(TaskStatus::Skipped),
+        SoftwareUpdateAction::Download => {
+            softwareupdate(&["--download"], &updates, is_root)?;
+        }
+        SoftwareUpdateAction::Install => {
+            let (restart_updates, updates): (Vec<_>, Vec<_>) =
+                updates.into_iter().partition(|update| update.restart);
+            if !updates.is_empty() {
+                softwareupdate(&["--install"], &updates, is_root)?;
+            }
+            if !restart_updates.is_empty() {
+                if may_restart(config.reboot, restart_updates.len()) {
+                    softwareupdate(&["--install", "--restart"], &restart_updates, is_root)?;
+                } else {
+                    softwareupdate(&["--download"], &restart_updates, is_root)?;
+                    warn!(
+                        "Downloaded {count} software updates that need a restart, run `sudo \
+                         softwareupdate --install --all --restart` to install them.",
+                        count = restart_updates.len()
+                    );
+                }
+            }
+        }
+    }
+    Ok(TaskStatus::Passed(TaskChanges::default()))
+}
+
+/// Run `softwareupdate <args> <labels>` for the `updates`, with sudo unless we're already root.
+pub(super) fn softwareupdate(
+    args: &[&str],
+    updates: &[AvailableUpdate],
+    is_root: bool,
+) -> Result<(), E> {
+    let (program, mut command_args) = if is_root {
+        ("softwareupdate", Vec::new())
+    } else {
+        ("sudo", vec!["-n", "softwareupdate"])
+    };
+    command_args.extend(args);
+    command_args.extend(updates.iter().map(|update| update.label.as_str()));
+    cmd(program, &command_args)
+        .run_with(Expression::stdout_to_stderr)
+        .map_err(|e| E::Run {
+            args: args.join(" "),
+            source: e,
+        })?;
+    Ok(())
+}
+
+/// Whether the `reboot` policy allows restarting to install `count` updates.
+fn may_restart(reboot: RebootPolicy, count: usize) -> bool {
+    match reboot {
+        RebootPolicy::Never => false,
+        RebootPolicy::Auto => true,
+        RebootPolicy::Prompt if !io::stdin().is_terminal() => {
+            warn!("Not restarting to install software updates, as up isn't running in a terminal.");
+            false
+        }
+        RebootPolicy::Prompt => {
+            info!("Restart now to install {count} software updates? [y/N]");
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer).is_ok()
+                && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+        }
+    }
+}
+
+/**
+Parse the output of `softwareupdate --list`, which looks like:
+
+```text
+Software Update found the following new or updated software:
+* Label: macOS Sonoma 14.5-23F79
+    Title: macOS Sonoma 14.5, Version: 14.5, Size: 1234567KiB, Recommended: YES, Action: restart,
+```
+*/
+pub(super) fn parse_updates(output: &str) -> Vec<AvailableUpdate> {
+    let mut updates: Vec<AvailableUpdate> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(label) = line.strip_prefix("* Label: ") {
+            updates.push(AvailableUpdate {
+                label: label.trim().to_owned(),
+                recommended: false,
+                restart: false,
+            });
+        } else if line.starts_with("Title: ") {
+            if let Some(update) = updates.last_mut() {
+                update.recommended = line.contains("Recommended: YES");
+                update.restart = line.contains("Action: restart");
+            }
+        }
+    }
+    updates
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum SoftwareUpdateError {
+    /// Installing software updates needs root, set `needs_sudo: true` in the task.
+    NeedsSudo,
+    /// Failed to list software updates.
+    List {
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to run `softwareupdate {args}`.
+    Run {
+        /// Arguments passed to softwareupdate.
+        args: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AvailableUpdate;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_parse_updates() -> color_eyre::Result<()> {
+        let output = "Software Update Tool
+
+Finding available software
+Software Update found the following new or updated software:
+* Label: Command Line Tools for Xcode-15.3
+\tTitle: Command Line Tools for Xcode, Version: 15.3, Size: 751226KiB, Recommended: YES,
+* Label: macOS Sonoma 14.5-23F79
+\tTitle: macOS Sonoma 14.5, Version: 14.5, Size: 1234567KiB, Recommended: YES, Action: restart,
+* Label: Safari17.5Preview-23F79
+\tTitle: Safari Technology Preview, Version: 17.5, Size: 150000KiB,
+";
+        ensure_eq!(
+            vec![
+                AvailableUpdate {
+                    label: "Command Line Tools for Xcode-15.3".to_owned(),
+                    recommended: true,
+                    restart: false,
+                },
+                AvailableUpdate {
+                    label: "macOS Sonoma 14.5-23F79".to_owned(),
+                    recommended: true,
+                    restart: true,
+                },
+                AvailableUpdate {
+                    label: "Safari17.5Preview-23F79".to_owned(),
+                    recommended: false,
+                    restart: false,
+                },
+            ],
+            super::parse_updates(output)
+        );
+        ensure_eq!(
+            Vec::<AvailableUpdate>::new(),
+            super::parse_updates("Software Update Tool\n\nNo new software available.\n")
+        );
+        Ok(())
+    }
+}
@@ -9,14 +9,31 @@ use crate::opts::GenerateGitConfig;
 use crate::opts::LinkOptions;
 use crate::opts::UpdateSelfOptions;
 use crate::tasks;
+use crate::tasks::brew::BrewConfig;
+use crate::tasks::colima::ColimaConfig;
+use crate::tasks::config_file::ConfigFileConfig;
+use crate::tasks::config_file::ConfigFormat;
+use crate::tasks::default_apps::DefaultAppsConfig;
 use crate::tasks::defaults::DefaultsConfig;
+use crate::tasks::developer_tools::DeveloperToolsConfig;
+use crate::tasks::finder::FinderConfig;
 use crate::tasks::git::GitConfig;
+use crate::tasks::keygen::KeygenConfig;
+use crate::tasks::plugin::Plugins;
+use crate::tasks::plugin::PLUGIN_PREFIX;
+use crate::tasks::resources::Resource;
+use crate::tasks::software_update::SoftwareUpdateConfig;
+use crate::tasks::vscode::VsCodeConfig;
 use crate::tasks::ResolveEnv;
 use crate::tasks::TaskError as E;
+use crate::utils::duration::HumanDuration;
+use crate::utils::yaml;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Result;
+use duct::Expression;
+use regex::bytes::Regex;
 use schemars::JsonSchema;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
@@ -24,13 +41,27 @@ use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
 use std::fs;
+use std::fs::File;
+use std::fs::Permissions;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::iter::Sum;
+use std::ops::AddAssign;
+use std::os::unix::fs::PermissionsExt;
 use std::process::Output;
 use std::string::String;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 use tracing::debug;
 use tracing::info;
+use tracing::level_filters::LevelFilter;
 use tracing::trace;
+use tracing::warn;
 use tracing::Level;
 
 /// Possible statuses an asynchronously running task can have.
@@ -40,10 +71,127 @@ pub enum TaskStatus {
     Incomplete,
     /// Skipped.
     Skipped,
-    /// Completed successfully.
-    Passed,
+    /// Completed successfully, with what the task changed.
+    Passed(TaskChanges),
     /// Completed unsuccessfully.
     Failed(E),
+    /// Completed unsuccessfully, but the task has `allow_failure` set, so it doesn't fail the run.
+    Warned(E),
+    /// Not run, as it needs the network and up is running with `--offline`.
+    Offline,
+    /// Not run, as the run's `--max-duration` was exceeded before it started.
+    NotRun,
+}
+
+/// Changes a task applied, counted by the run libraries that can tell (tasks using other run
+/// libraries or commands report no changes).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TaskChanges {
+    /// Files symlinked by the `link` library.
+    pub files_linked: usize,
+    /// Keys changed by the `defaults` library.
+    pub defaults_changed: usize,
+    /// Repos cloned or updated by the `git` library.
+    pub repos_updated: usize,
+    /// Repos whose branch was fast-forwarded by the `git` library (a subset of `repos_updated`).
+    pub repos_fast_forwarded: usize,
+    /// Branches pruned by the `git` library, e.g. `~/code/up: my-pr (was at 1234abc)`.
+    pub branches_pruned: Vec<String>,
+    /// Branches the `git` library would have pruned if `prune_dry_run` wasn't set.
+    pub branches_to_prune: Vec<String>,
+}
+
+impl TaskChanges {
+    /// Whether no changes were counted (branches that would have been pruned aren't changes).
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        let Self {
+            files_linked,
+            defaults_changed,
+            repos_updated,
+            repos_fast_forwarded,
+            branches_pruned,
+            ..
+        } = self;
+        *files_linked == 0
+            && *defaults_changed == 0
+            && *repos_updated == 0
+            && *repos_fast_forwarded == 0
+            && branches_pruned.is_empty()
+    }
+}
+
+impl AddAssign for TaskChanges {
+    fn add_assign(&mut self, other: Self) {
+        let Self {
+            files_linked,
+            defaults_changed,
+            repos_updated,
+            repos_fast_forwarded,
+            branches_pruned,
+            branches_to_prune,
+        } = other;
+        self.files_linked += files_linked;
+        self.defaults_changed += defaults_changed;
+        self.repos_updated += repos_updated;
+        self.repos_fast_forwarded += repos_fast_forwarded;
+        self.branches_pruned.extend(branches_pruned);
+        self.branches_to_prune.extend(branches_to_prune);
+    }
+}
+
+impl Sum for TaskChanges {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut total, changes| {
+            total += changes;
+            total
+        })
+    }
+}
+
+impl fmt::Display for TaskChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            files_linked,
+            defaults_changed,
+            repos_updated,
+            repos_fast_forwarded,
+            branches_pruned,
+            ..
+        } = self;
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        let mut parts = Vec::new();
+        if *files_linked > 0 {
+            parts.push(format!(
+                "{files_linked} file{} linked",
+                plural(*files_linked)
+            ));
+        }
+        if *defaults_changed > 0 {
+            parts.push(format!(
+                "{defaults_changed} default{} changed",
+                plural(*defaults_changed)
+            ));
+        }
+        if *repos_updated > 0 {
+            parts.push(format!(
+                "{repos_updated} repo{} updated ({repos_fast_forwarded} fast-forwarded)",
+                plural(*repos_updated)
+            ));
+        }
+        if !branches_pruned.is_empty() {
+            parts.push(format!(
+                "{} branch{} pruned",
+                branches_pruned.len(),
+                if branches_pruned.len() == 1 { "" } else { "es" }
+            ));
+        }
+        if parts.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
 }
 
 /// A task's state.
@@ -59,14 +207,20 @@ pub struct Task {
     pub start_time: Instant,
     /// Current task status.
     pub status: TaskStatus,
+    /// How long the task took to run, once it has run.
+    pub run_time: Option<Duration>,
+    /// When to kill the task's commands if they're still running (from `--max-duration --hard`).
+    pub kill_at: Option<Instant>,
 }
 
 /// Configuration a task can have, a `~/.config/up/tasks/<name>.yaml` will deserialize to this
 /// struct.
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct TaskConfig {
-    /// Task name, defaults to file name (minus extension) if unset.
+    /// Task name, defaults to file name (minus extension) if unset. Tasks in subdirectories of
+    /// the tasks directory are prefixed with the subdirectory, e.g. `tasks/work/vpn.yaml` is
+    /// called `work/vpn`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     /// Set of Constraints that will cause the task to be run.
@@ -75,11 +229,23 @@ pub struct TaskConfig {
     /// Tasks that must have been executed beforehand.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub requires: Option<Vec<String>>,
+    /// Commands that must be in the `PATH` (or paths that must be executable) for the task to
+    /// run, e.g. `[brew, jq]`. Checked before the task runs, see `on_missing_commands`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_commands: Option<Vec<String>>,
+    /// What to do if any of the `requires_commands` are missing, `fail` (the default) or `skip`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_missing_commands: Option<OnMissingCommands>,
     /// Whether to run this by default, or only if required.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auto_run: Option<bool>,
+    /// Whether this is a bootstrap task. Every task that isn't a bootstrap task implicitly
+    /// requires the bootstrap tasks, so they run first (use `requires` to order them among
+    /// themselves). Replaces `bootstrap_tasks` in `up.yaml`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bootstrap: Option<bool>,
     /// Run library: up-rs library to use for this task. Either use this or
-    /// `run_cmd` + `run_if_cmd`.
+    /// `run_cmd`/`run_script` + `run_if_cmd`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub run_lib: Option<String>,
     /**
@@ -98,9 +264,53 @@ pub struct TaskConfig {
     */
     #[serde(skip_serializing_if = "Option::is_none")]
     pub run_cmd: Option<Vec<String>>,
+    /**
+    Run script: a script to run to perform the update, instead of a `run_cmd`.
+
+    The script is written to a file in the task's temporary directory and executed. If it
+    doesn't start with a shebang line (e.g. `#!/usr/bin/env zsh`), it is run with bash.
+    Exit codes are handled in the same way as for `run_cmd`.
+    */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_script: Option<String>,
     /// Description of the task.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Tags for the task, used to select groups of tasks to run with `up run --tags`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Resources the task makes heavy use of (`network`, `cpu`, or `disk`). The number of tasks
+    /// using a resource that run at once can be limited with `max_parallel` in `up.yaml`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Vec<Resource>>,
+    /// Name of a lock the task holds while it runs, so tasks with the same `mutex` (e.g.
+    /// `homebrew` for tasks that all run `brew`) never run at the same time. Other tasks still run
+    /// in parallel with them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mutex: Option<String>,
+    /// Warn if the task takes longer than this to run, e.g. `5m` (overrides `slow_warn_after` in
+    /// `up.yaml`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slow_warn_after: Option<HumanDuration>,
+    /// Only show the task's logs at this level or above (`off`, `error`, `warn`, `info`, `debug`,
+    /// or `trace`), e.g. `warn` to quiet a chatty task, or `debug` to see more of one task's
+    /// logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<TaskLogLevel>,
+    /// Regexes for secrets in the task command's output (e.g. `password=.*`), which are replaced
+    /// with `[REDACTED]` in the output shown on stderr and written to the task output file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redact: Option<Vec<String>>,
+    /// Set to true for tasks that prompt for input (e.g. `gh auth login`). Interactive tasks run
+    /// one at a time, with nothing else running, attached to the terminal and without a progress
+    /// bar. The `--tui` dashboard isn't shown if any are run.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interactive: bool,
+    /// Set to true for tasks that are known to fail sometimes (e.g. ones that need a VPN). If
+    /// the task fails it's reported as `warned`, and doesn't fail the run (tasks that require it
+    /// still run).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_failure: bool,
     /// Set to true to prompt for superuser privileges before running.
     /// This will allow all subtasks that up executes in this iteration.
     #[serde(default = "default_false")]
@@ -115,6 +325,221 @@ pub struct TaskConfig {
     pub data: Option<serde_yaml::Value>,
 }
 
+/// What to do when a task's `requires_commands` aren't all available.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OnMissingCommands {
+    /// Fail the task.
+    #[default]
+    Fail,
+    /// Skip the task.
+    Skip,
+}
+
+/// Log level for a task's logs, see [`TaskConfig::log_level`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskLogLevel {
+    /// Don't show any logs.
+    Off,
+    /// Only show errors.
+    Error,
+    /// Show warnings and errors.
+    Warn,
+    /// Show info logs, warnings, and errors.
+    Info,
+    /// Show debug logs and above.
+    Debug,
+    /// Show all logs.
+    Trace,
+}
+
+impl From<TaskLogLevel> for LevelFilter {
+    fn from(level: TaskLogLevel) -> Self {
+        match level {
+            TaskLogLevel::Off => Self::OFF,
+            TaskLogLevel::Error => Self::ERROR,
+            TaskLogLevel::Warn => Self::WARN,
+            TaskLogLevel::Info => Self::INFO,
+            TaskLogLevel::Debug => Self::DEBUG,
+            TaskLogLevel::Trace => Self::TRACE,
+        }
+    }
+}
+
+/// Defaults for every task, set with `defaults` in `up.yaml`. Fields a task sets itself aren't
+/// changed, e.g. a task's own `tags` replace the default `tags` rather than adding to them.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskDefaults {
+    /// Default for [`TaskConfig::constraints`].
+    pub constraints: Option<HashMap<String, String>>,
+    /// Default for [`TaskConfig::requires`].
+    pub requires: Option<Vec<String>>,
+    /// Default for [`TaskConfig::requires_commands`].
+    pub requires_commands: Option<Vec<String>>,
+    /// Default for [`TaskConfig::on_missing_commands`].
+    pub on_missing_commands: Option<OnMissingCommands>,
+    /// Default for [`TaskConfig::auto_run`].
+    pub auto_run: Option<bool>,
+    /// Default for [`TaskConfig::tags`].
+    pub tags: Option<Vec<String>>,
+    /// Default for [`TaskConfig::resources`].
+    pub resources: Option<Vec<Resource>>,
+}
+
+impl TaskConfig {
+    /// Fill in the fields this config doesn't set from the `defaults`.
+    pub(crate) fn apply_defaults(&mut self, defaults: &TaskDefaults) {
+        /// Set `field` to `default` if it isn't already set.
+        fn fill<T: Clone>(field: &mut Option<T>, default: Option<&T>) {
+            if let (None, Some(default)) = (&field, default) {
+                *field = Some(default.clone());
+            }
+        }
+        fill(&mut self.constraints, defaults.constraints.as_ref());
+        fill(&mut self.requires, defaults.requires.as_ref());
+        fill(
+            &mut self.requires_commands,
+            defaults.requires_commands.as_ref(),
+        );
+        fill(
+            &mut self.on_missing_commands,
+            defaults.on_missing_commands.as_ref(),
+        );
+        fill(&mut self.auto_run, defaults.auto_run.as_ref());
+        fill(&mut self.tags, defaults.tags.as_ref());
+        fill(&mut self.resources, defaults.resources.as_ref());
+    }
+}
+
+/// Libraries that can be used as a task's `run_lib` (must match those handled in `Task::try_run`).
+/// Plugins can also be used, as `plugin:<name>`.
+pub(crate) const RUN_LIBS: [&str; 15] = [
+    "brew",
+    "colima",
+    "default_apps",
+    "defaults",
+    "developer_tools",
+    "finder",
+    "generate_git",
+    "git",
+    "json",
+    "keygen",
+    "link",
+    "self",
+    "software_update",
+    "toml",
+    "vscode",
+];
+
+/// Libraries from [`RUN_LIBS`] that need the network, so aren't run with `--offline`.
+pub(crate) const NETWORK_RUN_LIBS: [&str; 7] = [
+    "brew",
+    "colima",
+    "developer_tools",
+    "git",
+    "self",
+    "software_update",
+    "vscode",
+];
+
+/// Start of the comment line in an executable task script that sets the task's other fields.
+pub(crate) const SCRIPT_FRONT_MATTER_PREFIX: &str = "# up:";
+
+/// Whether the task file `contents` are an executable script (starting with a shebang line) rather
+/// than yaml.
+pub(crate) fn is_script(contents: &str) -> bool {
+    contents.starts_with("#!")
+}
+
+/// File in the task tempdir that task command stdout and stderr are written to.
+pub(crate) const TASK_OUTPUT_FILE: &str = "task_stdout_stderr.txt";
+
+/// What matches of a task's `redact` patterns are replaced with in its output.
+const REDACTED: &[u8] = b"[REDACTED]";
+
+/// File in the task tempdir that the task's [`TaskRunRecord`] is written to.
+pub(crate) const TASK_STATUS_FILE: &str = "task_status.json";
+
+/// How long a task (or git repo update) can take before we warn that it was slow, unless
+/// `slow_warn_after` is set.
+pub(crate) const DEFAULT_SLOW_WARN_AFTER: Duration = Duration::from_mins(1);
+
+/// How a task run finished, saved in the task tempdir so `up explain` can show the last run.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TaskRunRecord {
+    /// Final task status (`passed`, `skipped`, `failed`, `warned`, `offline`, `not run`, or
+    /// `incomplete`).
+    pub(crate) status: String,
+    /// Error message if the task failed (or warned).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    /// How long the task took to run.
+    pub(crate) duration: Duration,
+}
+
+impl TaskRunRecord {
+    /// Record of a task that finished with `status` after `duration`.
+    pub(crate) fn new(status: &TaskStatus, duration: Duration) -> Self {
+        let (status, error) = match status {
+            TaskStatus::Incomplete => ("incomplete", None),
+            TaskStatus::Skipped => ("skipped", None),
+            TaskStatus::Passed(_) => ("passed", None),
+            TaskStatus::Failed(e) => ("failed", Some(format!("{e}"))),
+            TaskStatus::Warned(e) => ("warned", Some(format!("{e}"))),
+            TaskStatus::Offline => ("offline", None),
+            TaskStatus::NotRun => ("not run", None),
+        };
+        Self {
+            status: status.to_owned(),
+            error,
+            duration,
+        }
+    }
+
+    /// Save the record to the [`TASK_STATUS_FILE`] in `task_tempdir`.
+    pub(crate) fn write(&self, task_tempdir: &Utf8Path) -> Result<()> {
+        fs::write(
+            task_tempdir.join(TASK_STATUS_FILE),
+            serde_json::to_string(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Read the record from the [`TASK_STATUS_FILE`] in `task_tempdir`.
+    pub(crate) fn read(task_tempdir: &Utf8Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(
+            task_tempdir.join(TASK_STATUS_FILE),
+        )?)?)
+    }
+}
+
+/// Whether `path` is an executable file.
+fn is_executable(path: &Utf8Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/**
+Find `command` in the colon-separated `path`, returning the path to the executable.
+
+Commands containing a `/` are treated as paths rather than looked up.
+*/
+fn find_command(command: &str, path: &str) -> Option<Utf8PathBuf> {
+    if command.contains('/') {
+        let candidate = Utf8PathBuf::from(command);
+        return is_executable(&candidate).then_some(candidate);
+    }
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Utf8Path::new(dir).join(command))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Interpreter used for a `run_script` that doesn't have a shebang line.
+const DEFAULT_SHEBANG: &str = "#!/usr/bin/env bash";
+
 /// Used for serde defaults above.
 const fn default_false() -> bool {
     false
@@ -127,6 +552,8 @@ pub enum CommandType {
     RunIf,
     /// `run_cmd` field in the yaml.
     Run,
+    /// `run_script` field in the yaml.
+    RunScript,
 }
 
 impl Display for CommandType {
@@ -134,6 +561,7 @@ impl Display for CommandType {
         match self {
             Self::Run => write!(f, "run command"),
             Self::RunIf => write!(f, "run_if command"),
+            Self::RunScript => write!(f, "run script"),
         }
     }
 }
@@ -141,16 +569,71 @@ impl Display for CommandType {
 impl Task {
     /// Parse a Task from a path to a task config file.
     pub fn from(path: &Utf8Path) -> Result<Self> {
-        let start_time = Instant::now();
+        let contents = Self::read_file(path)?;
+        let config = Self::parse_config(path, &contents)?;
+        Self::from_config(path, config)
+    }
+
+    /// Read the contents of the task config file at `path`.
+    pub(crate) fn read_file(path: &Utf8Path) -> Result<String, E> {
         let s = fs::read_to_string(path).map_err(|e| E::ReadFile {
             path: path.to_owned(),
             source: e,
         })?;
         trace!("Task '{path}' contents: <<<{s}>>>");
-        let config = serde_yaml::from_str::<TaskConfig>(&s).map_err(|e| E::InvalidYaml {
+        Ok(s)
+    }
+
+    /// Parse the `contents` of the task config file (or executable script) at `path`.
+    pub(crate) fn parse_config(path: &Utf8Path, contents: &str) -> Result<TaskConfig, E> {
+        if is_script(contents) {
+            return Self::parse_script_config(path, contents);
+        }
+        yaml::from_str::<TaskConfig>(contents).map_err(|e| E::InvalidYaml {
             path: path.to_owned(),
             source: e,
-        })?;
+        })
+    }
+
+    /**
+    Config for a task that runs the executable script at `path`.
+
+    Other task fields can be set with a `# up:` front-matter comment just after the shebang line,
+    e.g. `# up: {description: Install rust, needs_sudo: true}`.
+    */
+    fn parse_script_config(path: &Utf8Path, contents: &str) -> Result<TaskConfig, E> {
+        if !is_executable(path) {
+            return Err(E::ScriptNotExecutable {
+                path: path.to_owned(),
+            });
+        }
+        let front_matter = contents
+            .lines()
+            .skip(1)
+            .take_while(|line| line.starts_with('#'))
+            .find_map(|line| line.strip_prefix(SCRIPT_FRONT_MATTER_PREFIX))
+            .filter(|front_matter| !front_matter.trim().is_empty());
+        let mut config = match front_matter {
+            Some(front_matter) => {
+                yaml::from_str::<TaskConfig>(front_matter).map_err(|e| E::InvalidFrontMatter {
+                    path: path.to_owned(),
+                    source: e,
+                })?
+            }
+            None => TaskConfig::default(),
+        };
+        if config.run_cmd.is_some() || config.run_script.is_some() || config.run_lib.is_some() {
+            return Err(E::ScriptRunField {
+                path: path.to_owned(),
+            });
+        }
+        config.run_cmd = Some(vec![path.to_string()]);
+        Ok(config)
+    }
+
+    /// Create a Task from the already parsed `config` of the task config file at `path`.
+    pub(crate) fn from_config(path: &Utf8Path, config: TaskConfig) -> Result<Self> {
+        let start_time = Instant::now();
         let name = match &config.name {
             Some(n) => n.clone(),
             None => path
@@ -164,24 +647,95 @@ impl Task {
             config,
             start_time,
             status: TaskStatus::Incomplete,
+            run_time: None,
+            kill_at: None,
         };
+        // Check the patterns now, so invalid ones are found before the run.
+        task.redactions()?;
         debug!("Task '{name}': {task:?}", name = &task.name);
         Ok(task)
     }
 
+    /// The task's compiled `redact` patterns.
+    pub(crate) fn redactions(&self) -> Result<Vec<Regex>, E> {
+        self.config
+            .redact
+            .iter()
+            .flatten()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| E::InvalidRedact {
+                    name: self.name.clone(),
+                    pattern: pattern.clone(),
+                    source: e,
+                })
+            })
+            .collect()
+    }
+
+    /// How long the task can take before we warn that it was slow.
+    pub(crate) fn slow_warn_after(&self) -> Duration {
+        self.config
+            .slow_warn_after
+            .as_ref()
+            .map_or(DEFAULT_SLOW_WARN_AFTER, HumanDuration::duration)
+    }
+
+    /// Whether the task needs the network, so isn't run with `--offline`: tasks using one of the
+    /// [`NETWORK_RUN_LIBS`], and tasks using the `network` resource.
+    pub(crate) fn needs_network(&self) -> bool {
+        self.config
+            .run_lib
+            .as_deref()
+            .is_some_and(|lib| NETWORK_RUN_LIBS.contains(&lib))
+            || self
+                .config
+                .resources
+                .iter()
+                .flatten()
+                .any(|resource| *resource == Resource::Network)
+    }
+
+    /// The `requires_commands` that can't be found in the `PATH` of `env`.
+    pub(crate) fn missing_commands(&self, env: &HashMap<String, String>) -> Vec<String> {
+        let path = env
+            .get("PATH")
+            .cloned()
+            .or_else(|| std::env::var("PATH").ok())
+            .unwrap_or_default();
+        self.config
+            .requires_commands
+            .iter()
+            .flatten()
+            .filter(|command| find_command(command, &path).is_none())
+            .cloned()
+            .collect()
+    }
+
     /// Run a task.
     pub fn run<F>(
         &mut self,
         env_fn: F,
         env: &HashMap<String, String>,
         task_tempdir: &Utf8Path,
+        backup_dir: &Utf8Path,
         console: bool,
+        plugins: &Plugins,
     ) where
         F: Fn(&str) -> Result<String, E>,
     {
-        match self.try_run(env_fn, env, task_tempdir, console) {
+        match self.try_run(env_fn, env, task_tempdir, backup_dir, console, plugins) {
             Ok(status) => self.status = status,
-            Err(e) => self.status = TaskStatus::Failed(e),
+            Err(e) => self.status = self.failed_status(e),
+        }
+    }
+
+    /// Status for the task failing with `e`, [`TaskStatus::Warned`] if it has `allow_failure` set.
+    pub(crate) fn failed_status(&self, e: E) -> TaskStatus {
+        if self.config.allow_failure {
+            warn!("Task failed, but has allow_failure set so the run continues: {e}");
+            TaskStatus::Warned(e)
+        } else {
+            TaskStatus::Failed(e)
         }
     }
 
@@ -191,7 +745,9 @@ impl Task {
         env_fn: F,
         env: &HashMap<String, String>,
         task_tempdir: &Utf8Path,
+        backup_dir: &Utf8Path,
         console: bool,
+        plugins: &Plugins,
     ) -> Result<TaskStatus, E>
     where
         F: Fn(&str) -> Result<String, E>,
@@ -199,6 +755,23 @@ impl Task {
         let name = &self.name;
         info!("Running");
 
+        let missing_commands = self.missing_commands(env);
+        if !missing_commands.is_empty() {
+            let commands = missing_commands.join(", ");
+            match self.config.on_missing_commands.unwrap_or_default() {
+                OnMissingCommands::Skip => {
+                    info!("Skipping task as required commands are missing: {commands}");
+                    return Ok(TaskStatus::Skipped);
+                }
+                OnMissingCommands::Fail => {
+                    return Err(E::MissingCommands {
+                        name: name.clone(),
+                        commands,
+                    });
+                }
+            }
+        }
+
         if let Some(mut cmd) = self.config.run_if_cmd.clone() {
             debug!("Running run_if command.");
             for s in &mut cmd {
@@ -218,10 +791,40 @@ impl Task {
             let maybe_data = self.config.data.clone();
 
             let status = match lib.as_str() {
+                "brew" => {
+                    let data: BrewConfig =
+                        parse_task_config(maybe_data, &self.name, false, env_fn)?;
+                    tasks::brew::run(&data)
+                }
+
+                "colima" => {
+                    let data: ColimaConfig =
+                        parse_task_config(maybe_data, &self.name, false, env_fn)?;
+                    tasks::colima::run(&data)
+                }
+
+                "default_apps" => {
+                    let data: DefaultAppsConfig =
+                        parse_task_config(maybe_data, &self.name, false, env_fn)?;
+                    tasks::default_apps::run(&data)
+                }
+
                 "defaults" => {
                     let data: DefaultsConfig =
                         parse_task_config(maybe_data, &self.name, false, env_fn)?;
-                    tasks::defaults::run(data, task_tempdir)
+                    tasks::defaults::run(data, backup_dir)
+                }
+
+                "developer_tools" => {
+                    let data: DeveloperToolsConfig =
+                        parse_task_config(maybe_data, &self.name, true, env_fn)?;
+                    tasks::developer_tools::run(&data, self.config.needs_sudo)
+                }
+
+                "finder" => {
+                    let data: FinderConfig =
+                        parse_task_config(maybe_data, &self.name, false, env_fn)?;
+                    tasks::finder::run(&data, backup_dir)
                 }
 
                 "generate_git" => {
@@ -233,13 +836,25 @@ impl Task {
                 "git" => {
                     let data: Vec<GitConfig> =
                         parse_task_config(maybe_data, &self.name, false, env_fn)?;
-                    tasks::git::run(&data)
+                    tasks::git::run(&data, self.slow_warn_after())
+                }
+
+                "json" => {
+                    let data: ConfigFileConfig =
+                        parse_task_config(maybe_data, &self.name, false, env_fn)?;
+                    tasks::config_file::run(ConfigFormat::Json, data, backup_dir)
+                }
+
+                "keygen" => {
+                    let data: KeygenConfig =
+                        parse_task_config(maybe_data, &self.name, true, env_fn)?;
+                    tasks::keygen::run(data)
                 }
 
                 "link" => {
                     let data: LinkOptions =
                         parse_task_config(maybe_data, &self.name, false, env_fn)?;
-                    tasks::link::run(data, task_tempdir)
+                    tasks::link::run(data, backup_dir)
                 }
 
                 "self" => {
@@ -248,6 +863,36 @@ impl Task {
                     tasks::update_self::run(&data)
                 }
 
+                "software_update" => {
+                    let data: SoftwareUpdateConfig =
+                        parse_task_config(maybe_data, &self.name, true, env_fn)?;
+                    tasks::software_update::run(&data, self.config.needs_sudo)
+                }
+
+                "toml" => {
+                    let data: ConfigFileConfig =
+                        parse_task_config(maybe_data, &self.name, false, env_fn)?;
+                    tasks::config_file::run(ConfigFormat::Toml, data, backup_dir)
+                }
+
+                "vscode" => {
+                    let data: VsCodeConfig =
+                        parse_task_config(maybe_data, &self.name, false, env_fn)?;
+                    tasks::vscode::run(data, backup_dir)
+                }
+
+                lib if lib.starts_with(PLUGIN_PREFIX) => tasks::plugin::run(
+                    plugins,
+                    lib.trim_start_matches(PLUGIN_PREFIX),
+                    maybe_data,
+                    env_fn,
+                    env,
+                    &self.name,
+                    task_tempdir,
+                    backup_dir,
+                    console,
+                ),
+
                 _ => Err(eyre!("This run_lib is invalid or not yet implemented.")),
             }
             .map_err(|e| E::TaskError {
@@ -258,13 +903,28 @@ impl Task {
             return Ok(status);
         }
 
+        if let Some(script) = &self.config.run_script {
+            debug!("Running '{name}' run script.");
+            let script_path = write_run_script(script, task_tempdir)?;
+            if self.run_command(
+                CommandType::RunScript,
+                &[script_path.into_string()],
+                env,
+                task_tempdir,
+                console,
+            )? {
+                return Ok(TaskStatus::Passed(TaskChanges::default()));
+            }
+            return Ok(TaskStatus::Skipped);
+        }
+
         if let Some(mut cmd) = self.config.run_cmd.clone() {
             debug!("Running '{name}' run command.");
             for s in &mut cmd {
                 *s = env_fn(s)?;
             }
             if self.run_command(CommandType::Run, &cmd, env, task_tempdir, console)? {
-                return Ok(TaskStatus::Passed);
+                return Ok(TaskStatus::Passed(TaskChanges::default()));
             }
             return Ok(TaskStatus::Skipped);
         }
@@ -287,7 +947,14 @@ impl Task {
         console: bool,
     ) -> Result<bool, E> {
         let now = Instant::now();
-        let task_output_file = task_tempdir.join("task_stdout_stderr.txt");
+        let task_output_file = task_tempdir.join(TASK_OUTPUT_FILE);
+        // Only used for `--trace-file`, so hidden from the progress bars.
+        let _span = tracing::debug_span!(
+            "command",
+            command_type = %command_type,
+            indicatif.pb_hide = true
+        )
+        .entered();
 
         let command = cmd_log(
             Level::DEBUG,
@@ -298,7 +965,26 @@ impl Task {
         .full_env(env)
         .unchecked();
 
-        let output = if console {
+        let redactions = self.redactions()?;
+        let output = if !redactions.is_empty() {
+            run_redacted(
+                &command,
+                &redactions,
+                (!console).then_some(task_output_file.as_path()),
+                self.kill_at,
+            )
+        } else if let Some(kill_at) = self.kill_at {
+            let command = if console {
+                command
+            } else {
+                command
+                    .stderr_path(&task_output_file)
+                    .stdout_path(&task_output_file)
+            };
+            command.start().and_then(|handle| {
+                kill_at_deadline(kill_at, || handle.kill(), || handle.wait().cloned())
+            })
+        } else if console {
             command.run_with_inherit()
         } else {
             command
@@ -383,6 +1069,93 @@ impl Task {
     }
 }
 
+/**
+Run `command`, writing its stdout and stderr (combined) to `output_file` (or to stderr if `None`)
+a line at a time, with any matches of the `redactions` replaced by [`REDACTED`]. The command is
+killed if it's still running at `kill_at`.
+*/
+fn run_redacted(
+    command: &Expression,
+    redactions: &[Regex],
+    output_file: Option<&Utf8Path>,
+    kill_at: Option<Instant>,
+) -> io::Result<Output> {
+    let mut writer: Box<dyn Write> = match output_file {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stderr()),
+    };
+    let reader = command.stderr_to_stdout().reader()?;
+    let mut copy_output = || {
+        for line in BufReader::new(&reader).split(b'\n') {
+            let mut line = line?;
+            for redaction in redactions {
+                line = redaction.replace_all(&line, REDACTED).into_owned();
+            }
+            line.push(b'\n');
+            writer.write_all(&line)?;
+        }
+        writer.flush()?;
+        // The command has exited once all its output has been read.
+        reader
+            .try_wait()?
+            .cloned()
+            .ok_or_else(|| io::Error::other("command still running after its output was closed"))
+    };
+    match kill_at {
+        Some(kill_at) => kill_at_deadline(kill_at, || reader.kill(), copy_output),
+        None => copy_output(),
+    }
+}
+
+/**
+Wait for a task command to finish with `wait`, calling `kill` if it's still running at `kill_at`
+(the run's `--max-duration` with `--hard`).
+*/
+fn kill_at_deadline(
+    kill_at: Instant,
+    kill: impl FnOnce() -> io::Result<()> + Send,
+    wait: impl FnOnce() -> io::Result<Output>,
+) -> io::Result<Output> {
+    let span = tracing::Span::current();
+    let (finished, finished_rx) = mpsc::channel::<()>();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            let _span = span.enter();
+            // Returns on a message or disconnect, i.e. when the command finishes.
+            let timeout = kill_at.saturating_duration_since(Instant::now());
+            if let Err(RecvTimeoutError::Timeout) = finished_rx.recv_timeout(timeout) {
+                warn!("Killing task command as the --max-duration was exceeded.");
+                if let Err(e) = kill() {
+                    warn!("Failed to kill task command: {e}");
+                }
+            }
+        });
+        let output = wait();
+        drop(finished);
+        output
+    })
+}
+
+/**
+Write a task's `run_script` to an executable file in the task tempdir, adding a bash shebang if
+the script doesn't have one.
+*/
+fn write_run_script(script: &str, task_tempdir: &Utf8Path) -> Result<Utf8PathBuf, E> {
+    let script_path = task_tempdir.join("run_script");
+    let contents = if script.starts_with("#!") {
+        script.to_owned()
+    } else {
+        format!("{DEFAULT_SHEBANG}\n{script}")
+    };
+    fs::write(&script_path, contents)
+        .and_then(|()| fs::set_permissions(&script_path, Permissions::from_mode(0o755)))
+        .map_err(|e| E::WriteScript {
+            path: script_path.clone(),
+            source: e,
+        })?;
+    Ok(script_path)
+}
+
 /// Convert a task's `data:` block into a task config.
 /// Set `has_default` to `true` if the task should fall back to `Default::default()`, or `false` if
 /// it should error when no value was passed.
@@ -0,0 +1,487 @@
+//! Full-screen dashboard showing the progress of the tasks being run (`up run --tui`).
+use crate::tasks::task::Task;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::task::TASK_OUTPUT_FILE;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::Event;
+use ratatui::crossterm::event::KeyCode;
+use ratatui::crossterm::event::KeyEventKind;
+use ratatui::crossterm::event::KeyModifiers;
+use ratatui::layout::Constraint;
+use ratatui::layout::Layout;
+use ratatui::style::Color;
+use ratatui::style::Style;
+use ratatui::style::Stylize;
+use ratatui::widgets::Block;
+use ratatui::widgets::Cell;
+use ratatui::widgets::Paragraph;
+use ratatui::widgets::Row;
+use ratatui::widgets::Table;
+use ratatui::widgets::TableState;
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// How often to redraw the dashboard.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+/// How many bytes from the end of each task's output file to read.
+const OUTPUT_TAIL_BYTES: u64 = 16 * 1024;
+
+/// Whether the dashboard is currently drawn on the terminal.
+static DASHBOARD_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/**
+Writer for stderr logs that discards them while the dashboard is drawn, as they would corrupt it.
+
+Logs are still written to the log file.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct StderrWriter;
+
+impl MakeWriter<'_> for StderrWriter {
+    type Writer = Box<dyn io::Write>;
+
+    fn make_writer(&self) -> Self::Writer {
+        if DASHBOARD_ACTIVE.load(Ordering::Relaxed) {
+            Box::new(io::sink())
+        } else {
+            Box::new(io::stderr())
+        }
+    }
+}
+
+/// The state of a task shown in the dashboard.
+#[derive(Debug)]
+enum RowStatus {
+    /// Not yet started.
+    Pending,
+    /// Started at this time.
+    Running(Instant),
+    /// Completed.
+    Finished {
+        /// How long the task took.
+        elapsed: Duration,
+        /// Final task status to show.
+        label: &'static str,
+        /// Colour to show the status in.
+        color: Color,
+    },
+}
+
+/// A task shown in the dashboard.
+#[derive(Debug)]
+struct TaskRow {
+    /// Task name.
+    name: String,
+    /// File the task's commands write their output to, once it has started.
+    output_file: Option<Utf8PathBuf>,
+    /// Current task state.
+    status: RowStatus,
+}
+
+/// Everything the dashboard shows.
+#[derive(Debug, Default)]
+struct DashboardState {
+    /// One row per task, in the order they'll be run.
+    rows: Vec<TaskRow>,
+    /// Index of the selected row.
+    selected: usize,
+    /// Whether to show the output of the selected task.
+    expanded: bool,
+}
+
+/// Handle to the dashboard, which is drawn on a separate thread until this is dropped.
+#[derive(Debug)]
+pub(super) struct Dashboard {
+    /// State shared with the drawing thread.
+    state: Arc<Mutex<DashboardState>>,
+    /// Tells the drawing thread to stop.
+    stop: Arc<AtomicBool>,
+    /// The drawing thread.
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Dashboard {
+    /// Take over the terminal and start drawing the dashboard for the tasks named.
+    pub(super) fn start(task_names: impl IntoIterator<Item = String>) -> Result<Self> {
+        let state = Arc::new(Mutex::new(DashboardState {
+            rows: task_names
+                .into_iter()
+                .map(|name| TaskRow {
+                    name,
+                    output_file: None,
+                    status: RowStatus::Pending,
+                })
+                .collect(),
+            ..DashboardState::default()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut terminal = ratatui::try_init()?;
+        DASHBOARD_ACTIVE.store(true, Ordering::Relaxed);
+        let thread = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let result = draw_until_stopped(&mut terminal, &state, &stop);
+                DASHBOARD_ACTIVE.store(false, Ordering::Relaxed);
+                ratatui::try_restore()?;
+                result
+            })
+        };
+
+        Ok(Self {
+            state,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Mark a task as started, with its output going to files in `task_tempdir`.
+    pub(super) fn task_started(&self, name: &str, task_tempdir: &Utf8Path) {
+        let mut state = lock(&self.state);
+        if let Some(row) = state.rows.iter_mut().find(|row| row.name == name) {
+            row.output_file = Some(task_tempdir.join(TASK_OUTPUT_FILE));
+            row.status = RowStatus::Running(Instant::now());
+        }
+    }
+
+    /// Mark a task as finished.
+    pub(super) fn task_finished(&self, task: &Task) {
+        let (label, color) = match task.status {
+            TaskStatus::Passed(_) => ("passed", Color::Green),
+            TaskStatus::Skipped => ("skipped", Color::Blue),
+            TaskStatus::Failed(_) => ("failed", Color::Red),
+            TaskStatus::Warned(_) => ("warned", Color::Yellow),
+            TaskStatus::Offline => ("offline", Color::DarkGray),
+            TaskStatus::NotRun => ("not run", Color::DarkGray),
+            TaskStatus::Incomplete => ("incomplete", Color::Magenta),
+        };
+        let mut state = lock(&self.state);
+        if let Some(row) = state.rows.iter_mut().find(|row| row.name == task.name) {
+            let elapsed = match row.status {
+                RowStatus::Running(started) => started.elapsed(),
+                _ => Duration::ZERO,
+            };
+            row.status = RowStatus::Finished {
+                elapsed,
+                label,
+                color,
+            };
+        }
+    }
+
+    /// Stop drawing the dashboard and give the terminal back.
+    pub(super) fn finish(mut self) -> Result<()> {
+        self.stop_thread()
+    }
+
+    /// Stop the drawing thread (if it's still running) and wait for it to restore the terminal.
+    fn stop_thread(&mut self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take().map(JoinHandle::join) {
+            None => Ok(()),
+            Some(Ok(result)) => Ok(result?),
+            Some(Err(_)) => Err(eyre!("Task dashboard thread panicked.")),
+        }
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        // Errors are ignored, as we're probably already returning an earlier error.
+        _ = self.stop_thread();
+    }
+}
+
+/// Lock the shared state, ignoring poisoning as the state is always left consistent.
+fn lock(state: &Mutex<DashboardState>) -> MutexGuard<'_, DashboardState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Draw the dashboard and handle key presses until `stop` is set or the user hides it.
+fn draw_until_stopped(
+    terminal: &mut DefaultTerminal,
+    state: &Mutex<DashboardState>,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    while !stop.load(Ordering::Relaxed) {
+        terminal.draw(|frame| render(frame, &lock(state)))?;
+        if !event::poll(REFRESH_INTERVAL)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let mut state = lock(state);
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => state.selected = state.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                state.selected = (state.selected + 1).min(state.rows.len().saturating_sub(1));
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => state.expanded = !state.expanded,
+            // The terminal is in raw mode, so Ctrl-C doesn't send an interrupt. Hide the
+            // dashboard so the next one does.
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Draw a single frame of the dashboard.
+fn render(frame: &mut Frame, state: &DashboardState) {
+    let [table_area, output_area, help_area] = if state.expanded {
+        Layout::vertical([
+            Constraint::Percentage(50),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+    } else {
+        Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(0),
+            Constraint::Length(1),
+        ])
+    }
+    .areas(frame.area());
+
+    let finished_count = state
+        .rows
+        .iter()
+        .filter(|row| matches!(row.status, RowStatus::Finished { .. }))
+        .count();
+    let name_width = state
+        .rows
+        .iter()
+        .map(|row| row.name.len())
+        .max()
+        .unwrap_or_default();
+
+    let rows = state.rows.iter().map(|row| {
+        let (label, color, elapsed) = match row.status {
+            RowStatus::Pending => ("pending", Color::DarkGray, None),
+            RowStatus::Running(started) => ("running", Color::Yellow, Some(started.elapsed())),
+            RowStatus::Finished {
+                elapsed,
+                label,
+                color,
+            } => (label, color, Some(elapsed)),
+        };
+        let last_line = row
+            .output_file
+            .as_deref()
+            .and_then(last_output_line)
+            .unwrap_or_default();
+        Row::new([
+            Cell::from(label).style(Style::new().fg(color)),
+            Cell::from(row.name.as_str()),
+            Cell::from(elapsed.map_or_else(String::new, |e| format!("{}s", e.as_secs()))),
+            Cell::from(last_line),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(10),
+            Constraint::Length(u16::try_from(name_width).unwrap_or(u16::MAX)),
+            Constraint::Length(6),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(Row::new(["Status", "Task", "Time", "Last output"]).bold())
+    .block(Block::bordered().title(format!(
+        " up run: {finished_count}/{total} tasks finished ",
+        total = state.rows.len()
+    )))
+    .row_highlight_style(Style::new().reversed());
+    let mut table_state = TableState::default().with_selected(Some(state.selected));
+    frame.render_stateful_widget(table, table_area, &mut table_state);
+
+    if state.expanded {
+        if let Some(row) = state.rows.get(state.selected) {
+            let output = row
+                .output_file
+                .as_deref()
+                .map(read_output_tail)
+                .unwrap_or_default();
+            // Leave room for the borders.
+            let visible_lines = usize::from(output_area.height.saturating_sub(2));
+            let lines: Vec<&str> = output.lines().collect();
+            let text = lines
+                .get(lines.len().saturating_sub(visible_lines)..)
+                .unwrap_or_default()
+                .iter()
+                .map(|line| strip_control_chars(line))
+                .collect::<Vec<_>>()
+                .join("\n");
+            frame.render_widget(
+                Paragraph::new(text)
+                    .block(Block::bordered().title(format!(" {name} output ", name = row.name))),
+                output_area,
+            );
+        }
+    }
+
+    frame.render_widget(
+        Paragraph::new("↑/↓: select task   enter: show/hide output   q: hide dashboard").dim(),
+        help_area,
+    );
+}
+
+/// The last non-empty line of a task's output file, if it has written any output.
+pub(super) fn last_output_line(path: &Utf8Path) -> Option<String> {
+    read_output_tail(path)
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .map(strip_control_chars)
+}
+
+/// Read the end of a task's output file, returning an empty string if it can't be read (e.g.
+/// because the task hasn't written any output yet).
+fn read_output_tail(path: &Utf8Path) -> String {
+    let mut bytes = Vec::new();
+    let read_result = File::open(path).and_then(|mut file| {
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(len.saturating_sub(OUTPUT_TAIL_BYTES)))?;
+        file.read_to_end(&mut bytes)
+    });
+    match read_result {
+        Ok(_) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Remove control characters (e.g. colour escape codes) that would mess up the dashboard.
+fn strip_control_chars(line: &str) -> String {
+    line.chars().filter(|c| !c.is_control()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::last_output_line;
+    use super::render;
+    use super::DashboardState;
+    use super::RowStatus;
+    use super::TaskRow;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use ratatui::backend::TestBackend;
+    use ratatui::style::Color;
+    use ratatui::Terminal;
+    use std::fs;
+    use std::time::Duration;
+    use std::time::Instant;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_last_output_line() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let output_file = temp_dir.join("output.txt");
+        ensure_eq!(None, last_output_line(&output_file));
+
+        fs::write(&output_file, "first\n\tsecond\r\n  \n\n")?;
+        ensure_eq!(Some("second".to_owned()), last_output_line(&output_file));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let output_file = temp_dir.join("output.txt");
+        fs::write(&output_file, "compiling\nlinking\n")?;
+
+        let mut state = DashboardState {
+            rows: vec![
+                TaskRow {
+                    name: "done".to_owned(),
+                    output_file: None,
+                    status: RowStatus::Finished {
+                        elapsed: Duration::from_secs(3),
+                        label: "passed",
+                        color: Color::Green,
+                    },
+                },
+                TaskRow {
+                    name: "building".to_owned(),
+                    output_file: Some(output_file),
+                    status: RowStatus::Running(Instant::now()),
+                },
+                TaskRow {
+                    name: "waiting".to_owned(),
+                    output_file: None,
+                    status: RowStatus::Pending,
+                },
+            ],
+            selected: 1,
+            expanded: false,
+        };
+        let mut terminal = Terminal::new(TestBackend::new(80, 12))?;
+
+        terminal.draw(|frame| render(frame, &state))?;
+        let screen = screen_text(&terminal);
+        ensure!(
+            screen.contains("up run: 1/3 tasks finished"),
+            "Title missing from:\n{screen}"
+        );
+        for row in [
+            "passed     done     3s",
+            "running    building 0s     linking",
+            "pending    waiting",
+        ] {
+            ensure!(screen.contains(row), "Row '{row}' missing from:\n{screen}");
+        }
+        ensure!(
+            !screen.contains("building output"),
+            "Output shown in:\n{screen}"
+        );
+
+        // Expanding the selected task shows its output.
+        state.expanded = true;
+        terminal.draw(|frame| render(frame, &state))?;
+        let screen = screen_text(&terminal);
+        for text in ["building output", "compiling"] {
+            ensure!(screen.contains(text), "'{text}' missing from:\n{screen}");
+        }
+        Ok(())
+    }
+
+    /// The text drawn on the test terminal, one line per row.
+    fn screen_text(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(usize::from(buffer.area.width))
+            .map(|line| {
+                line.iter()
+                    .map(ratatui::buffer::Cell::symbol)
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -1,25 +1,37 @@
 //! The `up self` library, for updating the CLI itself.
 use self::UpdateSelfError as E;
 use crate::cmd;
+use crate::config::UpConfig;
+use crate::exec::UpDuct;
 use crate::opts::UpdateSelfOptions;
+use crate::tasks::task::TaskChanges;
 use crate::tasks::task::TaskStatus;
 use crate::tasks::ResolveEnv;
+use crate::utils::files;
+use crate::utils::files::AtomicWriteOptions;
+use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use chrono::Utc;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use displaydoc::Display;
+use duct::Expression;
 use serde_derive::Deserialize;
 use std::env;
 use std::fs;
 use std::fs::File;
+use std::fs::Metadata;
 use std::fs::Permissions;
 use std::io;
+use std::os::unix;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
 use thiserror::Error;
 use tracing::debug;
 use tracing::info;
 use tracing::trace;
+use tracing::warn;
 
 /// GitHub latest release API endpoint JSON response.
 /// <https://docs.github.com/en/rest/releases/releases?apiVersion=2022-11-28#get-the-latest-release>
@@ -36,6 +48,14 @@ const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 impl ResolveEnv for UpdateSelfOptions {}
 
+/// Extended attribute macOS sets on downloaded files, which stops them running until approved.
+const QUARANTINE_XATTR: &str = "com.apple.quarantine";
+
+/// File in the up cache dir that caches the latest version found by [`passive_check`].
+const UPDATE_CHECK_CACHE_FILE: &str = "latest_version_check";
+/// How long to use the cached latest version for before checking again.
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_hours(24);
+
 /// Downloads the latest version of the binary from the specified URL and
 /// replaces the current executable path with it.
 pub(crate) fn run(opts: &UpdateSelfOptions) -> Result<TaskStatus> {
@@ -48,19 +68,11 @@ pub(crate) fn run(opts: &UpdateSelfOptions) -> Result<TaskStatus> {
         return Ok(TaskStatus::Skipped);
     }
 
-    let client = reqwest::blocking::Client::builder()
-        .user_agent(APP_USER_AGENT)
-        .build()?;
-
     trace!("Self update opts: {opts:?}");
-    if opts.url == crate::opts::SELF_UPDATE_URL {
-        let latest_github_release = client
-            .get(crate::opts::LATEST_RELEASE_URL)
-            .send()?
-            .error_for_status()?
-            .json::<GitHubReleaseJsonResponse>()?;
-        trace!("latest_github_release: {latest_github_release:?}");
-        let latest_github_release = latest_github_release.tag_name;
+    // Only the default GitHub URL has a releases API to check, for files and other mirrors we
+    // download the binary and compare its `--version` output below.
+    if opts.from_file.is_none() && opts.url == crate::opts::SELF_UPDATE_URL {
+        let latest_github_release = latest_github_release()?;
         if semver::Version::parse(&latest_github_release)?
             <= semver::Version::parse(CURRENT_VERSION)?
         {
@@ -75,32 +87,47 @@ pub(crate) fn run(opts: &UpdateSelfOptions) -> Result<TaskStatus> {
 
     let temp_dir = Utf8PathBuf::try_from(env::temp_dir())?;
     let temp_path = &temp_dir.join(format!("up_rs-{}", Utc::now().to_rfc3339()));
-
-    trace!("Downloading url {url} to path {up_path}", url = &opts.url,);
-
     trace!("Using temporary path: {temp_path}");
-    let mut response = reqwest::blocking::get(&opts.url)?.error_for_status()?;
-
     fs::create_dir_all(&temp_dir).wrap_err_with(|| E::CreateDir { path: temp_dir })?;
-    let mut dest = File::create(temp_path).wrap_err_with(|| E::CreateFile {
-        path: temp_path.clone(),
-    })?;
-    io::copy(&mut response, &mut dest).wrap_err(E::Copy {})?;
 
-    let permissions = Permissions::from_mode(0o755);
-    fs::set_permissions(temp_path, permissions).wrap_err_with(|| E::SetPermissions {
-        path: temp_path.clone(),
+    if let Some(from_file) = &opts.from_file {
+        trace!("Copying file {from_file} to path {up_path}");
+        fs::copy(from_file, temp_path).wrap_err_with(|| E::CopyFile {
+            from: from_file.clone(),
+            to: temp_path.clone(),
+        })?;
+    } else {
+        let mut response = download(&opts.url)?;
+        let mut dest = File::create(temp_path).wrap_err_with(|| E::CreateFile {
+            path: temp_path.clone(),
+        })?;
+        io::copy(&mut response, &mut dest).wrap_err(E::Copy {})?;
+    }
+
+    let up_metadata = fs::metadata(&up_path).wrap_err_with(|| E::ReadMetadata {
+        path: up_path.clone(),
+    })?;
+    // Keep the current binary's permissions, but make sure its owner can still run it.
+    let mode = up_metadata.permissions().mode() | 0o100;
+    fs::set_permissions(temp_path, Permissions::from_mode(mode)).wrap_err_with(|| {
+        E::SetPermissions {
+            path: temp_path.clone(),
+        }
     })?;
+    remove_quarantine(temp_path);
 
-    let new_version = cmd!(temp_path.as_str(), "--version").read()?;
+    let new_version = cmd!(temp_path.as_str(), "--version")
+        .read()
+        .wrap_err_with(|| E::VerifyBinary {
+            path: temp_path.clone(),
+        })?;
     let new_version = new_version.trim_start_matches(concat!(env!("CARGO_PKG_NAME"), " "));
     if semver::Version::parse(new_version)? > semver::Version::parse(CURRENT_VERSION)? {
         info!("Updating up-rs from '{CURRENT_VERSION}' to '{new_version}'",);
-        fs::rename(temp_path, &up_path).wrap_err_with(|| E::Rename {
-            from: temp_path.clone(),
-            to: up_path.clone(),
-        })?;
-        Ok(TaskStatus::Passed)
+        replace_binary(&up_path, temp_path, &up_metadata)?;
+        // Nothing else uses the download, and the temp dir is cleaned up eventually anyway.
+        _ = fs::remove_file(temp_path);
+        Ok(TaskStatus::Passed(TaskChanges::default()))
     } else {
         debug!(
             "Skipping up-rs update, current version '{CURRENT_VERSION}' and new version \
@@ -110,6 +137,211 @@ pub(crate) fn run(opts: &UpdateSelfOptions) -> Result<TaskStatus> {
     }
 }
 
+/**
+Replace the binary at `up_path` with the one at `new_path`, keeping the mode and owner of the old
+binary (described by `up_metadata`).
+
+The old binary is backed up next to `new_path`, and restored if the new binary doesn't run
+`--version` successfully once it's in place.
+*/
+fn replace_binary(up_path: &Utf8Path, new_path: &Utf8Path, up_metadata: &Metadata) -> Result<()> {
+    // Written via a temporary file next to the binary (rather than renaming the download, which
+    // may be on another filesystem), so a crash can't leave a truncated binary.
+    let new_binary = fs::read(new_path).wrap_err_with(|| E::ReadFile {
+        path: new_path.to_owned(),
+    })?;
+    let backup_dir = Utf8PathBuf::from(format!("{new_path}-backup"));
+    let opts = AtomicWriteOptions {
+        fsync: true,
+        backup_dir: Some(&backup_dir),
+        mode: Some(up_metadata.permissions().mode() | 0o100),
+    };
+    let backup_path = files::atomic_write(up_path, new_binary, opts)
+        .wrap_err_with(|| E::Replace {
+            from: new_path.to_owned(),
+            to: up_path.to_owned(),
+        })?
+        .ok_or_else(|| E::ReadFile {
+            path: up_path.to_owned(),
+        })?;
+    preserve_owner(up_path, up_metadata);
+
+    let Err(verify_error) = cmd!(up_path.as_str(), "--version").read() else {
+        trace!("Old binary backed up to {backup_path}");
+        return Ok(());
+    };
+    warn!("Updated binary failed to run, restoring the old binary from {backup_path}");
+    let old_binary = fs::read(&backup_path).wrap_err_with(|| E::ReadFile {
+        path: backup_path.clone(),
+    })?;
+    files::atomic_write(
+        up_path,
+        old_binary,
+        AtomicWriteOptions {
+            backup_dir: None,
+            ..opts
+        },
+    )
+    .wrap_err_with(|| E::Replace {
+        from: backup_path.clone(),
+        to: up_path.to_owned(),
+    })?;
+    preserve_owner(up_path, up_metadata);
+    Err(verify_error).wrap_err_with(|| E::RolledBack {
+        path: up_path.to_owned(),
+    })
+}
+
+/// Give `path` the owner and group in `metadata` if it doesn't already have them (e.g. if up was
+/// run with sudo).
+fn preserve_owner(path: &Utf8Path, metadata: &Metadata) {
+    let owner = (metadata.uid(), metadata.gid());
+    match fs::metadata(path) {
+        Ok(new_metadata) if (new_metadata.uid(), new_metadata.gid()) == owner => {}
+        _ => {
+            if let Err(e) = unix::fs::chown(path, Some(owner.0), Some(owner.1)) {
+                warn!("Failed to set the owner of {path} to {owner:?}: {e}");
+            }
+        }
+    }
+}
+
+/// Remove the [`QUARANTINE_XATTR`] from `path` on macOS, so the downloaded binary can run without
+/// the user approving it.
+fn remove_quarantine(path: &Utf8Path) {
+    if !cfg!(target_os = "macos") {
+        return;
+    }
+    // Fails if the attribute isn't set (e.g. for `--from-file`), which is fine.
+    if let Err(e) = cmd!("xattr", "-d", QUARANTINE_XATTR, path.as_str())
+        .stderr_null()
+        .run_with(Expression::stdout_null)
+    {
+        trace!("Didn't remove {QUARANTINE_XATTR} from {path}: {e}");
+    }
+}
+
+/**
+Start downloading the up binary from `url`.
+
+For the default release URL, first try the asset for the current architecture (e.g.
+`up-darwin-arm64`), falling back to the universal binary if there isn't one.
+*/
+pub(crate) fn download(url: &str) -> Result<reqwest::blocking::Response> {
+    let urls = download_urls(url);
+    let (last_url, arch_urls) = urls.split_last().ok_or(E::NoDownloadUrl)?;
+    for url in arch_urls {
+        trace!("Downloading url {url}");
+        let response = reqwest::blocking::get(url)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            debug!("No release asset found at {url}, falling back to the next URL.");
+            continue;
+        }
+        return Ok(response.error_for_status()?);
+    }
+    trace!("Downloading url {last_url}");
+    Ok(reqwest::blocking::get(last_url)?.error_for_status()?)
+}
+
+/// URLs to try downloading the up binary from, most specific first.
+fn download_urls(url: &str) -> Vec<String> {
+    if url != crate::opts::SELF_UPDATE_URL {
+        return vec![url.to_owned()];
+    }
+    let arch = match env::consts::ARCH {
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    vec![format!("{url}-{arch}"), url.to_owned()]
+}
+
+/**
+Check whether a newer version of up has been released (`up self check`).
+
+Errors if an update is available, so the exit code can be used in scripts.
+*/
+pub(crate) fn check() -> Result<()> {
+    let latest_github_release = latest_github_release()?;
+    if semver::Version::parse(&latest_github_release)? > semver::Version::parse(CURRENT_VERSION)? {
+        println!(
+            "up-rs {latest_github_release} is available (current version is {CURRENT_VERSION}), \
+             run `up self` to update."
+        );
+        return Err(E::UpdateAvailable {
+            latest: latest_github_release,
+        }
+        .into());
+    }
+    println!("up-rs {CURRENT_VERSION} is up to date.");
+    Ok(())
+}
+
+/**
+Print a one-line hint if a newer version of up is available and the `update_check` config option
+is set (and up isn't `--offline`).
+
+The latest version is checked at most once a day, and cached in the up cache dir (`--cache-dir`,
+`$XDG_CACHE_HOME/up` by default). Failures are only logged, as this shouldn't fail the run.
+*/
+pub(crate) fn passive_check(config: &UpConfig) {
+    if !config.config_yaml.update_check.unwrap_or(false) {
+        return;
+    }
+    if config.offline {
+        debug!("Not checking for up-rs updates as running offline.");
+        return;
+    }
+    if let Err(e) = try_passive_check(&config.cache_dir) {
+        debug!("Failed to check for up-rs updates: {e:?}");
+    }
+}
+
+/// Implementation of [`passive_check`].
+fn try_passive_check(cache_dir: &Utf8Path) -> Result<()> {
+    let cache_path = cache_dir.join(UPDATE_CHECK_CACHE_FILE);
+    let cached_version = fs::metadata(&cache_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .filter(|modified| {
+            modified
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed < UPDATE_CHECK_INTERVAL)
+        })
+        .and_then(|_| fs::read_to_string(&cache_path).ok());
+
+    let latest_version = if let Some(cached_version) = cached_version {
+        trace!("Using cached latest version from {cache_path}: {cached_version}");
+        cached_version.trim().to_owned()
+    } else {
+        let latest_version = latest_github_release()?;
+        files::create_dir_all(cache_dir)?;
+        files::write(&cache_path, &latest_version)?;
+        latest_version
+    };
+
+    if semver::Version::parse(&latest_version)? > semver::Version::parse(CURRENT_VERSION)? {
+        info!(
+            "up-rs {latest_version} is available (current version is {CURRENT_VERSION}), run `up \
+             self` to update."
+        );
+    }
+    Ok(())
+}
+
+/// Query the GitHub releases API for the latest released version of up.
+fn latest_github_release() -> Result<String> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(APP_USER_AGENT)
+        .build()?;
+    let latest_github_release = client
+        .get(crate::opts::LATEST_RELEASE_URL)
+        .send()?
+        .error_for_status()?
+        .json::<GitHubReleaseJsonResponse>()?;
+    trace!("latest_github_release: {latest_github_release:?}");
+    Ok(latest_github_release.tag_name)
+}
+
 #[derive(Error, Debug, Display)]
 /// Errors thrown by this file.
 pub enum UpdateSelfError {
@@ -125,16 +357,103 @@ pub enum UpdateSelfError {
     },
     /// Failed to copy to destination file.
     Copy,
+    /// Failed to copy `{from}` to `{to}`.
+    CopyFile {
+        /// Path we were copying from.
+        from: Utf8PathBuf,
+        /// Path we were copying to.
+        to: Utf8PathBuf,
+    },
     /// Failed to set permissions for `{path}`.
     SetPermissions {
         /// Path we failed to set permissions for.
         path: Utf8PathBuf,
     },
-    /// Failed to rename `{from}` to `{to}`.
-    Rename {
-        /// Old name (path).
+    /// No URL to download up from.
+    NoDownloadUrl,
+    /// Update available: up-rs {latest} has been released.
+    UpdateAvailable {
+        /// Latest released version.
+        latest: String,
+    },
+    /// Failed to read `{path}`.
+    ReadFile {
+        /// File path we failed to read.
+        path: Utf8PathBuf,
+    },
+    /// Failed to read the metadata of `{path}`.
+    ReadMetadata {
+        /// File path we failed to read.
+        path: Utf8PathBuf,
+    },
+    /// Downloaded binary `{path}` failed to run `--version`.
+    VerifyBinary {
+        /// Path of the downloaded binary.
+        path: Utf8PathBuf,
+    },
+    /// Updated binary failed to run `--version`, so restored the previous version at `{path}`.
+    RolledBack {
+        /// Path of the binary.
+        path: Utf8PathBuf,
+    },
+    /// Failed to replace `{to}` with `{from}`.
+    Replace {
+        /// Path of the new binary.
         from: Utf8PathBuf,
-        /// Attempted new name (path).
+        /// Path of the binary being replaced.
         to: Utf8PathBuf,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::download_urls;
+    use super::replace_binary;
+    use crate::opts::SELF_UPDATE_URL;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::fs;
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_download_urls() -> Result<()> {
+        let urls = download_urls(SELF_UPDATE_URL);
+        ensure_eq!(2, urls.len());
+        ensure_eq!(Some(&SELF_UPDATE_URL.to_owned()), urls.last());
+        #[cfg(target_arch = "aarch64")]
+        ensure_eq!(Some(&format!("{SELF_UPDATE_URL}-arm64")), urls.first());
+        #[cfg(target_arch = "x86_64")]
+        ensure_eq!(Some(&format!("{SELF_UPDATE_URL}-x86_64")), urls.first());
+
+        let mirror_url = "https://example.com/up";
+        ensure_eq!(vec![mirror_url.to_owned()], download_urls(mirror_url));
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_binary() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let up_path = temp_dir.join("up");
+        let old_binary = "#!/bin/sh\necho up 1.0.0\n";
+        fs::write(&up_path, old_binary)?;
+        fs::set_permissions(&up_path, Permissions::from_mode(0o750))?;
+        let up_metadata = fs::metadata(&up_path)?;
+
+        let new_path = temp_dir.join("up-new");
+        let new_binary = "#!/bin/sh\necho up 2.0.0\n";
+        fs::write(&new_path, new_binary)?;
+        replace_binary(&up_path, &new_path, &up_metadata)?;
+        ensure_eq!(new_binary, fs::read_to_string(&up_path)?);
+        ensure_eq!(0o750, fs::metadata(&up_path)?.permissions().mode() & 0o777);
+
+        // A binary that doesn't run is rolled back.
+        let broken_binary = "#!/bin/sh\nexit 1\n";
+        fs::write(&new_path, broken_binary)?;
+        ensure!(replace_binary(&up_path, &new_path, &up_metadata).is_err());
+        ensure_eq!(new_binary, fs::read_to_string(&up_path)?);
+        ensure_eq!(0o750, fs::metadata(&up_path)?.permissions().mode() & 0o777);
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+/*!
+The `vscode` library task, for VS Code (or Cursor or `VSCodium`) extensions and settings.
+
+Installs any `extensions` that `<editor> --list-extensions` doesn't list, and merges `settings`
+into the editor's user `settings.json`. Settings are deep-merged as in the `json` library: objects
+are merged key by key, and arrays containing `...` have it replaced by the existing values:
+
+```yaml
+run_lib: vscode
+data:
+  editor: cursor
+  extensions:
+    - rust-lang.rust-analyzer
+    - vscodevim.vim
+  settings:
+    editor.formatOnSave: true
+    files.associations:
+      "*.rs.in": rust
+      "...": null
+```
+
+`settings.json` files with comments or trailing commas can't be updated, as they aren't valid JSON.
+*/
+use self::VsCodeError as E;
+use crate::cmd_debug;
+use crate::exec::cmd;
+use crate::exec::UpDuct;
+use crate::tasks::config_file;
+use crate::tasks::config_file::ConfigFormat;
+use crate::tasks::resolve_config_path;
+use crate::tasks::task::TaskChanges;
+use crate::tasks::task::TaskStatus;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TaskError;
+use crate::utils::files;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use duct::Expression;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashSet;
+use thiserror::Error;
+use tracing::debug;
+
+/// Editors sharing VS Code's CLI and settings format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Editor {
+    /// Visual Studio Code.
+    #[default]
+    Code,
+    /// Cursor.
+    Cursor,
+    /// `VSCodium`.
+    Codium,
+}
+
+impl Editor {
+    /// Name of the editor's CLI.
+    const fn cli(self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Cursor => "cursor",
+            Self::Codium => "codium",
+        }
+    }
+
+    /// Name of the editor's directory in the user config dir.
+    const fn config_dir_name(self) -> &'static str {
+        match self {
+            Self::Code => "Code",
+            Self::Cursor => "Cursor",
+            Self::Codium => "VSCodium",
+        }
+    }
+}
+
+/// Configuration for a `vscode` run library task.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsCodeConfig {
+    /// Editor to manage, defaults to `code`.
+    #[serde(default)]
+    pub editor: Editor,
+    /// Extension IDs (`<publisher>.<name>`) to install.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Settings to merge into the user `settings.json`.
+    pub settings: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Path to the user `settings.json`, defaults to the editor's default location.
+    pub settings_path: Option<String>,
+}
+
+impl ResolveEnv for VsCodeConfig {
+    fn resolve_env<F>(&mut self, env_fn: F) -> Result<(), TaskError>
+    where
+        F: Fn(&str) -> Result<String, TaskError>,
+    {
+        for extension in &mut self.extensions {
+            *extension = env_fn(extension)?;
+        }
+        if let Some(settings_path) = &self.settings_path {
+            self.settings_path = Some(resolve_config_path(settings_path, &env_fn)?.into_string());
+        }
+        for value in self.settings.iter_mut().flat_map(|s| s.values_mut()) {
+            resolve_json_strings(value, &env_fn)?;
+        }
+        Ok(())
+    }
+}
+
+/// Resolve env vars in all the strings in `value`.
+pub(super) fn resolve_json_strings<F>(
+    value: &mut serde_json::Value,
+    env_fn: &F,
+) -> Result<(), TaskError>
+where
+    F: Fn(&str) -> Result<String, TaskError>,
+{
+    match value {
+        serde_json::Value::String(s) => *s = env_fn(s)?,
+        serde_json::Value::Array(array) => {
+            for v in array {
+                resolve_json_strings(v, env_fn)?;
+            }
+        }
+        serde_json::Value::Object(object) => {
+            for v in object.values_mut() {
+                resolve_json_strings(v, env_fn)?;
+            }
+        }
+        serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+/// Run a `vscode` run library task.
+pub(crate) fn run(config: VsCodeConfig, backup_dir: &Utf8Path) -> Result<TaskStatus> {
+    let mut did_work = false;
+    if !config.extensions.is_empty() {
+        did_work |= install_extensions(config.editor, &config.extensions)?;
+    }
+    if let Some(settings) = config.settings {
+        let settings_path = match config.settings_path {
+            Some(path) => Utf8PathBuf::from(path),
+            None => default_settings_path(config.editor)?,
+        };
+        did_work |= merge_settings(&settings_path, settings, backup_dir)?;
+    }
+
+    if did_work {
+        Ok(TaskStatus::Passed(TaskChanges::default()))
+    } else {
+        Ok(TaskStatus::Skipped)
+    }
+}
+
+/// Install the `extensions` the `editor` doesn't already have, returning whether any were
+/// installed.
+fn install_extensions(editor: Editor, extensions: &[String]) -> Result<bool, E> {
+    let cli = editor.cli();
+    let installed = cmd_debug!(cli, "--list-extensions")
+        .read()
+        .map_err(|e| E::ListExtensions {
+            cli: cli.to_owned(),
+            source: e,
+        })?;
+    // Extension IDs are case-insensitive.
+    let installed: HashSet<String> = installed.lines().map(str::to_lowercase).collect();
+    let missing: Vec<&String> = extensions
+        .iter()
+        .filter(|extension| !installed.contains(&extension.to_lowercase()))
+        .collect();
+    if missing.is_empty() {
+        debug!(
+            "All {count} extensions already installed.",
+            count = extensions.len()
+        );
+        return Ok(false);
+    }
+
+    let mut args = Vec::new();
+    for extension in &missing {
+        args.extend(["--install-extension", extension.as_str()]);
+    }
+    cmd(cli, &args)
+        .run_with(Expression::stdout_to_stderr)
+        .map_err(|e| E::InstallExtensions {
+            extensions: missing
+                .iter()
+                .map(|e| e.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            source: e,
+        })?;
+    Ok(true)
+}
+
+/// Default path of the `editor`'s user `settings.json`.
+fn default_settings_path(editor: Editor) -> Result<Utf8PathBuf> {
+    let config_dir = if cfg!(target_os = "macos") {
+        files::home_dir()?.join("Library/Application Support")
+    } else {
+        files::home_dir()?.join(".config")
+    };
+    Ok(config_dir
+        .join(editor.config_dir_name())
+        .join("User/settings.json"))
+}
+
+/**
+Deep-merge `settings` into the settings file at `settings_path` (as the `json` library does),
+returning whether it changed.
+*/
+fn merge_settings(
+    settings_path: &Utf8Path,
+    settings: serde_json::Map<String, serde_json::Value>,
+    backup_dir: &Utf8Path,
+) -> Result<bool> {
+    Ok(config_file::merge_file(
+        ConfigFormat::Json,
+        settings_path,
+        settings,
+        &backup_dir.join("vscode"),
+    )?)
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum VsCodeError {
+    /// Failed to list extensions with `{cli} --list-extensions`, is `{cli}` in your PATH?
+    ListExtensions {
+        /// Editor CLI.
+        cli: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Failed to install extensions: {extensions}.
+    InstallExtensions {
+        /// Extensions that were being installed.
+        extensions: String,
+        /// Source error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::Result;
+    use std::fs;
+    use testutils::ensure_eq;
+
+    /// Settings are merged into the existing file, which is backed up.
+    #[test]
+    fn test_merge_settings() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let settings_path = temp_dir.join("User/settings.json");
+        let backup_dir = temp_dir.join("backup");
+
+        let settings = serde_yaml::from_str(
+            "{editor.fontSize: 14, files.exclude: {'**/target': true, '...': null}}",
+        )?;
+        ensure_eq!(
+            true,
+            super::merge_settings(&settings_path, settings, &backup_dir)?
+        );
+        ensure_eq!(false, backup_dir.exists());
+
+        fs::write(
+            &settings_path,
+            r#"{"workbench.colorTheme": "Solarized Light", "files.exclude": {"**/.git": true}}"#,
+        )?;
+        let settings = serde_yaml::from_str(
+            "{editor.fontSize: 14, files.exclude: {'**/target': true, '...': null}}",
+        )?;
+        ensure_eq!(
+            true,
+            super::merge_settings(&settings_path, settings, &backup_dir)?
+        );
+        ensure_eq!(
+            serde_json::json!({
+                "workbench.colorTheme": "Solarized Light",
+                "files.exclude": {"**/target": true, "**/.git": true},
+                "editor.fontSize": 14,
+            }),
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&settings_path)?)?
+        );
+        ensure_eq!(true, backup_dir.join("vscode/settings.json").exists());
+
+        // Merging the same settings again changes nothing.
+        let settings = serde_yaml::from_str(
+            "{editor.fontSize: 14, files.exclude: {'**/target': true, '...': null}}",
+        )?;
+        ensure_eq!(
+            false,
+            super::merge_settings(&settings_path, settings, &backup_dir)?
+        );
+
+        // Comments aren't valid JSON, so the file isn't touched.
+        fs::write(&settings_path, "{\n  // A comment\n}")?;
+        let settings = serde_yaml::from_str("{editor.fontSize: 14}")?;
+        ensure_eq!(
+            true,
+            super::merge_settings(&settings_path, settings, &backup_dir).is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,541 @@
+/*!
+Re-run tasks when their config changes (`up watch`).
+
+Changes are found with the platform's file system notifications (via the `notify` crate), so up
+uses no CPU while nothing changes, however many files are watched.
+Changes are collected until nothing has changed for the `--debounce` duration, so saving several
+files (or an editor writing a file in several steps) only re-runs the tasks once.
+
+- Changing `up.yaml` re-runs all the tasks.
+- Changing a task file re-runs that task.
+- With `--dotfiles`, changing a file in the `from_dir` of a `link` task re-runs that task.
+
+Only tasks that the config's tasks and tags filters select are re-run.
+*/
+use self::WatchError as E;
+use crate::config::UpConfig;
+use crate::opts;
+use crate::opts::LinkOptions;
+use crate::opts::WatchOptions;
+use crate::tasks;
+use crate::tasks::task::Task;
+use crate::tasks::ResolveEnv;
+use crate::tasks::TasksAction;
+use crate::tasks::TasksDir;
+use crate::utils::interpolate;
+use camino::Utf8PathBuf;
+use color_eyre::eyre::Context;
+use color_eyre::eyre::Result;
+use displaydoc::Display;
+use itertools::Itertools;
+use notify::Event;
+use notify::EventKind;
+use notify::RecursiveMode;
+use notify::Watcher;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use std::time::Instant;
+use thiserror::Error;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// Run the `up watch` command.
+pub(crate) fn run(cmd_opts: &WatchOptions, mut config: UpConfig) -> Result<()> {
+    let mut watched = Watched::new(&config, cmd_opts.dotfiles)?;
+    // Kept alive for the whole command, so changes made while tasks are running aren't missed.
+    let mut change_watcher = ChangeWatcher::new()?;
+    change_watcher.watch(&watched.roots)?;
+    loop {
+        info!(
+            "Watching {} for changes.",
+            watched
+                .roots
+                .iter()
+                .map(|root| format!("'{root}'"))
+                .join(", ")
+        );
+        let changed = change_watcher.wait_for_changes(cmd_opts.debounce.duration())?;
+        let changed_list = changed.iter().map(|path| format!("'{path}'")).join(", ");
+
+        // Re-run `$(cmd ...)` interpolations, as their output is only cached for a single run.
+        interpolate::clear_cache();
+        // Reload the config, so changes to up.yaml and new tasks are picked up, and so each run
+        // gets its own run directory.
+        let reloaded = UpConfig::from(opts::parse()).and_then(|new_config| {
+            let new_watched = Watched::new(&new_config, cmd_opts.dotfiles)?;
+            Ok((new_config, new_watched))
+        });
+        match reloaded {
+            Ok((new_config, new_watched)) => {
+                change_watcher.watch(&new_watched.roots)?;
+                (config, watched) = (new_config, new_watched);
+            }
+            Err(e) if cmd_opts.once => return Err(e),
+            Err(e) => {
+                warn!("Failed to reload the config, watching for more changes: {e}");
+                continue;
+            }
+        }
+        match watched.affected_tasks(&changed) {
+            None => info!("Re-running all tasks as {changed_list} changed."),
+            Some(names) if names.is_empty() => {
+                info!("No tasks use {changed_list}, so not re-running any.");
+                continue;
+            }
+            Some(names) => {
+                info!("Re-running {} as {changed_list} changed.", names.join(", "));
+                // The names already passed the tasks and tags filters, so they replace them.
+                config.tasks = Some(
+                    names
+                        .iter()
+                        .map(|name| glob::Pattern::escape(name))
+                        .collect(),
+                );
+                config.tags = None;
+            }
+        }
+
+        let result = tasks::run(&config, TasksDir::Tasks, TasksAction::Run);
+        if cmd_opts.once {
+            return result.map(|_summary| ());
+        }
+        if let Err(e) = result {
+            warn!("Re-running tasks failed, watching for more changes: {e}");
+        }
+    }
+}
+
+/// The files being watched, and the tasks that use them.
+#[derive(Debug, Default)]
+struct Watched {
+    /// Files and directories to watch.
+    roots: Vec<Utf8PathBuf>,
+    /// Path to the up config file.
+    up_yaml_path: Option<Utf8PathBuf>,
+    /// Task names keyed by the path of their task file.
+    task_paths: HashMap<Utf8PathBuf, String>,
+    /// The `from_dir` of each `link` task, keyed by task name (only with `--dotfiles`).
+    link_dirs: HashMap<String, Utf8PathBuf>,
+    /// The tasks selected by the config's tasks and tags filters, or `None` if there are none.
+    selected: Option<HashSet<String>>,
+}
+
+impl Watched {
+    /// Watch the up config and tasks in `config`, and the dotfiles of link tasks if `dotfiles`.
+    fn new(config: &UpConfig, dotfiles: bool) -> Result<Self> {
+        let tasks_dir = tasks::tasks_dir(config, TasksDir::Tasks)?;
+        let tasks = tasks::load_tasks(
+            &tasks_dir,
+            &config.cache_dir,
+            config.config_yaml.task_extensions.as_deref(),
+        )?;
+        let link_dirs = if dotfiles {
+            let env = tasks::config_env(config)?;
+            tasks
+                .values()
+                .filter_map(|task| Some((task.name.clone(), link_from_dir(task, &env)?)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let selected = selected_tasks(config, tasks.values())?;
+        let roots = config
+            .up_yaml_path
+            .iter()
+            .chain([&tasks_dir])
+            .chain(link_dirs.values().sorted().dedup())
+            .cloned()
+            .collect();
+        Ok(Self {
+            roots,
+            up_yaml_path: config.up_yaml_path.clone(),
+            task_paths: tasks
+                .into_values()
+                .map(|task| (task.path, task.name))
+                .collect(),
+            link_dirs,
+            selected,
+        })
+    }
+
+    /**
+    The names of the selected tasks to re-run as the `changed` files changed, sorted, or `None` if
+    all the tasks should be re-run.
+    */
+    fn affected_tasks(&self, changed: &[Utf8PathBuf]) -> Option<Vec<String>> {
+        if changed
+            .iter()
+            .any(|path| Some(path) == self.up_yaml_path.as_ref())
+        {
+            return None;
+        }
+        let mut names = BTreeSet::new();
+        for path in changed {
+            if let Some(name) = self.task_paths.get(path) {
+                names.insert(name.clone());
+            }
+            names.extend(
+                self.link_dirs
+                    .iter()
+                    .filter(|(_, from_dir)| path.starts_with(from_dir))
+                    .map(|(name, _)| name.clone()),
+            );
+        }
+        Some(
+            names
+                .into_iter()
+                .filter(|name| {
+                    self.selected
+                        .as_ref()
+                        .is_none_or(|selected| selected.contains(name))
+                })
+                .collect(),
+        )
+    }
+}
+
+/**
+The names of the `tasks` matching the `config`'s tasks patterns or tags, or `None` if there are no
+filters.
+*/
+fn selected_tasks<'a>(
+    config: &UpConfig,
+    tasks: impl Iterator<Item = &'a Task>,
+) -> Result<Option<HashSet<String>>> {
+    if config.tasks.is_none() && config.tags.is_none() {
+        return Ok(None);
+    }
+    let patterns = tasks::task_patterns(config.tasks.as_deref().unwrap_or_default())?;
+    let tags = config.tags.iter().flatten().collect::<HashSet<_>>();
+    Ok(Some(
+        tasks
+            .filter(|task| {
+                patterns.iter().any(|pattern| pattern.matches(&task.name))
+                    || task
+                        .config
+                        .tags
+                        .iter()
+                        .flatten()
+                        .any(|tag| tags.contains(tag))
+            })
+            .map(|task| task.name.clone())
+            .collect(),
+    ))
+}
+
+/// The `from_dir` of `task` if it's a `link` task.
+fn link_from_dir(task: &Task, env: &HashMap<String, String>) -> Option<Utf8PathBuf> {
+    if task.config.run_lib.as_deref() != Some("link") {
+        return None;
+    }
+    let mut link_options: LinkOptions = serde_yaml::from_value(task.config.data.clone()?)
+        .inspect_err(|e| debug!("Not watching link task '{}': {e}", task.name))
+        .ok()?;
+    link_options
+        .resolve_env(|s| tasks::resolve_env_vars(s, env))
+        .inspect_err(|e| debug!("Not watching link task '{}': {e}", task.name))
+        .ok()?;
+    Some(Utf8PathBuf::from(link_options.from_dir))
+}
+
+/// A file system watcher, and the changes it has seen that haven't been returned yet.
+struct ChangeWatcher {
+    /// The platform file system watcher.
+    watcher: notify::RecommendedWatcher,
+    /// Receives the watcher's events.
+    receiver: mpsc::Receiver<notify::Result<Event>>,
+    /// Files and directories whose changes are returned.
+    roots: Vec<Utf8PathBuf>,
+    /// Paths the watcher is watching, which are the parent directories of file roots.
+    watching: HashSet<(Utf8PathBuf, RecursiveMode)>,
+}
+
+impl ChangeWatcher {
+    /// Create a watcher that isn't watching anything yet.
+    fn new() -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        Ok(Self {
+            watcher: notify::recommended_watcher(sender)?,
+            receiver,
+            roots: Vec::new(),
+            watching: HashSet::new(),
+        })
+    }
+
+    /// Watch `roots` for changes instead of the previous roots.
+    fn watch(&mut self, roots: &[Utf8PathBuf]) -> Result<()> {
+        let watching = roots
+            .iter()
+            .map(|root| {
+                // Watch the directory containing a file rather than the file itself, so we still
+                // see changes after an editor replaces the file with a new one.
+                match root.parent() {
+                    Some(parent) if root.is_file() => {
+                        (parent.to_owned(), RecursiveMode::NonRecursive)
+                    }
+                    _ => (root.clone(), RecursiveMode::Recursive),
+                }
+            })
+            .collect::<HashSet<_>>();
+        for (path, _) in self.watching.difference(&watching) {
+            if let Err(e) = self.watcher.unwatch(path.as_std_path()) {
+                debug!("Failed to stop watching '{path}': {e}");
+            }
+        }
+        for (path, mode) in watching.difference(&self.watching) {
+            self.watcher
+                .watch(path.as_std_path(), *mode)
+                .wrap_err_with(|| E::Watch { path: path.clone() })?;
+        }
+        self.watching = watching;
+        self.roots = roots.to_vec();
+        Ok(())
+    }
+
+    /**
+    Block until files in the roots change, returning the files that changed (sorted) once nothing
+    else has changed for `debounce`.
+
+    Changes made since the last call (e.g. while tasks were running) are returned too.
+    */
+    fn wait_for_changes(&self, debounce: Duration) -> Result<Vec<Utf8PathBuf>> {
+        let mut changed = BTreeSet::new();
+        let mut last_change: Option<Instant> = None;
+
+        // Drain the changes that were queued up since we last waited.
+        for event in self.receiver.try_iter() {
+            if let Some(newly_changed) = self.event_changes(event) {
+                debug!("Files changed while not waiting: {newly_changed:?}");
+                changed.extend(newly_changed);
+                last_change = Some(Instant::now());
+            }
+        }
+
+        loop {
+            let event = match last_change {
+                None => self.receiver.recv().map_err(|_| E::WatcherStopped)?,
+                Some(last_change) => {
+                    match self
+                        .receiver
+                        .recv_timeout(debounce.saturating_sub(last_change.elapsed()))
+                    {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => return Ok(changed.into_iter().collect()),
+                        Err(RecvTimeoutError::Disconnected) => return Err(E::WatcherStopped.into()),
+                    }
+                }
+            };
+            if let Some(newly_changed) = self.event_changes(event) {
+                debug!("Files changed: {newly_changed:?}");
+                changed.extend(newly_changed);
+                last_change = Some(Instant::now());
+            }
+        }
+    }
+
+    /// The watched paths changed by `event`, or `None` if it didn't change any.
+    fn event_changes(&self, event: notify::Result<Event>) -> Option<Vec<Utf8PathBuf>> {
+        let event = event
+            .inspect_err(|e| warn!("Error watching for changes: {e}"))
+            .ok()?;
+        Some(changed_paths(&self.roots, &event)).filter(|changed| !changed.is_empty())
+    }
+}
+
+/**
+The paths in `roots` that `event` changed, skipping `.git` directories.
+
+Reads (including up's own reads of the config) and changes to other files in the directory of a
+watched file are ignored.
+*/
+fn changed_paths(roots: &[Utf8PathBuf], event: &Event) -> Vec<Utf8PathBuf> {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return Vec::new();
+    }
+    event
+        .paths
+        .iter()
+        .filter_map(|path| Utf8PathBuf::from_path_buf(path.clone()).ok())
+        .filter(|path| roots.iter().any(|root| path.starts_with(root)))
+        .filter(|path| {
+            !path
+                .components()
+                .any(|component| component.as_str() == ".git")
+        })
+        .collect()
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum WatchError {
+    /// Failed to watch `{path}` for changes.
+    Watch {
+        /// Path being watched.
+        path: Utf8PathBuf,
+    },
+    /// The file watcher stopped unexpectedly.
+    WatcherStopped,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::changed_paths;
+    use super::ChangeWatcher;
+    use super::Watched;
+    use camino::Utf8PathBuf;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use notify::event::AccessKind;
+    use notify::event::CreateKind;
+    use notify::event::ModifyKind;
+    use notify::Event;
+    use notify::EventKind;
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_changed_paths() -> Result<()> {
+        let roots = [
+            Utf8PathBuf::from("/config/up.yaml"),
+            Utf8PathBuf::from("/config/tasks"),
+        ];
+        let changed = |kind, paths: &[&str]| {
+            let event = paths
+                .iter()
+                .fold(Event::new(kind), |event, path| event.add_path(path.into()));
+            changed_paths(&roots, &event)
+        };
+
+        ensure_eq!(
+            vec![
+                Utf8PathBuf::from("/config/up.yaml"),
+                Utf8PathBuf::from("/config/tasks/brew.yaml"),
+            ],
+            changed(
+                EventKind::Modify(ModifyKind::Any),
+                &[
+                    "/config/up.yaml",
+                    "/config/tasks/brew.yaml",
+                    // Other files next to up.yaml are ignored.
+                    "/config/README.md",
+                    // Files in .git directories are ignored.
+                    "/config/tasks/.git/index",
+                ]
+            )
+        );
+        ensure_eq!(
+            vec![Utf8PathBuf::from("/config/tasks/new.yaml")],
+            changed(
+                EventKind::Create(CreateKind::File),
+                &["/config/tasks/new.yaml"]
+            )
+        );
+        // Reading files doesn't change them.
+        ensure_eq!(
+            Vec::<Utf8PathBuf>::new(),
+            changed(EventKind::Access(AccessKind::Any), &["/config/up.yaml"])
+        );
+        Ok(())
+    }
+
+    /// Changes are collected until files stop changing, and files edited by replacing them are
+    /// still noticed.
+    #[test]
+    fn test_wait_for_changes() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let up_yaml_path = temp_dir.join("up.yaml");
+        let tasks_dir = temp_dir.join("tasks");
+        fs::create_dir_all(&tasks_dir)?;
+        fs::write(&up_yaml_path, "")?;
+        let mut watcher = ChangeWatcher::new()?;
+        watcher.watch(&[up_yaml_path.clone(), tasks_dir.clone()])?;
+
+        let edit = {
+            let up_yaml_path = up_yaml_path.clone();
+            let tasks_dir = tasks_dir.clone();
+            thread::spawn(move || -> Result<()> {
+                thread::sleep(Duration::from_millis(200));
+                fs::write(tasks_dir.join("one.yaml"), "")?;
+                thread::sleep(Duration::from_millis(50));
+                let new_path = up_yaml_path.with_extension("yaml.new");
+                fs::write(&new_path, "edited")?;
+                fs::rename(&new_path, &up_yaml_path)?;
+                Ok(())
+            })
+        };
+        let mut changed = watcher.wait_for_changes(Duration::from_millis(500))?;
+        edit.join().map_err(|_| eyre!("Edit thread panicked."))??;
+        changed.sort();
+        ensure_eq!(vec![tasks_dir.join("one.yaml"), up_yaml_path], changed);
+
+        // Changes made while we weren't waiting (e.g. while tasks ran) are still returned.
+        fs::write(tasks_dir.join("two.yaml"), "")?;
+        thread::sleep(Duration::from_millis(200));
+        ensure_eq!(
+            vec![tasks_dir.join("two.yaml")],
+            watcher.wait_for_changes(Duration::from_millis(200))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_affected_tasks() -> Result<()> {
+        let watched = Watched {
+            up_yaml_path: Some(Utf8PathBuf::from("/config/up.yaml")),
+            task_paths: HashMap::from([
+                ("/config/tasks/brew.yaml".into(), "brew".to_owned()),
+                ("/config/tasks/dotfiles.yaml".into(), "dotfiles".to_owned()),
+            ]),
+            link_dirs: HashMap::from([("dotfiles".to_owned(), "/dotfiles".into())]),
+            ..Watched::default()
+        };
+        let affected = |paths: &[&str]| {
+            watched.affected_tasks(
+                &paths
+                    .iter()
+                    .map(|path| Utf8PathBuf::from(*path))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        ensure_eq!(
+            Some(vec!["brew".to_owned()]),
+            affected(&["/config/tasks/brew.yaml"])
+        );
+        ensure_eq!(
+            Some(vec!["brew".to_owned(), "dotfiles".to_owned()]),
+            affected(&["/dotfiles/.zshrc", "/config/tasks/brew.yaml"])
+        );
+        ensure_eq!(None, affected(&["/config/up.yaml", "/dotfiles/.zshrc"]));
+        ensure_eq!(Some(Vec::new()), affected(&["/config/tasks/notes.txt"]));
+
+        // Tasks that aren't selected by the config's filters aren't re-run.
+        let watched = Watched {
+            selected: Some(HashSet::from(["dotfiles".to_owned()])),
+            ..watched
+        };
+        let affected = |paths: &[&str]| {
+            watched.affected_tasks(
+                &paths
+                    .iter()
+                    .map(|path| Utf8PathBuf::from(*path))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        ensure_eq!(
+            Some(vec!["dotfiles".to_owned()]),
+            affected(&["/dotfiles/.zshrc", "/config/tasks/brew.yaml"])
+        );
+        ensure_eq!(Some(Vec::new()), affected(&["/config/tasks/brew.yaml"]));
+        Ok(())
+    }
+}
@@ -1,7 +1,16 @@
 //! General-use utility functions.
 
+pub(crate) mod backup;
+pub mod chrome_trace;
+pub mod duration;
+pub(crate) mod ellipsis;
 pub mod errors;
 pub mod files;
-pub(crate) mod log;
+pub(crate) mod host;
+pub(crate) mod interpolate;
+pub mod log;
 pub(crate) mod mac;
+pub(crate) mod progress;
+pub(crate) mod sleep;
 pub(crate) mod user;
+pub(crate) mod yaml;
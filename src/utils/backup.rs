@@ -0,0 +1,189 @@
+//! Backups of files that up overwrites (e.g. in `up link` and `up defaults`).
+use crate::opts::paths::TempDir;
+use crate::opts::start_time::StartTime;
+use crate::opts::Opts;
+use crate::opts::DEFAULT_KEEP_BACKUPS;
+use crate::utils::files;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use color_eyre::eyre::Context;
+use color_eyre::eyre::Result;
+use std::fs;
+use std::io::ErrorKind;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// Name of the backup directory inside the up state directory.
+const BACKUP_DIR: &str = "backup";
+
+/// Where up backs up files, and how many runs' backups it keeps.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    /// Directory containing a timestamped subdirectory for each run's backups.
+    root: Utf8PathBuf,
+    /// Backup directory for the current run.
+    run_dir: Utf8PathBuf,
+    /// Number of runs' backups to keep.
+    keep: usize,
+}
+
+impl Backups {
+    /// Work out the backup directories from the CLI options.
+    pub(crate) fn new(opts: &Opts) -> Result<Self> {
+        let root = match &opts.backup_dir {
+            Some(backup_dir) => backup_dir.clone(),
+            None => opts.state_dir()?.join(BACKUP_DIR),
+        };
+        let run_dir = root.join(timestamp_dir_name(&opts.start_time));
+        Ok(Self {
+            root,
+            run_dir,
+            keep: opts.keep_backups,
+        })
+    }
+
+    /// The directory to back up files into for the current run.
+    pub(crate) fn run_dir(&self) -> &Utf8Path {
+        &self.run_dir
+    }
+
+    /// Remove all but the most recent runs' backups, returning the directories removed.
+    pub(crate) fn prune(&self, dry_run: bool) -> Result<Vec<Utf8PathBuf>> {
+        prune_timestamped_dirs(&self.root, self.keep, dry_run)
+    }
+
+    /// Same as [`Self::prune`], but only warns on failure, as failing to clean up old backups
+    /// shouldn't fail the command that made new ones.
+    pub(crate) fn prune_or_warn(&self) {
+        if let Err(e) = self.prune(false) {
+            warn!(
+                "Failed to remove old backups from {root}: {e:?}",
+                root = self.root
+            );
+        }
+    }
+}
+
+impl Default for Backups {
+    fn default() -> Self {
+        // Without a home directory there's no state dir, so fall back to the temp dir.
+        let root = files::state_dir()
+            .unwrap_or_else(|_| TempDir::default().0)
+            .join(BACKUP_DIR);
+        let run_dir = root.join(timestamp_dir_name(&StartTime::default()));
+        Self {
+            root,
+            run_dir,
+            keep: DEFAULT_KEEP_BACKUPS,
+        }
+    }
+}
+
+/// Name of the directory to use for a run that started at `start_time`, e.g.
+/// `2024-04-26T11_22_24.834348Z`.
+pub(crate) fn timestamp_dir_name(start_time: &DateTime<Utc>) -> String {
+    start_time
+        .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        // : is not an allowed filename character in Finder.
+        .replace(':', "_")
+}
+
+/**
+Remove all but the `keep` most recent timestamped subdirectories of `dir` (as named by
+[`timestamp_dir_name`]), returning the directories removed (or that would be removed if
+`dry_run` is set).
+
+Subdirectories that aren't named with a timestamp are left alone.
+*/
+pub(crate) fn prune_timestamped_dirs(
+    dir: &Utf8Path,
+    keep: usize,
+    dry_run: bool,
+) -> Result<Vec<Utf8PathBuf>> {
+    let entries = match dir.read_dir_utf8() {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            debug!("Nothing to prune as {dir} doesn't exist.");
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e).wrap_err_with(|| format!("Failed to read directory {dir}")),
+    };
+
+    let mut timestamped_dirs = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        match DateTime::parse_from_rfc3339(&entry.file_name().replace('_', ":")) {
+            Ok(time) => timestamped_dirs.push((time, entry.into_path())),
+            Err(e) => debug!("Not pruning {path} as {e}", path = entry.path()),
+        }
+    }
+    // Most recent first.
+    timestamped_dirs.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut removed = Vec::new();
+    for (_, path) in timestamped_dirs.into_iter().skip(keep) {
+        if dry_run {
+            info!("Would remove {path}");
+        } else {
+            debug!("Removing {path}");
+            fs::remove_dir_all(&path).wrap_err_with(|| format!("Failed to remove {path}"))?;
+        }
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prune_timestamped_dirs;
+    use color_eyre::Result;
+    use std::fs;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_prune_timestamped_dirs() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        for name in [
+            "2024-01-01T00_00_00Z",
+            "2024-01-02T00_00_00.5Z",
+            "2024-01-02T00_00_00Z",
+            "not_a_timestamp",
+        ] {
+            fs::create_dir(temp_dir.join(name))?;
+        }
+
+        ensure_eq!(
+            vec![temp_dir.join("2024-01-01T00_00_00Z")],
+            prune_timestamped_dirs(&temp_dir, 2, true)?
+        );
+        ensure_eq!(4, temp_dir.read_dir_utf8()?.count());
+
+        ensure_eq!(
+            vec![
+                temp_dir.join("2024-01-02T00_00_00Z"),
+                temp_dir.join("2024-01-01T00_00_00Z"),
+            ],
+            prune_timestamped_dirs(&temp_dir, 1, false)?
+        );
+        let mut remaining: Vec<String> = temp_dir
+            .read_dir_utf8()?
+            .map(|e| Ok(e?.file_name().to_owned()))
+            .collect::<Result<_>>()?;
+        remaining.sort();
+        ensure_eq!(
+            vec![
+                "2024-01-02T00_00_00.5Z".to_owned(),
+                "not_a_timestamp".to_owned()
+            ],
+            remaining
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+/*!
+Export the spans of a run (tasks, commands, git updates) as a [Chrome trace] JSON file.
+
+Open the file in <https://ui.perfetto.dev>, `chrome://tracing`, or <https://www.speedscope.app> to
+see a timeline or flamegraph of where a run spent its time. Each span becomes a complete (`X`)
+event, with the span's fields as the event's `args`. Progress reported by run libraries becomes an
+instant (`i`) event, with the task's name, the step counts, and the message as `args`.
+
+[Chrome trace]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+*/
+use crate::utils::progress::PROGRESS_TARGET;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::process;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::field::Field;
+use tracing::field::Visit;
+use tracing::info;
+use tracing::span;
+use tracing::warn;
+use tracing::Event;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Source of the small numeric thread ids used in the trace.
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// The trace thread id of the current thread.
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A single event in the trace, see the Chrome trace format docs for the fields.
+#[derive(Debug, Serialize)]
+struct TraceEvent {
+    /// Span name, or `progress` for progress events.
+    name: String,
+    /// Event type, `X` (a complete event with a duration) or `i` (an instant event).
+    ph: &'static str,
+    /// Start time in microseconds since the run started.
+    ts: u128,
+    /// Duration in microseconds, unset for instant events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<u128>,
+    /// Process id.
+    pid: u32,
+    /// Thread the span (or event) was created on.
+    tid: u64,
+    /// The span's (or event's) fields.
+    args: BTreeMap<String, String>,
+}
+
+/// The trace file contents.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    /// Events in the order their spans closed.
+    trace_events: &'a [TraceEvent],
+    /// Unit the viewer shows times in.
+    display_time_unit: &'static str,
+}
+
+/// Timing data stored in the extensions of each open span.
+struct SpanTiming {
+    /// When the span was created.
+    start: Instant,
+    /// Thread the span was created on.
+    tid: u64,
+    /// The span's fields.
+    args: BTreeMap<String, String>,
+}
+
+/// Collects span field values as strings.
+struct ArgsVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+impl Visit for ArgsVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if !is_marker(field) {
+            self.0.insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !is_marker(field) {
+            self.0.insert(field.name().to_owned(), format!("{value:?}"));
+        }
+    }
+}
+
+/// Whether the field is only a marker for the progress bars (e.g. `indicatif.pb_hide`).
+fn is_marker(field: &Field) -> bool {
+    field.name().starts_with("indicatif.")
+}
+
+/// A tracing layer that records the timing of every span it sees.
+pub struct ChromeTraceLayer {
+    /// When the run started, trace times are relative to this.
+    start: Instant,
+    /// Events for the spans that have closed.
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+/// Writes the trace recorded by the matching [`ChromeTraceLayer`] when dropped.
+pub struct ChromeTraceWriter {
+    /// Path to write the trace to.
+    path: Utf8PathBuf,
+    /// Events for the spans that have closed.
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+/// Create a layer that records spans, and a writer that writes them to `path` when dropped.
+#[must_use]
+pub fn chrome_trace_layer(path: &Utf8Path) -> (ChromeTraceLayer, ChromeTraceWriter) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    (
+        ChromeTraceLayer {
+            start: Instant::now(),
+            events: Arc::clone(&events),
+        },
+        ChromeTraceWriter {
+            path: path.to_owned(),
+            events,
+        },
+    )
+}
+
+impl<S> Layer<S> for ChromeTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut args = BTreeMap::new();
+        attrs.record(&mut ArgsVisitor(&mut args));
+        span.extensions_mut().insert(SpanTiming {
+            start: Instant::now(),
+            tid: THREAD_ID.with(|tid| *tid),
+            args,
+        });
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(timing) = extensions.get_mut::<SpanTiming>() {
+            values.record(&mut ArgsVisitor(&mut timing.args));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if event.metadata().target() != PROGRESS_TARGET {
+            return;
+        }
+        let mut args = BTreeMap::new();
+        event.record(&mut ArgsVisitor(&mut args));
+        let task = ctx.event_span(event).and_then(|span| {
+            span.scope().find_map(|s| {
+                s.extensions()
+                    .get::<SpanTiming>()?
+                    .args
+                    .get("task")
+                    .cloned()
+            })
+        });
+        if let Some(task) = task {
+            args.insert("task".to_owned(), task);
+        }
+        let event = TraceEvent {
+            name: "progress".to_owned(),
+            ph: "i",
+            ts: self.start.elapsed().as_micros(),
+            dur: None,
+            pid: process::id(),
+            tid: THREAD_ID.with(|tid| *tid),
+            args,
+        };
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<SpanTiming>() else {
+            return;
+        };
+        let event = TraceEvent {
+            name: span.name().to_owned(),
+            ph: "X",
+            ts: timing.start.duration_since(self.start).as_micros(),
+            dur: Some(timing.start.elapsed().as_micros()),
+            pid: process::id(),
+            tid: timing.tid,
+            args: timing.args,
+        };
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+}
+
+impl ChromeTraceWriter {
+    /// Write the trace file.
+    fn write(&self) -> color_eyre::Result<()> {
+        let events = self
+            .events
+            .lock()
+            .map_err(|e| color_eyre::eyre::eyre!("Trace events lock poisoned: {e}"))?;
+        let trace = Trace {
+            trace_events: &events,
+            display_time_unit: "ms",
+        };
+        fs::write(&self.path, serde_json::to_vec(&trace)?)?;
+        Ok(())
+    }
+}
+
+impl Drop for ChromeTraceWriter {
+    fn drop(&mut self) {
+        match self.write() {
+            Ok(()) => info!("Wrote trace to {path}", path = self.path),
+            Err(e) => warn!("Failed to write trace to {path}: {e}", path = self.path),
+        }
+    }
+}
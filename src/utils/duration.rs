@@ -0,0 +1,119 @@
+//! Durations written for humans in config files, e.g. `90s`, `5m`, or `1h30m`.
+use schemars::JsonSchema;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A duration parsed from a string like `500ms`, `90s`, `5m`, `2h`, `1d`, or a combination like
+/// `1h30m`. A plain number is a number of seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "String", into = "String")]
+#[schemars(with = "String")]
+pub struct HumanDuration {
+    /// The string the duration was parsed from.
+    text: String,
+    /// The parsed duration.
+    duration: Duration,
+}
+
+impl HumanDuration {
+    /// The parsed duration.
+    #[must_use]
+    pub const fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid duration '{s}', expected e.g. '90s', '5m', or '1h30m'");
+        let text = s.trim();
+        if text.is_empty() {
+            return Err(invalid());
+        }
+        if let Ok(seconds) = text.parse::<u64>() {
+            return Ok(Self {
+                text: text.to_owned(),
+                duration: Duration::from_secs(seconds),
+            });
+        }
+
+        let mut duration = Duration::ZERO;
+        let mut rest = text;
+        while !rest.is_empty() {
+            let digits_end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(invalid)?;
+            let (number, after_number) = rest.split_at(digits_end);
+            let number: u64 = number.parse().map_err(|_| invalid())?;
+            let unit_end = after_number
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(after_number.len());
+            let (unit, after_unit) = after_number.split_at(unit_end);
+            let unit_duration = match unit {
+                "ms" => Duration::from_millis(1),
+                "s" => Duration::from_secs(1),
+                "m" => Duration::from_mins(1),
+                "h" => Duration::from_hours(1),
+                "d" => Duration::from_hours(24),
+                _ => return Err(invalid()),
+            };
+            duration += unit_duration
+                .checked_mul(u32::try_from(number).map_err(|_| invalid())?)
+                .ok_or_else(invalid)?;
+            rest = after_unit;
+        }
+        Ok(Self {
+            text: text.to_owned(),
+            duration,
+        })
+    }
+}
+
+impl TryFrom<String> for HumanDuration {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<HumanDuration> for String {
+    fn from(d: HumanDuration) -> Self {
+        d.text
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HumanDuration;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::time::Duration;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_parse_human_duration() -> Result<()> {
+        let parse = |s: &str| s.parse::<HumanDuration>().map(|d| d.duration());
+        ensure_eq!(Ok(Duration::from_secs(90)), parse("90"));
+        ensure_eq!(Ok(Duration::from_secs(90)), parse("90s"));
+        ensure_eq!(Ok(Duration::from_mins(5)), parse("5m"));
+        ensure_eq!(Ok(Duration::from_mins(90)), parse("1h30m"));
+        ensure_eq!(Ok(Duration::from_hours(24)), parse("1d"));
+        ensure_eq!(Ok(Duration::from_millis(60_500)), parse("1m500ms"));
+        for invalid in ["", "m", "5 minutes", "5x", "1.5h"] {
+            ensure!(parse(invalid).is_err(), "{invalid:?} should be invalid");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+/*!
+Merge new values into existing ones, using `...` to mean "the existing values go here".
+
+Used by the defaults library for plist values, and the json and toml libraries (which the vscode
+library uses for its settings) for config file values.
+*/
+use itertools::Itertools;
+use std::fmt;
+use tracing::trace;
+
+/// A value or key-value pair that means "insert existing values here" for arrays and dictionaries.
+const ELLIPSIS: &str = "...";
+/// Option inside an array ellipsis dictionary to match array-of-dicts entries by one of their keys.
+const IDENTITY_KEY: &str = "identity_key";
+
+/// A structured value (e.g. a plist or JSON value) that can contain ellipses.
+pub(crate) trait EllipsisValue: Clone + PartialEq + fmt::Debug {
+    /// The value as a string, if it is one.
+    fn as_str(&self) -> Option<&str>;
+    /// The value as an array, if it is one.
+    fn as_array(&self) -> Option<&Vec<Self>>;
+    /// The value as a mutable array, if it is one.
+    fn as_array_mut(&mut self) -> Option<&mut Vec<Self>>;
+    /// The entries of the value in order, if it is a dictionary.
+    fn dict_entries(&self) -> Option<Vec<(&str, &Self)>>;
+    /// The value of `key`, if this is a dictionary containing it.
+    fn dict_get(&self, key: &str) -> Option<&Self>;
+    /// Remove `key`, if this is a dictionary containing it.
+    fn dict_remove(&mut self, key: &str);
+    /// Set `key` to `value`, if this is a dictionary.
+    fn dict_insert(&mut self, key: String, value: Self);
+}
+
+impl EllipsisValue for plist::Value {
+    fn as_str(&self) -> Option<&str> {
+        self.as_string()
+    }
+
+    fn as_array(&self) -> Option<&Vec<Self>> {
+        self.as_array()
+    }
+
+    fn as_array_mut(&mut self) -> Option<&mut Vec<Self>> {
+        self.as_array_mut()
+    }
+
+    fn dict_entries(&self) -> Option<Vec<(&str, &Self)>> {
+        Some(
+            self.as_dictionary()?
+                .iter()
+                .map(|(k, v)| (k.as_str(), v))
+                .collect(),
+        )
+    }
+
+    fn dict_get(&self, key: &str) -> Option<&Self> {
+        self.as_dictionary()?.get(key)
+    }
+
+    fn dict_remove(&mut self, key: &str) {
+        if let Some(dict) = self.as_dictionary_mut() {
+            dict.remove(key);
+        }
+    }
+
+    fn dict_insert(&mut self, key: String, value: Self) {
+        if let Some(dict) = self.as_dictionary_mut() {
+            dict.insert(key, value);
+        }
+    }
+}
+
+impl EllipsisValue for serde_json::Value {
+    fn as_str(&self) -> Option<&str> {
+        self.as_str()
+    }
+
+    fn as_array(&self) -> Option<&Vec<Self>> {
+        self.as_array()
+    }
+
+    fn as_array_mut(&mut self) -> Option<&mut Vec<Self>> {
+        self.as_array_mut()
+    }
+
+    fn dict_entries(&self) -> Option<Vec<(&str, &Self)>> {
+        Some(
+            self.as_object()?
+                .iter()
+                .map(|(k, v)| (k.as_str(), v))
+                .collect(),
+        )
+    }
+
+    fn dict_get(&self, key: &str) -> Option<&Self> {
+        self.as_object()?.get(key)
+    }
+
+    fn dict_remove(&mut self, key: &str) {
+        if let Some(object) = self.as_object_mut() {
+            object.shift_remove(key);
+        }
+    }
+
+    fn dict_insert(&mut self, key: String, value: Self) {
+        if let Some(object) = self.as_object_mut() {
+            object.insert(key, value);
+        }
+    }
+}
+
+/**
+Replace `...` values in an input array.
+Does nothing if not an array.
+You end up with: [`<new values before ...>`, `<old values>`, `<new values after ...>`]
+But any duplicates between old and new values are removed, with the first value taking
+precedence.
+
+For arrays of dictionaries, the ellipsis can instead be written as `...: {identity_key: <key>}`.
+Dictionaries are then matched by the value of `<key>`, and new dictionaries that match an existing
+one replace it in place (rather than both being kept).
+*/
+pub(crate) fn replace_ellipsis_array<V: EllipsisValue>(new_value: &mut V, old_value: Option<&V>) {
+    let Some(array) = new_value.as_array_mut() else {
+        trace!("Value isn't an array, skipping ellipsis replacement...");
+        return;
+    };
+    let Some(position) = array.iter().position(is_ellipsis) else {
+        trace!("New value doesn't contain ellipsis, skipping ellipsis replacement...");
+        return;
+    };
+    let identity_key = array.get(position).and_then(ellipsis_identity_key);
+
+    let Some(old_array) = old_value.and_then(EllipsisValue::as_array) else {
+        trace!("Old value wasn't an array, skipping ellipsis replacement...");
+        array.remove(position);
+        return;
+    };
+
+    let array_copy: Vec<_> = std::mem::take(array);
+
+    let identity = |value: &V| -> Option<V> { value.dict_get(identity_key.as_deref()?).cloned() };
+
+    // New entries whose identity matches an existing entry, these replace the existing entry.
+    let mut replacements: Vec<(V, V)> = Vec::new();
+    for (index, element) in array_copy.iter().enumerate() {
+        if index == position {
+            continue;
+        }
+        let Some(id) = identity(element) else {
+            continue;
+        };
+        if old_array
+            .iter()
+            .any(|old| identity(old).as_ref() == Some(&id))
+            && !replacements
+                .iter()
+                .any(|(existing_id, _)| existing_id == &id)
+        {
+            replacements.push((id, element.clone()));
+        }
+    }
+    trace!("Identity key {identity_key:?} replacements: {replacements:?}");
+
+    trace!("Performing array ellipsis replacement...");
+    for (index, element) in array_copy.into_iter().enumerate() {
+        if index == position {
+            for old_element in old_array {
+                let element = identity(old_element)
+                    .and_then(|id| {
+                        replacements
+                            .iter()
+                            .find(|(existing_id, _)| existing_id == &id)
+                    })
+                    .map_or(old_element, |(_, replacement)| replacement);
+                if array.contains(element) {
+                    continue;
+                }
+                array.push(element.clone());
+            }
+        } else if identity(&element).is_some_and(|id| {
+            replacements
+                .iter()
+                .any(|(existing_id, _)| existing_id == &id)
+        }) {
+            trace!("Entry will replace an existing entry in place: {element:?}");
+        } else if !array.contains(&element) {
+            array.push(element);
+        }
+    }
+}
+
+/// Whether an array element is an ellipsis, either `...` or `...: {identity_key: <key>}`.
+fn is_ellipsis<V: EllipsisValue>(element: &V) -> bool {
+    element.as_str() == Some(ELLIPSIS) || ellipsis_identity_key(element).is_some()
+}
+
+/// Get the identity key to match dictionaries by from a `...: {identity_key: <key>}` array element.
+fn ellipsis_identity_key<V: EllipsisValue>(element: &V) -> Option<String> {
+    if element.dict_entries()?.len() != 1 {
+        return None;
+    }
+    let identity_key = element
+        .dict_get(ELLIPSIS)?
+        .dict_get(IDENTITY_KEY)?
+        .as_str()?;
+    Some(identity_key.to_owned())
+}
+
+/// Replace `...` keys in an input dict.
+/// Does nothing if not a dictionary.
+/// You end up with: [`<new contents before ...>`, `<old contents>`, `<new contents after ...>`]
+/// But any duplicates between old and new values are removed, with the first value taking
+/// precedence.
+pub(crate) fn replace_ellipsis_dict<V: EllipsisValue>(new_value: &mut V, old_value: Option<&V>) {
+    let Some(entries) = new_value.dict_entries() else {
+        trace!("Value isn't a dict, skipping ellipsis replacement...");
+        return;
+    };
+
+    if !entries.iter().any(|(key, _)| *key == ELLIPSIS) {
+        trace!("New value doesn't contain ellipsis, skipping ellipsis replacement...");
+        return;
+    }
+
+    let before = entries
+        .iter()
+        .map(|(key, _)| *key)
+        .take_while(|key| *key != ELLIPSIS)
+        .map(ToOwned::to_owned)
+        .collect_vec();
+    new_value.dict_remove(ELLIPSIS);
+
+    let Some(old_entries) = old_value.and_then(EllipsisValue::dict_entries) else {
+        trace!("Old value wasn't a dict, skipping ellipsis replacement...");
+        return;
+    };
+
+    trace!("Performing dict ellipsis replacement...");
+    for (key, value) in old_entries {
+        if !before.iter().any(|before_key| before_key == key) {
+            new_value.dict_insert(key.to_owned(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::Result;
+    use testutils::ensure_eq;
+
+    /// Replacing an array of dicts with an identity key should update matching entries in place.
+    #[test]
+    fn test_replace_ellipsis_array_identity_key() -> Result<()> {
+        let old_value: plist::Value = serde_yaml::from_str(
+            "[{replace: omw, with: On my way}, {replace: ty, with: Thanks}, {replace: brb, with: \
+             Back soon}]",
+        )?;
+        let mut new_value: plist::Value = serde_yaml::from_str(
+            "[{replace: hi, with: Hello}, {...: {identity_key: replace}}, {replace: ty, with: \
+             Thank you}]",
+        )?;
+        let expected_value: plist::Value = serde_yaml::from_str(
+            "[{replace: hi, with: Hello}, {replace: omw, with: On my way}, {replace: ty, with: \
+             Thank you}, {replace: brb, with: Back soon}]",
+        )?;
+
+        super::replace_ellipsis_array(&mut new_value, Some(&old_value));
+        ensure_eq!(expected_value, new_value);
+
+        // Without an identity key, the changed entry is duplicated.
+        let mut new_value: plist::Value =
+            serde_yaml::from_str("[..., {replace: ty, with: Thank you}]")?;
+        let expected_value: plist::Value = serde_yaml::from_str(
+            "[{replace: omw, with: On my way}, {replace: ty, with: Thanks}, {replace: brb, with: \
+             Back soon}, {replace: ty, with: Thank you}]",
+        )?;
+        super::replace_ellipsis_array(&mut new_value, Some(&old_value));
+        ensure_eq!(expected_value, new_value);
+
+        Ok(())
+    }
+
+    /// JSON values follow the same rules, keys before `...` override existing values, and keys
+    /// after it are overridden by them.
+    #[test]
+    fn test_replace_ellipsis_json() -> Result<()> {
+        let old_value: serde_json::Value =
+            serde_json::from_str(r#"{"a": 1, "b": 2, "list": ["x", "y"]}"#)?;
+        let mut new_value: serde_json::Value =
+            serde_json::from_str(r#"{"a": 10, "...": null, "b": 20, "c": 30}"#)?;
+        super::replace_ellipsis_dict(&mut new_value, Some(&old_value));
+        ensure_eq!(
+            serde_json::json!({"a": 10, "b": 2, "c": 30, "list": ["x", "y"]}),
+            new_value
+        );
+
+        let mut new_list = serde_json::json!(["w", "...", "x", "z"]);
+        super::replace_ellipsis_array(&mut new_list, old_value.get("list"));
+        ensure_eq!(serde_json::json!(["w", "x", "y", "z"]), new_list);
+        Ok(())
+    }
+}
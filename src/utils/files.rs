@@ -7,10 +7,19 @@ use camino::Utf8PathBuf;
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::Result;
+use std::env;
 use std::fs;
 use std::fs::File;
 use std::fs::OpenOptions;
+use std::fs::Permissions;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Write;
 use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::PermissionsExt;
+use std::process;
+use std::sync::OnceLock;
+use tracing::debug;
 use tracing::trace;
 use tracing::warn;
 
@@ -21,8 +30,27 @@ directories and files under the user's home directory, which this directory is u
 */
 const EMPTY_HOME_DIR: &str = "/var/empty";
 
-/// Return path to user's home directory if we can discover it.
+/// Home directory to use instead of the user's, from `--home`.
+static HOME_DIR_OVERRIDE: OnceLock<Utf8PathBuf> = OnceLock::new();
+
+/**
+Use `home_dir` as the home directory for the rest of the run (see [`home_dir`]).
+
+The `$XDG_*` base directory env vars describe the user's real home directory, so they're ignored
+once this is set.
+*/
+pub fn set_home_dir_override(home_dir: Utf8PathBuf) {
+    if let Err(home_dir) = HOME_DIR_OVERRIDE.set(home_dir) {
+        debug!("Home directory override already set, ignoring {home_dir}.");
+    }
+}
+
+/// Return path to user's home directory if we can discover it, or the override set by
+/// [`set_home_dir_override`].
 pub fn home_dir() -> Result<Utf8PathBuf> {
+    if let Some(home_dir) = HOME_DIR_OVERRIDE.get() {
+        return Ok(home_dir.clone());
+    }
     let home_dir = dirs::home_dir()
         .ok_or_else(|| eyre!("Expected to be able to calculate the user's home directory."))?;
     let home_dir = Utf8PathBuf::try_from(home_dir)?;
@@ -35,9 +63,43 @@ pub fn home_dir() -> Result<Utf8PathBuf> {
     Ok(home_dir)
 }
 
-/// The directory to which we write log files.
+/// The default directory for up's caches, `$XDG_CACHE_HOME/up` (`~/.cache/up` if unset).
+pub fn cache_dir() -> Result<Utf8PathBuf> {
+    Ok(xdg_dir("XDG_CACHE_HOME", ".cache")?.join("up"))
+}
+
+/// The default directory for up's state (backups and runs), `$XDG_STATE_HOME/up`
+/// (`~/.local/state/up` if unset).
+pub fn state_dir() -> Result<Utf8PathBuf> {
+    Ok(xdg_dir("XDG_STATE_HOME", ".local/state")?.join("up"))
+}
+
+/// The default directory to which we write log files, `~/Library/Logs/co.fahn.up` on macOS, or
+/// the `logs` subdirectory of the [`state_dir`] elsewhere.
 pub fn log_dir() -> Result<Utf8PathBuf> {
-    Ok(home_dir()?.join("Library/Logs").join(UP_BUNDLE_ID))
+    if cfg!(target_os = "macos") {
+        Ok(home_dir()?.join("Library/Logs").join(UP_BUNDLE_ID))
+    } else {
+        Ok(state_dir()?.join("logs"))
+    }
+}
+
+/// The user's config directory, `$XDG_CONFIG_HOME` (`~/.config` if unset).
+pub fn config_dir() -> Result<Utf8PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/**
+The XDG base directory set in `var`, or `default` relative to the home directory if unset or if
+the home directory was overridden.
+*/
+fn xdg_dir(var: &str, default: &str) -> Result<Utf8PathBuf> {
+    match env::var(var) {
+        Ok(dir) if !dir.is_empty() && HOME_DIR_OVERRIDE.get().is_none() => {
+            Ok(Utf8PathBuf::from(dir))
+        }
+        _ => Ok(home_dir()?.join(default)),
+    }
 }
 
 /// Get a parent path or provide a useful error message.
@@ -106,3 +168,158 @@ pub(crate) fn write(path: impl AsRef<Utf8Path>, contents: impl AsRef<[u8]>) -> R
     trace!("Writing data to {path}");
     fs::write(path, contents).wrap_err_with(|| eyre!("Failed to write to file {path}"))
 }
+
+/// Options for [`atomic_write()`].
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct AtomicWriteOptions<'a> {
+    /// Flush the new contents (and the rename) to disk before returning, so a crash can't leave
+    /// the file empty or truncated.
+    pub(crate) fsync: bool,
+    /// Copy any existing file into this directory (with the same file name) before replacing it.
+    pub(crate) backup_dir: Option<&'a Utf8Path>,
+    /// Permissions for the file, defaults to those of the existing file (or `0o666` minus the
+    /// umask for a new file).
+    pub(crate) mode: Option<u32>,
+}
+
+/**
+Write `contents` to `path` atomically, by writing them to a temporary file in the same directory
+and renaming it over `path`, so the file always has either its old or its new contents, even if
+up is killed halfway through. Returns the backup path if an existing file was backed up.
+
+If `path` is a symlink the file it points to is replaced, so linked dotfiles stay linked. The
+parent directory must already exist.
+*/
+pub(crate) fn atomic_write(
+    path: &Utf8Path,
+    contents: impl AsRef<[u8]>,
+    opts: AtomicWriteOptions,
+) -> Result<Option<Utf8PathBuf>, UpError> {
+    let io_error = |path: &Utf8Path| {
+        let path = path.to_owned();
+        move |e| UpError::IoError { path, source: e }
+    };
+    let path = &match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_symlink() => {
+            path.canonicalize_utf8().map_err(io_error(path))?
+        }
+        _ => path.to_owned(),
+    };
+    trace!("Atomically writing data to {path}");
+    let existing_mode = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata.permissions().mode()),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(io_error(path)(e)),
+    };
+    let file_name = path.file_name().ok_or_else(|| UpError::IoError {
+        path: path.clone(),
+        source: io::Error::new(ErrorKind::InvalidInput, "path has no file name"),
+    })?;
+
+    let backup_path = match opts.backup_dir {
+        Some(backup_dir) if existing_mode.is_some() => {
+            let backup_path = backup_dir.join(file_name);
+            trace!("Backing up {path} -> {backup_path}");
+            fs::create_dir_all(backup_dir)
+                .and_then(|()| fs::copy(path, &backup_path))
+                .map_err(|e| UpError::BackupError {
+                    path: path.clone(),
+                    backup_path: backup_path.clone(),
+                    source: e,
+                })?;
+            Some(backup_path)
+        }
+        _ => None,
+    };
+
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_str().is_empty())
+        .unwrap_or_else(|| Utf8Path::new("."));
+    let temp_path = dir.join(format!(".{file_name}.{pid}.tmp", pid = process::id()));
+    let mode = opts.mode.or(existing_mode);
+    let result = write_temp_file(&temp_path, contents.as_ref(), mode, opts.fsync)
+        .and_then(|()| fs::rename(&temp_path, path));
+    if let Err(e) = result {
+        // The temporary file is useless now, and may not even exist.
+        _ = fs::remove_file(&temp_path);
+        return Err(io_error(path)(e));
+    }
+    if opts.fsync {
+        // Make sure the rename itself is on disk.
+        File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(io_error(dir))?;
+    }
+    Ok(backup_path)
+}
+
+/// Write `contents` to a new file at `temp_path` with `mode`, flushing it to disk if `fsync`.
+fn write_temp_file(
+    temp_path: &Utf8Path,
+    contents: &[u8],
+    mode: Option<u32>,
+    fsync: bool,
+) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o666)
+        .open(temp_path)?;
+    file.write_all(contents)?;
+    if let Some(mode) = mode {
+        // Set explicitly, as the mode passed to `open` is masked by the umask.
+        file.set_permissions(Permissions::from_mode(mode))?;
+    }
+    if fsync {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::atomic_write;
+    use super::AtomicWriteOptions;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_atomic_write() -> Result<()> {
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let path = temp_dir.join("config.yaml");
+        let backup_dir = temp_dir.join("backup");
+        let opts = AtomicWriteOptions {
+            fsync: true,
+            backup_dir: Some(&backup_dir),
+            mode: None,
+        };
+
+        // Nothing to back up for a new file.
+        ensure_eq!(None, atomic_write(&path, "first", opts)?);
+        ensure_eq!("first", fs::read_to_string(&path)?);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        ensure_eq!(
+            Some(backup_dir.join("config.yaml")),
+            atomic_write(&path, "second", opts)?
+        );
+        ensure_eq!("second", fs::read_to_string(&path)?);
+        ensure_eq!("first", fs::read_to_string(backup_dir.join("config.yaml"))?);
+        // Existing permissions are kept, and no temporary files are left behind.
+        ensure_eq!(0o600, fs::metadata(&path)?.permissions().mode() & 0o777);
+        ensure_eq!(2, fs::read_dir(&temp_dir)?.count());
+
+        // Writing through a symlink replaces the file it points to.
+        let link_path = temp_dir.join("link.yaml");
+        std::os::unix::fs::symlink(&path, &link_path)?;
+        atomic_write(&link_path, "third", AtomicWriteOptions::default())?;
+        ensure_eq!("third", fs::read_to_string(&path)?);
+        ensure!(fs::symlink_metadata(&link_path)?.is_symlink());
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! Identify the machine up is running on, to pick its entry in the `hosts` section of `up.yaml`.
+use crate::exec::cmd_log;
+use crate::utils::mac;
+use std::fs;
+use tracing::debug;
+use tracing::Level;
+
+/// File with the machine's serial number on Linux (usually only readable by root).
+const LINUX_SERIAL_FILE: &str = "/sys/class/dmi/id/product_serial";
+
+/**
+The names this machine could be listed under in `hosts`, each with what it is, e.g.
+`("hostname", "work-laptop.local")`, in the order they're matched: the hostname, the hostname
+without its domain, then the serial number.
+
+Identifiers that can't be found (e.g. the serial number of a VM) are left out.
+*/
+pub(crate) fn identifiers() -> Vec<(&'static str, String)> {
+    let mut identifiers = Vec::new();
+    match cmd_log(Level::DEBUG, "uname", &["-n"]).read() {
+        Ok(hostname) => {
+            let hostname = hostname.trim().to_owned();
+            let short_hostname = hostname
+                .split_once('.')
+                .map(|(short_hostname, _domain)| short_hostname.to_owned());
+            identifiers.push(("hostname", hostname));
+            if let Some(short_hostname) = short_hostname {
+                identifiers.push(("short hostname", short_hostname));
+            }
+        }
+        Err(e) => debug!("Failed to get the hostname: {e}"),
+    }
+    if let Some(serial_number) = serial_number() {
+        identifiers.push(("serial number", serial_number));
+    } else {
+        debug!("Couldn't get this machine's serial number.");
+    }
+    identifiers
+}
+
+/// The machine's serial number, if it has one we can read.
+fn serial_number() -> Option<String> {
+    if cfg!(target_os = "macos") {
+        mac::get_serial_number()
+            .inspect_err(|e| debug!("Failed to get the serial number: {e}"))
+            .ok()
+            .flatten()
+    } else {
+        fs::read_to_string(LINUX_SERIAL_FILE)
+            .inspect_err(|e| debug!("Failed to read {LINUX_SERIAL_FILE}: {e}"))
+            .ok()
+            .map(|serial_number| serial_number.trim().to_owned())
+            .filter(|serial_number| !serial_number.is_empty())
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+Interpolate the output of commands into config values with `$(cmd <program> <args>...)`, e.g.
+`$(cmd brew --prefix)` or `$(cmd security find-generic-password -w -s npm)`.
+
+- Only `$(cmd ...)` is interpolated, so shell command substitutions (`$(...)`) in scripts are left
+  for the shell.
+- Commands aren't run in a shell: arguments are split on whitespace (quote them with `'` or `"` to
+  include spaces), and env vars in them aren't expanded. They get only up's env (like tasks), no
+  stdin, and are killed if they take longer than [`COMMAND_TIMEOUT`].
+- Each command is run at most once per up run, later uses get the cached output.
+
+The command's stdout (minus trailing newlines) replaces the `$(cmd ...)`. Values are interpolated
+before env vars in them are expanded, so only commands written in the config are run (not ones in
+the values of env vars), and each `$` in the output is escaped as `$$` so expanding env vars leaves
+the output as it was.
+*/
+use self::InterpolateError as E;
+use crate::exec::cmd_log;
+use displaydoc::Display;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io;
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use thiserror::Error;
+use tracing::debug;
+use tracing::Level;
+
+/// Start of a command to interpolate, the command runs until the next unquoted `)`.
+const PREFIX: &str = "$(cmd ";
+
+/// How long an interpolated command can run before it's killed.
+pub(crate) const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to check whether a command has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The output of a command, once it has run. Each command has its own lock, so only uses of the
+/// same command wait for it to run.
+type CommandOutput = Arc<Mutex<Option<String>>>;
+
+/// Output of the commands run so far in this run, keyed by their arguments.
+static OUTPUTS: Mutex<BTreeMap<Vec<String>, CommandOutput>> = Mutex::new(BTreeMap::new());
+
+/// Forget the outputs of the commands run so far, so they're run again in the next run (e.g. when
+/// `up watch` re-runs tasks).
+pub(crate) fn clear_cache() {
+    OUTPUTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+/// Replace each `$(cmd ...)` in `value` with the output of the command (with `$` escaped as `$$`),
+/// run with `env`.
+pub(crate) fn interpolate(value: &str, env: &HashMap<String, String>) -> Result<String, E> {
+    if !value.contains(PREFIX) {
+        return Ok(value.to_owned());
+    }
+    let mut interpolated = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find(PREFIX) {
+        interpolated.push_str(&rest[..start]);
+        let command = &rest[start + PREFIX.len()..];
+        let (args, end) = parse_command(command).ok_or_else(|| E::Unterminated {
+            value: value.to_owned(),
+        })?;
+        interpolated.push_str(&cached_output(&args, env)?.replace('$', "$$"));
+        rest = &command[end + 1..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Split the `command` (after the [`PREFIX`]) into its arguments, returning them and the index of
+/// the closing `)`, or `None` if there isn't one.
+fn parse_command(command: &str) -> Option<(Vec<String>, usize)> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    for (index, c) in command.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, ')') => {
+                args.extend(arg);
+                return Some((args, index));
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (_, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    None
+}
+
+/// The output of the command `args`, running it if it hasn't already been run.
+fn cached_output(args: &[String], env: &HashMap<String, String>) -> Result<String, E> {
+    let command_output = Arc::clone(
+        OUTPUTS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(args.to_vec())
+            .or_default(),
+    );
+    // Hold the command's lock while running, so tasks resolving it in parallel run it once.
+    let mut command_output = command_output
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(output) = command_output.as_ref() {
+        debug!("Using cached output of interpolated command {args:?}");
+        return Ok(output.clone());
+    }
+    let output = run(args, env)?;
+    *command_output = Some(output.clone());
+    Ok(output)
+}
+
+/// Run the command `args` with `env`, returning its stdout without trailing newlines.
+fn run(args: &[String], env: &HashMap<String, String>) -> Result<String, E> {
+    let (program, program_args) = args.split_first().ok_or(E::Empty)?;
+    let command = args.join(" ");
+    let handle = cmd_log(Level::DEBUG, program, program_args)
+        .full_env(env)
+        .stdin_null()
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .start()
+        .map_err(|e| E::Run {
+            command: command.clone(),
+            source: e,
+        })?;
+    let start = Instant::now();
+    let output = loop {
+        if let Some(output) = handle.try_wait().map_err(|e| E::Run {
+            command: command.clone(),
+            source: e,
+        })? {
+            break output;
+        }
+        if start.elapsed() > COMMAND_TIMEOUT {
+            // The command is already being abandoned, so there's nothing to do if this fails.
+            _ = handle.kill();
+            return Err(E::Timeout {
+                command,
+                timeout: COMMAND_TIMEOUT,
+            });
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    if !output.status.success() {
+        return Err(E::Failed {
+            command,
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_owned())
+}
+
+#[derive(Error, Debug, Display)]
+/// Errors thrown by this file.
+pub enum InterpolateError {
+    /// Missing closing `)` for `$(cmd ...)` in `{value}`.
+    Unterminated {
+        /// The value being interpolated.
+        value: String,
+    },
+    /// No command given in `$(cmd )`.
+    Empty,
+    /// Failed to run interpolated command `{command}`.
+    Run {
+        /// The command.
+        command: String,
+        /// Source error.
+        source: io::Error,
+    },
+    /// Interpolated command `{command}` took longer than {timeout:?}.
+    Timeout {
+        /// The command.
+        command: String,
+        /// How long it was given.
+        timeout: Duration,
+    },
+    /// Interpolated command `{command}` failed with {status}: {stderr}
+    Failed {
+        /// The command.
+        command: String,
+        /// Exit status.
+        status: ExitStatus,
+        /// The command's stderr.
+        stderr: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clear_cache;
+    use super::interpolate;
+    use super::parse_command;
+    use color_eyre::eyre::ensure;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use std::time::Instant;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_parse_command() -> Result<()> {
+        ensure_eq!(
+            Some((vec!["echo".to_owned(), "a b".to_owned(), String::new()], 13)),
+            parse_command(r#"echo 'a b' "") rest"#)
+        );
+        ensure_eq!(
+            Some((vec!["echo".to_owned(), ")".to_owned()], 9)),
+            parse_command("echo ')' )")
+        );
+        ensure_eq!(None, parse_command("echo 'a)'"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate() -> Result<()> {
+        let env = HashMap::from([
+            ("PATH".to_owned(), std::env::var("PATH")?),
+            ("greeting".to_owned(), "hello".to_owned()),
+        ]);
+        ensure_eq!("no commands", interpolate("no commands", &env)?);
+        ensure_eq!("$$HOME", interpolate("$(cmd echo '$HOME')", &env)?);
+        ensure_eq!(
+            "$(date) hello world/bin",
+            interpolate(
+                "$(date) $(cmd printenv greeting) $(cmd echo 'world')/bin",
+                &env
+            )?
+        );
+
+        // Commands are only run once per run.
+        let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+        let counter = temp_dir.join("counter");
+        let command = format!("$(cmd sh -c 'echo x >> {counter}; wc -l < {counter}')");
+        let first = interpolate(&command, &env)?;
+        ensure_eq!(first, interpolate(&command, &env)?);
+        ensure_eq!("x\n", std::fs::read_to_string(&counter)?);
+
+        // Clearing the cache (as `up watch` does between runs) runs them again.
+        clear_cache();
+        ensure!(first != interpolate(&command, &env)?);
+        ensure_eq!("x\nx\n", std::fs::read_to_string(&counter)?);
+
+        // A slow command doesn't hold up other commands.
+        std::thread::scope(|s| -> Result<()> {
+            let slow = s.spawn(|| interpolate("$(cmd sleep 2)", &env));
+            std::thread::sleep(Duration::from_millis(200));
+            let start = Instant::now();
+            ensure_eq!("quick", interpolate("$(cmd echo quick)", &env)?);
+            ensure!(start.elapsed() < Duration::from_secs(1));
+            ensure_eq!(
+                "",
+                slow.join()
+                    .map_err(|_| eyre!("Slow command thread panicked."))??
+            );
+            Ok(())
+        })?;
+
+        ensure!(interpolate("$(cmd false)", &env).is_err());
+        ensure!(interpolate("$(cmd echo", &env).is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,20 @@
 //! Utilities to help with logging.
 
+use std::cell::Cell;
+use tracing::level_filters::LevelFilter;
+use tracing::span::Attributes;
+use tracing::span::Id;
+use tracing::span::Record;
+use tracing::subscriber::Interest;
+use tracing::Event;
+use tracing::Metadata;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::layer::Filter;
+
+/// Log target for the end of run summary, which is still shown with `--quiet`.
+pub(crate) const SUMMARY_TARGET: &str = "up_rs::summary";
+
 /**
 Equivalent of `::log::log!()` for the tracing crate.
 
@@ -17,3 +32,97 @@ macro_rules! log {
         }
     };
 }
+
+thread_local! {
+    /// Stderr log level of the task running on this thread, set with [`TaskLogLevelGuard`].
+    static TASK_LOG_LEVEL: Cell<Option<LevelFilter>> = const { Cell::new(None) };
+}
+
+/// Sets the stderr log level for up's logs on this thread (a task's `log_level`) until dropped.
+#[derive(Debug)]
+pub(crate) struct TaskLogLevelGuard {
+    /// Level to restore when dropped.
+    previous: Option<LevelFilter>,
+}
+
+impl TaskLogLevelGuard {
+    /// Use `level` for up's logs on this thread, or the normal log level if `None`.
+    pub(crate) fn set(level: Option<LevelFilter>) -> Self {
+        Self {
+            previous: TASK_LOG_LEVEL.replace(level),
+        }
+    }
+}
+
+impl Drop for TaskLogLevelGuard {
+    fn drop(&mut self) {
+        TASK_LOG_LEVEL.set(self.previous);
+    }
+}
+
+/**
+Stderr log filter that applies the `log_level` of the task running on the current thread to up's
+logs, and otherwise falls back to the `inner` filter (e.g. from `--log`).
+
+Task log levels can be more verbose than the `inner` filter, so every event is checked when it's
+logged rather than being disabled up front.
+*/
+#[derive(Debug)]
+pub struct TaskLogLevelFilter<F> {
+    /// Filter for logs outside tasks with a `log_level`.
+    inner: F,
+}
+
+impl<F> TaskLogLevelFilter<F> {
+    /// Wrap the `inner` filter.
+    pub const fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: Subscriber, F: Filter<S>> Filter<S> for TaskLogLevelFilter<F> {
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        match TASK_LOG_LEVEL.get() {
+            Some(level) if meta.is_event() && meta.target().starts_with("up_rs") => {
+                *meta.level() <= level
+            }
+            _ => self.inner.enabled(meta, cx),
+        }
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        if meta.is_event() {
+            Interest::sometimes()
+        } else {
+            self.inner.callsite_enabled(meta)
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        None
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, cx: &Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, cx)
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        self.inner.on_record(id, values, ctx);
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+}
@@ -5,19 +5,45 @@ use color_eyre::eyre::eyre;
 use color_eyre::Result;
 use serde::Deserialize;
 use serde::Serialize;
+use std::sync::OnceLock;
+use tracing::debug;
 
-/// Get the hardware UUID of the current Mac.
+/// Hardware UUID to use instead of the current Mac's, from the `hardware_uuid` of the selected
+/// `hosts` entry in `up.yaml`.
+static HARDWARE_UUID_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+/// Use `uuid` as the hardware UUID for the rest of the run (e.g. for `current_host` defaults).
+pub(crate) fn set_hardware_uuid_override(uuid: String) {
+    if let Err(uuid) = HARDWARE_UUID_OVERRIDE.set(uuid) {
+        debug!("Hardware UUID override already set, ignoring {uuid}.");
+    }
+}
+
+/// Get the hardware UUID of the current Mac, or the override set by
+/// [`set_hardware_uuid_override`].
 /// You can get the Hardware UUID from:
 /// <https://apple.stackexchange.com/questions/342042/how-can-i-query-the-hardware-uuid-of-a-mac-programmatically-from-a-command-line>
 pub(crate) fn get_hardware_uuid() -> Result<String> {
+    if let Some(uuid) = HARDWARE_UUID_OVERRIDE.get() {
+        return Ok(uuid.clone());
+    }
+    Ok(platform_expert_device()?.io_platform_uuid)
+}
+
+/// Get the serial number of the current Mac, `None` if it doesn't have one (e.g. in a VM).
+pub(crate) fn get_serial_number() -> Result<Option<String>> {
+    Ok(platform_expert_device()?.io_platform_serial_number)
+}
+
+/// The `IOPlatformExpertDevice` entry from the `IORegistry`.
+fn platform_expert_device() -> Result<IoRegistryEntryChildren> {
     let raw_output = cmd_debug!("ioreg", "-d2", "-a", "-c", "IOPlatformExpertDevice").read()?;
     let ioreg_output: IoregOutput = plist::from_bytes(raw_output.as_bytes())?;
-    Ok(ioreg_output
+    ioreg_output
         .io_registry_entry_children
         .into_iter()
         .next()
-        .ok_or_else(|| eyre!("Failed to get the Hardware UUID for the current Mac."))?
-        .io_platform_uuid)
+        .ok_or_else(|| eyre!("Failed to get the Hardware UUID for the current Mac."))
 }
 
 /// XML output returned by `ioreg -d2 -a -c IOPlatformExpertDevice`
@@ -34,6 +60,9 @@ struct IoRegistryEntryChildren {
     /// The platform UUID.
     #[serde(rename = "IOPlatformUUID")]
     io_platform_uuid: String,
+    /// The platform serial number.
+    #[serde(rename = "IOPlatformSerialNumber")]
+    io_platform_serial_number: Option<String>,
 }
 
 #[cfg(target_os = "macos")]
@@ -0,0 +1,55 @@
+/*!
+Progress reporting for run libraries that take a while (e.g. `git` updating many repos).
+
+Progress is shown in the task's progress bar, and recorded as events in the `--trace-file`.
+*/
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use tracing::Span;
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+
+/// Log target for progress events, which the `--trace-file` records.
+pub(crate) const PROGRESS_TARGET: &str = "up_rs::progress";
+
+/**
+Report that the current task has done `current` of `total` steps, `msg` describes the latest one.
+
+Must be called from inside the task's span, use [`TaskProgress`] to report progress from other
+threads.
+*/
+pub(crate) fn task_progress(current: u64, total: u64, msg: &str) {
+    let span = Span::current();
+    span.pb_set_length(total);
+    span.pb_set_position(current);
+    span.pb_set_message(&format!("{current}/{total} {msg}"));
+    tracing::debug!(target: PROGRESS_TARGET, current, total, "{msg}");
+}
+
+/// Counts the steps of a task as they finish, which can be on any thread (e.g. with rayon).
+#[derive(Debug)]
+pub(crate) struct TaskProgress {
+    /// The task's span, progress is reported in it.
+    span: Span,
+    /// Number of steps that have finished.
+    done: AtomicU64,
+    /// Total number of steps.
+    total: u64,
+}
+
+impl TaskProgress {
+    /// Start counting progress for the task whose span is the current span.
+    pub(crate) fn new(total: usize) -> Self {
+        Self {
+            span: Span::current(),
+            done: AtomicU64::new(0),
+            total: total as u64,
+        }
+    }
+
+    /// Record that another step has finished, `msg` describes it.
+    pub(crate) fn step(&self, msg: &str) {
+        let current = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.span
+            .in_scope(|| task_progress(current, self.total, msg));
+    }
+}
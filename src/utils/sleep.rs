@@ -0,0 +1,122 @@
+//! Stop the machine sleeping while tasks run (the `prevent_sleep` option in `up.yaml`).
+//!
+//! On macOS this uses `caffeinate`, on Linux `systemd-inhibit` (if it's installed). The helper
+//! process is killed when the returned [`SleepInhibitor`] is dropped, and also exits by itself
+//! when up exits.
+use crate::cmd_debug;
+use duct::Handle;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fs;
+use std::process;
+use tracing::debug;
+
+/// When to stop the machine sleeping while tasks run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreventSleep {
+    /// Whenever tasks run.
+    #[default]
+    Always,
+    /// Only if the machine isn't running on battery power.
+    OnAc,
+    /// Let the machine sleep as normal.
+    Never,
+}
+
+/// Keeps the machine awake until dropped.
+#[derive(Debug)]
+pub(crate) struct SleepInhibitor(Option<Handle>);
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.0 {
+            if let Err(e) = handle.kill() {
+                debug!("Failed to stop sleep inhibitor: {e}");
+            }
+        }
+    }
+}
+
+/// Stop the machine sleeping (depending on `mode`) until the returned value is dropped.
+pub(crate) fn prevent_sleep(mode: PreventSleep) -> SleepInhibitor {
+    let inhibit = match mode {
+        PreventSleep::Always => true,
+        PreventSleep::OnAc => !on_battery(),
+        PreventSleep::Never => false,
+    };
+    if !inhibit {
+        debug!("Not preventing sleep (prevent_sleep is {mode:?}).");
+        return SleepInhibitor(None);
+    }
+    let pid = process::id().to_string();
+    let command = if cfg!(target_os = "macos") {
+        // Don't let the display sleep until up exits.
+        cmd_debug!("caffeinate", "-ds", "-w", &pid)
+    } else {
+        cmd_debug!(
+            "systemd-inhibit",
+            "--what=sleep:idle",
+            "--who=up",
+            "--why=Running up tasks",
+            "tail",
+            &format!("--pid={pid}"),
+            "-f",
+            "/dev/null"
+        )
+    };
+    match command.stdout_null().stderr_null().unchecked().start() {
+        Ok(handle) => SleepInhibitor(Some(handle)),
+        Err(e) => {
+            debug!("Failed to start sleep inhibitor: {e}");
+            SleepInhibitor(None)
+        }
+    }
+}
+
+/// Whether the machine is currently running on battery power.
+fn on_battery() -> bool {
+    if cfg!(target_os = "macos") {
+        cmd_debug!("pmset", "-g", "batt")
+            .stderr_null()
+            .read()
+            .is_ok_and(|output| pmset_on_battery(&output))
+    } else {
+        // Any battery that's discharging means we're not on AC power.
+        fs::read_dir("/sys/class/power_supply")
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .any(|supply| {
+                fs::read_to_string(supply.path().join("status"))
+                    .is_ok_and(|status| status.trim() == "Discharging")
+            })
+    }
+}
+
+/// Whether `pmset -g batt` output says we're on battery, e.g. `Now drawing from 'Battery Power'`.
+fn pmset_on_battery(output: &str) -> bool {
+    output
+        .lines()
+        .next()
+        .is_some_and(|line| line.contains("'Battery Power'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pmset_on_battery;
+    use color_eyre::eyre::ensure;
+    use color_eyre::Result;
+
+    #[test]
+    fn test_pmset_on_battery() -> Result<()> {
+        ensure!(pmset_on_battery(
+            "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=1234)\t85%; discharging;\n"
+        ));
+        ensure!(!pmset_on_battery(
+            "Now drawing from 'AC Power'\n -InternalBattery-0 (id=1234)\t100%; charged;\n"
+        ));
+        ensure!(!pmset_on_battery(""));
+        Ok(())
+    }
+}
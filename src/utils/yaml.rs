@@ -0,0 +1,93 @@
+/*!
+Parse YAML with merge keys (`<<: *anchor`), which `serde_yaml` resolves aliases for but otherwise
+treats as a normal `<<` key.
+
+Top-level keys starting with `x-` are ignored, so they can hold anchors to share between fields,
+e.g.:
+
+```yaml
+x-mac: &mac
+  constraints:
+    os: macos
+<<: *mac
+run_cmd: [brew, update]
+```
+*/
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+
+/// Prefix of top-level keys that are only there to hold anchors.
+const EXTENSION_PREFIX: &str = "x-";
+
+/// Parse `contents` as YAML, applying merge keys and dropping top-level `x-` keys.
+///
+/// Files without either are parsed directly, so errors keep their line numbers (errors from
+/// parsing the merged value don't have them).
+pub(crate) fn from_str<T: DeserializeOwned>(contents: &str) -> Result<T, serde_yaml::Error> {
+    let parsed: Value = serde_yaml::from_str(contents)?;
+    let mut merged = parsed.clone();
+    merged.apply_merge()?;
+    if let Some(mapping) = merged.as_mapping_mut() {
+        mapping.retain(|key, _| {
+            !key.as_str()
+                .is_some_and(|key| key.starts_with(EXTENSION_PREFIX))
+        });
+    }
+    if merged == parsed {
+        serde_yaml::from_str(contents)
+    } else {
+        serde_yaml::from_value(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_str;
+    use crate::tasks::task::TaskConfig;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use serde_yaml::Value;
+    use std::collections::HashMap;
+    use testutils::ensure_eq;
+
+    #[test]
+    fn test_from_str_merge_keys() -> Result<()> {
+        let config: TaskConfig = from_str(
+            "
+x-mac: &mac
+  constraints:
+    os: macos
+  tags: [mac]
+<<: *mac
+tags: [brew]
+run_cmd: [brew, update]
+",
+        )?;
+        ensure_eq!(
+            Some("macos"),
+            config
+                .constraints
+                .as_ref()
+                .and_then(|c| c.get("os"))
+                .map(String::as_str)
+        );
+        // Keys set directly override merged ones.
+        ensure_eq!(Some(vec!["brew".to_owned()]), config.tags);
+
+        let error = from_str::<TaskConfig>("run_cmd: [ls]\nunknown: true\n")
+            .err()
+            .ok_or_else(|| eyre!("Expected unknown field error."))?;
+        ensure_eq!(Some(2), error.location().map(|location| location.line()));
+
+        // Merge keys are applied even for types that would accept a `<<` key.
+        let map: HashMap<String, Value> = from_str("x-base: &base\n  a: 1\n<<: *base\nb: 2\n")?;
+        ensure_eq!(
+            HashMap::from([
+                ("a".to_owned(), Value::from(1)),
+                ("b".to_owned(), Value::from(2))
+            ]),
+            map
+        );
+        Ok(())
+    }
+}
@@ -88,6 +88,23 @@ fn test_defaults_read_local() -> Result<()> {
             .try_stdout(expected_value.clone())?;
     }
 
+    // Asking for the defaults format should give the same output as the defaults command.
+    {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.args([
+            "defaults",
+            "read",
+            "--format",
+            "defaults",
+            "com.apple.dock",
+            "region",
+        ]);
+        cmd.assert()
+            .eprint_stdout_stderr()
+            .try_success()?
+            .try_stdout(expected_value.clone())?;
+    }
+
     // A .plist extension should be allowed too.
     {
         let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
@@ -119,6 +136,159 @@ fn test_defaults_read_local() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_defaults_stdin() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    let input_plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>array</key>
+	<array>
+		<string>a</string>
+	</array>
+</dict>
+</plist>
+"#;
+
+    // Writing to `-` should print the updated plist in the same format as the input.
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "write", "-", "array", r#"["...", "b"]"#])
+        .write_stdin(input_plist);
+    let output = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let output_plist = String::from_utf8_lossy(&output.get_output().stdout).to_string();
+    ensure_eq!(
+        input_plist.replace(
+            "\t\t<string>a</string>\n",
+            "\t\t<string>a</string>\n\t\t<string>b</string>\n"
+        ),
+        output_plist
+    );
+
+    // Reading from `-` should read the plist from stdin.
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "read", "-", "array"])
+        .write_stdin(output_plist);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stdout("- a\n- b\n")?;
+
+    Ok(())
+}
+
+/// Applying a defaults file writes its values, and applying it again changes nothing.
+#[test]
+fn test_defaults_apply() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    let plist_path = temp_dir.join("test.plist");
+    let apply_path = temp_dir.join("defaults.yaml");
+    std::fs::write(
+        &apply_path,
+        format!("{plist_path}:\n  number: 1\n  array: [a, b]\n"),
+    )?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "apply", apply_path.as_str()]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stderr(predicate::str::contains("changes: 2 defaults changed"))?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "apply", apply_path.as_str()]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stderr(predicate::str::contains("already up to date"))?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "read", plist_path.as_str(), "array"]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stdout("- a\n- b\n")?;
+
+    Ok(())
+}
+
+/// `defaults write --from-file` writes several keys at once, from a file or stdin.
+#[test]
+fn test_defaults_write_from_file() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    let plist_path = temp_dir.join("test.plist");
+    let values_path = temp_dir.join("values.yaml");
+    std::fs::write(&values_path, "number: 1\narray: [a, b]\n")?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "defaults",
+        "write",
+        plist_path.as_str(),
+        "--from-file",
+        values_path.as_str(),
+    ]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stderr(predicate::str::contains("2 defaults changed"))?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "write", plist_path.as_str(), "--from-file", "-"])
+        .write_stdin(r#"array: ["...", c]"#);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stderr(predicate::str::contains("1 defaults changed"))?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "read", plist_path.as_str(), "array"]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stdout("- a\n- b\n- c\n")?;
+
+    Ok(())
+}
+
+/// `defaults unmanage` reverts the keys up wrote, and fails once nothing is left to unmanage.
+#[test]
+fn test_defaults_unmanage() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    let plist_path = temp_dir.join("test.plist");
+
+    for (key, value) in [("kept", "1"), ("changed", "a"), ("changed", "b")] {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.args(["defaults", "write", plist_path.as_str(), key, value]);
+        cmd.assert().eprint_stdout_stderr().try_success()?;
+    }
+
+    // The key didn't exist before up first wrote it, so it's deleted.
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "unmanage", plist_path.as_str(), "changed"]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stderr(predicate::str::contains("Unmanaged 1 defaults"))?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "read", plist_path.as_str(), "--keys"]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stdout("kept\n")?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["defaults", "unmanage", plist_path.as_str(), "changed"]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_failure()?
+        .try_stderr(predicate::str::contains("nothing to unmanage"))?;
+
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 struct TestCase {
     name: &'static str,
@@ -0,0 +1,62 @@
+use color_eyre::Result;
+use testutils::ensure_eq;
+use testutils::AssertCmdExt;
+
+/// Check that `up env` prints the resolved env, masking secrets.
+#[test]
+fn test_env() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let up_yaml = temp_dir.join("up_config_dir/up.yaml");
+
+    // The hardware UUID depends on the machine, so ignore it. The colour env vars are set as
+    // tests pass `--color=always`.
+    let env_output = |args: &[&str]| -> Result<String> {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.args(["--config", up_yaml.as_str(), "env"]).args(args);
+        let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+        Ok(String::from_utf8_lossy(&cmd_assert.get_output().stdout)
+            .lines()
+            .filter(|line| !line.contains("UP_HARDWARE_UUID"))
+            .map(|line| format!("{line}\n"))
+            .collect())
+    };
+
+    let config_dir = temp_dir.join("up_config_dir").canonicalize_utf8()?;
+    ensure_eq!(
+        format!(
+            "CLICOLOR=1
+CLICOLOR_FORCE=1
+FORCE_COLOR=1
+GITHUB_TOKEN=********
+UP_CONFIG_DIR={config_dir}
+greeting=hello
+message=hello world
+"
+        ),
+        env_output(&[])?
+    );
+
+    ensure_eq!(
+        format!(
+            "export CLICOLOR=1
+export CLICOLOR_FORCE=1
+export FORCE_COLOR=1
+export GITHUB_TOKEN=not-a-real-token
+export UP_CONFIG_DIR={config_dir}
+export greeting=hello
+export message='hello world'
+"
+        ),
+        env_output(&["--format", "export", "--show-secrets"])?
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&env_output(&["--format", "json"])?)?;
+    ensure_eq!(Some("********"), json["GITHUB_TOKEN"].as_str());
+    ensure_eq!(Some("hello world"), json["message"].as_str());
+    Ok(())
+}
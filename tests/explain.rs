@@ -0,0 +1,62 @@
+use color_eyre::Result;
+use testutils::ensure_eq;
+use testutils::AssertCmdExt;
+
+/// Check that `up explain` shows a task's details with env vars resolved.
+#[test]
+fn test_explain() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let config_dir = temp_dir.join("up_config_dir");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        config_dir.join("up.yaml").as_str(),
+        "--run-temp-dir",
+        temp_dir.join("run_temp_dir").as_str(),
+        "explain",
+        "hello",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    ensure_eq!(
+        format!(
+            "Task: hello
+Source file: {config_dir}/tasks/hello.yaml
+Description: Say hello.
+Runs by default: yes
+Needs sudo: no
+Tags: greet
+Constraints: none
+
+Dependencies:
+  Requires: base
+  Requires (including indirectly): base
+  Required by: none
+
+Commands:
+  run_cmd: echo 'hello world'
+
+Env vars used:
+  $greeting = hello
+
+Last run: never
+"
+        ),
+        String::from_utf8_lossy(&cmd_assert.get_output().stdout)
+    );
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        config_dir.join("up.yaml").as_str(),
+        "explain",
+        "missing",
+    ]);
+    cmd.assert().eprint_stdout_stderr().try_failure()?;
+    Ok(())
+}
@@ -0,0 +1,3 @@
+#!/bin/sh
+# Helper scripts in task subdirectories are not tasks.
+echo helper
@@ -0,0 +1,4 @@
+#!/bin/sh
+# up: {description: "Write a greeting.", requires: [other]}
+set -eu
+echo "hello from $(basename "$0")" > "$UP_CONFIG_DIR/greeting"
@@ -0,0 +1,3 @@
+#!/bin/sh
+# Helper scripts in subdirectories aren't tasks.
+exit 1
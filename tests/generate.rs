@@ -1,3 +1,5 @@
+use assert_cmd::Command;
+use camino::Utf8Path;
 use color_eyre::Result;
 use std::collections::HashMap;
 use std::fs;
@@ -20,7 +22,7 @@ fn test_generate_passing() -> Result<()> {
     // Git won't let us check in .git subdirs, so check them in as _git and add them here.
     let mut renamed_git_dirs = 0;
     // Bump this if you add a new git repo.
-    let expected_git_dirs_count = 6;
+    let expected_git_dirs_count = 8;
     let mut it = WalkDir::new(&temp_dir).into_iter();
     loop {
         let entry = match it.next() {
@@ -73,5 +75,80 @@ fn test_generate_passing() -> Result<()> {
         ),
     )?;
 
+    // Nothing should change if we regenerate with --check.
+    let git_1_path = temp_dir.join("up_config_dir/tasks/git_1.yaml");
+    generate_git_1_cmd(&temp_dir, &git_1_path)?
+        .arg("--check")
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+
+    // Entries above the managed section marker are kept, and not duplicated below it.
+    fs::write(
+        &git_1_path,
+        format!(
+            "run_lib: git\ndata:\n- path: {temp_dir}/git_scan_dir_2/good_dir_3\n  remotes: []\n# \
+             up-rs managed section\n"
+        ),
+    )?;
+    generate_git_1_cmd(&temp_dir, &git_1_path)?
+        .arg("--check")
+        .assert()
+        .eprint_stdout_stderr()
+        .try_failure()?;
+    generate_git_1_cmd(&temp_dir, &git_1_path)?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    ensure_utils::file(
+        &git_1_path,
+        &format!(
+            "run_lib: git
+data:
+- path: {temp_dir}/git_scan_dir_2/good_dir_3
+  remotes: []
+# up-rs managed section, entries below this line are regenerated.
+- path: {temp_dir}/git_scan_dir_1/some_dir/good_dir
+  remotes: []
+  prune: true
+- path: {temp_dir}/git_scan_dir_2/maybe_matches_exclude
+  remotes: []
+  prune: true
+"
+        ),
+    )?;
+    generate_git_1_cmd(&temp_dir, &git_1_path)?
+        .arg("--check")
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+
     Ok(())
 }
+
+/// `up generate git` command matching the `generate_git_1.yaml` generate task.
+fn generate_git_1_cmd(temp_dir: &Utf8Path, git_1_path: &Utf8Path) -> Result<Command> {
+    let mut cmd = testutils::crate_binary_cmd("up", temp_dir)?;
+    cmd.args([
+        "generate",
+        "git",
+        "--path",
+        git_1_path.as_str(),
+        "--search-paths",
+        temp_dir.join("git_scan_dir_1").as_str(),
+        "--search-paths",
+        temp_dir.join("git_scan_dir_2").as_str(),
+        "--excludes",
+        "/up-tmp/",
+        "--excludes",
+        "/go/",
+        "--skip-paths",
+        temp_dir.join("git_scan_dir_1/bad_skip_path").as_str(),
+        "--prune",
+        "--remote-order",
+        "up",
+        "--remote-order",
+        "fork",
+    ]);
+    Ok(cmd)
+}
@@ -1,5 +1,6 @@
 use assert_cmd::Command;
 use camino::Utf8Path;
+use color_eyre::eyre::ensure;
 use color_eyre::Result;
 use testutils::ensure_eq;
 use testutils::ensure_utils;
@@ -130,6 +131,23 @@ fn test_real_clone() -> Result<()> {
             true,
         )?;
 
+        let mut cmd = up_git_cmd(&git_path, &temp_dir)?;
+        // Dry run prune, which should only report the branch.
+        cmd.args(["--branch", "test", "--prune-dry-run"]);
+        cmd.assert().eprint_stdout_stderr().try_success()?;
+
+        // Branch shouldn't have been pruned as this was a dry run.
+        run_git_cmd(
+            &git_path,
+            &[
+                "show-ref",
+                "--verify",
+                "--quiet",
+                "refs/heads/should_be_pruned",
+            ],
+            true,
+        )?;
+
         let mut cmd = up_git_cmd(&git_path, &temp_dir)?;
         // This time try to prune.
         cmd.args(["--branch", "test", "--prune"]);
@@ -187,11 +205,272 @@ fn test_real_clone() -> Result<()> {
             ],
             false,
         )?;
+
+        // Without --trash-pruned the pruned branch isn't kept anywhere.
+        let trashed = run_git_cmd(
+            &git_path,
+            &["for-each-ref", "--format=%(refname)", "refs/up/trash/"],
+            true,
+        )?;
+        ensure_eq!(trashed, "");
+
+        // Prune another branch, keeping it in the trash this time.
+        run_git_cmd(
+            &git_path,
+            &["branch", "--track", "should_be_trashed", "@"],
+            true,
+        )?;
+        let mut cmd = up_git_cmd(&git_path, &temp_dir)?;
+        cmd.args(["--branch", "test", "--prune", "--trash-pruned"]);
+        cmd.assert().eprint_stdout_stderr().try_success()?;
+        run_git_cmd(
+            &git_path,
+            &[
+                "show-ref",
+                "--verify",
+                "--quiet",
+                "refs/heads/should_be_trashed",
+            ],
+            false,
+        )?;
+
+        // The pruned branch should have been moved to the trash.
+        let trashed = run_git_cmd(
+            &git_path,
+            &["for-each-ref", "--format=%(refname)", "refs/up/trash/"],
+            true,
+        )?;
+        ensure_eq!(trashed.lines().count(), 1);
+        ensure!(
+            trashed.trim().ends_with("/should_be_trashed"),
+            "Unexpected trashed refs: {trashed}"
+        );
+    }
+
+    Ok(())
+}
+
+/// When the remote renames its default branch, the local branch and its upstream follow.
+#[test]
+fn test_renamed_default_branch() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    let remote_path = temp_dir.join("remote_repo");
+    let git_path = temp_dir.join("local_repo");
+    let head_commit = create_local_remote(&remote_path)?;
+
+    let up_local_git_cmd = || -> Result<Command> {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.args([
+            "git",
+            "--git-url",
+            remote_path.as_str(),
+            "--git-path",
+            git_path.as_str(),
+            "--remote",
+            "up",
+        ]);
+        Ok(cmd)
+    };
+
+    up_local_git_cmd()?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    check_repo(&git_path, &head_commit, "master", "up/master")?;
+
+    run_git_cmd(&remote_path, &["branch", "--move", "master", "main"], true)?;
+    up_local_git_cmd()?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    check_repo(&git_path, &head_commit, "main", "up/main")?;
+    // The stale remote-tracking branch is removed.
+    run_git_cmd(
+        &git_path,
+        &["show-ref", "--verify", "--quiet", "refs/remotes/up/master"],
+        false,
+    )?;
+
+    Ok(())
+}
+
+/// Repos with a detached HEAD are skipped, or with `--on-detached-head=checkout` have the default
+/// branch checked out if they're clean.
+#[test]
+fn test_detached_head() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    let remote_path = temp_dir.join("remote_repo");
+    let git_path = temp_dir.join("local_repo");
+    let head_commit = create_local_remote(&remote_path)?;
+
+    let up_local_git_cmd = |extra_args: &[&str]| -> Result<Command> {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.args([
+            "git",
+            "--git-url",
+            remote_path.as_str(),
+            "--git-path",
+            git_path.as_str(),
+            "--remote",
+            "up",
+        ]);
+        cmd.args(extra_args);
+        Ok(cmd)
+    };
+
+    up_local_git_cmd(&[])?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    run_git_cmd(&git_path, &["checkout", "--detach"], true)?;
+
+    let assert = up_local_git_cmd(&[])?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Not updating repo as HEAD is detached at"),
+        "Expected a warning that the detached repo was skipped."
+    );
+    ensure!(
+        stderr.contains("not on any branch"),
+        "Expected the status to report the detached HEAD."
+    );
+    ensure_eq!(
+        run_git_cmd(&git_path, &["rev-parse", "--abbrev-ref", "HEAD"], true)?.trim(),
+        "HEAD"
+    );
+
+    // Uncommitted changes would be overwritten by a checkout, so the repo is still skipped.
+    let untracked_file = git_path.join("untracked_file");
+    std::fs::write(&untracked_file, "")?;
+    let assert = up_local_git_cmd(&["--on-detached-head=checkout"])?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    ensure!(
+        stderr.contains("the repo isn't clean"),
+        "Expected a warning that the dirty repo was skipped."
+    );
+    ensure_eq!(
+        run_git_cmd(&git_path, &["rev-parse", "--abbrev-ref", "HEAD"], true)?.trim(),
+        "HEAD"
+    );
+
+    std::fs::remove_file(&untracked_file)?;
+    up_local_git_cmd(&["--on-detached-head=checkout"])?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    check_repo(&git_path, &head_commit, "master", "up/master")?;
+    Ok(())
+}
+
+/// `~` and env vars in `--git-path` are expanded, even if the shell didn't expand them.
+#[test]
+fn test_git_path_expansion() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    let remote_path = temp_dir.join("remote_repo");
+    let head_commit = create_local_remote(&remote_path)?;
+
+    for git_path in ["~/repos/tilde_repo", "$repos_dir/env_repo"] {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.env("HOME", &temp_dir);
+        cmd.env("repos_dir", temp_dir.join("repos"));
+        cmd.args([
+            "git",
+            "--git-url",
+            remote_path.as_str(),
+            "--git-path",
+            git_path,
+            "--remote",
+            "up",
+        ]);
+        cmd.assert().eprint_stdout_stderr().try_success()?;
     }
 
+    check_repo(
+        &temp_dir.join("repos/tilde_repo"),
+        &head_commit,
+        "master",
+        "up/master",
+    )?;
+    check_repo(
+        &temp_dir.join("repos/env_repo"),
+        &head_commit,
+        "master",
+        "up/master",
+    )?;
     Ok(())
 }
 
+/// `up git --from-file` clones every repo in the file, expanding `~` and env vars in their paths.
+#[test]
+fn test_from_file() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    let remote_path = temp_dir.join("remote_repo");
+    let head_commit = create_local_remote(&remote_path)?;
+    let repos_file = temp_dir.join("repos.yaml");
+    std::fs::write(
+        &repos_file,
+        format!(
+            "- path: $repos_dir/repo_1
+  remotes:
+  - name: up
+    fetch_url: {remote_path}
+- path: ~/repos/repo_2
+  remotes:
+  - name: up
+    fetch_url: {remote_path}
+"
+        ),
+    )?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.env("HOME", &temp_dir);
+    cmd.env("repos_dir", temp_dir.join("repos"));
+    cmd.args(["git", "--from-file", repos_file.as_str()]);
+    cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    check_repo(
+        &temp_dir.join("repos/repo_1"),
+        &head_commit,
+        "master",
+        "up/master",
+    )?;
+    check_repo(
+        &temp_dir.join("repos/repo_2"),
+        &head_commit,
+        "master",
+        "up/master",
+    )?;
+    Ok(())
+}
+
+/// Create a git repo at `path` with a single commit on `master`, returning the commit hash.
+fn create_local_remote(path: &Utf8Path) -> Result<String> {
+    std::fs::create_dir_all(path)?;
+    run_git_cmd(path, &["init", "--initial-branch=master"], true)?;
+    run_git_cmd(
+        path,
+        &[
+            "-c",
+            "user.name=up",
+            "-c",
+            "user.email=up@example.com",
+            "commit",
+            "--allow-empty",
+            "--message=first commit",
+        ],
+        true,
+    )?;
+    Ok(run_git_cmd(path, &["rev-parse", "HEAD"], true)?
+        .trim()
+        .to_owned())
+}
+
 fn up_git_cmd(git_path: &Utf8Path, temp_dir: &Utf8Path) -> Result<Command> {
     let mut cmd = testutils::crate_binary_cmd("up", temp_dir)?;
     cmd.args(
@@ -1,4 +1,5 @@
 use camino::Utf8Path;
+use color_eyre::eyre::ensure;
 use color_eyre::Result;
 use predicates::prelude::*;
 use testutils::AssertCmdExt;
@@ -23,6 +24,26 @@ fn test_version_test() -> Result<()> {
     Ok(())
 }
 
+/// `up man --out-dir` should write a man page for each command.
+#[test]
+fn test_man_out_dir() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+    let out_dir = temp_dir.join("man");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["man", "--out-dir", out_dir.as_str()]);
+    cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    for page in ["up.1", "up-run.1", "up-defaults-read.1"] {
+        ensure!(
+            out_dir.join(page).is_file(),
+            "Expected man page {page} to exist in {out_dir}."
+        );
+    }
+
+    Ok(())
+}
+
 fn check_help(arg: &str, temp_dir: &Utf8Path) -> Result<()> {
     let mut cmd = testutils::crate_binary_cmd("up", temp_dir)?;
     cmd.arg(arg);
@@ -7,6 +7,11 @@ use std::fs::File;
 use std::os::unix;
 use testutils::ensure_utils;
 
+/// Start time passed to link commands, so we know which backup dir they'll use.
+const START_TIME: &str = "2024-01-01T00:00:00Z";
+/// Backup subdirectory for runs started at [`START_TIME`].
+const START_TIME_DIR: &str = "2024-01-01T00_00_00Z";
+
 /// Set up a basic `home_dir`, run the link function against it, and make sure we
 /// get the expected changes.
 #[test]
@@ -27,6 +32,8 @@ fn test_new_link() -> Result<()> {
     ensure_utils::link(&home_dir.join("good_link"), &dotfile_dir.join("good_link"))?;
     // Empty backup dir should be removed.
     ensure_utils::nothing_at(&backup_dir)?;
+    // As should the backup dir for this run.
+    ensure_utils::nothing_at(&temp_dir.join(format!("up-rs/backup/{START_TIME_DIR}")))?;
 
     Ok(())
 }
@@ -43,6 +50,11 @@ fn test_backup_files() -> Result<()> {
     ensure_utils::dir(&backup_dir)?;
     // Files in backup should be overwritten with the new backups.
     ensure_utils::file(&backup_dir.join("already_in_backup"), "new backup\n")?;
+    // Backups from previous runs should be kept.
+    ensure_utils::file(
+        &temp_dir.join("up-rs/backup/2023-12-31T00_00_00Z/link/already_in_backup"),
+        "previous run backup\n",
+    )?;
     // Symlinks in home should be overwritten.
     ensure_utils::link(
         &home_dir.join("existing_symlink"),
@@ -173,6 +185,132 @@ fn test_hidden_and_nested() -> Result<()> {
     Ok(())
 }
 
+/// With `--relative`, links point to paths relative to the link's directory.
+#[test]
+fn test_relative_link() -> Result<()> {
+    use testutils::AssertCmdExt;
+
+    let (home_dir, dotfile_dir, backup_dir, temp_dir) =
+        get_home_dotfile_dirs(testutils::function_path!())?;
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--start-time",
+        START_TIME,
+        "link",
+        "--relative",
+        "--from",
+        dotfile_dir.as_str(),
+        "--to",
+        home_dir.as_str(),
+    ]);
+    cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    // Existing files shouldn't be touched.
+    ensure_utils::file(&home_dir.join("existing_file"), "existing file\n")?;
+    // Links should be relative to the directory they're in.
+    ensure_utils::link(&home_dir.join("file"), Utf8Path::new("../dotfile_dir/file"))?;
+    ensure_utils::link(
+        &home_dir.join("subdir/nested_file"),
+        Utf8Path::new("../../dotfile_dir/subdir/nested_file"),
+    )?;
+    // And still point to the right files.
+    ensure_utils::file(&home_dir.join("subdir/nested_file"), "nested file\n")?;
+    // Nothing should have been backed up.
+    ensure_utils::nothing_at(&backup_dir)?;
+
+    Ok(())
+}
+
+/// With `--home`, the default `--to` directory (`~`) is the passed home directory.
+#[test]
+fn test_home_override() -> Result<()> {
+    use testutils::AssertCmdExt;
+
+    let (home_dir, dotfile_dir, backup_dir, temp_dir) =
+        get_home_dotfile_dirs(testutils::function_path!())?;
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--start-time",
+        START_TIME,
+        "--home",
+        home_dir.as_str(),
+        "link",
+        "--from",
+        dotfile_dir.as_str(),
+    ]);
+    cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    // Existing files shouldn't be touched.
+    ensure_utils::file(&home_dir.join("existing_file"), "existing file\n")?;
+    // Files should be linked into the --home directory.
+    ensure_utils::link(&home_dir.join("file"), &dotfile_dir.join("file"))?;
+    ensure_utils::link(
+        &home_dir.join("subdir/nested_file"),
+        &dotfile_dir.join("subdir/nested_file"),
+    )?;
+    // Nothing should have been backed up.
+    ensure_utils::nothing_at(&backup_dir)?;
+
+    Ok(())
+}
+
+/// Check each `--on-conflict` option handles an existing file where a link should go.
+#[test]
+fn test_on_conflict() -> Result<()> {
+    use testutils::AssertCmdExt;
+
+    let (home_dir, dotfile_dir, backup_dir, temp_dir) =
+        get_home_dotfile_dirs(testutils::function_path!())?;
+    let link_cmd = |on_conflict: &str| -> Result<assert_cmd::Command> {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.args([
+            "--start-time",
+            START_TIME,
+            "link",
+            "--on-conflict",
+            on_conflict,
+            "--from",
+            dotfile_dir.as_str(),
+            "--to",
+            home_dir.as_str(),
+        ]);
+        Ok(cmd)
+    };
+
+    // Fail before creating any links.
+    let cmd_assert = link_cmd("fail")?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_failure()?;
+    ensure_utils::contains(
+        &String::from_utf8_lossy(&cmd_assert.get_output().stderr),
+        home_dir.join("existing_file").as_str(),
+    )?;
+    ensure_utils::file(&home_dir.join("existing_file"), "old existing_file\n")?;
+    ensure_utils::nothing_at(&home_dir.join("new_file"))?;
+
+    // Skip the existing file but link the rest.
+    link_cmd("skip")?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    ensure_utils::file(&home_dir.join("existing_file"), "old existing_file\n")?;
+    ensure_utils::link(&home_dir.join("new_file"), &dotfile_dir.join("new_file"))?;
+
+    // Replace the existing file without backing it up.
+    link_cmd("overwrite")?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    ensure_utils::link(
+        &home_dir.join("existing_file"),
+        &dotfile_dir.join("existing_file"),
+    )?;
+    ensure_utils::nothing_at(&backup_dir)?;
+
+    Ok(())
+}
+
 /// Pass a `from_dir` that doesn't exist and make sure we fail.
 #[test]
 fn test_missing_from_dir() -> Result<()> {
@@ -225,8 +363,9 @@ fn test_uncreateable_backup_dir() -> Result<()> {
     let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
     fs::create_dir(temp_dir.join("dotfile_dir")).unwrap();
     fs::create_dir(temp_dir.join("home_dir")).unwrap();
-    fs::create_dir_all(temp_dir.join("up-rs/backup")).unwrap();
-    File::create(temp_dir.join("up-rs/backup/link")).unwrap();
+    let run_backup_dir = temp_dir.join(format!("up-rs/backup/{START_TIME_DIR}"));
+    fs::create_dir_all(&run_backup_dir).unwrap();
+    File::create(run_backup_dir.join("link")).unwrap();
     let assert = run_link_cmd(
         &temp_dir.join("dotfile_dir"),
         &temp_dir.join("home_dir"),
@@ -238,7 +377,7 @@ fn test_uncreateable_backup_dir() -> Result<()> {
         &[
             "Backup directory",
             "should exist and be a directory",
-            "uncreateable_backup_dir/up-rs/backup/link",
+            &format!("uncreateable_backup_dir/up-rs/backup/{START_TIME_DIR}/link"),
         ],
     )?;
 
@@ -258,7 +397,7 @@ fn get_home_dotfile_dirs(
     Ok((
         temp_dir.join("home_dir").canonicalize_utf8().unwrap(),
         temp_dir.join("dotfile_dir").canonicalize_utf8().unwrap(),
-        temp_dir.join("up-rs/backup/link"),
+        temp_dir.join(format!("up-rs/backup/{START_TIME_DIR}/link")),
         temp_dir,
     ))
 }
@@ -296,6 +435,8 @@ fn run_link_cmd(
     // Always show coloured logs.
     cmd.args(
         [
+            "--start-time",
+            START_TIME,
             "link",
             "--from",
             dotfile_dir.as_str(),
@@ -0,0 +1,42 @@
+use color_eyre::Result;
+use testutils::ensure_eq;
+use testutils::AssertCmdExt;
+
+/// Check that each lint rule is reported with its location and fix.
+#[test]
+fn test_lint() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let config_dir = temp_dir.join("up_config_dir");
+    let tasks_dir = config_dir.join("tasks");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", config_dir.join("up.yaml").as_str(), "lint"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_failure()?;
+
+    ensure_eq!(
+        format!(
+            "{tasks_dir}/bad_lib.yaml:2: run_lib 'homebrew' doesn't exist.
+    fix: use one of: brew, colima, default_apps, defaults, developer_tools, finder, generate_git, \
+             git, json, keygen, link, self, software_update, toml, vscode.
+{tasks_dir}/no_run.yaml:1: Task has no run_cmd, run_script, or run_lib, so won't do anything.
+    fix: add one of them, or delete the task.
+{tasks_dir}/undefined_env.yaml:3: Env var 'undefined_var' is used but isn't set in up.yaml or an \
+             env file.
+    fix: add 'undefined_var' to the `env` section of up.yaml or to an `env_file`, or to \
+             `inherit_env` to pass it through from the environment up is run in.
+{tasks_dir}/good.yaml:1: Task name 'good' is also used by {tasks_dir}/duplicate.yaml, only one of \
+             them will be run.
+    fix: rename one of the tasks (with the `name` field or by renaming the file).
+{config_dir}/up.yaml:6: bootstrap_tasks entry 'missing_task' doesn't match any task in {tasks_dir}.
+    fix: remove it, or fix it to one of: bad_lib, good, no_run, undefined_env.
+"
+        ),
+        String::from_utf8_lossy(&cmd_assert.get_output().stdout)
+    );
+    Ok(())
+}
@@ -4,6 +4,7 @@ use camino::Utf8PathBuf;
 use color_eyre::Result;
 use itertools::Itertools;
 use std::collections::HashMap;
+use testutils::ensure_eq;
 use testutils::ensure_utils;
 use testutils::AssertCmdExt;
 
@@ -45,9 +46,376 @@ fn test_up_list_passing() -> Result<()> {
         .sorted(),
     );
 
+    itertools::assert_equal(
+        ["run_self_cmd", "skip_self_cmd"],
+        check_list(&["--tasks", "*_self_cmd"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    itertools::assert_equal(
+        ["link", "run_self_cmd"],
+        check_list(&["-x", "skip_self_cmd"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    // Excluded tasks must exist.
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.envs(&envs);
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "list",
+        "--exclude-tasks=link,nonexistent_task",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_failure()?;
+    ensure_utils::contains_all(
+        &String::from_utf8_lossy(&cmd_assert.get_output().stderr),
+        &[r#"Excluded tasks ["nonexistent_task"] were not found"#],
+    )?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.envs(&envs);
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "plan",
+        "--output=json",
+        "--exclude-tasks=skip_self_cmd",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let plan: serde_json::Value = serde_json::from_slice(&cmd_assert.get_output().stdout)?;
+    ensure_eq!(
+        vec![("link", 1), ("run_self_cmd", 1)],
+        planned_steps(&plan["tasks"])
+    );
+    ensure_eq!(Some("link"), plan["tasks"][0]["run_lib"].as_str());
+    ensure_eq!(
+        Some("skip_self_cmd"),
+        plan["excluded_tasks"][0]["name"].as_str()
+    );
+    ensure_utils::nothing_at(&temp_dir.join("link_dir/home_dir/file_to_link"))?;
+
+    Ok(())
+}
+
+/// `--tags` selects the tasks with any of the tags, as well as the tasks matching `--tasks`.
+#[test]
+fn test_up_list_tags() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let envs = HashMap::new();
+
+    itertools::assert_equal(
+        ["apt", "brew"],
+        check_list(&["--tags", "packages"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    itertools::assert_equal(
+        ["brew", "dotfiles"],
+        check_list(&["--tags", "mac", "--tasks", "d?tfiles"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    Ok(())
+}
+
+/// `--until` runs a task and the tasks it requires, and `--only-deps` leaves out the task itself.
+#[test]
+fn test_up_list_requires() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let envs = HashMap::new();
+
+    itertools::assert_equal(
+        ["base", "dependent", "unrelated"],
+        check_list(&[], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    itertools::assert_equal(
+        ["base", "dependent"],
+        check_list(&["--until", "dependent"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    itertools::assert_equal(
+        ["base"],
+        check_list(&["--until", "dependent", "--only-deps"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    Ok(())
+}
+
+/// Tasks in subdirectories are namespaced by the subdirectory, and can be filtered by it.
+#[test]
+fn test_up_list_namespaced() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let envs = HashMap::new();
+
+    itertools::assert_equal(
+        ["top", "work/nested/renamed", "work/vpn"],
+        check_list(&[], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    itertools::assert_equal(
+        ["work/nested/renamed", "work/vpn"],
+        check_list(&["--tasks", "work/*"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    itertools::assert_equal(
+        ["work/vpn"],
+        check_list(&["--tasks", "work/vpn"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    Ok(())
+}
+
+/// Tasks with `auto_run: false` are only run when required by a task that runs, or named in
+/// `--tasks`.
+#[test]
+fn test_up_list_auto_run() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let envs = HashMap::new();
+
+    itertools::assert_equal(
+        ["helper", "main"],
+        check_list(&[], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    itertools::assert_equal(
+        ["manual"],
+        check_list(&["--tasks", "manual"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    // Matching a glob isn't enough, auto_run: false tasks have to be named.
+    itertools::assert_equal(
+        ["main"],
+        check_list(&["--tasks", "m*"], &envs, &temp_dir)?
+            .split_whitespace()
+            .sorted(),
+    );
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "plan",
+        "--output=json",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let plan: serde_json::Value = serde_json::from_slice(&cmd_assert.get_output().stdout)?;
+    ensure_eq!(
+        vec![("helper", 1), ("main", 2)],
+        planned_steps(&plan["tasks"])
+    );
+    ensure_eq!(
+        Some("its auto_run field is false and no task that runs requires it"),
+        plan["excluded_tasks"][0]["reason"].as_str()
+    );
+
+    Ok(())
+}
+
+/// Tasks with `bootstrap: true` run before all the other tasks.
+#[test]
+fn test_up_list_bootstrap() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "plan",
+        "--output=json",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let plan: serde_json::Value = serde_json::from_slice(&cmd_assert.get_output().stdout)?;
+    ensure_eq!(
+        vec![("setup", 1), ("keys", 2), ("packages", 3), ("repos", 3)],
+        planned_steps(&plan["tasks"])
+    );
+    ensure_eq!(
+        Some("it is a bootstrap task, so the other tasks require it"),
+        plan["tasks"][0]["reason"].as_str()
+    );
+
+    // A bootstrap task requiring a non-bootstrap task would be a cycle.
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        temp_dir.join("bad_config_dir/up.yaml").as_str(),
+        "plan",
+    ]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_failure()?
+        .try_stderr(predicates::str::contains(
+            "Bootstrap task 'keys' requires task 'repos', which isn't a bootstrap task.",
+        ))?;
+
+    Ok(())
+}
+
+/// `up list --json` shows how each task's last run went.
+#[test]
+fn test_up_list_json() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let up_yaml = temp_dir.join("up_config_dir/up.yaml");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        up_yaml.as_str(),
+        "run",
+        "--tasks=passing,skipping",
+    ]);
+    cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", up_yaml.as_str(), "list", "--json"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let listed: serde_json::Value = serde_json::from_slice(&cmd_assert.get_output().stdout)?;
+    let listed_tasks = listed.as_array().cloned().unwrap_or_default();
+    ensure_eq!(
+        vec![
+            ("not_run", None, false),
+            ("passing", Some("passed"), true),
+            ("skipping", Some("skipped"), true),
+        ],
+        listed_tasks
+            .iter()
+            .map(|t| (
+                t["name"].as_str().unwrap_or_default(),
+                t["last_status"].as_str(),
+                t["last_run"].is_string() && t["last_duration_secs"].is_f64(),
+            ))
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+/// `up task which` and `up task cat` find task files by name, using `tasks_path` from up.yaml.
+#[test]
+fn test_up_task_which() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let task_cmd = |args: &[&str]| -> Result<assert_cmd::Command> {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.args([
+            "--config",
+            temp_dir.join("up_config_dir/up.yaml").as_str(),
+            "task",
+        ]);
+        cmd.args(args);
+        Ok(cmd)
+    };
+
+    // A name without a subdirectory matches the only task with that name.
+    for name in ["work/vpn", "vpn"] {
+        let cmd_assert = task_cmd(&["which", name])?
+            .assert()
+            .eprint_stdout_stderr()
+            .try_success()?;
+        ensure_eq!(
+            format!(
+                "{}\n",
+                temp_dir.join("up_config_dir/my_tasks/work/vpn.yaml")
+            ),
+            String::from_utf8_lossy(&cmd_assert.get_output().stdout)
+        );
+    }
+
+    let cmd_assert = task_cmd(&["cat", "vpn"])?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    ensure_eq!(
+        "description: Connect to the VPN.\nrun_cmd: [\"true\"]\n",
+        String::from_utf8_lossy(&cmd_assert.get_output().stdout)
+    );
+
+    task_cmd(&["which", "missing"])?
+        .assert()
+        .eprint_stdout_stderr()
+        .try_failure()?
+        .try_stderr(predicates::str::contains("tasks are: top, work/vpn"))?;
+
     Ok(())
 }
 
+/// The `(name, step)` of each task in the `tasks` of an `up plan --output=json` plan.
+fn planned_steps(tasks: &serde_json::Value) -> Vec<(&str, u64)> {
+    tasks
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|t| {
+            (
+                t["name"].as_str().unwrap_or_default(),
+                t["step"].as_u64().unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
 fn check_list(
     args: &[&str],
     envs: &HashMap<&str, Utf8PathBuf>,
@@ -68,3 +436,74 @@ fn check_list(
 
     return Ok(String::from_utf8_lossy(&cmd_assert.get_output().stdout).to_string());
 }
+
+/// The `hosts` entry for the machine changes which tasks are run, and is shown by `up plan` and
+/// `up explain`.
+#[test]
+fn test_up_plan_hosts() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let config_path = temp_dir.join("up_config_dir/up.yaml");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        config_path.as_str(),
+        "--host",
+        "work-laptop",
+        "plan",
+        "--output=json",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let plan: serde_json::Value = serde_json::from_slice(&cmd_assert.get_output().stdout)?;
+    ensure_eq!(
+        Some("work-laptop (matched by --host)"),
+        plan["host"].as_str()
+    );
+    ensure_eq!(Some("tools"), plan["tasks"][0]["name"].as_str());
+    ensure_eq!(Some("games"), plan["excluded_tasks"][0]["name"].as_str());
+    ensure_eq!(
+        Some("it is in the exclude_tasks of host 'work-laptop'"),
+        plan["excluded_tasks"][0]["reason"].as_str()
+    );
+
+    // The host's tasks are used when --tasks isn't passed.
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.env("UP_HOST", "home");
+    cmd.args(["--config", config_path.as_str(), "plan", "--output=json"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let plan: serde_json::Value = serde_json::from_slice(&cmd_assert.get_output().stdout)?;
+    ensure_eq!(Some("games"), plan["tasks"][0]["name"].as_str());
+    ensure_eq!(Some("tools"), plan["excluded_tasks"][0]["name"].as_str());
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        config_path.as_str(),
+        "--host",
+        "work-laptop",
+        "explain",
+        "games",
+    ]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_success()?
+        .try_stdout(predicates::str::contains(
+            "Host: work-laptop (matched by --host), which excludes this task\n",
+        ))?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", config_path.as_str(), "--host", "office", "plan"]);
+    cmd.assert()
+        .eprint_stdout_stderr()
+        .try_failure()?
+        .try_stderr(predicates::str::contains(
+            "--host 'office' isn't in the hosts of up.yaml",
+        ))?;
+
+    Ok(())
+}
@@ -1,10 +1,10 @@
 use assert_cmd::cargo::cargo_bin;
 use camino::Utf8PathBuf;
+use color_eyre::eyre::ensure;
 use color_eyre::Result;
 #[cfg(target_os = "macos")]
 use duct::Expression;
 use std::collections::HashMap;
-#[cfg(target_os = "macos")]
 use testutils::ensure_eq;
 use testutils::ensure_utils;
 use testutils::AssertCmdExt;
@@ -65,7 +65,14 @@ fn test_up_run_passing() -> Result<()> {
     cmd.envs(envs);
 
     cmd.args(["--config", temp_dir.join("up_config_dir/up.yaml").as_str()].iter());
-    cmd.assert().eprint_stdout_stderr().try_success()?;
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    // Check the summary counts the files the link task linked.
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Changes applied: 1 file linked"),
+        "Expected the run summary to count the linked file."
+    );
 
     // Link Task: Check symlinks were created correctly.
     ensure_utils::link(
@@ -73,6 +80,21 @@ fn test_up_run_passing() -> Result<()> {
         &temp_dir.join("link_dir/dotfile_dir/file_to_link"),
     )?;
 
+    // Run Script Task: Check the script was run.
+    ensure_utils::file(
+        &temp_dir.join("link_dir/run_script_output"),
+        "ran with bash\n",
+    )?;
+
+    // Plugin Task: Check the plugin got the task data with env vars resolved.
+    ensure_utils::file(
+        &temp_dir.join("link_dir/plugin_output"),
+        &format!(
+            r#"{{"home_dir":"{home_dir}","count":2}}"#,
+            home_dir = temp_dir.join("link_dir/home_dir")
+        ),
+    )?;
+
     #[cfg(target_os = "macos")]
     {
         use duct::cmd;
@@ -146,3 +168,641 @@ fn test_up_run_passing() -> Result<()> {
 
     Ok(())
 }
+
+/// With `--quiet` only warnings, errors, and the run summary are shown.
+#[test]
+fn test_up_run_quiet() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--quiet",
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 1 tasks, 1 passed, 0 failed, 0 skipped"),
+        "Expected the run summary to be shown."
+    );
+    ensure!(
+        !stderr.contains("Tasks by tag:"),
+        "Expected other info logs to be hidden."
+    );
+    Ok(())
+}
+
+/// `--generate-first` runs the generate tasks before the main tasks, so tasks they write are run.
+#[test]
+fn test_up_run_generate_first() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let config_dir = temp_dir.join("up_config_dir");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        config_dir.join("up.yaml").as_str(),
+        "run",
+        "--generate-first",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    ensure_utils::file(
+        &config_dir.join("tasks/generated.yaml"),
+        &std::fs::read_to_string(config_dir.join("generated.yaml.template"))?,
+    )?;
+    ensure_utils::file(&config_dir.join("generated_ran"), "ran\n")?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 2 tasks, 2 passed, 0 failed, 0 skipped"),
+        "Expected the generated task to run alongside the existing one."
+    );
+    Ok(())
+}
+
+/// `--trace-file` writes a Chrome trace with a span for each task and command, and an event for
+/// each progress update.
+#[test]
+fn test_up_run_trace_file() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let trace_file = temp_dir.join("trace.json");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.env("link_from_dir", temp_dir.join("link_dir/dotfile_dir"));
+    cmd.env("link_to_dir", temp_dir.join("link_dir/home_dir"));
+    cmd.args([
+        "--trace-file",
+        trace_file.as_str(),
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+    ]);
+    cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let trace: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&trace_file)?)?;
+    let events = trace["traceEvents"].as_array().cloned().unwrap_or_default();
+    // Tasks run in parallel, so sort to get a consistent order.
+    let mut spans: Vec<(&str, &str)> = events
+        .iter()
+        .filter_map(|event| {
+            let args = event["args"].as_object()?;
+            let arg = args.get("task").or_else(|| args.get("command_type"))?;
+            Some((event["name"].as_str()?, arg.as_str()?))
+        })
+        .collect();
+    spans.sort_unstable();
+    ensure!(
+        spans
+            == [
+                ("command", "run command"),
+                ("progress", "link"),
+                ("progress", "link"),
+                ("task", "link"),
+                ("task", "passing")
+            ],
+        "Unexpected trace spans: {spans:?}"
+    );
+    // The order files are linked in depends on the filesystem.
+    let mut progress: Vec<(&str, &str, &str)> = events
+        .iter()
+        .filter(|event| event["name"] == "progress")
+        .filter_map(|event| {
+            let args = &event["args"];
+            Some((
+                args["message"].as_str()?,
+                args["current"].as_str()?,
+                args["total"].as_str()?,
+            ))
+        })
+        .collect();
+    let currents: Vec<&str> = progress.iter().map(|(_, current, _)| *current).collect();
+    progress.sort_unstable();
+    ensure!(
+        currents == ["1", "2"]
+            && progress
+                .iter()
+                .map(|(msg, _, total)| (*msg, *total))
+                .eq([("file_a", "2"), ("file_b", "2")]),
+        "Unexpected progress events: {progress:?}"
+    );
+    Ok(())
+}
+
+/// Tasks with missing `requires_commands` fail, or are skipped with `on_missing_commands: skip`.
+#[test]
+fn test_up_run_requires_commands() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "run",
+        "--tasks=present,missing_skip",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 2 tasks, 1 passed, 0 failed, 1 skipped"),
+        "Expected the task with missing commands to be skipped."
+    );
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "run",
+        "--tasks=missing_fail",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_failure()?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("requires commands that weren't found in the PATH: up-rs-missing-command"),
+        "Expected the task with missing commands to fail."
+    );
+    Ok(())
+}
+
+/// Tasks that haven't written output for `heartbeat_after` log a heartbeat with their last output
+/// (the second task stops the output going to the console).
+#[test]
+fn test_up_run_heartbeat() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", temp_dir.join("up_config_dir/up.yaml").as_str()]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Task 'quiet' still running, ")
+            && stderr.contains(" elapsed, last output: working on it"),
+        "Expected a heartbeat for the quiet task."
+    );
+    Ok(())
+}
+
+/// Link `on_change` commands only run when one of their links was created or changed.
+#[test]
+fn test_up_run_link_on_change() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let on_change_log = temp_dir.join("on_change.log");
+
+    for run in 1..=2 {
+        let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+        cmd.env("link_from_dir", temp_dir.join("link_dir/dotfile_dir"));
+        cmd.env("link_to_dir", temp_dir.join("link_dir/home_dir"));
+        cmd.env("on_change_log", &on_change_log);
+        cmd.args(["--config", temp_dir.join("up_config_dir/up.yaml").as_str()]);
+        let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+        let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+        ensure!(
+            stderr.contains("on_change path .zshrc doesn't match any files to link."),
+            "Expected a warning for the unmatched on_change path in run {run}."
+        );
+        // The second run doesn't change any links, so doesn't run the command again.
+        ensure_utils::file(&on_change_log, "karabiner\n")?;
+    }
+    Ok(())
+}
+
+/// Interactive tasks get the terminal's stdin and stdout, with a warning when stdin isn't a
+/// terminal (the second task stops the other output going to the console).
+#[test]
+fn test_up_run_interactive() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", temp_dir.join("up_config_dir/up.yaml").as_str()]);
+    cmd.write_stdin("yes\n");
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stdout = String::from_utf8_lossy(&cmd_assert.get_output().stdout);
+    ensure!(
+        stdout.contains("got yes"),
+        "Expected the interactive task to read stdin and write to stdout."
+    );
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Running interactive tasks (prompt) but stdin isn't a terminal"),
+        "Expected a warning that stdin isn't a terminal."
+    );
+    Ok(())
+}
+
+/// Test that `defaults` in `up.yaml` apply to tasks that don't override them, and that YAML
+/// merge keys work in both `up.yaml` and task configs.
+#[test]
+fn test_up_run_config_defaults() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "run",
+        "--tags",
+        "default",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stdout = String::from_utf8_lossy(&cmd_assert.get_output().stdout);
+    ensure!(
+        stdout.contains("tagged ran"),
+        "Expected the task with the default tag to run."
+    );
+    Ok(())
+}
+
+/// A task with `allow_failure: true` that fails is reported as warned, without failing the run.
+#[test]
+fn test_up_run_allow_failure() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", temp_dir.join("up_config_dir/up.yaml").as_str()]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 2 tasks, 1 passed, 0 failed, 0 skipped, 1 warned"),
+        "Expected the flaky task to be warned, and the task requiring it to pass."
+    );
+    ensure!(
+        stderr.contains("Tasks failed but have allow_failure set: flaky"),
+        "Expected the warned tasks to be listed."
+    );
+    Ok(())
+}
+
+/// With `--offline`, tasks that need the network aren't run, and are reported as offline.
+#[test]
+fn test_up_run_offline() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--offline",
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 1 tasks, 1 passed, 0 failed, 0 skipped, 3 offline"),
+        "Expected the network tasks to be reported as offline."
+    );
+    Ok(())
+}
+
+/// Once the `--max-duration` is exceeded no more tasks are started, and with `--hard` running task
+/// commands are killed.
+#[test]
+fn test_up_run_max_duration() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let up_yaml = temp_dir.join("up_config_dir/up.yaml");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", up_yaml.as_str(), "run", "--max-duration=2s"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Exceeded the 2s --max-duration, not starting any more tasks."),
+        "Expected a warning when the budget ran out."
+    );
+    ensure!(
+        stderr.contains("Ran 1 tasks, 1 passed, 0 failed, 0 skipped, 1 not run"),
+        "Expected the running task to finish, and the later task not to be run."
+    );
+    ensure!(
+        stderr.contains("Tasks not run as the --max-duration was exceeded: later"),
+        "Expected the tasks that weren't run to be listed."
+    );
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        up_yaml.as_str(),
+        "run",
+        "--max-duration=2s",
+        "--hard",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_failure()?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Killing task command as the --max-duration was exceeded."),
+        "Expected the running task command to be killed."
+    );
+    ensure!(
+        stderr.contains("Ran 1 tasks, 0 passed, 1 failed, 0 skipped, 1 not run"),
+        "Expected the killed task to fail, and the later task not to be run."
+    );
+    Ok(())
+}
+
+/// `--resume` skips the bootstrap tasks that passed in an interrupted run.
+#[test]
+fn test_up_run_resume() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let config_dir = temp_dir.join("up_config_dir");
+    let up_yaml = config_dir.join("up.yaml");
+
+    // The second bootstrap task fails, so the run fails after the first passes.
+    std::fs::write(config_dir.join("fail"), "")?;
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", up_yaml.as_str()]);
+    cmd.assert().eprint_stdout_stderr().try_failure()?;
+
+    std::fs::remove_file(config_dir.join("fail"))?;
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", up_yaml.as_str(), "run", "--resume"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 2 tasks, 2 passed, 0 failed, 0 skipped"),
+        "Expected the passed bootstrap task not to be rerun."
+    );
+    let first_runs = std::fs::read_to_string(config_dir.join("first_runs"))?;
+    ensure!(
+        first_runs == "ran\n",
+        "Expected the first task to run once, but got: {first_runs:?}"
+    );
+
+    // The resumed run finished the bootstrap, so there's nothing left to resume.
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", up_yaml.as_str(), "run", "--resume"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 3 tasks, 3 passed, 0 failed, 0 skipped"),
+        "Expected all the tasks to run when there's nothing to resume."
+    );
+    Ok(())
+}
+
+/// Executable scripts in the tasks directory are run as tasks.
+#[test]
+fn test_up_run_script_task() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let config_dir = temp_dir.join("up_config_dir");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", config_dir.join("up.yaml").as_str()]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Ran 2 tasks, 2 passed, 0 failed, 0 skipped"),
+        "Expected the script and yaml tasks (but not the helper script) to run."
+    );
+    let greeting = std::fs::read_to_string(config_dir.join("greeting"))?;
+    ensure!(
+        greeting == "hello from greet.sh\n",
+        "Unexpected greeting: {greeting:?}"
+    );
+    Ok(())
+}
+
+/// A task's `redact` patterns are replaced in its output, and its `log_level` hides its debug logs.
+#[test]
+fn test_up_run_redact() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", temp_dir.join("up_config_dir/up.yaml").as_str()]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    // A single task streams its output to stderr.
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains("Logged in with [REDACTED]\n") && stderr.contains("Using [REDACTED] for"),
+        "Expected the secrets to be redacted."
+    );
+    ensure!(
+        !stderr.contains("hunter2") && !stderr.contains("abc123"),
+        "Expected no secrets in the output."
+    );
+    ensure!(
+        !stderr.contains("Running command: sh"),
+        "Expected the task's debug logs to be hidden."
+    );
+    ensure!(
+        stderr.contains("Ran 1 tasks, 1 passed, 0 failed, 0 skipped"),
+        "Expected the run summary to still be shown."
+    );
+    Ok(())
+}
+
+/// With `update_check: true`, `up run` prints a hint when the cached latest version is newer.
+#[test]
+fn test_up_run_update_check() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let up_yaml = temp_dir.join("up_config_dir/up.yaml");
+    // Matches the `--cache-dir` passed by `crate_binary_cmd()`, written before the run so it
+    // doesn't query GitHub.
+    let version_cache = temp_dir.join("up-rs/cache/latest_version_check");
+    std::fs::create_dir_all(temp_dir.join("up-rs/cache"))?;
+
+    std::fs::write(&version_cache, "999.0.0")?;
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", up_yaml.as_str(), "run"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        stderr.contains(&format!(
+            "up-rs 999.0.0 is available (current version is {})",
+            env!("CARGO_PKG_VERSION")
+        )),
+        "Expected an update hint for the cached latest version."
+    );
+
+    std::fs::write(&version_cache, env!("CARGO_PKG_VERSION"))?;
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args(["--config", up_yaml.as_str(), "run"]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure!(
+        !stderr.contains("is available"),
+        "Expected no update hint when up is already the latest version."
+    );
+    Ok(())
+}
+
+/// With `--run-temp-dir`, task temp dirs and the run history are kept there rather than in the
+/// state dir.
+#[test]
+fn test_up_run_temp_dir() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let up_yaml = temp_dir.join("up_config_dir/up.yaml");
+    let run_temp_dir = temp_dir.join("run_temp_dir");
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        up_yaml.as_str(),
+        "--run-temp-dir",
+        run_temp_dir.as_str(),
+        "run",
+    ]);
+    cmd.assert().eprint_stdout_stderr().try_success()?;
+
+    ensure_eq!(1, std::fs::read_dir(run_temp_dir.join("runs"))?.count());
+    // Matches the `--state-dir` passed by `crate_binary_cmd()`.
+    ensure_utils::nothing_at(&temp_dir.join("up-rs/runs"))?;
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        up_yaml.as_str(),
+        "--run-temp-dir",
+        run_temp_dir.as_str(),
+        "list",
+        "--json",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    let listed: serde_json::Value = serde_json::from_slice(&cmd_assert.get_output().stdout)?;
+    ensure_eq!(Some("passed"), listed[0]["last_status"].as_str());
+    Ok(())
+}
+
+/// `up prompt-hook` summarises the latest status of each task from the run history.
+#[test]
+fn test_up_prompt_hook() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--run-temp-dir",
+        temp_dir.join("run_temp_dir").as_str(),
+        "--start-time",
+        "2024-01-05T00:00:00Z",
+        "prompt-hook",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    // brew failed but passed in the newer run, git is still failing.
+    ensure_eq!(
+        "up: 1 task failing, last run 3d ago\n",
+        String::from_utf8_lossy(&cmd_assert.get_output().stdout)
+    );
+    // Nothing is logged, not even to a log file.
+    ensure_eq!("", String::from_utf8_lossy(&cmd_assert.get_output().stderr));
+    ensure!(!temp_dir.join("up-rs/logs").exists());
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--run-temp-dir",
+        temp_dir.join("missing_run_temp_dir").as_str(),
+        "prompt-hook",
+    ]);
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    ensure_eq!(
+        "up: never run\n",
+        String::from_utf8_lossy(&cmd_assert.get_output().stdout)
+    );
+    Ok(())
+}
@@ -36,11 +36,19 @@ pub fn crate_binary_cmd(binary_name: &str, temp_dir: &Utf8Path) -> Result<Comman
     // Show backtrace on exit, nightly only for now.
     // https://github.com/rust-lang/rust/issues/53487
     cmd.env("RUST_BACKTRACE", "1");
+    // Keep up's temp, state, cache, and log dirs inside our test's temp dir too.
+    let up_dir = temp_dir.join("up-rs");
     cmd.args(
         [
             "--log-level=trace",
             "--up-dir",
-            temp_dir.join("up-rs").as_str(),
+            up_dir.as_str(),
+            "--state-dir",
+            up_dir.as_str(),
+            "--cache-dir",
+            up_dir.join("cache").as_str(),
+            "--log-dir",
+            up_dir.join("logs").as_str(),
             "--color=always",
         ]
         .iter(),
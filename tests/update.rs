@@ -0,0 +1,71 @@
+use assert_cmd::Command;
+use camino::Utf8Path;
+use color_eyre::eyre::ensure;
+use color_eyre::Result;
+use std::fs;
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
+use testutils::ensure_utils;
+use testutils::AssertCmdExt;
+
+/// `up self --from-file` installs a local binary, but only if it's a newer version.
+#[test]
+fn test_update_self_from_file() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!())?;
+
+    // Run a copy of up, as up skips updating itself when run from the cargo target dir, and we
+    // don't want to replace the binary the other tests use.
+    let up_path = temp_dir.join("bin/up");
+    fs::create_dir_all(temp_dir.join("bin"))?;
+    fs::copy(assert_cmd::cargo::cargo_bin("up"), &up_path)?;
+
+    let older_path = temp_dir.join("up-older");
+    let older_binary = "#!/bin/sh\necho up-rs 0.0.1\n";
+    fs::write(&older_path, older_binary)?;
+    fs::set_permissions(&older_path, Permissions::from_mode(0o755))?;
+    let up_binary = fs::read(&up_path)?;
+    update_self_cmd(&up_path, &older_path, &temp_dir)
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    ensure!(
+        fs::read(&up_path)? == up_binary,
+        "Expected {up_path} not to be replaced by an older version."
+    );
+
+    let newer_path = temp_dir.join("up-newer");
+    let newer_binary = "#!/bin/sh\necho up-rs 999.0.0\n";
+    fs::write(&newer_path, newer_binary)?;
+    fs::set_permissions(&newer_path, Permissions::from_mode(0o755))?;
+    update_self_cmd(&up_path, &newer_path, &temp_dir)
+        .assert()
+        .eprint_stdout_stderr()
+        .try_success()?;
+    ensure_utils::file(&up_path, newer_binary)?;
+    // The source file is copied, not moved.
+    ensure_utils::file(&newer_path, newer_binary)?;
+
+    Ok(())
+}
+
+/// Returns a command to update the up binary at `up_path` from the file at `from_file`.
+fn update_self_cmd(up_path: &Utf8Path, from_file: &Utf8Path, temp_dir: &Utf8Path) -> Command {
+    let up_dir = temp_dir.join("up-rs");
+    let mut cmd = Command::new(up_path);
+    cmd.env("TMPDIR", temp_dir.join("up_temp_dir"));
+    cmd.args([
+        "--log-level=trace",
+        "--up-dir",
+        up_dir.as_str(),
+        "--state-dir",
+        up_dir.as_str(),
+        "--cache-dir",
+        up_dir.join("cache").as_str(),
+        "--log-dir",
+        up_dir.join("logs").as_str(),
+        "self",
+        "--from-file",
+        from_file.as_str(),
+    ]);
+    cmd
+}
@@ -0,0 +1,63 @@
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use testutils::ensure_eq;
+use testutils::ensure_utils;
+use testutils::AssertCmdExt;
+
+/// `up watch --once` re-runs the task whose file changed, then exits.
+#[test]
+fn test_watch_once() -> Result<()> {
+    let temp_dir = testutils::temp_dir("up", testutils::function_path!()).unwrap();
+    testutils::copy_all(
+        &testutils::fixtures_subdir(testutils::function_path!())?,
+        &temp_dir,
+    )
+    .unwrap();
+    let task_path = temp_dir.join("up_config_dir/tasks/hello.yaml");
+    let log_dir = temp_dir.join("up-rs/logs");
+
+    // Change the task file once up has started watching it.
+    let edit_task = thread::spawn(move || -> Result<()> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(60) {
+            let watching = fs::read_dir(&log_dir).into_iter().flatten().any(|entry| {
+                entry
+                    .and_then(|entry| fs::read_to_string(entry.path()))
+                    .is_ok_and(|log| log.contains("for changes."))
+            });
+            if watching {
+                let contents = fs::read_to_string(&task_path)?;
+                fs::write(&task_path, format!("# Edited.\n{contents}"))?;
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        bail!("up didn't start watching.");
+    });
+
+    let mut cmd = testutils::crate_binary_cmd("up", &temp_dir)?;
+    cmd.args([
+        "--config",
+        temp_dir.join("up_config_dir/up.yaml").as_str(),
+        "watch",
+        "--once",
+        "--debounce=100ms",
+    ])
+    .timeout(Duration::from_secs(120));
+    let cmd_assert = cmd.assert().eprint_stdout_stderr().try_success()?;
+    edit_task.join().expect("Edit thread panicked.")?;
+
+    let stderr = String::from_utf8_lossy(&cmd_assert.get_output().stderr);
+    ensure_utils::contains_all(
+        &stderr,
+        &["Re-running hello as ", "/tasks/hello.yaml' changed."],
+    )?;
+    // A single task's output goes straight to stdout.
+    let stdout = String::from_utf8_lossy(&cmd_assert.get_output().stdout);
+    ensure_eq!("hello from the watched task\n", stdout);
+    Ok(())
+}